@@ -11,6 +11,7 @@ pub enum Format {
     Octal,
     Compact,
     HexStr,
+    Base64,
     Hexdump,
     Xxd,
 }
@@ -28,6 +29,8 @@ pub struct Attrs<'a> {
     pub annotate: Option<&'a Attribute>,
     pub format: Format,
     pub comment: Comment,
+    pub value_comment: Comment,
+    pub color: Option<String>,
 }
 
 pub fn get(input: &[Attribute]) -> Result<Attrs> {
@@ -35,6 +38,8 @@ pub fn get(input: &[Attribute]) -> Result<Attrs> {
         annotate: None,
         format: Format::None,
         comment: Comment::None,
+        value_comment: Comment::None,
+        color: None,
     };
 
     for attr in input {
@@ -52,9 +57,27 @@ fn function_call(input: ParseStream) -> Result<bool> {
     Ok(content.is_empty())
 }
 
+// Parses the value of a comment attribute: a string, a field or a function.
+fn parse_comment(input: ParseStream, attr: &Attribute) -> Result<Comment> {
+    if input.peek(Ident) {
+        let ident: Ident = input.parse()?;
+        match function_call(input) {
+            Ok(true) => Ok(Comment::Function(ident)),
+            Ok(false) => Err(Error::new_spanned(attr, "Function args not permitted")),
+            Err(_) => Ok(Comment::Field(ident)),
+        }
+    } else {
+        let comment: LitStr = input.parse()?;
+        Ok(Comment::Static(comment.value()))
+    }
+}
+
 fn parse_annotate_attribute<'a>(attrs: &mut Attrs<'a>, attr: &'a Attribute) -> Result<()> {
     syn::custom_keyword!(format);
     syn::custom_keyword!(comment);
+    syn::custom_keyword!(value_comment);
+    syn::custom_keyword!(value_comment_fn);
+    syn::custom_keyword!(color);
 
     attr.parse_args_with(|input: ParseStream| {
         let mut more = true;
@@ -71,6 +94,7 @@ fn parse_annotate_attribute<'a>(attrs: &mut Attrs<'a>, attr: &'a Attribute) -> R
                     "oct" => Format::Octal,
                     "hex" => Format::Hex,
                     "hexstr" => Format::HexStr,
+                    "base64" => Format::Base64,
                     "hexdump" => Format::Hexdump,
                     "xxd" => Format::Xxd,
                     "compact" => Format::Compact,
@@ -83,20 +107,21 @@ fn parse_annotate_attribute<'a>(attrs: &mut Attrs<'a>, attr: &'a Attribute) -> R
             } else if input.peek(comment) {
                 let _kw = input.parse::<comment>()?;
                 let _eq: Token![=] = input.parse()?;
-                if input.peek(Ident) {
-                    let ident: Ident = input.parse()?;
-                    let func = function_call(input);
-                    attrs.comment = match func {
-                        Ok(true) => Comment::Function(ident.clone()),
-                        Ok(false) => {
-                            return Err(Error::new_spanned(attr, "Function args not permitted"));
-                        }
-                        Err(_) => Comment::Field(ident.clone()),
-                    };
-                } else {
-                    let comment: LitStr = input.parse()?;
-                    attrs.comment = Comment::Static(comment.value());
-                }
+                attrs.comment = parse_comment(input, attr)?;
+            } else if input.peek(value_comment) {
+                let _kw = input.parse::<value_comment>()?;
+                let _eq: Token![=] = input.parse()?;
+                attrs.value_comment = parse_comment(input, attr)?;
+            } else if input.peek(value_comment_fn) {
+                let _kw = input.parse::<value_comment_fn>()?;
+                let _eq: Token![=] = input.parse()?;
+                let ident: Ident = input.parse()?;
+                attrs.value_comment = Comment::Function(ident);
+            } else if input.peek(color) {
+                let _kw = input.parse::<color>()?;
+                let _eq: Token![=] = input.parse()?;
+                let class: LitStr = input.parse()?;
+                attrs.color = Some(class.value());
             } else {
                 return Err(Error::new_spanned(attr, "parse error"));
             }
@@ -23,6 +23,7 @@ fn impl_format(a: &Attrs) -> TokenStream {
         Format::Octal => quote! { Some(Format::Octal) },
         Format::Compact => quote! { Some(Format::Compact) },
         Format::HexStr => quote! { Some(Format::HexStr) },
+        Format::Base64 => quote! { Some(Format::Base64) },
         Format::Hexdump => quote! { Some(Format::Hexdump) },
         Format::Xxd => quote! { Some(Format::Xxd) },
     }
@@ -46,8 +47,11 @@ fn impl_field_format(fields: &[Field]) -> Vec<TokenStream> {
         .collect::<Vec<_>>()
 }
 
-fn impl_comment(a: &Attrs) -> TokenStream {
-    match &a.comment {
+// Selects one of the comment attributes of a field or variant.
+type CommentAttr = for<'a, 'b> fn(&'b Attrs<'a>) -> &'b Comment;
+
+fn impl_comment(comment: &Comment) -> TokenStream {
+    match comment {
         Comment::None => quote! { None },
         Comment::Static(s) => quote! {
             Some(#s.to_string())
@@ -61,11 +65,11 @@ fn impl_comment(a: &Attrs) -> TokenStream {
     }
 }
 
-fn impl_field_comment(fields: &[Field]) -> Vec<TokenStream> {
+fn impl_field_comment(fields: &[Field], which: CommentAttr) -> Vec<TokenStream> {
     fields
         .iter()
         .map(|f| {
-            let comment = impl_comment(&f.attrs);
+            let comment = impl_comment(which(&f.attrs));
             match &f.member {
                 Member::Named(id) => {
                     let id = id.to_string();
@@ -79,6 +83,49 @@ fn impl_field_comment(fields: &[Field]) -> Vec<TokenStream> {
         .collect::<Vec<_>>()
 }
 
+fn impl_color(a: &Attrs) -> TokenStream {
+    match &a.color {
+        None => quote! { None },
+        Some(s) => quote! { Some(#s) },
+    }
+}
+
+fn impl_field_color(fields: &[Field]) -> Vec<TokenStream> {
+    fields
+        .iter()
+        .map(|f| {
+            let color = impl_color(&f.attrs);
+            match &f.member {
+                Member::Named(id) => {
+                    let id = id.to_string();
+                    quote! { MemberId::Name(#id) => #color }
+                }
+                Member::Unnamed(Index { index: i, .. }) => {
+                    quote! { MemberId::Index(#i) => #color }
+                }
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
+fn impl_variant_comments(variants: &[Variant], which: CommentAttr) -> Vec<TokenStream> {
+    variants
+        .iter()
+        .map(|v| {
+            let variant = v.ident.to_string();
+            let comments = impl_field_comment(&v.fields, which);
+            let vcomment = impl_comment(which(&v.attrs));
+            quote! {
+                #variant => match field {
+                    MemberId::Variant => #vcomment,
+                    #(#comments,)*
+                    _ => None,
+                }
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
 fn impl_variants(variants: &[Variant]) -> (Vec<TokenStream>, Vec<TokenStream>) {
     let formats = variants
         .iter()
@@ -95,28 +142,30 @@ fn impl_variants(variants: &[Variant]) -> (Vec<TokenStream>, Vec<TokenStream>) {
             }
         })
         .collect::<Vec<_>>();
-    let comments = variants
+    let colors = variants
         .iter()
         .map(|v| {
             let variant = v.ident.to_string();
-            let comments = impl_field_comment(&v.fields);
-            let vcomment = impl_comment(&v.attrs);
+            let colors = impl_field_color(&v.fields);
+            let vcolor = impl_color(&v.attrs);
             quote! {
                 #variant => match field {
-                    MemberId::Variant => #vcomment,
-                    #(#comments,)*
+                    MemberId::Variant => #vcolor,
+                    #(#colors,)*
                     _ => None,
                 }
             }
         })
         .collect::<Vec<_>>();
 
-    (formats, comments)
+    (formats, colors)
 }
 
 fn impl_struct(input: Struct) -> TokenStream {
     let formats = impl_field_format(&input.fields);
-    let comments = impl_field_comment(&input.fields);
+    let comments = impl_field_comment(&input.fields, |a| &a.comment);
+    let value_comments = impl_field_comment(&input.fields, |a| &a.value_comment);
+    let colors = impl_field_color(&input.fields);
     let name = &input.ident;
     quote! {
         const _: () = {
@@ -136,6 +185,18 @@ fn impl_struct(input: Struct) -> TokenStream {
                         _ => None,
                     }
                 }
+                fn value_comment(&self, _variant: Option<&str>, field: &MemberId) -> Option<String> {
+                    match field {
+                        #(#value_comments,)*
+                        _ => None,
+                    }
+                }
+                fn color(&self, _variant: Option<&str>, field: &MemberId) -> Option<&'static str> {
+                    match field {
+                        #(#colors,)*
+                        _ => None,
+                    }
+                }
                 fn as_annotate(&self) -> Option<&dyn Annotate> { Some(self) }
                 // We don't have to implement `thunk_serialize` because the default implementation
                 // already does what we need.
@@ -145,7 +206,9 @@ fn impl_struct(input: Struct) -> TokenStream {
 }
 
 fn impl_enum(input: Enum) -> TokenStream {
-    let (formats, comments) = impl_variants(&input.variants);
+    let (formats, colors) = impl_variants(&input.variants);
+    let comments = impl_variant_comments(&input.variants, |a| &a.comment);
+    let value_comments = impl_variant_comments(&input.variants, |a| &a.value_comment);
     let name = &input.ident;
     quote! {
         const _: () = {
@@ -167,6 +230,20 @@ fn impl_enum(input: Enum) -> TokenStream {
                         _ => None,
                     }
                 }
+                fn value_comment(&self, variant: Option<&str>, field: &MemberId) -> Option<String> {
+                    let variant = variant?;
+                    match variant {
+                        #(#value_comments,)*
+                        _ => None,
+                    }
+                }
+                fn color(&self, variant: Option<&str>, field: &MemberId) -> Option<&'static str> {
+                    let variant = variant?;
+                    match variant {
+                        #(#colors,)*
+                        _ => None,
+                    }
+                }
                 fn as_annotate(&self) -> Option<&dyn Annotate> { Some(self) }
                 // We don't have to implement `thunk_serialize` because the default implementation
                 // already does what we need.
@@ -2,7 +2,7 @@
 use anyhow::{anyhow, Result};
 use clap::{ArgEnum, Parser};
 use serde::{Deserialize, Serialize};
-use serde_annotate::{serialize, Annotate, ColorProfile};
+use serde_annotate::{serialize, Annotate, ColorProfile, Emit};
 
 #[derive(Serialize, Deserialize, Annotate, Debug, PartialEq)]
 struct Coordinate {
@@ -165,28 +165,28 @@ fn main() -> Result<()> {
             if args.color {
                 d = d.color(profile);
             }
-            d.to_string()
+            d.to_text()?
         }
         Format::Json5 => {
             let mut d = document.to_json5();
             if args.color {
                 d = d.color(profile);
             }
-            d.to_string()
+            d.to_text()?
         }
         Format::Hjson => {
             let mut d = document.to_hjson();
             if args.color {
                 d = d.color(profile);
             }
-            d.to_string()
+            d.to_text()?
         }
         Format::Yaml => {
             let mut d = document.to_yaml();
             if args.color {
                 d = d.color(profile);
             }
-            d.to_string()
+            d.to_text()?
         }
     };
 
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use clap::{ArgEnum, Parser};
-use serde_annotate::{ColorProfile, Document};
+use serde_annotate::{ColorProfile, Document, Emit};
 use std::path::PathBuf;
 
 #[derive(ArgEnum, Clone, Copy, Debug)]
@@ -36,28 +36,28 @@ fn main() -> Result<()> {
             if args.color {
                 d = d.color(profile);
             }
-            d.to_string()
+            d.to_text()?
         }
         Format::Json5 => {
             let mut d = document.to_json5();
             if args.color {
                 d = d.color(profile);
             }
-            d.to_string()
+            d.to_text()?
         }
         Format::Hjson => {
             let mut d = document.to_hjson();
             if args.color {
                 d = d.color(profile);
             }
-            d.to_string()
+            d.to_text()?
         }
         Format::Yaml => {
             let mut d = document.to_yaml();
             if args.color {
                 d = d.color(profile);
             }
-            d.to_string()
+            d.to_text()?
         }
     };
 
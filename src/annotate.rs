@@ -3,6 +3,7 @@ use std::fmt;
 use crate::{AnnotatedSerializer, Deserializer, Document, Error};
 
 /// Specifies the formatting options to use when serializing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Format {
     /// Format a string in block/multiline style.
     Block,
@@ -18,6 +19,8 @@ pub enum Format {
     Compact,
     /// Format a bytes object as a hex string.
     HexStr,
+    /// Format a bytes object as a base64 string (e.g. `base64:mKtFzer/`).
+    Base64,
     /// Format a bytes object as hexdump (e.g. `hexdump -vC <file>`).
     Hexdump,
     /// Format a bytes object as xxd (e.g. `xxd <file>`).
@@ -36,6 +39,15 @@ pub enum MemberId<'a> {
 pub trait Annotate {
     fn format(&self, variant: Option<&str>, field: &MemberId) -> Option<Format>;
     fn comment(&self, variant: Option<&str>, field: &MemberId) -> Option<String>;
+    /// Returns the comment describing the value of a field, which is emitted
+    /// after the value rather than above the key.
+    fn value_comment(&self, _variant: Option<&str>, _field: &MemberId) -> Option<String> {
+        None
+    }
+    /// Returns the user-defined color class of a field's value.
+    fn color(&self, _variant: Option<&str>, _field: &MemberId) -> Option<&'static str> {
+        None
+    }
     fn as_annotate(&self) -> Option<&dyn Annotate>;
     fn thunk_serialize(&self, serializer: &mut AnnotatedSerializer) -> Result<Document, Error>;
 }
@@ -49,6 +61,12 @@ impl<T: ?Sized + serde::Serialize> Annotate for T {
     default fn comment(&self, _variant: Option<&str>, _field: &MemberId) -> Option<String> {
         None
     }
+    default fn value_comment(&self, _variant: Option<&str>, _field: &MemberId) -> Option<String> {
+        None
+    }
+    default fn color(&self, _variant: Option<&str>, _field: &MemberId) -> Option<&'static str> {
+        None
+    }
     default fn as_annotate(&self) -> Option<&dyn Annotate> {
         None
     }
@@ -120,26 +138,36 @@ impl serde::Serialize for dyn Annotate {
                 std::any::type_name::<S>()
             );
         }
-        unsafe {
-            // If `serializer` is the correct type, then we can transmute the
-            // reference into `&mut AnnotatedSerializer` and forget the prior reference.
-            let szr: &mut AnnotatedSerializer = std::mem::transmute_copy(&serializer);
-            std::mem::forget(serializer);
-            let r = self.thunk_serialize(szr);
-            // Similarly, if the `serializer` was the correct type, we can assume the
-            // return type will be correct, and thus the transmute is a no-op... Actually,
-            // its a simple copy because `transmute` can't be sure that
-            // `Result<Document, Error>` is the same size as whatever
-            // `Result<S::Ok, S::Error>` happens to be.  They _will_ be the same size
-            // (indeed the same type) because only `AnnotatedSerializer` is permitted to
-            // call this function and it wants `Result<Document, Error>` returned).
-            let result = std::mem::transmute_copy(&r);
-            std::mem::forget(r);
-            result
-        }
+        // Safety: `serializer` was checked above.
+        unsafe { with_annotated_serializer(serializer, |szr| self.thunk_serialize(szr)) }
     }
 }
 
+// Calls `f` with `serializer` viewed as our own `AnnotatedSerializer`.
+//
+// Safety: the caller must have checked that `serializer.is_serde_annotate()`.
+pub(crate) unsafe fn with_annotated_serializer<S, F>(serializer: S, f: F) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    F: FnOnce(&mut AnnotatedSerializer) -> Result<Document, Error>,
+{
+    // If `serializer` is the correct type, then we can transmute the
+    // reference into `&mut AnnotatedSerializer` and forget the prior reference.
+    let szr: &mut AnnotatedSerializer = std::mem::transmute_copy(&serializer);
+    std::mem::forget(serializer);
+    let r = f(szr);
+    // Similarly, if the `serializer` was the correct type, we can assume the
+    // return type will be correct, and thus the transmute is a no-op... Actually,
+    // its a simple copy because `transmute` can't be sure that
+    // `Result<Document, Error>` is the same size as whatever
+    // `Result<S::Ok, S::Error>` happens to be.  They _will_ be the same size
+    // (indeed the same type) because only `AnnotatedSerializer` is permitted to
+    // call this function and it wants `Result<Document, Error>` returned).
+    let result = std::mem::transmute_copy(&r);
+    std::mem::forget(r);
+    result
+}
+
 impl fmt::Debug for dyn Annotate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "dyn Annotate({:p})", self)
@@ -0,0 +1,190 @@
+// A value carrying its own annotations.
+use crate::annotate::{with_annotated_serializer, Format, IsSerializer};
+use std::ops::{Deref, DerefMut};
+
+/// A value along with the comment, format and color class to use when it
+/// is serialized.
+///
+/// `Annotated` annotates individual values without deriving `Annotate` on
+/// the enclosing type, so it can be used inside ordinary structs and
+/// collections:
+///
+/// ```
+/// use serde_annotate::{serialize, Annotated, Emit};
+/// use std::collections::BTreeMap;
+///
+/// let mut regs = BTreeMap::new();
+/// regs.insert("ctrl", Annotated::new(0x80u32).comment("Enable bit").hex());
+/// let text = serialize(&regs).unwrap().to_json5().to_text().unwrap();
+/// assert_eq!(text, "{\n  // Enable bit\n  ctrl: 0x80\n}");
+/// ```
+///
+/// When serialized by `serde_annotate::serialize` the comment is placed
+/// ahead of the value (or ahead of its key within a mapping).  Other
+/// serializers and all deserializers see only the value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Annotated<T> {
+    value: T,
+    comment: Option<String>,
+    format: Option<Format>,
+    color: Option<String>,
+}
+
+impl<T> Annotated<T> {
+    /// Wraps `value` without any annotations.
+    pub fn new(value: T) -> Self {
+        Annotated {
+            value,
+            comment: None,
+            format: None,
+            color: None,
+        }
+    }
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// Sets the comment to emit with the value.
+    pub fn comment(mut self, c: impl Into<String>) -> Self {
+        self.comment = Some(c.into());
+        self
+    }
+    /// Sets the color class of the value (see `Document::color_class`).
+    pub fn color(mut self, class: impl Into<String>) -> Self {
+        self.color = Some(class.into());
+        self
+    }
+    /// Sets the format of the value.
+    pub fn format(mut self, f: Format) -> Self {
+        self.format = Some(f);
+        self
+    }
+    /// Formats an integer as binary.
+    pub fn bin(self) -> Self {
+        self.format(Format::Binary)
+    }
+    /// Formats an integer as octal.
+    pub fn oct(self) -> Self {
+        self.format(Format::Octal)
+    }
+    /// Formats an integer as decimal.
+    pub fn dec(self) -> Self {
+        self.format(Format::Decimal)
+    }
+    /// Formats an integer as hexadecimal.
+    pub fn hex(self) -> Self {
+        self.format(Format::Hex)
+    }
+    /// Formats a string in block/multiline style.
+    pub fn block(self) -> Self {
+        self.format(Format::Block)
+    }
+    /// Formats an aggregate in compact mode.
+    pub fn compact(self) -> Self {
+        self.format(Format::Compact)
+    }
+}
+
+impl<T> From<T> for Annotated<T> {
+    fn from(value: T) -> Self {
+        Annotated::new(value)
+    }
+}
+
+impl<T> Deref for Annotated<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Annotated<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: serde::Serialize> serde::Serialize for Annotated<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_serde_annotate() {
+            // Safety: `serializer` was checked above.
+            unsafe {
+                with_annotated_serializer(serializer, |szr| {
+                    szr.serialize_annotated(
+                        &self.value,
+                        self.format,
+                        self.comment.as_deref(),
+                        self.color.as_deref(),
+                    )
+                })
+            }
+        } else {
+            self.value.serialize(serializer)
+        }
+    }
+}
+
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Annotated<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Annotated::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::emit::Emit;
+    use crate::integer::Base;
+    use crate::ser::serialize;
+    use anyhow::Result;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Register {
+        name: String,
+        value: Annotated<u32>,
+        fields: Vec<Annotated<u8>>,
+    }
+
+    #[test]
+    fn test_annotated() -> Result<()> {
+        let reg = Register {
+            name: "ctrl".into(),
+            value: Annotated::new(0x1234).comment("Reset value").hex(),
+            fields: vec![
+                Annotated::new(5).comment("Mode").bin(),
+                Annotated::new(8).oct().color("warning"),
+            ],
+        };
+        let doc = serialize(&reg)?;
+        assert_eq!(
+            doc.to_json5()
+                .literals(&[Base::Bin, Base::Oct, Base::Hex])
+                .to_text()?,
+            r#"{
+  name: "ctrl",
+  // Reset value
+  value: 0x1234,
+  fields: [
+    // Mode
+    0b101,
+    0o10
+  ]
+}"#
+        );
+        let doc = serialize(&reg)?;
+        assert!(doc
+            .iter()
+            .any(|n| matches!(n, Document::ColorClass(c, _) if c == "warning")));
+
+        // Other serializers see only the value.
+        let json = serde_json::to_string(&reg)?;
+        assert_eq!(json, r#"{"name":"ctrl","value":4660,"fields":[5,8]}"#);
+        let back: Register = crate::from_str(&doc.to_json().to_text()?)?;
+        assert_eq!(*back.value, 0x1234);
+        assert_eq!(back.fields.iter().map(|f| **f).collect::<Vec<_>>(), [5, 8]);
+        Ok(())
+    }
+}
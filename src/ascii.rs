@@ -0,0 +1,127 @@
+// Conversion of text to pure ASCII for the `ascii_only` emitter mode.
+use std::borrow::Cow;
+
+/// How comments containing non-ASCII characters are rendered when
+/// ASCII-only output is requested.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AsciiComments {
+    /// Non-ASCII characters are written as `\uXXXX` escapes.
+    Escape,
+    /// Non-ASCII characters are replaced by a close ASCII approximation
+    /// (e.g. `é` becomes `e`), or `?` if there is none.
+    Transliterate,
+    /// A comment containing non-ASCII characters fails rendering with
+    /// `Error::NonAsciiComment`, whether or not ASCII-only output is
+    /// requested.
+    Reject,
+}
+
+impl AsciiComments {
+    pub(crate) fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if text.is_ascii() {
+            return Cow::Borrowed(text);
+        }
+        let mut out = String::with_capacity(text.len());
+        for ch in text.chars() {
+            if ch.is_ascii() {
+                out.push(ch);
+                continue;
+            }
+            match self {
+                AsciiComments::Escape => escape_char(&mut out, ch),
+                AsciiComments::Transliterate => out.push_str(transliterate(ch)),
+                // Rejected comments are never rendered.
+                AsciiComments::Reject => out.push(ch),
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+/// Appends the `\u` escape sequence(s) for `ch`, using a UTF-16 surrogate
+/// pair for characters outside of the basic multilingual plane.
+pub(crate) fn escape_char(out: &mut String, ch: char) {
+    let mut buf = [0u16; 2];
+    for unit in ch.encode_utf16(&mut buf) {
+        out.push_str(&format!("\\u{:04x}", unit));
+    }
+}
+
+// Returns an ASCII approximation of a non-ASCII character.
+fn transliterate(ch: char) -> &'static str {
+    match ch {
+        'À'..='Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+        'à'..='å' | 'ā' | 'ă' | 'ą' => "a",
+        'Æ' => "AE",
+        'æ' => "ae",
+        'Ç' | 'Ć' | 'Ĉ' | 'Ċ' | 'Č' => "C",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'Ð' | 'Ď' | 'Đ' => "D",
+        'ð' | 'ď' | 'đ' => "d",
+        'È'..='Ë' | 'Ē' | 'Ĕ' | 'Ė' | 'Ę' | 'Ě' => "E",
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'Ĝ' | 'Ğ' | 'Ġ' | 'Ģ' => "G",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'Ì'..='Ï' | 'Ĩ' | 'Ī' | 'Ĭ' | 'Į' | 'İ' => "I",
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'Ł' | 'Ĺ' | 'Ļ' | 'Ľ' => "L",
+        'ł' | 'ĺ' | 'ļ' | 'ľ' => "l",
+        'Ñ' | 'Ń' | 'Ņ' | 'Ň' => "N",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'Ò'..='Ö' | 'Ø' | 'Ō' | 'Ŏ' | 'Ő' => "O",
+        'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'Œ' => "OE",
+        'œ' => "oe",
+        'Ŕ' | 'Ŗ' | 'Ř' => "R",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'Ś' | 'Ŝ' | 'Ş' | 'Š' => "S",
+        'ś' | 'ŝ' | 'ş' | 'š' => "s",
+        'ß' => "ss",
+        'Ţ' | 'Ť' => "T",
+        'ţ' | 'ť' => "t",
+        'Þ' => "TH",
+        'þ' => "th",
+        'Ù'..='Ü' | 'Ũ' | 'Ū' | 'Ŭ' | 'Ů' | 'Ű' | 'Ų' => "U",
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'Ý' | 'Ÿ' => "Y",
+        'ý' | 'ÿ' => "y",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        'ź' | 'ż' | 'ž' => "z",
+        '\u{a0}' | '\u{2000}'..='\u{200a}' => " ",
+        '‐' | '‑' | '‒' | '–' | '—' | '―' | '−' => "-",
+        '‘' | '’' | '‚' | '′' => "'",
+        '“' | '”' | '„' | '″' => "\"",
+        '…' => "...",
+        '•' | '·' => "*",
+        '×' => "x",
+        '÷' => "/",
+        '©' => "(c)",
+        '®' => "(R)",
+        '™' => "(TM)",
+        '°' => "deg",
+        'µ' | 'μ' => "u",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_comments() {
+        let text = "Größe — 5µs “fast” 😀";
+        assert_eq!(
+            AsciiComments::Transliterate.apply(text),
+            "Grosse - 5us \"fast\" ?"
+        );
+        assert_eq!(
+            AsciiComments::Escape.apply(text),
+            "Gr\\u00f6\\u00dfe \\u2014 5\\u00b5s \\u201cfast\\u201d \\ud83d\\ude00"
+        );
+        assert!(matches!(
+            AsciiComments::Escape.apply("plain"),
+            Cow::Borrowed(_)
+        ));
+    }
+}
@@ -0,0 +1,539 @@
+// Binary encodings of documents: CBOR (RFC 8949) and MessagePack.
+use crate::document::{CommentFormat, Document};
+use crate::entry::{MapEntry, SeqItem};
+use crate::error::Error;
+use crate::integer::{Int, IntValue};
+use crate::sort::KeySort;
+use std::io;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The CBOR tag under which `Cbor::comments` encodes a value with its
+/// comments.  The tag lies in the first-come-first-served range of the
+/// IANA registry and is not registered; it spells "cmnt" in ASCII.
+pub const CBOR_COMMENT_TAG: u64 = 0x636d_6e74;
+
+/// A CBOR (RFC 8949) encoding of a document.
+///
+/// Integers are encoded in the smallest head which holds them, with
+/// integers beyond 64 bits encoded as bignums (tags 2 and 3), and floats
+/// as 64-bit floats.  Comments are dropped unless `Cbor::comments` is
+/// set.
+pub struct Cbor {
+    document: Document,
+    comments: bool,
+    sort_keys: KeySort,
+    max_depth: Option<usize>,
+}
+
+impl Cbor {
+    /// Set whether comments are encoded (the default is `false`).  A value
+    /// with comments is encoded as `CBOR_COMMENT_TAG` applied to the array
+    /// `[value, [comments before], [comments after]]`, in which the
+    /// comments are text strings.  Readers which do not know the tag see it
+    /// as an unknown tag around an array.  Comments which do not belong to
+    /// a value, such as those at the end of a mapping, are dropped.
+    pub fn comments(mut self, b: bool) -> Self {
+        self.comments = b;
+        self
+    }
+    /// Set the order in which the entries of mappings are encoded.
+    pub fn sort_keys(mut self, s: KeySort) -> Self {
+        self.sort_keys = s;
+        self
+    }
+    /// Fail encoding with `Error::DepthLimit` when mappings and sequences
+    /// nest more than `n` deep.
+    pub fn max_depth(mut self, n: usize) -> Self {
+        self.max_depth = Some(n);
+        self
+    }
+
+    /// Returns the encoded document.
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut encoder = Encoder {
+            format: CborFormat {
+                comments: self.comments,
+            },
+            out: Vec::new(),
+            sort_keys: &self.sort_keys,
+            depth: 0,
+            max_depth: self.max_depth,
+        };
+        encoder.encode_item(&self.document)?;
+        Ok(encoder.out)
+    }
+
+    /// Writes the encoded document to `w`.  Failures of `w` are reported
+    /// as `Error::IoError`.
+    pub fn write_to<W: io::Write>(&self, mut w: W) -> Result<()> {
+        Ok(w.write_all(&self.to_vec()?)?)
+    }
+}
+
+/// A MessagePack encoding of a document.
+///
+/// Integers and lengths are encoded in the smallest form which holds them,
+/// and floats as 64-bit floats.  MessagePack has no place for comments,
+/// so they are dropped.  Integers beyond 64 bits cannot be encoded.
+pub struct MsgPack {
+    document: Document,
+    sort_keys: KeySort,
+    max_depth: Option<usize>,
+}
+
+impl MsgPack {
+    /// Set the order in which the entries of mappings are encoded.
+    pub fn sort_keys(mut self, s: KeySort) -> Self {
+        self.sort_keys = s;
+        self
+    }
+    /// Fail encoding with `Error::DepthLimit` when mappings and sequences
+    /// nest more than `n` deep.
+    pub fn max_depth(mut self, n: usize) -> Self {
+        self.max_depth = Some(n);
+        self
+    }
+
+    /// Returns the encoded document.
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut encoder = Encoder {
+            format: MsgPackFormat,
+            out: Vec::new(),
+            sort_keys: &self.sort_keys,
+            depth: 0,
+            max_depth: self.max_depth,
+        };
+        encoder.encode_item(&self.document)?;
+        Ok(encoder.out)
+    }
+
+    /// Writes the encoded document to `w`.  Failures of `w` are reported
+    /// as `Error::IoError`.
+    pub fn write_to<W: io::Write>(&self, mut w: W) -> Result<()> {
+        Ok(w.write_all(&self.to_vec()?)?)
+    }
+}
+
+impl Document {
+    pub fn to_cbor(self) -> Cbor {
+        Cbor {
+            document: self,
+            comments: false,
+            sort_keys: KeySort::default(),
+            max_depth: None,
+        }
+    }
+
+    pub fn to_msgpack(self) -> MsgPack {
+        MsgPack {
+            document: self,
+            sort_keys: KeySort::default(),
+            max_depth: None,
+        }
+    }
+}
+
+// Returns the sign and magnitude of an integer.
+fn sign_magnitude(i: &Int) -> (bool, u128) {
+    match *i.value() {
+        IntValue::U128(v) => (false, v),
+        // Unwrap is ok: every other type converts to an `i128`.
+        _ => {
+            let v = i.as_i128().unwrap();
+            (v < 0, v.unsigned_abs())
+        }
+    }
+}
+
+// The encoding of each kind of value in a binary format.
+trait Format {
+    const NAME: &'static str;
+
+    // Encodes the comments around a value ahead of the value, returning
+    // whether they were encoded.
+    fn comments(&self, _out: &mut Vec<u8>, _before: &[&str], _after: &[&str]) -> bool {
+        false
+    }
+    fn int(&self, out: &mut Vec<u8>, i: &Int) -> Result<()>;
+    fn float(&self, out: &mut Vec<u8>, f: f64);
+    fn string(&self, out: &mut Vec<u8>, s: &str) -> Result<()>;
+    fn bytes(&self, out: &mut Vec<u8>, b: &[u8]) -> Result<()>;
+    fn boolean(&self, out: &mut Vec<u8>, b: bool);
+    fn null(&self, out: &mut Vec<u8>);
+    fn array(&self, out: &mut Vec<u8>, len: usize) -> Result<()>;
+    fn map(&self, out: &mut Vec<u8>, len: usize) -> Result<()>;
+}
+
+struct CborFormat {
+    comments: bool,
+}
+
+impl CborFormat {
+    // Encodes the head of a data item: its major type and argument.
+    fn head(out: &mut Vec<u8>, major: u8, arg: u64) {
+        let major = major << 5;
+        match arg {
+            0..=23 => out.push(major | arg as u8),
+            24..=0xFF => out.extend([major | 24, arg as u8]),
+            0x100..=0xFFFF => {
+                out.push(major | 25);
+                out.extend((arg as u16).to_be_bytes());
+            }
+            0x1_0000..=0xFFFF_FFFF => {
+                out.push(major | 26);
+                out.extend((arg as u32).to_be_bytes());
+            }
+            _ => {
+                out.push(major | 27);
+                out.extend(arg.to_be_bytes());
+            }
+        }
+    }
+}
+
+impl Format for CborFormat {
+    const NAME: &'static str = "CBOR";
+
+    fn comments(&self, out: &mut Vec<u8>, before: &[&str], after: &[&str]) -> bool {
+        if !self.comments || (before.is_empty() && after.is_empty()) {
+            return false;
+        }
+        Self::head(out, 6, CBOR_COMMENT_TAG);
+        Self::head(out, 4, 3);
+        // The value follows, and then the comments, which are encoded by
+        // `Encoder::encode_value` once the value has been encoded.
+        true
+    }
+
+    fn int(&self, out: &mut Vec<u8>, i: &Int) -> Result<()> {
+        let (negative, magnitude) = sign_magnitude(i);
+        // A negative integer `n` is encoded as `-1 - n`.
+        let (major, tag, arg) = match negative {
+            false => (0, 2, magnitude),
+            true => (1, 3, magnitude - 1),
+        };
+        match u64::try_from(arg) {
+            Ok(arg) => Self::head(out, major, arg),
+            Err(_) => {
+                Self::head(out, 6, tag);
+                let bytes = arg.to_be_bytes();
+                let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+                self.bytes(out, &bytes[start..])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn float(&self, out: &mut Vec<u8>, f: f64) {
+        out.push(0xFB);
+        out.extend(f.to_be_bytes());
+    }
+
+    fn string(&self, out: &mut Vec<u8>, s: &str) -> Result<()> {
+        Self::head(out, 3, s.len() as u64);
+        out.extend(s.as_bytes());
+        Ok(())
+    }
+
+    fn bytes(&self, out: &mut Vec<u8>, b: &[u8]) -> Result<()> {
+        Self::head(out, 2, b.len() as u64);
+        out.extend(b);
+        Ok(())
+    }
+
+    fn boolean(&self, out: &mut Vec<u8>, b: bool) {
+        out.push(if b { 0xF5 } else { 0xF4 });
+    }
+
+    fn null(&self, out: &mut Vec<u8>) {
+        out.push(0xF6);
+    }
+
+    fn array(&self, out: &mut Vec<u8>, len: usize) -> Result<()> {
+        Self::head(out, 4, len as u64);
+        Ok(())
+    }
+
+    fn map(&self, out: &mut Vec<u8>, len: usize) -> Result<()> {
+        Self::head(out, 5, len as u64);
+        Ok(())
+    }
+}
+
+struct MsgPackFormat;
+
+impl MsgPackFormat {
+    // Encodes the marker of a string, bytes, array or map and its length
+    // in the smallest of the given forms.
+    fn length(
+        out: &mut Vec<u8>,
+        len: usize,
+        fixed: Option<(u8, usize)>,
+        markers: [Option<u8>; 3],
+    ) -> Result<()> {
+        match (fixed, markers) {
+            (Some((marker, limit)), _) if len < limit => out.push(marker | len as u8),
+            (_, [Some(m), _, _]) if len <= 0xFF => out.extend([m, len as u8]),
+            (_, [_, Some(m), _]) if len <= 0xFFFF => {
+                out.push(m);
+                out.extend((len as u16).to_be_bytes());
+            }
+            (_, [_, _, Some(m)]) if len <= 0xFFFF_FFFF => {
+                out.push(m);
+                out.extend((len as u32).to_be_bytes());
+            }
+            _ => {
+                return Err(Error::Unrepresentable(
+                    "a value of 2^32 or more items",
+                    "MessagePack",
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Format for MsgPackFormat {
+    const NAME: &'static str = "MessagePack";
+
+    fn int(&self, out: &mut Vec<u8>, i: &Int) -> Result<()> {
+        let outside =
+            || Error::Unrepresentable("an integer outside the 64-bit range", "MessagePack");
+        match sign_magnitude(i) {
+            (false, v) => match u64::try_from(v).map_err(|_| outside())? {
+                v @ 0..=0x7F => out.push(v as u8),
+                v @ 0x80..=0xFF => out.extend([0xCC, v as u8]),
+                v @ 0x100..=0xFFFF => {
+                    out.push(0xCD);
+                    out.extend((v as u16).to_be_bytes());
+                }
+                v @ 0x1_0000..=0xFFFF_FFFF => {
+                    out.push(0xCE);
+                    out.extend((v as u32).to_be_bytes());
+                }
+                v => {
+                    out.push(0xCF);
+                    out.extend(v.to_be_bytes());
+                }
+            },
+            (true, _) => {
+                let v = i64::try_from(i.as_i128().ok_or_else(outside)?).map_err(|_| outside())?;
+                match v {
+                    -32..=-1 => out.push(v as u8),
+                    -0x80..=-33 => out.extend([0xD0, v as u8]),
+                    -0x8000..=-0x81 => {
+                        out.push(0xD1);
+                        out.extend((v as i16).to_be_bytes());
+                    }
+                    -0x8000_0000..=-0x8001 => {
+                        out.push(0xD2);
+                        out.extend((v as i32).to_be_bytes());
+                    }
+                    _ => {
+                        out.push(0xD3);
+                        out.extend(v.to_be_bytes());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn float(&self, out: &mut Vec<u8>, f: f64) {
+        out.push(0xCB);
+        out.extend(f.to_be_bytes());
+    }
+
+    fn string(&self, out: &mut Vec<u8>, s: &str) -> Result<()> {
+        let markers = [Some(0xD9), Some(0xDA), Some(0xDB)];
+        Self::length(out, s.len(), Some((0xA0, 32)), markers)?;
+        out.extend(s.as_bytes());
+        Ok(())
+    }
+
+    fn bytes(&self, out: &mut Vec<u8>, b: &[u8]) -> Result<()> {
+        let markers = [Some(0xC4), Some(0xC5), Some(0xC6)];
+        Self::length(out, b.len(), None, markers)?;
+        out.extend(b);
+        Ok(())
+    }
+
+    fn boolean(&self, out: &mut Vec<u8>, b: bool) {
+        out.push(if b { 0xC3 } else { 0xC2 });
+    }
+
+    fn null(&self, out: &mut Vec<u8>) {
+        out.push(0xC0);
+    }
+
+    fn array(&self, out: &mut Vec<u8>, len: usize) -> Result<()> {
+        Self::length(out, len, Some((0x90, 16)), [None, Some(0xDC), Some(0xDD)])
+    }
+
+    fn map(&self, out: &mut Vec<u8>, len: usize) -> Result<()> {
+        Self::length(out, len, Some((0x80, 16)), [None, Some(0xDE), Some(0xDF)])
+    }
+}
+
+struct Encoder<'a, F: Format> {
+    format: F,
+    out: Vec<u8>,
+    sort_keys: &'a KeySort,
+    depth: usize,
+    max_depth: Option<usize>,
+}
+
+impl<F: Format> Encoder<'_, F> {
+    // Encodes a sequence element or the document itself.
+    fn encode_item(&mut self, node: &Document) -> Result<()> {
+        let item = SeqItem::new(node)?;
+        self.encode_value(item.value, &item.comments, &item.trailing)
+    }
+
+    // Encodes a value along with the comments around it, if the format
+    // encodes comments.
+    fn encode_value(
+        &mut self,
+        node: &Document,
+        before: &[(&str, CommentFormat)],
+        after: &[(&str, CommentFormat)],
+    ) -> Result<()> {
+        let before = before.iter().map(|(c, _)| *c).collect::<Vec<_>>();
+        let after = after.iter().map(|(c, _)| *c).collect::<Vec<_>>();
+        let commented = self.format.comments(&mut self.out, &before, &after);
+        self.encode(node)?;
+        if commented {
+            for comments in [before, after] {
+                self.format.array(&mut self.out, comments.len())?;
+                for c in comments {
+                    self.format.string(&mut self.out, c)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self, node: &Document) -> Result<()> {
+        match node {
+            Document::String(s, _) => self.format.string(&mut self.out, s)?,
+            Document::StaticStr(s, _) => self.format.string(&mut self.out, s)?,
+            Document::Boolean(b) => self.format.boolean(&mut self.out, *b),
+            Document::Int(i) => self.format.int(&mut self.out, i)?,
+            Document::Float(f) => self.format.float(&mut self.out, *f),
+            Document::Bytes(b) => self.format.bytes(&mut self.out, b)?,
+            Document::Null => self.format.null(&mut self.out),
+            Document::Mapping(m) => {
+                self.enter()?;
+                let entries = self
+                    .sort_keys
+                    .order(m)
+                    .into_iter()
+                    .filter_map(|e| MapEntry::new(e).ok())
+                    .collect::<Vec<_>>();
+                self.format.map(&mut self.out, entries.len())?;
+                for e in entries {
+                    self.encode(e.key)?;
+                    self.encode_value(e.value, &e.comments, &e.trailing)?;
+                }
+                self.depth -= 1;
+            }
+            Document::Sequence(s) => {
+                self.enter()?;
+                let items = s
+                    .iter()
+                    .filter_map(|e| SeqItem::new(e).ok())
+                    .collect::<Vec<_>>();
+                self.format.array(&mut self.out, items.len())?;
+                for i in items {
+                    self.encode_value(i.value, &i.comments, &i.trailing)?;
+                }
+                self.depth -= 1;
+            }
+            Document::Fragment(_) => self.encode_item(node)?,
+            Document::Compact(d)
+            | Document::ColorClass(_, d)
+            | Document::Identified(_, d)
+            | Document::Typed(_, d) => self.encode(d)?,
+            Document::Absent => return Err(Error::AbsentValue),
+            Document::Comment(_, _) => {
+                return Err(Error::Unrepresentable(
+                    "a document holding only comments",
+                    F::NAME,
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        match self.max_depth {
+            Some(n) if self.depth >= n => Err(Error::DepthLimit(n)),
+            _ => {
+                self.depth += 1;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    const DOC: &str = r#"{
+        // The name.
+        name: "a",
+        list: [1, -1, 500, -500],
+        ok: true,
+        none: null,
+    }"#;
+
+    #[test]
+    fn test_cbor() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_cbor().to_vec()?,
+            b"\xA4\x64name\x61a\x64list\x84\x01\x20\x19\x01\xF4\x39\x01\xF3\x62ok\xF5\x64none\xF6"
+        );
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_cbor().comments(true).to_vec()?,
+            b"\xA4\x64name\xDA\x63\x6D\x6E\x74\x83\x61a\x81\x69The name.\x80\
+              \x64list\x84\x01\x20\x19\x01\xF4\x39\x01\xF3\x62ok\xF5\x64none\xF6"
+        );
+        let doc = Document::Int(Int::new(u128::MAX, crate::integer::Base::Dec));
+        let mut expected = b"\xC2\x50".to_vec();
+        expected.extend([0xFF; 16]);
+        assert_eq!(doc.to_cbor().to_vec()?, expected);
+        let doc = Document::Float(1.5);
+        assert_eq!(doc.to_cbor().to_vec()?, b"\xFB\x3F\xF8\0\0\0\0\0\0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_msgpack() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_msgpack().to_vec()?,
+            b"\x84\xA4name\xA1a\xA4list\x94\x01\xFF\xCD\x01\xF4\xD1\xFE\x0C\xA2ok\xC3\xA4none\xC0"
+        );
+        let doc = Document::Bytes(vec![0; 300].into());
+        assert_eq!(doc.to_msgpack().to_vec()?[..3], [0xC5, 0x01, 0x2C]);
+        let doc = Document::Int(Int::new(u128::MAX, crate::integer::Base::Dec));
+        assert!(doc.to_msgpack().to_vec().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrapped_nodes() -> Result<()> {
+        let doc = Document::Compact(Box::new(Document::Sequence(vec![Document::Boolean(true)])));
+        assert_eq!(doc.to_cbor().to_vec()?, b"\x81\xF5");
+        let doc = Document::ColorClass("warn".into(), Box::new(Document::Boolean(true)));
+        assert_eq!(doc.to_msgpack().to_vec()?, b"\xC3");
+        let doc = Document::parse("{a: 1}")?.color_class("/a", "warn");
+        assert_eq!(doc.to_msgpack().to_vec()?, b"\x81\xA1a\x01");
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+// A shared bytes buffer which serializes without copying.
+use crate::annotate::{with_annotated_serializer, IsSerializer};
+use serde::de::{self, SeqAccess, Visitor};
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A reference-counted bytes buffer.
+///
+/// When serialized by `serde_annotate::serialize`, the resulting
+/// `Document::Bytes` node shares the buffer instead of copying it, so large
+/// payloads (e.g. firmware images) are not duplicated in memory.  Other
+/// serializers see an ordinary `serialize_bytes` call.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SharedBytes(Arc<[u8]>);
+
+impl SharedBytes {
+    /// Returns the underlying shared buffer.
+    pub fn into_inner(self) -> Arc<[u8]> {
+        self.0
+    }
+}
+
+impl Deref for SharedBytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Arc<[u8]>> for SharedBytes {
+    fn from(b: Arc<[u8]>) -> Self {
+        SharedBytes(b)
+    }
+}
+
+impl From<Vec<u8>> for SharedBytes {
+    fn from(b: Vec<u8>) -> Self {
+        SharedBytes(b.into())
+    }
+}
+
+impl From<&[u8]> for SharedBytes {
+    fn from(b: &[u8]) -> Self {
+        SharedBytes(b.into())
+    }
+}
+
+impl serde::Serialize for SharedBytes {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_serde_annotate() {
+            // Safety: `serializer` was checked above.
+            unsafe {
+                with_annotated_serializer(serializer, |szr| {
+                    szr.serialize_shared_bytes(&self.0, || self.0.clone())
+                })
+            }
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SharedBytes {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_byte_buf(SharedBytesVisitor)
+    }
+}
+
+struct SharedBytesVisitor;
+
+impl<'de> Visitor<'de> for SharedBytesVisitor {
+    type Value = SharedBytes;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a byte array")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut v = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(b) = seq.next_element()? {
+            v.push(b);
+        }
+        Ok(v.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::emit::Emit;
+    use crate::ser::serialize;
+    use anyhow::Result;
+
+    #[test]
+    fn test_shared_bytes() -> Result<()> {
+        let bytes = SharedBytes::from(vec![1u8, 2, 3, 0xff]);
+        let doc = serialize(&bytes)?;
+        match &doc {
+            Document::Bytes(b) => assert!(Arc::ptr_eq(b, &bytes.clone().into_inner())),
+            _ => panic!("Expected bytes"),
+        }
+        let text = doc.to_json().compact(true).to_text()?;
+        assert_eq!(text, "[1, 2, 3, 255]");
+        let back: SharedBytes = crate::from_str(&text)?;
+        assert_eq!(back, bytes);
+
+        // Other serializers receive ordinary bytes.
+        assert_eq!(serde_json::to_string(&bytes)?, "[1,2,3,255]");
+        let back: SharedBytes = serde_json::from_str("[1,2,3,255]")?;
+        assert_eq!(back, bytes);
+        Ok(())
+    }
+}
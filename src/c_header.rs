@@ -0,0 +1,834 @@
+use crate::color::ColorProfile;
+use crate::document::{CommentFormat, Document};
+use crate::emit::{common_options, guarded, CommonOptions, Emit};
+use crate::entry::{comments_of, MapEntry, SeqItem};
+use crate::error::Error;
+use crate::integer::{Base, Int};
+use crate::layout::TokenClass;
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use crate::render::float_literal;
+use crate::sort::KeySort;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Error>;
+
+const FORMAT: &str = "a C header";
+
+// The number of elements written on each line of a table.
+const ITEMS_PER_LINE: usize = 8;
+
+/// The letter case of the names defined by `CHeader`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NameCase {
+    /// `SERVER_BASE_ADDR`: words split at case changes, upper case.
+    #[default]
+    Upper,
+    /// `server_base_addr`: words split at case changes, lower case.
+    Lower,
+    /// `server_baseAddr`: keys as they are.
+    Preserve,
+}
+
+/// A document rendered as a C header.
+///
+/// The document must be a mapping.  Nested mappings are flattened, with the
+/// name of each definition mangled from the path of keys leading to it
+/// (`{server: {port: 80}}` defines `SERVER_PORT`).  Scalars are defined as
+/// macros (`#define SERVER_PORT 80`), or as `static const` variables if
+/// `CHeader::defines` is false.  Sequences of scalars and bytes are defined
+/// as `static const` tables whose element type is the smallest fixed-width
+/// type holding all of the elements.  Other sequences are flattened with
+/// their indices in the names (`SERVERS_0_PORT`).
+///
+/// Integers keep their annotated base, except that binary is written as
+/// hexadecimal.  Booleans are written as `true` and `false`, and null as
+/// an empty macro.  The headers required by the definitions (`<stdint.h>`
+/// and `<stdbool.h>`) are included.  Comments are written as block
+/// comments: those ahead of an entry above it and those after its value at
+/// the end of its line.
+pub struct CHeader {
+    document: Document,
+    common: CommonOptions,
+    prefix: String,
+    separator: String,
+    name_case: NameCase,
+    guard: Option<String>,
+    defines: bool,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+}
+
+impl CHeader {
+    common_options!(
+        /// Set the amount of indentation of the elements of tables.
+        indent,
+        /// Set whether the header is emitted without comments, with each table
+        /// on a single line.
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        max_depth,
+        /// Set the order in which the entries of mappings are emitted.
+        sort_keys,
+        newline,
+        ascii_comments,
+    );
+    /// Set the prefix of every defined name (e.g. `"BOARD"` defines
+    /// `BOARD_SERVER_PORT`).  The prefix is mangled as a key.
+    pub fn prefix(mut self, s: &str) -> Self {
+        self.prefix = s.to_string();
+        self
+    }
+    /// Set the text between the words of a defined name (the default is
+    /// `"_"`).
+    pub fn separator(mut self, s: &str) -> Self {
+        self.separator = s.to_string();
+        self
+    }
+    /// Set the letter case of the defined names.
+    pub fn name_case(mut self, c: NameCase) -> Self {
+        self.name_case = c;
+        self
+    }
+    /// Wraps the header in an include guard defining `name`.
+    pub fn guard(mut self, name: &str) -> Self {
+        self.guard = Some(name.to_string());
+        self
+    }
+    /// Set whether scalars are defined as macros (the default is `true`)
+    /// rather than `static const` variables.
+    pub fn defines(mut self, b: bool) -> Self {
+        self.defines = b;
+        self
+    }
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.  `FloatFormat::Hex`
+    /// renders as C99 hexadecimal float literals.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+
+    fn emit<W: fmt::Write>(&self, w: &mut W) -> Result<()> {
+        self.common.check_comments(&self.document)?;
+        let mut emitter = CHeaderEmitter {
+            color: self.common.color.clone(),
+            compact: self.common.compact,
+            indent: self.common.indent,
+            sort_keys: self.common.sort_keys.clone(),
+            separator: &self.separator,
+            name_case: self.name_case,
+            defines: self.defines,
+            numbers: self.numbers.clone(),
+            float_format: self.float_format,
+            depth: 0,
+            max_depth: self.common.max_depth,
+            names: HashSet::new(),
+            definitions: Vec::new(),
+        };
+        let prefix = match self.prefix.as_str() {
+            "" => String::new(),
+            p => emitter.mangle("", p),
+        };
+        emitter.collect_document(&prefix, &self.document)?;
+        emitter.emit_header(w, self.guard.as_deref())
+    }
+}
+
+impl Emit for CHeader {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.common, w, |mut w| self.emit(&mut w))
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.common
+    }
+}
+
+impl Document {
+    pub fn to_c_header(self) -> CHeader {
+        CHeader {
+            document: self,
+            common: CommonOptions::default(),
+            prefix: String::new(),
+            separator: "_".to_string(),
+            name_case: NameCase::Upper,
+            guard: None,
+            defines: true,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+        }
+    }
+}
+
+// The C type of a scalar.  Integers are typed by the range of their
+// values, so that a table takes the smallest type holding all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CType {
+    Bool,
+    Integer(i128, i128),
+    Double,
+    String,
+}
+
+impl CType {
+    const BYTE: CType = CType::Integer(0, u8::MAX as i128);
+
+    fn of_int(i: &Int) -> Result<CType> {
+        match i.as_i128() {
+            Some(v) if (i64::MIN as i128..=u64::MAX as i128).contains(&v) => {
+                Ok(CType::Integer(v, v))
+            }
+            _ => Err(Error::Unrepresentable("an integer beyond 64 bits", FORMAT)),
+        }
+    }
+
+    // Returns the type holding values of both types.
+    fn join(self, other: CType) -> Result<CType> {
+        match (self, other) {
+            (CType::Integer(a, b), CType::Integer(c, d)) => match (a.min(c), b.max(d)) {
+                (min, max) if min < 0 && max > i64::MAX as i128 => Err(Error::Unrepresentable(
+                    "a table of mixed-sign 64-bit integers",
+                    FORMAT,
+                )),
+                (min, max) => Ok(CType::Integer(min, max)),
+            },
+            (CType::Double, CType::Integer(..)) | (CType::Integer(..), CType::Double) => {
+                Ok(CType::Double)
+            }
+            (a, b) if a == b => Ok(a),
+            _ => Err(Error::Unrepresentable("a table of mixed types", FORMAT)),
+        }
+    }
+
+    // Returns the name of the type, as written before the declared name.
+    fn name(self) -> String {
+        match self {
+            CType::Bool => "bool".to_string(),
+            CType::Integer(min, max) => {
+                let signed = min < 0;
+                let bits = [8, 16, 32, 64]
+                    .into_iter()
+                    .find(|&n| match signed {
+                        true => min >= -(1 << (n - 1)) && max < 1 << (n - 1),
+                        false => max < 1 << n,
+                    })
+                    .unwrap_or(64);
+                format!("{}int{}_t", if signed { "" } else { "u" }, bits)
+            }
+            CType::Double => "double".to_string(),
+            CType::String => "char *const".to_string(),
+        }
+    }
+}
+
+// A scalar value: its text and the class in which it is colored.
+struct Value {
+    text: String,
+    class: TokenClass,
+    ctype: Option<CType>,
+}
+
+enum Body {
+    // A comment between definitions.
+    Comment,
+    // A scalar, defined as a macro or a variable.
+    Scalar(Value),
+    // A table of scalars.
+    Table(CType, Vec<Value>),
+}
+
+struct Definition {
+    comments: Vec<String>,
+    name: String,
+    body: Body,
+    trailing: Vec<String>,
+}
+
+struct CHeaderEmitter<'a> {
+    color: ColorProfile,
+    compact: bool,
+    indent: usize,
+    sort_keys: KeySort,
+    separator: &'a str,
+    name_case: NameCase,
+    defines: bool,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    depth: usize,
+    max_depth: Option<usize>,
+    names: HashSet<String>,
+    definitions: Vec<Definition>,
+}
+
+impl CHeaderEmitter<'_> {
+    // Returns the name of the key `key` beneath the name `prefix`.  Keys
+    // are split into words at characters which are not valid in C names
+    // and, unless the case is preserved, where lower case is followed by
+    // upper case.
+    fn mangle(&self, prefix: &str, key: &str) -> String {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut lower = false;
+        for ch in key.chars() {
+            let split = self.name_case != NameCase::Preserve && lower && ch.is_ascii_uppercase();
+            if !ch.is_ascii_alphanumeric() || split {
+                words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+            }
+            if ch.is_ascii_alphanumeric() {
+                word.push(match self.name_case {
+                    NameCase::Upper => ch.to_ascii_uppercase(),
+                    NameCase::Lower => ch.to_ascii_lowercase(),
+                    NameCase::Preserve => ch,
+                });
+            }
+            lower = ch.is_ascii_lowercase() || ch.is_ascii_digit();
+        }
+        words.extend((!word.is_empty()).then_some(word));
+        let name = words.join(self.separator);
+        match (prefix, name.as_str()) {
+            ("", "") => "_".to_string(),
+            ("", n) if n.starts_with(|c: char| c.is_ascii_digit()) => format!("_{}", n),
+            ("", _) => name,
+            (p, "") => p.to_string(),
+            (p, n) => format!("{}{}{}", p, self.separator, n),
+        }
+    }
+
+    fn collect_document(&mut self, prefix: &str, node: &Document) -> Result<()> {
+        let nodes = match node {
+            Document::Fragment(ds) => &ds[..],
+            n => std::slice::from_ref(n),
+        };
+        for node in nodes {
+            match node.comment() {
+                Some((c, _)) => self.collect_comment(c),
+                None => match node.as_value()? {
+                    Document::Mapping(m) => self.collect_mapping(prefix, m)?,
+                    v => return Err(Error::StructureError("Mapping", v.variant())),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_comment(&mut self, comment: &str) {
+        self.definitions.push(Definition {
+            comments: vec![comment.to_string()],
+            name: String::new(),
+            body: Body::Comment,
+            trailing: Vec::new(),
+        });
+    }
+
+    // Collects the definitions of the entries of a mapping whose names are
+    // prefixed by `prefix`.
+    fn collect_mapping(&mut self, prefix: &str, mapping: &[Document]) -> Result<()> {
+        self.enter()?;
+        for node in self.sort_keys.order(mapping) {
+            let entry = match MapEntry::new(node) {
+                Ok(entry) => entry,
+                Err(_) => {
+                    for (c, _) in comments_of(node) {
+                        self.collect_comment(c);
+                    }
+                    continue;
+                }
+            };
+            let key = match entry.key.as_value()? {
+                Document::String(s, _) => s.clone(),
+                Document::StaticStr(s, _) => s.to_string(),
+                Document::Int(i) => i.format(None),
+                Document::Boolean(b) => b.to_string(),
+                v => return Err(Error::KeyTypeError(v.variant())),
+            };
+            let name = self.mangle(prefix, &key);
+            self.collect_node(&name, entry.comments, entry.value, entry.trailing)?;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    // Collects the definitions of the elements of a sequence of aggregates,
+    // with names indexed by position.
+    fn collect_sequence(&mut self, prefix: &str, sequence: &[Document]) -> Result<()> {
+        self.enter()?;
+        let mut index = 0;
+        for node in sequence {
+            let item = match SeqItem::new(node) {
+                Ok(item) => item,
+                Err(_) => {
+                    for (c, _) in comments_of(node) {
+                        self.collect_comment(c);
+                    }
+                    continue;
+                }
+            };
+            let name = format!("{}{}{}", prefix, self.separator, index);
+            self.collect_node(&name, item.comments, item.value, item.trailing)?;
+            index += 1;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn collect_node(
+        &mut self,
+        name: &str,
+        comments: Vec<(&str, CommentFormat)>,
+        node: &Document,
+        trailing: Vec<(&str, CommentFormat)>,
+    ) -> Result<()> {
+        let comments = comments.iter().map(|(c, _)| c.to_string()).collect();
+        let trailing = trailing.iter().map(|(c, _)| c.to_string()).collect();
+        let body = match node.as_value()? {
+            Document::Mapping(m) => {
+                self.definitions.push(Definition {
+                    comments,
+                    name: String::new(),
+                    body: Body::Comment,
+                    trailing: Vec::new(),
+                });
+                self.collect_mapping(name, m)?;
+                return Ok(());
+            }
+            Document::Sequence(s) => match self.table(s)? {
+                Some(body) => body,
+                None => {
+                    self.definitions.push(Definition {
+                        comments,
+                        name: String::new(),
+                        body: Body::Comment,
+                        trailing: Vec::new(),
+                    });
+                    return self.collect_sequence(name, s);
+                }
+            },
+            Document::Bytes(b) => {
+                let values = b.iter().map(|b| Value {
+                    text: format!("0x{:02X}", b),
+                    class: TokenClass::Integer,
+                    ctype: Some(CType::BYTE),
+                });
+                Body::Table(CType::BYTE, values.collect())
+            }
+            v => Body::Scalar(self.scalar(v)?),
+        };
+        if !self.names.insert(name.to_string()) {
+            return Err(Error::DuplicateName(name.to_string()));
+        }
+        self.definitions.push(Definition {
+            comments,
+            name: name.to_string(),
+            body,
+            trailing,
+        });
+        Ok(())
+    }
+
+    // Returns the table of a sequence of scalars, or `None` if the sequence
+    // holds aggregates.
+    fn table(&self, sequence: &[Document]) -> Result<Option<Body>> {
+        let mut values = Vec::new();
+        for node in sequence.iter().filter(|n| n.has_value()) {
+            match node.as_value()? {
+                Document::Mapping(_) | Document::Sequence(_) | Document::Bytes(_) => {
+                    return Ok(None)
+                }
+                v => values.push(self.scalar(v)?),
+            }
+        }
+        let mut ctype = None;
+        for value in &values {
+            ctype = match (ctype, value.ctype) {
+                (_, None) => return Err(Error::Unrepresentable("a table of nulls", FORMAT)),
+                (None, t) => t,
+                (Some(a), Some(b)) => Some(CType::join(a, b)?),
+            };
+        }
+        match ctype {
+            Some(t) => Ok(Some(Body::Table(t, values))),
+            None => Err(Error::Unrepresentable("an empty sequence", FORMAT)),
+        }
+    }
+
+    fn scalar(&self, node: &Document) -> Result<Value> {
+        let (text, class, ctype) = match node {
+            Document::String(s, _) => (self.string(s), TokenClass::String, CType::String),
+            Document::StaticStr(s, _) => (self.string(s), TokenClass::String, CType::String),
+            Document::Boolean(b) => (b.to_string(), TokenClass::Boolean, CType::Bool),
+            Document::Int(i) => (self.int(i)?, TokenClass::Integer, CType::of_int(i)?),
+            Document::Float(f) => (self.float(*f)?, TokenClass::Float, CType::Double),
+            Document::Null => {
+                return Ok(Value {
+                    text: String::new(),
+                    class: TokenClass::Null,
+                    ctype: None,
+                })
+            }
+            Document::Absent => return Err(Error::AbsentValue),
+            v => return Err(Error::StructureError("scalar", v.variant())),
+        };
+        Ok(Value {
+            text,
+            class,
+            ctype: Some(ctype),
+        })
+    }
+
+    // Renders an integer in its annotated base.  Negative integers are
+    // written in decimal, and binary, which C99 lacks, as hexadecimal.
+    fn int(&self, i: &Int) -> Result<String> {
+        let negative = i.as_i128().map_or(false, |v| v < 0);
+        let base = match i.base() {
+            _ if negative => Base::Dec,
+            Base::Bin => Base::Hex,
+            b => b,
+        };
+        let ctx = NumberContext {
+            base,
+            width: i.width(),
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        // The dialect has no integer type suffixes.
+        let i = &i.unsuffixed();
+        i.check_format(ctx.base)?;
+        let text = self.numbers.format_int(i, &ctx);
+        Ok(match text.strip_prefix("0o") {
+            Some(digits) => format!("0{}", digits),
+            // Literals beyond `long long` must be unsigned.
+            None if i.as_i128().map_or(true, |v| v > i64::MAX as i128) => format!("{}ULL", text),
+            None => text,
+        })
+    }
+
+    fn float(&self, f: f64) -> Result<String> {
+        if !f.is_finite() {
+            return Err(Error::Unrepresentable("a non-finite float", FORMAT));
+        }
+        Ok(float_literal(&*self.numbers, f, self.float_format, true))
+    }
+
+    // Returns a string literal.  Characters outside printable ASCII are
+    // written as octal escapes, which, unlike hexadecimal escapes, cannot
+    // run into the characters after them.
+    fn string(&self, value: &str) -> String {
+        let mut text = String::from("\"");
+        for ch in value.chars() {
+            match ch {
+                '"' => text.push_str("\\\""),
+                '\\' => text.push_str("\\\\"),
+                '\n' => text.push_str("\\n"),
+                '\t' => text.push_str("\\t"),
+                '\r' => text.push_str("\\r"),
+                // Escaping `?` avoids trigraphs.
+                '?' => text.push_str("\\?"),
+                ' '..='~' => text.push(ch),
+                c => {
+                    for b in c.encode_utf8(&mut [0; 4]).bytes() {
+                        text.push_str(&format!("\\{:03o}", b));
+                    }
+                }
+            }
+        }
+        text.push('"');
+        text
+    }
+
+    fn emit_header<W: fmt::Write>(&mut self, w: &mut W, guard: Option<&str>) -> Result<()> {
+        let preprocessor = self.color.aggregate;
+        if let Some(guard) = guard {
+            writeln!(w, "{} {}", preprocessor.paint("#ifndef"), guard)?;
+            writeln!(w, "{} {}", preprocessor.paint("#define"), guard)?;
+            writeln!(w)?;
+        }
+        let types = self
+            .definitions
+            .iter()
+            .filter_map(|d| match &d.body {
+                Body::Table(t, _) => Some(*t),
+                Body::Scalar(v) if !self.defines => v.ctype,
+                Body::Scalar(v) => v.ctype.filter(|t| *t == CType::Bool),
+                Body::Comment => None,
+            })
+            .collect::<Vec<_>>();
+        let mut includes = Vec::new();
+        if types.contains(&CType::Bool) {
+            includes.push("<stdbool.h>");
+        }
+        if types.iter().any(|t| matches!(t, CType::Integer(..))) {
+            includes.push("<stdint.h>");
+        }
+        for include in &includes {
+            writeln!(w, "{} {}", preprocessor.paint("#include"), include)?;
+        }
+        if !includes.is_empty() {
+            writeln!(w)?;
+        }
+        let definitions = std::mem::take(&mut self.definitions);
+        for d in &definitions {
+            for c in &d.comments {
+                self.emit_comment(w, c)?;
+            }
+            match &d.body {
+                Body::Comment => continue,
+                Body::Scalar(v) if self.defines => {
+                    write!(
+                        w,
+                        "{} {}",
+                        preprocessor.paint("#define"),
+                        self.name(&d.name)
+                    )?;
+                    if !v.text.is_empty() {
+                        // Negative values are parenthesized so that they
+                        // bind as a whole in expressions.
+                        let text = match v.text.starts_with('-') {
+                            true => format!("({})", v.text),
+                            false => v.text.clone(),
+                        };
+                        write!(w, " {}", self.color.style(&v.class).paint(text))?;
+                    }
+                }
+                Body::Scalar(v) => {
+                    let ctype = v
+                        .ctype
+                        .ok_or(Error::Unrepresentable("a null constant", FORMAT))?;
+                    let (ctype, name) = match ctype {
+                        CType::String => ("char".to_string(), format!("{}[]", d.name)),
+                        t => (t.name(), d.name.clone()),
+                    };
+                    write!(
+                        w,
+                        "{} {} {} ",
+                        self.color.aggregate.paint("static const"),
+                        ctype,
+                        self.name(&name)
+                    )?;
+                    write!(
+                        w,
+                        "{} {}{}",
+                        self.color.punctuation.paint("="),
+                        self.color.style(&v.class).paint(&v.text),
+                        self.color.punctuation.paint(";")
+                    )?;
+                }
+                Body::Table(ctype, values) => self.emit_table(w, &d.name, *ctype, values)?,
+            }
+            self.end_line(w, &d.trailing)?;
+        }
+        if let Some(guard) = guard {
+            writeln!(w)?;
+            let comment = self.color.comment.paint(format!("/* {} */", guard));
+            writeln!(w, "{}  {}", preprocessor.paint("#endif"), comment)?;
+        }
+        Ok(())
+    }
+
+    fn emit_table<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        name: &str,
+        ctype: CType,
+        values: &[Value],
+    ) -> Result<()> {
+        write!(
+            w,
+            "{} {} {} {} {}",
+            self.color.aggregate.paint("static const"),
+            ctype.name(),
+            self.name(&format!("{}[]", name)),
+            self.color.punctuation.paint("="),
+            self.color.aggregate.paint("{"),
+        )?;
+        let comma = self.color.punctuation.paint(",").to_string();
+        let values = values
+            .iter()
+            .map(|v| self.color.style(&v.class).paint(&v.text).to_string())
+            .collect::<Vec<_>>();
+        if self.compact {
+            write!(w, "{}", values.join(&format!("{} ", comma)))?;
+        } else {
+            writeln!(w)?;
+            for chunk in values.chunks(ITEMS_PER_LINE) {
+                let items = chunk.join(&format!("{} ", comma));
+                writeln!(w, "{:indent$}{}{}", "", items, comma, indent = self.indent)?;
+            }
+        }
+        write!(
+            w,
+            "{}{}",
+            self.color.aggregate.paint("}"),
+            self.color.punctuation.paint(";")
+        )?;
+        Ok(())
+    }
+
+    fn name(&self, name: &str) -> String {
+        self.color
+            .style(&TokenClass::BareKey)
+            .paint(name)
+            .to_string()
+    }
+
+    // Ends the line of a definition with the comments after its value.
+    fn end_line<W: fmt::Write>(&mut self, w: &mut W, trailing: &[String]) -> Result<()> {
+        match trailing {
+            [c] if !self.compact && !c.contains('\n') => {
+                let c = c.replace("*/", "* /");
+                writeln!(w, " {}", self.color.comment.paint(format!("/* {} */", c)))?;
+            }
+            _ => {
+                writeln!(w)?;
+                for c in trailing {
+                    self.emit_comment(w, c)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Emits a block comment, on one line if it fits.
+    fn emit_comment<W: fmt::Write>(&mut self, w: &mut W, comment: &str) -> Result<()> {
+        if self.compact {
+            return Ok(());
+        }
+        // The end of a comment within the comment would end it early.
+        let comment = comment.replace("*/", "* /");
+        if !comment.contains('\n') {
+            writeln!(
+                w,
+                "{}",
+                self.color.comment.paint(format!("/* {} */", comment))
+            )?;
+            return Ok(());
+        }
+        writeln!(w, "{}", self.color.comment.paint("/*"))?;
+        for line in comment.split('\n') {
+            let line = match line {
+                "" => " *".to_string(),
+                l => format!(" * {}", l),
+            };
+            writeln!(w, "{}", self.color.comment.paint(line))?;
+        }
+        writeln!(w, "{}", self.color.comment.paint(" */"))?;
+        Ok(())
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        match self.max_depth {
+            Some(n) if self.depth >= n => Err(Error::DepthLimit(n)),
+            _ => {
+                self.depth += 1;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    const DOC: &str = r#"{
+        // Generated; do not edit.
+        uart: {
+            baseAddr: 0x4000C000,
+            irq: 5, // The receive interrupt.
+            enabled: true,
+        },
+        name: "rev?b",
+        gain: -2,
+        scale: 2.0,
+        // Pin assignments.
+        pins: [0x10, 0x11, 0x12],
+        offsets: [-1, 200],
+        regions: [
+            {start: 0x0, size: 0x1000},
+        ],
+        reserved: null,
+    }"#;
+
+    #[test]
+    fn test_defines() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_c_header()
+                .indent(4)
+                .prefix("board")
+                .guard("BOARD_H")
+                .to_text()?,
+            r#"#ifndef BOARD_H
+#define BOARD_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/* Generated; do not edit. */
+#define BOARD_UART_BASE_ADDR 0x4000C000
+#define BOARD_UART_IRQ 5 /* The receive interrupt. */
+#define BOARD_UART_ENABLED true
+#define BOARD_NAME "rev\?b"
+#define BOARD_GAIN (-2)
+#define BOARD_SCALE 2.0
+/* Pin assignments. */
+static const uint8_t BOARD_PINS[] = {
+    0x10, 0x11, 0x12,
+};
+static const int16_t BOARD_OFFSETS[] = {
+    -1, 200,
+};
+#define BOARD_REGIONS_0_START 0x0
+#define BOARD_REGIONS_0_SIZE 0x1000
+#define BOARD_RESERVED
+
+#endif  /* BOARD_H */
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_constants() -> Result<()> {
+        let doc = Document::parse(r#"{baseAddr: 0x4000, name: "a\tb", bits: [0x1, -0x2]}"#)?;
+        assert_eq!(
+            doc.to_c_header()
+                .compact(true)
+                .defines(false)
+                .name_case(NameCase::Lower)
+                .to_text()?,
+            r#"#include <stdint.h>
+
+static const uint16_t base_addr = 0x4000;
+static const char name[] = "a\tb";
+static const int8_t bits[] = {0x1, -2};
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> Result<()> {
+        let doc = Document::parse(r#"{a_b: 1, aB: 2}"#)?;
+        assert!(matches!(
+            doc.to_c_header().to_text(),
+            Err(Error::DuplicateName(n)) if n == "A_B"
+        ));
+        let doc = Document::parse(r#"{a: [1, "x"]}"#)?;
+        assert!(matches!(
+            doc.to_c_header().to_text(),
+            Err(Error::Unrepresentable(..))
+        ));
+        let doc = Document::parse(r#"[1]"#)?;
+        assert!(doc.to_c_header().to_text().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+// Canonical JSON (RFC 8785) emission.
+use std::fmt::Write;
+
+use crate::document::Document;
+use crate::entry::key_text;
+use crate::error::Error;
+
+impl Document {
+    /// Emits the document as canonical JSON according to the JSON
+    /// Canonicalization Scheme (RFC 8785), so that equal documents always
+    /// produce the same bytes and may be hashed or signed.
+    ///
+    /// Mapping keys are sorted by their UTF-16 code units, numbers are
+    /// written in their shortest ECMAScript form, strings escape only the
+    /// characters JSON requires and no whitespace or comments are emitted.
+    /// It is an error if the document contains a non-finite float, an
+    /// integer beyond the exactly representable range of a double (2^53),
+    /// or duplicate keys in a mapping.
+    pub fn to_canonical_json(&self) -> Result<String, Error> {
+        let mut out = String::new();
+        emit(&mut out, self)?;
+        Ok(out)
+    }
+}
+
+fn canonical_error(msg: impl Into<String>) -> Error {
+    Error::CanonicalError(msg.into())
+}
+
+fn emit(out: &mut String, node: &Document) -> Result<(), Error> {
+    match node {
+        Document::Comment(_, _) => {}
+        Document::String(s, _) => emit_string(out, s),
+        Document::StaticStr(s, _) => emit_string(out, s),
+        Document::Boolean(b) => write!(out, "{}", b)?,
+        Document::Int(i) => {
+            if !i.is_legal_json() {
+                return Err(canonical_error(format!("integer {} is out of range", i)));
+            }
+            write!(out, "{}", i.format(None))?
+        }
+        Document::Float(f) => out.push_str(&es_number(*f)?),
+        Document::Null => out.push_str("null"),
+        Document::Absent => return Err(Error::AbsentValue),
+        Document::Bytes(b) => {
+            out.push('[');
+            for (i, byte) in b.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write!(out, "{}", byte)?;
+            }
+            out.push(']');
+        }
+        Document::Sequence(s) => {
+            out.push('[');
+            for (i, elem) in s.iter().filter(|n| n.has_value()).enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                emit(out, elem)?;
+            }
+            out.push(']');
+        }
+        Document::Mapping(m) => {
+            let mut entries = Vec::new();
+            for entry in m.iter().filter(|n| n.has_value()) {
+                let (k, v) = entry.as_kv()?;
+                entries.push((key_string(k)?, v));
+            }
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            if let Some(w) = entries.windows(2).find(|w| w[0].0 == w[1].0) {
+                return Err(canonical_error(format!("duplicate key {:?}", w[0].0)));
+            }
+            out.push('{');
+            for (i, (k, v)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                emit_string(out, &k);
+                out.push(':');
+                emit(out, v)?;
+            }
+            out.push('}');
+        }
+        Document::Compact(d)
+        | Document::ColorClass(_, d)
+        | Document::Identified(_, d)
+        | Document::Typed(_, d) => emit(out, d)?,
+        Document::Fragment(_) => emit(out, node.as_value()?)?,
+    }
+    Ok(())
+}
+
+// Returns the text of a mapping key.  Scalar keys other than strings are
+// written as strings, as by the JSON emitter.
+fn key_string(key: &Document) -> Result<String, Error> {
+    match key.as_value()? {
+        Document::Float(f) => es_number(*f),
+        k => key_text(k).ok_or_else(|| Error::KeyTypeError(k.variant())),
+    }
+}
+
+fn emit_string(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{08}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < ' ' => {
+                // Unwrap is ok: writing to a String cannot fail.
+                write!(out, "\\u{:04x}", c as u32).unwrap()
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// Formats a float as by ECMAScript's `Number.prototype.toString`.
+fn es_number(f: f64) -> Result<String, Error> {
+    if !f.is_finite() {
+        return Err(canonical_error(format!("{} is not a finite number", f)));
+    }
+    if f == 0.0 {
+        return Ok("0".into());
+    }
+    // Rust's `{:e}` formatting yields the shortest digits which round-trip,
+    // as ECMAScript requires.
+    let sci = format!("{:e}", f.abs());
+    // Unwrap is ok: `{:e}` always writes an exponent.
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    // Unwrap is ok: the exponent is an integer.
+    let n = exp.parse::<i32>().unwrap() + 1;
+    let k = digits.len() as i32;
+    let mut s = String::new();
+    if f < 0.0 {
+        s.push('-');
+    }
+    if k <= n && n <= 21 {
+        s.push_str(&digits);
+        s.extend(std::iter::repeat('0').take((n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        s.push_str(&digits[..n as usize]);
+        s.push('.');
+        s.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        s.push_str("0.");
+        s.extend(std::iter::repeat('0').take(-n as usize));
+        s.push_str(&digits);
+    } else {
+        s.push_str(&digits[..1]);
+        if k > 1 {
+            s.push('.');
+            s.push_str(&digits[1..]);
+        }
+        let e = n - 1;
+        write!(s, "e{}{}", if e < 0 { '-' } else { '+' }, e.abs())?;
+    }
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_es_number() -> Result<()> {
+        let cases = [
+            (1.0, "1"),
+            (-0.0, "0"),
+            (0.5, "0.5"),
+            (123.456, "123.456"),
+            (1e21, "1e+21"),
+            (1e20, "100000000000000000000"),
+            (1.5e-7, "1.5e-7"),
+            (0.000001, "0.000001"),
+            (-4.5e100, "-4.5e+100"),
+            (333333333.3333333, "333333333.3333333"),
+            (9007199254740992.0, "9007199254740992"),
+            (5e-324, "5e-324"),
+        ];
+        for (f, s) in cases {
+            assert_eq!(es_number(f)?, s);
+        }
+        assert!(es_number(f64::NAN).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_json() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // Comments are dropped.
+                "b": [1, 2.50, 1e30, "x"],
+                "a": {"€": "\u0007\n\"€", "😀": null, "é": true},
+                10: 0x10,
+            }"#,
+        )?;
+        assert_eq!(
+            doc.to_canonical_json()?,
+            "{\"10\":16,\"a\":{\"é\":true,\"€\":\"\\u0007\\n\\\"€\",\"😀\":null},\"b\":[1,2.5,1e+30,\"x\"]}"
+        );
+        let doc = Document::parse("{a: 1, a: 2}")?;
+        assert!(matches!(
+            doc.to_canonical_json(),
+            Err(Error::CanonicalError(_))
+        ));
+        let doc = Document::parse("[18446744073709551615]")?;
+        assert!(doc.to_canonical_json().is_err());
+        Ok(())
+    }
+}
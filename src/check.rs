@@ -0,0 +1,181 @@
+// Checks of whether text is formatted as an emitter would write it.
+use crate::edit_script::{edit_script, Edit};
+use std::fmt;
+
+/// The result of `Json::check`: whether the input text is byte-for-byte
+/// the text the emitter writes for it, and the difference if not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatCheck {
+    formatted: String,
+    diff: Option<String>,
+}
+
+// The number of unchanged lines shown around each change.
+const CONTEXT: usize = 2;
+
+impl FormatCheck {
+    // Compares the `input` text with the `formatted` text of the document
+    // parsed from it.  A single trailing line ending after the document is
+    // permitted, as written by editors and by `writeln!`.
+    pub(crate) fn new(input: &str, formatted: String) -> Self {
+        let body = input
+            .strip_suffix("\r\n")
+            .or_else(|| input.strip_suffix('\n'))
+            .unwrap_or(input);
+        let diff = if input == formatted || body == formatted {
+            None
+        } else {
+            Some(line_diff(input, &formatted))
+        };
+        FormatCheck { formatted, diff }
+    }
+
+    /// Returns whether the input is formatted.
+    pub fn is_formatted(&self) -> bool {
+        self.diff.is_none()
+    }
+
+    /// Returns the formatted text, which may replace the input.
+    pub fn formatted(&self) -> &str {
+        &self.formatted
+    }
+
+    /// Returns the difference between the input and the formatted text, in
+    /// unified diff form, if the input is not formatted.
+    pub fn diff(&self) -> Option<&str> {
+        self.diff.as_deref()
+    }
+}
+
+impl fmt::Display for FormatCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.diff {
+            Some(d) => write!(f, "{}", d),
+            None => Ok(()),
+        }
+    }
+}
+
+// Computes a line-level difference between two texts as unified diff hunks.
+// Line endings are compared exactly, so a line which differs only in its
+// line ending is reported as changed.
+fn line_diff(old: &str, new: &str) -> String {
+    let a = old.split_inclusive('\n').collect::<Vec<_>>();
+    let b = new.split_inclusive('\n').collect::<Vec<_>>();
+    // Each edit is a marker and the index of its line in `a` or `b`.
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for edit in edit_script(&a, &b) {
+        match edit {
+            Edit::Same => {
+                edits.push((' ', i, j));
+                i += 1;
+                j += 1;
+            }
+            Edit::Removed => {
+                edits.push(('-', i, j));
+                i += 1;
+            }
+            Edit::Added => {
+                edits.push(('+', i, j));
+                j += 1;
+            }
+        }
+    }
+
+    let mut out = String::from("--- input\n+++ formatted\n");
+    let mut k = 0;
+    while let Some(first) = edits[k..].iter().position(|e| e.0 != ' ') {
+        // Extend the hunk until CONTEXT unchanged lines separate it from
+        // the next change.
+        let start = (k + first).saturating_sub(CONTEXT);
+        let mut end = k + first;
+        while end < edits.len() {
+            let gap = edits[end..].iter().take_while(|e| e.0 == ' ').count();
+            if end + gap == edits.len() || gap > 2 * CONTEXT {
+                end = (end + gap.min(CONTEXT)).min(edits.len());
+                break;
+            }
+            end += gap + 1;
+        }
+        let hunk = &edits[start..end];
+        let old_len = hunk.iter().filter(|e| e.0 != '+').count();
+        let new_len = hunk.iter().filter(|e| e.0 != '-').count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk[0].1 + 1,
+            old_len,
+            hunk[0].2 + 1,
+            new_len
+        ));
+        for &(marker, i, j) in hunk {
+            let line = if marker == '+' { b[j] } else { a[i] };
+            out.push(marker);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        k = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use anyhow::Result;
+
+    #[test]
+    fn test_check() -> Result<()> {
+        let json = Document::Null.to_json5();
+        let formatted = "{\n  // The name.\n  name: \"x\",\n  sizes: [\n    1,\n    2\n  ]\n}";
+        let check = json.check(formatted)?;
+        assert!(check.is_formatted());
+        assert_eq!(check.formatted(), formatted);
+        assert!(json.check(&format!("{}\n", formatted))?.is_formatted());
+        assert!(!json.check(&format!("{}\n\n", formatted))?.is_formatted());
+
+        let check = json.check("{\n  // The name.\n  name: 'x',\n  sizes: [1, 2]\n}\n")?;
+        assert!(!check.is_formatted());
+        assert_eq!(check.formatted(), formatted);
+        assert_eq!(
+            check.diff(),
+            Some(
+                r#"--- input
++++ formatted
+@@ -1,5 +1,8 @@
+ {
+   // The name.
+-  name: 'x',
+-  sizes: [1, 2]
+-}
++  name: "x",
++  sizes: [
++    1,
++    2
++  ]
++}
+\ No newline at end of file
+"#
+            )
+        );
+        assert!(json.check("{").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_diff_hunks() {
+        let old = (1..=20).map(|i| format!("{}\n", i)).collect::<String>();
+        let new = old
+            .replace("\n3\n", "\nthree\n")
+            .replace("\n17\n", "\nseventeen\n");
+        assert_eq!(
+            line_diff(&old, &new),
+            "--- input\n+++ formatted\n\
+             @@ -1,5 +1,5 @@\n 1\n 2\n-3\n+three\n 4\n 5\n\
+             @@ -15,5 +15,5 @@\n 15\n 16\n-17\n+seventeen\n 18\n 19\n"
+        );
+    }
+}
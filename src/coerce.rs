@@ -0,0 +1,113 @@
+// Coercion of string values to booleans and integers.
+use crate::document::Document;
+use crate::integer::{Base, Int};
+use crate::path::{children_mut, segments};
+use crate::transform::unwrapped;
+
+/// How `Document::coerce` rewrites the string values at a path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coercion {
+    /// Strings which are `true` or `false`, in any case, become booleans.
+    Boolean,
+    /// Strings which hold an integer, in decimal or with a `0x`, `0o` or
+    /// `0b` prefix, become integers emitted in the given base.
+    Int(Base),
+}
+
+impl Document {
+    /// Rewrites the string values at `path` which hold a boolean or an
+    /// integer according to `coercion`, so that a document which arrived
+    /// with every value as a string can be emitted with its proper types.
+    ///
+    /// The path is a JSON Pointer through mapping keys and sequence
+    /// indices, where `*` matches any single key or index (e.g.
+    /// `/flags/*`).  Strings which do not hold a value of the requested
+    /// type, and paths which do not exist in the document, are left alone.
+    pub fn coerce(mut self, path: &str, coercion: Coercion) -> Document {
+        let path = segments(path).collect::<Vec<_>>();
+        coerce_at(&mut self, &path, coercion);
+        self
+    }
+}
+
+fn coerce_at(node: &mut Document, path: &[String], coercion: Coercion) {
+    let value = match unwrapped(node) {
+        Some(v) => v,
+        None => return,
+    };
+    match (path, &mut *value) {
+        ([], Document::String(..) | Document::StaticStr(..)) => {
+            // Unwrap is ok: the value is a string.
+            if let Some(v) = coerced(value.as_str().unwrap(), coercion) {
+                *value = v;
+            }
+        }
+        ([], _) => {}
+        ([seg, rest @ ..], value) => {
+            for n in children_mut(value, seg) {
+                coerce_at(n, rest, coercion);
+            }
+        }
+    }
+}
+
+// Returns the value held by the string `s` under `coercion`, if any.
+fn coerced(s: &str, coercion: Coercion) -> Option<Document> {
+    match coercion {
+        Coercion::Boolean if s.eq_ignore_ascii_case("true") => Some(Document::Boolean(true)),
+        Coercion::Boolean if s.eq_ignore_ascii_case("false") => Some(Document::Boolean(false)),
+        Coercion::Boolean => None,
+        Coercion::Int(base) => {
+            let mut i = Int::from_str_radix(s.trim(), 0).ok()?;
+            let negative = i.negative_format();
+            i.copy_format(&Int::new(0u8, base));
+            Some(Document::Int(i.with_negative_format(negative)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    #[test]
+    fn test_coerce() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                flags: {
+                    // Verbose output.
+                    verbose: "true",
+                    quiet: "FALSE",
+                    color: "auto",
+                },
+                regs: ["16", "0x20", "-6", "many"],
+                name: "true",
+            }"#,
+        )?;
+        let doc = doc
+            .coerce("/flags/*", Coercion::Boolean)
+            .coerce("/regs/*", Coercion::Int(Base::Hex))
+            .coerce("/missing", Coercion::Boolean);
+        assert_eq!(
+            doc.to_json5().to_text()?,
+            r#"{
+  flags: {
+    // Verbose output.
+    verbose: true,
+    quiet: false,
+    color: "auto"
+  },
+  regs: [
+    0x10,
+    0x20,
+    -0x6,
+    "many"
+  ],
+  name: "true"
+}"#
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
+use crate::layout::TokenClass;
 use ansi_term::{Color, Style};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::sync::Arc;
 
 /// A `ColorProfile` describes how to apply color information when rendering a document.
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone)]
 pub struct ColorProfile {
     /// The style to use for aggregate symbols (`[]{}`).
     pub aggregate: Style,
@@ -13,6 +18,9 @@ pub struct ColorProfile {
     pub null: Style,
     /// The style to use for object keys.
     pub key: Style,
+    /// The style to use for unquoted object keys.  If `None`, the `key`
+    /// style is used.
+    pub bare_key: Option<Style>,
     /// The style to use for string values.
     pub string: Style,
     /// The style to use for escap sequences in strings.
@@ -21,8 +29,14 @@ pub struct ColorProfile {
     pub boolean: Style,
     /// The style to use for integer values.
     pub integer: Style,
+    /// The style to use for integer values emitted as quoted strings (e.g.
+    /// hex integers in strict JSON).  If `None`, the `integer` style is used.
+    pub quoted_integer: Option<Style>,
     /// The style to use for float values.
     pub float: Style,
+    /// The styles of user-defined color classes, by class name.  The table
+    /// is shared between clones of the profile.
+    pub classes: Arc<BTreeMap<String, Style>>,
 }
 
 impl ColorProfile {
@@ -34,11 +48,318 @@ impl ColorProfile {
             comment: Style::new().fg(Color::White).italic(),
             null: Style::new().fg(Color::Red).bold(),
             key: Style::new().fg(Color::Cyan),
+            bare_key: None,
             string: Style::new().fg(Color::Green),
             escape: Style::new().fg(Color::Green).bold(),
             boolean: Style::new().fg(Color::Blue),
             integer: Style::new().fg(Color::Blue).bold(),
+            quoted_integer: None,
             float: Style::new().fg(Color::Purple),
+            classes: Arc::default(),
         }
     }
+
+    /// Returns the basic color profile if stdout is a terminal, or an
+    /// uncolored profile otherwise.
+    ///
+    /// The environment overrides the detection: a non-empty `NO_COLOR`
+    /// disables color, and `CLICOLOR_FORCE` set to anything other than `0`
+    /// enables it.  `NO_COLOR` takes precedence.
+    pub fn auto() -> Self {
+        Self::auto_for(1)
+    }
+
+    /// Returns the basic color profile if stderr is a terminal, or an
+    /// uncolored profile otherwise.  See `ColorProfile::auto`.
+    pub fn auto_stderr() -> Self {
+        Self::auto_for(2)
+    }
+
+    fn auto_for(fd: i32) -> Self {
+        let enable = use_color(
+            is_terminal(fd),
+            env::var_os("NO_COLOR"),
+            env::var_os("CLICOLOR_FORCE"),
+        );
+        if enable {
+            Self::basic()
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Adds a user-defined color class to the profile.
+    ///
+    /// Nodes marked with the class (e.g. via `#[annotate(color = "warning")]`
+    /// or `Document::color_class`) are rendered with `style`.
+    pub fn class(mut self, name: &str, style: Style) -> Self {
+        Arc::make_mut(&mut self.classes).insert(name.to_string(), style);
+        self
+    }
+
+    /// Sets the style for aggregate symbols (`[]{}`).
+    pub fn aggregate(mut self, style: Style) -> Self {
+        self.aggregate = style;
+        self
+    }
+
+    /// Sets the style for punctuation symbols (`"',:`).
+    pub fn punctuation(mut self, style: Style) -> Self {
+        self.punctuation = style;
+        self
+    }
+
+    /// Sets the style for comments.
+    pub fn comment(mut self, style: Style) -> Self {
+        self.comment = style;
+        self
+    }
+
+    /// Sets the style for null values.
+    pub fn null(mut self, style: Style) -> Self {
+        self.null = style;
+        self
+    }
+
+    /// Sets the style for object keys.  Unquoted keys also use this style
+    /// unless given their own with `bare_key`.
+    pub fn key(mut self, style: Style) -> Self {
+        self.key = style;
+        self
+    }
+
+    /// Sets the style for unquoted object keys.
+    pub fn bare_key(mut self, style: Style) -> Self {
+        self.bare_key = Some(style);
+        self
+    }
+
+    /// Sets the style for string values.
+    pub fn string(mut self, style: Style) -> Self {
+        self.string = style;
+        self
+    }
+
+    /// Sets the style for escape sequences in strings.
+    pub fn escape(mut self, style: Style) -> Self {
+        self.escape = style;
+        self
+    }
+
+    /// Sets the style for boolean values.
+    pub fn boolean(mut self, style: Style) -> Self {
+        self.boolean = style;
+        self
+    }
+
+    /// Sets the style for integer values.  Quoted integers also use this
+    /// style unless given their own with `quoted_integer`.
+    pub fn integer(mut self, style: Style) -> Self {
+        self.integer = style;
+        self
+    }
+
+    /// Sets the style for integer values emitted as quoted strings.
+    pub fn quoted_integer(mut self, style: Style) -> Self {
+        self.quoted_integer = Some(style);
+        self
+    }
+
+    /// Sets the style for float values.
+    pub fn float(mut self, style: Style) -> Self {
+        self.float = style;
+        self
+    }
+
+    /// Returns the style for tokens of class `class`.  Tokens of a
+    /// user-defined class not in the profile are unstyled.
+    pub fn style(&self, class: &TokenClass) -> Style {
+        match class {
+            TokenClass::Plain => Style::new(),
+            TokenClass::Aggregate => self.aggregate,
+            TokenClass::Punctuation => self.punctuation,
+            TokenClass::Comment => self.comment,
+            TokenClass::Null => self.null,
+            TokenClass::Key => self.key,
+            TokenClass::BareKey => self.bare_key.unwrap_or(self.key),
+            TokenClass::String => self.string,
+            TokenClass::Escape => self.escape,
+            TokenClass::Boolean => self.boolean,
+            TokenClass::Integer => self.integer,
+            TokenClass::QuotedInteger => self.quoted_integer.unwrap_or(self.integer),
+            TokenClass::Float => self.float,
+            TokenClass::Class(name) => self.classes.get(name).copied().unwrap_or_default(),
+        }
+    }
+
+    /// Returns the profile to use for a node of color class `name`.
+    ///
+    /// All styles except the comment style are replaced by the class style.
+    /// Classes not defined in the profile leave the profile unchanged.
+    pub(crate) fn for_class(&self, name: &str) -> ColorProfile {
+        let style = match self.classes.get(name) {
+            Some(style) => *style,
+            None => return self.clone(),
+        };
+        ColorProfile {
+            aggregate: style,
+            punctuation: style,
+            comment: self.comment,
+            null: style,
+            key: style,
+            bare_key: Some(style),
+            string: style,
+            escape: style,
+            boolean: style,
+            integer: style,
+            quoted_integer: Some(style),
+            float: style,
+            classes: Arc::clone(&self.classes),
+        }
+    }
+}
+
+// Returns whether the file descriptor `fd` refers to a terminal.
+#[cfg(unix)]
+fn is_terminal(fd: i32) -> bool {
+    extern "C" {
+        fn isatty(fd: i32) -> i32;
+    }
+    // Safety: `isatty` only inspects the descriptor, and fails harmlessly if
+    // it is not open.
+    unsafe { isatty(fd) == 1 }
+}
+
+// Terminal detection is only supported on unix; elsewhere, color is enabled
+// through `CLICOLOR_FORCE`.
+#[cfg(not(unix))]
+fn is_terminal(_fd: i32) -> bool {
+    false
+}
+
+// Decides whether to emit color given whether the output is a terminal and
+// the values of the `NO_COLOR` and `CLICOLOR_FORCE` variables.
+fn use_color(terminal: bool, no_color: Option<OsString>, force: Option<OsString>) -> bool {
+    if no_color.map_or(false, |v| !v.is_empty()) {
+        false
+    } else if force.map_or(false, |v| !v.is_empty() && v != "0") {
+        true
+    } else {
+        terminal
+    }
+}
+
+/// An `HtmlProfile` describes how to mark up a document rendered as HTML.
+///
+/// Each token is wrapped in a `<span>` whose CSS class is the profile's
+/// prefix followed by the name of the token's class (see `TokenClass::name`),
+/// e.g. `<span class="json-key">`.  The styles themselves are left to a
+/// stylesheet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HtmlProfile {
+    /// The prefix of each CSS class name.
+    pub prefix: String,
+    /// Whether to enclose the output in a `<pre>` element.
+    pub pre: bool,
+}
+
+impl Default for HtmlProfile {
+    fn default() -> Self {
+        HtmlProfile {
+            prefix: "json".into(),
+            pre: true,
+        }
+    }
+}
+
+impl HtmlProfile {
+    /// Sets the prefix of each CSS class name (the default is `json`).
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Sets whether to enclose the output in a `<pre class="{prefix}">`
+    /// element (the default is `true`).
+    pub fn pre(mut self, b: bool) -> Self {
+        self.pre = b;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::integer::Base;
+    use anyhow::Result;
+
+    #[test]
+    fn test_overrides() -> Result<()> {
+        let doc = Document::parse(r#"{a: 0x10, "b c": 2}"#)?;
+        let classes = |profile: ColorProfile| -> Result<Vec<(String, Style)>> {
+            let layout = doc
+                .clone()
+                .to_json()
+                .bases(&[Base::Hex])
+                .bare_keys(true)
+                .layout()?;
+            Ok(layout
+                .tokens()
+                .filter(|(_, _, t)| t.class != TokenClass::Plain)
+                .map(|(_, _, t)| (t.text.clone(), profile.style(&t.class)))
+                .collect())
+        };
+        let red = Style::new().fg(Color::Red);
+        let blue = Style::new().fg(Color::Blue);
+
+        // Without overrides, bare and quoted tokens share a style.
+        let profile = ColorProfile::default().key(red).integer(blue);
+        let styles = classes(profile)?;
+        assert!(styles.contains(&("a".into(), red)));
+        assert!(styles.contains(&("b c".into(), red)));
+        assert!(styles.contains(&("0x10".into(), blue)));
+        assert!(styles.contains(&("2".into(), blue)));
+
+        let profile = ColorProfile::default()
+            .key(red)
+            .bare_key(blue)
+            .integer(blue)
+            .quoted_integer(red);
+        let styles = classes(profile)?;
+        assert!(styles.contains(&("a".into(), blue)));
+        assert!(styles.contains(&("b c".into(), red)));
+        assert!(styles.contains(&("0x10".into(), red)));
+        assert!(styles.contains(&("2".into(), blue)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_classes() {
+        let red = Style::new().fg(Color::Red);
+        let blue = Style::new().fg(Color::Blue);
+        let a = ColorProfile::default().class("x", red).class("y", blue);
+        let b = ColorProfile::default().class("y", blue).class("x", blue);
+        let b = b.class("x", red);
+        assert_eq!(a.classes, b.classes);
+        // Clones share the table of classes.
+        let c = a.clone();
+        assert!(Arc::ptr_eq(&a.classes, &c.classes));
+        assert_eq!(a.style(&TokenClass::Class("x".into())), red);
+        assert_eq!(a.style(&TokenClass::Class("y".into())), blue);
+        assert_eq!(a.style(&TokenClass::Class("z".into())), Style::new());
+    }
+
+    #[test]
+    fn test_use_color() {
+        let v = |s: &str| Some(OsString::from(s));
+        assert!(use_color(true, None, None));
+        assert!(!use_color(false, None, None));
+        assert!(!use_color(true, v("1"), None));
+        assert!(use_color(true, v(""), None));
+        assert!(use_color(false, None, v("1")));
+        assert!(!use_color(false, None, v("0")));
+        assert!(!use_color(false, None, v("")));
+        assert!(!use_color(false, v("1"), v("1")));
+    }
 }
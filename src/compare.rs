@@ -0,0 +1,166 @@
+// Equality comparisons between documents.
+use crate::diff::scalar_eq;
+use crate::document::Document;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Semantic,
+    KeysUnordered,
+}
+
+impl Document {
+    /// Compares two documents for exact equality, including comments,
+    /// string and comment formats, integer bases and widths, compactness,
+    /// color classes and node identifiers.
+    pub fn eq_strict(&self, other: &Document) -> bool {
+        match (self, other) {
+            (Document::Comment(a, af), Document::Comment(b, bf)) => a == b && af == bf,
+            (Document::String(a, af), Document::String(b, bf)) => a == b && af == bf,
+            (Document::StaticStr(a, af), Document::StaticStr(b, bf)) => a == b && af == bf,
+            (Document::Boolean(a), Document::Boolean(b)) => a == b,
+            (Document::Int(a), Document::Int(b)) => {
+                a.format(None) == b.format(None) && a.base() == b.base() && a.width() == b.width()
+            }
+            (Document::Float(a), Document::Float(b)) => a.to_bits() == b.to_bits(),
+            (Document::Bytes(a), Document::Bytes(b)) => a == b,
+            (Document::Null, Document::Null) => true,
+            (Document::Compact(a), Document::Compact(b)) => a.eq_strict(b),
+            (Document::ColorClass(ac, a), Document::ColorClass(bc, b)) => {
+                ac == bc && a.eq_strict(b)
+            }
+            (Document::Identified(ai, a), Document::Identified(bi, b)) => {
+                ai == bi && a.eq_strict(b)
+            }
+            (Document::Typed(at, a), Document::Typed(bt, b)) => at == bt && a.eq_strict(b),
+            (Document::Mapping(a), Document::Mapping(b))
+            | (Document::Sequence(a), Document::Sequence(b))
+            | (Document::Fragment(a), Document::Fragment(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_strict(b))
+            }
+            _ => false,
+        }
+    }
+
+    /// Compares the values of two documents, ignoring comments and
+    /// formatting hints such as string style, integer base, compactness
+    /// and color classes.  Mapping entries must appear in the same order.
+    pub fn eq_semantic(&self, other: &Document) -> bool {
+        value_eq(self, other, Mode::Semantic)
+    }
+
+    /// Compares the values of two documents like `eq_semantic`, but
+    /// without regard to the order of the entries in each mapping.
+    pub fn eq_keys_unordered(&self, other: &Document) -> bool {
+        value_eq(self, other, Mode::KeysUnordered)
+    }
+}
+
+// Returns the value-holding nodes of an aggregate, skipping comments.
+fn values(nodes: &[Document]) -> Vec<&Document> {
+    nodes.iter().filter(|n| n.has_value()).collect()
+}
+
+// Compares two nodes which may hold comments alongside a value or a
+// key-value pair.
+fn slot_eq(a: &Document, b: &Document, mode: Mode) -> bool {
+    match (a.as_kv(), b.as_kv()) {
+        (Ok((ak, av)), Ok((bk, bv))) => value_eq(ak, bk, mode) && value_eq(av, bv, mode),
+        (Err(_), Err(_)) => match (a.as_value(), b.as_value()) {
+            (Ok(a), Ok(b)) => value_eq(a, b, mode),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn value_eq(a: &Document, b: &Document, mode: Mode) -> bool {
+    match (a, b) {
+        (Document::Compact(a), b)
+        | (Document::ColorClass(_, a), b)
+        | (Document::Identified(_, a), b)
+        | (Document::Typed(_, a), b) => value_eq(a, b, mode),
+        (a, Document::Compact(b))
+        | (a, Document::ColorClass(_, b))
+        | (a, Document::Identified(_, b))
+        | (a, Document::Typed(_, b)) => value_eq(a, b, mode),
+        (Document::Fragment(_), _) | (_, Document::Fragment(_)) => slot_eq(a, b, mode),
+        (Document::Sequence(a), Document::Sequence(b)) => {
+            let (a, b) = (values(a), values(b));
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| slot_eq(a, b, mode))
+        }
+        (Document::Mapping(a), Document::Mapping(b)) => {
+            let (a, b) = (values(a), values(b));
+            if a.len() != b.len() {
+                return false;
+            }
+            if mode == Mode::Semantic {
+                return a.iter().zip(b).all(|(a, b)| slot_eq(a, b, mode));
+            }
+            // Match each entry to a distinct entry with the same key, so
+            // that duplicate keys must appear the same number of times.
+            let mut used = vec![false; b.len()];
+            a.iter().all(|a| {
+                let found = b
+                    .iter()
+                    .enumerate()
+                    .position(|(i, b)| !used[i] && slot_eq(a, b, mode));
+                match found {
+                    Some(i) => {
+                        used[i] = true;
+                        true
+                    }
+                    None => false,
+                }
+            })
+        }
+        (a, b) => scalar_eq(a, b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_eq_strict() -> Result<()> {
+        let a = Document::parse("{\n  // A comment.\n  a: 0x10,\n  b: [1, 'x']\n}")?;
+        assert!(a.eq_strict(&a.clone()));
+        let b = Document::parse("{\n  // A comment.\n  a: 0x10,\n  b: [1, \"x\"]\n}")?;
+        assert!(!a.eq_strict(&b));
+        let c = Document::parse("{\n  // Another comment.\n  a: 0x10,\n  b: [1, 'x']\n}")?;
+        assert!(!a.eq_strict(&c));
+        let d = Document::parse("{\n  // A comment.\n  a: 16,\n  b: [1, 'x']\n}")?;
+        assert!(!a.eq_strict(&d));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eq_semantic() -> Result<()> {
+        let a = Document::parse("{\n  // A comment.\n  a: 0x10,\n  b: [1, 'x']\n}")?;
+        let b = Document::parse("{a: 16, b: [0b1, \"x\"]}")?;
+        assert!(a.eq_semantic(&b));
+        assert!(a.eq_semantic(&Document::Compact(Box::new(b.clone()))));
+        let c = Document::parse("{a: 16, b: [1, \"y\"]}")?;
+        assert!(!a.eq_semantic(&c));
+        let d = Document::parse("{b: [1, 'x'], a: 16}")?;
+        assert!(!a.eq_semantic(&d));
+        assert!(a.eq_keys_unordered(&d));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eq_keys_unordered() -> Result<()> {
+        let a = Document::parse("{x: {p: 1, q: 2}, y: [{m: 1, n: 2}]}")?;
+        let b = Document::parse("{y: [{n: 2, m: 1}], x: {q: 2, p: 1}}")?;
+        assert!(a.eq_keys_unordered(&b));
+        assert!(!a.eq_semantic(&b));
+        // Sequences remain ordered.
+        let c = Document::parse("[1, 2]")?;
+        assert!(!c.eq_keys_unordered(&Document::parse("[2, 1]")?));
+        // Duplicate keys must match one-to-one.
+        let d = Document::parse("{k: 1, k: 1}")?;
+        assert!(!d.eq_keys_unordered(&Document::parse("{k: 1, j: 1}")?));
+        Ok(())
+    }
+}
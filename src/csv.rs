@@ -0,0 +1,309 @@
+use crate::color::ColorProfile;
+use crate::document::{BytesFormat, Document};
+use crate::emit::{common_options, guarded, CommonOptions, Emit};
+use crate::entry::MapEntry;
+use crate::error::Error;
+use crate::hexdump;
+use crate::integer::{Base, Int};
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use std::fmt;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A CSV (or TSV) table and its formatting properties.
+///
+/// The document must be a sequence of mappings whose values are scalars.
+/// Each mapping is a row and each key a column, in the order in which the
+/// keys first appear; a row without a key leaves its cell empty.
+///
+/// Cells holding the delimiter, a quote or a line break are quoted, with
+/// quotes doubled.  Integers annotated with a base other than decimal are
+/// written in that base as quoted cells (e.g. `"0x50"`) so that
+/// spreadsheets keep them as text.  Null is written as an empty cell and
+/// bytes as a hex string unless another format is requested.  The
+/// comments anywhere in the document are written ahead of the table as
+/// lines starting with `#`.
+pub struct Csv {
+    document: Document,
+    common: CommonOptions,
+    delimiter: char,
+    header: bool,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+}
+
+impl Csv {
+    common_options!(
+        /// Set whether the table is emitted without its comments.
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        newline,
+        ascii_comments,
+    );
+    /// Set the character separating the cells of a row (`,` for CSV and
+    /// a tab for TSV).
+    pub fn delimiter(mut self, c: char) -> Self {
+        self.delimiter = c;
+        self
+    }
+    /// Set whether the table starts with a row of column names (the
+    /// default is `true`).
+    pub fn header(mut self, b: bool) -> Self {
+        self.header = b;
+        self
+    }
+    /// Set the rendering of bytes values.  `BytesFormat::Standard` renders
+    /// as `BytesFormat::HexStr`.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.bytes_format = b;
+        self
+    }
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+
+    fn emit<W: fmt::Write>(&self, w: &mut W) -> Result<()> {
+        self.common.check_comments(&self.document)?;
+        let rows = match self.document.as_value()? {
+            Document::Sequence(s) => s
+                .iter()
+                .filter(|n| n.has_value())
+                .map(|n| match n.as_value()? {
+                    Document::Mapping(m) => Ok(m
+                        .iter()
+                        .filter_map(|e| MapEntry::new(e).ok())
+                        .collect::<Vec<_>>()),
+                    _ => Err(Error::Unrepresentable(
+                        "a row which is not a mapping",
+                        "CSV",
+                    )),
+                })
+                .collect::<Result<Vec<_>>>()?,
+            _ => {
+                return Err(Error::Unrepresentable(
+                    "a document which is not a sequence",
+                    "CSV",
+                ))
+            }
+        };
+        let mut columns = Vec::<&str>::new();
+        for entry in rows.iter().flatten() {
+            let key = entry.key.as_str()?;
+            if !columns.contains(&key) {
+                columns.push(key);
+            }
+        }
+
+        let emitter = CsvEmitter {
+            color: self.common.color.clone(),
+            delimiter: self.delimiter,
+            bytes_format: self.bytes_format,
+            numbers: self.numbers.clone(),
+            float_format: self.float_format,
+        };
+        if !self.common.compact {
+            let comments = self.document.comments();
+            for line in comments.iter().flat_map(|(_, c, _)| c.split('\n')) {
+                let text = if line.is_empty() {
+                    "#".to_string()
+                } else {
+                    format!("# {}", line)
+                };
+                writeln!(w, "{}", self.common.color.comment.paint(text))?;
+            }
+        }
+        if self.header {
+            let cells = columns
+                .iter()
+                .map(|c| emitter.quote(c, self.common.color.key, false))
+                .collect::<Vec<_>>();
+            emitter.emit_row(w, &cells)?;
+        }
+        for row in rows {
+            let cells = columns
+                .iter()
+                .map(
+                    |c| match row.iter().find(|e| e.key.as_str().ok() == Some(c)) {
+                        Some(entry) => emitter.cell(entry.value),
+                        None => Ok(String::new()),
+                    },
+                )
+                .collect::<Result<Vec<_>>>()?;
+            emitter.emit_row(w, &cells)?;
+        }
+        Ok(())
+    }
+}
+
+impl Emit for Csv {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.common, w, |mut w| self.emit(&mut w))
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.common
+    }
+}
+
+impl Document {
+    pub fn to_csv(self) -> Csv {
+        Csv {
+            document: self,
+            common: CommonOptions::default(),
+            delimiter: ',',
+            header: true,
+            bytes_format: BytesFormat::Standard,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+        }
+    }
+
+    pub fn to_tsv(self) -> Csv {
+        self.to_csv().delimiter('\t')
+    }
+}
+
+struct CsvEmitter {
+    color: ColorProfile,
+    delimiter: char,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+}
+
+impl CsvEmitter {
+    fn emit_row<W: fmt::Write>(&self, w: &mut W, cells: &[String]) -> Result<()> {
+        let delimiter = self.color.punctuation.paint(self.delimiter.to_string());
+        writeln!(w, "{}", cells.join(&delimiter.to_string()))?;
+        Ok(())
+    }
+
+    // Renders a value as the text of a cell.
+    fn cell(&self, node: &Document) -> Result<String> {
+        Ok(match node.as_value()? {
+            Document::String(s, _) => self.quote(s, self.color.string, false),
+            Document::StaticStr(s, _) => self.quote(s, self.color.string, false),
+            Document::Boolean(b) => self.color.boolean.paint(b.to_string()).to_string(),
+            Document::Int(i) => self.int(i)?,
+            Document::Float(f) => {
+                let ctx = NumberContext {
+                    base: Base::Dec,
+                    width: 0,
+                    precision: None,
+                    float: self.float_format,
+                };
+                let text = self.numbers.format_float(*f, &ctx);
+                self.quote(&text, self.color.float, false)
+            }
+            Document::Bytes(b) => {
+                let format = match self.bytes_format {
+                    BytesFormat::Standard => BytesFormat::HexStr,
+                    f => f,
+                };
+                // Unwrap is ok: every format but `Standard` renders a string.
+                let text = hexdump::to_string(b, format).unwrap();
+                self.quote(&text, self.color.string, false)
+            }
+            Document::Null => String::new(),
+            Document::Mapping(_) | Document::Sequence(_) => {
+                return Err(Error::Unrepresentable(
+                    "a nested mapping or sequence",
+                    "CSV",
+                ))
+            }
+            _ => return Err(Error::AbsentValue),
+        })
+    }
+
+    // Renders an integer, quoting those written in a base other than
+    // decimal.
+    fn int(&self, i: &Int) -> Result<String> {
+        let ctx = NumberContext {
+            base: i.base(),
+            width: i.width(),
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        // The dialect has no integer type suffixes.
+        let i = &i.unsuffixed();
+        i.check_format(ctx.base)?;
+        let text = self.numbers.format_int(i, &ctx);
+        Ok(self.quote(&text, self.color.integer, i.base() != Base::Dec))
+    }
+
+    // Renders `text` as a cell, quoted if `force` is set or if it holds the
+    // delimiter, a quote or a line break.
+    fn quote(&self, text: &str, color: ansi_term::Style, force: bool) -> String {
+        if force || text.contains([self.delimiter, '"', '\n', '\r']) {
+            let quote = self.color.punctuation.paint("\"");
+            let text = text.replace('"', "\"\"");
+            format!("{}{}{}", quote, color.paint(text), quote)
+        } else {
+            color.paint(text).to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    const DOC: &str = r#"[
+        // Registers of the UART.
+        {name: "CTRL", offset: 0x00, reset: 0b1010, note: "enables, \"tx\""},
+        // The status is read-only.
+        {name: "STATUS", offset: 0x04, reset: 0, note: null},
+        {name: "DATA", offset: 0x08, reset: -1, width: 1.5},
+    ]"#;
+
+    #[test]
+    fn test_csv() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_csv().to_text()?,
+            r#"# Registers of the UART.
+# The status is read-only.
+name,offset,reset,note,width
+CTRL,"0x00","0b1010","enables, ""tx""",
+STATUS,"0x04",0,,
+DATA,"0x08",-1,,1.5
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tsv() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_tsv().compact(true).header(false).to_text()?,
+            "CTRL\t\"0x00\"\t\"0b1010\"\t\"enables, \"\"tx\"\"\"\t\n\
+             STATUS\t\"0x04\"\t0\t\t\n\
+             DATA\t\"0x08\"\t-1\t\t1.5\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> Result<()> {
+        let doc = Document::parse("{a: 1}")?;
+        assert!(doc.to_csv().to_text().is_err());
+        let doc = Document::parse("[{a: [1]}]")?;
+        assert!(doc.to_csv().to_text().is_err());
+        let doc = Document::parse("[1]")?;
+        assert!(doc.to_csv().to_text().is_err());
+        Ok(())
+    }
+}
@@ -175,7 +175,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        v.visit_f32(self.doc.try_into()?)
+        v.visit_f64(self.doc.try_into()?)
     }
     fn deserialize_char<V>(self, v: V) -> Result<V::Value>
     where
@@ -207,7 +207,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         match self.doc.as_value()? {
-            Document::Bytes(b) => v.visit_byte_buf(b.clone()),
+            Document::Bytes(b) => v.visit_byte_buf(b.to_vec()),
             Document::String(s, _) => v.visit_byte_buf(hexdump::from_str(s)?),
             Document::Sequence(_) => self.deserialize_seq(v),
             _ => Err(Error::StructureError(
@@ -222,7 +222,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         match self.doc.as_value()? {
-            Document::Null => v.visit_none(),
+            Document::Null | Document::Absent => v.visit_none(),
             _ => v.visit_some(self),
         }
     }
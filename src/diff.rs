@@ -0,0 +1,473 @@
+// Structural differences between documents.
+use crate::document::Document;
+use crate::edit_script::{edit_script, Edit};
+use crate::entry::{comments_of, key_text, value_of};
+use crate::path::{pointer, segments};
+use crate::render::display_text;
+use std::fmt;
+
+/// A single difference between two documents.
+///
+/// Each change carries the path of the affected node as a JSON Pointer
+/// (e.g. `/c/k` or `/d/0`).
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// A value present only in the new document.
+    Added(String, Document),
+    /// A value present only in the old document.
+    Removed(String, Document),
+    /// A value whose contents differ between the documents.
+    Modified(String, Document, Document),
+    /// A node whose value is unchanged but whose comments differ.
+    Comment(String, Vec<String>, Vec<String>),
+}
+
+impl Change {
+    /// Returns the object-path of the changed node.
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added(p, _) => p,
+            Change::Removed(p, _) => p,
+            Change::Modified(p, _, _) => p,
+            Change::Comment(p, _, _) => p,
+        }
+    }
+
+    /// Returns whether this change only affects comments.
+    pub fn is_comment(&self) -> bool {
+        matches!(self, Change::Comment(..))
+    }
+
+    // Returns the changelog heading under which the change is listed.
+    fn heading(&self) -> &'static str {
+        match self {
+            Change::Added(..) => "Added",
+            Change::Removed(..) => "Removed",
+            Change::Modified(..) => "Changed",
+            Change::Comment(..) => "Comments",
+        }
+    }
+}
+
+/// A comment-aware difference between two documents.
+///
+/// Value changes and comment-only changes are recorded separately so that
+/// edits to annotations do not obscure edits to the data.  When displayed,
+/// all value changes are listed before any comment changes.
+#[derive(Clone, Debug)]
+pub struct Diff {
+    changes: Vec<Change>,
+    // The comments attached to changed nodes, for the changelog.
+    descriptions: Vec<(String, String)>,
+    word_diff: bool,
+}
+
+impl Diff {
+    /// Computes the differences between the `old` and `new` documents.
+    /// Mapping entries are matched by key and sequence elements are
+    /// matched by index.
+    pub fn new(old: &Document, new: &Document) -> Self {
+        let mut changes = Vec::new();
+        diff_slot(&mut changes, &mut Vec::new(), old, new);
+        let mut descriptions = Vec::new();
+        for change in changes.iter() {
+            let node = match change {
+                Change::Removed(..) => old,
+                _ => new,
+            };
+            if let Some(d) = description(node, change.path()) {
+                descriptions.push((change.path().to_string(), d));
+            }
+        }
+        Diff {
+            changes,
+            descriptions,
+            word_diff: false,
+        }
+    }
+
+    /// Display comment changes as a word-diff rather than as whole comments.
+    pub fn word_diff(mut self, b: bool) -> Self {
+        self.word_diff = b;
+        self
+    }
+
+    /// Returns all changes in document order.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Returns the changes which affect values.
+    pub fn values(&self) -> impl Iterator<Item = &Change> {
+        self.changes.iter().filter(|c| !c.is_comment())
+    }
+
+    /// Returns the changes which only affect comments.
+    pub fn comments(&self) -> impl Iterator<Item = &Change> {
+        self.changes.iter().filter(|c| c.is_comment())
+    }
+
+    /// Returns whether the documents are identical in both values and comments.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Renders the changes as a Markdown changelog section headed by
+    /// `title`, suitable for release notes.
+    ///
+    /// Changes are grouped under `Changed`, `Added`, `Removed` and
+    /// `Comments` headings, and each value change is followed by the
+    /// comment describing the node, so that readers need not consult the
+    /// document to learn what a setting means.
+    pub fn changelog(&self, title: &str) -> String {
+        let mut out = format!("## {}\n", title);
+        if self.is_empty() {
+            out.push_str("\nNo changes.\n");
+            return out;
+        }
+        for heading in ["Changed", "Added", "Removed", "Comments"] {
+            let mut changes = self
+                .changes
+                .iter()
+                .filter(|c| c.heading() == heading)
+                .peekable();
+            if changes.peek().is_none() {
+                continue;
+            }
+            out.push_str(&format!("\n### {}\n\n", heading));
+            for change in changes {
+                let text = match change {
+                    Change::Added(_, v) | Change::Removed(_, v) => format!("`{}`", display_text(v)),
+                    Change::Modified(_, o, n) => {
+                        format!("`{}` -> `{}`", display_text(o), display_text(n))
+                    }
+                    Change::Comment(_, o, n) => {
+                        let (o, n) = (o.join(" "), n.join(" "));
+                        if self.word_diff {
+                            word_diff(&o, &n)
+                        } else {
+                            format!("{:?} -> {:?}", o, n)
+                        }
+                    }
+                };
+                out.push_str(&format!("- `{}`: {}\n", change.path(), text));
+                let description = self
+                    .descriptions
+                    .iter()
+                    .find(|(p, _)| p == change.path())
+                    .filter(|_| !change.is_comment());
+                if let Some((_, d)) = description {
+                    out.push_str(&format!("  {}\n", d));
+                }
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in self.values().chain(self.comments()) {
+            match change {
+                Change::Added(p, v) => writeln!(f, "+ {}: {}", p, display_text(v))?,
+                Change::Removed(p, v) => writeln!(f, "- {}: {}", p, display_text(v))?,
+                Change::Modified(p, o, n) => {
+                    writeln!(f, "~ {}: {} -> {}", p, display_text(o), display_text(n))?
+                }
+                Change::Comment(p, o, n) => {
+                    let (o, n) = (o.join("\n"), n.join("\n"));
+                    if self.word_diff {
+                        writeln!(f, "# {}: {}", p, word_diff(&o, &n))?;
+                    } else {
+                        writeln!(f, "# {}: {:?} -> {:?}", p, o, n)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Computes a word-level difference between two strings.
+///
+/// Words are separated by whitespace.  Removed runs of words are rendered
+/// as `[-words-]` and added runs as `{+words+}`, in the style of
+/// `git diff --word-diff`.
+pub fn word_diff(old: &str, new: &str) -> String {
+    let a = old.split_whitespace().collect::<Vec<_>>();
+    let b = new.split_whitespace().collect::<Vec<_>>();
+    let mut out = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let flush = |out: &mut Vec<String>, removed: &mut Vec<&str>, added: &mut Vec<&str>| {
+        if !removed.is_empty() {
+            out.push(format!("[-{}-]", removed.join(" ")));
+            removed.clear();
+        }
+        if !added.is_empty() {
+            out.push(format!("{{+{}+}}", added.join(" ")));
+            added.clear();
+        }
+    };
+    let (mut i, mut j) = (0, 0);
+    for edit in edit_script(&a, &b) {
+        match edit {
+            Edit::Same => {
+                flush(&mut out, &mut removed, &mut added);
+                out.push(a[i].to_string());
+                i += 1;
+                j += 1;
+            }
+            Edit::Removed => {
+                removed.push(a[i]);
+                i += 1;
+            }
+            Edit::Added => {
+                added.push(b[j]);
+                j += 1;
+            }
+        }
+    }
+    flush(&mut out, &mut removed, &mut added);
+    out.join(" ")
+}
+
+fn path_string(path: &[String]) -> String {
+    pointer(path)
+}
+
+// Returns the text of all comments directly attached to a node.
+fn comments(node: &Document) -> Vec<String> {
+    comments_of(node)
+        .into_iter()
+        .map(|(c, _)| c.to_string())
+        .collect()
+}
+
+// Returns the comments attached to the node at `path`, joined
+// into a single line.
+fn description(root: &Document, path: &str) -> Option<String> {
+    let mut node = root;
+    for key in segments(path) {
+        let value = node.as_kv().map(|kv| kv.1).unwrap_or(node);
+        node = match value.as_value().ok()? {
+            Document::Mapping(m) => m.iter().find(|n| key_of(n) == Some(key.clone()))?,
+            Document::Sequence(s) => {
+                let i = key.parse::<usize>().ok()?;
+                s.iter().filter(|d| d.has_value()).nth(i)?
+            }
+            _ => return None,
+        };
+    }
+    let text = comments(node)
+        .iter()
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+// Diffs a node which may hold comments alongside its value (e.g. a sequence
+// element or a mapping entry).
+fn diff_slot(changes: &mut Vec<Change>, path: &mut Vec<String>, old: &Document, new: &Document) {
+    let (oc, nc) = (comments(old), comments(new));
+    if oc != nc {
+        changes.push(Change::Comment(path_string(path), oc, nc));
+    }
+    if let (Some(old), Some(new)) = (value_of(old), value_of(new)) {
+        diff_value(changes, path, old, new);
+    }
+}
+
+fn diff_value(changes: &mut Vec<Change>, path: &mut Vec<String>, old: &Document, new: &Document) {
+    match (old, new) {
+        (Document::Compact(o), n) => diff_value(changes, path, o, n),
+        (o, Document::Compact(n)) => diff_value(changes, path, o, n),
+        (Document::ColorClass(_, o), n) => diff_value(changes, path, o, n),
+        (o, Document::ColorClass(_, n)) => diff_value(changes, path, o, n),
+        (Document::Identified(_, o), n) => diff_value(changes, path, o, n),
+        (o, Document::Identified(_, n)) => diff_value(changes, path, o, n),
+        (Document::Typed(_, o), n) => diff_value(changes, path, o, n),
+        (o, Document::Typed(_, n)) => diff_value(changes, path, o, n),
+        (Document::Mapping(o), Document::Mapping(n)) => diff_mapping(changes, path, o, n),
+        (Document::Sequence(o), Document::Sequence(n)) => {
+            let o = o.iter().filter(|d| d.has_value()).collect::<Vec<_>>();
+            let n = n.iter().filter(|d| d.has_value()).collect::<Vec<_>>();
+            for i in 0..o.len().max(n.len()) {
+                path.push(i.to_string());
+                match (o.get(i), n.get(i)) {
+                    (Some(o), Some(n)) => diff_slot(changes, path, o, n),
+                    (Some(o), None) => changes.push(Change::Removed(
+                        path_string(path),
+                        o.as_value().unwrap_or(o).clone(),
+                    )),
+                    (None, Some(n)) => changes.push(Change::Added(
+                        path_string(path),
+                        n.as_value().unwrap_or(n).clone(),
+                    )),
+                    (None, None) => unreachable!(),
+                }
+                path.pop();
+            }
+        }
+        (o, n) => {
+            if !scalar_eq(o, n) {
+                changes.push(Change::Modified(path_string(path), o.clone(), n.clone()));
+            }
+        }
+    }
+}
+
+fn key_of(entry: &Document) -> Option<String> {
+    let (k, _) = entry.as_kv().ok()?;
+    Some(key_text(k).unwrap_or_else(|| display_text(k)))
+}
+
+fn diff_mapping(
+    changes: &mut Vec<Change>,
+    path: &mut Vec<String>,
+    old: &[Document],
+    new: &[Document],
+) {
+    for o in old.iter() {
+        let key = match key_of(o) {
+            Some(k) => k,
+            None => continue,
+        };
+        path.push(key.clone());
+        match new.iter().find(|n| key_of(n).as_ref() == Some(&key)) {
+            Some(n) => diff_slot(changes, path, o, n),
+            None => {
+                // Unwrap is ok: `key_of` succeeded, so this is a kvpair.
+                let (_, v) = o.as_kv().unwrap();
+                changes.push(Change::Removed(path_string(path), v.clone()));
+            }
+        }
+        path.pop();
+    }
+    for n in new.iter() {
+        let key = match key_of(n) {
+            Some(k) => k,
+            None => continue,
+        };
+        if !old.iter().any(|o| key_of(o).as_ref() == Some(&key)) {
+            path.push(key);
+            // Unwrap is ok: `key_of` succeeded, so this is a kvpair.
+            let (_, v) = n.as_kv().unwrap();
+            changes.push(Change::Added(path_string(path), v.clone()));
+            path.pop();
+        }
+    }
+}
+
+// Compares two non-aggregate values, ignoring formatting preferences such
+// as string style or integer base.
+pub(crate) fn scalar_eq(old: &Document, new: &Document) -> bool {
+    match (old, new) {
+        (Document::Int(o), Document::Int(n)) => o.format(None) == n.format(None),
+        (Document::Float(o), Document::Float(n)) => o == n,
+        (Document::Boolean(o), Document::Boolean(n)) => o == n,
+        (Document::Bytes(o), Document::Bytes(n)) => o == n,
+        (Document::Null, Document::Null) => true,
+        (
+            Document::String(_, _) | Document::StaticStr(_, _),
+            Document::String(_, _) | Document::StaticStr(_, _),
+        ) => old.as_str().ok() == new.as_str().ok(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    const OLD: &str = r#"{
+  // The name of the part.
+  name: "winbond",
+  // Base address of the part.
+  base: 0x2000,
+  sizes: [4096, 65536]
+}"#;
+
+    const NEW: &str = r#"{
+  // The vendor name of the part.
+  name: "winbond",
+  // Base address of the part.
+  base: 0x4000,
+  sizes: [4096, 65536, 1048576]
+}"#;
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let diff = Diff::new(&Document::parse(OLD)?, &Document::parse(NEW)?);
+        let values = diff.values().map(Change::path).collect::<Vec<_>>();
+        assert_eq!(values, vec!["/base", "/sizes/2"]);
+        let comments = diff.comments().map(Change::path).collect::<Vec<_>>();
+        assert_eq!(comments, vec!["/name"]);
+        assert_eq!(
+            diff.to_string(),
+            r#"~ /base: 0x2000 -> 0x4000
++ /sizes/2: 1048576
+# /name: "The name of the part." -> "The vendor name of the part."
+"#
+        );
+        assert_eq!(
+            diff.word_diff(true).to_string(),
+            r#"~ /base: 0x2000 -> 0x4000
++ /sizes/2: 1048576
+# /name: The {+vendor+} name of the part.
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_changelog() -> Result<()> {
+        let new = Document::parse(NEW)?;
+        let (text, changelog) = new
+            .clone()
+            .to_json5()
+            .emit_with_changelog(&Document::parse(OLD)?, "v2.0")?;
+        assert_eq!(text, new.to_json5().to_text()?);
+        assert_eq!(
+            changelog,
+            r#"## v2.0
+
+### Changed
+
+- `/base`: `0x2000` -> `0x4000`
+  Base address of the part.
+
+### Added
+
+- `/sizes/2`: `1048576`
+
+### Comments
+
+- `/name`: The {+vendor+} name of the part.
+"#
+        );
+        let doc = Document::parse(OLD)?;
+        assert_eq!(
+            Diff::new(&doc, &doc).changelog("v1.1"),
+            "## v1.1\n\nNo changes.\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_identical() -> Result<()> {
+        let doc = Document::parse(OLD)?;
+        assert!(Diff::new(&doc, &doc).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_word_diff() {
+        assert_eq!(word_diff("a b c", "a x c"), "a [-b-] {+x+} c");
+        assert_eq!(word_diff("a b", "a b c d"), "a b {+c d+}");
+        assert_eq!(word_diff("a b c", "c"), "[-a b-] c");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::document::Document;
+use crate::document::{CommentFormat, Document};
 
 impl Document {
     /// Returns an iterator over all all document nodes including
@@ -34,6 +34,64 @@ impl Document {
             path: Vec::new(),
         }
     }
+
+    /// Returns all comments in the document along with the object-path of
+    /// the node each is attached to.
+    ///
+    /// Comments attached to a mapping entry or sequence element have the
+    /// path of that entry or element; comments which are not attached to
+    /// any child (e.g. comments at the end of a mapping) have the path of
+    /// the enclosing aggregate.
+    pub fn comments(&self) -> Vec<(Vec<DocPath<'_>>, &str, CommentFormat)> {
+        let mut comments = Vec::new();
+        collect_comments(self, &mut Vec::new(), &mut comments);
+        comments
+    }
+}
+
+fn collect_comments<'a>(
+    node: &'a Document,
+    path: &mut Vec<DocPath<'a>>,
+    comments: &mut Vec<(Vec<DocPath<'a>>, &'a str, CommentFormat)>,
+) {
+    match node {
+        Document::Comment(c, f) => comments.push((path.clone(), c, *f)),
+        Document::Fragment(nodes) => {
+            for n in nodes {
+                collect_comments(n, path, comments);
+            }
+        }
+        Document::Mapping(m) => {
+            for entry in m {
+                match entry.as_kv().map(|(k, _)| k.as_str()) {
+                    Ok(Ok(key)) => {
+                        path.push(DocPath::Name(key));
+                        collect_comments(entry, path, comments);
+                        path.pop();
+                    }
+                    _ => collect_comments(entry, path, comments),
+                }
+            }
+        }
+        Document::Sequence(s) => {
+            let mut index = 0;
+            for elem in s {
+                if elem.has_value() {
+                    path.push(DocPath::Index(index));
+                    collect_comments(elem, path, comments);
+                    path.pop();
+                    index += 1;
+                } else {
+                    collect_comments(elem, path, comments);
+                }
+            }
+        }
+        Document::Compact(v)
+        | Document::ColorClass(_, v)
+        | Document::Identified(_, v)
+        | Document::Typed(_, v) => collect_comments(v, path, comments),
+        _ => {}
+    }
 }
 
 impl<'a> IntoIterator for &'a Document {
@@ -66,6 +124,9 @@ impl<'a> Iterator for DocIter<'a> {
             Document::Mapping(v) => self.stack.push(v.iter()),
             Document::Sequence(v) => self.stack.push(v.iter()),
             Document::Compact(v) => self.stack.push(std::slice::from_ref(&**v).iter()),
+            Document::ColorClass(_, v) | Document::Identified(_, v) | Document::Typed(_, v) => {
+                self.stack.push(std::slice::from_ref(&**v).iter())
+            }
             Document::Fragment(v) => self.stack.push(v.iter()),
             _ => {}
         };
@@ -117,7 +178,10 @@ impl<'a> Iterator for DocPathIter<'a> {
                     self.path.push(DocPath::Index(usize::MAX));
                     self.aggregate.push(true);
                 }
-                Some(Document::Compact(v)) => {
+                Some(Document::Compact(v))
+                | Some(Document::ColorClass(_, v))
+                | Some(Document::Identified(_, v))
+                | Some(Document::Typed(_, v)) => {
                     self.stack.push(std::slice::from_ref(&**v).iter());
                     self.aggregate.push(false);
                 }
@@ -175,7 +239,10 @@ impl<'a> Iterator for DocPathIterMut<'a> {
                     self.path.push(DocPath::Index(usize::MAX));
                     self.aggregate.push(true);
                 }
-                Some(Document::Compact(ref mut v)) => {
+                Some(Document::Compact(ref mut v))
+                | Some(Document::ColorClass(_, ref mut v))
+                | Some(Document::Identified(_, ref mut v))
+                | Some(Document::Typed(_, ref mut v)) => {
                     self.stack.push(std::slice::from_mut(&mut **v).iter_mut());
                     self.aggregate.push(false);
                 }
@@ -272,6 +339,53 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_comments() -> Result<()> {
+        let doc = Document::parse(
+            r#"
+            // The sample.
+            {
+                // First.
+                a: 1,
+                /* Inner. */
+                c: {
+                    # Key.
+                    k: 0,
+                },
+                d: [
+                    100,
+                    // Second element.
+                    200,
+                ],
+                // Trailing.
+            }"#,
+        )?;
+        let comments = doc
+            .comments()
+            .into_iter()
+            .map(|(p, c, f)| {
+                let path = p
+                    .iter()
+                    .map(DocPath::to_string)
+                    .collect::<Vec<_>>()
+                    .join(".");
+                (path, c, f)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            comments,
+            vec![
+                ("".into(), "The sample.", CommentFormat::SlashSlash),
+                ("a".into(), "First.", CommentFormat::SlashSlash),
+                ("c".into(), "Inner.", CommentFormat::Block),
+                ("c.k".into(), "Key.", CommentFormat::Hash),
+                ("d.1".into(), "Second element.", CommentFormat::SlashSlash),
+                ("".into(), "Trailing.", CommentFormat::SlashSlash),
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_iter_path_mut() -> Result<()> {
         let expect = Sample {
@@ -1,8 +1,13 @@
 // Document Enum for serialization
 use std::convert::TryFrom;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
 
 use crate::error::Error;
 use crate::integer::Int;
+use crate::node_id::NodeId;
+use crate::number::parse_hex_float;
 use crate::relax::Relax;
 
 /// Represents possible serialized string formats.
@@ -16,6 +21,24 @@ pub enum StrFormat {
     Unquoted,
     /// Format the string as a multiline block, if allowed by the backend.
     Multiline,
+    /// Quote the string with single quotes, if allowed by the backend.
+    SingleQuoted,
+    /// Format the string as a literal block (`|`), if allowed by the backend.
+    /// Backends without literal blocks treat this as `Multiline`.
+    Literal,
+    /// Format the string as a folded block (`>`), if allowed by the backend.
+    /// Backends without folded blocks treat this as `Multiline`.
+    Folded,
+}
+
+impl StrFormat {
+    // Returns whether this is one of the multiline block formats.
+    pub(crate) fn is_multiline(&self) -> bool {
+        matches!(
+            self,
+            StrFormat::Multiline | StrFormat::Literal | StrFormat::Folded
+        )
+    }
 }
 
 /// Represents possible serialized bytes formats.
@@ -25,12 +48,24 @@ pub enum BytesFormat {
     Standard,
     /// Hexadecimal string (e.g. "98ab45cdeaff").
     HexStr,
+    /// Base64 string marked with a `base64:` prefix (e.g. "base64:mKtFzer/").
+    Base64,
     /// Hexdump like `hexdump -vC ...`.
     Hexdump,
     /// Hexdump like `xxd ...`.
     Xxd,
 }
 
+impl BytesFormat {
+    // Returns the string format of bytes rendered in this format.
+    pub(crate) fn str_format(&self) -> StrFormat {
+        match self {
+            BytesFormat::Hexdump | BytesFormat::Xxd => StrFormat::Multiline,
+            _ => StrFormat::Standard,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CommentFormat {
     /// The standard format for the serialization backend.
@@ -41,6 +76,34 @@ pub enum CommentFormat {
     Hash,
     /// Render comments in single-line slash-slash form if allowed by the backend.
     SlashSlash,
+    /// Render comments in single-line form with a custom leader (e.g. `;;`,
+    /// `--` or `REM`) if allowed by the backend.
+    Custom(&'static str),
+}
+
+impl CommentFormat {
+    /// Returns the leader which starts each line of a comment in this format.
+    /// The lines of block comments, which are enclosed in `/*` and `*/`,
+    /// start with ` *`.
+    pub fn leader(&self) -> &'static str {
+        match self {
+            CommentFormat::Standard | CommentFormat::SlashSlash => "//",
+            CommentFormat::Hash => "#",
+            CommentFormat::Block => " *",
+            CommentFormat::Custom(leader) => leader,
+        }
+    }
+}
+
+/// The serde types recorded on serialized nodes by `Document::Typed`,
+/// which the node alone does not show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SerdeType {
+    /// An enum variant in serde's externally tagged form: a mapping with a
+    /// single entry from the variant name to its content.
+    Variant,
+    /// The value of an `Option` which is `Some`.
+    Some,
 }
 
 #[derive(Clone, Debug)]
@@ -61,12 +124,25 @@ pub enum Document {
     Mapping(Vec<Document>),
     // A sequence objecct (e.g. list/array/etc)
     Sequence(Vec<Document>),
-    // A special form for bytes objects.
-    Bytes(Vec<u8>),
+    // A special form for bytes objects.  The buffer is shared so that large
+    // payloads serialized via `SharedBytes` are not copied.
+    Bytes(Arc<[u8]>),
     // A null value.
     Null,
+    // The absence of a value: a mapping entry whose value is absent is
+    // removed by `Document::merge_patch`, whereas one whose value is null is
+    // set to null.  Emitters reject absent values.
+    Absent,
     // A hint to the emitter to emit in compact form.
     Compact(Box<Document>),
+    // A hint to the emitter to style the node with a user-defined color
+    // class from its `ColorProfile`.
+    ColorClass(String, Box<Document>),
+    // A node marked with a stable identifier (see `Document::get_by_id`).
+    Identified(NodeId, Box<Document>),
+    // A hint recording the serde type a node was serialized from, for
+    // emitters which reproduce Rust types.
+    Typed(SerdeType, Box<Document>),
     // A fragment holds a set of document nodes that may be useful as an
     // aggregate, such as Key-Value pairs.
     Fragment(Vec<Document>),
@@ -78,6 +154,16 @@ impl From<&'static str> for Document {
     }
 }
 
+impl FromStr for Document {
+    type Err = Error;
+
+    /// Parses a string into a `Document` using strict json.  Use
+    /// `Document::parse` for the maximally permissive parser.
+    fn from_str(text: &str) -> Result<Document, Error> {
+        Document::from_json(text)
+    }
+}
+
 impl Document {
     /// Parses a string into a `Document` using the maximally permissive parser.
     pub fn parse(text: &str) -> Result<Document, Error> {
@@ -85,12 +171,45 @@ impl Document {
         relax.from_str(text)
     }
 
+    /// Parses a string into a `Document` using the maximally permissive
+    /// parser, recovering from syntax errors.
+    ///
+    /// Returns a best-effort document along with every error found, so that
+    /// editors and linters can report all of the problems in a file at once.
+    /// Values supplied in place of malformed text are wrapped in the
+    /// `ERROR_CLASS` color class.
+    pub fn parse_tolerant(text: &str) -> (Document, Vec<Error>) {
+        let relax = Relax::default();
+        relax.from_str_tolerant(text)
+    }
+
     /// Parses a string into a `Document` using strict json.
     pub fn from_json(text: &str) -> Result<Document, Error> {
         let relax = Relax::json();
         relax.from_str(text)
     }
 
+    /// Reads and parses a `Document` using strict json.  Failures to read
+    /// are reported as `Error::IoError`.
+    pub fn from_reader<R: io::Read>(mut reader: R) -> Result<Document, Error> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Document::from_json(&text)
+    }
+
+    /// Parses a string into a `Document` using strict json, recovering from
+    /// syntax errors.  See `Document::parse_tolerant`.
+    pub fn from_json_tolerant(text: &str) -> (Document, Vec<Error>) {
+        let relax = Relax::json();
+        relax.from_str_tolerant(text)
+    }
+
+    /// Parses a string into a `Document` using JSON with comments.
+    pub fn from_jsonc(text: &str) -> Result<Document, Error> {
+        let relax = Relax::jsonc();
+        relax.from_str(text)
+    }
+
     /// Parses a string into a `Document` using json5.
     pub fn from_json5(text: &str) -> Result<Document, Error> {
         let relax = Relax::json5();
@@ -116,7 +235,11 @@ impl Document {
             Document::Sequence(_) => "Sequence",
             Document::Bytes(_) => "Bytes",
             Document::Null => "Null",
+            Document::Absent => "Absent",
             Document::Compact(_) => "Compact",
+            Document::ColorClass(_, _) => "ColorClass",
+            Document::Identified(_, _) => "Identified",
+            Document::Typed(_, _) => "Typed",
             Document::Fragment(_) => "Fragment",
         }
     }
@@ -177,6 +300,9 @@ impl Document {
         match self {
             Document::Comment(_, _) => Err(Error::StructureError("a value", "Comment")),
             Document::Compact(c) => c.as_value(),
+            Document::ColorClass(_, c) => c.as_value(),
+            Document::Identified(_, c) => c.as_value(),
+            Document::Typed(_, c) => c.as_value(),
             Document::Fragment(frags) => {
                 let values = frags.iter().filter(|f| f.has_value()).collect::<Vec<_>>();
                 match values.len() {
@@ -196,6 +322,9 @@ impl Document {
         match self {
             Document::Comment(_, _) => Err(Error::StructureError("a value", "Comment")),
             Document::Compact(c) => c.as_value_mut(),
+            Document::ColorClass(_, c) => c.as_value_mut(),
+            Document::Identified(_, c) => c.as_value_mut(),
+            Document::Typed(_, c) => c.as_value_mut(),
             Document::Fragment(frags) => {
                 let mut values = frags
                     .iter_mut()
@@ -211,16 +340,36 @@ impl Document {
         }
     }
 
+    /// Returns the number of `StaticStr` nodes in the document.  Each one
+    /// is a string which was not copied into its own allocation, such as
+    /// the struct field names and enum variant names of a serialized value.
+    pub fn static_str_count(&self) -> usize {
+        self.iter()
+            .filter(|n| matches!(n, Document::StaticStr(_, _)))
+            .count()
+    }
+
     /// Returns whether this node is a value-containing node.
     pub fn has_value(&self) -> bool {
         match self {
             Document::Comment(_, _) => false,
             Document::Compact(c) => c.has_value(),
+            Document::ColorClass(_, c) => c.has_value(),
+            Document::Identified(_, c) => c.has_value(),
+            Document::Typed(_, c) => c.has_value(),
             Document::Fragment(f) => f.iter().any(Document::has_value),
             _ => true,
         }
     }
 
+    /// Returns whether this node is a key-value pair whose value is null.
+    pub(crate) fn is_null_entry(&self) -> bool {
+        matches!(
+            self.as_kv().map(|(_, v)| v.as_value()),
+            Ok(Ok(Document::Null))
+        )
+    }
+
     /// Returns the index of the last value containing node in a slice.
     pub fn last_value_index(sequence: &[Document]) -> usize {
         let mut last = sequence.len();
@@ -306,8 +455,10 @@ macro_rules! impl_int_conv {
                 match v.as_value()? {
                     Document::Int(v) => Ok(<$t>::from(v)),
                     Document::Float(v) => Ok(*v as $t),
-                    Document::String(s, _) => Ok(<$t>::from(Int::from_str_radix(s.as_str(), 0)?)),
-                    Document::StaticStr(s, _) => Ok(<$t>::from(Int::from_str_radix(s, 0)?)),
+                    Document::String(s, _) => {
+                        Ok(<$t>::from(Int::from_str_suffixed(s.as_str(), 0)?))
+                    }
+                    Document::StaticStr(s, _) => Ok(<$t>::from(Int::from_str_suffixed(s, 0)?)),
                     _ => Err(Error::StructureError("Int", v.variant())),
                 }
             }
@@ -325,6 +476,41 @@ impl_int_conv!(i32);
 impl_int_conv!(i64);
 impl_int_conv!(i128);
 
+// Returns the value of a non-finite float encoded as a string (e.g. `"NaN"`
+// or `"-Infinity"`).
+pub(crate) fn non_finite_from_str(s: &str) -> Option<f64> {
+    match s {
+        "NaN" | "nan" => Some(f64::NAN),
+        "Infinity" | "+Infinity" | "inf" | "+inf" => Some(f64::INFINITY),
+        "-Infinity" | "-inf" => Some(f64::NEG_INFINITY),
+        _ => None,
+    }
+}
+
+// Returns the value of a float encoded as a string: either a non-finite
+// value or a hexadecimal float.
+fn float_from_str(s: &str) -> Option<f64> {
+    non_finite_from_str(s).or_else(|| parse_hex_float(s))
+}
+
+impl Document {
+    /// Returns the value of a non-finite float encoded as an object of the
+    /// form `{"$float": "inf"}`.
+    pub(crate) fn as_float_object(&self) -> Option<f64> {
+        match self {
+            Document::Mapping(m) if m.iter().filter(|e| e.has_value()).count() == 1 => {
+                let (k, v) = m.iter().find(|e| e.has_value())?.as_kv().ok()?;
+                if k.as_str().ok()? == "$float" {
+                    non_finite_from_str(v.as_str().ok()?)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
 macro_rules! impl_float_conv {
     ($t:ty) => {
         /// Tries to convert the document into a float value.
@@ -334,7 +520,16 @@ macro_rules! impl_float_conv {
                 match v.as_value()? {
                     Document::Int(v) => Ok(<$t>::from(v)),
                     Document::Float(v) => Ok(*v as $t),
-                    _ => Err(Error::StructureError("Float", v.variant())),
+                    Document::String(s, _) => float_from_str(s)
+                        .map(|f| f as $t)
+                        .ok_or(Error::StructureError("Float", "String")),
+                    Document::StaticStr(s, _) => float_from_str(s)
+                        .map(|f| f as $t)
+                        .ok_or(Error::StructureError("Float", "String")),
+                    d => d
+                        .as_float_object()
+                        .map(|f| f as $t)
+                        .ok_or(Error::StructureError("Float", v.variant())),
                 }
             }
         }
@@ -0,0 +1,249 @@
+// A line-oriented dump of the document tree for debugging.
+use crate::ascii::escape_char;
+use crate::document::{CommentFormat, Document, SerdeType, StrFormat};
+use crate::integer::{Base, Int, IntValue, NegativeFormat};
+use std::fmt::Write;
+
+impl Document {
+    /// Returns an explicit, lossless dump of the document tree, one node per
+    /// line, for inclusion in bug reports about surprising output.
+    ///
+    /// Each line holds the node's variant name followed by its formatting
+    /// properties and value, and the children of a node follow it on lines
+    /// indented by two more spaces:
+    ///
+    /// ```text
+    /// Mapping
+    ///   Fragment
+    ///     Comment Standard "The port."
+    ///     String Standard "port"
+    ///     Int U16(8080) base=Hex width=0 negative=TwosComplement
+    /// ```
+    ///
+    /// Strings are double-quoted, escaping `"`, `\`, and control characters.
+    /// Floats are written in their shortest round-trip form and bytes in
+    /// hexadecimal.  The format of each kind of line is stable: new
+    /// properties are only ever appended.
+    pub fn dump_debug(&self) -> String {
+        let mut out = String::new();
+        dump(&mut out, self, 0);
+        out
+    }
+}
+
+fn dump(out: &mut String, node: &Document, level: usize) {
+    for _ in 0..level {
+        out.push_str("  ");
+    }
+    out.push_str(node.variant());
+    let children: &[Document] = match node {
+        Document::Comment(c, f) => {
+            out.push(' ');
+            comment_format(out, f);
+            out.push(' ');
+            quote(out, c);
+            &[]
+        }
+        Document::String(s, f) => {
+            let _ = write!(out, " {} ", str_format(f));
+            quote(out, s);
+            &[]
+        }
+        Document::StaticStr(s, f) => {
+            let _ = write!(out, " {} ", str_format(f));
+            quote(out, s);
+            &[]
+        }
+        Document::Boolean(b) => {
+            let _ = write!(out, " {}", b);
+            &[]
+        }
+        Document::Int(i) => {
+            dump_int(out, i);
+            &[]
+        }
+        Document::Float(f) => {
+            let _ = write!(out, " {:?}", f);
+            &[]
+        }
+        Document::Bytes(b) => {
+            let _ = write!(out, " {} ", b.len());
+            for byte in b.iter() {
+                let _ = write!(out, "{:02x}", byte);
+            }
+            &[]
+        }
+        Document::Null | Document::Absent => &[],
+        Document::Mapping(m) | Document::Sequence(m) | Document::Fragment(m) => m,
+        Document::Compact(d) => std::slice::from_ref(d.as_ref()),
+        Document::ColorClass(c, d) => {
+            out.push(' ');
+            quote(out, c);
+            std::slice::from_ref(d.as_ref())
+        }
+        Document::Identified(id, d) => {
+            let _ = write!(out, " NodeId({})", id.raw());
+            std::slice::from_ref(d.as_ref())
+        }
+        Document::Typed(t, d) => {
+            out.push_str(match t {
+                SerdeType::Variant => " variant",
+                SerdeType::Some => " some",
+            });
+            std::slice::from_ref(d.as_ref())
+        }
+    };
+    out.push('\n');
+    for child in children {
+        dump(out, child, level + 1);
+    }
+}
+
+fn dump_int(out: &mut String, i: &Int) {
+    let (ty, v) = match i.value() {
+        IntValue::U8(v) => ("U8", v.to_string()),
+        IntValue::U16(v) => ("U16", v.to_string()),
+        IntValue::U32(v) => ("U32", v.to_string()),
+        IntValue::U64(v) => ("U64", v.to_string()),
+        IntValue::U128(v) => ("U128", v.to_string()),
+        IntValue::I8(v) => ("I8", v.to_string()),
+        IntValue::I16(v) => ("I16", v.to_string()),
+        IntValue::I32(v) => ("I32", v.to_string()),
+        IntValue::I64(v) => ("I64", v.to_string()),
+        IntValue::I128(v) => ("I128", v.to_string()),
+    };
+    let base = match i.base() {
+        Base::Bin => "Bin",
+        Base::Oct => "Oct",
+        Base::Dec => "Dec",
+        Base::Hex => "Hex",
+    };
+    let _ = write!(out, " {}({}) base={} width=", ty, v, base);
+    // A width of `usize::MAX` pads to the width of the value's type.
+    match i.width() {
+        usize::MAX => out.push_str("type"),
+        w => {
+            let _ = write!(out, "{}", w);
+        }
+    }
+    out.push_str(" negative=");
+    match i.negative_format() {
+        NegativeFormat::TwosComplement => out.push_str("TwosComplement"),
+        NegativeFormat::SignMagnitude => out.push_str("SignMagnitude"),
+        NegativeFormat::Wrapped(bits) => {
+            let _ = write!(out, "Wrapped({})", bits);
+        }
+        NegativeFormat::WrappedSuffix(bits) => {
+            let _ = write!(out, "WrappedSuffix({})", bits);
+        }
+    }
+}
+
+// The names below are spelled out rather than taken from `Debug` so that the
+// dump format stays stable when the enums change.
+fn str_format(f: &StrFormat) -> &'static str {
+    match f {
+        StrFormat::Standard => "Standard",
+        StrFormat::Quoted => "Quoted",
+        StrFormat::Unquoted => "Unquoted",
+        StrFormat::Multiline => "Multiline",
+        StrFormat::SingleQuoted => "SingleQuoted",
+        StrFormat::Literal => "Literal",
+        StrFormat::Folded => "Folded",
+    }
+}
+
+fn comment_format(out: &mut String, f: &CommentFormat) {
+    match f {
+        CommentFormat::Standard => out.push_str("Standard"),
+        CommentFormat::Block => out.push_str("Block"),
+        CommentFormat::Hash => out.push_str("Hash"),
+        CommentFormat::SlashSlash => out.push_str("SlashSlash"),
+        CommentFormat::Custom(leader) => {
+            out.push_str("Custom(");
+            quote(out, leader);
+            out.push(')');
+        }
+    }
+}
+
+// Writes `s` double-quoted, escaping quotes, backslashes and control
+// characters.
+fn quote(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => escape_char(out, c),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_dump_debug() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // The "port".
+                port: 0x1F90,
+                tags: ['a\tb', 1.5],
+                none: null,
+            }"#,
+        )?;
+        assert_eq!(
+            doc.dump_debug(),
+            r#"Mapping
+  Fragment
+    Comment SlashSlash "The \"port\"."
+    String Unquoted "port"
+    Int U128(8080) base=Hex width=4 negative=TwosComplement
+  Fragment
+    String Unquoted "tags"
+    Sequence
+      String SingleQuoted "a\tb"
+      Float 1.5
+  Fragment
+    String Unquoted "none"
+    Null
+"#
+        );
+
+        let doc = Document::Compact(Box::new(Document::Sequence(vec![
+            Document::Int(Int::new_padded(7u8, Base::Bin)),
+            Document::Int(
+                Int::new(-6i8, Base::Hex).with_negative_format(NegativeFormat::Wrapped(16)),
+            ),
+            Document::Comment("note".into(), CommentFormat::Custom(";;")),
+            Document::Bytes(vec![0, 255].into()),
+            Document::ColorClass(
+                "warn".into(),
+                Box::new(Document::StaticStr("é\u{1}", StrFormat::Quoted)),
+            ),
+            Document::Float(f64::NAN),
+        ])));
+        assert_eq!(
+            doc.dump_debug(),
+            r#"Compact
+  Sequence
+    Int U8(7) base=Bin width=type negative=TwosComplement
+    Int I8(-6) base=Hex width=0 negative=Wrapped(16)
+    Comment Custom(";;") "note"
+    Bytes 2 00ff
+    ColorClass "warn"
+      StaticStr Quoted "é\u0001"
+    Float NaN
+"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+// Shortest edit scripts between sequences, for line and word diffs.
+
+/// One step of an edit script turning a sequence `a` into a sequence `b`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Edit {
+    /// The next elements of `a` and `b` are equal.
+    Same,
+    /// The next element of `a` is removed.
+    Removed,
+    /// The next element of `b` is added.
+    Added,
+}
+
+// Returns a shortest edit script turning `a` into `b`.
+//
+// This is Myers' O((N+M)D) difference algorithm in its linear space form:
+// the script is split at a "middle snake" found by searching from both ends
+// at once, and each half is solved recursively.  Within each run of
+// changes, removals precede additions.
+pub(crate) fn edit_script<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(a.len().max(b.len()));
+    script(a, b, &mut edits);
+    // Reorder each run of changes so that its removals come first.
+    let mut start = 0;
+    while start < edits.len() {
+        let len = edits[start..]
+            .iter()
+            .take_while(|e| **e != Edit::Same)
+            .count();
+        edits[start..start + len].sort_by_key(|e| *e == Edit::Added);
+        start += len + 1;
+    }
+    edits
+}
+
+fn script<T: PartialEq>(a: &[T], b: &[T], out: &mut Vec<Edit>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+    repeat(out, Edit::Same, prefix);
+    if a.is_empty() || b.is_empty() {
+        repeat(out, Edit::Removed, a.len());
+        repeat(out, Edit::Added, b.len());
+    } else {
+        // With the common prefix and suffix removed, at least two edits
+        // are needed, so both halves are strictly smaller problems.
+        let (x, y, u, v) = middle_snake(a, b);
+        script(&a[..x], &b[..y], out);
+        repeat(out, Edit::Same, u - x);
+        script(&a[u..], &b[v..], out);
+    }
+    repeat(out, Edit::Same, suffix);
+}
+
+fn repeat(out: &mut Vec<Edit>, edit: Edit, count: usize) {
+    out.resize(out.len() + count, edit);
+}
+
+// Returns the start and end points `(x, y, u, v)` of a diagonal run of
+// equal elements which lies on some shortest edit path from `(0, 0)` to
+// `(a.len(), b.len())`.
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> (usize, usize, usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    // The furthest x reached on each diagonal k = x - y, searching forward
+    // from the start and backward from the end.  Diagonals of the backward
+    // search are measured from the end, so that forward diagonal k is
+    // backward diagonal delta - k.
+    let offset = max + 1;
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)]
+            } else {
+                forward[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+            let c = delta - k;
+            if odd && c.abs() < d && x + backward[at(c)] >= n {
+                return (x0 as usize, y0 as usize, x as usize, y as usize);
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                backward[at(k + 1)]
+            } else {
+                backward[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(k)] = x;
+            let c = delta - k;
+            if !odd && c.abs() <= d && x + forward[at(c)] >= n {
+                return (
+                    (n - x) as usize,
+                    (m - y) as usize,
+                    (n - x0) as usize,
+                    (m - y0) as usize,
+                );
+            }
+        }
+    }
+    unreachable!("the searches meet within (n + m + 1) / 2 steps");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Applies `edits` to `a`, taking added elements from `b`.
+    fn apply(a: &[char], b: &[char], edits: &[Edit]) -> Vec<char> {
+        let (mut i, mut j) = (0, 0);
+        let mut out = Vec::new();
+        for e in edits {
+            match e {
+                Edit::Same => {
+                    assert_eq!(a[i], b[j]);
+                    out.push(a[i]);
+                    i += 1;
+                    j += 1;
+                }
+                Edit::Removed => i += 1,
+                Edit::Added => {
+                    out.push(b[j]);
+                    j += 1;
+                }
+            }
+        }
+        assert_eq!((i, j), (a.len(), b.len()));
+        out
+    }
+
+    #[test]
+    fn test_edit_script() {
+        for (a, b, distance) in [
+            ("", "", 0),
+            ("abc", "abc", 0),
+            ("", "abc", 3),
+            ("abc", "", 3),
+            ("abcabba", "cbabac", 5),
+            ("kitten", "sitting", 5),
+            ("xaxbxcx", "abc", 4),
+            ("the quick brown fox", "a quick brown dog", 8),
+        ] {
+            let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+            let edits = edit_script(&a, &b);
+            assert_eq!(apply(&a, &b, &edits), b);
+            assert_eq!(edits.iter().filter(|e| **e != Edit::Same).count(), distance);
+        }
+        // Compare the script lengths with a longest-common-subsequence
+        // table over pseudo-random strings.
+        let mut seed = 7u32;
+        let mut text = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    (b'a' + (seed >> 16) as u8 % 3) as char
+                })
+                .collect::<Vec<_>>()
+        };
+        for len in 0..40 {
+            let (a, b) = (text(len), text(len / 2 + 3));
+            let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for i in (0..a.len()).rev() {
+                for j in (0..b.len()).rev() {
+                    lcs[i][j] = if a[i] == b[j] {
+                        lcs[i + 1][j + 1] + 1
+                    } else {
+                        lcs[i + 1][j].max(lcs[i][j + 1])
+                    };
+                }
+            }
+            let edits = edit_script(&a, &b);
+            assert_eq!(apply(&a, &b, &edits), b);
+            let same = edits.iter().filter(|e| **e == Edit::Same).count();
+            assert_eq!(same, lcs[0][0]);
+        }
+
+        use Edit::*;
+        assert_eq!(
+            edit_script(&['a', 'b', 'c'], &['a', 'x', 'c']),
+            [Same, Removed, Added, Same]
+        );
+    }
+}
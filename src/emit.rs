@@ -0,0 +1,335 @@
+// Selection of the output dialect at runtime.
+use std::fmt;
+use std::io;
+
+use crate::ascii::AsciiComments;
+use crate::color::ColorProfile;
+use crate::document::Document;
+use crate::error::Error;
+use crate::newline::{Newline, NewlineWriter};
+use crate::path::pointer;
+use crate::sink::{GuardWriter, TeeWriter};
+use crate::sort::KeySort;
+use crate::unambiguous::Dialect;
+
+/// Formatting options shared by the emitters of every dialect.
+///
+/// The fields mirror the builder methods of the same names on `Json` and
+/// `Yaml`; they are public so that generic code can adjust them through
+/// `Emit::options` without knowing the dialect.
+#[derive(Clone)]
+pub struct CommonOptions {
+    /// The number of spaces per level of indentation.
+    pub indent: usize,
+    /// Whether to emit the document on a single line.
+    pub compact: bool,
+    /// The colors used for each class of token.
+    pub color: ColorProfile,
+    /// The line ending written after each line.
+    pub newline: Newline,
+    /// The order in which mapping keys are emitted.
+    pub sort_keys: KeySort,
+    /// Whether mapping entries with null values are omitted.
+    pub skip_null: bool,
+    /// The number of bytes of output which are colorized, if limited.
+    pub color_sampling: Option<usize>,
+    /// The number of bytes of output, including color escapes, beyond which
+    /// rendering fails, if limited.
+    pub max_output: Option<usize>,
+    /// The depth of nesting of mappings and sequences beyond which rendering
+    /// fails, if limited.
+    pub max_depth: Option<usize>,
+    /// How comments containing non-ASCII characters are handled.
+    pub ascii_comments: AsciiComments,
+}
+
+impl Default for CommonOptions {
+    fn default() -> Self {
+        CommonOptions {
+            indent: 2,
+            compact: false,
+            color: ColorProfile::default(),
+            newline: Newline::Lf,
+            sort_keys: KeySort::None,
+            skip_null: false,
+            color_sampling: None,
+            max_output: None,
+            max_depth: None,
+            ascii_comments: AsciiComments::Escape,
+        }
+    }
+}
+
+impl CommonOptions {
+    // Fails with `Error::NonAsciiComment` if the `ascii_comments` policy
+    // rejects a comment of `doc`.  Compact output has no comments.
+    pub(crate) fn check_comments(&self, doc: &Document) -> Result<(), Error> {
+        if self.compact || self.ascii_comments != AsciiComments::Reject {
+            return Ok(());
+        }
+        match doc.comments().into_iter().find(|(_, c, _)| !c.is_ascii()) {
+            Some((path, comment, _)) => {
+                let path = path.iter().map(ToString::to_string).collect::<Vec<_>>();
+                Err(Error::NonAsciiComment(pointer(&path), comment.to_string()))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+// Defines the builder methods which set the fields of the `CommonOptions`
+// held in the `common` field of an emitter.  The options whose meaning is
+// the same in every dialect carry their own documentation; the others take
+// the doc comments written before their names.
+macro_rules! common_options {
+    ($($(#[doc = $doc:literal])* $option:ident),* $(,)?) => {
+        $(common_options!(@option [$($doc)*] $option);)*
+    };
+    (@option [$($doc:literal)*] indent) => {
+        $(#[doc = $doc])*
+        pub fn indent(mut self, i: usize) -> Self {
+            self.common.indent = i;
+            self
+        }
+    };
+    (@option [$($doc:literal)*] compact) => {
+        $(#[doc = $doc])*
+        pub fn compact(mut self, b: bool) -> Self {
+            self.common.compact = b;
+            self
+        }
+    };
+    (@option [$($doc:literal)*] sort_keys) => {
+        $(#[doc = $doc])*
+        pub fn sort_keys(mut self, s: $crate::sort::KeySort) -> Self {
+            self.common.sort_keys = s;
+            self
+        }
+    };
+    (@option [$($doc:literal)*] skip_null) => {
+        $(#[doc = $doc])*
+        pub fn skip_null(mut self, b: bool) -> Self {
+            self.common.skip_null = b;
+            self
+        }
+    };
+    (@option [] color) => {
+        /// Set the colors used for each class of token.
+        pub fn color(mut self, c: $crate::color::ColorProfile) -> Self {
+            self.common.color = c;
+            self
+        }
+    };
+    (@option [] color_sampling) => {
+        /// Colorize only the first `n` bytes of the output, so that
+        /// colorizing a huge document does not flood a terminal with escape
+        /// sequences.
+        pub fn color_sampling(mut self, n: usize) -> Self {
+            self.common.color_sampling = Some(n);
+            self
+        }
+    };
+    (@option [] max_output) => {
+        /// Fail rendering with `Error::OutputLimit` once the output,
+        /// including color escapes, would exceed `n` bytes.
+        pub fn max_output(mut self, n: usize) -> Self {
+            self.common.max_output = Some(n);
+            self
+        }
+    };
+    (@option [] max_depth) => {
+        /// Fail rendering with `Error::DepthLimit` when mappings and
+        /// sequences nest more than `n` deep, rather than recursing without
+        /// bound.
+        pub fn max_depth(mut self, n: usize) -> Self {
+            self.common.max_depth = Some(n);
+            self
+        }
+    };
+    (@option [] ascii_comments) => {
+        /// Set whether comments containing non-ASCII characters fail
+        /// rendering with `Error::NonAsciiComment`
+        /// (`AsciiComments::Reject`).  The dialect has no ASCII-only output,
+        /// so other policies leave comments as they are.
+        pub fn ascii_comments(mut self, a: $crate::ascii::AsciiComments) -> Self {
+            self.common.ascii_comments = a;
+            self
+        }
+    };
+    (@option [$($doc:literal)+] ascii_comments) => {
+        $(#[doc = $doc])*
+        pub fn ascii_comments(mut self, a: $crate::ascii::AsciiComments) -> Self {
+            self.common.ascii_comments = a;
+            self
+        }
+    };
+    (@option [] newline) => {
+        /// Set the line ending style of the output.
+        pub fn newline(mut self, n: $crate::newline::Newline) -> Self {
+            self.common.newline = n;
+            self
+        }
+    };
+}
+pub(crate) use common_options;
+
+/// An emitter of a `Document` in some dialect, so that the output format
+/// can be chosen at runtime (e.g. from a command line flag) and handled as
+/// a `Box<dyn Emit>`.
+///
+/// Rendering can fail, for example when the output exceeds
+/// `CommonOptions::max_output`, so the emitters do not implement `Display`:
+/// use `to_text` to render to a string.
+///
+/// ```
+/// # use serde_annotate::{Dialect, Document, Emit};
+/// let doc = Document::parse("{a: [1, 2]}")?;
+/// let mut emitter = doc.to_dialect(Dialect::Yaml);
+/// emitter.options().compact = true;
+/// assert_eq!(emitter.to_text()?, "---\n{a: [1, 2]}");
+/// # Ok::<(), serde_annotate::Error>(())
+/// ```
+pub trait Emit {
+    /// Renders the document to `w`, applying the `newline`,
+    /// `color_sampling` and `max_output` options.
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<(), Error>;
+
+    /// Returns the options shared by all dialects, for modification.
+    fn options(&mut self) -> &mut CommonOptions;
+
+    /// Emits the document as a string.
+    fn to_text(&self) -> Result<String, Error> {
+        let mut text = String::new();
+        self.write_to(&mut text)?;
+        Ok(text)
+    }
+
+    /// Emits the document to `writer` (e.g. a file or socket), streaming
+    /// the output as it is produced.  Failures of `writer` are reported as
+    /// `Error::IoError`.  Unbuffered writers such as files should be
+    /// wrapped in a `BufWriter`.
+    fn to_writer(&self, writer: &mut dyn io::Write) -> Result<(), Error> {
+        write_sinks(&mut [writer], |w| self.write_to(w))
+    }
+}
+
+// Runs `render` with a writer to `w` which applies the `newline`,
+// `color_sampling` and `max_output` options.
+pub(crate) fn guarded<F>(
+    common: &CommonOptions,
+    mut w: &mut dyn fmt::Write,
+    render: F,
+) -> Result<(), Error>
+where
+    F: FnOnce(&mut dyn fmt::Write) -> Result<(), Error>,
+{
+    let mut nl = NewlineWriter::new(&mut w, common.newline);
+    let mut w = GuardWriter::new(&mut nl, common.color_sampling, common.max_output);
+    let result = render(&mut w);
+    match common.max_output {
+        Some(n) if w.exceeded => Err(Error::OutputLimit(n)),
+        _ => result,
+    }
+}
+
+// Runs `render` with a writer which copies its output to each of `sinks`,
+// and flushes the sinks once rendering completes.
+pub(crate) fn write_sinks<F>(sinks: &mut [&mut dyn io::Write], render: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut dyn fmt::Write) -> Result<(), Error>,
+{
+    let mut tee = TeeWriter::new(sinks);
+    let result = render(&mut tee);
+    if let Some(e) = tee.error.take() {
+        return Err(e.into());
+    }
+    result?;
+    Ok(tee.flush()?)
+}
+
+impl Document {
+    /// Converts this document into an emitter for `dialect`, configured with
+    /// that dialect's defaults.
+    ///
+    /// `Dialect` only names the json family and YAML, which the highlighter
+    /// and the quoting rules also understand, so the other formats are not
+    /// reachable here.  Their emitters implement `Emit` too, and can be
+    /// boxed directly:
+    ///
+    /// ```
+    /// # use serde_annotate::{Document, Emit};
+    /// let doc = Document::parse("{a: 1}")?;
+    /// let emitter: Box<dyn Emit> = Box::new(doc.to_toml());
+    /// assert_eq!(emitter.to_text()?, "a = 1\n");
+    /// # Ok::<(), serde_annotate::Error>(())
+    /// ```
+    pub fn to_dialect(self, dialect: Dialect) -> Box<dyn Emit> {
+        match dialect {
+            Dialect::Json => Box::new(self.to_json()),
+            Dialect::Json5 => Box::new(self.to_json5()),
+            Dialect::Hjson => Box::new(self.to_hjson()),
+            Dialect::Yaml => Box::new(self.to_yaml()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_emit_dialects() -> Result<()> {
+        let doc = Document::parse("{\n  // The sizes.\n  sizes: [1, 2],\n  name: null\n}")?;
+        let mut emitters = [Dialect::Json, Dialect::Json5, Dialect::Hjson, Dialect::Yaml]
+            .into_iter()
+            .map(|d| doc.clone().to_dialect(d))
+            .collect::<Vec<_>>();
+        for e in emitters.iter_mut() {
+            let options = e.options();
+            options.indent = 4;
+            options.skip_null = true;
+        }
+        let text = emitters
+            .iter()
+            .map(|e| e.to_text())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(
+            text,
+            vec![
+                "{\n    \"sizes\": [\n        1,\n        2\n    ]\n}",
+                "{\n    // The sizes.\n    sizes: [\n        1,\n        2\n    ]\n}",
+                "{\n    // The sizes.\n    sizes: [\n        1,\n        2\n    ]\n}",
+                "---\n# The sizes.\nsizes:\n    - 1\n    - 2",
+            ]
+        );
+
+        let mut out = Vec::new();
+        emitters[1].options().compact = true;
+        emitters[1].to_writer(&mut out)?;
+        assert_eq!(String::from_utf8(out)?, "{sizes: [1, 2]}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_non_ascii_comments() -> Result<()> {
+        let doc = Document::parse("{\n  a: {\n    // Größe\n    b: 1\n  }\n}")?;
+        let yaml = doc.clone().to_yaml().ascii_comments(AsciiComments::Reject);
+        match yaml.to_text() {
+            Err(Error::NonAsciiComment(path, comment)) => {
+                assert_eq!((path.as_str(), comment.as_str()), ("/a/b", "Größe"));
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(doc
+            .clone()
+            .to_toml()
+            .ascii_comments(AsciiComments::Reject)
+            .to_text()
+            .is_err());
+        // Compact output has no comments to reject.
+        let yaml = yaml.compact(true);
+        assert_eq!(yaml.to_text()?, "---\n{a: {b: 1}}");
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+// Decoding of legacy (non-UTF-8) text encodings.
+use std::borrow::Cow;
+
+use crate::de::Deserializer;
+use crate::document::Document;
+use crate::error::Error;
+use serde::de::DeserializeOwned;
+
+/// The encoding of a byte buffer passed to `Document::from_bytes_lossy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8, with or without a byte order mark.  Invalid sequences are
+    /// replaced with U+FFFD.
+    Utf8,
+    /// ISO-8859-1, in which each byte is the code point of its character.
+    Latin1,
+    /// Windows-1252, which differs from ISO-8859-1 in placing punctuation
+    /// such as curly quotes and the euro sign at bytes 0x80 to 0x9F.  The
+    /// five bytes it leaves undefined decode to the control characters of
+    /// the same code points.
+    Windows1252,
+}
+
+// The characters of Windows-1252 bytes 0x80 to 0x9F.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+impl Encoding {
+    /// Guesses the encoding of `bytes`: UTF-8 if they are valid UTF-8 (or
+    /// start with a UTF-8 byte order mark), and Windows-1252 otherwise.
+    /// Windows-1252 is a superset of the printable characters of
+    /// ISO-8859-1, so it also decodes latin-1 text.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        if bytes.starts_with(BOM) || std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Windows1252
+        }
+    }
+
+    /// Decodes `bytes` into text.  Valid UTF-8 is borrowed rather than
+    /// copied.
+    pub fn decode(self, bytes: &[u8]) -> Cow<'_, str> {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes.strip_prefix(BOM).unwrap_or(bytes)),
+            Encoding::Latin1 => Cow::Owned(bytes.iter().map(|&b| char::from(b)).collect()),
+            Encoding::Windows1252 => Cow::Owned(
+                bytes
+                    .iter()
+                    .map(|&b| match b {
+                        0x80..=0x9F => WINDOWS_1252[usize::from(b - 0x80)],
+                        b => char::from(b),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Document {
+    /// Parses a byte buffer which may not be UTF-8 into a `Document` using
+    /// the maximally permissive parser.
+    ///
+    /// The buffer is decoded with `encoding`, or with the encoding guessed
+    /// by `Encoding::detect` if it is `None`.  Decoding never fails: bytes
+    /// which are not valid in the encoding are replaced.
+    pub fn from_bytes_lossy(bytes: &[u8], encoding: Option<Encoding>) -> Result<Document, Error> {
+        let encoding = encoding.unwrap_or_else(|| Encoding::detect(bytes));
+        Document::parse(&encoding.decode(bytes))
+    }
+}
+
+/// Parses and deserializes a byte buffer which may not be UTF-8 into a `T`.
+/// See `Document::from_bytes_lossy`.
+pub fn from_bytes_lossy<T>(bytes: &[u8], encoding: Option<Encoding>) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let doc = Document::from_bytes_lossy(bytes, encoding)?;
+    let mut ds = Deserializer::from_document(&doc)?;
+    T::deserialize(&mut ds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+    use serde_derive::Deserialize;
+
+    // A legacy Hjson-ish config saved in Windows-1252.
+    const CONFIG: &[u8] =
+        b"{\n  # Caf\xe9 settings\n  name: \x93Caf\xe9\x94\n  price: \"\x805\"\n}";
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        price: String,
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Encoding::detect(b"plain"), Encoding::Utf8);
+        assert_eq!(Encoding::detect("caf\u{e9}".as_bytes()), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b"\xEF\xBB\xBFbom"), Encoding::Utf8);
+        assert_eq!(Encoding::detect(CONFIG), Encoding::Windows1252);
+    }
+
+    #[test]
+    fn test_decode() {
+        let bytes = b"\x93caf\xe9\x94 \x81";
+        assert_eq!(
+            Encoding::Windows1252.decode(bytes),
+            "\u{201C}caf\u{e9}\u{201D} \u{81}"
+        );
+        assert_eq!(
+            Encoding::Latin1.decode(bytes),
+            "\u{93}caf\u{e9}\u{94} \u{81}"
+        );
+        assert_eq!(Encoding::Utf8.decode(bytes), "\u{FFFD}caf\u{FFFD} \u{FFFD}");
+        assert!(matches!(
+            Encoding::Utf8.decode(b"\xEF\xBB\xBFok"),
+            Cow::Borrowed("ok")
+        ));
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let doc = Document::from_bytes_lossy(CONFIG, None)?;
+        let text = doc.to_hjson().to_text()?;
+        assert_eq!(
+            text,
+            "{\n  # Caf\u{e9} settings\n  name: \u{201C}Caf\u{e9}\u{201D}\n  price: \"\u{20AC}5\"\n}"
+        );
+        // The emitted UTF-8 parses back to the same document.
+        let again = Document::parse(&text)?;
+        assert_eq!(again.to_hjson().to_text()?, text);
+
+        let config: Config = from_bytes_lossy(CONFIG, Some(Encoding::Windows1252))?;
+        assert_eq!(
+            config,
+            Config {
+                name: "\u{201C}Caf\u{e9}\u{201D}".into(),
+                price: "\u{20AC}5".into(),
+            }
+        );
+        // Latin-1 keeps bytes 0x80 to 0x9F as control characters.
+        let config: Config = from_bytes_lossy(CONFIG, Some(Encoding::Latin1))?;
+        assert_eq!(config.price, "\u{80}5");
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+// Typed views of mapping entries and sequence elements.
+use crate::document::{CommentFormat, Document, StrFormat};
+use crate::error::Error;
+
+/// A mapping entry split into its key, its value and the comments around
+/// them.
+///
+/// An entry is stored as a `Document::Fragment` holding the key and the
+/// value along with any comments.  `MapEntry` names those parts so that they
+/// need not be found again from the positions of the nodes.
+#[derive(Clone, Debug)]
+pub struct MapEntry<'a> {
+    /// The comments ahead of the value, which are emitted above the key.
+    pub comments: Vec<(&'a str, CommentFormat)>,
+    pub key: &'a Document,
+    pub value: &'a Document,
+    /// The comments following the value, which are emitted after it where
+    /// the format permits.
+    pub trailing: Vec<(&'a str, CommentFormat)>,
+}
+
+/// A sequence element split into its value and the comments around it.
+///
+/// An element with comments is stored as a `Document::Fragment` holding the
+/// value and the comments, and one without as the bare value.
+#[derive(Clone, Debug)]
+pub struct SeqItem<'a> {
+    /// The comments ahead of the value.
+    pub comments: Vec<(&'a str, CommentFormat)>,
+    pub value: &'a Document,
+    /// The comments following the value.
+    pub trailing: Vec<(&'a str, CommentFormat)>,
+}
+
+// Returns the text and format of the comments among `nodes`.
+fn comments(nodes: &[Document]) -> Vec<(&str, CommentFormat)> {
+    nodes
+        .iter()
+        .filter_map(Document::comment)
+        .map(|(c, f)| (c, *f))
+        .collect()
+}
+
+// Returns the nodes of an entry or element: the nodes of a fragment, or the
+// node itself.
+fn nodes(node: &Document) -> &[Document] {
+    match node {
+        Document::Fragment(ds) => ds,
+        n => std::slice::from_ref(n),
+    }
+}
+
+// Returns the comments of an entry or element which holds no value, or of
+// one which `MapEntry::new` or `SeqItem::new` rejects.
+pub(crate) fn comments_of(node: &Document) -> Vec<(&str, CommentFormat)> {
+    comments(nodes(node))
+}
+
+// Returns the trimmed lines of the comments of an entry or element.
+pub(crate) fn comment_lines(node: &Document) -> impl Iterator<Item = &str> {
+    nodes(node)
+        .iter()
+        .filter_map(Document::comment)
+        .flat_map(|(c, _)| c.lines())
+        .map(str::trim)
+}
+
+// Returns the value of an entry or element, or `None` for a comment or a
+// fragment holding neither a key and a value nor a single value.
+pub(crate) fn value_of(node: &Document) -> Option<&Document> {
+    match MapEntry::new(node) {
+        Ok(entry) => Some(entry.value),
+        Err(_) => SeqItem::new(node).ok().map(|item| item.value),
+    }
+}
+
+// Returns the text of a mapping key which is a string, a boolean or a
+// number.
+pub(crate) fn key_text(key: &Document) -> Option<String> {
+    match key.as_value().ok()? {
+        Document::String(s, _) => Some(s.clone()),
+        Document::StaticStr(s, _) => Some(s.to_string()),
+        Document::Boolean(b) => Some(b.to_string()),
+        Document::Int(i) => Some(i.format(None)),
+        Document::Float(f) => Some(f.to_string()),
+        _ => None,
+    }
+}
+
+// Returns a mapping entry with the string key `key`.
+pub(crate) fn kv(key: &str, value: Document) -> Document {
+    Document::Fragment(vec![
+        Document::String(key.to_string(), StrFormat::Standard),
+        value,
+    ])
+}
+
+impl<'a> MapEntry<'a> {
+    /// Splits a mapping entry.  Fails with `Error::StructureError` if the
+    /// node does not hold exactly a key and a value, as for a fragment
+    /// holding only comments.
+    pub fn new(node: &'a Document) -> Result<Self, Error> {
+        let (key, value) = node.as_kv()?;
+        let nodes = node.fragments()?;
+        // Unwrap is ok: the node is a kvpair, so it holds a value.
+        let end = nodes.iter().rposition(Document::has_value).unwrap();
+        Ok(MapEntry {
+            comments: comments(&nodes[..end]),
+            key,
+            value,
+            trailing: comments(&nodes[end + 1..]),
+        })
+    }
+}
+
+impl<'a> SeqItem<'a> {
+    /// Splits a sequence element.  Fails with `Error::StructureError` if the
+    /// node does not hold exactly one value, as for a fragment holding only
+    /// comments.
+    pub fn new(node: &'a Document) -> Result<Self, Error> {
+        let nodes = nodes(node);
+        let mut values = nodes.iter().enumerate().filter(|(_, n)| n.has_value());
+        match (values.next(), values.next()) {
+            (Some((i, value)), None) => Ok(SeqItem {
+                comments: comments(&nodes[..i]),
+                value,
+                trailing: comments(&nodes[i + 1..]),
+            }),
+            (None, _) => Err(Error::StructureError("one value", "zero")),
+            (Some(_), Some(_)) => Err(Error::StructureError("one value", "many")),
+        }
+    }
+}
+
+impl Document {
+    /// Returns the entries of a mapping.  Comments which are not attached to
+    /// an entry, such as those at the end of a mapping, are skipped.
+    pub fn entries(&self) -> Result<impl Iterator<Item = MapEntry<'_>>, Error> {
+        match self.as_value()? {
+            Document::Mapping(m) => Ok(m.iter().filter_map(|e| MapEntry::new(e).ok())),
+            v => Err(Error::StructureError("Mapping", v.variant())),
+        }
+    }
+
+    /// Returns the elements of a sequence.  Comments which are not attached
+    /// to an element are skipped.
+    pub fn items(&self) -> Result<impl Iterator<Item = SeqItem<'_>>, Error> {
+        match self.as_value()? {
+            Document::Sequence(s) => Ok(s.iter().filter_map(|e| SeqItem::new(e).ok())),
+            v => Err(Error::StructureError("Sequence", v.variant())),
+        }
+    }
+
+    /// Returns the value of the entry with the string key `key` in a mapping.
+    pub fn get(&self, key: &str) -> Option<&Document> {
+        self.entries()
+            .ok()?
+            .find(|e| e.key.as_str().map_or(false, |k| k == key))
+            .map(|e| e.value)
+    }
+
+    /// Returns the mutable value of the entry with the string key `key` in a
+    /// mapping.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Document> {
+        let entry = self
+            .mapping_mut()
+            .ok()?
+            .iter_mut()
+            .find(|e| is_entry(e, key))?;
+        // Unwrap is ok: the entry was matched by its key.
+        Some(entry.as_kv_mut().unwrap().1)
+    }
+
+    /// Sets the value of the entry with the string key `key` in a mapping,
+    /// keeping the entry's comments, and returns the previous value.  If
+    /// there is no such entry, a new one is appended with a key which is
+    /// left unquoted where the backend allows.
+    pub fn insert(&mut self, key: &str, value: Document) -> Result<Option<Document>, Error> {
+        if let Some(v) = self.get_mut(key) {
+            return Ok(Some(std::mem::replace(v, value)));
+        }
+        self.mapping_mut()?.push(Document::Fragment(vec![
+            Document::String(key.to_string(), StrFormat::Unquoted),
+            value,
+        ]));
+        Ok(None)
+    }
+
+    /// Removes the entry with the string key `key` from a mapping, along
+    /// with its comments, and returns its value.
+    pub fn remove(&mut self, key: &str) -> Result<Option<Document>, Error> {
+        let mapping = self.mapping_mut()?;
+        let index = match mapping.iter().position(|e| is_entry(e, key)) {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+        let mut entry = mapping.remove(index);
+        // Unwrap is ok: the entry was matched by its key.
+        let value = entry.as_kv_mut().unwrap().1;
+        Ok(Some(std::mem::replace(value, Document::Null)))
+    }
+
+    // Returns the entries of a mapping for modification.
+    fn mapping_mut(&mut self) -> Result<&mut Vec<Document>, Error> {
+        match self.as_value_mut()? {
+            Document::Mapping(m) => Ok(m),
+            v => Err(Error::StructureError("Mapping", v.variant())),
+        }
+    }
+}
+
+// Returns whether `entry` is a mapping entry with the string key `key`.
+pub(crate) fn is_entry(entry: &Document, key: &str) -> bool {
+    match entry.as_kv() {
+        Ok((k, _)) => matches!(k.as_str(), Ok(k) if k == key),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    #[test]
+    fn test_views() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // The name.
+                name: "demo", // Trailing.
+                ports: [
+                    // HTTP.
+                    80,
+                    443,
+                ],
+                // Dangling.
+            }"#,
+        )?;
+        let entries = doc.entries()?.collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].comments[0].0, "The name.");
+        assert_eq!(entries[0].key.as_str()?, "name");
+        assert_eq!(entries[0].value.as_str()?, "demo");
+        assert_eq!(entries[0].trailing[0].0, "Trailing.");
+        assert!(entries[1].comments.is_empty());
+
+        let items = entries[1].value.items()?.collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0].comments,
+            vec![("HTTP.", CommentFormat::SlashSlash)]
+        );
+        assert!(matches!(items[1].value, Document::Int(_)));
+        assert!(doc.items().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mutation() -> Result<()> {
+        let mut doc = Document::parse("{\n  // The name.\n  name: 'demo',\n  old: 1\n}")?;
+        assert_eq!(
+            doc.insert("name", Document::from("renamed"))?
+                .unwrap()
+                .as_str()?,
+            "demo"
+        );
+        assert!(doc.insert("added", Document::Boolean(true))?.is_none());
+        assert!(matches!(doc.remove("old")?, Some(Document::Int(_))));
+        assert!(doc.remove("missing")?.is_none());
+        *doc.get_mut("added").unwrap() = Document::Boolean(false);
+        assert!(matches!(doc.get("added"), Some(Document::Boolean(false))));
+        assert_eq!(
+            doc.to_json5().to_text()?,
+            "{\n  // The name.\n  name: \"renamed\",\n  added: false\n}"
+        );
+        assert!(Document::Null.insert("a", Document::Null).is_err());
+        Ok(())
+    }
+}
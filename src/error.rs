@@ -1,4 +1,6 @@
-use crate::relax::ParseError;
+use crate::node_id::NodeId;
+use crate::relax::{ParseError, Rule};
+use pest::error::{ErrorVariant, LineColLocation};
 use serde::{de, ser};
 use std::char::CharTryFromError;
 use std::fmt::Display;
@@ -18,6 +20,8 @@ pub enum Error {
     EscapeError(char),
     #[error("formatter error: {0:?}")]
     FmtError(std::fmt::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
     #[error("Hexdump error: {0}")]
     HexdumpError(String),
     #[error("Type {0:?} is not valid as a mapping key")]
@@ -32,8 +36,36 @@ pub enum Error {
     CharTryFromError(#[from] CharTryFromError),
     #[error("document structure error: expected {0} but got {1}")]
     StructureError(&'static str, &'static str),
+    #[error("path error: {0}")]
+    PathError(String),
+    #[error("comment at {0:?} cannot be emitted: {1:?}")]
+    CommentDropped(String, String),
+    #[error("comment at {0:?} contains non-ASCII characters: {1:?}")]
+    NonAsciiComment(String, String),
+    #[error("configuration error: {0}")]
+    ConfigError(String),
+    #[error("validation error: {0}")]
+    ValidationError(String),
+    #[error("output exceeds {0} bytes")]
+    OutputLimit(usize),
+    #[error("nesting exceeds depth limit of {0}")]
+    DepthLimit(usize),
+    #[error("node not found: {0:?}")]
+    NodeNotFound(NodeId),
+    #[error("stream error: {0}")]
+    StreamError(&'static str),
+    #[error("cannot canonicalize: {0}")]
+    CanonicalError(String),
+    #[error("an absent value cannot be emitted")]
+    AbsentValue,
     #[error("syntax error: {0} at {1}:{2}\n| {3}\n| {4:>2$}")]
     SyntaxError(String, usize, usize, String, &'static str),
+    #[error("{0} cannot be emitted as {1}")]
+    Unrepresentable(&'static str, &'static str),
+    #[error("integer {0} does not fit in {1} bits")]
+    IntegerWidth(String, u32),
+    #[error("name {0:?} is defined more than once")]
+    DuplicateName(String),
 }
 
 impl ser::Error for Error {
@@ -53,3 +85,43 @@ impl From<std::fmt::Error> for Error {
         Error::FmtError(e)
     }
 }
+
+impl Error {
+    /// Returns the one-based line and column at which a parse error
+    /// occurred, or `None` if the error has no location.
+    pub fn line_col(&self) -> Option<(usize, usize)> {
+        match self {
+            Error::ParseError(e) => match e.line_col {
+                LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => Some(pos),
+            },
+            Error::SyntaxError(_, line, column, _, _) => Some((*line, *column)),
+            _ => None,
+        }
+    }
+
+    /// Returns a description of each token the parser expected at the
+    /// location of a parse error (e.g. `"object"` or `"comma"`).  The list
+    /// is empty for other errors.
+    pub fn expected(&self) -> Vec<String> {
+        match self {
+            Error::ParseError(e) => match &e.variant {
+                ErrorVariant::ParsingError { positives, .. } => {
+                    positives.iter().map(rule_name).collect()
+                }
+                ErrorVariant::CustomError { .. } => Vec::new(),
+            },
+            Error::SyntaxError(msg, _, _, _, _) => msg
+                .strip_prefix("expected ")
+                .map(|token| vec![token.to_string()])
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn rule_name(rule: &Rule) -> String {
+    match rule {
+        Rule::EOI => "end of input".into(),
+        r => format!("{:?}", r).to_lowercase().replace('_', " "),
+    }
+}
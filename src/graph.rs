@@ -0,0 +1,199 @@
+// Graphviz and Mermaid rendering of document structure.
+use crate::document::Document;
+use crate::entry::{comments_of, value_of};
+use crate::render::display_text;
+
+// Maximum number of characters of a scalar value shown in a node label.
+const PREVIEW_LEN: usize = 32;
+
+struct GraphNode {
+    parent: Option<usize>,
+    label: String,
+    tooltip: String,
+}
+
+impl Document {
+    /// Renders the structure of the document as a Graphviz `dot` digraph.
+    ///
+    /// Each mapping entry and sequence element becomes a node labeled with
+    /// its key (or index) and, for scalars, a preview of the value.
+    /// Comments attached to a node become its tooltip.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph document {\n  node [shape=box];\n");
+        for (i, node) in graph_nodes(self).iter().enumerate() {
+            out.push_str(&format!("  n{} [label=\"{}\"", i, dot_escape(&node.label)));
+            if !node.tooltip.is_empty() {
+                out.push_str(&format!(", tooltip=\"{}\"", dot_escape(&node.tooltip)));
+            }
+            out.push_str("];\n");
+            if let Some(p) = node.parent {
+                out.push_str(&format!("  n{} -> n{};\n", p, i));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Renders the structure of the document as a Mermaid flowchart.
+    ///
+    /// Nodes are labeled as in `to_dot`.  Comments are attached as
+    /// tooltips using Mermaid `click` directives.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart TD\n");
+        let mut tooltips = String::new();
+        for (i, node) in graph_nodes(self).iter().enumerate() {
+            out.push_str(&format!("  n{}[\"{}\"]\n", i, mermaid_escape(&node.label)));
+            if let Some(p) = node.parent {
+                out.push_str(&format!("  n{} --> n{}\n", p, i));
+            }
+            if !node.tooltip.is_empty() {
+                tooltips.push_str(&format!(
+                    "  click n{} \"#\" \"{}\"\n",
+                    i,
+                    mermaid_escape(&node.tooltip)
+                ));
+            }
+        }
+        out.push_str(&tooltips);
+        out
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', "<br>")
+}
+
+// Returns a short, single-line rendering of a scalar value.
+fn preview(value: &Document) -> String {
+    let text = display_text(value);
+    let text = text.replace('\n', " ");
+    if text.chars().count() > PREVIEW_LEN {
+        let mut short = text.chars().take(PREVIEW_LEN - 1).collect::<String>();
+        short.push('…');
+        short
+    } else {
+        text
+    }
+}
+
+fn graph_nodes(doc: &Document) -> Vec<GraphNode> {
+    let mut nodes = Vec::new();
+    visit(&mut nodes, None, None, doc);
+    nodes
+}
+
+// Visits a node which may be a fragment holding comments alongside a value
+// (or a key and a value), adding it and its children to `nodes`.
+fn visit(nodes: &mut Vec<GraphNode>, parent: Option<usize>, name: Option<String>, node: &Document) {
+    let tooltip = comments_of(node)
+        .into_iter()
+        .map(|(c, _)| c)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let value = match value_of(node) {
+        Some(v) => v,
+        None => return,
+    };
+    let value = match value {
+        Document::Compact(v)
+        | Document::ColorClass(_, v)
+        | Document::Identified(_, v)
+        | Document::Typed(_, v) => v.as_ref(),
+        v => v,
+    };
+    let name = name.unwrap_or_else(|| "root".to_string());
+    let label = match value {
+        Document::Mapping(_) => format!("{} {{}}", name),
+        Document::Sequence(_) => format!("{} []", name),
+        v => format!("{}: {}", name, preview(v)),
+    };
+    let id = nodes.len();
+    nodes.push(GraphNode {
+        parent,
+        label,
+        tooltip,
+    });
+    match value {
+        Document::Mapping(m) => {
+            for entry in m {
+                if let Ok((k, _)) = entry.as_kv() {
+                    let key = match k.as_str() {
+                        Ok(k) => k.to_string(),
+                        Err(_) => preview(k),
+                    };
+                    visit(nodes, Some(id), Some(key), entry);
+                }
+            }
+        }
+        Document::Sequence(s) => {
+            for (i, elem) in s.iter().filter(|e| e.has_value()).enumerate() {
+                visit(nodes, Some(id), Some(i.to_string()), elem);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    const SAMPLE: &str = r#"{
+        // The name of the part.
+        name: "winbond",
+        sizes: [0x1000]
+    }"#;
+
+    #[test]
+    fn test_to_dot() -> Result<()> {
+        let doc = Document::parse(SAMPLE)?;
+        assert_eq!(
+            doc.to_dot(),
+            r#"digraph document {
+  node [shape=box];
+  n0 [label="root {}"];
+  n1 [label="name: \"winbond\"", tooltip="The name of the part."];
+  n0 -> n1;
+  n2 [label="sizes []"];
+  n0 -> n2;
+  n3 [label="0: 0x1000"];
+  n2 -> n3;
+}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_mermaid() -> Result<()> {
+        let doc = Document::parse(SAMPLE)?;
+        assert_eq!(
+            doc.to_mermaid(),
+            r##"flowchart TD
+  n0["root {}"]
+  n1["name: #quot;winbond#quot;"]
+  n0 --> n1
+  n2["sizes []"]
+  n0 --> n2
+  n3["0: 0x1000"]
+  n2 --> n3
+  click n1 "#" "The name of the part."
+"##
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_preview_truncation() {
+        let doc = Document::from("a very long string value which will not fit in a label");
+        assert_eq!(preview(&doc).chars().count(), PREVIEW_LEN);
+        assert!(preview(&doc).ends_with('…'));
+    }
+}
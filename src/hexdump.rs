@@ -15,6 +15,52 @@ fn hexstr(data: &[u8]) -> String {
     s
 }
 
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Marks a string as base64, which cannot otherwise be told apart from a hex
+// string (e.g. "1234").
+const BASE64_PREFIX: &str = "base64:";
+
+// Emit bytes as a padded base64 string (RFC 4648).
+fn base64(data: &[u8]) -> String {
+    let mut s = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let v = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |v, (i, &b)| v | ((b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64[((v >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+// Parse a padded or unpadded base64 string, ignoring whitespace.
+fn from_base64(text: &str) -> Result<Vec<u8>, Error> {
+    let text = text.trim_end_matches(|c: char| c == '=' || c.is_whitespace());
+    let mut res = Vec::with_capacity(text.len() * 3 / 4);
+    let mut v = 0u32;
+    let mut bits = 0;
+    for ch in text.bytes().filter(|b| !b.is_ascii_whitespace()) {
+        let digit = BASE64
+            .iter()
+            .position(|&b| b == ch)
+            .ok_or_else(|| Error::HexdumpError("invalid base64 character".into()))?;
+        v = (v << 6) | digit as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((v >> bits) as u8);
+        }
+    }
+    Ok(res)
+}
+
 // Emit bytes as a hexdump in the style of `hexdump -vC`.
 fn hexdump(data: &[u8]) -> String {
     // Hexdump always emits a full line of output (78 chars plus newline)
@@ -89,6 +135,7 @@ fn xxd(data: &[u8], grouping: usize) -> String {
 pub fn to_string(data: &[u8], format: BytesFormat) -> Option<String> {
     match format {
         BytesFormat::HexStr => Some(hexstr(data)),
+        BytesFormat::Base64 => Some(format!("{}{}", BASE64_PREFIX, base64(data))),
         BytesFormat::Hexdump => Some(hexdump(data)),
         // By default, `xxd` emits outputs with grouping 2.
         BytesFormat::Xxd => Some(xxd(data, 2)),
@@ -122,7 +169,11 @@ fn from_hex(text: &str, vec: &mut Vec<u8>) -> Result<(), Error> {
 }
 
 /// Parses a hexdump string in a variety of forms, returning the resulting bytes.
+/// Strings starting with `base64:` are parsed as base64.
 pub fn from_str(text: &str) -> Result<Vec<u8>, Error> {
+    if let Some(text) = text.strip_prefix(BASE64_PREFIX) {
+        return from_base64(text);
+    }
     // Detects `xxd -g<n>` formats.
     let xxd = RegexBuilder::new(r"^[[:xdigit:]]{8}:\s+((?:[[:xdigit:]]{2,}\s)+)\s+.{1,16}$")
         .multi_line(true)
@@ -135,7 +186,7 @@ pub fn from_str(text: &str) -> Result<Vec<u8>, Error> {
             .build()
             .unwrap();
     // Detects a simple hex string with optional whitespace.
-    let hexstr = RegexBuilder::new(r"(?:0[xX])?((?:[[:xdigit:]]{2}\s*)+)")
+    let hexstr = RegexBuilder::new(r"^\s*(?:0[xX])?((?:[[:xdigit:]]{2}\s*)+)$")
         .multi_line(false)
         .build()
         .unwrap();
@@ -169,6 +220,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_base64() -> Result<()> {
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (data, text) in cases {
+            assert_eq!(base64(data.as_bytes()), text);
+            assert_eq!(from_base64(text)?, data.as_bytes());
+        }
+        assert_eq!(from_str("base64:Zm9v\nYmE")?, b"fooba");
+        assert_eq!(from_str("0xcafe")?, [0xca, 0xfe]);
+        assert!(from_str("base64:Zm9v!").is_err());
+        assert!(from_str("Zm9v").is_err());
+
+        // Base64 text which is also a valid hex string reads back as base64.
+        let data = [0xd7, 0x6d, 0xf8];
+        let text = to_string(&data, BytesFormat::Base64).unwrap();
+        assert_eq!(text, "base64:1234");
+        assert_eq!(from_str(&text)?, data);
+        Ok(())
+    }
+
     const TEST_STR: &str = "The quick brown fox jumped over the lazy dog!";
 
     // Output from `hexdump -vC ...`
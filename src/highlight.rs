@@ -0,0 +1,530 @@
+// Dialect-aware syntax highlighting of text.
+use crate::color::ColorProfile;
+use crate::layout::TokenClass;
+use crate::relax::{Relax, Rule};
+use crate::unambiguous::Dialect;
+use pest::Parser;
+use std::fmt::Write;
+
+/// Colorizes `input`, written in `dialect`, with the styles of `profile`.
+///
+/// Unlike parsing the input into a `Document` and emitting it, highlighting
+/// passes the text through unchanged apart from the color escapes, so the
+/// formatting of a user's file is preserved.  Highlighting is lexical: the
+/// strings, numbers and comments of the json family of dialects are
+/// recognized with the rules of the parser's grammar, and malformed input is
+/// highlighted as far as its tokens can be recognized.
+pub fn highlight(input: &str, dialect: Dialect, profile: &ColorProfile) -> String {
+    let mut out = String::with_capacity(input.len() * 2);
+    for (class, text) in tokenize(input, dialect) {
+        match class {
+            TokenClass::Plain => out.push_str(text),
+            c => {
+                // Unwrap is ok: writing to a String cannot fail.
+                write!(out, "{}", profile.style(&c).paint(text)).unwrap();
+            }
+        }
+    }
+    out
+}
+
+// Splits `input` into runs of text of a single class.
+fn tokenize(input: &str, dialect: Dialect) -> Vec<(TokenClass, &str)> {
+    let mut lexer = Lexer {
+        input,
+        pos: 0,
+        dialect,
+        after_colon: false,
+        tokens: Vec::new(),
+    };
+    match dialect {
+        Dialect::Yaml => lexer.yaml(),
+        _ => lexer.json(),
+    }
+    lexer
+        .tokens
+        .into_iter()
+        .map(|(class, start, end)| (class, &input[start..end]))
+        .collect()
+}
+
+// Returns the length of the `rule` token at the start of `text`, as
+// recognized by the parser's grammar.
+fn lexeme(rule: Rule, text: &str) -> Option<usize> {
+    Relax::parse(rule, text)
+        .ok()?
+        .next()
+        .map(|p| p.as_span().end())
+}
+
+struct Lexer<'a> {
+    input: &'a str,
+    pos: usize,
+    dialect: Dialect,
+    // Whether the next token is a mapping value rather than a key.
+    after_colon: bool,
+    tokens: Vec<(TokenClass, usize, usize)>,
+}
+
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    // Emits the next `len` bytes as a token of class `class`.
+    fn push(&mut self, class: TokenClass, len: usize) {
+        let end = self.pos + len;
+        match self.tokens.last_mut() {
+            Some((c, _, e)) if *c == class && *e == self.pos => *e = end,
+            _ => self.tokens.push((class, self.pos, end)),
+        }
+        self.pos = end;
+    }
+
+    // Returns the length of the line at the current position, excluding the
+    // line terminator.
+    fn line_len(&self) -> usize {
+        let rest = self.rest();
+        rest.find(['\n', '\r']).unwrap_or(rest.len())
+    }
+
+    // Returns whether the text at `pos` (skipping whitespace) is a colon.
+    fn colon_at(&self, pos: usize, newlines: bool) -> bool {
+        self.input[pos..]
+            .trim_start_matches(|c: char| c.is_whitespace() && (newlines || c != '\n'))
+            .starts_with(':')
+    }
+
+    // Returns the length of a comment at the current position.
+    fn comment_len(&self) -> Option<usize> {
+        let rest = self.rest();
+        let hash = matches!(self.dialect, Dialect::Hjson | Dialect::Yaml);
+        let slash = matches!(self.dialect, Dialect::Json5 | Dialect::Hjson);
+        if (hash && rest.starts_with('#')) || (slash && rest.starts_with("//")) {
+            Some(self.line_len())
+        } else if slash && rest.starts_with("/*") {
+            Some(lexeme(Rule::block_comment, rest).unwrap_or(rest.len()))
+        } else {
+            None
+        }
+    }
+
+    // Returns the length of a quoted string at the current position.  A
+    // malformed string ends at the end of the line.
+    fn string_len(&self, quote: char) -> usize {
+        let rest = self.rest();
+        if self.dialect != Dialect::Yaml {
+            // The string rule also matches Hjson quoteless strings, which may
+            // start with an unmatched quote.
+            return lexeme(Rule::string, rest)
+                .filter(|&n| rest[..n].ends_with(quote))
+                .unwrap_or_else(|| self.line_len());
+        }
+        let mut chars = rest.char_indices().skip(1);
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '\\' if quote == '"' => {
+                    let _ = chars.next();
+                }
+                '\n' => return i,
+                c if c == quote => {
+                    // YAML escapes a single quote by doubling it.
+                    if rest[i + 1..].starts_with(quote) {
+                        let _ = chars.next();
+                    } else {
+                        return i + 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        rest.len()
+    }
+
+    // Emits a quoted string of length `len`, with its escape sequences in the
+    // escape class.
+    fn string(&mut self, len: usize) {
+        let end = self.pos + len;
+        let escapes = !self.rest().starts_with("'''")
+            && (self.dialect != Dialect::Yaml || self.rest().starts_with('"'));
+        while self.pos < end {
+            let rest = &self.input[self.pos..end];
+            match rest.find('\\').filter(|_| escapes) {
+                Some(0) => {
+                    let n = match rest[1..].chars().next() {
+                        Some(c @ ('u' | 'x')) => {
+                            // A malformed escape ends at its last hex digit.
+                            let digits = if c == 'u' { 4 } else { 2 };
+                            let hex = rest[2..].chars().take(digits);
+                            2 + hex.take_while(|c| c.is_ascii_hexdigit()).count()
+                        }
+                        Some('\r') if rest[2..].starts_with('\n') => 3,
+                        Some(ch) => 1 + ch.len_utf8(),
+                        None => 1,
+                    };
+                    self.push(TokenClass::Escape, n);
+                }
+                Some(n) => self.push(TokenClass::String, n),
+                None => self.push(TokenClass::String, rest.len()),
+            }
+        }
+    }
+
+    fn json(&mut self) {
+        let hjson = self.dialect == Dialect::Hjson;
+        while let Some(ch) = self.rest().chars().next() {
+            if ch.is_whitespace() {
+                let len = self.rest().find(|c: char| !c.is_whitespace());
+                self.push(TokenClass::Plain, len.unwrap_or(self.rest().len()));
+            } else if let Some(len) = self.comment_len() {
+                self.push(TokenClass::Comment, len);
+            } else if "{}[]".contains(ch) {
+                self.push(TokenClass::Aggregate, 1);
+                self.after_colon = false;
+            } else if ch == ':' || ch == ',' {
+                self.push(TokenClass::Punctuation, 1);
+                self.after_colon = ch == ':';
+            } else if ch == '"' || (ch == '\'' && self.dialect != Dialect::Json) {
+                let len = self.string_len(ch);
+                if !self.after_colon && self.colon_at(self.pos + len, true) {
+                    self.push(TokenClass::Key, len);
+                } else {
+                    self.string(len);
+                }
+                self.after_colon = false;
+            } else {
+                let rest = self.rest();
+                let len = rest
+                    .find(|c: char| c.is_whitespace() || ",:[]{}".contains(c))
+                    .unwrap_or(rest.len())
+                    .max(ch.len_utf8());
+                let word = &rest[..len];
+                let class = if !self.after_colon && self.colon_at(self.pos + len, true) {
+                    TokenClass::BareKey
+                } else {
+                    scalar_class(word)
+                };
+                // A Hjson value not ending at a delimiter is a quoteless
+                // string, which extends to the end of the line.
+                let line = rest[..self.line_len()].trim_end();
+                let after = line[len.min(line.len())..].trim_start();
+                let ends = after.is_empty()
+                    || after.starts_with([',', ']', '}', '#'])
+                    || after.starts_with("//")
+                    || after.starts_with("/*");
+                if hjson && class != TokenClass::BareKey && !(ends && class != TokenClass::String) {
+                    self.push(TokenClass::String, line.len());
+                } else {
+                    self.push(class, len);
+                }
+                self.after_colon = false;
+            }
+        }
+    }
+
+    fn yaml(&mut self) {
+        // The indentation of the line introducing a block scalar.
+        let mut block = None;
+        let mut flow = 0usize;
+        while self.pos < self.input.len() {
+            let rest = self.rest();
+            let indent = rest.len() - rest.trim_start_matches(' ').len();
+            let blank = rest[..self.line_len()].trim().is_empty();
+            match block {
+                Some(b) if blank || indent > b => {
+                    self.push(TokenClass::Plain, indent);
+                    let len = self.line_len();
+                    self.push(TokenClass::String, len);
+                }
+                _ => {
+                    block = None;
+                    self.push(TokenClass::Plain, indent);
+                    if self.yaml_line(&mut flow) {
+                        block = Some(indent);
+                    }
+                }
+            }
+            // The line terminator.
+            let len = self.rest().find('\n').map_or(self.rest().len(), |n| n + 1);
+            self.push(TokenClass::Plain, len);
+        }
+    }
+
+    // Emits the tokens of a line of YAML, returning whether the line
+    // introduces a block scalar.
+    fn yaml_line(&mut self, flow: &mut usize) -> bool {
+        let end = self.pos + self.line_len();
+        let mut start = true;
+        let mut block = false;
+        while self.pos < end {
+            let rest = &self.input[self.pos..end];
+            // Unwrap is ok: the rest of the line is not empty.
+            let ch = rest.chars().next().unwrap();
+            let next = rest[1..].chars().next();
+            if ch == ' ' || ch == '\t' {
+                let len = rest.find(|c| c != ' ' && c != '\t').unwrap_or(rest.len());
+                self.push(TokenClass::Plain, len);
+                continue;
+            }
+            if ch == '#' {
+                self.push(TokenClass::Comment, rest.len());
+            } else if start
+                && self.pos == self.input[..self.pos].rfind('\n').map_or(0, |n| n + 1)
+                && (rest.starts_with("---") || rest.starts_with("..."))
+            {
+                self.push(TokenClass::Punctuation, 3);
+            } else if ch == '-' && next.map_or(true, |c| c == ' ') {
+                self.push(TokenClass::Punctuation, 1);
+            } else if "[]{}".contains(ch) {
+                self.push(TokenClass::Aggregate, 1);
+                if ch == '[' || ch == '{' {
+                    *flow += 1;
+                } else {
+                    *flow = flow.saturating_sub(1);
+                }
+            } else if ch == ',' && *flow > 0 {
+                self.push(TokenClass::Punctuation, 1);
+            } else if ch == ':' && next.map_or(true, |c| c == ' ' || *flow > 0) {
+                self.push(TokenClass::Punctuation, 1);
+                self.after_colon = true;
+                start = false;
+                continue;
+            } else if (ch == '|' || ch == '>')
+                && rest[1..].split('#').next().map_or(false, |m| {
+                    m.trim().chars().all(|c| "+-0123456789".contains(c))
+                })
+            {
+                let len = rest.find([' ', '#']).unwrap_or(rest.len());
+                self.push(TokenClass::Punctuation, len);
+                block = true;
+            } else if ch == '"' || ch == '\'' {
+                let len = self.string_len(ch).min(rest.len());
+                if self.colon_at(self.pos + len, false) {
+                    self.push(TokenClass::Key, len);
+                } else {
+                    self.string(len);
+                }
+            } else if ch == '&' || ch == '*' || ch == '!' {
+                let len = rest.find([' ', ',', ']', '}']).unwrap_or(rest.len());
+                self.push(TokenClass::Plain, len);
+            } else {
+                // A plain scalar ends at `: `, ` #` or, in flow context, a
+                // flow indicator.
+                let mut len = rest.len();
+                for (i, c) in rest.char_indices() {
+                    let next = rest[i + c.len_utf8()..].chars().next();
+                    if (c == ':'
+                        && next.map_or(true, |n| n == ' ' || (*flow > 0 && ",[]{}".contains(n))))
+                        || (c == ' ' && next == Some('#'))
+                        || (*flow > 0 && ",[]{}".contains(c))
+                    {
+                        len = i;
+                        break;
+                    }
+                }
+                let scalar = rest[..len].trim_end();
+                let class = if rest[len..].starts_with(':') {
+                    TokenClass::BareKey
+                } else {
+                    yaml_scalar_class(scalar)
+                };
+                self.push(class, scalar.len());
+            }
+            start = false;
+            self.after_colon = false;
+        }
+        block
+    }
+}
+
+// Returns the class of a bare word in the json family of dialects.
+fn scalar_class(word: &str) -> TokenClass {
+    match word {
+        "null" => TokenClass::Null,
+        "true" | "false" => TokenClass::Boolean,
+        _ => number_class(word).unwrap_or(TokenClass::String),
+    }
+}
+
+// Returns the class of a plain YAML scalar.
+fn yaml_scalar_class(word: &str) -> TokenClass {
+    match word {
+        "~" | "null" | "Null" | "NULL" => TokenClass::Null,
+        "true" | "True" | "TRUE" | "false" | "False" | "FALSE" => TokenClass::Boolean,
+        ".nan" | ".NaN" | ".NAN" | ".inf" | ".Inf" | ".INF" | "-.inf" | "-.Inf" | "-.INF" => {
+            TokenClass::Float
+        }
+        _ => number_class(word).unwrap_or(TokenClass::String),
+    }
+}
+
+// Returns the class of a numeric literal, or `None` if `word` is not a number.
+fn number_class(word: &str) -> Option<TokenClass> {
+    if lexeme(Rule::number, word) != Some(word.len()) {
+        return None;
+    }
+    let lower = word.to_ascii_lowercase();
+    let float = if lower.contains("0x") {
+        lower.contains('p')
+    } else {
+        lower.contains(['.', 'e']) || lower.ends_with("infinity") || lower.ends_with("nan")
+    };
+    Some(if float {
+        TokenClass::Float
+    } else {
+        TokenClass::Integer
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    fn classes(input: &str, dialect: Dialect) -> Vec<(TokenClass, &str)> {
+        tokenize(input, dialect)
+            .into_iter()
+            .filter(|(c, _)| *c != TokenClass::Plain)
+            .collect()
+    }
+
+    #[test]
+    fn test_highlight_json5() -> Result<()> {
+        use TokenClass::*;
+        let text =
+            "{\n  // Note\n  key: 'a\\nb',\n  \"hex\": 0x1F, f: -1.5e3,\n  n: null, b: [true]\n}";
+        assert_eq!(
+            classes(text, Dialect::Json5),
+            [
+                (Aggregate, "{"),
+                (Comment, "// Note"),
+                (BareKey, "key"),
+                (Punctuation, ":"),
+                (String, "'a"),
+                (Escape, "\\n"),
+                (String, "b'"),
+                (Punctuation, ","),
+                (Key, "\"hex\""),
+                (Punctuation, ":"),
+                (Integer, "0x1F"),
+                (Punctuation, ","),
+                (BareKey, "f"),
+                (Punctuation, ":"),
+                (Float, "-1.5e3"),
+                (Punctuation, ","),
+                (BareKey, "n"),
+                (Punctuation, ":"),
+                (Null, "null"),
+                (Punctuation, ","),
+                (BareKey, "b"),
+                (Punctuation, ":"),
+                (Aggregate, "["),
+                (Boolean, "true"),
+                (Aggregate, "]"),
+                (Aggregate, "}"),
+            ]
+        );
+
+        // The text is unchanged apart from the color escapes.
+        let colored = highlight(text, Dialect::Json5, &ColorProfile::basic());
+        assert_ne!(colored, text);
+        let escapes = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+        assert_eq!(escapes.replace_all(&colored, ""), text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlight_hjson() -> Result<()> {
+        use TokenClass::*;
+        let text = "{\n  # Note\n  url: http://example.com, 1\n  port: 80\n  list: [\n    a b\n    2\n  ]\n}";
+        assert_eq!(
+            classes(text, Dialect::Hjson),
+            [
+                (Aggregate, "{"),
+                (Comment, "# Note"),
+                (BareKey, "url"),
+                (Punctuation, ":"),
+                (String, "http://example.com, 1"),
+                (BareKey, "port"),
+                (Punctuation, ":"),
+                (Integer, "80"),
+                (BareKey, "list"),
+                (Punctuation, ":"),
+                (Aggregate, "["),
+                (String, "a b"),
+                (Integer, "2"),
+                (Aggregate, "]"),
+                (Aggregate, "}"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlight_yaml() -> Result<()> {
+        use TokenClass::*;
+        let text = "---\nname: \"a\\tb\" # Note\nitems:\n  - 1\n  - 2.5\n  - ~\nflow: {a: true, b: [x]}\ntext: |\n  line: one\n  two\nend: yes\n";
+        assert_eq!(
+            classes(text, Dialect::Yaml),
+            [
+                (Punctuation, "---"),
+                (BareKey, "name"),
+                (Punctuation, ":"),
+                (String, "\"a"),
+                (Escape, "\\t"),
+                (String, "b\""),
+                (Comment, "# Note"),
+                (BareKey, "items"),
+                (Punctuation, ":"),
+                (Punctuation, "-"),
+                (Integer, "1"),
+                (Punctuation, "-"),
+                (Float, "2.5"),
+                (Punctuation, "-"),
+                (Null, "~"),
+                (BareKey, "flow"),
+                (Punctuation, ":"),
+                (Aggregate, "{"),
+                (BareKey, "a"),
+                (Punctuation, ":"),
+                (Boolean, "true"),
+                (Punctuation, ","),
+                (BareKey, "b"),
+                (Punctuation, ":"),
+                (Aggregate, "["),
+                (String, "x"),
+                (Aggregate, "]}"),
+                (BareKey, "text"),
+                (Punctuation, ":"),
+                (Punctuation, "|"),
+                (String, "line: one"),
+                (String, "two"),
+                (BareKey, "end"),
+                (Punctuation, ":"),
+                (String, "yes"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlight_escapes() -> Result<()> {
+        use TokenClass::*;
+        // Malformed escapes followed by a multibyte character.  A malformed
+        // string ends at the end of the line.
+        let text = "[\"\\uabcé\",\n'\\x4é']";
+        assert_eq!(
+            classes(text, Dialect::Json5),
+            [
+                (Aggregate, "["),
+                (String, "\""),
+                (Escape, "\\uabc"),
+                (String, "é\","),
+                (String, "'"),
+                (Escape, "\\x4"),
+                (String, "é']"),
+            ]
+        );
+        Ok(())
+    }
+}
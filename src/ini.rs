@@ -0,0 +1,434 @@
+use crate::color::ColorProfile;
+use crate::document::{BytesFormat, Document};
+use crate::emit::{common_options, guarded, CommonOptions, Emit};
+use crate::entry::{comments_of, MapEntry};
+use crate::error::Error;
+use crate::hexdump;
+use crate::integer::{Base, Int};
+use crate::layout::TokenClass;
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use crate::sort::KeySort;
+use std::fmt;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// An INI document and its formatting properties.
+///
+/// The document must be a mapping.  Its entries whose values are mappings
+/// are emitted as `[sections]` holding their entries, after the entries
+/// with scalar values, which are emitted ahead of the first section.
+/// Sections cannot nest and values cannot be sequences.
+///
+/// INI has no standard for quoting, so strings are written as they are
+/// unless they are empty, start or end with whitespace, or hold a line
+/// break, a quote or the comment character, in which case they are quoted
+/// with `\` escapes.  Null is written as an empty value and bytes as a hex
+/// string unless another format is requested.
+///
+/// Comments are written on their own lines, since many INI readers do not
+/// recognize comments at the end of a line: those ahead of an entry above
+/// it, and those after an entry's value below it.
+pub struct Ini {
+    document: Document,
+    common: CommonOptions,
+    separator: String,
+    comment_char: char,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+}
+
+impl Ini {
+    common_options!(
+        /// Set whether the document is emitted without comments or the empty
+        /// lines between sections.
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        /// Set the order in which sections and the entries of sections are
+        /// emitted.
+        sort_keys,
+        newline,
+        ascii_comments,
+    );
+    /// Set the text between a key and its value (the default is `" = "`).
+    pub fn separator(mut self, s: &str) -> Self {
+        self.separator = s.to_string();
+        self
+    }
+    /// Set the character starting comment lines: `;` (the default) or `#`.
+    pub fn comment_char(mut self, c: char) -> Self {
+        self.comment_char = c;
+        self
+    }
+    /// Set the rendering of bytes values.  `BytesFormat::Standard` renders
+    /// as `BytesFormat::HexStr`.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.bytes_format = b;
+        self
+    }
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+
+    fn emit<W: fmt::Write>(&self, w: &mut W) -> Result<()> {
+        self.common.check_comments(&self.document)?;
+        let mut emitter = IniEmitter {
+            color: self.common.color.clone(),
+            compact: self.common.compact,
+            sort_keys: self.common.sort_keys.clone(),
+            separator: &self.separator,
+            comment_char: self.comment_char,
+            bytes_format: self.bytes_format,
+            numbers: self.numbers.clone(),
+            float_format: self.float_format,
+            started: false,
+        };
+        emitter.emit_document(w, &self.document)
+    }
+}
+
+impl Emit for Ini {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.common, w, |mut w| self.emit(&mut w))
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.common
+    }
+}
+
+impl Document {
+    pub fn to_ini(self) -> Ini {
+        Ini {
+            document: self,
+            common: CommonOptions::default(),
+            separator: " = ".to_string(),
+            comment_char: ';',
+            bytes_format: BytesFormat::Standard,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+        }
+    }
+}
+
+// Returns the entries of `node` if it is a mapping.
+fn section(node: &Document) -> Option<&[Document]> {
+    match node.as_value() {
+        Ok(Document::Mapping(m)) => Some(m),
+        _ => None,
+    }
+}
+
+struct IniEmitter<'a> {
+    color: ColorProfile,
+    compact: bool,
+    sort_keys: KeySort,
+    separator: &'a str,
+    comment_char: char,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    // Whether anything has been written, so that sections are separated
+    // from what precedes them by an empty line.
+    started: bool,
+}
+
+impl IniEmitter<'_> {
+    fn emit_document<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        let nodes = match node {
+            Document::Fragment(ds) => &ds[..],
+            n => std::slice::from_ref(n),
+        };
+        for node in nodes {
+            match node.comment() {
+                Some((c, _)) => self.emit_comment(w, c)?,
+                None => match section(node) {
+                    Some(m) => self.emit_sections(w, m)?,
+                    None => return Err(Error::StructureError("Mapping", node.variant())),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // Emits the entries of the document: its entries with scalar values,
+    // followed by its sections.
+    fn emit_sections<W: fmt::Write>(&mut self, w: &mut W, mapping: &[Document]) -> Result<()> {
+        let entries = self.sort_keys.order(mapping);
+        let (sections, plain): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .partition(|e| e.as_kv().map_or(false, |(_, v)| section(v).is_some()));
+        for node in plain {
+            self.emit_entry(w, node)?;
+        }
+        for node in sections {
+            let entry = MapEntry::new(node)?;
+            if self.started && !self.compact {
+                writeln!(w)?;
+            }
+            for (c, _) in &entry.comments {
+                self.emit_comment(w, c)?;
+            }
+            let name = self.key(entry.key)?;
+            writeln!(
+                w,
+                "{}{}{}",
+                self.color.punctuation.paint("["),
+                self.color.style(&TokenClass::BareKey).paint(name),
+                self.color.punctuation.paint("]")
+            )?;
+            self.started = true;
+            // Unwrap is ok: sections were chosen for their mapping values.
+            for node in self.sort_keys.order(section(entry.value).unwrap()) {
+                if node.as_kv().map_or(false, |(_, v)| section(v).is_some()) {
+                    return Err(Error::Unrepresentable(
+                        "a mapping nested more than one level",
+                        "INI",
+                    ));
+                }
+                self.emit_entry(w, node)?;
+            }
+            for (c, _) in &entry.trailing {
+                self.emit_comment(w, c)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Emits a `key = value` line along with its comments.
+    fn emit_entry<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        let entry = match MapEntry::new(node) {
+            Ok(entry) => entry,
+            Err(_) => {
+                for (c, _) in comments_of(node) {
+                    self.emit_comment(w, c)?;
+                }
+                return Ok(());
+            }
+        };
+        for (c, _) in &entry.comments {
+            self.emit_comment(w, c)?;
+        }
+        let key = self.key(entry.key)?;
+        let value = self.value(entry.value)?;
+        // An empty value leaves no whitespace at the end of the line.
+        let separator = match value.is_empty() {
+            true => self.separator.trim_end(),
+            false => self.separator,
+        };
+        writeln!(
+            w,
+            "{}{}{}",
+            self.color.style(&TokenClass::BareKey).paint(key),
+            self.color.punctuation.paint(separator),
+            value
+        )?;
+        self.started = true;
+        for (c, _) in &entry.trailing {
+            self.emit_comment(w, c)?;
+        }
+        Ok(())
+    }
+
+    fn emit_comment<W: fmt::Write>(&mut self, w: &mut W, comment: &str) -> Result<()> {
+        if self.compact {
+            return Ok(());
+        }
+        for line in comment.split('\n') {
+            let text = if line.is_empty() {
+                self.comment_char.to_string()
+            } else {
+                format!("{} {}", self.comment_char, line)
+            };
+            writeln!(w, "{}", self.color.comment.paint(text))?;
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    // Returns the text of a key.  Keys are never quoted, so keys which
+    // could not be read back are rejected.
+    fn key(&self, key: &Document) -> Result<String> {
+        let text = match key.as_value()? {
+            Document::String(s, _) => s.clone(),
+            Document::StaticStr(s, _) => s.to_string(),
+            Document::Int(i) => i.format(None),
+            Document::Boolean(b) => b.to_string(),
+            v => return Err(Error::KeyTypeError(v.variant())),
+        };
+        let separator = self.separator.trim();
+        if text.is_empty()
+            || text.trim() != text
+            || text.starts_with(['[', self.comment_char])
+            || text.contains(['\n', '\r', ']'])
+            || (!separator.is_empty() && text.contains(separator))
+        {
+            return Err(Error::Unrepresentable(
+                "a key which is empty or holds a separator",
+                "INI",
+            ));
+        }
+        Ok(text)
+    }
+
+    // Returns the colored text of a scalar value.
+    fn value(&self, node: &Document) -> Result<String> {
+        Ok(match node.as_value()? {
+            Document::String(s, _) => self.string(s),
+            Document::StaticStr(s, _) => self.string(s),
+            Document::Boolean(b) => self.color.boolean.paint(b.to_string()).to_string(),
+            Document::Int(i) => self.int(i)?,
+            Document::Float(f) => {
+                let ctx = NumberContext {
+                    base: Base::Dec,
+                    width: 0,
+                    precision: None,
+                    float: self.float_format,
+                };
+                let text = self.numbers.format_float(*f, &ctx);
+                self.color.float.paint(text).to_string()
+            }
+            Document::Bytes(b) => {
+                let format = match self.bytes_format {
+                    BytesFormat::Standard => BytesFormat::HexStr,
+                    f => f,
+                };
+                // Unwrap is ok: every format but `Standard` renders a string.
+                self.string(&hexdump::to_string(b, format).unwrap())
+            }
+            Document::Null => String::new(),
+            Document::Sequence(_) => return Err(Error::Unrepresentable("a sequence", "INI")),
+            Document::Absent => return Err(Error::AbsentValue),
+            v => return Err(Error::StructureError("scalar", v.variant())),
+        })
+    }
+
+    fn int(&self, i: &Int) -> Result<String> {
+        let ctx = NumberContext {
+            base: i.base(),
+            width: i.width(),
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        // The dialect has no integer type suffixes.
+        let i = &i.unsuffixed();
+        i.check_format(ctx.base)?;
+        let text = self.numbers.format_int(i, &ctx);
+        Ok(self.color.integer.paint(text).to_string())
+    }
+
+    // Returns a string value, quoted if it would not read back as written.
+    fn string(&self, value: &str) -> String {
+        let quoted = value.is_empty()
+            || value.trim() != value
+            || value.starts_with('"')
+            || value.contains(['\n', '\r', self.comment_char]);
+        if !quoted {
+            return self.color.string.paint(value).to_string();
+        }
+        let mut text = String::new();
+        for ch in value.chars() {
+            match ch {
+                '"' => text.push_str("\\\""),
+                '\\' => text.push_str("\\\\"),
+                '\n' => text.push_str("\\n"),
+                '\r' => text.push_str("\\r"),
+                '\t' => text.push_str("\\t"),
+                c => text.push(c),
+            }
+        }
+        let quote = self.color.punctuation.paint("\"");
+        format!("{}{}{}", quote, self.color.string.paint(text), quote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    const DOC: &str = r#"{
+        // Written by the installer.
+        version: 3,
+        database: {
+            host: "db.local",
+            port: 5432,
+            // Seconds.
+            timeout: 2.5,
+        },
+        // The network settings.
+        network: {
+            mask: 0xFF00,
+            banner: " hello; world ",
+            proxy: null,
+            enabled: true,
+        },
+    }"#;
+
+    #[test]
+    fn test_ini() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_ini().to_text()?,
+            r#"; Written by the installer.
+version = 3
+
+[database]
+host = db.local
+port = 5432
+; Seconds.
+timeout = 2.5
+
+; The network settings.
+[network]
+mask = 0xFF00
+banner = " hello; world "
+proxy =
+enabled = true
+"#
+        );
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_ini()
+                .compact(true)
+                .separator("=")
+                .comment_char('#')
+                .to_text()?,
+            r#"version=3
+[database]
+host=db.local
+port=5432
+timeout=2.5
+[network]
+mask=0xFF00
+banner=" hello; world "
+proxy=
+enabled=true
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> Result<()> {
+        let doc = Document::parse("{a: {b: {c: 1}}}")?;
+        assert!(doc.to_ini().to_text().is_err());
+        let doc = Document::parse("{a: [1]}")?;
+        assert!(doc.to_ini().to_text().is_err());
+        let doc = Document::parse("{\"a=b\": 1}")?;
+        assert!(doc.to_ini().to_text().is_err());
+        let doc = Document::parse("[1]")?;
+        assert!(doc.to_ini().to_text().is_err());
+        Ok(())
+    }
+}
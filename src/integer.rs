@@ -1,5 +1,6 @@
 // Integer container types for annotated serialization.
 //
+use crate::error::Error;
 use num_traits::int::PrimInt;
 use std::fmt;
 use std::num::ParseIntError;
@@ -13,7 +14,30 @@ pub enum Base {
     Hex = 16,
 }
 
-#[derive(Clone, Debug)]
+/// The representation of negative integers in bases other than decimal.
+///
+/// Emitters fail on values which do not fit the bit width of a `Wrapped` or
+/// `WrappedSuffix` format, or whose bit width is not between 1 and 128;
+/// `Int::format` writes such values in two's complement instead.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum NegativeFormat {
+    /// The two's complement bit pattern of the value (e.g. `-6i8` as `0xFA`).
+    #[default]
+    TwosComplement,
+    /// A minus sign followed by the magnitude (e.g. `-6i8` as `-0x6`).
+    SignMagnitude,
+    /// The two's complement bit pattern truncated to the given bit width
+    /// (e.g. `-6` in 16 bits as `0xFFFA`).
+    Wrapped(u32),
+    /// Like `Wrapped`, followed by a signed type suffix (e.g. `0xFFFAi16`) from
+    /// which the permissive parser recovers the signed value.  Only the Rust
+    /// source emitter writes the suffix, on values in the range of the
+    /// signed type; the other dialects do not accept it and write the value
+    /// as `Wrapped`.
+    WrappedSuffix(u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum IntValue {
     U8(u8),
     U16(u16),
@@ -121,6 +145,51 @@ impl IntValue {
         }
     }
 
+    /// Like `format`, but negative values are written as a minus sign followed
+    /// by the magnitude.
+    pub fn format_sign_magnitude(&self, base: Base, bitwidth: usize) -> String {
+        let (negative, magnitude) = match self {
+            IntValue::I8(v) => (*v < 0, IntValue::U8(v.unsigned_abs())),
+            IntValue::I16(v) => (*v < 0, IntValue::U16(v.unsigned_abs())),
+            IntValue::I32(v) => (*v < 0, IntValue::U32(v.unsigned_abs())),
+            IntValue::I64(v) => (*v < 0, IntValue::U64(v.unsigned_abs())),
+            IntValue::I128(v) => (*v < 0, IntValue::U128(v.unsigned_abs())),
+            _ => (false, self.clone()),
+        };
+        let text = magnitude.format(base, bitwidth);
+        if negative {
+            format!("-{}", text)
+        } else {
+            text
+        }
+    }
+
+    /// Formats the two's complement bit pattern of the value truncated to
+    /// `bits`, zero-padded to the width of `bits` if `bitwidth` exceeds it.
+    pub fn format_wrapped(&self, base: Base, bitwidth: usize, bits: u32) -> String {
+        let v = match *self {
+            IntValue::U8(v) => v as u128,
+            IntValue::U16(v) => v as u128,
+            IntValue::U32(v) => v as u128,
+            IntValue::U64(v) => v as u128,
+            IntValue::U128(v) => v,
+            IntValue::I8(v) => v as i128 as u128,
+            IntValue::I16(v) => v as i128 as u128,
+            IntValue::I32(v) => v as i128 as u128,
+            IntValue::I64(v) => v as i128 as u128,
+            IntValue::I128(v) => v as u128,
+        };
+        let bits = bits.clamp(1, 128) as usize;
+        let mask = u128::MAX >> (128 - bits);
+        let digits = match base {
+            Base::Bin => bits,
+            Base::Oct => (bits + 2) / 3,
+            Base::Dec => 0,
+            Base::Hex => (bits + 3) / 4,
+        };
+        Self::convert(v & mask, base, bitwidth.min(digits))
+    }
+
     pub fn negate(self) -> Self {
         match self {
             IntValue::U8(v) => IntValue::I16(-(v as i16)),
@@ -137,11 +206,12 @@ impl IntValue {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Int {
     value: IntValue,
     base: Base,
     width: usize,
+    negative: NegativeFormat,
 }
 
 impl Int {
@@ -152,6 +222,7 @@ impl Int {
             value: v.into(),
             base,
             width,
+            negative: NegativeFormat::default(),
         }
     }
     /// Creates an `Int` that will display with no zero padding.
@@ -174,14 +245,104 @@ impl Int {
         }
     }
 
+    /// Returns the value as an `i128`, if it is in range.
+    pub(crate) fn as_i128(&self) -> Option<i128> {
+        match self.value {
+            IntValue::U128(v) => i128::try_from(v).ok(),
+            _ => Some(i128::from(self)),
+        }
+    }
+
+    /// Returns the value and the primitive type which held it.
+    pub(crate) fn value(&self) -> &IntValue {
+        &self.value
+    }
+
     /// Returns the preferred base for expressing this integer.
     pub fn base(&self) -> Base {
         self.base
     }
 
+    /// Returns the minimum number of digits used when formatting this integer.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the representation of negative values in non-decimal bases.
+    pub fn negative_format(&self) -> NegativeFormat {
+        self.negative
+    }
+
+    /// Sets the representation of negative values in non-decimal bases.
+    pub fn with_negative_format(mut self, negative: NegativeFormat) -> Int {
+        self.negative = negative;
+        self
+    }
+
+    /// Adopts the preferred base, padding width and negative format of `other`.
+    pub fn copy_format(&mut self, other: &Int) {
+        self.base = other.base;
+        self.width = other.width;
+        self.negative = other.negative;
+    }
+
+    /// Returns the integer with a `WrappedSuffix` format written as
+    /// `Wrapped`, for the dialects which do not accept integer type suffixes.
+    pub(crate) fn unsuffixed(&self) -> Int {
+        match self.negative {
+            NegativeFormat::WrappedSuffix(bits) => self
+                .clone()
+                .with_negative_format(NegativeFormat::Wrapped(bits)),
+            _ => self.clone(),
+        }
+    }
+
+    /// Checks that the integer fits its negative format in `base`: the bit
+    /// width of a `Wrapped` format must be between 1 and 128 and hold the
+    /// value, and the value of a `WrappedSuffix` format must fit in the
+    /// signed type named by its suffix.
+    pub fn check_format(&self, base: Base) -> Result<(), Error> {
+        let (bits, signed) = match self.negative {
+            _ if base == Base::Dec => return Ok(()),
+            NegativeFormat::Wrapped(bits) => (bits, false),
+            NegativeFormat::WrappedSuffix(bits) => (bits, true),
+            _ => return Ok(()),
+        };
+        // Powers of two beyond the range of `i128` are `None`.
+        let pow = |n: u32| 1i128.checked_shl(n).filter(|p| *p > 0);
+        let fits = match self.as_i128() {
+            _ if !(1..=128).contains(&bits) => false,
+            _ if signed && ![8, 16, 32, 64, 128].contains(&bits) => false,
+            // Only an unsigned 128-bit value is out of the range of `i128`.
+            None => !signed && bits == 128,
+            Some(v) => {
+                let min = pow(bits - 1).map_or(i128::MIN, |p| -p);
+                let max = pow(if signed { bits - 1 } else { bits });
+                v >= min && !matches!(max, Some(max) if v >= max)
+            }
+        };
+        if fits {
+            Ok(())
+        } else {
+            Err(Error::IntegerWidth(self.value.format(base, 0), bits))
+        }
+    }
+
     /// Formats the integer in the requested base, defaulting to the preferred base.
     pub fn format(&self, base: Option<&Base>) -> String {
-        self.value.format(*base.unwrap_or(&Base::Dec), self.width)
+        let base = *base.unwrap_or(&Base::Dec);
+        match self.negative {
+            _ if self.check_format(base).is_err() => self.value.format(base, self.width),
+            _ if base == Base::Dec => self.value.format(base, self.width),
+            NegativeFormat::TwosComplement => self.value.format(base, self.width),
+            NegativeFormat::SignMagnitude => self.value.format_sign_magnitude(base, self.width),
+            NegativeFormat::Wrapped(bits) => self.value.format_wrapped(base, self.width, bits),
+            NegativeFormat::WrappedSuffix(bits) => format!(
+                "{}i{}",
+                self.value.format_wrapped(base, self.width, bits),
+                bits
+            ),
+        }
     }
 
     fn strip_numeric_prefix<'a>(src: &'a str, ch: u8) -> &'a str {
@@ -207,6 +368,34 @@ impl Int {
         }
     }
 
+    /// Inserts `_` separators between groups of digits of an integer
+    /// formatted by `Int::format`: every 3 digits in decimal and every 4
+    /// digits in other bases (e.g. `1_000_000` or `0x1234_5678`).  Text which
+    /// is not a plain integer in `base` is returned unchanged.
+    pub fn group_digits(text: &str, base: Base) -> String {
+        let (sign, rest) = match text.strip_prefix('-') {
+            Some(r) => ("-", r),
+            None => ("", text),
+        };
+        let (prefix, digits) = match base {
+            Base::Dec => ("", rest),
+            _ => rest.split_at(2.min(rest.len())),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(base as u32)) {
+            return text.to_string();
+        }
+        let group = if base == Base::Dec { 3 } else { 4 };
+        let mut out = String::from(sign);
+        out.push_str(prefix);
+        for (i, ch) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % group == 0 {
+                out.push('_');
+            }
+            out.push(ch);
+        }
+        out
+    }
+
     /// Converts from a string into an integer value.
     /// - If the `radix` is 2, 8 or 16, the integer is parsed in that base.
     ///   The integer may start with one of the common prefixes `0x`, `0b`, or `0o`.
@@ -214,7 +403,30 @@ impl Int {
     /// - If `radix` is `0`, the base is inferred from the common integer
     ///   prefixes `0x`, `0b` and `0o`.  If there is no prefix, the base defaults
     ///   to base 10.
+    ///
+    /// Digit group separators (`_`) are ignored.  Negative values keep their
+    /// sign-magnitude form (`-0x6`) when formatted.
     pub fn from_str_radix(src: &str, radix: u32) -> Result<Int, ParseIntError> {
+        Self::parse(src, radix, "")
+    }
+
+    /// Like `from_str_radix`, but tolerates a C or Rust integer type suffix as
+    /// found in C headers (e.g. `0x10u32`, `16UL` or `-5i8`).
+    ///
+    /// A suffix naming a bit width converts the value to that type, failing if
+    /// it is out of range, and pads the value to the width of the type.  Other
+    /// C suffixes (`u`, `l`, `ul`, `ll`, `ull` in any case and order) are
+    /// ignored.  Unsigned hexadecimal, binary and octal digits with a signed
+    /// suffix are a two's complement bit pattern (e.g. `0xFFFAi16` is `-6`)
+    /// and are formatted as `NegativeFormat::WrappedSuffix`.
+    pub fn from_str_suffixed(src: &str, radix: u32) -> Result<Int, ParseIntError> {
+        match src.find(['u', 'U', 'l', 'L', 'i', 'I']) {
+            Some(i) => Self::parse(&src[..i], radix, &src[i..]),
+            None => Self::parse(src, radix, ""),
+        }
+    }
+
+    fn parse(src: &str, radix: u32, suffix: &str) -> Result<Int, ParseIntError> {
         let (negative, src) = if let Some(s) = src.strip_prefix('-') {
             (true, s)
         } else if let Some(s) = src.strip_prefix('+') {
@@ -229,9 +441,71 @@ impl Int {
             10 => (Base::Dec, src),
             _ => Self::detect_numeric_prefix(src),
         };
-        let value = IntValue::U128(u128::from_str_radix(text, base as u32)?);
-        let value = if negative { value.negate() } else { value };
-        Ok(Self::new_with_padding(value, base, text.len()))
+        let digits;
+        let text = if text.contains('_') {
+            digits = text.replace('_', "");
+            digits.as_str()
+        } else {
+            text
+        };
+        let signed = || {
+            if negative {
+                format!("-{}", text)
+            } else {
+                text.to_string()
+            }
+        };
+        let radix = base as u32;
+        let wrap = base != Base::Dec && !negative;
+        let suffix = suffix.to_ascii_lowercase();
+        let (value, width) = match suffix.as_str() {
+            "" | "u" | "l" | "ul" | "lu" | "ll" | "ull" | "llu" => {
+                let value = IntValue::U128(u128::from_str_radix(text, radix)?);
+                let value = if negative { value.negate() } else { value };
+                (value, text.len())
+            }
+            "u8" => (u8::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            "u16" => (u16::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            "u32" => (u32::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            "u64" => (u64::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            "u128" => (u128::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            "i8" if wrap => ((u8::from_str_radix(text, radix)? as i8).into(), usize::MAX),
+            "i16" if wrap => (
+                (u16::from_str_radix(text, radix)? as i16).into(),
+                usize::MAX,
+            ),
+            "i32" if wrap => (
+                (u32::from_str_radix(text, radix)? as i32).into(),
+                usize::MAX,
+            ),
+            "i64" if wrap => (
+                (u64::from_str_radix(text, radix)? as i64).into(),
+                usize::MAX,
+            ),
+            "i128" if wrap => (
+                (u128::from_str_radix(text, radix)? as i128).into(),
+                usize::MAX,
+            ),
+            "i8" => (i8::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            "i16" => (i16::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            "i32" => (i32::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            "i64" => (i64::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            "i128" => (i128::from_str_radix(&signed(), radix)?.into(), usize::MAX),
+            // Reject the unknown suffix as an invalid digit.
+            _ => (
+                u128::from_str_radix(&format!("{}{}", text, suffix), radix)?.into(),
+                0,
+            ),
+        };
+        let int = Self::new_with_padding(value, base, width);
+        Ok(match suffix.strip_prefix('i') {
+            Some(bits) if wrap => {
+                // Unwrap is ok: the suffix was matched as a signed type above.
+                int.with_negative_format(NegativeFormat::WrappedSuffix(bits.parse().unwrap()))
+            }
+            _ if negative => int.with_negative_format(NegativeFormat::SignMagnitude),
+            _ => int,
+        })
     }
 }
 
@@ -384,4 +658,124 @@ mod tests {
             "0x10000"
         );
     }
+
+    #[test]
+    fn test_group_digits() -> Result<()> {
+        let cases = [
+            (
+                Int::new(0x123456789abcdef0u64, Base::Hex),
+                "0x1234_5678_9ABC_DEF0",
+            ),
+            (Int::new(0x12345u32, Base::Hex), "0x1_2345"),
+            (Int::new(1234567u32, Base::Dec), "1_234_567"),
+            (Int::new(-1234i32, Base::Dec), "-1_234"),
+            (Int::new(123u8, Base::Dec), "123"),
+            (Int::new(0b101010u8, Base::Bin), "0b10_1010"),
+            (Int::new(0o7755u16, Base::Oct), "0o7755"),
+        ];
+        for (int, grouped) in cases {
+            let text = Int::group_digits(&int.to_string(), int.base());
+            assert_eq!(text, grouped);
+            assert_eq!(u128::from(Int::from_str_radix(&text, 0)?), u128::from(&int));
+        }
+        assert_eq!(Int::group_digits("1.5", Base::Dec), "1.5");
+        Ok(())
+    }
+
+    #[test]
+    fn negative_format() -> Result<()> {
+        let int = Int::new(-6i8, Base::Hex);
+        assert_eq!(int.to_string(), "0xFA");
+        let int = int.with_negative_format(NegativeFormat::SignMagnitude);
+        assert_eq!(int.to_string(), "-0x6");
+        let int = Int::new_padded(i32::MIN, Base::Hex)
+            .with_negative_format(NegativeFormat::SignMagnitude);
+        assert_eq!(int.to_string(), "-0x80000000");
+        // Parsed negative values retain their sign.
+        let int = Int::from_str_radix("-0x6", 0)?;
+        assert_eq!(int.negative_format(), NegativeFormat::SignMagnitude);
+        assert_eq!(int.to_string(), "-0x6");
+        assert_eq!(i8::from(int), -6);
+        Ok(())
+    }
+
+    #[test]
+    fn suffixed_parse() -> Result<()> {
+        let int = Int::from_str_suffixed("0x10u32", 0)?;
+        assert_eq!(u32::from(&int), 16);
+        assert_eq!(int.to_string(), "0x00000010");
+        let int = Int::from_str_suffixed("-5i8", 0)?;
+        assert_eq!(i8::from(&int), -5);
+        let int = Int::from_str_suffixed("0xFFul", 0)?;
+        assert_eq!(int.to_string(), "0xFF");
+        let int = Int::from_str_suffixed("16LLU", 0)?;
+        assert_eq!(u64::from(int), 16);
+        assert!(Int::from_str_suffixed("0x100u8", 0).is_err());
+        assert!(Int::from_str_suffixed("-1u8", 0).is_err());
+        assert!(Int::from_str_suffixed("10lul", 0).is_err());
+        assert!(Int::from_str_radix("10u32", 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn wrapped_format() -> Result<()> {
+        let int = Int::new(-6i32, Base::Hex).with_negative_format(NegativeFormat::Wrapped(16));
+        assert_eq!(int.to_string(), "0xFFFA");
+        let int =
+            Int::new_padded(6i32, Base::Hex).with_negative_format(NegativeFormat::Wrapped(16));
+        assert_eq!(int.to_string(), "0x0006");
+        let int = Int::new(-1i64, Base::Bin).with_negative_format(NegativeFormat::Wrapped(4));
+        assert_eq!(int.to_string(), "0b1111");
+        let int = Int::new(-6i32, Base::Dec).with_negative_format(NegativeFormat::Wrapped(16));
+        assert_eq!(int.to_string(), "-6");
+
+        // The suffix records the signed type so parsing recovers the value.
+        let int =
+            Int::new(-6i16, Base::Hex).with_negative_format(NegativeFormat::WrappedSuffix(16));
+        assert_eq!(int.to_string(), "0xFFFAi16");
+        let int = Int::from_str_suffixed(&int.to_string(), 0)?;
+        assert_eq!(i128::from(&int), -6);
+        assert_eq!(int.negative_format(), NegativeFormat::WrappedSuffix(16));
+        assert_eq!(int.to_string(), "0xFFFAi16");
+        let int = Int::from_str_suffixed("0x7FFFi16", 0)?;
+        assert_eq!(i128::from(int), 0x7FFF);
+        assert!(Int::from_str_suffixed("0x1FFFFi16", 0).is_err());
+
+        // Values which do not fit are not truncated.
+        let int = Int::new(0x1FFFFi32, Base::Hex).with_negative_format(NegativeFormat::Wrapped(16));
+        assert!(int.check_format(Base::Hex).is_err());
+        assert!(int.check_format(Base::Dec).is_ok());
+        assert_eq!(int.to_string(), "0x1FFFF");
+        let int =
+            Int::new(0xFFFFi32, Base::Hex).with_negative_format(NegativeFormat::WrappedSuffix(16));
+        assert!(int.check_format(Base::Hex).is_err());
+        let int =
+            Int::new(-0x8000i32, Base::Hex).with_negative_format(NegativeFormat::WrappedSuffix(16));
+        assert!(int.check_format(Base::Hex).is_ok());
+        let int =
+            Int::new(-1i32, Base::Hex).with_negative_format(NegativeFormat::WrappedSuffix(12));
+        assert!(int.check_format(Base::Hex).is_err());
+
+        // Bit widths near the range of `i128` do not overflow, and widths
+        // outside of 1 to 128 are rejected.
+        for (value, bits, fits) in [
+            (i128::MAX, 127, true),
+            (-(1i128 << 126), 127, true),
+            (-(1i128 << 126) - 1, 127, false),
+            (i128::MIN, 128, true),
+            (-1, 0, false),
+            (-1, 129, false),
+        ] {
+            let int =
+                Int::new(value, Base::Hex).with_negative_format(NegativeFormat::Wrapped(bits));
+            assert_eq!(
+                int.check_format(Base::Hex).is_ok(),
+                fits,
+                "{} in {}",
+                value,
+                bits
+            );
+        }
+        Ok(())
+    }
 }
@@ -1,10 +1,28 @@
-use crate::color::ColorProfile;
-use crate::document::{CommentFormat, Document, StrFormat};
+use crate::ascii::{escape_char, AsciiComments};
+use crate::check::FormatCheck;
+use crate::color::{ColorProfile, HtmlProfile};
+use crate::diff::Diff;
+use crate::document::{BytesFormat, CommentFormat, Document, StrFormat};
+use crate::emit::{common_options, guarded, write_sinks, CommonOptions, Emit};
+use crate::entry::{comments_of, MapEntry, SeqItem};
 use crate::error::Error;
+use crate::hexdump;
 use crate::integer::{Base, Int};
-use once_cell::sync::OnceCell;
-use std::collections::HashSet;
+use crate::layout::{Layout, TokenClass, TokenSink};
+use crate::newline::Newline;
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use crate::path::{pointer, segments};
+use crate::progress::{Progress, ProgressCallback, ProgressWriter, Reporter};
+use crate::render::{line_width, write_indent};
+use crate::sort::KeySort;
+use crate::source_map::{SourceMap, Tracker, TrackingWriter};
+use crate::unambiguous::{is_bareword, is_unambiguous_value, Bareword, Dialect};
+use crate::width::str_width;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
+use std::io;
+use std::sync::Arc;
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -16,31 +34,271 @@ pub enum Multiline {
     Hjson,
 }
 
-/// A JSON document and its formatting properties.
-pub struct Json {
-    document: Document,
-    indent: usize,
-    color: ColorProfile,
-    comment: HashSet<CommentFormat>,
+/// Placement of the comments attached to mapping entries and sequence
+/// elements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommentPlacement {
+    /// Comments are emitted on the lines above the key or element.
+    Above,
+    /// Single-line comments on scalar (or compact) values are emitted after
+    /// the value on the same line.  Other comments are emitted above.
+    Trailing,
+}
+
+/// Placement of the comments attached to expanded mapping and sequence
+/// values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggregateCommentPlacement {
+    /// Comments are emitted on the lines above the key or opening bracket.
+    Above,
+    /// Comments are emitted just inside the opening bracket, ahead of the
+    /// first element.
+    Inside,
+    /// A single-line comment is emitted after the closing bracket.  Other
+    /// comments are emitted above.
+    After,
+}
+
+/// Placement of the opening bracket of an expanded mapping or sequence which
+/// is the value of a mapping entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BracePlacement {
+    /// The bracket follows the key on the same line.
+    SameLine,
+    /// The bracket is emitted on the line after the key, at the indentation
+    /// of the key (Allman style).
+    NextLine,
+}
+
+/// Encoding of non-finite float values (NaN and ±Infinity), which have no
+/// representation in strict JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonFinite {
+    /// Emit the literals `NaN`, `Infinity` and `-Infinity` (valid json5).
+    Literal,
+    /// Emit the quoted strings `"NaN"`, `"Infinity"` and `"-Infinity"`.
+    String,
+    /// Emit objects of the form `{"$float": "nan"}`, `{"$float": "inf"}`
+    /// and `{"$float": "-inf"}`.
+    Object,
+    /// Emit `null`.  The value is lost, but the output is accepted by any
+    /// JSON consumer.
+    Null,
+}
+
+/// The handling of comments which cannot be emitted, either because no
+/// comment formats are permitted or because they are inside compact output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommentPolicy {
+    /// Silently omit the comments.
+    #[default]
+    Drop,
+    /// Fail with `Error::CommentDropped`.
+    Error,
+    /// Omit the comments from the document and return them from
+    /// `Json::emit` instead.
+    HoistToSidecar,
+}
+
+/// The insertion of `_` separators between groups of digits in integers:
+/// every 3 digits in decimal and every 4 digits in other bases.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DigitGrouping {
+    /// Never group digits.
+    #[default]
+    None,
+    /// Group digits in integers emitted as quoted strings.
+    Quoted,
+    /// Group digits in quoted strings and in hexadecimal, binary and octal
+    /// literals, in formats whose literals permit separators (e.g. TOML).
+    /// No JSON dialect does, and emitting such a literal as JSON fails with
+    /// `Error::ConfigError`.
+    Literals,
+}
+
+/// A comment omitted from the output under `CommentPolicy::HoistToSidecar`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DroppedComment {
+    /// The JSON Pointer path of the node the comment is attached to.
+    pub path: String,
+    /// The text of the comment.
+    pub comment: String,
+}
+
+/// Formatting properties of a JSON document.
+#[derive(Clone)]
+pub struct JsonOptions {
+    common: CommonOptions,
+    // Options which accept several values keep them in the order they were
+    // given, so that any decision between them is deterministic.
+    comment: Vec<CommentFormat>,
     standard_comment: CommentFormat,
-    bases: HashSet<Base>,
-    literals: HashSet<Base>,
+    comment_policy: CommentPolicy,
+    comment_placement: CommentPlacement,
+    aggregate_comment_placement: AggregateCommentPlacement,
+    brace_placement: BracePlacement,
+    align_comments: bool,
+    bases: Vec<Base>,
+    literals: Vec<Base>,
     strict_numeric_limits: bool,
+    digit_grouping: DigitGrouping,
+    non_finite: NonFinite,
     multiline: Multiline,
+    multiline_indent: usize,
+    multiline_inline_open: bool,
+    multiline_trailing_whitespace: bool,
     bare_keys: bool,
-    compact: bool,
+    bareword: Bareword,
+    ascii_only: bool,
+    escape_line_separators: bool,
+    dialect: Dialect,
+    trailing_comma: bool,
+    preserve_style: bool,
+    quoteless: bool,
+    bytes_format: BytesFormat,
+    bytes_per_row: usize,
+    bytes_ascii: bool,
+    inline_empty: bool,
+    snippet: bool,
+    compact_threshold: Option<usize>,
+    pack_width: Option<usize>,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
 }
 
-impl Json {
-    /// Set the amount of indentation for each level of nesting.
-    pub fn indent(mut self, i: usize) -> Self {
-        self.indent = i;
-        self
+impl Default for JsonOptions {
+    fn default() -> Self {
+        JsonOptions {
+            common: CommonOptions::default(),
+            comment: Vec::new(),
+            standard_comment: CommentFormat::SlashSlash,
+            comment_policy: CommentPolicy::Drop,
+            comment_placement: CommentPlacement::Above,
+            aggregate_comment_placement: AggregateCommentPlacement::Above,
+            brace_placement: BracePlacement::SameLine,
+            align_comments: false,
+            bases: vec![Base::Dec],
+            literals: vec![Base::Dec],
+            strict_numeric_limits: true,
+            digit_grouping: DigitGrouping::None,
+            non_finite: NonFinite::String,
+            multiline: Multiline::None,
+            multiline_indent: 1,
+            multiline_inline_open: false,
+            multiline_trailing_whitespace: true,
+            bare_keys: false,
+            bareword: Bareword::Ascii,
+            ascii_only: false,
+            escape_line_separators: false,
+            dialect: Dialect::Json,
+            trailing_comma: false,
+            preserve_style: false,
+            quoteless: false,
+            bytes_format: BytesFormat::Standard,
+            bytes_per_row: 1,
+            bytes_ascii: false,
+            inline_empty: true,
+            snippet: false,
+            compact_threshold: None,
+            pack_width: None,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+        }
     }
-    /// Set the comment style to use in the document.
+}
+
+impl JsonOptions {
+    /// Returns the options of a strict JSON document, as used by
+    /// `Document::to_json`.  Equivalent to `JsonOptions::default()`.
+    pub fn strict_default() -> Self {
+        Self::default()
+    }
+
+    /// Returns the options of a JSON with comments document, as used by
+    /// `Document::to_jsonc`.
+    /// A JSONC document allows `//` and `/* */` comments but is otherwise
+    /// strict JSON, as read by VS Code settings and `tsconfig.json`.
+    pub fn jsonc_default() -> Self {
+        Self::default().comment(&[CommentFormat::Block, CommentFormat::SlashSlash])
+    }
+
+    /// Returns the options of a Json5 document, as used by
+    /// `Document::to_json5`.
+    /// A Json5 document allows `//` comments, hex literals,
+    /// multiline strings and bare keys.
+    pub fn json5_default() -> Self {
+        Self::default()
+            .comment(&[CommentFormat::Block, CommentFormat::SlashSlash])
+            .literals(&[Base::Hex])
+            .multiline(Multiline::Json5)
+            .non_finite(NonFinite::Literal)
+            .bare_keys(true)
+            .dialect(Dialect::Json5)
+    }
+
+    /// Returns the options of a Hjson document, as used by
+    /// `Document::to_hjson`.
+    /// A Hjson document allows comments, multiline strings and bare keys.
+    /// Defaults to `#` comments, but hjson also supports `//` comments.
+    pub fn hjson_default() -> Self {
+        Self::default()
+            .comment(&[
+                CommentFormat::Block,
+                CommentFormat::Hash,
+                CommentFormat::SlashSlash,
+            ])
+            .standard_comment(CommentFormat::Hash)
+            .multiline(Multiline::Hjson)
+            .bare_keys(true)
+            .quoteless(true)
+            .dialect(Dialect::Hjson)
+    }
+
+    // Returns the style in which to emit a comment in `format`.
+    fn comment_format(&self, format: CommentFormat) -> CommentFormat {
+        [format, self.standard_comment]
+            .into_iter()
+            .find(|f| self.comment.contains(f))
+            .or_else(|| self.comment.first().copied())
+            .unwrap_or(self.standard_comment)
+    }
+
+    // Returns the base in which to emit an integer in `base`.
+    fn int_base(&self, base: Base) -> Base {
+        if self.bases.contains(&base) {
+            base
+        } else {
+            Base::Dec
+        }
+    }
+
+    common_options!(
+        /// Set the amount of indentation for each level of nesting.
+        indent,
+        /// Set the order in which mapping entries are emitted.
+        sort_keys,
+        /// Set whether mapping entries whose value is null are omitted, along
+        /// with their comments.
+        skip_null,
+        /// Set whether or not to use compact form.
+        /// Compact form eliminates comments, newlines and indentation.
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        max_depth,
+        newline,
+        /// Set how comments are rendered in ASCII-only output, or whether
+        /// comments with non-ASCII characters are rejected
+        /// (`AsciiComments::Reject`) for environments which forbid them.
+        ascii_comments,
+    );
+    /// Set the comment styles permitted in the document.
+    /// A comment whose style is not permitted is emitted in the standard
+    /// style if that is permitted, or else in the first permitted style.
     pub fn comment(mut self, c: &[CommentFormat]) -> Self {
         for x in c {
-            self.comment.insert(*x);
+            insert(&mut self.comment, *x);
         }
         self
     }
@@ -49,23 +307,54 @@ impl Json {
         self.standard_comment = c;
         self
     }
+    /// Set the handling of comments which cannot be emitted.
+    pub fn comment_policy(mut self, p: CommentPolicy) -> Self {
+        self.comment_policy = p;
+        self
+    }
+    /// Set where the comments attached to mapping entries and sequence
+    /// elements are emitted.
+    pub fn comment_placement(mut self, p: CommentPlacement) -> Self {
+        self.comment_placement = p;
+        self
+    }
+    /// Set where the comments attached to expanded mapping and sequence
+    /// values are emitted.
+    pub fn aggregate_comment_placement(mut self, p: AggregateCommentPlacement) -> Self {
+        self.aggregate_comment_placement = p;
+        self
+    }
+    /// Set where the opening bracket of an expanded mapping or sequence
+    /// value is emitted relative to its key.
+    pub fn brace_placement(mut self, p: BracePlacement) -> Self {
+        self.brace_placement = p;
+        self
+    }
+    /// Set whether the trailing comments of a mapping's entries are padded
+    /// to start at the same column.
+    pub fn align_comments(mut self, b: bool) -> Self {
+        self.align_comments = b;
+        self
+    }
 
     /// Set the allowable bases for integers.
     /// Note: an allowed base that is _not_ allowed for literals will be
     /// emitted as a quoted string.
+    /// An integer whose base is not allowed is emitted in decimal, which is
+    /// always allowed.
     pub fn bases(mut self, b: &[Base]) -> Self {
         for x in b {
-            self.bases.insert(*x);
+            insert(&mut self.bases, *x);
         }
         self
     }
     /// Set the allowable bases for integer literals.
     /// Note: bases allowed as literals will be emitted directly into
-    /// the document.
+    /// the document (e.g. `0x1F`, `0b1010` or `0o755`).
     pub fn literals(mut self, b: &[Base]) -> Self {
         for x in b {
-            self.bases.insert(*x);
-            self.literals.insert(*x);
+            insert(&mut self.bases, *x);
+            insert(&mut self.literals, *x);
         }
         self
     }
@@ -76,127 +365,961 @@ impl Json {
         self.strict_numeric_limits = b;
         self
     }
+    /// Set the insertion of digit group separators in integers.
+    pub fn digit_grouping(mut self, g: DigitGrouping) -> Self {
+        self.digit_grouping = g;
+        self
+    }
+    /// Set the encoding of non-finite float values.
+    pub fn non_finite(mut self, n: NonFinite) -> Self {
+        self.non_finite = n;
+        self
+    }
     /// Set the style of multiline strings to be used in the document.
     pub fn multiline(mut self, m: Multiline) -> Self {
         self.multiline = m;
         self
     }
+    /// Set the number of indent levels by which the `'''` delimiters and
+    /// lines of Hjson multiline strings are indented relative to their key.
+    /// The default is one level.
+    pub fn multiline_indent(mut self, n: usize) -> Self {
+        self.multiline_indent = n;
+        self
+    }
+    /// Set whether the opening `'''` of Hjson multiline strings is written
+    /// on the key's line rather than on a line of its own.  Hjson removes
+    /// the leading whitespace of each line up to the column of the opening
+    /// delimiter, so the lines are indented at least to that column.
+    pub fn multiline_inline_open(mut self, b: bool) -> Self {
+        self.multiline_inline_open = b;
+        self
+    }
+    /// Set whether trailing whitespace in the lines of Hjson multiline
+    /// strings is written.  When disabled, it is removed from each line.
+    pub fn multiline_trailing_whitespace(mut self, b: bool) -> Self {
+        self.multiline_trailing_whitespace = b;
+        self
+    }
     /// Set whether bare keys in mappings are allowed.
     pub fn bare_keys(mut self, b: bool) -> Self {
         self.bare_keys = b;
         self
     }
+    /// Set the characters permitted in bare keys.
+    pub fn bareword(mut self, b: Bareword) -> Self {
+        self.bareword = b;
+        self
+    }
+    /// Set whether the output is restricted to ASCII.  Non-ASCII characters
+    /// in keys and strings are written as `\u` escapes, with characters
+    /// beyond the Basic Multilingual Plane written as a UTF-16 surrogate
+    /// pair (e.g. `\ud83d\ude00`).  Hjson multiline and quoteless strings
+    /// cannot hold escapes, so such strings are quoted instead.
+    ///
+    /// When disabled (the default), only the control characters U+0000
+    /// through U+001F are escaped; everything else, including DEL and the
+    /// C1 controls, is written as raw UTF-8.
+    pub fn ascii_only(mut self, b: bool) -> Self {
+        self.ascii_only = b;
+        self
+    }
+    /// Set whether the line and paragraph separators (U+2028 and U+2029)
+    /// are written as `\u` escapes.  Both are legal in JSON strings but
+    /// terminate JavaScript string literals, so output which may be
+    /// embedded in a script should escape them.
+    pub fn escape_line_separators(mut self, b: bool) -> Self {
+        self.escape_line_separators = b;
+        self
+    }
+    /// Set the dialect in which the document is written.  Options which
+    /// the dialect does not permit are ignored: JSON has no trailing commas
+    /// or single-quoted strings.
+    pub fn dialect(mut self, d: Dialect) -> Self {
+        self.dialect = d;
+        self
+    }
+    /// Set whether expanded sequences and mappings emit a comma after their
+    /// last element.  Only json5 and hjson permit trailing commas, so this
+    /// is ignored in the JSON dialect.
+    pub fn trailing_comma(mut self, b: bool) -> Self {
+        self.trailing_comma = b;
+        self
+    }
+    /// Set whether to preserve the quoting style recorded on each string.
+    /// When true, single-quoted strings are emitted with single quotes and
+    /// only keys recorded as unquoted are emitted as bare keys.  This is
+    /// intended for re-emitting parsed documents in a dialect which permits
+    /// those forms (e.g. json5); the JSON dialect always uses double quotes.
+    pub fn preserve_style(mut self, b: bool) -> Self {
+        self.preserve_style = b;
+        self
+    }
+    /// Set whether strings recorded as unquoted may be emitted as hjson
+    /// quoteless strings.  Strings are only emitted quoteless when doing so
+    /// is unambiguous; see `is_unambiguous_value`.
+    pub fn quoteless(mut self, b: bool) -> Self {
+        self.quoteless = b;
+        self
+    }
+    /// Set the rendering of bytes values: a list of integers
+    /// (`BytesFormat::Standard`) or a string in the given format.  Bytes
+    /// annotated with a format are rendered as strings regardless.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.bytes_format = b;
+        self
+    }
+    /// Set the number of bytes emitted per row when bytes are rendered as a
+    /// list of integers.  When greater than one, each row is followed by a
+    /// comment giving its offset (if comments are permitted).
+    pub fn bytes_per_row(mut self, n: usize) -> Self {
+        self.bytes_per_row = n;
+        self
+    }
+    /// Set whether the offset comment of each row of bytes includes the
+    /// printable ASCII characters of the row, as in `hexdump -C`.
+    pub fn bytes_ascii(mut self, b: bool) -> Self {
+        self.bytes_ascii = b;
+        self
+    }
+    /// Set whether empty mappings and sequences are emitted on one line as
+    /// `{}` and `[]`, rather than with the closing symbol on its own line.
+    /// Enabled by default.
+    pub fn inline_empty(mut self, b: bool) -> Self {
+        self.inline_empty = b;
+        self
+    }
+    /// Emit the entries of a root mapping, or the elements of a root
+    /// sequence, without the enclosing braces or brackets and without
+    /// indentation, as a snippet for inclusion in another document.  The
+    /// last entry is followed by a comma only under `trailing_comma`.
+    pub fn snippet(mut self, b: bool) -> Self {
+        self.snippet = b;
+        self
+    }
+    /// Emit any mapping or sequence whose compact form, including its
+    /// indentation, fits within `n` columns on a single line.
+    /// Aggregates containing comments are only compacted when comments
+    /// are not being emitted.
+    pub fn compact_threshold(mut self, n: usize) -> Self {
+        self.compact_threshold = Some(n);
+        self
+    }
+    /// Pack the elements of sequences of scalar values several to a line,
+    /// filling each line up to `n` columns including its indentation.
+    /// Sequences containing comments or aggregates are emitted one element
+    /// per line.
+    pub fn pack_width(mut self, n: usize) -> Self {
+        self.pack_width = Some(n);
+        self
+    }
+
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+}
+
+type OptionsOverride = Arc<dyn Fn(JsonOptions) -> JsonOptions + Send + Sync>;
+
+/// A JSON document and its formatting properties.
+pub struct Json {
+    document: Document,
+    options: JsonOptions,
+    overrides: Vec<(Vec<String>, OptionsOverride)>,
+    progress: Option<(usize, ProgressCallback)>,
+}
+
+impl Json {
+    /// Set the amount of indentation for each level of nesting.
+    pub fn indent(mut self, i: usize) -> Self {
+        self.options = self.options.indent(i);
+        self
+    }
+    /// Set the comment style to use in the document.
+    pub fn comment(mut self, c: &[CommentFormat]) -> Self {
+        self.options = self.options.comment(c);
+        self
+    }
+    /// Set the comment style to use in the document.
+    pub fn standard_comment(mut self, c: CommentFormat) -> Self {
+        self.options = self.options.standard_comment(c);
+        self
+    }
+    /// Set the handling of comments which cannot be emitted.
+    pub fn comment_policy(mut self, p: CommentPolicy) -> Self {
+        self.options = self.options.comment_policy(p);
+        self
+    }
+    /// Set where the comments attached to mapping entries and sequence
+    /// elements are emitted.
+    pub fn comment_placement(mut self, p: CommentPlacement) -> Self {
+        self.options = self.options.comment_placement(p);
+        self
+    }
+    /// Set where the comments attached to expanded mapping and sequence
+    /// values are emitted.
+    pub fn aggregate_comment_placement(mut self, p: AggregateCommentPlacement) -> Self {
+        self.options = self.options.aggregate_comment_placement(p);
+        self
+    }
+    /// Set where the opening bracket of an expanded mapping or sequence
+    /// value is emitted relative to its key.
+    pub fn brace_placement(mut self, p: BracePlacement) -> Self {
+        self.options = self.options.brace_placement(p);
+        self
+    }
+    /// Set whether the trailing comments of a mapping's entries are padded
+    /// to start at the same column.
+    pub fn align_comments(mut self, b: bool) -> Self {
+        self.options = self.options.align_comments(b);
+        self
+    }
+
+    /// Set the allowable bases for integers.
+    /// Note: an allowed base that is _not_ allowed for literals will be
+    /// emitted as a quoted string.
+    pub fn bases(mut self, b: &[Base]) -> Self {
+        self.options = self.options.bases(b);
+        self
+    }
+    /// Set the allowable bases for integer literals.
+    /// Note: bases allowed as literals will be emitted directly into
+    /// the document (e.g. `0x1F`, `0b1010` or `0o755`).
+    pub fn literals(mut self, b: &[Base]) -> Self {
+        self.options = self.options.literals(b);
+        self
+    }
+    /// Set whether to obey strict numeric limits on integer values.
+    /// When true, any number larger than 2^53 in magnitude will be
+    /// emitted as a quoted string.
+    pub fn strict_numeric_limits(mut self, b: bool) -> Self {
+        self.options = self.options.strict_numeric_limits(b);
+        self
+    }
+    /// Set the insertion of digit group separators in integers.
+    pub fn digit_grouping(mut self, g: DigitGrouping) -> Self {
+        self.options = self.options.digit_grouping(g);
+        self
+    }
+    /// Set the encoding of non-finite float values.
+    pub fn non_finite(mut self, n: NonFinite) -> Self {
+        self.options = self.options.non_finite(n);
+        self
+    }
+    /// Set the style of multiline strings to be used in the document.
+    pub fn multiline(mut self, m: Multiline) -> Self {
+        self.options = self.options.multiline(m);
+        self
+    }
+    /// Set the number of indent levels by which the `'''` delimiters and
+    /// lines of Hjson multiline strings are indented relative to their key.
+    /// The default is one level.
+    pub fn multiline_indent(mut self, n: usize) -> Self {
+        self.options = self.options.multiline_indent(n);
+        self
+    }
+    /// Set whether the opening `'''` of Hjson multiline strings is written
+    /// on the key's line rather than on a line of its own.  Hjson removes
+    /// the leading whitespace of each line up to the column of the opening
+    /// delimiter, so the lines are indented at least to that column.
+    pub fn multiline_inline_open(mut self, b: bool) -> Self {
+        self.options = self.options.multiline_inline_open(b);
+        self
+    }
+    /// Set whether trailing whitespace in the lines of Hjson multiline
+    /// strings is written.  When disabled, it is removed from each line.
+    pub fn multiline_trailing_whitespace(mut self, b: bool) -> Self {
+        self.options = self.options.multiline_trailing_whitespace(b);
+        self
+    }
+    /// Set whether bare keys in mappings are allowed.
+    pub fn bare_keys(mut self, b: bool) -> Self {
+        self.options = self.options.bare_keys(b);
+        self
+    }
+    /// Set the characters permitted in bare keys.
+    pub fn bareword(mut self, b: Bareword) -> Self {
+        self.options = self.options.bareword(b);
+        self
+    }
+    /// Set whether the output is restricted to ASCII.  Non-ASCII characters
+    /// in keys and strings are written as `\u` escapes, with characters
+    /// beyond the Basic Multilingual Plane written as a UTF-16 surrogate
+    /// pair (e.g. `\ud83d\ude00`).  Hjson multiline and quoteless strings
+    /// cannot hold escapes, so such strings are quoted instead.
+    ///
+    /// When disabled (the default), only the control characters U+0000
+    /// through U+001F are escaped; everything else, including DEL and the
+    /// C1 controls, is written as raw UTF-8.
+    pub fn ascii_only(mut self, b: bool) -> Self {
+        self.options = self.options.ascii_only(b);
+        self
+    }
+    /// Set whether the line and paragraph separators (U+2028 and U+2029)
+    /// are written as `\u` escapes.  Both are legal in JSON strings but
+    /// terminate JavaScript string literals, so output which may be
+    /// embedded in a script should escape them.
+    pub fn escape_line_separators(mut self, b: bool) -> Self {
+        self.options = self.options.escape_line_separators(b);
+        self
+    }
+    /// Set how comments are rendered in ASCII-only output.
+    pub fn ascii_comments(mut self, a: AsciiComments) -> Self {
+        self.options = self.options.ascii_comments(a);
+        self
+    }
+    /// Set the dialect in which the document is written.
+    /// See `JsonOptions::dialect`.
+    pub fn dialect(mut self, d: Dialect) -> Self {
+        self.options = self.options.dialect(d);
+        self
+    }
+    /// Set whether expanded sequences and mappings emit a comma after their
+    /// last element.  Only json5 and hjson permit trailing commas.
+    pub fn trailing_comma(mut self, b: bool) -> Self {
+        self.options = self.options.trailing_comma(b);
+        self
+    }
+    /// Set whether to preserve the quoting style recorded on each string.
+    /// See `JsonOptions::preserve_style`.
+    pub fn preserve_style(mut self, b: bool) -> Self {
+        self.options = self.options.preserve_style(b);
+        self
+    }
+    /// Set whether strings recorded as unquoted may be emitted as hjson
+    /// quoteless strings.
+    pub fn quoteless(mut self, b: bool) -> Self {
+        self.options = self.options.quoteless(b);
+        self
+    }
+    /// Set the order in which mapping entries are emitted.
+    pub fn sort_keys(mut self, s: KeySort) -> Self {
+        self.options = self.options.sort_keys(s);
+        self
+    }
+    /// Set whether mapping entries whose value is null are omitted, along
+    /// with their comments.
+    pub fn skip_null(mut self, b: bool) -> Self {
+        self.options = self.options.skip_null(b);
+        self
+    }
+    /// Set the rendering of bytes values: a list of integers
+    /// (`BytesFormat::Standard`) or a string in the given format.  Bytes
+    /// annotated with a format are rendered as strings regardless.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.options = self.options.bytes_format(b);
+        self
+    }
+    /// Set the number of bytes emitted per row when bytes are rendered as a
+    /// list of integers.  When greater than one, each row is followed by a
+    /// comment giving its offset (if comments are permitted).
+    pub fn bytes_per_row(mut self, n: usize) -> Self {
+        self.options = self.options.bytes_per_row(n);
+        self
+    }
+    /// Set whether the offset comment of each row of bytes includes the
+    /// printable ASCII characters of the row, as in `hexdump -C`.
+    pub fn bytes_ascii(mut self, b: bool) -> Self {
+        self.options = self.options.bytes_ascii(b);
+        self
+    }
     /// Set whether or not to use compact form.
     /// Compact form eliminates comments, newlines and indentation.
     pub fn compact(mut self, b: bool) -> Self {
-        self.compact = b;
+        self.options = self.options.compact(b);
+        self
+    }
+    /// Set whether empty mappings and sequences are emitted on one line as
+    /// `{}` and `[]`, rather than with the closing symbol on its own line.
+    /// Enabled by default.
+    pub fn inline_empty(mut self, b: bool) -> Self {
+        self.options = self.options.inline_empty(b);
+        self
+    }
+    /// Emit the entries of a root mapping, or the elements of a root
+    /// sequence, without the enclosing braces or brackets and without
+    /// indentation, as a snippet for inclusion in another document.
+    pub fn snippet(mut self, b: bool) -> Self {
+        self.options = self.options.snippet(b);
+        self
+    }
+    /// Emit any mapping or sequence whose compact form, including its
+    /// indentation, fits within `n` columns on a single line.
+    pub fn compact_threshold(mut self, n: usize) -> Self {
+        self.options = self.options.compact_threshold(n);
+        self
+    }
+    /// Pack the elements of sequences of scalar values several to a line,
+    /// filling each line up to `n` columns including its indentation.
+    pub fn pack_width(mut self, n: usize) -> Self {
+        self.options = self.options.pack_width(n);
         self
     }
 
     pub fn color(mut self, c: ColorProfile) -> Self {
-        self.color = c;
+        self.options = self.options.color(c);
+        self
+    }
+    /// Colorize only the first `n` bytes of the output.
+    pub fn color_sampling(mut self, n: usize) -> Self {
+        self.options = self.options.color_sampling(n);
+        self
+    }
+    /// Fail rendering with `Error::OutputLimit` once the output, including
+    /// color escapes, would exceed `n` bytes.
+    pub fn max_output(mut self, n: usize) -> Self {
+        self.options = self.options.max_output(n);
+        self
+    }
+    /// Fail rendering with `Error::DepthLimit` when mappings and sequences
+    /// nest more than `n` deep, rather than recursing without bound.
+    pub fn max_depth(mut self, n: usize) -> Self {
+        self.options = self.options.max_depth(n);
+        self
+    }
+
+    /// Set the line ending style of the output.
+    pub fn newline(mut self, n: Newline) -> Self {
+        self.options = self.options.newline(n);
+        self
+    }
+
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.options = self.options.number_formatter(n);
+        self
+    }
+    /// Set the style in which float values are rendered.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.options = self.options.float_format(f);
+        self
+    }
+
+    /// Replace all formatting options of the document.
+    pub fn options(mut self, options: JsonOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Override the formatting options for the nodes at `path` and their
+    /// children.
+    ///
+    /// The path is a JSON Pointer through mapping keys and sequence
+    /// indices, where `*` matches any single key or index (e.g.
+    /// `/flash/partitions/*`).  The function `f` receives the options in
+    /// effect at the matching node and returns the options to use for it.
+    /// When several overrides match the same node, they are applied in the
+    /// order they were added.
+    pub fn override_at<F>(mut self, path: &str, f: F) -> Self
+    where
+        F: Fn(JsonOptions) -> JsonOptions + Send + Sync + 'static,
+    {
+        let path = segments(path).collect();
+        self.overrides.push((path, Arc::new(f)));
+        self
+    }
+
+    /// Set a callback which receives the progress of rendering after every
+    /// `interval` values are emitted and once rendering completes, so that
+    /// a tool exporting a large document can display a progress bar.
+    /// Progress is reported by `emit`, `emit_tee` and the `Emit` methods.
+    pub fn progress<F>(mut self, interval: usize, f: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.progress = Some((interval.max(1), Arc::new(f)));
         self
     }
 }
 
-impl fmt::Display for Json {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut emitter = JsonEmitter {
+impl Json {
+    fn emitter(&self) -> JsonEmitter<'_> {
+        JsonEmitter {
             level: 0,
-            indent: self.indent,
-            color: self.color,
-            comment: self.comment.clone(),
-            standard_comment: self.standard_comment,
-            bases: self.bases.clone(),
-            literals: self.literals.clone(),
-            strict_numeric_limits: self.strict_numeric_limits,
-            multiline: self.multiline,
-            bare_keys: self.bare_keys,
-            compact: self.compact,
+            opts: Cow::Borrowed(&self.options),
+            compact: self.options.common.compact,
+            color: self.options.common.color.clone(),
+            trial: false,
+            depth: 0,
+            overrides: &self.overrides,
+            path: Vec::new(),
+            quoteless_ok: false,
+            wrote_quoteless: false,
+            inside_comments: Vec::new(),
+            color_class: None,
+            entry: None,
+            value_column: None,
+            compact_widths: HashMap::new(),
+            brace_pending: false,
+            dropped: Vec::new(),
+            tracker: None,
+            progress: None,
+        }
+    }
+
+    /// Renders the document, returning the text and the comments omitted
+    /// under `CommentPolicy::HoistToSidecar`.
+    pub fn emit(&self) -> Result<(String, Vec<DroppedComment>)> {
+        let mut text = String::new();
+        let mut dropped = Vec::new();
+        guarded(&self.options.common, &mut text, |mut w| {
+            dropped = self.with_progress(&mut w, |emitter, mut w| {
+                emitter.emit_node_at(&mut w, &self.document)
+            })?;
+            Ok(())
+        })?;
+        Ok((text, dropped))
+    }
+
+    // Runs `f` with an emitter and a writer to `w` which report progress to
+    // the callback, if one was set, and returns the dropped comments.
+    fn with_progress<W, F>(&self, w: &mut W, f: F) -> Result<Vec<DroppedComment>>
+    where
+        W: fmt::Write,
+        F: FnOnce(&mut JsonEmitter<'_>, &mut dyn fmt::Write) -> Result<()>,
+    {
+        let mut emitter = self.emitter();
+        let (interval, callback) = match &self.progress {
+            Some(p) => p,
+            None => {
+                f(&mut emitter, w)?;
+                return Ok(emitter.dropped);
+            }
         };
-        emitter.emit_node(f, &self.document).map_err(|_| fmt::Error)
+        let reporter = Reporter::new(callback, *interval, &self.document);
+        emitter.progress = Some(&reporter);
+        f(&mut emitter, &mut ProgressWriter::new(w, &reporter))?;
+        reporter.finish();
+        Ok(emitter.dropped)
+    }
+
+    /// Renders the document along with a map from the path of each emitted
+    /// value to its location in the text, so that tools which post-process
+    /// or report errors on the output can point at exact locations.
+    pub fn emit_with_map(&self) -> Result<(String, SourceMap)> {
+        let mut text = String::new();
+        let tracker = Tracker::default();
+        let mut emitter = self.emitter();
+        emitter.tracker = Some(&tracker);
+        let mut tw = TrackingWriter::new(&mut text, &tracker);
+        guarded(&self.options.common, &mut tw, |mut w| {
+            emitter.emit_node_at(&mut w, &self.document)
+        })?;
+        Ok((text, tracker.into_map()))
+    }
+
+    /// Renders the document along with a Markdown changelog section headed
+    /// by `title` describing how it differs from `previous`, so that a
+    /// release can ship both the new file and human-readable notes.
+    /// See `Diff::changelog`.
+    pub fn emit_with_changelog(
+        &self,
+        previous: &Document,
+        title: &str,
+    ) -> Result<(String, String)> {
+        let (text, _) = self.emit()?;
+        let changelog = Diff::new(previous, &self.document)
+            .word_diff(true)
+            .changelog(title);
+        Ok((text, changelog))
+    }
+
+    /// Checks whether `input` is formatted: whether parsing it and emitting
+    /// the result with these options, in place of this document,
+    /// reproduces `input` byte for byte.  This is the check a CI job makes
+    /// to ask "is this file formatted?"; the result holds the formatted
+    /// text and a diff against it.  Fails if `input` cannot be parsed.
+    pub fn check(&self, input: &str) -> Result<FormatCheck> {
+        let json = Json {
+            document: Document::parse(input)?,
+            options: self.options.clone(),
+            overrides: self.overrides.clone(),
+            progress: None,
+        };
+        let (formatted, _) = json.emit()?;
+        Ok(FormatCheck::new(input, formatted))
+    }
+
+    // Renders the opening bracket of a sequence streamed by a
+    // `JsonStreamWriter`.
+    pub(crate) fn stream_open(&self) -> String {
+        self.options.common.color.aggregate.paint("[").to_string()
+    }
+
+    // Renders the `index`th element of a streamed sequence, preceded by the
+    // separator from the previous element.  Returns the text and whether
+    // the element was written as a quoteless string.
+    pub(crate) fn stream_element(
+        &self,
+        index: usize,
+        node: &Document,
+        after_quoteless: bool,
+    ) -> Result<(String, bool)> {
+        let mut text = String::new();
+        let mut emitter = self.emitter();
+        emitter.level = 1;
+        emitter.depth = 1;
+        let mut quoteless = false;
+        guarded(&self.options.common, &mut text, |mut w| {
+            if index > 0 && !after_quoteless {
+                write!(w, "{}", emitter.color.punctuation.paint(","))?;
+            }
+            if emitter.compact {
+                if index > 0 {
+                    write!(w, " ")?;
+                }
+            } else {
+                writeln!(w)?;
+                emitter.emit_indent(&mut w)?;
+            }
+            quoteless = emitter.emit_child(&mut w, index.to_string(), node, false)?;
+            Ok(())
+        })?;
+        Ok((text, quoteless))
+    }
+
+    // Renders the closing bracket of a streamed sequence of `count`
+    // elements.
+    pub(crate) fn stream_close(&self, count: usize, after_quoteless: bool) -> String {
+        let emitter = self.emitter();
+        let mut text = String::new();
+        if count > 0 && !after_quoteless && emitter.trailing_comma() {
+            text.push_str(&emitter.color.punctuation.paint(",").to_string());
+        }
+        if !emitter.compact && !(count == 0 && emitter.inline_empty()) {
+            text.push_str(match self.options.common.newline {
+                Newline::Lf => "\n",
+                Newline::CrLf => "\r\n",
+            });
+        }
+        text.push_str(&emitter.color.aggregate.paint("]").to_string());
+        text
+    }
+
+    /// Renders the document once, writing the output to each of `sinks` as
+    /// it is produced, so that the whole document is never held in memory.
+    ///
+    /// The emitter writes many small pieces, so unbuffered sinks such as
+    /// files should be wrapped in a `BufWriter`.  Each sink is flushed once
+    /// rendering completes.
+    pub fn emit_tee(&self, sinks: &mut [&mut dyn io::Write]) -> Result<()> {
+        write_sinks(sinks, |w| self.write_to(w))
+    }
+}
+
+impl Json {
+    /// Lays out the document as lines of classified tokens.  The layout
+    /// uses `\n` line endings regardless of the `newline` option, and the
+    /// `color_sampling` and `max_output` options do not apply.
+    pub fn layout(&self) -> Result<Layout> {
+        Layout::capture(|w| self.emitter().emit_node_at(w, &self.document))
+    }
+
+    /// Renders the document as HTML, marking up each token with a `<span>`
+    /// of the CSS class selected by `profile` rather than with color
+    /// escapes.  See `Layout::render_html`.
+    pub fn to_html(&self, profile: &HtmlProfile) -> Result<String> {
+        Ok(self.layout()?.to_html(profile))
+    }
+}
+
+// Adds `x` to an ordered set of options.
+fn insert<T: PartialEq>(set: &mut Vec<T>, x: T) {
+    if !set.contains(&x) {
+        set.push(x);
+    }
+}
+
+impl Emit for Json {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.options.common, w, |mut w| {
+            self.with_progress(&mut w, |emitter, mut w| {
+                emitter.emit_node_at(&mut w, &self.document)
+            })?;
+            Ok(())
+        })
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.options.common
     }
 }
 
 impl Document {
     /// Convert a `Document` to a JSON document.
+    /// See `JsonOptions::strict_default`.
     pub fn to_json(self) -> Json {
         Json {
             document: self,
-            indent: 2,
-            color: ColorProfile::default(),
-            comment: HashSet::new(),
-            standard_comment: CommentFormat::SlashSlash,
-            bases: HashSet::from([Base::Dec]),
-            literals: HashSet::from([Base::Dec]),
-            strict_numeric_limits: true,
-            multiline: Multiline::None,
-            bare_keys: false,
-            compact: false,
+            options: JsonOptions::strict_default(),
+            overrides: Vec::new(),
+            progress: None,
         }
     }
 
+    /// Convert a `Document` to a JSON with comments (JSONC) document.
+    /// See `JsonOptions::jsonc_default`.
+    pub fn to_jsonc(self) -> Json {
+        self.to_json().options(JsonOptions::jsonc_default())
+    }
+
     /// Convert a `Document` to a Json5 document.
-    /// A Json5 document allows `//` comments, hex literals,
-    /// multiline strings and bare keys.
+    /// See `JsonOptions::json5_default`.
     pub fn to_json5(self) -> Json {
-        self.to_json()
-            .comment(&[CommentFormat::Block, CommentFormat::SlashSlash])
-            .literals(&[Base::Hex])
-            .multiline(Multiline::Json5)
-            .bare_keys(true)
+        self.to_json().options(JsonOptions::json5_default())
+    }
+
+    /// Convert a `Document` to a Hjson document.
+    /// See `JsonOptions::hjson_default`.
+    pub fn to_hjson(self) -> Json {
+        self.to_json().options(JsonOptions::hjson_default())
+    }
+}
+
+// A mapping entry or sequence element with its comments placed for emitting.
+struct Item<'n> {
+    key: Option<&'n Document>,
+    value: &'n Document,
+    // The comments on the lines above the entry.
+    above: Vec<(&'n str, CommentFormat)>,
+    // The comments just inside the entry's aggregate value.
+    inside: Vec<(&'n str, CommentFormat)>,
+    // The comments after the value on its line.
+    after: Vec<(&'n str, CommentFormat)>,
+}
+
+impl<'n> From<MapEntry<'n>> for Item<'n> {
+    fn from(entry: MapEntry<'n>) -> Self {
+        Item {
+            key: Some(entry.key),
+            value: entry.value,
+            above: entry.comments,
+            inside: Vec::new(),
+            after: entry.trailing,
+        }
+    }
+}
+
+impl<'n> From<SeqItem<'n>> for Item<'n> {
+    fn from(item: SeqItem<'n>) -> Self {
+        Item {
+            key: None,
+            value: item.value,
+            above: item.comments,
+            inside: Vec::new(),
+            after: item.trailing,
+        }
+    }
+}
+
+// The emitter borrows its options from the `Json` being rendered, so that
+// constructing one is cheap and a `Json` may be rendered from several threads
+// at once.  The options are copied only where an override applies.
+struct JsonEmitter<'a> {
+    level: usize,
+    opts: Cow<'a, JsonOptions>,
+    // The `compact` and `color` options, which change while emitting compact
+    // and color-classed nodes.
+    compact: bool,
+    color: ColorProfile,
+    // Whether the emitter is measuring output rather than rendering it, in
+    // which case comments which cannot be emitted are dropped silently.
+    trial: bool,
+    // The number of mappings and sequences enclosing the current node.
+    depth: usize,
+    overrides: &'a [(Vec<String>, OptionsOverride)],
+    path: Vec<String>,
+    quoteless_ok: bool,
+    wrote_quoteless: bool,
+    inside_comments: Vec<(String, CommentFormat)>,
+    // The color class of the node being emitted, which is the class of its
+    // tokens.
+    color_class: Option<String>,
+    // The path segment of the aggregate entry being emitted, to which its
+    // comments are attached.
+    entry: Option<String>,
+    // The output column of the mapping value being emitted, if it follows
+    // its key on the same line.
+    value_column: Option<usize>,
+    // Set after the key of a mapping entry under
+    // `BracePlacement::NextLine`: the value writes the space after the
+    // colon, or, if it expands over several lines, the line break.
+    brace_pending: bool,
+    dropped: Vec<DroppedComment>,
+    // Records the location of each node in the output, if a source map was
+    // requested.
+    tracker: Option<&'a Tracker>,
+    // The width of the compact form of each aggregate measured under the
+    // `compact_threshold` option, by address, or `None` if it is too wide.
+    compact_widths: HashMap<usize, Option<usize>>,
+    // Counts the values emitted, if progress is reported.
+    progress: Option<&'a Reporter<'a>>,
+}
+
+impl<'a> JsonEmitter<'a> {
+    // Emits a node, applying any option overrides which match the current
+    // path, recording its location in the output and reporting progress.
+    fn emit_node_at<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        if !node.has_value() {
+            return self.emit_overridden(w, node);
+        }
+        let start = self.tracker.map(Tracker::position);
+        self.emit_overridden(w, node)?;
+        if let (Some(tracker), Some(start)) = (self.tracker, start) {
+            tracker.record(&self.path, start);
+        }
+        if let Some(reporter) = self.progress {
+            reporter.node();
+        }
+        Ok(())
+    }
+
+    // Emits a node, applying any option overrides which match the current path.
+    fn emit_overridden<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        let matching = self
+            .overrides
+            .iter()
+            .filter(|(p, _)| {
+                p.len() == self.path.len()
+                    && p.iter().zip(&self.path).all(|(p, s)| p == "*" || p == s)
+            })
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            return self.emit_node(w, node);
+        }
+        let saved = (self.opts.clone(), self.compact, self.color.clone());
+        for (_, f) in matching {
+            let mut opts = self.opts.as_ref().clone();
+            opts.common.compact = self.compact;
+            opts.common.color = self.color.clone();
+            let opts = f(opts);
+            self.compact = opts.common.compact;
+            self.color = opts.common.color.clone();
+            self.opts = Cow::Owned(opts);
+        }
+        let result = self.emit_node(w, node);
+        (self.opts, self.compact, self.color) = saved;
+        result
+    }
+
+    // Returns whether to emit a comma after the last element of an aggregate.
+    fn trailing_comma(&self) -> bool {
+        self.opts.trailing_comma && self.opts.dialect != Dialect::Json && !self.compact
+    }
+
+    // Returns whether a string recorded as single-quoted is emitted with
+    // single quotes.
+    fn single_quoted(&self, f: StrFormat) -> bool {
+        self.opts.preserve_style
+            && self.opts.dialect != Dialect::Json
+            && f == StrFormat::SingleQuoted
     }
 
-    /// Convert a `Document` to a Hjson document.
-    /// A Hjson document allows comments, multiline strings and bare keys.
-    /// Defaults to `#` comments, but hjson also supports `//` comments.
-    pub fn to_hjson(self) -> Json {
-        self.to_json()
-            .comment(&[
-                CommentFormat::Block,
-                CommentFormat::Hash,
-                CommentFormat::SlashSlash,
-            ])
-            .standard_comment(CommentFormat::Hash)
-            .multiline(Multiline::Hjson)
-            .bare_keys(true)
+    // Emits the child node named `segment` of the current node.  The child
+    // may only be written as a quoteless string if nothing else follows it
+    // on the same line; returns whether it was.
+    fn emit_child<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        segment: String,
+        node: &Document,
+        trailing_comment: bool,
+    ) -> Result<bool> {
+        self.path.push(segment);
+        let entry = self.entry.take();
+        self.quoteless_ok =
+            !trailing_comment && matches!(node, Document::String(_, _) | Document::StaticStr(_, _));
+        let result = self.emit_node_at(w, node);
+        self.quoteless_ok = false;
+        self.entry = entry;
+        self.path.pop();
+        result.map(|_| std::mem::take(&mut self.wrote_quoteless))
     }
-}
 
-struct JsonEmitter {
-    level: usize,
-    indent: usize,
-    color: ColorProfile,
-    comment: HashSet<CommentFormat>,
-    standard_comment: CommentFormat,
-    bases: HashSet<Base>,
-    literals: HashSet<Base>,
-    strict_numeric_limits: bool,
-    multiline: Multiline,
-    bare_keys: bool,
-    compact: bool,
-}
+    // Places the comments of an aggregate entry.  With trailing (or after)
+    // comment placement, a single leading comment is moved after the value,
+    // and with inside aggregate comment placement, the leading comments are
+    // moved inside the value.
+    fn arrange<'n>(&mut self, mut item: Item<'n>) -> Result<Item<'n>> {
+        if item.above.is_empty()
+            || (self.opts.comment_placement == CommentPlacement::Above
+                && self.opts.aggregate_comment_placement == AggregateCommentPlacement::Above)
+        {
+            return Ok(item);
+        }
+        let value = match item.value {
+            Document::ColorClass(_, v) | Document::Identified(_, v) | Document::Typed(_, v) => {
+                v.as_ref()
+            }
+            v => v,
+        };
+        let (aggregate, one_line_value) = match value {
+            Document::Mapping(m) | Document::Sequence(m) => {
+                let expanded = m.iter().any(Document::has_value)
+                    && !self.fits_compact(value, self.entry_column(item.key)?)?;
+                (expanded, !expanded)
+            }
+            Document::String(_, f) if f.is_multiline() => {
+                (false, self.opts.multiline == Multiline::None)
+            }
+            _ => (false, true),
+        };
+        let after = if aggregate {
+            match self.opts.aggregate_comment_placement {
+                AggregateCommentPlacement::Above => false,
+                AggregateCommentPlacement::Inside => {
+                    item.inside = std::mem::take(&mut item.above);
+                    return Ok(item);
+                }
+                AggregateCommentPlacement::After => true,
+            }
+        } else {
+            self.opts.comment_placement == CommentPlacement::Trailing && one_line_value
+        };
+        let single_line = matches!(&item.above[..], [(c, _)] if !c.contains('\n'));
+        if after && single_line && item.after.is_empty() {
+            item.after = std::mem::take(&mut item.above);
+        }
+        Ok(item)
+    }
 
-impl Default for JsonEmitter {
-    fn default() -> Self {
-        JsonEmitter {
-            level: 0,
-            indent: 2,
-            comment: HashSet::new(),
-            standard_comment: CommentFormat::SlashSlash,
-            color: ColorProfile::default(),
-            bases: HashSet::new(),
-            literals: HashSet::new(),
-            strict_numeric_limits: true,
-            multiline: Multiline::None,
-            bare_keys: false,
-            compact: false,
+    // Returns the column at which the value of an aggregate entry starts,
+    // following its key if it has one, as far as the `compact_threshold`
+    // option needs it.
+    fn entry_column(&self, key: Option<&Document>) -> Result<usize> {
+        let indent = self.level * self.opts.common.indent;
+        match key {
+            Some(key) if self.opts.compact_threshold.is_some() && !self.compact => {
+                let mut text = String::new();
+                self.trial().emit_entry_key(&mut text, key)?;
+                Ok(indent + str_width(&text) + 2)
+            }
+            _ => Ok(indent),
         }
     }
-}
 
-impl JsonEmitter {
-    fn emit_node<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+    fn emit_node<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        // Aggregates decide for themselves whether they expand, and wrappers
+        // leave the decision to the node they wrap.
+        if matches!(
+            node,
+            Document::String(_, _)
+                | Document::StaticStr(_, _)
+                | Document::Boolean(_)
+                | Document::Int(_)
+                | Document::Float(_)
+                | Document::Bytes(_)
+                | Document::Null
+        ) {
+            self.emit_brace_separator(w, false)?;
+        }
         match node {
             Document::Comment(c, f) => self.emit_comment_newline(w, c, f),
             Document::String(v, f) => self.emit_string(w, v.as_str(), *f),
@@ -204,11 +1327,13 @@ impl JsonEmitter {
             Document::Boolean(v) => self.emit_boolean(w, *v),
             Document::Int(v) => self.emit_int(w, v),
             Document::Float(v) => self.emit_float(w, *v),
-            Document::Mapping(m) => self.emit_mapping(w, m),
-            Document::Sequence(s) => self.emit_sequence(w, s),
+            Document::Mapping(_) | Document::Sequence(_) => self.emit_aggregate(w, node),
             Document::Bytes(v) => self.emit_bytes(w, v),
             Document::Null => self.emit_null(w),
+            Document::Absent => Err(Error::AbsentValue),
             Document::Compact(d) => self.emit_compact(w, d),
+            Document::ColorClass(c, d) => self.emit_color_class(w, c, d),
+            Document::Identified(_, d) | Document::Typed(_, d) => self.emit_node(w, d),
             Document::Fragment(ds) => {
                 match &ds[..] {
                     // Currently, an enum unit-variant is the only place in the serializer where a
@@ -216,7 +1341,7 @@ impl JsonEmitter {
                     // we want to emit the variant name followed by the comment on the same line.
                     [n, Document::Comment(c, f)] => {
                         self.emit_node(w, n)?;
-                        if !self.compact && !self.comment.is_empty() {
+                        if !self.compact && !self.opts.comment.is_empty() {
                             write!(w, " ")?;
                             self.emit_comment(w, c, f)?;
                         }
@@ -238,7 +1363,119 @@ impl JsonEmitter {
         }
     }
 
-    fn emit_compact<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+    // Returns an emitter in the current state which renders without color,
+    // for measuring the width of output.
+    fn trial(&self) -> JsonEmitter<'a> {
+        JsonEmitter {
+            level: self.level,
+            opts: self.opts.clone(),
+            compact: self.compact,
+            color: ColorProfile::default(),
+            trial: true,
+            depth: self.depth,
+            overrides: self.overrides,
+            path: self.path.clone(),
+            quoteless_ok: false,
+            wrote_quoteless: false,
+            inside_comments: Vec::new(),
+            color_class: None,
+            entry: None,
+            value_column: None,
+            compact_widths: HashMap::new(),
+            brace_pending: false,
+            dropped: Vec::new(),
+            tracker: None,
+            progress: None,
+        }
+    }
+
+    // Returns the width of each entry's `key: value,` text where a trailing
+    // comment follows it on the same line, so that the comments of a
+    // mapping can be aligned.
+    fn comment_columns(
+        &mut self,
+        entries: &[&Document],
+        last: usize,
+    ) -> Result<Vec<Option<usize>>> {
+        let mut columns = vec![None; entries.len()];
+        if !self.opts.align_comments || self.compact || self.opts.comment.is_empty() {
+            return Ok(columns);
+        }
+        for (i, entry) in entries.iter().enumerate().take(last + 1) {
+            let entry = match MapEntry::new(entry) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let (key, value) = (entry.key, entry.value);
+            if self.arrange(entry.into())?.after.is_empty() {
+                continue;
+            }
+            let mut trial = self.trial();
+            let mut text = String::new();
+            let key = trial.emit_entry_key(&mut text, key)?;
+            text.push_str(": ");
+            trial.emit_child(&mut text, key, value, true)?;
+            if i != last || self.trailing_comma() {
+                text.push(',');
+            }
+            if !text.contains('\n') {
+                columns[i] = Some(str_width(&text));
+            }
+        }
+        Ok(columns)
+    }
+
+    // Returns whether an aggregate starting at `column` should be compacted
+    // under the `compact_threshold` option.
+    fn fits_compact(&mut self, node: &Document, column: usize) -> Result<bool> {
+        let threshold = match self.opts.compact_threshold {
+            Some(t) if !self.compact => t,
+            _ => return Ok(false),
+        };
+        let address = node as *const Document as usize;
+        let width = match self.compact_widths.get(&address) {
+            Some(width) => *width,
+            None => {
+                // Measure the compact rendering without color escapes.
+                let mut trial = self.trial();
+                trial.level = 0;
+                trial.compact = true;
+                let width = line_width(threshold, |w| trial.emit_node(w, node))?.filter(|_| {
+                    self.opts.comment.is_empty() || !node.iter().any(|n| n.comment().is_some())
+                });
+                self.compact_widths.insert(address, width);
+                width
+            }
+        };
+        Ok(matches!(width, Some(width) if column + width <= threshold))
+    }
+
+    fn emit_aggregate<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        match self.opts.common.max_depth {
+            Some(n) if self.depth >= n => return Err(Error::DepthLimit(n)),
+            _ => {}
+        }
+        let column = self
+            .value_column
+            .unwrap_or(self.level * self.opts.common.indent);
+        let compact = self.fits_compact(node, column)?;
+        self.compact_widths
+            .remove(&(node as *const Document as usize));
+        if compact {
+            self.emit_brace_separator(w, false)?;
+            return self.emit_compact(w, node);
+        }
+        self.depth += 1;
+        let result = match node {
+            Document::Mapping(m) => self.emit_mapping(w, m),
+            Document::Sequence(s) => self.emit_sequence(w, s),
+            _ => unreachable!(),
+        };
+        self.depth -= 1;
+        result
+    }
+
+    fn emit_compact<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<()> {
         let compact = self.compact;
         self.compact = true;
         self.emit_node(w, node)?;
@@ -246,9 +1483,33 @@ impl JsonEmitter {
         Ok(())
     }
 
-    fn emit_bytes<W: fmt::Write>(&mut self, w: &mut W, bytes: &[u8]) -> Result<()> {
+    fn emit_color_class<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        class: &str,
+        node: &Document,
+    ) -> Result<()> {
+        let saved = (
+            self.color.clone(),
+            self.color_class.replace(class.to_string()),
+        );
+        self.color = self.color.for_class(class);
+        let result = self.emit_node(w, node);
+        (self.color, self.color_class) = saved;
+        result
+    }
+
+    fn emit_bytes<W: TokenSink>(&mut self, w: &mut W, bytes: &[u8]) -> Result<()> {
+        let format = self.opts.bytes_format;
+        if let Some(string) = hexdump::to_string(bytes, format) {
+            return self.emit_string(w, &string, format.str_format());
+        }
+        if self.opts.bytes_per_row > 1 && !self.compact {
+            return self.emit_bytes_rows(w, bytes);
+        }
         self.level += 1;
-        self.writeln(w, &self.color.aggregate.paint("[").to_string())?;
+        self.token(w, TokenClass::Aggregate, "[")?;
+        self.writeln(w, "")?;
         self.emit_indent(w)?;
         for (i, value) in bytes.iter().enumerate() {
             if i > 0 {
@@ -260,173 +1521,439 @@ impl JsonEmitter {
         self.writeln(w, "")?;
         self.level -= 1;
         self.emit_indent(w)?;
-        write!(w, "{}", &self.color.aggregate.paint("]"))?;
+        self.token(w, TokenClass::Aggregate, "]")?;
         Ok(())
     }
 
-    // TODO: Can this function be rewritten to be less complex?
-    fn emit_sequence<W: fmt::Write>(&mut self, w: &mut W, sequence: &[Document]) -> Result<()> {
+    // Emits bytes as a list of integers in rows of `bytes_per_row`, each
+    // followed by an offset comment.
+    fn emit_bytes_rows<W: TokenSink>(&mut self, w: &mut W, bytes: &[u8]) -> Result<()> {
+        let per_row = self.opts.bytes_per_row;
+        let hex = self.opts.literals.contains(&Base::Hex);
+        // The offset comments follow their rows, unless only block comments
+        // are permitted, in which case they precede them.
+        let format = self.opts.comment_format(CommentFormat::Standard);
+        let format = [format, CommentFormat::SlashSlash, CommentFormat::Hash]
+            .into_iter()
+            .find(|f| *f != CommentFormat::Block && self.opts.comment.contains(f))
+            .unwrap_or(format);
+        let above = format == CommentFormat::Block;
+        // The width of a full row, including its comma.
+        let row_width = per_row * if hex { 6 } else { 5 } - 1;
         self.level += 1;
-        self.writeln(w, &self.color.aggregate.paint("[").to_string())?;
+        self.token(w, TokenClass::Aggregate, "[")?;
+        self.writeln(w, "")?;
+        let rows = (bytes.len() + per_row - 1) / per_row;
+        for (i, chunk) in bytes.chunks(per_row).enumerate() {
+            self.emit_indent(w)?;
+            let mut row = chunk
+                .iter()
+                .map(|b| {
+                    if hex {
+                        format!("0x{:02X}", b)
+                    } else {
+                        format!("{:>3}", b)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            if i + 1 < rows {
+                row.push(',');
+            }
+            if self.opts.comment.is_empty() {
+                self.writeln(w, &row)?;
+                continue;
+            }
+            let mut comment = format!("{:08x}", i * per_row);
+            if self.opts.bytes_ascii {
+                let ascii = chunk
+                    .iter()
+                    .map(|&b| match b {
+                        0x20..=0x7e => b as char,
+                        _ => '.',
+                    })
+                    .collect::<String>();
+                comment = format!("{}  |{}|", comment, ascii);
+            }
+            if above {
+                self.emit_comment(w, &comment, &format)?;
+                self.writeln(w, &row)?;
+            } else {
+                write!(w, "{:<1$} ", row, row_width)?;
+                self.emit_comment(w, &comment, &format)?;
+                self.writeln(w, "")?;
+            }
+        }
+        self.level -= 1;
+        self.emit_indent(w)?;
+        self.token(w, TokenClass::Aggregate, "]")?;
+        Ok(())
+    }
+
+    fn emit_sequence<W: TokenSink>(&mut self, w: &mut W, sequence: &[Document]) -> Result<()> {
+        self.value_column = None;
+        let snippet = self.snippet();
+        if sequence.is_empty() && (snippet || self.inline_empty()) {
+            if !snippet {
+                self.emit_brace_separator(w, false)?;
+                self.token(w, TokenClass::Aggregate, "[]")?;
+            }
+            return Ok(());
+        }
+        if let Some(width) = self.opts.pack_width {
+            if !self.compact && !snippet && sequence.iter().all(|n| self.is_packable(n)) {
+                self.emit_brace_separator(w, false)?;
+                return self.emit_sequence_packed(w, sequence, width);
+            }
+        }
+        if !snippet {
+            self.emit_brace_separator(w, true)?;
+            self.level += 1;
+            self.token(w, TokenClass::Aggregate, "[")?;
+            self.writeln(w, "")?;
+        }
         if !sequence.is_empty() {
             self.emit_indent(w)?;
         }
+        self.emit_inside_comments(w)?;
         let last = Document::last_value_index(sequence);
         let mut need_eol = false;
+        let mut index = 0;
         for (i, value) in sequence.iter().enumerate() {
+            self.entry = Some(index.to_string());
             if i > 0 && need_eol {
                 write!(w, "{}", if self.compact { " " } else { "\n" })?;
-                if i <= last || !self.comment.is_empty() {
+                if i <= last || !self.opts.comment.is_empty() {
                     self.emit_indent(w)?;
                 }
-                need_eol = false;
             }
-            if let Document::Fragment(nodes) = value {
-                let mut val_done = false;
-                for node in nodes {
-                    if let Some((c, f)) = node.comment() {
-                        if val_done && need_eol {
-                            write!(w, " ")?;
-                        }
-                        need_eol = self.emit_comment(w, c, f)?;
-                        if need_eol && !val_done {
-                            writeln!(w)?;
-                            self.emit_indent(w)?;
-                        }
-                        need_eol |= i < last;
-                        continue;
-                    }
-                    if !val_done {
-                        self.emit_node(w, node)?;
-                        if i != last {
-                            write!(w, "{}", &self.color.punctuation.paint(","))?;
-                        }
-                        val_done = true;
-                        need_eol = true;
-                    } else {
-                        return Err(Error::StructureError("Comment", node.variant()));
-                    }
-                }
-            } else {
-                self.emit_node(w, value)?;
-                if i != last {
-                    write!(w, "{}", &self.color.punctuation.paint(","))?;
+            let item = match SeqItem::new(value) {
+                Ok(item) => self.arrange(item.into())?,
+                Err(_) => {
+                    need_eol = self.emit_loose_comments(w, value)?;
+                    continue;
                 }
-                need_eol = true;
+            };
+            self.inside_comments = Self::comment_text(&item.inside);
+            self.emit_above_comments(w, &item.above)?;
+            let trailing = !item.after.is_empty();
+            let quoteless = self.emit_child(w, index.to_string(), item.value, trailing)?;
+            index += 1;
+            if (i != last || self.trailing_comma()) && !quoteless {
+                self.token(w, TokenClass::Punctuation, ",")?;
             }
+            self.emit_after_comments(w, &item.after, 0)?;
+            need_eol = true;
+        }
+        self.entry = None;
+        if snippet {
+            return Ok(());
         }
         if need_eol {
             self.writeln(w, "")?;
         }
         self.level -= 1;
         self.emit_indent(w)?;
-        write!(w, "{}", &self.color.aggregate.paint("]"))?;
+        self.token(w, TokenClass::Aggregate, "]")?;
+        Ok(())
+    }
+
+    // Returns whether a sequence element may share its line with others
+    // under the `pack_width` option.
+    fn is_packable(&self, node: &Document) -> bool {
+        match node {
+            Document::Int(_) | Document::Float(_) | Document::Boolean(_) | Document::Null => true,
+            Document::String(_, f) | Document::StaticStr(_, f) => {
+                !self.opts.quoteless && !f.is_multiline()
+            }
+            Document::ColorClass(_, n) | Document::Identified(_, n) | Document::Typed(_, n) => {
+                self.is_packable(n)
+            }
+            _ => false,
+        }
+    }
+
+    fn emit_sequence_packed<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        sequence: &[Document],
+        width: usize,
+    ) -> Result<()> {
+        self.level += 1;
+        self.token(w, TokenClass::Aggregate, "[")?;
+        self.writeln(w, "")?;
+        let indent = self.level * self.opts.common.indent;
+        let mut column = 0;
+        for (i, value) in sequence.iter().enumerate() {
+            // Measure the element without color escapes.
+            let mut plain = String::new();
+            self.trial()
+                .emit_child(&mut plain, i.to_string(), value, false)?;
+            let mut len = str_width(&plain);
+            if i + 1 != sequence.len() || self.trailing_comma() {
+                len += 1;
+            }
+            if column > 0 && column + 1 + len > width {
+                writeln!(w)?;
+                column = 0;
+            }
+            if column == 0 {
+                self.emit_indent(w)?;
+                column = indent;
+            } else {
+                write!(w, " ")?;
+                column += 1;
+            }
+            self.emit_child(w, i.to_string(), value, false)?;
+            if i + 1 != sequence.len() || self.trailing_comma() {
+                self.token(w, TokenClass::Punctuation, ",")?;
+            }
+            column += len;
+        }
+        if !sequence.is_empty() {
+            writeln!(w)?;
+        }
+        self.level -= 1;
+        self.emit_indent(w)?;
+        self.token(w, TokenClass::Aggregate, "]")?;
         Ok(())
     }
 
-    fn emit_key<W: fmt::Write>(&mut self, w: &mut W, s: &str) -> Result<()> {
-        if self.bare_keys && is_legal_bareword(s) {
-            write!(w, "{}", self.color.key.paint(s))?
+    fn emit_key<W: TokenSink>(&mut self, w: &mut W, s: &str, f: StrFormat) -> Result<()> {
+        let quote = if self.single_quoted(f) { "'" } else { "\"" };
+        let bare =
+            self.opts.bare_keys && is_bareword(s, self.opts.bareword) && !self.needs_escape(s);
+        if bare && (!self.opts.preserve_style || f == StrFormat::Unquoted) {
+            self.token(w, TokenClass::BareKey, s)?
         } else {
-            write!(
-                w,
-                "{}{}{}",
-                self.color.punctuation.paint("\""),
-                self.color.key.paint(s),
-                self.color.punctuation.paint("\"")
-            )?
+            self.token(w, TokenClass::Punctuation, quote)?;
+            self.emit_run(w, s, TokenClass::Key)?;
+            self.token(w, TokenClass::Punctuation, quote)?;
         }
         Ok(())
     }
 
-    // TODO: Can this function be rewritten to be less complex?
-    fn emit_mapping<W: fmt::Write>(&mut self, w: &mut W, mapping: &[Document]) -> Result<()> {
-        self.level += 1;
-        self.writeln(w, &self.color.aggregate.paint("{").to_string())?;
-        if !mapping.is_empty() {
+    // Emits the key of a mapping entry and returns its text.
+    fn emit_entry_key<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<String> {
+        let key = match node {
+            Document::String(s, f) => {
+                self.emit_key(w, s.as_str(), *f)?;
+                s.clone()
+            }
+            Document::StaticStr(s, f) => {
+                self.emit_key(w, s, *f)?;
+                s.to_string()
+            }
+            Document::Boolean(v) => self.emit_quoted_key(w, v.to_string())?,
+            Document::Int(v) => self.emit_quoted_key(w, v.to_string())?,
+            Document::Float(v) => self.emit_quoted_key(w, v.to_string())?,
+            Document::Identified(_, k) | Document::Typed(_, k) => return self.emit_entry_key(w, k),
+            Document::Comment(_, _) => return Err(Error::KeyTypeError("comment")),
+            Document::Mapping(_) => return Err(Error::KeyTypeError("mapping")),
+            Document::Sequence(_) => return Err(Error::KeyTypeError("sequence")),
+            Document::Bytes(_) => return Err(Error::KeyTypeError("bytes")),
+            Document::Compact(_) => return Err(Error::KeyTypeError("compact")),
+            Document::ColorClass(_, _) => return Err(Error::KeyTypeError("color class")),
+            Document::Fragment(_) => return Err(Error::KeyTypeError("fragment")),
+            Document::Null => return Err(Error::KeyTypeError("null")),
+            Document::Absent => return Err(Error::AbsentValue),
+        };
+        Ok(key)
+    }
+
+    // Emits a non-string key, which is always quoted.
+    fn emit_quoted_key<W: TokenSink>(&mut self, w: &mut W, key: String) -> Result<String> {
+        self.token(w, TokenClass::Punctuation, "\"")?;
+        self.token(w, TokenClass::Key, &key)?;
+        self.token(w, TokenClass::Punctuation, "\"")?;
+        Ok(key)
+    }
+
+    fn emit_mapping<W: TokenSink>(&mut self, w: &mut W, mapping: &[Document]) -> Result<()> {
+        self.value_column = None;
+        let mut entries = self.opts.common.sort_keys.order(mapping);
+        if self.opts.common.skip_null {
+            entries.retain(|e| !e.is_null_entry());
+        }
+        let snippet = self.snippet();
+        if entries.is_empty() && (snippet || self.inline_empty()) {
+            if !snippet {
+                self.emit_brace_separator(w, false)?;
+                self.token(w, TokenClass::Aggregate, "{}")?;
+            }
+            return Ok(());
+        }
+        if !snippet {
+            self.emit_brace_separator(w, true)?;
+            self.level += 1;
+            self.token(w, TokenClass::Aggregate, "{")?;
+            self.writeln(w, "")?;
+        }
+        if !entries.is_empty() {
             self.emit_indent(w)?;
         }
-        let last = Document::last_value_index(mapping);
+        self.emit_inside_comments(w)?;
+        let last = entries
+            .iter()
+            .rposition(|e| e.has_value())
+            .unwrap_or(entries.len());
+        let columns = self.comment_columns(&entries, last)?;
+        let align = columns.iter().flatten().max().copied().unwrap_or(0);
         let mut need_eol = false;
-        for (i, frag) in mapping.iter().enumerate() {
-            let nodes = frag.fragments()?;
+        for (i, node) in entries.into_iter().enumerate() {
             if i > 0 && need_eol {
                 write!(w, "{}", if self.compact { " " } else { "\n" })?;
-                if i <= last || !self.comment.is_empty() {
+                if i <= last || !self.opts.comment.is_empty() {
                     self.emit_indent(w)?;
                 }
-                need_eol = false;
-            }
-            let mut key_done = i > last;
-            let mut val_done = i > last;
-            for node in nodes {
-                if let Some((c, f)) = node.comment() {
-                    if val_done && need_eol {
-                        write!(w, " ")?;
-                    }
-                    need_eol = self.emit_comment(w, c, f)?;
-                    if need_eol && !key_done {
-                        writeln!(w)?;
-                        self.emit_indent(w)?;
-                    }
-                    need_eol |= i < last;
+            }
+            let entry = match MapEntry::new(node) {
+                Ok(entry) => entry,
+                Err(_) => {
+                    self.entry = None;
+                    need_eol = self.emit_loose_comments(w, node)?;
                     continue;
                 }
-                if !key_done {
-                    match node {
-                        Document::String(s, _) => self.emit_key(w, s.as_str())?,
-                        Document::StaticStr(s, _) => self.emit_key(w, s)?,
-                        Document::Boolean(v) => write!(
-                            w,
-                            "{}{}{}",
-                            self.color.punctuation.paint("\""),
-                            self.color.key.paint(format!("{}", v)),
-                            self.color.punctuation.paint("\"")
-                        )?,
-                        Document::Int(v) => write!(
-                            w,
-                            "{}{}{}",
-                            self.color.punctuation.paint("\""),
-                            self.color.key.paint(format!("{}", v)),
-                            self.color.punctuation.paint("\"")
-                        )?,
-                        Document::Float(v) => write!(
-                            w,
-                            "{}{}{}",
-                            self.color.punctuation.paint("\""),
-                            self.color.key.paint(format!("{}", v)),
-                            self.color.punctuation.paint("\"")
-                        )?,
-                        Document::Comment(_, _) => return Err(Error::KeyTypeError("comment")),
-                        Document::Mapping(_) => return Err(Error::KeyTypeError("mapping")),
-                        Document::Sequence(_) => return Err(Error::KeyTypeError("sequence")),
-                        Document::Bytes(_) => return Err(Error::KeyTypeError("bytes")),
-                        Document::Compact(_) => return Err(Error::KeyTypeError("compact")),
-                        Document::Fragment(_) => return Err(Error::KeyTypeError("fragment")),
-                        Document::Null => return Err(Error::KeyTypeError("null")),
-                    };
-                    write!(w, "{}", &self.color.punctuation.paint(": "))?;
-                    key_done = true;
-                } else if !val_done {
-                    self.emit_node(w, node)?;
-                    if i != last {
-                        write!(w, "{}", &self.color.punctuation.paint(","))?;
-                    }
-                    val_done = true;
-                    need_eol = true;
-                }
+            };
+            let key_node = entry.key;
+            self.entry = Some(key_node.as_str().map(str::to_string).unwrap_or_default());
+            let item = self.arrange(entry.into())?;
+            self.inside_comments = Self::comment_text(&item.inside);
+            self.emit_above_comments(w, &item.above)?;
+            let key = self.emit_entry_key(w, key_node)?;
+            if self.opts.brace_placement == BracePlacement::NextLine && !self.compact {
+                self.token(w, TokenClass::Punctuation, ":")?;
+                self.brace_pending = true;
+            } else {
+                self.token(w, TokenClass::Punctuation, ": ")?;
             }
+            let threshold = self.opts.compact_threshold.is_some();
+            if (self.opts.multiline_inline_open || threshold) && !self.compact {
+                let mut text = String::new();
+                self.trial().emit_entry_key(&mut text, key_node)?;
+                let indent = self.level * self.opts.common.indent;
+                self.value_column = Some(indent + str_width(&text) + 2);
+            }
+            let trailing = !item.after.is_empty();
+            let quoteless = self.emit_child(w, key, item.value, trailing)?;
+            self.value_column = None;
+            if (i != last || self.trailing_comma()) && !quoteless {
+                self.token(w, TokenClass::Punctuation, ",")?;
+            }
+            let pad = columns[i].map_or(0, |c| align - c);
+            self.emit_after_comments(w, &item.after, pad)?;
+            need_eol = true;
+        }
+        self.entry = None;
+        if snippet {
+            return Ok(());
         }
         if need_eol {
             self.writeln(w, "")?;
         }
         self.level -= 1;
         self.emit_indent(w)?;
-        write!(w, "{}", &self.color.aggregate.paint("}"))?;
+        self.token(w, TokenClass::Aggregate, "}")?;
+        Ok(())
+    }
+
+    // Whether the aggregate being emitted is the root of a snippet, whose
+    // braces or brackets are omitted.
+    fn snippet(&self) -> bool {
+        self.opts.snippet && self.depth == 1
+    }
+
+    // Writes what separates the key of a mapping entry from its value under
+    // `BracePlacement::NextLine`: a line break before a value which expands
+    // over several lines, and a space before any other.
+    fn emit_brace_separator<W: TokenSink>(&mut self, w: &mut W, expanded: bool) -> Result<()> {
+        if !std::mem::take(&mut self.brace_pending) {
+            return Ok(());
+        }
+        if expanded && !self.compact {
+            writeln!(w)?;
+            self.emit_indent(w)
+        } else {
+            write!(w, " ")?;
+            Ok(())
+        }
+    }
+
+    // Whether an empty aggregate may be emitted on one line: comments placed
+    // inside it still need lines of their own.
+    fn inline_empty(&self) -> bool {
+        self.opts.inline_empty && self.inside_comments.is_empty()
+    }
+
+    // Emits the comments of an entry placed just inside its aggregate value.
+    fn emit_inside_comments<W: TokenSink>(&mut self, w: &mut W) -> Result<()> {
+        for (c, f) in std::mem::take(&mut self.inside_comments) {
+            self.emit_comment_newline(w, &c, &f)?;
+        }
+        Ok(())
+    }
+
+    fn comment_text(comments: &[(&str, CommentFormat)]) -> Vec<(String, CommentFormat)> {
+        comments.iter().map(|(c, f)| (c.to_string(), *f)).collect()
+    }
+
+    // Emits the comments placed above an entry, each followed by a line
+    // break.
+    fn emit_above_comments<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        comments: &[(&str, CommentFormat)],
+    ) -> Result<()> {
+        for (c, f) in comments {
+            if self.drops_comments() {
+                self.drop_comment(c)?;
+            } else if self.emit_comment(w, c, f)? {
+                writeln!(w)?;
+                self.emit_indent(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Emits the comments placed after an entry's value on its line, the
+    // first `pad` columns further out to align it with its neighbours'.
+    fn emit_after_comments<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        comments: &[(&str, CommentFormat)],
+        mut pad: usize,
+    ) -> Result<()> {
+        for (c, f) in comments {
+            if self.drops_comments() {
+                self.drop_comment(c)?;
+                continue;
+            }
+            write!(w, "{:1$}", "", std::mem::take(&mut pad) + 1)?;
+            self.emit_comment(w, c, f)?;
+        }
         Ok(())
     }
 
-    fn emit_comment_newline<W: fmt::Write>(
+    // Emits the comments of an aggregate's node which holds no entry, such
+    // as those following its last entry, each on a line of its own.
+    // Returns whether a line break is needed after them.
+    fn emit_loose_comments<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<bool> {
+        let mut need_eol = false;
+        for (c, f) in comments_of(node) {
+            if self.drops_comments() {
+                self.drop_comment(c)?;
+                continue;
+            }
+            if need_eol {
+                writeln!(w)?;
+                self.emit_indent(w)?;
+            }
+            need_eol = self.emit_comment(w, c, &f)?;
+        }
+        Ok(need_eol)
+    }
+
+    fn emit_comment_newline<W: TokenSink>(
         &mut self,
         w: &mut W,
         comment: &str,
@@ -439,38 +1966,74 @@ impl JsonEmitter {
         Ok(())
     }
 
-    fn emit_comment<W: fmt::Write>(
+    // Returns the path of the node the current comment is attached to.
+    fn comment_path(&self) -> String {
+        pointer(&self.path.iter().chain(&self.entry).collect::<Vec<_>>())
+    }
+
+    // Applies the comment policy to a comment which cannot be emitted.
+    fn drop_comment(&mut self, comment: &str) -> Result<()> {
+        if self.trial || self.opts.comment_policy == CommentPolicy::Drop {
+            return Ok(());
+        }
+        let path = self.comment_path();
+        if self.opts.comment_policy == CommentPolicy::Error {
+            return Err(Error::CommentDropped(path, comment.to_string()));
+        }
+        self.dropped.push(DroppedComment {
+            path,
+            comment: comment.to_string(),
+        });
+        Ok(())
+    }
+
+    // Whether comments are dropped rather than emitted.
+    fn drops_comments(&self) -> bool {
+        self.compact || self.opts.comment.is_empty()
+    }
+
+    fn emit_comment<W: TokenSink>(
         &mut self,
         w: &mut W,
         comment: &str,
         format: &CommentFormat,
     ) -> Result<bool> {
-        if self.compact || self.comment.is_empty() {
+        if self.drops_comments() {
+            self.drop_comment(comment)?;
             return Ok(false);
         }
-        let format = *self.comment.get(format).unwrap_or(&self.standard_comment);
-        let leader = match format {
-            CommentFormat::SlashSlash | CommentFormat::Standard => "//",
-            CommentFormat::Hash => "#",
-            CommentFormat::Block => " *",
-        };
+        if self.opts.common.ascii_comments == AsciiComments::Reject && !comment.is_ascii() {
+            return Err(Error::NonAsciiComment(
+                self.comment_path(),
+                comment.to_string(),
+            ));
+        }
+        let format = self.opts.comment_format(*format);
+        let leader = format.leader();
         if format == CommentFormat::Block {
             writeln!(w, "/*")?;
             self.emit_indent(w)?;
         }
+        let comment = if self.opts.ascii_only {
+            self.opts.common.ascii_comments.apply(comment)
+        } else {
+            Cow::Borrowed(comment)
+        };
         for (i, line) in comment.split('\n').enumerate() {
             if i > 0 {
                 writeln!(w)?;
                 self.emit_indent(w)?;
             }
+            // Keep the text of a block comment from ending it early.
+            let line = if format == CommentFormat::Block {
+                Cow::Owned(line.replace("*/", "* /"))
+            } else {
+                Cow::Borrowed(line)
+            };
             if line.is_empty() {
-                write!(w, "{}", self.color.comment.paint(leader))?;
+                self.token(w, TokenClass::Comment, &leader)?;
             } else {
-                write!(
-                    w,
-                    "{}",
-                    self.color.comment.paint(format!("{} {}", leader, line))
-                )?;
+                self.token(w, TokenClass::Comment, &format!("{} {}", leader, line))?;
             }
         }
         if format == CommentFormat::Block {
@@ -480,57 +2043,144 @@ impl JsonEmitter {
         Ok(true)
     }
 
-    fn emit_string<W: fmt::Write>(&mut self, w: &mut W, value: &str, f: StrFormat) -> Result<()> {
-        if self.multiline != Multiline::None && f == StrFormat::Multiline {
+    fn emit_string<W: TokenSink>(&mut self, w: &mut W, value: &str, f: StrFormat) -> Result<()> {
+        // Hjson multiline and quoteless strings cannot express escapes.
+        let escapable = !self.needs_escape(value);
+        if self.opts.multiline != Multiline::None
+            && f.is_multiline()
+            && (escapable || self.opts.multiline == Multiline::Json5)
+        {
             self.emit_string_multiline(w, value)
+        } else if f == StrFormat::Unquoted
+            && self.opts.quoteless
+            && self.quoteless_ok
+            && !self.compact
+            && escapable
+            && is_unambiguous_value(value, Dialect::Hjson)
+        {
+            self.wrote_quoteless = true;
+            self.token(w, TokenClass::String, &value)?;
+            Ok(())
+        } else if self.single_quoted(f) {
+            self.emit_string_quoted(w, value, b'\'')
         } else {
-            self.emit_string_strict(w, value)
+            self.emit_string_quoted(w, value, b'"')
+        }
+    }
+
+    // Returns whether `ch` must be written as a `\u` escape.  Characters in
+    // the control range are escaped separately.
+    fn must_escape(&self, ch: char) -> bool {
+        (self.opts.ascii_only && !ch.is_ascii())
+            || (self.opts.escape_line_separators && matches!(ch, '\u{2028}' | '\u{2029}'))
+    }
+
+    // Returns whether any character of `s` must be written as a `\u` escape.
+    fn needs_escape(&self, s: &str) -> bool {
+        (self.opts.ascii_only || self.opts.escape_line_separators)
+            && s.chars().any(|ch| self.must_escape(ch))
+    }
+
+    // Emits a run of text which needs no escaping other than of the
+    // multi-byte characters selected by `must_escape` (e.g. the non-ASCII
+    // characters in ASCII-only mode).
+    fn emit_run<W: TokenSink>(&mut self, w: &mut W, run: &str, class: TokenClass) -> Result<()> {
+        if !self.needs_escape(run) {
+            return self.token(w, class, run);
+        }
+        let mut start = 0;
+        for (i, ch) in run.char_indices() {
+            if !self.must_escape(ch) {
+                continue;
+            }
+            if start < i {
+                self.token(w, class.clone(), &run[start..i])?;
+            }
+            let mut escape = String::new();
+            escape_char(&mut escape, ch);
+            self.token(w, TokenClass::Escape, &escape)?;
+            start = i + ch.len_utf8();
         }
+        if start < run.len() {
+            self.token(w, class, &run[start..])?;
+        }
+        Ok(())
     }
 
-    fn emit_string_strict<W: fmt::Write>(&mut self, w: &mut W, value: &str) -> Result<()> {
-        write!(w, "{}", &self.color.punctuation.paint("\""))?;
+    fn emit_string_quoted<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        value: &str,
+        quote: u8,
+    ) -> Result<()> {
+        let quote_str = if quote == b'\'' { "'" } else { "\"" };
+        self.token(w, TokenClass::Punctuation, &quote_str)?;
         let bytes = value.as_bytes();
         let mut start = 0;
         for (i, &byte) in bytes.iter().enumerate() {
-            let escape = ESCAPE[byte as usize];
+            // Only the quote character delimiting the string needs escaping.
+            let escape = match byte {
+                b'"' | b'\'' if byte != quote => __,
+                b'\'' => b'\'',
+                _ => ESCAPE[byte as usize],
+            };
             if escape == 0 {
                 continue;
             }
             if start < i {
-                write!(w, "{}", &self.color.string.paint(&value[start..i]))?;
+                self.emit_run(w, &value[start..i], TokenClass::String)?;
             }
             match escape {
-                UU => write!(
-                    w,
-                    "{}",
-                    &self.color.escape.paint(format!("\\u{:04x}", byte))
-                )?,
-                _ => write!(
-                    w,
-                    "{}",
-                    &self.color.escape.paint(format!("\\{}", escape as char))
-                )?,
+                UU => self.token(w, TokenClass::Escape, &format!("\\u{:04x}", byte))?,
+                _ => self.token(w, TokenClass::Escape, &format!("\\{}", escape as char))?,
             };
             start = i + 1;
         }
         if start != bytes.len() {
-            write!(w, "{}", &self.color.string.paint(&value[start..]))?;
+            self.emit_run(w, &value[start..], TokenClass::String)?;
         }
-        write!(w, "{}", &self.color.punctuation.paint("\""))?;
+        self.token(w, TokenClass::Punctuation, &quote_str)?;
         Ok(())
     }
 
-    fn emit_string_multiline<W: fmt::Write>(&mut self, w: &mut W, value: &str) -> Result<()> {
-        if self.multiline == Multiline::Hjson {
-            writeln!(w)?;
-            self.level += 1;
-            self.emit_indent(w)?;
-            self.writeln(w, &self.color.punctuation.paint("'''").to_string())?;
+    fn emit_string_multiline<W: TokenSink>(&mut self, w: &mut W, value: &str) -> Result<()> {
+        let trimmed;
+        // Extra indentation of each line beyond the current level.
+        let mut pad = 0;
+        let value = if self.opts.multiline == Multiline::Hjson {
+            if self.opts.multiline_inline_open {
+                self.token(w, TokenClass::Punctuation, "'''")?;
+                self.writeln(w, "")?;
+                self.level += self.opts.multiline_indent;
+                // Hjson removes leading whitespace up to the column of the
+                // opening delimiter, so the lines must reach that column to
+                // keep their own indentation.
+                if let Some(column) = self.value_column.take() {
+                    pad = column.saturating_sub(self.level * self.opts.common.indent);
+                }
+            } else {
+                writeln!(w)?;
+                self.level += self.opts.multiline_indent;
+                self.emit_indent(w)?;
+                self.token(w, TokenClass::Punctuation, "'''")?;
+                self.writeln(w, "")?;
+            }
             self.emit_indent(w)?;
+            write!(w, "{:1$}", "", pad)?;
+            if self.opts.multiline_trailing_whitespace {
+                value
+            } else {
+                trimmed = value
+                    .split('\n')
+                    .map(str::trim_end)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                &trimmed
+            }
         } else {
-            write!(w, "{}", &self.color.punctuation.paint("\""))?;
-        }
+            self.token(w, TokenClass::Punctuation, "\"")?;
+            value
+        };
         let bytes = value.as_bytes();
         let mut start = 0;
         for (i, &byte) in bytes.iter().enumerate() {
@@ -539,110 +2189,159 @@ impl JsonEmitter {
                 continue;
             }
             if start < i {
-                write!(w, "{}", &self.color.string.paint(&value[start..i]))?;
+                self.emit_run(w, &value[start..i], TokenClass::String)?;
             }
             match escape {
-                UU => write!(
-                    w,
-                    "{}",
-                    &self.color.escape.paint(format!("\\u{:04x}", byte))
-                )?,
-                NN => match self.multiline {
-                    Multiline::None => write!(
-                        w,
-                        "{}",
-                        &self.color.escape.paint(format!("\\{}", escape as char))
-                    )?,
-                    Multiline::Json5 => writeln!(w, "{}", self.color.escape.paint("\\"))?,
+                UU => self.token(w, TokenClass::Escape, &format!("\\u{:04x}", byte))?,
+                NN => match self.opts.multiline {
+                    Multiline::None => {
+                        self.token(w, TokenClass::Escape, &format!("\\{}", escape as char))?
+                    }
+                    Multiline::Json5 => {
+                        self.token(w, TokenClass::Escape, "\\")?;
+                        writeln!(w)?
+                    }
                     Multiline::Hjson => {
                         writeln!(w)?;
                         self.emit_indent(w)?;
+                        write!(w, "{:1$}", "", pad)?;
                     }
                 },
-                _ => write!(
-                    w,
-                    "{}",
-                    &self.color.escape.paint(format!("\\{}", escape as char))
-                )?,
+                _ => self.token(w, TokenClass::Escape, &format!("\\{}", escape as char))?,
             };
             start = i + 1;
         }
         if start != bytes.len() {
-            write!(w, "{}", &self.color.string.paint(&value[start..]))?;
+            self.emit_run(w, &value[start..], TokenClass::String)?;
         }
-        if self.multiline == Multiline::Hjson {
+        if self.opts.multiline == Multiline::Hjson {
             writeln!(w)?;
             self.emit_indent(w)?;
-            write!(w, "{}", &self.color.punctuation.paint("'''"))?;
-            self.level -= 1;
+            self.token(w, TokenClass::Punctuation, "'''")?;
+            self.level -= self.opts.multiline_indent;
         } else {
-            write!(w, "{}", &self.color.punctuation.paint("\""))?;
+            self.token(w, TokenClass::Punctuation, "\"")?;
         }
         Ok(())
     }
 
-    fn emit_boolean<W: fmt::Write>(&mut self, w: &mut W, b: bool) -> Result<()> {
+    fn emit_boolean<W: TokenSink>(&mut self, w: &mut W, b: bool) -> Result<()> {
         if b {
-            write!(w, "{}", &self.color.boolean.paint("true"))?;
+            self.token(w, TokenClass::Boolean, "true")?;
         } else {
-            write!(w, "{}", &self.color.boolean.paint("false"))?;
+            self.token(w, TokenClass::Boolean, "false")?;
         }
         Ok(())
     }
 
-    fn emit_int<W: fmt::Write>(&mut self, w: &mut W, i: &Int) -> Result<()> {
+    fn emit_int<W: TokenSink>(&mut self, w: &mut W, i: &Int) -> Result<()> {
         let b = i.base();
-        let s = i.format(self.bases.get(&b));
-        if self.strict_numeric_limits && !i.is_legal_json()
-            || self.bases.get(&b).is_some() && self.literals.get(&b).is_none()
+        let ctx = NumberContext {
+            base: self.opts.int_base(b),
+            width: i.width(),
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        // The dialect has no integer type suffixes.
+        let i = &i.unsuffixed();
+        i.check_format(ctx.base)?;
+        let s = self.opts.numbers.format_int(i, &ctx);
+        if self.opts.strict_numeric_limits && !i.is_legal_json()
+            || self.opts.bases.contains(&b) && !self.opts.literals.contains(&b)
         {
-            write!(
-                w,
-                "{}{}{}",
-                self.color.punctuation.paint("\""),
-                self.color.integer.paint(s),
-                self.color.punctuation.paint("\"")
-            )?;
+            let s = match self.opts.digit_grouping {
+                DigitGrouping::None => s,
+                _ => Int::group_digits(&s, ctx.base),
+            };
+            self.token(w, TokenClass::Punctuation, "\"")?;
+            self.token(w, TokenClass::QuotedInteger, &s)?;
+            self.token(w, TokenClass::Punctuation, "\"")?;
+        } else {
+            if self.opts.digit_grouping == DigitGrouping::Literals && ctx.base != Base::Dec {
+                return Err(Error::ConfigError(
+                    "JSON literals cannot contain digit separators".into(),
+                ));
+            }
+            self.token(w, TokenClass::Integer, &s)?;
+        }
+        Ok(())
+    }
+
+    fn emit_float<W: TokenSink>(&mut self, w: &mut W, f: f64) -> Result<()> {
+        if !f.is_finite() {
+            return self.emit_non_finite(w, f);
+        }
+        let ctx = NumberContext {
+            base: Base::Dec,
+            width: 0,
+            precision: None,
+            float: self.opts.float_format,
+        };
+        let s = self.opts.numbers.format_float(f, &ctx);
+        if ctx.float == FloatFormat::Hex && !self.opts.literals.contains(&Base::Hex) {
+            return self.emit_string_quoted(w, &s, b'"');
+        }
+        self.token(w, TokenClass::Float, &s)?;
+        Ok(())
+    }
+
+    fn emit_non_finite<W: TokenSink>(&mut self, w: &mut W, f: f64) -> Result<()> {
+        let (literal, short) = if f.is_nan() {
+            ("NaN", "nan")
+        } else if f > 0.0 {
+            ("Infinity", "inf")
         } else {
-            write!(w, "{}", &self.color.integer.paint(s))?;
+            ("-Infinity", "-inf")
+        };
+        match self.opts.non_finite {
+            NonFinite::Literal => self.token(w, TokenClass::Float, &literal)?,
+            NonFinite::String => self.emit_string_quoted(w, literal, b'"')?,
+            NonFinite::Null => self.emit_null(w)?,
+            NonFinite::Object => {
+                self.token(w, TokenClass::Aggregate, "{")?;
+                self.emit_key(w, "$float", StrFormat::Standard)?;
+                self.token(w, TokenClass::Punctuation, ": ")?;
+                self.emit_string_quoted(w, short, b'"')?;
+                self.token(w, TokenClass::Aggregate, "}")?;
+            }
         }
         Ok(())
     }
 
-    fn emit_float<W: fmt::Write>(&mut self, w: &mut W, f: f64) -> Result<()> {
-        write!(w, "{}", &self.color.float.paint(format!("{}", f)))?;
+    fn emit_null<W: TokenSink>(&mut self, w: &mut W) -> Result<()> {
+        self.token(w, TokenClass::Null, "null")?;
         Ok(())
     }
 
-    fn emit_null<W: fmt::Write>(&mut self, w: &mut W) -> Result<()> {
-        write!(w, "{}", &self.color.null.paint("null"))?;
+    // Writes `text` as a token of `class`.  Within a node of a color class,
+    // every token but a comment is of that class.
+    fn token<W: TokenSink>(&self, w: &mut W, class: TokenClass, text: &str) -> Result<()> {
+        let style = self.color.style(&class);
+        let class = match &self.color_class {
+            Some(name) if class != TokenClass::Comment => TokenClass::Class(name.clone()),
+            _ => class,
+        };
+        w.write_token(&class, style, text)?;
         Ok(())
     }
 
-    fn emit_indent<W: fmt::Write>(&mut self, w: &mut W) -> Result<()> {
+    fn emit_indent<W: TokenSink>(&mut self, w: &mut W) -> Result<()> {
         if self.compact {
             return Ok(());
         }
-        let mut len = self.level * self.indent;
-        while len > 0 {
-            let chunk = std::cmp::min(len, SPACE.len());
-            write!(w, "{}", &SPACE[..chunk])?;
-            len -= chunk;
-        }
-        Ok(())
+        write_indent(w, self.level * self.opts.common.indent)
     }
 
-    fn writeln<W: fmt::Write>(&mut self, w: &mut W, s: &str) -> Result<()> {
-        if self.compact {
-            match s {
-                "," => write!(w, "{} ", self.color.punctuation.paint(","))?,
-                _ => write!(w, "{}", s)?,
-            };
+    fn writeln<W: TokenSink>(&mut self, w: &mut W, s: &str) -> Result<()> {
+        if s == "," {
+            self.token(w, TokenClass::Punctuation, ",")?;
         } else {
-            match s {
-                "," => writeln!(w, "{}", self.color.punctuation.paint(","))?,
-                _ => writeln!(w, "{}", s)?,
-            };
+            write!(w, "{}", s)?;
+        }
+        if !self.compact {
+            writeln!(w)?;
+        } else if s == "," {
+            write!(w, " ")?;
         }
         Ok(())
     }
@@ -681,83 +2380,6 @@ const ESCAPE: [u8; 256] = [
     __, __, __, __, __, __, __, __, __, __, __, __, __, __, __, __, // F
 ];
 
-const SPACE: &str = "                                                                                                    ";
-
-// More strict than javascript.
-fn bad_identifier_char(ch: char) -> bool {
-    match ch {
-        '0'..='9' => false,
-        'A'..='Z' => false,
-        'a'..='z' => false,
-        '_' => false,
-        '$' => false,
-        _ => true,
-    }
-}
-
-fn is_reserved_word(word: &str) -> bool {
-    static WORDS: OnceCell<HashSet<&str>> = OnceCell::new();
-    let words = WORDS.get_or_init(|| {
-        HashSet::from([
-            "break",
-            "do",
-            "instanceof",
-            "typeof",
-            "case",
-            "else",
-            "new",
-            "var",
-            "catch",
-            "finally",
-            "return",
-            "void",
-            "continue",
-            "for",
-            "switch",
-            "while",
-            "debugger",
-            "function",
-            "this",
-            "with",
-            "default",
-            "if",
-            "throw",
-            "",
-            "delete",
-            "in",
-            "try",
-            "class",
-            "enum",
-            "extends",
-            "super",
-            "const",
-            "export",
-            "import",
-            "implements",
-            "let",
-            "private",
-            "public",
-            "yield",
-            "interface",
-            "package",
-            "protected",
-            "static",
-            "null",
-            "true",
-            "false",
-        ])
-    });
-    words.get(word).is_some()
-}
-
-fn is_legal_bareword(word: &str) -> bool {
-    if word.len() == 0 {
-        return false;
-    }
-    let ch = word.chars().nth(0).unwrap();
-    !((ch >= '0' && ch <= '9') || word.contains(bad_identifier_char) || is_reserved_word(word))
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -806,29 +2428,29 @@ mod tests {
     #[test]
     fn basic_document() {
         let c = comment("woohoo!").to_json();
-        assert_eq!(c.to_string(), "");
+        assert_eq!(c.to_text().unwrap(), "");
         let c = comment("woohoo!").to_json5();
-        assert_eq!(c.to_string(), "// woohoo!\n");
+        assert_eq!(c.to_text().unwrap(), "// woohoo!\n");
         let n = null().to_json();
-        assert_eq!(n.to_string(), "null");
+        assert_eq!(n.to_text().unwrap(), "null");
         let b = boolean(true).to_json();
-        assert_eq!(b.to_string(), "true");
+        assert_eq!(b.to_text().unwrap(), "true");
         // Plain integer
         let i = int(5).to_json();
-        assert_eq!(i.to_string(), "5");
+        assert_eq!(i.to_text().unwrap(), "5");
         // Integer wants to be hex, but hex isn't allowed.
         let i = hex(15).to_json();
-        assert_eq!(i.to_string(), "15");
+        assert_eq!(i.to_text().unwrap(), "15");
         // Integer wants to be hex, hex is allowed, but not as a literal.
         let i = hex(16).to_json().bases(&[Base::Hex]);
-        assert_eq!(i.to_string(), "\"0x10\"");
+        assert_eq!(i.to_text().unwrap(), "\"0x10\"");
         // Integer wants to be hex, hex literals allowed.
         let i = hex(16).to_json5();
-        assert_eq!(i.to_string(), "0x10");
+        assert_eq!(i.to_text().unwrap(), "0x10");
         let s = string("hello").to_json();
-        assert_eq!(s.to_string(), "\"hello\"");
+        assert_eq!(s.to_text().unwrap(), "\"hello\"");
         let f = float(3.14159).to_json();
-        assert_eq!(f.to_string(), "3.14159");
+        assert_eq!(f.to_text().unwrap(), "3.14159");
     }
 
     #[test]
@@ -841,7 +2463,7 @@ mod tests {
 ]"#;
 
         let list = Document::Sequence(vec![int(5), int(10), int(15), string("foo")]).to_json();
-        assert_eq!(list.to_string(), expect);
+        assert_eq!(list.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -859,7 +2481,7 @@ mod tests {
             kv("true", string("foo")),
         ])
         .to_json();
-        assert_eq!(map.to_string(), expect);
+        assert_eq!(map.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -877,7 +2499,7 @@ mod tests {
             kv("true", string("foo")),
         ])
         .to_json5();
-        assert_eq!(map.to_string(), expect);
+        assert_eq!(map.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -891,7 +2513,7 @@ mod tests {
         ])
         .to_json5()
         .compact(true);
-        assert_eq!(map.to_string(), expect);
+        assert_eq!(map.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -913,7 +2535,7 @@ mod tests {
             kv("reset", nes_address("prg", -1, 0xFFFA)),
         ])
         .to_json5();
-        assert_eq!(map.to_string(), expect);
+        assert_eq!(map.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -953,7 +2575,68 @@ No \\n's!",
             kv("backwardsCompatible", string("with JSON")),
         ])
         .to_json5();
-        assert_eq!(map.to_string(), expect);
+        assert_eq!(map.to_text().unwrap(), expect);
+    }
+
+    #[test]
+    fn hjson_multiline_layout() -> Result<()> {
+        let doc = Document::Mapping(vec![kv("text", multistr("first  \n  second"))]);
+        assert_eq!(
+            doc.clone().to_hjson().to_text()?,
+            "{\n  text: \n    '''\n    first  \n      second\n    '''\n}"
+        );
+        assert_eq!(
+            doc.clone()
+                .to_hjson()
+                .multiline_indent(0)
+                .multiline_trailing_whitespace(false)
+                .to_text()?,
+            "{\n  text: \n  '''\n  first\n    second\n  '''\n}"
+        );
+        let text = doc.to_hjson().multiline_inline_open(true).to_text()?;
+        assert_eq!(
+            text,
+            "{\n  text: '''\n        first  \n          second\n    '''\n}"
+        );
+        // The lines reach the column of the opening delimiter, so their
+        // indentation survives a round trip.
+        let doc = Document::from_hjson(&text)?;
+        assert_eq!(
+            doc.to_json().to_text()?,
+            "{\n  \"text\": \"first  \\n  second\"\n}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn demo_mapc() -> Result<()> {
+        let expect = r#"{
+  // comments
+  "unquoted": "and you can quote me on that",
+  "lineBreaks": "Look, Mom!\nNo \\n's!",
+  "hexadecimal": 912559,
+  "infinity": "Infinity"
+}"#;
+        let map = Document::Mapping(vec![
+            kvcomment(
+                "unquoted",
+                string("and you can quote me on that"),
+                "comments",
+            ),
+            kv("lineBreaks", multistr("Look, Mom!\nNo \\n's!")),
+            kv("hexadecimal", hex(0xdecaf)),
+            kv("infinity", float(f64::INFINITY)),
+        ]);
+        assert_eq!(map.clone().to_jsonc().to_text()?, expect);
+        // The parser accepts comments and trailing commas, but not json5.
+        let doc = Document::from_jsonc("{\n  // a\n  \"b\": [1, 2,],\n}")?;
+        assert_eq!(
+            doc.to_jsonc().to_text()?,
+            "{\n  // a\n  \"b\": [\n    1,\n    2\n  ]\n}"
+        );
+        assert!(Document::from_jsonc("{b: 1}").is_err());
+        assert!(Document::from_jsonc(&expect.replace("912559", "0xdecaf")).is_err());
+        Ok(())
     }
 
     #[test]
@@ -996,7 +2679,1192 @@ No \\n's!",
             kv("backwardsCompatible", string("with JSON")),
         ])
         .to_hjson();
-        println!("{}", map);
-        assert_eq!(map.to_string(), expect);
+        println!("{}", map.to_text().unwrap());
+        assert_eq!(map.to_text().unwrap(), expect);
+    }
+
+    #[test]
+    fn preserve_style() -> Result<()> {
+        let text = r#"{
+  bare: 'single "quoted"',
+  "quoted": "double 'quoted'",
+  'single': 'it\'s'
+}"#;
+        let doc = Document::parse(text)?;
+        assert_eq!(doc.clone().to_json5().preserve_style(true).to_text()?, text);
+        assert_eq!(
+            doc.to_json5().to_text()?,
+            r#"{
+  bare: "single \"quoted\"",
+  quoted: "double 'quoted'",
+  single: "it's"
+}"#
+        );
+        // Strict JSON has no single-quoted strings.
+        let doc = Document::parse("['it']")?;
+        assert_eq!(
+            doc.to_json().compact(true).preserve_style(true).to_text()?,
+            r#"["it"]"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn override_at() {
+        let part = |name, offset| {
+            Document::Mapping(vec![kv("name", string(name)), kv("offset", hex(offset))])
+        };
+        let doc = Document::Mapping(vec![kv(
+            "flash",
+            Document::Mapping(vec![
+                kv("base", hex(0x2000)),
+                kv(
+                    "partitions",
+                    Document::Sequence(vec![part("boot", 0x1000), part("app", 0x8000)]),
+                ),
+            ]),
+        )]);
+        let json = doc.to_json().override_at("/flash/partitions/*", |opts| {
+            opts.compact(true).literals(&[Base::Hex])
+        });
+        assert_eq!(
+            json.to_text().unwrap(),
+            r#"{
+  "flash": {
+    "base": 8192,
+    "partitions": [
+      {"name": "boot", "offset": 0x1000},
+      {"name": "app", "offset": 0x8000}
+    ]
+  }
+}"#
+        );
+    }
+
+    #[test]
+    fn trailing_comma() {
+        let doc = Document::Mapping(vec![
+            kv("a", Document::Sequence(vec![int(1), int(2)])),
+            kv("b", nes_address("prg", 1, 0x8000)),
+        ]);
+        assert_eq!(
+            doc.to_json5().trailing_comma(true).to_text().unwrap(),
+            r#"{
+  a: [
+    1,
+    2,
+  ],
+  b: {prg: [1, 0x8000]},
+}"#
+        );
+        // Strict JSON has no trailing commas.
+        let doc = Document::Sequence(vec![int(1), int(2)]);
+        assert_eq!(
+            doc.to_json().trailing_comma(true).to_text().unwrap(),
+            "[\n  1,\n  2\n]"
+        );
+    }
+
+    #[test]
+    fn quoteless() -> Result<()> {
+        let doc = Document::from_hjson(
+            r#"{
+  name: hello, world
+  brace: "{not quoteless}"
+  hash: value # not a comment
+  list: [
+    one
+    two
+  ]
+}"#,
+        )?;
+        let hjson = doc.clone().to_hjson().to_text()?;
+        assert_eq!(
+            hjson,
+            r#"{
+  name: hello, world
+  brace: "{not quoteless}",
+  hash: "value # not a comment",
+  list: [
+    one
+    two
+  ]
+}"#
+        );
+        assert_eq!(Document::from_hjson(&hjson)?.to_hjson().to_text()?, hjson);
+        assert_eq!(
+            doc.to_json5().to_text()?,
+            r#"{
+  name: "hello, world",
+  brace: "{not quoteless}",
+  hash: "value # not a comment",
+  list: [
+    "one",
+    "two"
+  ]
+}"#
+        );
+
+        // A comment following the value on the same line requires quotes.
+        let unquoted = |v: &str| Document::String(v.to_string(), StrFormat::Unquoted);
+        let doc = Document::Mapping(vec![
+            Document::Fragment(vec![string("a"), unquoted("value"), comment("note")]),
+            kv("b", unquoted("value")),
+        ]);
+        assert_eq!(
+            doc.to_hjson().to_text()?,
+            r#"{
+  a: "value", # note
+  b: value
+}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn snippet() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // The log level.
+                level: "debug",
+                targets: ["stderr", "file"], // Where logs go.
+            }"#,
+        )?;
+        assert_eq!(
+            doc.clone().to_json5().snippet(true).to_text()?,
+            r#"// The log level.
+level: "debug",
+targets: [
+  "stderr",
+  "file"
+] // Where logs go."#
+        );
+        let doc = Document::parse("{a: 1, b: [2]}")?;
+        assert_eq!(
+            doc.clone()
+                .to_json()
+                .dialect(Dialect::Json5)
+                .snippet(true)
+                .trailing_comma(true)
+                .to_text()?,
+            "\"a\": 1,\n\"b\": [\n  2,\n],"
+        );
+        assert_eq!(
+            doc.to_json5().snippet(true).compact(true).to_text()?,
+            "a: 1, b: [2]"
+        );
+
+        let doc = Document::Sequence(vec![int(1), Document::Sequence(vec![int(2)])]);
+        assert_eq!(doc.to_json().snippet(true).to_text()?, "1,\n[\n  2\n]");
+        assert_eq!(
+            Document::Mapping(vec![])
+                .to_json()
+                .snippet(true)
+                .to_text()?,
+            ""
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compact_threshold() {
+        let doc = Document::Mapping(vec![
+            kv("short", Document::Sequence(vec![int(1), int(2), int(3)])),
+            kv(
+                "nested",
+                Document::Mapping(vec![kv("prg", nes_address("prg", 1, 0x8000))]),
+            ),
+            kv(
+                "commented",
+                Document::Sequence(vec![Document::Fragment(vec![comment("one"), int(1)])]),
+            ),
+            kv(
+                "long",
+                Document::Sequence((0..12).map(|i| int(i * 1000)).collect()),
+            ),
+        ]);
+        assert_eq!(
+            doc.clone()
+                .to_json5()
+                .compact_threshold(30)
+                .to_text()
+                .unwrap(),
+            r#"{
+  short: [1, 2, 3],
+  nested: {
+    prg: {prg: [1, 0x8000]}
+  },
+  commented: [
+    // one
+    1
+  ],
+  long: [
+    0,
+    1000,
+    2000,
+    3000,
+    4000,
+    5000,
+    6000,
+    7000,
+    8000,
+    9000,
+    10000,
+    11000
+  ]
+}"#
+        );
+        // With a smaller threshold, only the inner aggregates fit.
+        assert_eq!(
+            doc.to_json().compact_threshold(24).to_text().unwrap(),
+            r#"{
+  "short": [1, 2, 3],
+  "nested": {
+    "prg": {"prg": [1, 32768]}
+  },
+  "commented": [1],
+  "long": [
+    0,
+    1000,
+    2000,
+    3000,
+    4000,
+    5000,
+    6000,
+    7000,
+    8000,
+    9000,
+    10000,
+    11000
+  ]
+}"#
+        );
+    }
+
+    #[test]
+    fn comment_placement() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+  // The name.
+  name: "flash",
+  // The sizes.
+  sizes: [
+    // First.
+    1,
+    2
+  ],
+  // Two
+  // lines.
+  base: 0x1000,
+  addr: 0x8000 // Already trailing.
+}"#,
+        )?;
+        assert_eq!(
+            doc.to_json5()
+                .comment_placement(CommentPlacement::Trailing)
+                .to_text()?,
+            r#"{
+  name: "flash", // The name.
+  // The sizes.
+  sizes: [
+    1, // First.
+    2
+  ],
+  // Two
+  // lines.
+  base: 0x1000,
+  addr: 0x8000 // Already trailing.
+}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn non_finite() -> Result<()> {
+        let values = [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1.5];
+        let doc = Document::Sequence(values.iter().map(|f| float(*f)).collect());
+        let check = |text: &str| -> Result<()> {
+            let back: Vec<f64> = crate::from_str(text)?;
+            assert!(back[0].is_nan());
+            assert_eq!(&back[1..], &values[1..]);
+            Ok(())
+        };
+
+        let text = doc.clone().to_json5().compact(true).to_text()?;
+        assert_eq!(text, "[NaN, Infinity, -Infinity, 1.5]");
+        check(&text)?;
+
+        // Strict json defaults to the string encoding.
+        let text = doc.clone().to_json().compact(true).to_text()?;
+        assert_eq!(text, r#"["NaN", "Infinity", "-Infinity", 1.5]"#);
+        check(&text)?;
+
+        let text = doc
+            .clone()
+            .to_json()
+            .compact(true)
+            .non_finite(NonFinite::Null)
+            .to_text()?;
+        assert_eq!(text, "[null, null, null, 1.5]");
+
+        let text = doc
+            .to_json()
+            .compact(true)
+            .non_finite(NonFinite::Object)
+            .to_text()?;
+        assert_eq!(
+            text,
+            r#"[{"$float": "nan"}, {"$float": "inf"}, {"$float": "-inf"}, 1.5]"#
+        );
+        check(&text)?;
+        // The strict parser recognizes the object encoding.
+        let doc = Document::from_json(&text)?;
+        assert_eq!(
+            doc.to_json5().compact(true).to_text()?,
+            "[NaN, Infinity, -Infinity, 1.5]"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn align_comments() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+  // The name.
+  name: "flash",
+  size: 0x100000, // In bytes.
+  nested: {
+    a: 1 // One.
+  },
+  // The erase block size.
+  block: 4096
+}"#,
+        )?;
+        assert_eq!(
+            doc.to_json5()
+                .comment_placement(CommentPlacement::Trailing)
+                .align_comments(true)
+                .to_text()?,
+            r#"{
+  name: "flash",  // The name.
+  size: 0x100000, // In bytes.
+  nested: {
+    a: 1 // One.
+  },
+  block: 4096     // The erase block size.
+}"#
+        );
+
+        // Wide characters are measured in terminal columns.
+        let doc = Document::parse(
+            r#"{
+  name: "日本語", // 名前
+  tag: "🎉", // 🎉 party
+  id: 12345678, // Number.
+}"#,
+        )?;
+        assert_eq!(
+            doc.to_json5()
+                .comment_placement(CommentPlacement::Trailing)
+                .align_comments(true)
+                .to_text()?,
+            r#"{
+  name: "日本語", // 名前
+  tag: "🎉",      // 🎉 party
+  id: 12345678    // Number.
+}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_non_ascii_comments() -> Result<()> {
+        let doc = Document::parse("{\n  // Größe.\n  size: 1,\n  // Plain.\n  name: \"ü\"\n}")?;
+        let json = doc.clone().to_json5().ascii_comments(AsciiComments::Reject);
+        match json.emit() {
+            Err(Error::NonAsciiComment(path, comment)) => {
+                assert_eq!(path, "/size");
+                assert_eq!(comment, "Größe.");
+            }
+            other => panic!("Expected NonAsciiComment, got {:?}", other.map(|r| r.0)),
+        }
+        // Strings may hold non-ASCII characters, and comments which are not
+        // emitted are not rejected.
+        let doc = Document::parse("{\n  // Plain.\n  name: \"ü\"\n}")?;
+        assert_eq!(
+            doc.clone()
+                .to_json5()
+                .ascii_comments(AsciiComments::Reject)
+                .to_text()?,
+            "{\n  // Plain.\n  name: \"ü\"\n}"
+        );
+        let doc = Document::parse("{\n  // Größe.\n  size: 1\n}")?;
+        assert_eq!(
+            doc.to_json()
+                .ascii_comments(AsciiComments::Reject)
+                .to_text()?,
+            "{\n  \"size\": 1\n}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn aggregate_comment_placement() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+  // The banks.
+  banks: [
+    1,
+    2
+  ],
+  // Empty.
+  empty: [],
+  // A scalar.
+  size: 3
+}"#,
+        )?;
+        assert_eq!(
+            doc.clone()
+                .to_json5()
+                .aggregate_comment_placement(AggregateCommentPlacement::Inside)
+                .to_text()?,
+            r#"{
+  banks: [
+    // The banks.
+    1,
+    2
+  ],
+  // Empty.
+  empty: [],
+  // A scalar.
+  size: 3
+}"#
+        );
+        assert_eq!(
+            doc.to_json5()
+                .aggregate_comment_placement(AggregateCommentPlacement::After)
+                .to_text()?,
+            r#"{
+  banks: [
+    1,
+    2
+  ], // The banks.
+  // Empty.
+  empty: [],
+  // A scalar.
+  size: 3
+}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn brace_placement() -> Result<()> {
+        let doc = Document::Mapping(vec![
+            kv("a", Document::Mapping(vec![kv("b", int(1))])),
+            kv("c", Document::Sequence(vec![int(1), int(2)])),
+            kv("d", Document::Sequence(vec![])),
+            kv("e", int(3)),
+        ]);
+        let json = doc
+            .clone()
+            .to_json5()
+            .brace_placement(BracePlacement::NextLine);
+        assert_eq!(
+            json.to_text()?,
+            r#"{
+  a:
+  {
+    b: 1
+  },
+  c:
+  [
+    1,
+    2
+  ],
+  d: [],
+  e: 3
+}"#
+        );
+        // Values emitted on one line are unaffected.
+        let json = json.compact_threshold(20);
+        assert_eq!(
+            json.to_text()?,
+            "{\n  a: {b: 1},\n  c: [1, 2],\n  d: [],\n  e: 3\n}"
+        );
+        // The placement follows the options overridden at the value.
+        let json = doc
+            .to_json5()
+            .brace_placement(BracePlacement::NextLine)
+            .override_at("/c", |o| o.compact(true));
+        assert_eq!(
+            json.to_text()?,
+            "{\n  a:\n  {\n    b: 1\n  },\n  c: [1, 2],\n  d: [],\n  e: 3\n}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn inline_empty() -> Result<()> {
+        let doc = Document::Mapping(vec![
+            kv("a", Document::Mapping(vec![])),
+            kv(
+                "b",
+                Document::Sequence(vec![Document::Sequence(vec![]), Document::Mapping(vec![])]),
+            ),
+        ]);
+        let profiles = [
+            (
+                "json",
+                JsonOptions::strict_default(),
+                "\"a\": {},\n  \"b\": [\n    [],\n    {}\n  ]",
+            ),
+            (
+                "json5",
+                JsonOptions::json5_default(),
+                "a: {},\n  b: [\n    [],\n    {}\n  ]",
+            ),
+            (
+                "hjson",
+                JsonOptions::hjson_default(),
+                "a: {},\n  b: [\n    [],\n    {}\n  ]",
+            ),
+        ];
+        for (name, options, body) in profiles {
+            let json = doc.clone().to_json().options(options.clone());
+            assert_eq!(json.to_text()?, format!("{{\n  {}\n}}", body), "{}", name);
+            let split = doc.clone().to_json().options(options.inline_empty(false));
+            assert!(split.to_text()?.contains("{\n  }"), "{}", name);
+        }
+        assert_eq!(Document::Sequence(vec![]).to_json().to_text()?, "[]");
+        // A mapping left empty by `skip_null` is also inline.
+        let nulls = Document::Mapping(vec![kv("a", Document::Null)]);
+        assert_eq!(nulls.to_json().skip_null(true).to_text()?, "{}");
+        Ok(())
+    }
+
+    #[test]
+    fn ascii_only() -> Result<()> {
+        let doc = Document::Mapping(vec![
+            kvcomment("café", string("naïve 😀"), "Größe — “quoted”"),
+            kv("text", multistr("première\nligne")),
+        ]);
+        assert_eq!(
+            doc.clone().to_json5().ascii_only(true).to_text()?,
+            r#"{
+  // Gr\u00f6\u00dfe \u2014 \u201cquoted\u201d
+  "caf\u00e9": "na\u00efve \ud83d\ude00",
+  text: "premi\u00e8re\
+ligne"
+}"#
+        );
+        assert_eq!(
+            doc.clone()
+                .to_hjson()
+                .ascii_only(true)
+                .ascii_comments(AsciiComments::Transliterate)
+                .to_text()?,
+            r#"{
+  # Grosse - "quoted"
+  "caf\u00e9": "na\u00efve \ud83d\ude00",
+  text: "premi\u00e8re\nligne"
+}"#
+        );
+        // Escaped output parses back to the original text.
+        let text = doc.clone().to_json5().ascii_only(true).to_text()?;
+        assert!(text.is_ascii());
+        let (k, v) = match Document::parse(&text)? {
+            Document::Mapping(m) => m[0].as_kv().map(|(k, v)| (k.clone(), v.clone()))?,
+            _ => panic!("Expected a mapping"),
+        };
+        assert_eq!(k.as_str()?, "café");
+        assert_eq!(v.as_str()?, "naïve 😀");
+        Ok(())
+    }
+
+    #[test]
+    fn ascii_only_ranges() -> Result<()> {
+        let doc = Document::Mapping(vec![kv(
+            "\u{7f}\u{85}é",
+            string("\u{1f}\u{ffff}\u{10000}\u{10ffff}"),
+        )]);
+        assert_eq!(
+            doc.clone().to_json().ascii_only(true).to_text()?,
+            "{\n  \"\u{7f}\\u0085\\u00e9\": \"\\u001f\\uffff\\ud800\\udc00\\udbff\\udfff\"\n}"
+        );
+        assert_eq!(
+            doc.clone().to_json().to_text()?,
+            "{\n  \"\u{7f}\u{85}é\": \"\\u001f\u{ffff}\u{10000}\u{10ffff}\"\n}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn escape_line_separators() -> Result<()> {
+        let doc = Document::Mapping(vec![
+            kv("a\u{2028}b", string("c\u{2029}d — e")),
+            kv("text", multistr("f\u{2028}g\nh")),
+        ]);
+        assert_eq!(
+            doc.clone()
+                .to_json()
+                .escape_line_separators(true)
+                .to_text()?,
+            r#"{
+  "a\u2028b": "c\u2029d — e",
+  "text": "f\u2028g\nh"
+}"#
+        );
+        // Hjson multiline strings cannot hold escapes, so are quoted instead.
+        assert_eq!(
+            doc.clone()
+                .to_hjson()
+                .escape_line_separators(true)
+                .to_text()?,
+            r#"{
+  "a\u2028b": "c\u2029d — e",
+  text: "f\u2028g\nh"
+}"#
+        );
+        let text = doc.clone().to_json().to_text()?;
+        assert!(text.contains("c\u{2029}d"));
+        Ok(())
+    }
+
+    #[test]
+    fn comment_policy() -> Result<()> {
+        let doc = Document::parse(
+            r#"// The root.
+{
+  // The name.
+  name: "prod",
+  ports: [
+    // The first port.
+    80
+  ],
+  tls: {
+    // Disabled.
+    enabled: false
+  }
+}"#,
+        )?;
+        let json = doc.clone().to_json().compact(true);
+        assert_eq!(json.emit()?.1, []);
+
+        let json = json.comment_policy(CommentPolicy::HoistToSidecar);
+        let (text, dropped) = json.emit()?;
+        assert_eq!(
+            text,
+            r#"{"name": "prod", "ports": [80], "tls": {"enabled": false}}"#
+        );
+        let dropped = dropped
+            .iter()
+            .map(|d| (d.path.as_str(), d.comment.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dropped,
+            [
+                ("", "The root."),
+                ("/name", "The name."),
+                ("/ports/0", "The first port."),
+                ("/tls/enabled", "Disabled.")
+            ]
+        );
+
+        let err = doc
+            .clone()
+            .to_json()
+            .comment_policy(CommentPolicy::Error)
+            .emit()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"comment at "" cannot be emitted: "The root.""#
+        );
+        // Formats which permit comments are unaffected.
+        let json5 = doc.to_json5().comment_policy(CommentPolicy::Error);
+        assert_eq!(json5.emit()?.0, json5.to_text()?);
+        Ok(())
+    }
+
+    #[test]
+    fn hex_float() -> Result<()> {
+        let doc = Document::parse("{gain: 1.5, offset: 0.1, ratio: 0x1.8p-1}")?;
+        let json5 = doc
+            .clone()
+            .to_json5()
+            .compact(true)
+            .override_at("/gain", |o| o.float_format(FloatFormat::Hex));
+        assert_eq!(
+            json5.to_text()?,
+            "{gain: 0x1.8p0, offset: 0.1, ratio: 0.75}"
+        );
+        let text = doc
+            .clone()
+            .to_json5()
+            .compact(true)
+            .float_format(FloatFormat::Hex)
+            .to_text()?;
+        assert_eq!(
+            text,
+            "{gain: 0x1.8p0, offset: 0x1.999999999999ap-4, ratio: 0x1.8p-1}"
+        );
+        assert!(Document::from_json5(&text)?.eq_semantic(&doc));
+        // Without hex literals, the value is quoted.
+        let text = doc
+            .to_json()
+            .compact(true)
+            .float_format(FloatFormat::Hex)
+            .to_text()?;
+        assert_eq!(
+            text,
+            r#"{"gain": "0x1.8p0", "offset": "0x1.999999999999ap-4", "ratio": "0x1.8p-1"}"#
+        );
+        #[derive(serde::Deserialize)]
+        struct Gain {
+            gain: f64,
+            offset: f64,
+            ratio: f64,
+        }
+        let gain = crate::from_str::<Gain>(&text)?;
+        assert_eq!((gain.gain, gain.offset, gain.ratio), (1.5, 0.1, 0.75));
+        Ok(())
+    }
+
+    #[test]
+    fn bin_oct_literals() -> Result<()> {
+        let doc = Document::Mapping(vec![
+            kv("mask", Document::Int(Int::new(0b1010u8, Base::Bin))),
+            kv("mode", Document::Int(Int::new_padded(0o755u16, Base::Oct))),
+        ]);
+        // Literals when permitted.
+        let text = doc
+            .clone()
+            .to_json5()
+            .compact(true)
+            .literals(&[Base::Bin, Base::Oct])
+            .to_text()?;
+        assert_eq!(text, "{mask: 0b1010, mode: 0o000755}");
+        assert!(Document::parse(&text)?.eq_semantic(&doc));
+        // Quoted strings when the base is permitted but not as a literal.
+        let text = doc
+            .clone()
+            .to_json()
+            .compact(true)
+            .bases(&[Base::Bin, Base::Oct])
+            .to_text()?;
+        assert_eq!(text, r#"{"mask": "0b1010", "mode": "0o000755"}"#);
+        #[derive(serde::Deserialize)]
+        struct Perms {
+            mask: u8,
+            mode: u16,
+        }
+        let perms = crate::from_str::<Perms>(&text)?;
+        assert_eq!((perms.mask, perms.mode), (0b1010, 0o755));
+        // Decimal otherwise.
+        assert_eq!(
+            doc.to_json().compact(true).to_text()?,
+            r#"{"mask": 10, "mode": 493}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn digit_grouping() -> Result<()> {
+        let doc = Document::Mapping(vec![
+            kv("addr", Document::Int(Int::new(0xfff01000u32, Base::Hex))),
+            kv("big", Document::Int(Int::new(u64::MAX, Base::Dec))),
+        ]);
+        // Only quoted strings are grouped by default.
+        let text = doc
+            .clone()
+            .to_json()
+            .compact(true)
+            .bases(&[Base::Hex])
+            .strict_numeric_limits(true)
+            .digit_grouping(DigitGrouping::Quoted)
+            .to_text()?;
+        assert_eq!(
+            text,
+            r#"{"addr": "0xFFF0_1000", "big": "18_446_744_073_709_551_615"}"#
+        );
+        #[derive(serde::Deserialize)]
+        struct Limits {
+            addr: u32,
+            big: u64,
+        }
+        let limits = crate::from_str::<Limits>(&text)?;
+        assert_eq!((limits.addr, limits.big), (0xfff01000, u64::MAX));
+        // No JSON dialect permits separators in literals.
+        let result = doc
+            .to_json5()
+            .compact(true)
+            .literals(&[Base::Hex])
+            .digit_grouping(DigitGrouping::Literals)
+            .emit();
+        assert!(matches!(result, Err(Error::ConfigError(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn bytes_rows() -> Result<()> {
+        let data = b"Mary had a little\x00lamb";
+        let doc = Document::Bytes(data.as_slice().into());
+        let text = doc
+            .clone()
+            .to_hjson()
+            .bytes_per_row(8)
+            .bytes_ascii(true)
+            .to_text()?;
+        assert_eq!(
+            text,
+            r#"[
+   77,  97, 114, 121,  32, 104,  97, 100, # 00000000  |Mary had|
+   32,  97,  32, 108, 105, 116, 116, 108, # 00000008  | a littl|
+  101,   0, 108,  97, 109,  98            # 00000010  |e.lamb|
+]"#
+        );
+        let bytes = crate::from_str::<serde_bytes::ByteBuf>(&text).unwrap();
+        assert_eq!(bytes.as_slice(), data);
+        let text = doc.clone().to_json5().bytes_per_row(8).to_text()?;
+        assert_eq!(
+            text,
+            r#"[
+  0x4D, 0x61, 0x72, 0x79, 0x20, 0x68, 0x61, 0x64, // 00000000
+  0x20, 0x61, 0x20, 0x6C, 0x69, 0x74, 0x74, 0x6C, // 00000008
+  0x65, 0x00, 0x6C, 0x61, 0x6D, 0x62              // 00000010
+]"#
+        );
+        // No offset comments where comments are not permitted.
+        let text = doc.clone().to_json().bytes_per_row(16).to_text()?;
+        assert_eq!(
+            text,
+            r#"[
+   77,  97, 114, 121,  32, 104,  97, 100,  32,  97,  32, 108, 105, 116, 116, 108,
+  101,   0, 108,  97, 109,  98
+]"#
+        );
+        let bytes = serde_json::from_str::<serde_bytes::ByteBuf>(&text).unwrap();
+        assert_eq!(bytes.as_slice(), data);
+
+        // Block comments precede their rows, and the data cannot end them.
+        let doc = Document::Bytes(b"a*/b".as_slice().into());
+        let text = doc
+            .to_json()
+            .comment(&[CommentFormat::Block])
+            .bytes_per_row(4)
+            .bytes_ascii(true)
+            .to_text()?;
+        assert_eq!(
+            text,
+            r#"[
+  /*
+   * 00000000  |a* /b|*/
+   97,  42,  47,  98
+]"#
+        );
+        let bytes = crate::from_str::<serde_bytes::ByteBuf>(&text).unwrap();
+        assert_eq!(bytes.as_slice(), b"a*/b");
+        Ok(())
+    }
+
+    #[test]
+    fn option_presets() {
+        let doc = Document::Mapping(vec![kvcomment("addr", hex(0x400), "Base address")]);
+        assert_eq!(
+            doc.clone()
+                .to_json()
+                .options(JsonOptions::json5_default())
+                .to_text()
+                .unwrap(),
+            doc.clone().to_json5().to_text().unwrap()
+        );
+        // Presets compose with further customization.
+        let opts = JsonOptions::hjson_default().indent(4).compact(false);
+        assert_eq!(
+            doc.clone()
+                .to_json()
+                .options(opts.clone())
+                .to_text()
+                .unwrap(),
+            "{\n    # Base address\n    addr: 1024\n}"
+        );
+        assert_eq!(
+            doc.to_json()
+                .options(opts.standard_comment(CommentFormat::SlashSlash))
+                .to_text()
+                .unwrap(),
+            "{\n    // Base address\n    addr: 1024\n}"
+        );
+    }
+
+    #[test]
+    fn pack_width() -> Result<()> {
+        let doc = Document::Mapping(vec![
+            kv(
+                "table",
+                Document::Sequence((0..12).map(|i| int(i * 100)).collect()),
+            ),
+            kv(
+                "mixed",
+                Document::Sequence(vec![
+                    int(1),
+                    Document::Fragment(vec![comment("Two"), int(2)]),
+                ]),
+            ),
+        ]);
+        let text = doc.clone().to_json5().pack_width(30).to_text()?;
+        assert_eq!(
+            text,
+            r#"{
+  table: [
+    0, 100, 200, 300, 400,
+    500, 600, 700, 800, 900,
+    1000, 1100
+  ],
+  mixed: [
+    1,
+    // Two
+    2
+  ]
+}"#
+        );
+        assert!(Document::parse(&text)?.eq_semantic(&doc));
+        // Lines hold at least one element.
+        let doc = Document::Sequence(vec![string("lengthy"), string("strings")]);
+        assert_eq!(
+            doc.to_json().pack_width(4).to_text()?,
+            "[\n  \"lengthy\",\n  \"strings\"\n]"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn custom_comment() {
+        let doc = Document::Mapping(vec![
+            kvcomment("level", int(3), "Verbosity\nof the log"),
+            Document::Fragment(vec![
+                Document::Comment("Hashed".into(), CommentFormat::Hash),
+                string("path"),
+                string("/tmp"),
+            ]),
+        ]);
+        let lisp = CommentFormat::Custom(";;");
+        assert_eq!(
+            doc.clone()
+                .to_json()
+                .comment(&[lisp])
+                .standard_comment(lisp)
+                .to_text().unwrap(),
+            "{\n  ;; Verbosity\n  ;; of the log\n  \"level\": 3,\n  ;; Hashed\n  \"path\": \"/tmp\"\n}"
+        );
+        // A custom format falls back to the standard format where it is not
+        // permitted.
+        let doc = Document::Sequence(vec![Document::Fragment(vec![
+            Document::Comment("Remark".into(), CommentFormat::Custom("REM")),
+            int(1),
+        ])]);
+        assert_eq!(doc.to_json5().to_text().unwrap(), "[\n  // Remark\n  1\n]");
+    }
+
+    #[test]
+    fn option_precedence() {
+        let doc = Document::Sequence(vec![
+            Document::Fragment(vec![
+                Document::Comment("Hashed".into(), CommentFormat::Hash),
+                Document::Int(Int::new(8, Base::Oct)),
+            ]),
+            hex(16),
+        ]);
+        // A style which is not permitted falls back to the standard style,
+        // or else to the first permitted style.
+        let emit = |formats: &[CommentFormat], standard| {
+            doc.clone()
+                .to_json()
+                .comment(formats)
+                .standard_comment(standard)
+                .compact(false)
+                .to_text()
+                .unwrap()
+        };
+        use CommentFormat::*;
+        assert!(emit(&[Hash, Block], SlashSlash).contains("# Hashed"));
+        assert!(emit(&[Block, SlashSlash], SlashSlash).contains("// Hashed"));
+        assert!(emit(&[Block, SlashSlash], Hash).contains("/*"));
+        assert!(emit(&[SlashSlash, Block], Hash).contains("// Hashed"));
+
+        // Bases which are not allowed fall back to decimal, whatever the
+        // order in which bases were allowed.
+        for bases in [[Base::Hex, Base::Bin], [Base::Bin, Base::Hex]] {
+            assert_eq!(
+                doc.clone()
+                    .to_json()
+                    .literals(&bases)
+                    .compact(true)
+                    .to_text()
+                    .unwrap(),
+                "[8, 0x10]"
+            );
+        }
+    }
+
+    #[test]
+    fn color_sampling() -> Result<()> {
+        let doc = Document::Sequence((0..100).map(int).collect());
+        let plain = doc.clone().to_json().to_text()?;
+        let sampled = doc
+            .clone()
+            .to_json()
+            .color(ColorProfile::basic())
+            .color_sampling(64)
+            .to_text()?;
+        // Only the beginning of the output is colorized.
+        assert!(sampled.rfind('\x1b').unwrap() < 80);
+        let escapes = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+        assert_eq!(escapes.replace_all(&sampled, ""), plain);
+
+        let err = doc.clone().to_json().max_output(64).emit().unwrap_err();
+        assert!(matches!(err, Error::OutputLimit(64)));
+        assert!(doc.to_json().max_output(plain.len()).emit().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn max_depth() -> Result<()> {
+        let mut doc = int(1);
+        for i in 0..4 {
+            doc = match i % 2 {
+                0 => Document::Sequence(vec![doc]),
+                _ => Document::Mapping(vec![kv("a", doc)]),
+            };
+        }
+        assert!(doc.clone().to_json().max_depth(4).emit().is_ok());
+        let err = doc.clone().to_json().max_depth(3).emit().unwrap_err();
+        assert!(matches!(err, Error::DepthLimit(3)));
+        // The limit also applies to nodes short enough to be emitted compactly.
+        let err = doc
+            .to_json5()
+            .compact_threshold(80)
+            .max_depth(2)
+            .emit()
+            .unwrap_err();
+        assert!(matches!(err, Error::DepthLimit(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn unicode_bareword() -> Result<()> {
+        let doc = Document::Mapping(vec![kv("größe", int(42)), kv("日本", int(7))]);
+        assert_eq!(
+            doc.clone().to_json5().compact(true).to_text()?,
+            r#"{"größe": 42, "日本": 7}"#
+        );
+        let text = doc
+            .clone()
+            .to_json5()
+            .compact(true)
+            .bareword(Bareword::Unicode)
+            .to_text()?;
+        assert_eq!(text, "{größe: 42, 日本: 7}");
+        assert!(Document::parse(&text)?.eq_semantic(&doc));
+        // Restricting the output to ASCII quotes the keys regardless.
+        assert_eq!(
+            doc.to_json5()
+                .compact(true)
+                .bareword(Bareword::Unicode)
+                .ascii_only(true)
+                .to_text()?,
+            r#"{"gr\u00f6\u00dfe": 42, "\u65e5\u672c": 7}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn emit_tee() -> Result<()> {
+        // Accumulates a checksum of the bytes written to it.
+        #[derive(Default)]
+        struct Checksum(u32);
+        impl io::Write for Checksum {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                for b in buf {
+                    self.0 = self.0.rotate_left(5) ^ *b as u32;
+                }
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let doc = Document::parse("{\n  // Comment.\n  a: [1, 2],\n  b: 'text'\n}")?;
+        let json = doc.to_json5().newline(Newline::CrLf);
+        let (mut first, mut second, mut sum) = (Vec::new(), Vec::new(), Checksum::default());
+        json.emit_tee(&mut [&mut first, &mut second, &mut sum])?;
+        let text = json.to_text()?;
+        assert_eq!(String::from_utf8(first).unwrap(), text);
+        assert_eq!(String::from_utf8(second).unwrap(), text);
+        let mut expected = Checksum::default();
+        io::Write::write_all(&mut expected, text.as_bytes())?;
+        assert_eq!(sum.0, expected.0);
+
+        // Errors from a sink are reported.
+        let mut full = [0u8; 8];
+        let err = json
+            .emit_tee(&mut [&mut io::Cursor::new(&mut full[..])])
+            .unwrap_err();
+        assert!(matches!(err, Error::IoError(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_emit() -> Result<()> {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Json>();
+        assert_send_sync::<JsonOptions>();
+
+        let json = Document::parse("{a: [1, 2], b: {c: 'text'}}")?
+            .to_json5()
+            .sort_keys(KeySort::custom(|a, b| b.cmp(a)))
+            .override_at("/a", |o| o.compact(true));
+        let expected = json.to_text()?;
+        std::thread::scope(|s| {
+            let handles = (0..4)
+                .map(|_| s.spawn(|| json.to_text().unwrap()))
+                .collect::<Vec<_>>();
+            for h in handles {
+                assert_eq!(h.join().unwrap(), expected);
+            }
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn to_writer() -> Result<()> {
+        let doc = Document::parse("{\n  // Comment.\n  a: [1, 2]\n}")?;
+        let json = doc.to_json5();
+        let mut out = io::BufWriter::new(Vec::new());
+        json.to_writer(&mut out)?;
+        let out = out.into_inner().map_err(|e| e.into_error())?;
+        assert_eq!(String::from_utf8(out).unwrap(), json.to_text()?);
+        let err = json
+            .to_writer(&mut io::Cursor::new(&mut [0u8; 4][..]))
+            .unwrap_err();
+        assert!(matches!(err, Error::IoError(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn identified_keys() -> Result<()> {
+        let (key, _) = Document::from("a").identify();
+        let (num, _) = int(1).identify();
+        let doc = Document::Mapping(vec![
+            Document::Fragment(vec![key, int(1)]),
+            Document::Fragment(vec![num, int(2)]),
+        ]);
+        assert_eq!(
+            doc.to_json().compact(true).to_text()?,
+            r#"{"a": 1, "1": 2}"#
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,391 @@
+// The laid out form of an emitted document.
+use crate::color::{ColorProfile, HtmlProfile};
+use ansi_term::Style;
+use std::fmt;
+
+/// The syntactic class of a token, which selects its style when rendered.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    /// Text without a class, such as the whitespace between tokens.
+    Plain,
+    /// Aggregate symbols (`[]{}`).
+    Aggregate,
+    /// Punctuation symbols (`"',:`).
+    Punctuation,
+    Comment,
+    Null,
+    /// Quoted keys, and keys of any kind if the profile does not style
+    /// unquoted keys separately.
+    Key,
+    /// Unquoted keys.
+    BareKey,
+    String,
+    /// Escape sequences in strings.
+    Escape,
+    Boolean,
+    Integer,
+    /// Integers emitted as quoted strings.
+    QuotedInteger,
+    Float,
+    /// A user-defined color class (see `Document::color_class`).
+    Class(String),
+}
+
+impl TokenClass {
+    /// Returns the name of the class, as used in the CSS class names of
+    /// HTML output.  A user-defined class is named by its own name.
+    pub fn name(&self) -> &str {
+        match self {
+            TokenClass::Plain => "plain",
+            TokenClass::Aggregate => "aggregate",
+            TokenClass::Punctuation => "punctuation",
+            TokenClass::Comment => "comment",
+            TokenClass::Null => "null",
+            TokenClass::Key => "key",
+            TokenClass::BareKey => "bare-key",
+            TokenClass::String => "string",
+            TokenClass::Escape => "escape",
+            TokenClass::Boolean => "boolean",
+            TokenClass::Integer => "integer",
+            TokenClass::QuotedInteger => "quoted-integer",
+            TokenClass::Float => "float",
+            TokenClass::Class(name) => name,
+        }
+    }
+}
+
+/// A run of text of a single class.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub class: TokenClass,
+}
+
+/// A line of output: its indentation (in columns) and its tokens.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Line {
+    pub indent: usize,
+    pub tokens: Vec<Token>,
+}
+
+impl Line {
+    /// Returns the width of the line in characters, including indentation.
+    pub fn width(&self) -> usize {
+        self.indent
+            + self
+                .tokens
+                .iter()
+                .map(|t| t.text.chars().count())
+                .sum::<usize>()
+    }
+}
+
+/// The layout of an emitted document as lines of classified tokens.
+///
+/// A layout is the intermediate form between a `Document` and its text:
+/// it records where the emitter broke lines, how deeply each line is
+/// indented and the class of every token, without committing to colors.
+/// It may be inspected (e.g. to export semantic tokens to an editor or to
+/// check a width budget), rendered with any `ColorProfile`, or displayed as
+/// plain text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    pub lines: Vec<Line>,
+}
+
+impl Layout {
+    /// Captures the layout of the output written by `emit`, which reports
+    /// the class of each token to the writer it is given.
+    pub(crate) fn capture<E, F>(emit: F) -> Result<Layout, E>
+    where
+        F: FnOnce(&mut LayoutWriter) -> Result<(), E>,
+    {
+        let mut w = LayoutWriter {
+            layout: Layout {
+                lines: vec![Line::default()],
+            },
+        };
+        emit(&mut w)?;
+        Ok(w.layout)
+    }
+
+    /// Returns the width of the widest line in characters.
+    pub fn width(&self) -> usize {
+        self.lines.iter().map(Line::width).max().unwrap_or(0)
+    }
+
+    /// Returns each token along with its zero-based line and column.
+    pub fn tokens(&self) -> impl Iterator<Item = (usize, usize, &Token)> {
+        self.lines.iter().enumerate().flat_map(|(row, line)| {
+            let mut column = line.indent;
+            line.tokens.iter().map(move |t| {
+                let col = column;
+                column += t.text.chars().count();
+                (row, col, t)
+            })
+        })
+    }
+
+    /// Writes the layout as text, styling each token with `profile`.
+    pub fn render<W: fmt::Write>(&self, w: &mut W, profile: &ColorProfile) -> fmt::Result {
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                writeln!(w)?;
+            }
+            write!(w, "{:1$}", "", line.indent)?;
+            for token in line.tokens.iter() {
+                match token.class {
+                    TokenClass::Plain => write!(w, "{}", token.text)?,
+                    ref c => write!(w, "{}", profile.style(c).paint(token.text.as_str()))?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Layout {
+    /// Writes the layout as HTML, marking up each token with a `<span>` of
+    /// the CSS class selected by `profile`.  Text is escaped as needed.
+    pub fn render_html<W: fmt::Write>(&self, w: &mut W, profile: &HtmlProfile) -> fmt::Result {
+        if profile.pre {
+            write!(w, "<pre class=\"{}\">", escape_html(&profile.prefix))?;
+        }
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                writeln!(w)?;
+            }
+            write!(w, "{:1$}", "", line.indent)?;
+            for token in line.tokens.iter() {
+                match token.class {
+                    TokenClass::Plain => write!(w, "{}", escape_html(&token.text))?,
+                    ref c => write!(
+                        w,
+                        "<span class=\"{}-{}\">{}</span>",
+                        escape_html(&profile.prefix),
+                        escape_html(c.name()),
+                        escape_html(&token.text)
+                    )?,
+                }
+            }
+        }
+        if profile.pre {
+            write!(w, "</pre>")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the layout as HTML.  See `Layout::render_html`.
+    pub fn to_html(&self, profile: &HtmlProfile) -> String {
+        let mut html = String::new();
+        // Unwrap is ok: writing to a String cannot fail.
+        self.render_html(&mut html, profile).unwrap();
+        html
+    }
+}
+
+// Escapes the characters of `text` which are special in HTML.
+fn escape_html(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => s.push_str("&amp;"),
+            '<' => s.push_str("&lt;"),
+            '>' => s.push_str("&gt;"),
+            '"' => s.push_str("&quot;"),
+            '\'' => s.push_str("&#39;"),
+            c => s.push(c),
+        }
+    }
+    s
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(f, &ColorProfile::default())
+    }
+}
+
+/// A writer of emitted text which is told the class of each token.
+///
+/// The emitters write tokens through `write_token` and the text between
+/// them, such as whitespace, through `write_str`.  Every `fmt::Write` is a
+/// sink which renders each token in its style; a `LayoutWriter` records the
+/// tokens in a `Layout` instead.
+pub(crate) trait TokenSink {
+    fn write_str(&mut self, s: &str) -> fmt::Result;
+
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> fmt::Result;
+
+    // Writes `text` as a token of `class`, which is rendered in `style`.
+    fn write_token(&mut self, class: &TokenClass, style: Style, text: &str) -> fmt::Result;
+}
+
+impl<W: fmt::Write + ?Sized> TokenSink for W {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        fmt::Write::write_str(self, s)
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> fmt::Result {
+        fmt::Write::write_fmt(self, args)
+    }
+
+    fn write_token(&mut self, _class: &TokenClass, style: Style, text: &str) -> fmt::Result {
+        fmt::Write::write_fmt(self, format_args!("{}", style.paint(text)))
+    }
+}
+
+// Builds a `Layout` from the tokens reported by an emitter.
+pub(crate) struct LayoutWriter {
+    layout: Layout,
+}
+
+impl LayoutWriter {
+    fn push(&mut self, class: &TokenClass, text: &str) {
+        for ch in text.chars() {
+            // Unwrap is ok: the layout always has a line.
+            let line = self.layout.lines.last_mut().unwrap();
+            match ch {
+                '\n' => self.layout.lines.push(Line::default()),
+                '\r' => {}
+                ' ' if line.tokens.is_empty() && *class == TokenClass::Plain => line.indent += 1,
+                _ => match line.tokens.last_mut() {
+                    Some(t) if t.class == *class => t.text.push(ch),
+                    _ => line.tokens.push(Token {
+                        text: ch.to_string(),
+                        class: class.clone(),
+                    }),
+                },
+            }
+        }
+    }
+}
+
+impl TokenSink for LayoutWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(&TokenClass::Plain, s);
+        Ok(())
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> fmt::Result {
+        match args.as_str() {
+            Some(s) => self.push(&TokenClass::Plain, s),
+            None => self.push(&TokenClass::Plain, &args.to_string()),
+        }
+        Ok(())
+    }
+
+    fn write_token(&mut self, class: &TokenClass, _style: Style, text: &str) -> fmt::Result {
+        self.push(class, text);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    #[test]
+    fn test_layout() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // A comment.
+                key: "value",
+                list: [1, 2.5, true, null],
+            }"#,
+        )?;
+        let layout = doc.clone().to_json5().layout()?;
+        let json5 = doc.clone().to_json5().to_text()?;
+        assert_eq!(layout.to_string(), json5);
+        assert_eq!(layout.lines.len(), json5.lines().count());
+        assert_eq!(layout.width(), 15);
+        assert_eq!(layout.lines[1].indent, 2);
+
+        let tokens = layout
+            .tokens()
+            .filter(|(_, _, t)| t.class != TokenClass::Plain)
+            .map(|(row, col, t)| (row, col, t.text.as_str(), t.class.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(tokens[0], (0, 0, "{", TokenClass::Aggregate));
+        assert_eq!(tokens[1], (1, 2, "// A comment.", TokenClass::Comment));
+        assert_eq!(tokens[2], (2, 2, "key", TokenClass::BareKey));
+        assert!(tokens.contains(&(5, 4, "2.5", TokenClass::Float)));
+        assert!(tokens.contains(&(7, 4, "null", TokenClass::Null)));
+
+        // Rendering with a profile matches emitting with that profile.
+        let profile = ColorProfile::basic();
+        let mut colored = String::new();
+        layout.render(&mut colored, &profile)?;
+        assert_eq!(colored, doc.clone().to_json5().color(profile).to_text()?);
+
+        let yaml = doc.clone().to_yaml().layout()?;
+        assert_eq!(yaml.to_string(), doc.to_yaml().to_text()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_html() -> Result<()> {
+        let doc = Document::parse("{\n// <b>\nkey: \"a&b\", num: [1, 2.5]}")?
+            .color_class("/num", "warning");
+        let html = doc.clone().to_json5().to_html(&HtmlProfile::default())?;
+        assert_eq!(
+            html,
+            r#"<pre class="json"><span class="json-aggregate">{</span>
+  <span class="json-comment">// &lt;b&gt;</span>
+  <span class="json-bare-key">key</span><span class="json-punctuation">: &quot;</span><span class="json-string">a&amp;b</span><span class="json-punctuation">&quot;,</span>
+  <span class="json-bare-key">num</span><span class="json-punctuation">: </span><span class="json-warning">[</span>
+    <span class="json-warning">1,</span>
+    <span class="json-warning">2.5</span>
+  <span class="json-warning">]</span>
+<span class="json-aggregate">}</span></pre>"#
+        );
+        let profile = HtmlProfile::default().prefix("cfg").pre(false);
+        let html = doc.to_yaml().to_html(&profile)?;
+        assert_eq!(
+            html,
+            r#"---
+<span class="cfg-comment"># &lt;b&gt;</span>
+<span class="cfg-bare-key">key</span><span class="cfg-punctuation">:</span> <span class="cfg-string">a&amp;b</span>
+<span class="cfg-bare-key">num</span><span class="cfg-punctuation">:</span>
+  <span class="cfg-warning">-</span> <span class="cfg-warning">1</span>
+  <span class="cfg-warning">-</span> <span class="cfg-warning">2.5</span>"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_layout_color_class() -> Result<()> {
+        let doc = Document::parse("{a: 1, b: 2}")?.color_class("/b", "warning");
+        let layout = doc.to_json().layout()?;
+        let class = layout
+            .tokens()
+            .find(|(_, _, t)| t.text == "2")
+            .map(|(_, _, t)| t.class.clone());
+        assert_eq!(class, Some(TokenClass::Class("warning".into())));
+
+        // Classes are reported by name, however many there are.
+        let keys = (0..300)
+            .map(|i| format!("k{}: {}", i, i))
+            .collect::<Vec<_>>();
+        let mut doc = Document::parse(&format!("{{{}}}", keys.join(", ")))?;
+        for i in 0..300 {
+            doc = doc.color_class(&format!("/k{}", i), &format!("c{}", i));
+        }
+        let layout = doc.to_json().layout()?;
+        let class = layout
+            .tokens()
+            .find(|(_, _, t)| t.text == "299")
+            .map(|(_, _, t)| t.class.clone());
+        assert_eq!(class, Some(TokenClass::Class("c299".into())));
+
+        // Text which looks like an escape sequence is kept as text.
+        let doc = Document::parse("{\n// \x1b[38;5;3mred\na: 1}")?;
+        let layout = doc.to_json5().layout()?;
+        assert_eq!(layout.lines[1].tokens[0].text, "// \x1b[38;5;3mred");
+        assert_eq!(layout.lines[1].tokens[0].class, TokenClass::Comment);
+        Ok(())
+    }
+}
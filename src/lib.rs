@@ -1,27 +1,115 @@
 #![feature(min_specialization)]
 
 pub mod annotate;
+mod annotated;
+mod ascii;
+mod binary;
+mod bytes;
+mod c_header;
+mod canonical;
+mod check;
+mod coerce;
 mod color;
+mod compare;
+mod csv;
 mod de;
+mod diff;
 mod doc_iter;
 mod document;
+mod dump;
+mod edit_script;
+mod emit;
+mod encoding;
+mod entry;
 mod error;
+mod graph;
 mod hexdump;
+mod highlight;
+mod ini;
 mod integer;
 mod json;
+mod layout;
+mod loader;
+#[cfg(feature = "log")]
+mod logger;
+mod markdown;
+mod merge;
+mod newline;
+mod node_id;
+mod number;
 mod partial;
+mod path;
+mod progress;
+mod properties;
+mod reconcile;
 mod relax;
+mod render;
+mod ron;
+mod runs;
+mod rust;
+mod rust_types;
+mod schema;
 mod ser;
+mod sexpr;
+mod sink;
+mod sort;
+mod source_map;
+mod stream;
+mod structural;
+mod toml;
+mod transform;
+mod unambiguous;
+mod width;
+mod wizard;
 mod yaml;
 
 pub use annotate::Annotate;
 pub use annotate_derive::*;
-pub use color::ColorProfile;
+pub use annotated::Annotated;
+pub use ascii::AsciiComments;
+pub use binary::{Cbor, MsgPack, CBOR_COMMENT_TAG};
+pub use bytes::SharedBytes;
+pub use c_header::{CHeader, NameCase};
+pub use check::FormatCheck;
+pub use coerce::Coercion;
+pub use color::{ColorProfile, HtmlProfile};
+pub use csv::Csv;
 pub use de::{from_str, Deserialize, Deserializer};
+pub use diff::{word_diff, Change, Diff};
 pub use doc_iter::DocPath;
-pub use document::{BytesFormat, CommentFormat, Document, StrFormat};
+pub use document::{BytesFormat, CommentFormat, Document, SerdeType, StrFormat};
+pub use emit::{CommonOptions, Emit};
+pub use encoding::{from_bytes_lossy, Encoding};
+pub use entry::{MapEntry, SeqItem};
 pub use error::Error;
-pub use integer::{Int, IntValue};
-pub use json::Json;
+pub use highlight::highlight;
+pub use ini::Ini;
+pub use integer::{Base, Int, IntValue, NegativeFormat};
+pub use json::{
+    AggregateCommentPlacement, BracePlacement, CommentPlacement, CommentPolicy, DigitGrouping,
+    DroppedComment, Json, JsonOptions, Multiline, NonFinite,
+};
+pub use layout::{Layout, Line, Token, TokenClass};
+pub use loader::ConfigLoader;
+#[cfg(feature = "log")]
+pub use logger::LogSink;
+pub use merge::CommentMerge;
+pub use newline::Newline;
+pub use node_id::NodeId;
+pub use number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+pub use progress::Progress;
+pub use properties::Properties;
+pub use reconcile::reconcile;
+pub use ron::Ron;
+pub use runs::RunStyle;
+pub use rust::{RustItem, RustSource};
 pub use ser::{serialize, AnnotatedSerializer};
-pub use yaml::Yaml;
+pub use sexpr::{Sexpr, SexprDialect, SexprSequence};
+pub use sort::{KeyComparator, KeySort};
+pub use source_map::{Position, SourceMap, Span};
+pub use stream::JsonStreamWriter;
+pub use toml::Toml;
+pub use transform::ERROR_CLASS;
+pub use unambiguous::{is_bareword, is_unambiguous_key, is_unambiguous_value, Bareword, Dialect};
+pub use wizard::Wizard;
+pub use yaml::{VariantTags, Yaml, YamlCommentPlacement, YamlStream};
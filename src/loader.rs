@@ -0,0 +1,521 @@
+// Loading layered configuration files into typed values.
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+
+use crate::de::Deserializer;
+use crate::document::Document;
+use crate::error::Error;
+use crate::merge::{CommentMerge, Merger};
+
+// The mapping key which names files to be merged into the enclosing mapping.
+const INCLUDE_KEY: &str = "$include";
+
+enum Layer {
+    Text(String),
+    File(PathBuf, bool),
+}
+
+type Validator<T> = Box<dyn Fn(&T) -> Result<(), String>>;
+
+/// Loads a typed configuration value from one or more layered sources.
+///
+/// Each layer is parsed with the maximally permissive parser, `${NAME}`
+/// references in string values are expanded and `$include` directives are
+/// resolved.  The layers are then merged in the order they were added:
+/// mappings are merged key by key, while any other value in a later layer
+/// replaces the value of an earlier layer.  Finally, the merged document is
+/// deserialized into a `T` and checked by the registered validators.
+///
+/// ```
+/// # use serde_annotate::ConfigLoader;
+/// # #[derive(serde::Deserialize)]
+/// # struct Config { port: u16 }
+/// let (config, doc) = ConfigLoader::<Config>::new()
+///     .text("{port: 80}")
+///     .text("{port: 8080}")
+///     .load()?;
+/// assert_eq!(config.port, 8080);
+/// # Ok::<(), serde_annotate::Error>(())
+/// ```
+pub struct ConfigLoader<T> {
+    layers: Vec<Layer>,
+    interpolate: bool,
+    environment: bool,
+    vars: HashMap<String, String>,
+    includes: bool,
+    comments: CommentMerge,
+    validators: Vec<Validator<T>>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Default for ConfigLoader<T> {
+    fn default() -> Self {
+        ConfigLoader {
+            layers: Vec::new(),
+            interpolate: true,
+            environment: true,
+            vars: HashMap::new(),
+            includes: true,
+            comments: CommentMerge::default(),
+            validators: Vec::new(),
+            _type: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> ConfigLoader<T> {
+    /// Creates a loader with no layers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a layer parsed from `text`.  Includes in a text layer are
+    /// resolved relative to the current directory.
+    pub fn text(mut self, text: &str) -> Self {
+        self.layers.push(Layer::Text(text.to_string()));
+        self
+    }
+
+    /// Adds a layer read from the file at `path`.  A missing file is an
+    /// error when the configuration is loaded.
+    pub fn file(mut self, path: impl AsRef<Path>) -> Self {
+        self.layers
+            .push(Layer::File(path.as_ref().to_path_buf(), false));
+        self
+    }
+
+    /// Adds a layer read from the file at `path` if the file exists (e.g.
+    /// a per-user override of a system-wide configuration).
+    pub fn optional_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.layers
+            .push(Layer::File(path.as_ref().to_path_buf(), true));
+        self
+    }
+
+    /// Sets whether `${NAME}` references in string values are expanded.
+    ///
+    /// A reference may supply a default with `${NAME:-default}`, and `$${`
+    /// produces a literal `${`.  A reference to an undefined variable
+    /// without a default is an error.
+    pub fn interpolate(mut self, enable: bool) -> Self {
+        self.interpolate = enable;
+        self
+    }
+
+    /// Sets whether variable references may be resolved from the process
+    /// environment.  Variables defined with `var` are always available.
+    pub fn environment(mut self, enable: bool) -> Self {
+        self.environment = enable;
+        self
+    }
+
+    /// Defines a variable for interpolation, taking precedence over the
+    /// process environment.
+    pub fn var(mut self, name: &str, value: &str) -> Self {
+        self.vars.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Sets whether `$include` directives are resolved.
+    ///
+    /// A mapping containing the key `$include` with a path (or a sequence
+    /// of paths) as its value has the mappings in those files merged into
+    /// it, with the entries of the including mapping taking precedence.
+    /// Relative paths are resolved against the directory of the including
+    /// file.  When disabled, `$include` is an ordinary key.
+    pub fn includes(mut self, enable: bool) -> Self {
+        self.includes = enable;
+        self
+    }
+
+    /// Sets how the comments of entries with the same key in different
+    /// layers (or in included files) are combined.  By default, the comments
+    /// of the latest entry are kept.  For `CommentMerge::Origin`, the origin
+    /// of an entry is its file name, or `layer N` for a text layer.
+    pub fn comments(mut self, policy: CommentMerge) -> Self {
+        self.comments = policy;
+        self
+    }
+
+    /// Adds a validation check run on the deserialized value.  The message
+    /// returned by a failing check is reported as `Error::ValidationError`.
+    pub fn validate(mut self, check: impl Fn(&T) -> Result<(), String> + 'static) -> Self {
+        self.validators.push(Box::new(check));
+        self
+    }
+
+    /// Loads, merges and validates all layers, returning the typed value
+    /// along with the merged document.  The document retains the comments
+    /// of the source files and may be re-emitted.
+    pub fn load(&self) -> Result<(T, Document), Error> {
+        let mut merged: Option<(Document, Merger)> = None;
+        for (i, layer) in self.layers.iter().enumerate() {
+            let (doc, origin) = match layer {
+                Layer::Text(text) => (
+                    self.parse(text, Path::new(""), &mut Vec::new())?,
+                    format!("layer {}", i + 1),
+                ),
+                Layer::File(path, optional) => {
+                    if *optional && !path.exists() {
+                        continue;
+                    }
+                    (
+                        self.parse_file(path, &mut Vec::new())?,
+                        path.display().to_string(),
+                    )
+                }
+            };
+            match &mut merged {
+                Some((base, merger)) => merger.merge(base, doc, &origin),
+                None => merged = Some((doc, Merger::new(&self.comments, &origin))),
+            }
+        }
+        let doc = merged
+            .map(|(doc, _)| doc)
+            .ok_or_else(|| Error::ConfigError("no configuration layers".into()))?;
+        let mut ds = Deserializer::from_document(&doc)?;
+        let value = T::deserialize(&mut ds)?;
+        for check in self.validators.iter() {
+            check(&value).map_err(Error::ValidationError)?;
+        }
+        Ok((value, doc))
+    }
+
+    fn parse_file(&self, path: &Path, stack: &mut Vec<PathBuf>) -> Result<Document, Error> {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| Error::ConfigError(format!("{}: {}", path.display(), e)))?;
+        if stack.contains(&canonical) {
+            return Err(Error::ConfigError(format!(
+                "include cycle at {}",
+                path.display()
+            )));
+        }
+        let text = std::fs::read_to_string(&canonical)
+            .map_err(|e| Error::ConfigError(format!("{}: {}", path.display(), e)))?;
+        stack.push(canonical);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let doc = self.parse(&text, dir, stack);
+        stack.pop();
+        doc
+    }
+
+    fn parse(&self, text: &str, dir: &Path, stack: &mut Vec<PathBuf>) -> Result<Document, Error> {
+        let mut doc = Document::parse(text)?;
+        if self.interpolate {
+            self.interpolate_node(&mut doc)?;
+        }
+        if self.includes {
+            self.include_node(&mut doc, dir, stack)?;
+        }
+        Ok(doc)
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        self.vars.get(name).cloned().or_else(|| {
+            if self.environment {
+                std::env::var(name).ok()
+            } else {
+                None
+            }
+        })
+    }
+
+    fn expand(&self, text: &str) -> Result<String, Error> {
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(i) = rest.find('$') {
+            result.push_str(&rest[..i]);
+            rest = &rest[i..];
+            if let Some(r) = rest.strip_prefix("$${") {
+                result.push_str("${");
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("${") {
+                let end = r.find('}').ok_or_else(|| {
+                    Error::ConfigError(format!("unterminated `${{` in {:?}", text))
+                })?;
+                let (name, default) = match r[..end].split_once(":-") {
+                    Some((name, default)) => (name, Some(default)),
+                    None => (&r[..end], None),
+                };
+                let value = self
+                    .lookup(name)
+                    .or_else(|| default.map(str::to_string))
+                    .ok_or_else(|| Error::ConfigError(format!("undefined variable `{}`", name)))?;
+                result.push_str(&value);
+                rest = &r[end + 1..];
+            } else {
+                result.push('$');
+                rest = &rest[1..];
+            }
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+
+    // Expands variable references in the string values beneath `node`.
+    // Mapping keys are not expanded.
+    fn interpolate_node(&self, node: &mut Document) -> Result<(), Error> {
+        match node {
+            Document::String(s, _) => *s = self.expand(s)?,
+            Document::Mapping(m) => {
+                for entry in m.iter_mut() {
+                    if let Ok((_, v)) = entry.as_kv_mut() {
+                        self.interpolate_node(v)?;
+                    }
+                }
+            }
+            Document::Sequence(nodes) | Document::Fragment(nodes) => {
+                for n in nodes.iter_mut() {
+                    self.interpolate_node(n)?;
+                }
+            }
+            Document::Compact(n)
+            | Document::ColorClass(_, n)
+            | Document::Identified(_, n)
+            | Document::Typed(_, n) => self.interpolate_node(n)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Resolves the `$include` directives beneath `node`.
+    fn include_node(
+        &self,
+        node: &mut Document,
+        dir: &Path,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), Error> {
+        match node {
+            Document::Mapping(m) => {
+                let mut paths = Vec::new();
+                let mut i = 0;
+                while i < m.len() {
+                    if is_include(&m[i]) {
+                        let entry = m.remove(i);
+                        // Unwrap is ok: `is_include` checked the entry is a kvpair.
+                        let (_, v) = entry.as_kv().unwrap();
+                        paths.extend(include_paths(v)?);
+                    } else {
+                        if let Ok((_, v)) = m[i].as_kv_mut() {
+                            self.include_node(v, dir, stack)?;
+                        }
+                        i += 1;
+                    }
+                }
+                if paths.is_empty() {
+                    return Ok(());
+                }
+                let local = std::mem::take(m);
+                let mut included: Option<(Document, Merger)> = None;
+                for path in paths {
+                    let doc = into_value(self.parse_file(&dir.join(&path), stack)?);
+                    match &mut included {
+                        Some((base, merger)) => merger.merge(base, doc, &path),
+                        None => included = Some((doc, Merger::new(&self.comments, &path))),
+                    }
+                }
+                // Unwrap is ok: there is at least one path.
+                let (mut included, mut merger) = included.unwrap();
+                if !local.is_empty() {
+                    // The including file is at the top of the stack.
+                    let origin = match stack.last() {
+                        Some(path) => path.display().to_string(),
+                        None => "text".to_string(),
+                    };
+                    merger.merge(&mut included, Document::Mapping(local), &origin);
+                }
+                *node = included;
+            }
+            Document::Sequence(nodes) | Document::Fragment(nodes) => {
+                for n in nodes.iter_mut() {
+                    self.include_node(n, dir, stack)?;
+                }
+            }
+            Document::Compact(n)
+            | Document::ColorClass(_, n)
+            | Document::Identified(_, n)
+            | Document::Typed(_, n) => self.include_node(n, dir, stack)?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn is_include(entry: &Document) -> bool {
+    entry.as_kv().map_or(false, |(k, _)| {
+        k.as_str().map_or(false, |k| k == INCLUDE_KEY)
+    })
+}
+
+fn include_paths(value: &Document) -> Result<Vec<String>, Error> {
+    match value.as_value()? {
+        Document::Sequence(s) => s
+            .iter()
+            .filter(|n| n.has_value())
+            .map(|n| n.as_value()?.as_str().map(str::to_string))
+            .collect(),
+        v => Ok(vec![v.as_str()?.to_string()]),
+    }
+}
+
+// Discards the comments surrounding the root value of a document.
+fn into_value(doc: Document) -> Document {
+    match doc {
+        Document::Fragment(nodes) if nodes.iter().filter(|n| n.has_value()).count() == 1 => {
+            // Unwrap is ok: there is exactly one value.
+            nodes.into_iter().find(Document::has_value).unwrap()
+        }
+        doc => doc,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+    use serde_derive::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        name: String,
+        server: Server,
+    }
+
+    const BASE: &str = r#"
+        // Application settings.
+        {
+            name: "app",
+            // Where to listen.
+            server: {host: "localhost", port: 80},
+        }"#;
+
+    #[test]
+    fn test_layers() -> Result<()> {
+        let (config, doc) = ConfigLoader::<Config>::new()
+            .text(BASE)
+            .text("{server: {port: 8080}}")
+            .load()?;
+        assert_eq!(
+            config,
+            Config {
+                name: "app".into(),
+                server: Server {
+                    host: "localhost".into(),
+                    port: 8080,
+                },
+            }
+        );
+        let s = doc.to_json5().to_text()?;
+        assert!(s.contains("// Application settings."));
+        assert!(s.contains("// Where to listen."));
+        assert!(s.contains("port: 8080"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_comment_merge() -> Result<()> {
+        let load = |policy| -> Result<String> {
+            let (_, doc) = ConfigLoader::<Config>::new()
+                .comments(policy)
+                .text(BASE)
+                .text("{server: {port: 8080}}")
+                .text("{\n// Overridden.\nserver: {port: 9090}}")
+                .load()?;
+            Ok(doc.to_json5().to_text()?)
+        };
+        let s = load(CommentMerge::Latest)?;
+        assert!(s.contains("// Overridden.\n  server:"));
+        assert!(!s.contains("// Where to listen."));
+        let s = load(CommentMerge::Earliest)?;
+        assert!(s.contains("// Where to listen.\n  server:"));
+        assert!(!s.contains("// Overridden."));
+        let s = load(CommentMerge::Concatenate(String::new()))?;
+        assert!(s.contains("// Where to listen.\n  // Overridden.\n  server:"));
+        let s = load(CommentMerge::Origin)?;
+        assert!(s.contains(
+            "// layer 1:\n  // Where to listen.\n  // layer 3:\n  // Overridden.\n  server:"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolation() -> Result<()> {
+        let (config, _) = ConfigLoader::<Config>::new()
+            .environment(false)
+            .var("HOST", "example.com")
+            .text(r#"{name: "$${literal} ${NAME:-app}", server: {host: "${HOST}", port: 1}}"#)
+            .load()?;
+        assert_eq!(config.name, "${literal} app");
+        assert_eq!(config.server.host, "example.com");
+
+        let result = ConfigLoader::<Config>::new()
+            .environment(false)
+            .text(r#"{name: "${MISSING}", server: {host: "", port: 1}}"#)
+            .load();
+        assert!(matches!(result, Err(Error::ConfigError(_))));
+
+        let (config, _) = ConfigLoader::<Config>::new()
+            .interpolate(false)
+            .text(r#"{name: "${MISSING}", server: {host: "", port: 1}}"#)
+            .load()?;
+        assert_eq!(config.name, "${MISSING}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_includes() -> Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("serde-annotate-loader-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("conf.d"))?;
+        std::fs::write(dir.join("conf.d/server.json5"), "{host: 'db', port: 5432}")?;
+        std::fs::write(
+            dir.join("main.json5"),
+            "{name: 'app', server: {$include: 'conf.d/server.json5', port: 6543}}",
+        )?;
+        std::fs::write(dir.join("cycle.json5"), "{$include: 'cycle.json5'}")?;
+
+        let (config, _) = ConfigLoader::<Config>::new()
+            .file(dir.join("main.json5"))
+            .optional_file(dir.join("missing.json5"))
+            .load()?;
+        assert_eq!(config.server.host, "db");
+        assert_eq!(config.server.port, 6543);
+
+        let result = ConfigLoader::<Config>::new()
+            .file(dir.join("cycle.json5"))
+            .load();
+        assert!(matches!(result, Err(Error::ConfigError(_))));
+
+        let result = ConfigLoader::<Config>::new()
+            .file(dir.join("missing.json5"))
+            .load();
+        assert!(matches!(result, Err(Error::ConfigError(_))));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        let loader = ConfigLoader::<Config>::new().text(BASE).validate(|c| {
+            if c.server.port < 1024 {
+                Err(format!("port {} is privileged", c.server.port))
+            } else {
+                Ok(())
+            }
+        });
+        let result = loader.load();
+        assert!(matches!(result, Err(Error::ValidationError(m)) if m == "port 80 is privileged"));
+        Ok(())
+    }
+}
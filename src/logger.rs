@@ -0,0 +1,183 @@
+// A `log` sink which emits annotated log records.
+use crate::document::{CommentFormat, Document, StrFormat};
+use crate::emit::Emit;
+use crate::entry::kv;
+use log::kv::{Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::io;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A `log::Log` implementation which writes each record as a compact,
+/// single-line JSON5 mapping preceded by a `#` comment line carrying the
+/// timestamp, level and target of the record:
+///
+/// ```text
+/// # 1663776000.123 INFO app::flash
+/// {msg: "erased sector", addr: 4096}
+/// ```
+///
+/// The message is stored under the `msg` key and any structured key-values
+/// attached to the record follow it.  The output can be read back with
+/// `Document::parse`, which accepts `#` comments.
+///
+/// Applications using `tracing` can route their events to this sink by
+/// enabling the `log` feature of the `tracing` crate.
+pub struct LogSink<W: io::Write + Send> {
+    writer: Mutex<W>,
+    level: LevelFilter,
+    timestamp: bool,
+}
+
+impl<W: io::Write + Send> LogSink<W> {
+    /// Creates a sink which writes records to `writer`.
+    pub fn new(writer: W) -> Self {
+        LogSink {
+            writer: Mutex::new(writer),
+            level: LevelFilter::Trace,
+            timestamp: true,
+        }
+    }
+
+    /// Set the maximum level of records written by the sink.
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Set whether the record comment includes a timestamp.
+    pub fn timestamp(mut self, b: bool) -> Self {
+        self.timestamp = b;
+        self
+    }
+
+    /// Consumes the sink and returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Converts a log record into an annotated document.
+    pub fn to_document(&self, record: &Record) -> Document {
+        let mut header = Vec::new();
+        if self.timestamp {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            header.push(format!("{}.{:03}", now.as_secs(), now.subsec_millis()));
+        }
+        header.push(record.level().to_string());
+        header.push(record.target().to_string());
+
+        let mut fields = Fields(vec![kv(
+            "msg",
+            Document::String(record.args().to_string(), StrFormat::Standard),
+        )]);
+        // Visiting our own collector never fails.
+        let _ = record.key_values().visit(&mut fields);
+        Document::Fragment(vec![
+            Document::Comment(header.join(" "), CommentFormat::Hash),
+            Document::Compact(Box::new(Document::Mapping(fields.0))),
+        ])
+    }
+}
+
+impl<W: io::Write + Send> Log for LogSink<W> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let json = self
+            .to_document(record)
+            .to_json5()
+            .comment(&[CommentFormat::Hash])
+            .standard_comment(CommentFormat::Hash);
+        // A logger has nowhere to report its own errors.
+        if let (Ok(text), Ok(mut w)) = (json.to_text(), self.writer.lock()) {
+            let _ = writeln!(w, "{}", text);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut w) = self.writer.lock() {
+            let _ = w.flush();
+        }
+    }
+}
+
+// Collects the structured key-values of a record into mapping entries.
+struct Fields(Vec<Document>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = if let Some(v) = value.to_bool() {
+            Document::Boolean(v)
+        } else if let Some(v) = value.to_u64() {
+            Document::Int(v.into())
+        } else if let Some(v) = value.to_i64() {
+            Document::Int(v.into())
+        } else if let Some(v) = value.to_f64() {
+            Document::Float(v)
+        } else {
+            Document::String(value.to_string(), StrFormat::Standard)
+        };
+        self.0.push(kv(key.as_str(), value));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use log::Level;
+
+    fn emit(sink: &LogSink<Vec<u8>>, level: Level, msg: &str) {
+        let kvs: &[(&str, u32)] = &[("addr", 4096)];
+        sink.log(
+            &Record::builder()
+                .level(level)
+                .target("app::flash")
+                .args(format_args!("{}", msg))
+                .key_values(&kvs)
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_log_sink() -> Result<()> {
+        let sink = LogSink::new(Vec::new())
+            .timestamp(false)
+            .level(LevelFilter::Info);
+        emit(&sink, Level::Info, "erased sector");
+        emit(&sink, Level::Debug, "not logged");
+        let text = String::from_utf8(sink.into_inner())?;
+        assert_eq!(
+            text,
+            "# INFO app::flash\n{msg: \"erased sector\", addr: 4096}\n"
+        );
+        let doc = Document::parse(&text)?;
+        let (k, v) = match doc.as_value()? {
+            Document::Mapping(m) => m[0].as_kv()?,
+            _ => panic!("Expected a mapping"),
+        };
+        assert_eq!(k.as_str()?, "msg");
+        assert_eq!(v.as_str()?, "erased sector");
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_timestamp() {
+        let sink = LogSink::new(Vec::new());
+        emit(&sink, Level::Warn, "hot");
+        let text = String::from_utf8(sink.into_inner()).unwrap();
+        let header = text.lines().next().unwrap();
+        let parts = header.split(' ').collect::<Vec<_>>();
+        assert_eq!(parts[0], "#");
+        assert!(parts[1].parse::<f64>().is_ok());
+        assert_eq!(&parts[2..], &["WARN", "app::flash"]);
+    }
+}
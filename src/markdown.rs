@@ -0,0 +1,160 @@
+// Markdown table rendering of documents.
+use crate::document::Document;
+use crate::entry::{comments_of, value_of};
+use crate::path::pointer;
+use crate::render::display_text;
+
+impl Document {
+    /// Renders the document as a Markdown table with `Key`, `Value` and
+    /// `Comment` columns.
+    ///
+    /// Nested mappings and sequences are flattened so that each row holds a
+    /// single scalar value keyed by its JSON Pointer (e.g. `/c/k` or
+    /// `/d/0`).  Aggregates which carry comments get a row of their own with
+    /// an empty value cell so that section descriptions are not lost, and
+    /// empty aggregates get a row holding `{}` or `[]`.
+    pub fn to_markdown_table(&self) -> String {
+        let mut rows = Vec::new();
+        let mut path = Vec::new();
+        markdown_rows(&mut rows, &mut path, self);
+        let mut out = String::from("| Key | Value | Comment |\n| --- | --- | --- |\n");
+        for (key, value, comment) in rows {
+            out.push_str(&format!(
+                "| {} | {} | {} |\n",
+                escape(&key),
+                escape(&value),
+                escape(&comment)
+            ));
+        }
+        out
+    }
+}
+
+// Escapes text for use inside a Markdown table cell.
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', "<br>")
+}
+
+// Returns `text` as a Markdown code span, fenced by more backticks than
+// the longest run of them in `text`.
+fn code_span(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest + 1);
+    // A backtick at either end would merge with the fence, so it is padded
+    // with a space, which readers strip.
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", fence, text, fence)
+    } else {
+        format!("{}{}{}", fence, text, fence)
+    }
+}
+
+// Walks a node which may be a fragment holding comments alongside a value.
+fn markdown_rows(
+    rows: &mut Vec<(String, String, String)>,
+    path: &mut Vec<String>,
+    node: &Document,
+) {
+    let comments = comments_of(node)
+        .into_iter()
+        .map(|(c, _)| c)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let value = match value_of(node) {
+        Some(v) => v,
+        None => return,
+    };
+    let value = match value {
+        Document::Compact(v)
+        | Document::ColorClass(_, v)
+        | Document::Identified(_, v)
+        | Document::Typed(_, v) => v.as_ref(),
+        v => v,
+    };
+    let empty = match value {
+        Document::Mapping(items) | Document::Sequence(items) => {
+            !items.iter().any(|i| i.has_value())
+        }
+        _ => false,
+    };
+    match value {
+        _ if empty => {
+            rows.push((pointer(path), code_span(&display_text(value)), comments));
+        }
+        Document::Mapping(m) => {
+            if !comments.is_empty() {
+                rows.push((pointer(path), String::new(), comments));
+            }
+            for entry in m {
+                let key = match entry.as_kv() {
+                    Ok((k, _)) => match k.as_str() {
+                        Ok(k) => k.to_string(),
+                        Err(_) => display_text(k),
+                    },
+                    Err(_) => continue,
+                };
+                path.push(key);
+                markdown_rows(rows, path, entry);
+                path.pop();
+            }
+        }
+        Document::Sequence(s) => {
+            if !comments.is_empty() {
+                rows.push((pointer(path), String::new(), comments));
+            }
+            for (i, elem) in s.iter().filter(|e| e.has_value()).enumerate() {
+                path.push(i.to_string());
+                markdown_rows(rows, path, elem);
+                path.pop();
+            }
+        }
+        v => {
+            rows.push((pointer(path), code_span(&display_text(v)), comments));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_markdown_table() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // The name of the part.
+                name: "winbond",
+                // Flash geometry.
+                geometry: {
+                    // Size of a sector | in bytes.
+                    sector: 0x1000,
+                    banks: [1, 2],
+                    spare: [],
+                    // Unused.
+                    extra: {},
+                },
+                quote: "``a` b",
+            }"#,
+        )?;
+        assert_eq!(
+            doc.to_markdown_table(),
+            r#"| Key | Value | Comment |
+| --- | --- | --- |
+| /name | `"winbond"` | The name of the part. |
+| /geometry |  | Flash geometry. |
+| /geometry/sector | `0x1000` | Size of a sector \| in bytes. |
+| /geometry/banks/0 | `1` |  |
+| /geometry/banks/1 | `2` |  |
+| /geometry/spare | `[]` |  |
+| /geometry/extra | `{}` | Unused. |
+| /quote | ```"``a` b"``` |  |
+"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+// Coalescing of mapping entries with the same key.
+use std::collections::{HashMap, HashSet};
+
+use crate::document::{CommentFormat, Document};
+
+/// How to combine the comments of mapping entries with the same key when
+/// they are coalesced into a single entry (e.g. by the layers of a
+/// `ConfigLoader` or a parser's duplicate-key handling).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum CommentMerge {
+    /// Keep the comments of the latest entry, or those of the earlier entry
+    /// if the latest entry has none.
+    #[default]
+    Latest,
+    /// Keep the comments of the earliest entry, or those of the later entry
+    /// if the earliest entry has none.
+    Earliest,
+    /// Keep the comments of all entries in order.  A non-empty separator is
+    /// emitted as a comment between the comments of successive entries.
+    Concatenate(String),
+    /// Keep the comments of all entries in order, each preceded by a comment
+    /// naming its origin (a file name or a line number).
+    Origin,
+}
+
+impl Document {
+    /// Applies `patch` to this document: mappings are merged by key, an
+    /// entry whose value is `Document::Absent` removes the entry with that
+    /// key, and any other value (including `Document::Null`) replaces the
+    /// value in this document.  The comments of a patched entry are those
+    /// of the patch entry, or the existing comments if it has none.
+    pub fn merge_patch(&mut self, patch: Document) {
+        Merger::new(&CommentMerge::Latest, "").merge(self, patch, "");
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7396), in which a null mapping value
+    /// removes the entry with that key.  See `Document::merge_patch`.
+    pub fn json_merge_patch(&mut self, mut patch: Document) {
+        null_to_absent(&mut patch);
+        self.merge_patch(patch);
+    }
+}
+
+/// Returns the key of a mapping entry, if it is a string.
+pub(crate) fn entry_key(entry: &Document) -> Option<String> {
+    match entry.as_kv() {
+        Ok((k, _)) => k.as_str().ok().map(str::to_string),
+        Err(_) => None,
+    }
+}
+
+// Merges documents according to a `CommentMerge` policy, tracking the origin
+// of the entries for `CommentMerge::Origin`.
+pub(crate) struct Merger<'a> {
+    policy: &'a CommentMerge,
+    // The origin of the entries at and beneath each key path.
+    origins: HashMap<Vec<String>, String>,
+    // The key paths of entries whose comments are already labelled.
+    labelled: HashSet<Vec<String>>,
+}
+
+impl<'a> Merger<'a> {
+    /// Creates a merger for a base document originating from `origin`.
+    pub(crate) fn new(policy: &'a CommentMerge, origin: &str) -> Self {
+        let mut origins = HashMap::new();
+        origins.insert(Vec::new(), origin.to_string());
+        Merger {
+            policy,
+            origins,
+            labelled: HashSet::new(),
+        }
+    }
+
+    /// Records that the entry at `path` originates from `origin`.
+    pub(crate) fn set_origin(&mut self, path: Vec<String>, origin: &str) {
+        self.origins.insert(path, origin.to_string());
+    }
+
+    /// Merges `over`, originating from `origin`, into `base`.  Mappings are
+    /// merged by key; any other value in `over` replaces the value in `base`.
+    pub(crate) fn merge(&mut self, base: &mut Document, over: Document, origin: &str) {
+        self.merge_at(base, over, origin, &mut Vec::new());
+    }
+
+    fn merge_at(
+        &mut self,
+        base: &mut Document,
+        mut over: Document,
+        origin: &str,
+        path: &mut Vec<String>,
+    ) {
+        if let (Ok(Document::Mapping(b)), Ok(Document::Mapping(o))) =
+            (base.as_value_mut(), over.as_value_mut())
+        {
+            self.merge_mapping(b, std::mem::take(o), origin, path);
+            return;
+        }
+        self.forget(path);
+        self.origins.insert(path.clone(), origin.to_string());
+        remove_absent(&mut over);
+        *base = over;
+    }
+
+    // Forgets the origins and labels of the entries at and beneath `path`.
+    fn forget(&mut self, path: &[String]) {
+        self.origins.retain(|p, _| !p.starts_with(path));
+        self.labelled.retain(|p| !p.starts_with(path));
+    }
+
+    fn merge_mapping(
+        &mut self,
+        base: &mut Vec<Document>,
+        over: Vec<Document>,
+        origin: &str,
+        path: &mut Vec<String>,
+    ) {
+        for mut entry in over {
+            let key = entry_key(&entry);
+            let existing = key
+                .as_ref()
+                .and_then(|key| base.iter().position(|b| entry_key(b).as_ref() == Some(key)));
+            if is_absent_entry(&entry) {
+                if let (Some(key), Some(i)) = (key, existing) {
+                    path.push(key);
+                    self.forget(path);
+                    path.pop();
+                    base.remove(i);
+                }
+                continue;
+            }
+            match (key, existing) {
+                (Some(key), Some(i)) => {
+                    path.push(key);
+                    self.merge_entry(&mut base[i], entry, origin, path);
+                    path.pop();
+                }
+                (key, _) => {
+                    if let Some(key) = key {
+                        path.push(key);
+                        self.origins.insert(path.clone(), origin.to_string());
+                        path.pop();
+                    }
+                    remove_absent(&mut entry);
+                    base.push(entry);
+                }
+            }
+        }
+    }
+
+    /// Merges the mapping entry `over`, originating from `origin`, into the
+    /// entry `base` with the same key, which is at `path`.
+    pub(crate) fn merge_entry(
+        &mut self,
+        base: &mut Document,
+        mut over: Document,
+        origin: &str,
+        path: &mut Vec<String>,
+    ) {
+        let base_origin = self.origin(path).to_string();
+        // Unwraps are ok: both entries have a key, so both are kvpairs.
+        let (_, value) = over.as_kv_mut().unwrap();
+        let value = std::mem::replace(value, Document::Null);
+        let (_, base_value) = base.as_kv_mut().unwrap();
+        self.merge_at(base_value, value, origin, path);
+
+        let (Document::Fragment(b), Document::Fragment(o)) = (&mut *base, &mut over) else {
+            return;
+        };
+        let has_comments = |nodes: &[Document]| nodes.iter().any(|n| !n.has_value());
+        match self.policy {
+            CommentMerge::Latest => {
+                if has_comments(o) {
+                    adopt_comments(b, o);
+                }
+            }
+            CommentMerge::Earliest => {
+                if !has_comments(b) && has_comments(o) {
+                    adopt_comments(b, o);
+                }
+            }
+            CommentMerge::Concatenate(separator) => {
+                let later = comments(o);
+                if !later.is_empty() {
+                    let mut earlier = comments(b);
+                    if !earlier.is_empty() && !separator.is_empty() {
+                        earlier.push(Document::Comment(
+                            separator.clone(),
+                            CommentFormat::Standard,
+                        ));
+                    }
+                    earlier.extend(later);
+                    *b = with_comments(std::mem::take(b), earlier);
+                }
+            }
+            CommentMerge::Origin => {
+                let mut earlier = comments(b);
+                if !earlier.is_empty() && !self.labelled.contains(path) {
+                    earlier.insert(0, label(&base_origin));
+                }
+                let later = comments(o);
+                if !later.is_empty() {
+                    earlier.push(label(origin));
+                    earlier.extend(later);
+                }
+                *b = with_comments(std::mem::take(b), earlier);
+                self.labelled.insert(path.clone());
+            }
+        }
+    }
+
+    // Returns the origin of the entry at `path`.
+    fn origin(&self, path: &[String]) -> &str {
+        (0..=path.len())
+            .rev()
+            .find_map(|n| self.origins.get(&path[..n]))
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
+
+fn is_absent_entry(entry: &Document) -> bool {
+    matches!(entry.as_kv(), Ok((_, v)) if matches!(v.as_value(), Ok(Document::Absent)))
+}
+
+// Removes the mapping entries with absent values from `doc`, which is added
+// to a document without being merged.
+fn remove_absent(doc: &mut Document) {
+    match doc.as_value_mut() {
+        Ok(Document::Mapping(m)) => {
+            m.retain(|e| !is_absent_entry(e));
+            m.iter_mut().for_each(remove_absent);
+        }
+        Ok(Document::Sequence(s)) => s.iter_mut().for_each(remove_absent),
+        _ => {
+            if let Ok((_, v)) = doc.as_kv_mut() {
+                remove_absent(v);
+            }
+        }
+    }
+}
+
+// Replaces the null values of mapping entries in `doc` with absent values.
+fn null_to_absent(doc: &mut Document) {
+    if let Ok(Document::Mapping(m)) = doc.as_value_mut() {
+        for entry in m.iter_mut() {
+            if let Ok((_, v)) = entry.as_kv_mut() {
+                match v.as_value_mut() {
+                    Ok(n @ Document::Null) => *n = Document::Absent,
+                    _ => null_to_absent(v),
+                }
+            }
+        }
+    }
+}
+
+// Replaces the comments of the entry `base` with those of `over`, keeping the
+// layout of `over`.
+fn adopt_comments(base: &mut Vec<Document>, over: &mut Vec<Document>) {
+    let mut merged = std::mem::take(base).into_iter().filter(Document::has_value);
+    for n in over.iter_mut().filter(|n| n.has_value()) {
+        // Unwrap is ok: both fragments hold a key and a value.
+        *n = merged.next().unwrap();
+    }
+    *base = std::mem::take(over);
+}
+
+fn comments(nodes: &[Document]) -> Vec<Document> {
+    nodes.iter().filter(|n| !n.has_value()).cloned().collect()
+}
+
+// Returns the key and value of `nodes` preceded by `comments`.
+fn with_comments(nodes: Vec<Document>, mut comments: Vec<Document>) -> Vec<Document> {
+    comments.extend(nodes.into_iter().filter(Document::has_value));
+    comments
+}
+
+fn label(origin: &str) -> Document {
+    Document::Comment(format!("{}:", origin), CommentFormat::Standard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use crate::error::Error;
+    use anyhow::Result;
+
+    #[test]
+    fn test_merge_patch() -> Result<()> {
+        let mut doc = Document::parse(
+            r#"{
+                // The title.
+                title: "Goodbye!",
+                author: {given: "John", family: "Doe"},
+                tags: ["example", "sample"],
+                content: "This will be unchanged",
+            }"#,
+        )?;
+        // The example of RFC 7396, section 3.
+        let patch = Document::parse(
+            r#"{
+                title: "Hello!",
+                phoneNumber: "+01-123-456-7890",
+                author: {family: null},
+                tags: ["example"],
+                extra: {a: null, b: 1},
+            }"#,
+        )?;
+        let mut merged = doc.clone();
+        merged.json_merge_patch(patch.clone());
+        assert_eq!(
+            merged.to_json5().to_text()?,
+            r#"{
+  // The title.
+  title: "Hello!",
+  author: {
+    given: "John"
+  },
+  tags: [
+    "example"
+  ],
+  content: "This will be unchanged",
+  phoneNumber: "+01-123-456-7890",
+  extra: {
+    b: 1
+  }
+}"#
+        );
+
+        // Under `merge_patch`, null sets a null value and absent removes.
+        doc.merge_patch(patch);
+        doc.merge_patch(Document::Mapping(vec![
+            Document::Fragment(vec![Document::from("content"), Document::Absent]),
+            Document::Fragment(vec![Document::from("missing"), Document::Absent]),
+        ]));
+        assert_eq!(
+            doc.to_json5().compact(true).to_text()?,
+            r#"{title: "Hello!", author: {given: "John", family: null}, tags: ["example"], phoneNumber: "+01-123-456-7890", extra: {a: null, b: 1}}"#
+        );
+
+        // Absent values cannot be emitted.
+        let absent = Document::Sequence(vec![Document::Absent]);
+        assert!(matches!(
+            absent.clone().to_json().emit(),
+            Err(Error::AbsentValue)
+        ));
+        assert!(matches!(
+            absent.to_yaml().to_text(),
+            Err(Error::AbsentValue)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+// Line ending translation for emitted documents.
+use std::fmt;
+
+/// The line ending written by the emitters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Newline {
+    /// Unix-style `\n` line endings.
+    #[default]
+    Lf,
+    /// Windows-style `\r\n` line endings.
+    CrLf,
+}
+
+// A writer which translates the `\n` line endings written by the emitters
+// into the requested newline style.
+pub(crate) struct NewlineWriter<'a, W: fmt::Write> {
+    inner: &'a mut W,
+    newline: Newline,
+}
+
+impl<'a, W: fmt::Write> NewlineWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W, newline: Newline) -> Self {
+        NewlineWriter { inner, newline }
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for NewlineWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.newline {
+            Newline::Lf => self.inner.write_str(s),
+            Newline::CrLf => {
+                let mut lines = s.split('\n');
+                // Unwrap is ok: split always yields at least one item.
+                self.inner.write_str(lines.next().unwrap())?;
+                for line in lines {
+                    self.inner.write_str("\r\n")?;
+                    self.inner.write_str(line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    #[test]
+    fn test_crlf() -> Result<()> {
+        let doc = Document::parse("{\n  // Comment.\n  a: 1,\n  b: \"x\\ny\"\n}")?;
+        assert_eq!(
+            doc.clone().to_json5().newline(Newline::CrLf).to_text()?,
+            "{\r\n  // Comment.\r\n  a: 1,\r\n  b: \"x\\ny\"\r\n}"
+        );
+        assert_eq!(
+            doc.to_yaml().newline(Newline::CrLf).to_text()?,
+            "---\r\n# Comment.\r\na: 1\r\nb: \"x\\ny\""
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+// Stable handles to document nodes.
+use crate::document::Document;
+use crate::error::Error;
+use crate::path::{children_mut, segments};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A stable handle to a node within a `Document`.
+///
+/// Identifiers are unique within the process.  An identified node keeps its
+/// identifier when it is moved or when the document around it is
+/// transformed, so a tool can find "this exact node" again without
+/// re-resolving a path which may no longer lead to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u64);
+
+impl NodeId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        NodeId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    // The raw identifier, for debugging output.
+    pub(crate) fn raw(self) -> u64 {
+        self.0
+    }
+}
+
+// Finds the slot (a value, or a fragment holding comments and a value) at
+// `path` beneath the slot `node`.
+fn slot_at<'a>(node: &'a mut Document, path: &[String]) -> Result<&'a mut Document, Error> {
+    let (seg, rest) = match path {
+        [] => return Ok(node),
+        [seg, rest @ ..] => (seg.as_str(), rest),
+    };
+    let child = match node.as_value_mut()? {
+        v @ (Document::Mapping(_) | Document::Sequence(_)) => {
+            children_mut(v, seg).into_iter().next()
+        }
+        v => return Err(Error::StructureError("Mapping or Sequence", v.variant())),
+    };
+    slot_at(
+        child.ok_or_else(|| Error::PathError(seg.to_string()))?,
+        rest,
+    )
+}
+
+// Marks the value held by `slot` with an identifier, or returns its existing
+// identifier.
+fn identify_slot(slot: &mut Document) -> NodeId {
+    if let Document::Fragment(nodes) = slot {
+        if let Some(i) = nodes.iter().position(Document::has_value) {
+            return identify_slot(&mut nodes[i]);
+        }
+    }
+    if let Document::Identified(id, _) = slot {
+        return *id;
+    }
+    let id = NodeId::next();
+    let value = std::mem::replace(slot, Document::Null);
+    *slot = Document::Identified(id, Box::new(value));
+    id
+}
+
+fn find_mut(node: &mut Document, id: NodeId) -> Option<&mut Document> {
+    match node {
+        Document::Identified(i, v) => {
+            if *i == id {
+                Some(v)
+            } else {
+                find_mut(v, id)
+            }
+        }
+        Document::Compact(v) | Document::ColorClass(_, v) | Document::Typed(_, v) => {
+            find_mut(v, id)
+        }
+        Document::Mapping(v) | Document::Sequence(v) | Document::Fragment(v) => {
+            v.iter_mut().find_map(|n| find_mut(n, id))
+        }
+        _ => None,
+    }
+}
+
+impl Document {
+    /// Marks this document with a new identifier, returning the marked
+    /// document and its identifier.  A document which is already marked
+    /// keeps its identifier.
+    pub fn identify(mut self) -> (Document, NodeId) {
+        let id = identify_slot(&mut self);
+        (self, id)
+    }
+
+    /// Marks the value at `path` with an identifier and returns it.  A value
+    /// which is already marked keeps its identifier.
+    ///
+    /// The path is a JSON Pointer through mapping keys and sequence
+    /// indices (e.g. `/server/ports/0`).
+    pub fn id_at(&mut self, path: &str) -> Result<NodeId, Error> {
+        let path = segments(path).collect::<Vec<_>>();
+        Ok(identify_slot(slot_at(self, &path)?))
+    }
+
+    /// Returns the identifier of this node, if it has been marked.
+    pub fn node_id(&self) -> Option<NodeId> {
+        match self {
+            Document::Identified(id, _) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Returns the value marked with `id`.
+    pub fn get_by_id(&self, id: NodeId) -> Option<&Document> {
+        self.iter().find_map(|n| match n {
+            Document::Identified(i, v) if *i == id => Some(v.as_ref()),
+            _ => None,
+        })
+    }
+
+    /// Returns a mutable reference to the value marked with `id`.
+    pub fn get_by_id_mut(&mut self, id: NodeId) -> Option<&mut Document> {
+        find_mut(self, id)
+    }
+
+    /// Replaces the value marked with `id`, returning the previous value.
+    /// The new value keeps the identifier.
+    pub fn set_by_id(&mut self, id: NodeId, value: Document) -> Result<Document, Error> {
+        let node = self.get_by_id_mut(id).ok_or(Error::NodeNotFound(id))?;
+        Ok(std::mem::replace(node, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    const SAMPLE: &str = r#"{
+        // The server.
+        server: {
+            name: "prod",
+            ports: [80, 443]
+        }
+    }"#;
+
+    #[test]
+    fn test_node_ids() -> Result<()> {
+        let mut doc = Document::parse(SAMPLE)?;
+        let name = doc.id_at("/server/name")?;
+        let port = doc.id_at("/server/ports/1")?;
+        assert_ne!(name, port);
+        assert_eq!(doc.id_at("server/name")?, name);
+        assert_eq!(doc.get_by_id(name).unwrap().as_str()?, "prod");
+        assert_eq!(u16::try_from(doc.get_by_id(port).unwrap())?, 443);
+
+        let old = doc.set_by_id(name, Document::from("staging"))?;
+        assert_eq!(old.as_str()?, "prod");
+        // Identifiers are transparent to the emitters.
+        assert_eq!(
+            doc.clone().to_json5().to_text()?,
+            r#"{
+  // The server.
+  server: {
+    name: "staging",
+    ports: [
+      80,
+      443
+    ]
+  }
+}"#
+        );
+
+        // An identified node can be found after it moves.
+        let doc = doc.nest_under("/config");
+        assert_eq!(doc.get_by_id(name).unwrap().as_str()?, "staging");
+        assert!(matches!(
+            doc.clone().set_by_id(NodeId::next(), Document::Null),
+            Err(Error::NodeNotFound(_))
+        ));
+        assert!(matches!(
+            doc.clone().id_at("/config/missing"),
+            Err(Error::PathError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_identify() -> Result<()> {
+        let (value, id) = Document::from("x").identify();
+        assert_eq!(value.node_id(), Some(id));
+        assert_eq!(value.clone().identify().1, id);
+        let doc = Document::Sequence(vec![value]);
+        assert_eq!(doc.get_by_id(id).unwrap().as_str()?, "x");
+        assert_eq!(
+            crate::from_str::<Vec<String>>(&doc.to_json().to_text()?)?,
+            ["x"]
+        );
+        Ok(())
+    }
+}
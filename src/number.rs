@@ -0,0 +1,269 @@
+// Pluggable rendering of numeric values.
+use crate::integer::{Base, Int};
+
+/// Describes how the emitter would like a number to be rendered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NumberContext {
+    /// The base selected by the emitter.  This is the integer's preferred
+    /// base if the emitter permits it, otherwise `Base::Dec`.
+    pub base: Base,
+    /// The minimum number of digits requested for the value.
+    pub width: usize,
+    /// The number of fractional digits requested for floats, if any.
+    pub precision: Option<usize>,
+    /// The style requested for floats.
+    pub float: FloatFormat,
+}
+
+/// The styles in which floating point values may be rendered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// Positional notation using Rust's `Display` formatting.
+    #[default]
+    Display,
+    /// The shortest text which parses back to the same value, using
+    /// exponent notation when it is shorter (e.g. `1e-7` or `0.30000000000000004`).
+    Shortest,
+    /// Positional notation with a fixed number of fractional digits.
+    Fixed(usize),
+    /// The given number of significant digits, using exponent notation for
+    /// very large or very small magnitudes (like C's `%g`, but retaining
+    /// trailing zeros).
+    Significant(usize),
+    /// Exponent notation with the shortest round-trip mantissa (e.g. `1.5e3`).
+    Exponent,
+    /// C-style hexadecimal notation (e.g. `0x1.8p3`), which represents the
+    /// value exactly.  JSON emitters quote the value unless hex literals are
+    /// permitted.
+    Hex,
+}
+
+impl FloatFormat {
+    /// Formats a finite float in this style.
+    pub fn format(&self, value: f64) -> String {
+        match *self {
+            FloatFormat::Display => format!("{}", value),
+            FloatFormat::Shortest => {
+                let (pos, exp) = (format!("{}", value), format!("{:e}", value));
+                if exp.len() < pos.len() {
+                    exp
+                } else {
+                    pos
+                }
+            }
+            FloatFormat::Fixed(p) => format!("{:.*}", p, value),
+            FloatFormat::Significant(n) => {
+                let n = n.max(1);
+                let exp = format!("{:.*e}", n - 1, value);
+                // Unwrap is ok: `{:e}` formatting always contains an exponent.
+                let e = exp[exp.find('e').unwrap() + 1..].parse::<i32>().unwrap();
+                if e < -4 || e >= n as i32 {
+                    exp
+                } else {
+                    format!("{:.*}", (n as i32 - 1 - e) as usize, value)
+                }
+            }
+            FloatFormat::Exponent => format!("{:e}", value),
+            FloatFormat::Hex => hex_float(value),
+        }
+    }
+}
+
+// Formats a finite float in C-style hexadecimal notation (like `%a`).
+fn hex_float(value: f64) -> String {
+    let bits = value.to_bits();
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    let frac = bits & ((1 << 52) - 1);
+    let (lead, exp) = match ((bits >> 52) & 0x7ff) as i32 {
+        0 if frac == 0 => return format!("{}0x0p0", sign),
+        // Subnormal values have no implicit leading one.
+        0 => (0, -1022),
+        e => (1, e - 1023),
+    };
+    let digits = format!("{:013x}", frac);
+    let digits = digits.trim_end_matches('0');
+    if digits.is_empty() {
+        format!("{}0x{}p{}", sign, lead, exp)
+    } else {
+        format!("{}0x{}.{}p{}", sign, lead, digits, exp)
+    }
+}
+
+/// Parses a C-style hexadecimal float (e.g. `0x1.8p3`).
+pub(crate) fn parse_hex_float(text: &str) -> Option<f64> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(t) => (true, t),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let text = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))?;
+    let (mantissa, exp) = text.split_once(['p', 'P'])?;
+    let mut exp = exp.parse::<i32>().ok()?;
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int.is_empty() && frac.is_empty() {
+        return None;
+    }
+    let mut m = 0u64;
+    for (i, ch) in int.chars().chain(frac.chars()).enumerate() {
+        let d = ch.to_digit(16)? as u64;
+        // Digits beyond the precision of the mantissa are dropped.
+        if m >> 56 == 0 {
+            m = (m << 4) | d;
+            if i >= int.len() {
+                exp -= 4;
+            }
+        } else if i < int.len() {
+            exp += 4;
+        }
+    }
+    // Apply the exponent in two steps so subnormal results don't underflow
+    // to zero prematurely.
+    let half = exp / 2;
+    let value = m as f64 * 2f64.powi(half) * 2f64.powi(exp - half);
+    Some(if negative { -value } else { value })
+}
+
+/// A `NumberFormatter` converts numbers into their textual representation.
+///
+/// Emitters consult the formatter for the digits of every integer and float
+/// value; the emitter remains responsible for quoting and coloring the
+/// result.  Implement this trait to render domain-specific forms such as
+/// fixed-point or percentage values.
+pub trait NumberFormatter: Send + Sync {
+    /// Formats an integer.
+    fn format_int(&self, value: &Int, ctx: &NumberContext) -> String;
+    /// Formats a floating point value.
+    fn format_float(&self, value: f64, ctx: &NumberContext) -> String;
+}
+
+/// The number formatter used by the emitters unless another is configured.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultNumberFormatter;
+
+impl NumberFormatter for DefaultNumberFormatter {
+    fn format_int(&self, value: &Int, ctx: &NumberContext) -> String {
+        value.format(Some(&ctx.base))
+    }
+
+    fn format_float(&self, value: f64, ctx: &NumberContext) -> String {
+        match ctx.precision {
+            Some(p) => format!("{:.*}", p, value),
+            None => ctx.float.format(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::emit::Emit;
+
+    // Renders integers as Q8.8 fixed-point values and floats as percentages.
+    struct Domain;
+
+    impl NumberFormatter for Domain {
+        fn format_int(&self, value: &Int, _ctx: &NumberContext) -> String {
+            format!("{}", f64::from(value) / 256.0)
+        }
+        fn format_float(&self, value: f64, _ctx: &NumberContext) -> String {
+            format!("{}e-2", value * 100.0)
+        }
+    }
+
+    fn doc() -> Document {
+        Document::Sequence(vec![
+            Document::Int(Int::new(384u16, Base::Hex)),
+            Document::Float(0.25),
+        ])
+    }
+
+    #[test]
+    fn test_default_formatter() {
+        let ctx = NumberContext {
+            base: Base::Hex,
+            width: 0,
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        let f = DefaultNumberFormatter;
+        assert_eq!(f.format_int(&Int::new(255u8, Base::Dec), &ctx), "0xFF");
+        assert_eq!(f.format_float(1.5, &ctx), "1.5");
+        let ctx = NumberContext {
+            precision: Some(3),
+            ..ctx
+        };
+        assert_eq!(f.format_float(1.5, &ctx), "1.500");
+    }
+
+    #[test]
+    fn test_float_format() {
+        let sum = 0.1 + 0.2;
+        assert_eq!(FloatFormat::Display.format(1e-7), "0.0000001");
+        assert_eq!(FloatFormat::Shortest.format(1e-7), "1e-7");
+        assert_eq!(FloatFormat::Shortest.format(sum), "0.30000000000000004");
+        assert_eq!(FloatFormat::Shortest.format(1e21), "1e21");
+        assert_eq!(FloatFormat::Shortest.format(1500.0), "1500");
+        assert_eq!(FloatFormat::Fixed(2).format(sum), "0.30");
+        assert_eq!(FloatFormat::Significant(3).format(sum), "0.300");
+        assert_eq!(FloatFormat::Significant(3).format(12345.0), "1.23e4");
+        assert_eq!(FloatFormat::Significant(3).format(0.000012345), "1.23e-5");
+        assert_eq!(FloatFormat::Significant(3).format(-12.345), "-12.3");
+        assert_eq!(FloatFormat::Exponent.format(1500.0), "1.5e3");
+        assert_eq!(FloatFormat::Exponent.format(sum), "3.0000000000000004e-1");
+        assert_eq!(FloatFormat::Hex.format(12.0), "0x1.8p3");
+        assert_eq!(FloatFormat::Hex.format(-0.5), "-0x1p-1");
+        assert_eq!(FloatFormat::Hex.format(0.0), "0x0p0");
+        assert_eq!(FloatFormat::Hex.format(sum), "0x1.3333333333334p-2");
+    }
+
+    #[test]
+    fn test_hex_float_round_trip() {
+        for v in [
+            12.0,
+            -0.5,
+            0.0,
+            0.1 + 0.2,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::MIN_POSITIVE / 3.0,
+            5e-324,
+        ] {
+            let text = FloatFormat::Hex.format(v);
+            assert_eq!(parse_hex_float(&text), Some(v), "{}", text);
+        }
+        assert_eq!(parse_hex_float("0X.8P1"), Some(1.0));
+        assert_eq!(parse_hex_float("0x1.8"), None);
+        assert_eq!(parse_hex_float("0xp1"), None);
+    }
+
+    #[test]
+    fn test_float_format_emitters() {
+        let doc = Document::Sequence(vec![Document::Float(0.1 + 0.2), Document::Float(1e-7)]);
+        let json = doc
+            .clone()
+            .to_json()
+            .compact(true)
+            .float_format(FloatFormat::Significant(2));
+        assert_eq!(json.to_text().unwrap(), "[0.30, 1.0e-7]");
+        let yaml = doc
+            .to_yaml()
+            .header(false)
+            .compact(true)
+            .float_format(FloatFormat::Shortest);
+        assert_eq!(yaml.to_text().unwrap(), "[0.30000000000000004, 1e-7]");
+    }
+
+    #[test]
+    fn test_custom_formatter() {
+        let json = doc().to_json().compact(true).number_formatter(Domain);
+        assert_eq!(json.to_text().unwrap(), "[1.5, 25e-2]");
+        let yaml = doc()
+            .to_yaml()
+            .header(false)
+            .compact(true)
+            .number_formatter(Domain);
+        assert_eq!(yaml.to_text().unwrap(), "[1.5, 25e-2]");
+    }
+}
@@ -0,0 +1,78 @@
+// Paths selecting nodes beneath a document.
+//
+// Paths are JSON Pointers (RFC 6901): each mapping key or sequence index is
+// preceded by `/`, with `~` and `/` within a key written as `~0` and `~1`
+// (e.g. `/server/ports/0`).  The empty path refers to the root.
+use crate::document::Document;
+use crate::entry::is_entry;
+
+// Returns the unescaped segments of `path`.  The leading `/` may be
+// omitted.
+pub(crate) fn segments(path: &str) -> impl DoubleEndedIterator<Item = String> + '_ {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    (!path.is_empty())
+        .then(|| path.split('/'))
+        .into_iter()
+        .flatten()
+        .map(|s| s.replace("~1", "/").replace("~0", "~"))
+}
+
+// Returns the path of the node reached through `segments`.
+pub(crate) fn pointer<S: AsRef<str>>(segments: &[S]) -> String {
+    segments
+        .iter()
+        .map(|s| format!("/{}", s.as_ref().replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+// Returns whether the path segment `seg` selects the mapping entry `entry`.
+// The segment `*` selects every entry.
+pub(crate) fn selects_entry(seg: &str, entry: &Document) -> bool {
+    is_entry(entry, seg) || (seg == "*" && entry.as_kv().is_ok())
+}
+
+// Returns whether the path segment `seg` selects the sequence element at
+// `index`, counting only elements which hold a value.  The segment `*`
+// selects every element.
+pub(crate) fn selects_index(seg: &str, index: usize) -> bool {
+    seg == "*" || seg.parse() == Ok(index)
+}
+
+// Returns the children of `node` selected by `seg`: the values of the
+// selected entries of a mapping, or the selected elements of a sequence.
+pub(crate) fn children<'a>(node: &'a Document, seg: &str) -> Vec<&'a Document> {
+    match node {
+        Document::Mapping(m) => m
+            .iter()
+            .filter(|e| selects_entry(seg, e))
+            .filter_map(|e| e.as_kv().ok().map(|kv| kv.1))
+            .collect(),
+        Document::Sequence(s) => s
+            .iter()
+            .filter(|n| n.has_value())
+            .enumerate()
+            .filter(|(i, _)| selects_index(seg, *i))
+            .map(|(_, n)| n)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Returns the children of `node` selected by `seg`, as for `children`.
+pub(crate) fn children_mut<'a>(node: &'a mut Document, seg: &str) -> Vec<&'a mut Document> {
+    match node {
+        Document::Mapping(m) => m
+            .iter_mut()
+            .filter(|e| selects_entry(seg, e))
+            .filter_map(|e| e.as_kv_mut().ok().map(|kv| kv.1))
+            .collect(),
+        Document::Sequence(s) => s
+            .iter_mut()
+            .filter(|n| n.has_value())
+            .enumerate()
+            .filter(|(i, _)| selects_index(seg, *i))
+            .map(|(_, n)| n)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,135 @@
+// Progress reporting for the emission of large documents.
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::document::Document;
+
+/// The progress of rendering a document, as passed to the callback set with
+/// `Json::progress`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of values emitted so far.
+    pub nodes: usize,
+    /// The number of values in the document.  This is an estimate: values
+    /// which are not emitted, such as null entries omitted by `skip_null`,
+    /// are counted.
+    pub total: usize,
+    /// The number of bytes written so far, including color escapes.
+    pub bytes: usize,
+}
+
+pub(crate) type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+// Counts the values emitted and the bytes written, and invokes a progress
+// callback after every `interval` values.  The bytes are counted by a
+// `ProgressWriter` at the end of the writer chain.
+pub(crate) struct Reporter<'a> {
+    callback: &'a ProgressCallback,
+    interval: usize,
+    progress: Cell<Progress>,
+}
+
+impl<'a> Reporter<'a> {
+    pub(crate) fn new(
+        callback: &'a ProgressCallback,
+        interval: usize,
+        document: &Document,
+    ) -> Self {
+        Reporter {
+            callback,
+            interval,
+            progress: Cell::new(Progress {
+                total: count_values(document),
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub(crate) fn node(&self) {
+        let mut progress = self.progress.get();
+        progress.nodes += 1;
+        self.progress.set(progress);
+        if progress.nodes % self.interval == 0 {
+            (self.callback)(&progress);
+        }
+    }
+
+    // Reports the final progress, unless it was just reported.
+    pub(crate) fn finish(&self) {
+        let progress = self.progress.get();
+        if progress.nodes == 0 || progress.nodes % self.interval != 0 {
+            (self.callback)(&progress);
+        }
+    }
+}
+
+// Returns the number of values in `node`, counting each mapping and
+// sequence along with its elements but not the mapping keys.
+fn count_values(node: &Document) -> usize {
+    match node {
+        Document::Comment(_, _) => 0,
+        Document::Mapping(m) => {
+            1 + m
+                .iter()
+                .filter_map(|entry| entry.as_kv().ok())
+                .map(|(_, v)| count_values(v))
+                .sum::<usize>()
+        }
+        Document::Sequence(s) => 1 + s.iter().map(count_values).sum::<usize>(),
+        Document::Compact(d)
+        | Document::ColorClass(_, d)
+        | Document::Identified(_, d)
+        | Document::Typed(_, d) => count_values(d),
+        Document::Fragment(ds) => ds.iter().map(count_values).sum(),
+        _ => 1,
+    }
+}
+
+// A writer which counts the bytes written for a `Reporter`.
+pub(crate) struct ProgressWriter<'a, W: fmt::Write> {
+    inner: &'a mut W,
+    reporter: &'a Reporter<'a>,
+}
+
+impl<'a, W: fmt::Write> ProgressWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W, reporter: &'a Reporter<'a>) -> Self {
+        ProgressWriter { inner, reporter }
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for ProgressWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_str(s)?;
+        let mut progress = self.reporter.progress.get();
+        progress.bytes += s.len();
+        self.reporter.progress.set(progress);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_progress() -> Result<()> {
+        let doc = Document::parse("{\n  // A comment.\n  a: [1, 2, 3],\n  b: {c: null}\n}")?;
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let r = reports.clone();
+        let text = doc
+            .to_json()
+            .progress(3, move |p| r.lock().unwrap().push(*p))
+            .emit()?
+            .0;
+        let reports = reports.lock().unwrap();
+        let nodes = reports.iter().map(|p| p.nodes).collect::<Vec<_>>();
+        assert_eq!(nodes, vec![3, 6, 7]);
+        assert!(reports.iter().all(|p| p.total == 7));
+        assert!(reports.windows(2).all(|w| w[0].bytes < w[1].bytes));
+        assert_eq!(reports.last().unwrap().bytes, text.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,541 @@
+use crate::color::ColorProfile;
+use crate::document::{BytesFormat, Document};
+use crate::emit::{common_options, guarded, CommonOptions, Emit};
+use crate::entry::{comments_of, MapEntry, SeqItem};
+use crate::error::Error;
+use crate::hexdump;
+use crate::integer::{Base, Int};
+use crate::layout::TokenClass;
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use crate::sort::KeySort;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A Java `.properties` document and its formatting properties.
+///
+/// The document must be a mapping.  Nested mappings are flattened into
+/// dotted keys (`server.port=80`).  Sequences of scalars are joined into a
+/// single value (`hosts=a,b`), and other sequences, or those with an element
+/// containing the list separator, are flattened into indexed keys
+/// (`servers[0].port=80`).  Null is written as an empty value
+/// and bytes as a hex string unless another format is requested.
+///
+/// Empty mappings and sequences below the root have no properties to stand
+/// for them, so they fail with `Error::Unrepresentable`.  Keys which
+/// flatten to the same property (e.g. `{"a.b": 1}` and `{a: {b: 1}}`) fail
+/// with `Error::DuplicateName`, since readers cannot tell them apart.
+///
+/// Keys and values are escaped as by `java.util.Properties.store`, so the
+/// output reads back with `Properties.load`.  Comments are written as `#`
+/// lines: those ahead of an entry above its first property, and those
+/// after its value below its last.
+pub struct Properties {
+    document: Document,
+    common: CommonOptions,
+    separator: String,
+    join_lists: bool,
+    list_separator: String,
+    escape_unicode: bool,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+}
+
+impl Properties {
+    common_options!(
+        /// Set whether the document is emitted without comments.
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        max_depth,
+        /// Set the order in which the entries of mappings are emitted.
+        sort_keys,
+        newline,
+        ascii_comments,
+    );
+    /// Set the text between a key and its value (the default is `"="`).
+    pub fn separator(mut self, s: &str) -> Self {
+        self.separator = s.to_string();
+        self
+    }
+    /// Set whether sequences of scalars are joined into a single value (the
+    /// default is `true`) rather than flattened into indexed keys.
+    pub fn join_lists(mut self, b: bool) -> Self {
+        self.join_lists = b;
+        self
+    }
+    /// Set the text between the elements of a joined sequence (the default
+    /// is `","`).  A sequence with an element containing the separator is
+    /// flattened into indexed keys instead, since readers cannot tell it
+    /// apart from the separator.
+    pub fn list_separator(mut self, s: &str) -> Self {
+        self.list_separator = s.to_string();
+        self
+    }
+    /// Set whether characters outside printable ASCII are written as
+    /// `\uXXXX` escapes (the default is `true`), as required by readers
+    /// expecting ISO-8859-1.
+    pub fn escape_unicode(mut self, b: bool) -> Self {
+        self.escape_unicode = b;
+        self
+    }
+    /// Set the rendering of bytes values.  `BytesFormat::Standard` renders
+    /// as `BytesFormat::HexStr`.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.bytes_format = b;
+        self
+    }
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+
+    fn emit<W: fmt::Write>(&self, w: &mut W) -> Result<()> {
+        self.common.check_comments(&self.document)?;
+        let mut emitter = PropertiesEmitter {
+            color: self.common.color.clone(),
+            compact: self.common.compact,
+            sort_keys: self.common.sort_keys.clone(),
+            separator: &self.separator,
+            join_lists: self.join_lists,
+            list_separator: &self.list_separator,
+            escape_unicode: self.escape_unicode,
+            bytes_format: self.bytes_format,
+            numbers: self.numbers.clone(),
+            float_format: self.float_format,
+            depth: 0,
+            max_depth: self.common.max_depth,
+            keys: HashSet::new(),
+        };
+        emitter.emit_document(w, &self.document)
+    }
+}
+
+impl Emit for Properties {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.common, w, |mut w| self.emit(&mut w))
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.common
+    }
+}
+
+impl Document {
+    pub fn to_properties(self) -> Properties {
+        Properties {
+            document: self,
+            common: CommonOptions::default(),
+            separator: "=".to_string(),
+            join_lists: true,
+            list_separator: ",".to_string(),
+            escape_unicode: true,
+            bytes_format: BytesFormat::Standard,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+        }
+    }
+}
+
+struct PropertiesEmitter<'a> {
+    color: ColorProfile,
+    compact: bool,
+    sort_keys: KeySort,
+    separator: &'a str,
+    join_lists: bool,
+    list_separator: &'a str,
+    escape_unicode: bool,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    depth: usize,
+    max_depth: Option<usize>,
+    // The keys of the properties emitted so far.
+    keys: HashSet<String>,
+}
+
+impl PropertiesEmitter<'_> {
+    fn emit_document<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        let nodes = match node {
+            Document::Fragment(ds) => &ds[..],
+            n => std::slice::from_ref(n),
+        };
+        for node in nodes {
+            match node.comment() {
+                Some((c, _)) => self.emit_comment(w, c)?,
+                None => match node.as_value()? {
+                    Document::Mapping(m) => self.emit_mapping(w, "", m)?,
+                    v => return Err(Error::StructureError("Mapping", v.variant())),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // Emits the properties of the entries of a mapping whose keys are
+    // prefixed by `prefix`.
+    fn emit_mapping<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        prefix: &str,
+        mapping: &[Document],
+    ) -> Result<()> {
+        self.enter()?;
+        for node in self.sort_keys.order(mapping) {
+            let entry = match MapEntry::new(node) {
+                Ok(entry) => entry,
+                Err(_) => {
+                    for (c, _) in comments_of(node) {
+                        self.emit_comment(w, c)?;
+                    }
+                    continue;
+                }
+            };
+            let key = match entry.key.as_value()? {
+                Document::String(s, _) => s.clone(),
+                Document::StaticStr(s, _) => s.to_string(),
+                Document::Int(i) => i.format(None),
+                Document::Boolean(b) => b.to_string(),
+                v => return Err(Error::KeyTypeError(v.variant())),
+            };
+            let key = match prefix {
+                "" => key,
+                p => format!("{}.{}", p, key),
+            };
+            for (c, _) in &entry.comments {
+                self.emit_comment(w, c)?;
+            }
+            self.emit_node(w, &key, entry.value)?;
+            for (c, _) in &entry.trailing {
+                self.emit_comment(w, c)?;
+            }
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    // Emits the properties of the elements of a sequence, with keys indexed
+    // by position.
+    fn emit_sequence<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        prefix: &str,
+        sequence: &[Document],
+    ) -> Result<()> {
+        self.enter()?;
+        let mut index = 0;
+        for node in sequence {
+            let item = match SeqItem::new(node) {
+                Ok(item) => item,
+                Err(_) => {
+                    for (c, _) in comments_of(node) {
+                        self.emit_comment(w, c)?;
+                    }
+                    continue;
+                }
+            };
+            for (c, _) in &item.comments {
+                self.emit_comment(w, c)?;
+            }
+            self.emit_node(w, &format!("{}[{}]", prefix, index), item.value)?;
+            for (c, _) in &item.trailing {
+                self.emit_comment(w, c)?;
+            }
+            index += 1;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn emit_node<W: fmt::Write>(&mut self, w: &mut W, key: &str, node: &Document) -> Result<()> {
+        match node.as_value()? {
+            Document::Mapping(m) if !m.iter().any(Document::has_value) => {
+                Err(Error::Unrepresentable("an empty mapping", "properties"))
+            }
+            Document::Sequence(s) if !s.iter().any(Document::has_value) => {
+                Err(Error::Unrepresentable("an empty sequence", "properties"))
+            }
+            Document::Mapping(m) => self.emit_mapping(w, key, m),
+            Document::Sequence(s) => match self.joined(s)? {
+                Some(value) => self.emit_property(w, key, &value),
+                None => self.emit_sequence(w, key, s),
+            },
+            v => {
+                let value = self.scalar(v)?;
+                self.emit_property(w, key, &value)
+            }
+        }
+    }
+
+    // Returns the elements of a sequence of scalars joined into one value,
+    // if sequences are joined and no element contains the separator.
+    fn joined(&self, sequence: &[Document]) -> Result<Option<String>> {
+        let scalars = sequence.iter().filter(|n| n.has_value()).all(|n| {
+            !matches!(
+                n.as_value(),
+                Ok(Document::Mapping(_)) | Ok(Document::Sequence(_))
+            )
+        });
+        if !self.join_lists || !scalars {
+            return Ok(None);
+        }
+        let values = sequence
+            .iter()
+            .filter(|n| n.has_value())
+            .map(|n| self.scalar(n.as_value()?))
+            .collect::<Result<Vec<_>>>()?;
+        if self.list_separator.is_empty() || values.iter().any(|v| v.contains(self.list_separator))
+        {
+            return Ok(None);
+        }
+        Ok(Some(values.join(self.list_separator)))
+    }
+
+    // Returns the text of a scalar value, before escaping.
+    fn scalar(&self, node: &Document) -> Result<String> {
+        Ok(match node {
+            Document::String(s, _) => s.clone(),
+            Document::StaticStr(s, _) => s.to_string(),
+            Document::Boolean(b) => b.to_string(),
+            Document::Int(i) => self.int(i)?,
+            Document::Float(f) => {
+                let ctx = NumberContext {
+                    base: Base::Dec,
+                    width: 0,
+                    precision: None,
+                    float: self.float_format,
+                };
+                self.numbers.format_float(*f, &ctx)
+            }
+            Document::Bytes(b) => {
+                let format = match self.bytes_format {
+                    BytesFormat::Standard => BytesFormat::HexStr,
+                    f => f,
+                };
+                // Unwrap is ok: every format but `Standard` renders a string.
+                hexdump::to_string(b, format).unwrap()
+            }
+            Document::Null => String::new(),
+            Document::Absent => return Err(Error::AbsentValue),
+            v => return Err(Error::StructureError("scalar", v.variant())),
+        })
+    }
+
+    fn int(&self, i: &Int) -> Result<String> {
+        let ctx = NumberContext {
+            base: i.base(),
+            width: i.width(),
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        // The dialect has no integer type suffixes.
+        let i = &i.unsuffixed();
+        i.check_format(ctx.base)?;
+        Ok(self.numbers.format_int(i, &ctx))
+    }
+
+    fn emit_property<W: fmt::Write>(&mut self, w: &mut W, key: &str, value: &str) -> Result<()> {
+        if !self.keys.insert(key.to_string()) {
+            return Err(Error::DuplicateName(key.to_string()));
+        }
+        // An empty value leaves no whitespace at the end of the line.
+        let separator = match value.is_empty() {
+            true => self.separator.trim_end(),
+            false => self.separator,
+        };
+        writeln!(
+            w,
+            "{}{}{}",
+            self.color
+                .style(&TokenClass::BareKey)
+                .paint(self.escape(key, true)),
+            self.color.punctuation.paint(separator),
+            self.color.string.paint(self.escape(value, false))
+        )?;
+        Ok(())
+    }
+
+    fn emit_comment<W: fmt::Write>(&mut self, w: &mut W, comment: &str) -> Result<()> {
+        if self.compact {
+            return Ok(());
+        }
+        for line in comment.split('\n') {
+            let line = self.escape_chars(line);
+            let text = if line.is_empty() {
+                "#".to_string()
+            } else {
+                format!("# {}", line)
+            };
+            writeln!(w, "{}", self.color.comment.paint(text))?;
+        }
+        Ok(())
+    }
+
+    // Escapes a key or value as `Properties.store` does.  Spaces are escaped
+    // throughout keys but only at the start of values.
+    fn escape(&self, text: &str, key: bool) -> String {
+        let mut escaped = String::new();
+        for (i, ch) in text.chars().enumerate() {
+            match ch {
+                ' ' if key || i == 0 => escaped.push_str("\\ "),
+                '\\' | '=' | ':' | '#' | '!' => {
+                    escaped.push('\\');
+                    escaped.push(ch);
+                }
+                '\t' => escaped.push_str("\\t"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\u{c}' => escaped.push_str("\\f"),
+                c => escaped.push_str(&self.escape_chars(&c.to_string())),
+            }
+        }
+        escaped
+    }
+
+    // Writes the characters outside printable ASCII as `\uXXXX` escapes,
+    // as surrogate pairs beyond the basic multilingual plane.
+    fn escape_chars(&self, text: &str) -> String {
+        if !self.escape_unicode {
+            return text.to_string();
+        }
+        let mut escaped = String::new();
+        for ch in text.chars() {
+            if (' '..='~').contains(&ch) {
+                escaped.push(ch);
+            } else {
+                for unit in ch.encode_utf16(&mut [0; 2]) {
+                    escaped.push_str(&format!("\\u{:04X}", unit));
+                }
+            }
+        }
+        escaped
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        match self.max_depth {
+            Some(n) if self.depth >= n => Err(Error::DepthLimit(n)),
+            _ => {
+                self.depth += 1;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    const DOC: &str = r#"{
+        // Generated; do not edit.
+        server: {
+            host: "0.0.0.0",
+            port: 8080,
+            // Paths served.
+            paths: ["/api", "/static"],
+        },
+        backends: [
+            {name: "a", weight: 0x10},
+            // The fallback.
+            {name: "b c", weight: 1},
+        ],
+        greeting: " Grüße = 😀",
+        "odd key": null,
+    }"#;
+
+    #[test]
+    fn test_properties() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_properties().to_text()?,
+            r#"# Generated; do not edit.
+server.host=0.0.0.0
+server.port=8080
+# Paths served.
+server.paths=/api,/static
+backends[0].name=a
+backends[0].weight=0x10
+# The fallback.
+backends[1].name=b c
+backends[1].weight=1
+greeting=\ Gr\u00FC\u00DFe \= \uD83D\uDE00
+odd\ key=
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_options() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_properties()
+                .compact(true)
+                .separator(" = ")
+                .join_lists(false)
+                .escape_unicode(false)
+                .to_text()?,
+            r#"server.host = 0.0.0.0
+server.port = 8080
+server.paths[0] = /api
+server.paths[1] = /static
+backends[0].name = a
+backends[0].weight = 0x10
+backends[1].name = b c
+backends[1].weight = 1
+greeting = \ Grüße \= 😀
+odd\ key =
+"#
+        );
+        let doc = Document::parse(r#"{a: [1, 2]}"#)?;
+        assert_eq!(
+            doc.to_properties().list_separator(";").to_text()?,
+            "a=1;2\n"
+        );
+        // Elements containing the separator are not joined.
+        let doc = Document::parse(r#"{a: ["a,b", "c"]}"#)?;
+        assert_eq!(doc.to_properties().to_text()?, "a[0]=a,b\na[1]=c\n");
+        let doc = Document::parse("[1]")?;
+        assert!(doc.to_properties().to_text().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unrepresentable() -> Result<()> {
+        assert_eq!(Document::parse("{}")?.to_properties().to_text()?, "");
+        for (text, what) in [
+            ("{a: {}}", "an empty mapping"),
+            ("{a: [/* none */]}", "an empty sequence"),
+        ] {
+            let doc = Document::parse(text)?;
+            assert!(matches!(
+                doc.to_properties().to_text(),
+                Err(Error::Unrepresentable(w, "properties")) if w == what
+            ));
+        }
+        // Keys flattening to the same property cannot be told apart.
+        for text in [
+            "{'a.b': 1, a: {b: 2}}",
+            "{'a[0]': 1, a: [2, {b: 3}]}",
+            "{'a[0]': 1, a: [2, 'x,y']}",
+        ] {
+            let doc = Document::parse(text)?;
+            assert!(matches!(
+                doc.to_properties().to_text(),
+                Err(Error::DuplicateName(k)) if k == "a.b" || k == "a[0]"
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+// Re-attaching comments and formatting from a prior document.
+use crate::document::Document;
+use crate::entry::{is_entry, value_of};
+
+/// Re-attaches the comments and formatting of `old` to the matching nodes
+/// of `new`.
+///
+/// The typical workflow is to parse a file, deserialize it into a typed
+/// struct, modify the struct, serialize it back into a `Document` and then
+/// reconcile the new document against the originally parsed one before
+/// emitting it.  Mapping entries are matched by key and sequence elements
+/// are matched by index.  The structure and values of `new` always win;
+/// only comments, string styles, integer bases and compactness are taken
+/// from `old`.
+///
+/// Comments present in `old` replace any comments on the matching node in
+/// `new`.  Nodes of `new` without a counterpart in `old` are left untouched.
+pub fn reconcile(mut new: Document, old: &Document) -> Document {
+    reconcile_slot(&mut new, old);
+    new
+}
+
+// Returns the comments preceding the first value node and following the last
+// value node of a fragment.
+fn comments(node: &Document) -> (Vec<&Document>, Vec<&Document>) {
+    let mut leading = Vec::new();
+    let mut trailing = Vec::new();
+    if let Document::Fragment(nodes) = node {
+        let mut seen_value = false;
+        for n in nodes {
+            if n.has_value() {
+                seen_value = true;
+                trailing.clear();
+            } else if seen_value {
+                trailing.push(n);
+            } else {
+                leading.push(n);
+            }
+        }
+    }
+    (leading, trailing)
+}
+
+// Replaces the comments surrounding the value nodes of `node`.
+fn replace_comments(node: &mut Document, leading: Vec<&Document>, trailing: Vec<&Document>) {
+    if leading.is_empty() && trailing.is_empty() {
+        return;
+    }
+    let values = match std::mem::replace(node, Document::Null) {
+        Document::Fragment(nodes) => nodes.into_iter().filter(Document::has_value).collect(),
+        n => vec![n],
+    };
+    let mut nodes = leading.into_iter().cloned().collect::<Vec<_>>();
+    nodes.extend(values);
+    nodes.extend(trailing.into_iter().cloned());
+    *node = Document::Fragment(nodes);
+}
+
+// Reconciles a node which may be a bare value or a fragment holding comments
+// and a single value (e.g. a sequence element or the document root).
+fn reconcile_slot(new: &mut Document, old: &Document) {
+    if let Some(old_value) = value_of(old) {
+        match &mut *new {
+            Document::Fragment(nodes) => {
+                if let Some(n) = nodes.iter_mut().find(|n| n.has_value()) {
+                    reconcile_value(n, old_value);
+                }
+            }
+            n => reconcile_value(n, old_value),
+        }
+    }
+    let (leading, trailing) = comments(old);
+    replace_comments(new, leading, trailing);
+}
+
+fn reconcile_value(new: &mut Document, old: &Document) {
+    match (new, old) {
+        (Document::Compact(n), Document::Compact(o)) => reconcile_value(n, o),
+        (Document::Compact(n), o) => reconcile_value(n, o),
+        (n, Document::Compact(o)) => {
+            reconcile_value(n, o);
+            let inner = std::mem::replace(n, Document::Null);
+            *n = Document::Compact(Box::new(inner));
+        }
+        (Document::ColorClass(_, n), o) => reconcile_value(n, o),
+        (n, Document::ColorClass(_, o)) => reconcile_value(n, o),
+        (Document::Identified(_, n), o) => reconcile_value(n, o),
+        (n, Document::Identified(_, o)) => reconcile_value(n, o),
+        (Document::Typed(_, n), o) => reconcile_value(n, o),
+        (n, Document::Typed(_, o)) => reconcile_value(n, o),
+        (Document::Mapping(n), Document::Mapping(o)) => reconcile_mapping(n, o),
+        (Document::Sequence(n), Document::Sequence(o)) => {
+            for (n, o) in n.iter_mut().zip(o.iter()) {
+                reconcile_slot(n, o);
+            }
+        }
+        (Document::Int(n), Document::Int(o)) => n.copy_format(o),
+        (
+            Document::String(_, n) | Document::StaticStr(_, n),
+            Document::String(_, o) | Document::StaticStr(_, o),
+        ) => *n = *o,
+        _ => {}
+    }
+}
+
+fn reconcile_mapping(new: &mut [Document], old: &[Document]) {
+    for entry in new.iter_mut() {
+        let key = match entry.as_kv().map(|(k, _)| k.as_str()) {
+            Ok(Ok(k)) => k.to_string(),
+            _ => continue,
+        };
+        let old_entry = old.iter().find(|o| is_entry(o, &key));
+        let old_entry = match old_entry {
+            Some(o) => o,
+            None => continue,
+        };
+        // Unwraps are ok: both entries were successfully examined as kvpairs above.
+        let (nk, nv) = entry.as_kv_mut().unwrap();
+        let (ok, ov) = old_entry.as_kv().unwrap();
+        reconcile_value(nk, ok);
+        reconcile_value(nv, ov);
+        let (leading, trailing) = comments(old_entry);
+        replace_comments(entry, leading, trailing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use crate::{serialize, Deserialize};
+    use anyhow::Result;
+    use serde::{Deserialize as De, Serialize};
+
+    #[derive(Debug, Serialize, De, PartialEq, Eq)]
+    struct Flash {
+        name: String,
+        base: u32,
+        sizes: Vec<u32>,
+    }
+
+    const ORIGINAL: &str = r#"{
+  // The name of the part.
+  name: "winbond",
+  // Base address of the part.
+  base: 0x2000,
+  sizes: [
+    // Sector
+    0x1000,
+    0x10000
+  ]
+}"#;
+
+    #[test]
+    fn test_reconcile() -> Result<()> {
+        let old = Document::parse(ORIGINAL)?;
+        let mut flash = Deserialize::try_from(ORIGINAL)?.into::<Flash>()?;
+        flash.base = 0x4000;
+        flash.sizes[0] = 0x800;
+        let new = reconcile(serialize(&flash)?, &old);
+        assert_eq!(
+            new.to_json5().to_text()?,
+            r#"{
+  // The name of the part.
+  name: "winbond",
+  // Base address of the part.
+  base: 0x4000,
+  sizes: [
+    // Sector
+    0x0800,
+    0x10000
+  ]
+}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconcile_new_keys() -> Result<()> {
+        let old = Document::parse(r#"{ /* Just the name */ name: "winbond" }"#)?;
+        let flash = Flash {
+            name: "macronix".into(),
+            base: 16,
+            sizes: vec![],
+        };
+        let new = reconcile(serialize(&flash)?, &old);
+        let (k, v) = match &new {
+            Document::Mapping(m) => m[0].as_kv()?,
+            _ => panic!("Expected a mapping"),
+        };
+        assert_eq!(k.as_str()?, "name");
+        assert_eq!(v.as_str()?, "macronix");
+        assert!(new
+            .iter()
+            .any(|n| n.comment().map(|c| c.0) == Some("Just the name")));
+        Ok(())
+    }
+}
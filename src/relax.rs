@@ -1,4 +1,4 @@
-use pest::error::Error as PestError;
+use pest::error::{Error as PestError, ErrorVariant, InputLocation};
 use pest::iterators::{Pair, Pairs};
 use pest::Parser as P;
 use pest::Position;
@@ -8,10 +8,75 @@ use std::cell::RefCell;
 use crate::document::{CommentFormat, Document, StrFormat};
 use crate::error::Error;
 use crate::integer::Int;
+use crate::merge::{entry_key, CommentMerge, Merger};
+use crate::number::parse_hex_float;
+use crate::transform::ERROR_CLASS;
 
 #[derive(Default)]
 struct Inner {
     lines: Vec<usize>,
+    // State of tolerant parsing: the errors found, the edits made to repair
+    // the text and the positions of the repaired values.
+    tolerant: bool,
+    errors: Vec<Error>,
+    original: String,
+    edits: Vec<(usize, isize)>,
+    marks: Vec<usize>,
+}
+
+// The most errors a tolerant parse will repair before giving up.
+const MAX_REPAIRS: usize = 256;
+
+impl Inner {
+    // Maps a position in the repaired text to the original text.
+    fn original_pos(&self, mut pos: usize) -> usize {
+        for &(at, delta) in self.edits.iter().rev() {
+            if delta >= 0 {
+                let len = delta as usize;
+                if pos >= at + len {
+                    pos -= len;
+                } else if pos > at {
+                    pos = at;
+                }
+            } else if pos >= at {
+                pos += delta.unsigned_abs();
+            }
+        }
+        pos
+    }
+
+    // Records the replacement of `remove` bytes at `at` by `insert` bytes.
+    fn edit(&mut self, at: usize, remove: usize, insert: usize) {
+        self.marks.retain(|&m| m < at || m >= at + remove);
+        for m in self.marks.iter_mut().filter(|m| **m >= at) {
+            *m = *m + insert - remove;
+        }
+        if remove > 0 {
+            self.edits.push((at, -(remove as isize)));
+        }
+        if insert > 0 {
+            self.edits.push((at, insert as isize));
+        }
+    }
+
+    // Returns a syntax error at `pos` in the repaired text, located in the
+    // original text.
+    fn error_at(&self, msg: String, pos: usize) -> Error {
+        let pos = self.original_pos(pos).min(self.original.len());
+        let start = self.original[..pos].rfind('\n').map_or(0, |n| n + 1);
+        let end = self.original[pos..]
+            .find('\n')
+            .map_or(self.original.len(), |n| pos + n);
+        let line = self.original[..pos].matches('\n').count() + 1;
+        let col = self.original[start..pos].chars().count() + 1;
+        Error::SyntaxError(
+            msg,
+            line,
+            col,
+            self.original[start..end].trim_end().into(),
+            "^",
+        )
+    }
 }
 
 /// `Relax` is a permissive JSON parser that permits many common extensions to
@@ -31,6 +96,8 @@ pub struct Relax {
     pub number_oct: bool,
     pub number_plus: bool,
     pub number_lax_dec_point: bool,
+    pub number_separator: bool,
+    pub number_suffix: bool,
     pub string_single_quote: bool,
     pub string_unquoted: bool,
     pub string_ident: bool,
@@ -39,6 +106,19 @@ pub struct Relax {
     pub comment_slash: bool,
     pub comment_hash: bool,
     pub comment_block: bool,
+    /// How to handle duplicate keys in a mapping.  When `None`, duplicate
+    /// entries are kept.  Otherwise they are coalesced into the first entry
+    /// as by `ConfigLoader` (mappings are merged by key and later values
+    /// replace earlier ones) with comments combined according to the policy.
+    /// For `CommentMerge::Origin`, the origin of an entry is its line number.
+    pub duplicate_keys: Option<CommentMerge>,
+    /// Whether to expand the generator comments written for arithmetic runs
+    /// (see `RunStyle::Generator`) into the elements of their runs.
+    pub expand_runs: bool,
+    /// Whether to read objects of the form `{"$float": "inf"}`, as written
+    /// by `NonFinite::Object`, as floats.  Objects carrying comments are
+    /// kept as mappings so that the comments are not lost.
+    pub float_objects: bool,
 }
 
 pub(crate) type ParseError = PestError<Rule>;
@@ -55,6 +135,8 @@ impl Default for Relax {
             number_oct: true,
             number_plus: true,
             number_lax_dec_point: true,
+            number_separator: true,
+            number_suffix: true,
             string_single_quote: true,
             string_unquoted: true,
             string_ident: true,
@@ -63,6 +145,9 @@ impl Default for Relax {
             comment_slash: true,
             comment_hash: true,
             comment_block: true,
+            duplicate_keys: None,
+            expand_runs: false,
+            float_objects: false,
         }
     }
 }
@@ -79,6 +164,8 @@ impl Relax {
             number_oct: false,
             number_plus: false,
             number_lax_dec_point: false,
+            number_separator: false,
+            number_suffix: false,
             string_single_quote: false,
             string_unquoted: false,
             string_ident: false,
@@ -87,9 +174,23 @@ impl Relax {
             comment_slash: false,
             comment_hash: false,
             comment_block: false,
+            duplicate_keys: None,
+            expand_runs: false,
+            float_objects: true,
         }
     }
 
+    /// Creates a JSON with comments (JSONC) parser.  Like the parsers of
+    /// VS Code settings and `tsconfig.json`, it accepts `//` and `/* */`
+    /// comments and trailing commas.
+    pub fn jsonc() -> Self {
+        let mut r = Self::json();
+        r.comma_trailing = true;
+        r.comment_slash = true;
+        r.comment_block = true;
+        r
+    }
+
     /// Creates a json5 parser.
     pub fn json5() -> Self {
         let mut r = Self::default();
@@ -99,6 +200,8 @@ impl Relax {
         r.comment_hash = false;
         r.number_bin = false;
         r.number_oct = false;
+        r.number_separator = false;
+        r.number_suffix = false;
         r
     }
 
@@ -111,15 +214,220 @@ impl Relax {
         r.number_oct = false;
         r.number_plus = false;
         r.number_lax_dec_point = false;
+        r.number_separator = false;
+        r.number_suffix = false;
         r
     }
 
     /// Parses a string into a `Document`.
     pub fn from_str(&self, text: &str) -> Result<Document, Error> {
-        // Iterate over the input text and remember the line breaks. Since we use
-        // positioning information to infer which comments belong with which json
-        // items, caching the line-number information speeds up parsing
-        // quite a bit.
+        self.inner.replace(Self::index_lines(text));
+        let json = Relax::parse(Rule::text, text)?.next().unwrap();
+        let doc = self.handle_pair(json)?;
+        Ok(if self.expand_runs {
+            doc.expand_runs()
+        } else {
+            doc
+        })
+    }
+
+    /// Parses a string into a `Document`, recovering from syntax errors.
+    ///
+    /// Rather than stopping at the first error, the parser repairs the text
+    /// (e.g. by inserting a missing value or closing bracket, or by skipping
+    /// an unexpected token) and continues, returning a best-effort document
+    /// along with every error found, in order of position.  Values supplied
+    /// by a repair are wrapped in the `ERROR_CLASS` color class.
+    pub fn from_str_tolerant(&self, text: &str) -> (Document, Vec<Error>) {
+        let mut repaired = text.to_string();
+        let mut inner = Inner {
+            tolerant: true,
+            original: text.to_string(),
+            ..Default::default()
+        };
+        let error_node = || Document::ColorClass(ERROR_CLASS.into(), Box::new(Document::Null));
+        let mut last = None;
+        let mut stuck = 0;
+        let mut doc = None;
+        for _ in 0..MAX_REPAIRS {
+            let err = match Relax::parse(Rule::text, &repaired) {
+                Ok(_) => break,
+                Err(e) => e,
+            };
+            let pos = match err.location {
+                InputLocation::Pos(p) => p,
+                InputLocation::Span((p, _)) => p,
+            };
+            let expects_value = match &err.variant {
+                ErrorVariant::ParsingError { positives, .. } => positives.contains(&Rule::null),
+                ErrorVariant::CustomError { .. } => false,
+            };
+            // Repeated failure at one position means the repairs are not
+            // making progress, so skip the rest of the line, and failing that,
+            // give up.
+            if last == Some(inner.original_pos(pos)) {
+                stuck += 1;
+            } else {
+                stuck = 0;
+            }
+            last = Some(inner.original_pos(pos));
+            let (msg, at, remove, insert) = Self::repair(&repaired, pos, expects_value, stuck > 0);
+            let error = inner.error_at(msg, pos);
+            inner.errors.push(error);
+            if stuck > 2 || (remove == 0 && insert.is_empty()) {
+                doc = Some(error_node());
+                break;
+            }
+            inner.edit(at, remove, insert.len());
+            if insert != ":" && !insert.starts_with(['}', ']']) {
+                inner.marks.push(if insert == "null" { at } else { pos });
+            }
+            repaired.replace_range(at..at + remove, &insert);
+        }
+        inner.lines = Self::index_lines(&repaired).lines;
+        self.inner.replace(inner);
+        let doc = match doc {
+            Some(doc) => doc,
+            None => match Relax::parse(Rule::text, &repaired) {
+                Ok(mut pairs) => match self.handle_pair(pairs.next().unwrap()) {
+                    Ok(doc) => doc,
+                    Err(e) => {
+                        self.inner.borrow_mut().errors.push(e);
+                        error_node()
+                    }
+                },
+                Err(e) => {
+                    self.inner.borrow_mut().errors.push(e.into());
+                    error_node()
+                }
+            },
+        };
+        let mut inner = self.inner.take();
+        inner.errors.sort_by_key(|e| match e {
+            Error::SyntaxError(_, line, col, _, _) => (*line, *col),
+            _ => (usize::MAX, usize::MAX),
+        });
+        (doc, inner.errors)
+    }
+
+    // Returns a repair of the text at `pos`, where parsing failed: an error
+    // message, and the position, length and replacement of the text to
+    // replace.
+    fn repair(
+        text: &str,
+        pos: usize,
+        expects_value: bool,
+        stuck: bool,
+    ) -> (String, usize, usize, String) {
+        let rest = &text[pos..];
+        let line_end = rest.find(['\n', '\r']).unwrap_or(rest.len());
+        if stuck && line_end > 0 {
+            return ("unexpected text".into(), pos, line_end, String::new());
+        }
+        let open = Self::open_brackets(&text[..pos]);
+        let prev = text[..pos].trim_end().chars().last();
+        let closer = |open: char| if open == '{' { "}" } else { "]" };
+        match rest.chars().next() {
+            None => match open.last() {
+                Some(_) if expects_value && prev == Some(':') => {
+                    ("expected value".into(), pos, 0, "null".into())
+                }
+                Some(&b) => (
+                    format!("expected `{}`", closer(b)),
+                    pos,
+                    0,
+                    closer(b).into(),
+                ),
+                None if expects_value && prev.is_none() => {
+                    ("expected value".into(), pos, 0, "null".into())
+                }
+                None => ("unexpected end of input".into(), pos, 0, String::new()),
+            },
+            Some(ch @ ('}' | ']')) => match open.last() {
+                Some(&b) if closer(b).starts_with(ch) => {
+                    if expects_value && prev == Some(':') {
+                        ("expected value".into(), pos, 0, "null".into())
+                    } else if b == '{' && !expects_value {
+                        ("expected `:`".into(), pos, 0, ":".into())
+                    } else {
+                        (format!("unexpected `{}`", ch), pos, 1, String::new())
+                    }
+                }
+                // The bracket closes an outer aggregate, so the inner one is
+                // missing its closing bracket.
+                Some(&b) if open.iter().any(|&o| closer(o).starts_with(ch)) => (
+                    format!("expected `{}`", closer(b)),
+                    pos,
+                    0,
+                    closer(b).into(),
+                ),
+                _ => (format!("unexpected `{}`", ch), pos, 1, String::new()),
+            },
+            Some(',') if expects_value => ("expected value".into(), pos, 0, "null".into()),
+            Some(q @ ('"' | '\'')) if expects_value => {
+                let end = pos + line_end;
+                ("unterminated string".into(), end, 0, q.to_string())
+            }
+            Some(_) if !expects_value && open.last() == Some(&'{') => {
+                ("expected `:`".into(), pos, 0, ":".into())
+            }
+            Some(ch) => {
+                let len = match rest.find(|c: char| c.is_whitespace() || ",:{}[]".contains(c)) {
+                    Some(0) => ch.len_utf8(),
+                    Some(n) => n,
+                    None => rest.len(),
+                };
+                let token = &rest[..len];
+                (format!("unexpected `{}`", token), pos, len, String::new())
+            }
+        }
+    }
+
+    // Returns the brackets left open at the end of `text`, ignoring those in
+    // strings and comments.
+    fn open_brackets(text: &str) -> Vec<char> {
+        let mut open = Vec::new();
+        let mut it = text.chars().peekable();
+        while let Some(ch) = it.next() {
+            match ch {
+                '{' | '[' => open.push(ch),
+                '}' | ']' => {
+                    let _ = open.pop();
+                }
+                '"' | '\'' => {
+                    while let Some(c) = it.next() {
+                        match c {
+                            '\\' => {
+                                let _ = it.next();
+                            }
+                            '\n' => break,
+                            c if c == ch => break,
+                            _ => {}
+                        }
+                    }
+                }
+                '#' => while it.next_if(|&c| c != '\n').is_some() {},
+                '/' if it.peek() == Some(&'/') => while it.next_if(|&c| c != '\n').is_some() {},
+                '/' if it.peek() == Some(&'*') => {
+                    let mut prev = ' ';
+                    for c in it.by_ref() {
+                        if prev == '*' && c == '/' {
+                            break;
+                        }
+                        prev = c;
+                    }
+                }
+                _ => {}
+            }
+        }
+        open
+    }
+
+    // Iterates over the input text and remembers the line breaks. Since we use
+    // positioning information to infer which comments belong with which json
+    // items, caching the line-number information speeds up parsing
+    // quite a bit.
+    fn index_lines(text: &str) -> Inner {
         let mut inner = Inner::default();
         inner.lines.push(0);
         for (i, ch) in text.char_indices() {
@@ -128,9 +436,7 @@ impl Relax {
             }
         }
         inner.lines.push(usize::MAX);
-        self.inner.replace(inner);
-        let json = Relax::parse(Rule::text, text)?.next().unwrap();
-        self.handle_pair(json)
+        inner
     }
 
     fn line_col(&self, pos: usize) -> (usize, usize) {
@@ -152,6 +458,10 @@ impl Relax {
         }
     }
 
+    fn unhex4(it: &mut std::str::Chars) -> u32 {
+        (0..4).fold(0, |v, _| (v << 4) | Self::unhex(it.next().unwrap()))
+    }
+
     fn unescape(text: &str) -> Result<String, Error> {
         let mut s = String::with_capacity(text.len());
         let mut it = text.chars();
@@ -170,11 +480,18 @@ impl Relax {
                     't' => '\t',
                     '\n' => '\n', // json5 multi-line string.
                     'u' => {
-                        let mut v = 0;
-                        v = (v << 4) | Self::unhex(it.next().unwrap());
-                        v = (v << 4) | Self::unhex(it.next().unwrap());
-                        v = (v << 4) | Self::unhex(it.next().unwrap());
-                        v = (v << 4) | Self::unhex(it.next().unwrap());
+                        let mut v = Self::unhex4(&mut it);
+                        // Combine a UTF-16 surrogate pair into a single char.
+                        if (0xD800..0xDC00).contains(&v) {
+                            let mut next = it.clone();
+                            if next.next() == Some('\\') && next.next() == Some('u') {
+                                let lo = Self::unhex4(&mut next);
+                                if (0xDC00..0xE000).contains(&lo) {
+                                    v = 0x10000 + ((v - 0xD800) << 10) + (lo - 0xDC00);
+                                    it = next;
+                                }
+                            }
+                        }
                         char::try_from(v)?
                     }
                     'x' => {
@@ -193,47 +510,76 @@ impl Relax {
         Ok(s)
     }
 
-    fn from_str_radix(text: &str, radix: u32) -> Result<Document, Error> {
-        match Int::from_str_radix(text, radix) {
+    fn handle_integer(&self, pair: &Pair<Rule>, radix: u32) -> Result<Document, Error> {
+        let text = pair.as_str();
+        match Int::from_str_suffixed(text, radix) {
             Ok(val) => Ok(Document::Int(val)),
-            Err(_) => Ok(Document::String(text.into(), StrFormat::Standard)),
+            Err(_) => {
+                // A suffixed literal names its type, so a value outside that
+                // type is an error rather than an oversized number.
+                self.syntax_error(
+                    text.contains(['u', 'U', 'l', 'L', 'i', 'I']),
+                    "integer out of range for its suffix",
+                    pair.as_span().start_pos(),
+                )?;
+                Ok(Document::String(text.into(), StrFormat::Standard))
+            }
         }
     }
 
     fn handle_number(&self, pair: Pair<Rule>) -> Result<Document, Error> {
         let text = pair.as_str();
         let t = if let Some(t) = text.strip_prefix('+') {
-            Self::syntax_error(!self.number_plus, "leading `+`", pair.as_span().start_pos())?;
+            self.syntax_error(!self.number_plus, "leading `+`", pair.as_span().start_pos())?;
             t
         } else if let Some(t) = text.strip_prefix('-') {
             t
         } else {
             text
         };
-        if t.starts_with("0x") || t.starts_with("0X") {
+        self.syntax_error(
+            !self.number_separator && t.contains('_'),
+            "digit separator",
+            pair.as_span().start_pos(),
+        )?;
+        self.syntax_error(
+            !self.number_suffix && t.contains(['u', 'U', 'l', 'L', 'i', 'I']) && t != "Infinity",
+            "integer suffix",
+            pair.as_span().start_pos(),
+        )?;
+        if (t.starts_with("0x") || t.starts_with("0X")) && t.contains(['p', 'P']) {
+            // Hexadecimal float.
+            self.syntax_error(
+                !self.number_hex,
+                "hexadecimal literal",
+                pair.as_span().start_pos(),
+            )?;
+            // Unwrap is ok: the grammar only admits well-formed hex floats.
+            return Ok(Document::Float(parse_hex_float(text).unwrap()));
+        } else if t.starts_with("0x") || t.starts_with("0X") {
             // Hexadecimal integer.
-            Self::syntax_error(
+            self.syntax_error(
                 !self.number_hex,
                 "hexadecimal literal",
                 pair.as_span().start_pos(),
             )?;
-            return Self::from_str_radix(text, 16);
+            return self.handle_integer(&pair, 16);
         } else if t.starts_with("0b") || t.starts_with("0B") {
             // Binary integer.
-            Self::syntax_error(
+            self.syntax_error(
                 !self.number_bin,
                 "binary literal",
                 pair.as_span().start_pos(),
             )?;
-            return Self::from_str_radix(text, 2);
+            return self.handle_integer(&pair, 2);
         } else if t.starts_with("0o") || t.starts_with("0O") {
             // Octal integer.
-            Self::syntax_error(
+            self.syntax_error(
                 !self.number_oct,
                 "octal literal",
                 pair.as_span().start_pos(),
             )?;
-            return Self::from_str_radix(text, 8);
+            return self.handle_integer(&pair, 8);
         } else if t.contains('.')
             || t.contains('e')
             || t.contains('E')
@@ -241,7 +587,7 @@ impl Relax {
             || t == "Infinity"
         {
             // Floating point number.
-            Self::syntax_error(
+            self.syntax_error(
                 !self.number_lax_dec_point && (t.starts_with('.') || t.ends_with('.')),
                 "bad float literal",
                 pair.as_span().start_pos(),
@@ -249,11 +595,11 @@ impl Relax {
             return Ok(Document::Float(text.parse().unwrap()));
         } else {
             // Decimal integer.
-            return Self::from_str_radix(text, 10);
+            return self.handle_integer(&pair, 10);
         }
     }
 
-    fn handle_kvpair(&self, pairs: &mut Pairs<Rule>) -> Result<(Document, bool), Error> {
+    fn handle_kvpair(&self, pairs: &mut Pairs<Rule>) -> Result<(Document, bool, usize), Error> {
         let mut k = usize::MAX;
         let mut v = usize::MAX;
         let mut kv = vec![];
@@ -300,7 +646,33 @@ impl Relax {
             // Advance the iterator.
             let _ = pairs.next();
         }
-        Ok((Document::Fragment(kv), comma))
+        Ok((Document::Fragment(kv), comma, k))
+    }
+
+    // Coalesces the entries with the same key in a mapping.  Each entry is
+    // paired with the line of its key.
+    fn coalesce(&self, policy: &CommentMerge, kvs: Vec<(Document, usize)>) -> Vec<Document> {
+        let mut merger = Merger::new(policy, "");
+        let mut entries: Vec<Document> = Vec::with_capacity(kvs.len());
+        for (kv, line) in kvs {
+            let origin = format!("line {}", line + 1);
+            let existing = entry_key(&kv).and_then(|key| {
+                entries
+                    .iter()
+                    .position(|e| entry_key(e).as_ref() == Some(&key))
+                    .map(|i| (i, key))
+            });
+            match existing {
+                Some((i, key)) => merger.merge_entry(&mut entries[i], kv, &origin, &mut vec![key]),
+                None => {
+                    if let Some(key) = entry_key(&kv) {
+                        merger.set_origin(vec![key], &origin);
+                    }
+                    entries.push(kv);
+                }
+            }
+        }
+        entries
     }
 
     fn handle_array_elem(&self, pairs: &mut Pairs<Rule>) -> Result<(Document, bool), Error> {
@@ -365,8 +737,13 @@ impl Relax {
             .collect::<Vec<_>>()
     }
 
-    fn syntax_error(err: bool, msg: &str, pos: Position) -> Result<(), Error> {
-        if err {
+    fn syntax_error(&self, err: bool, msg: &str, pos: Position) -> Result<(), Error> {
+        if err && self.inner.borrow().tolerant {
+            let mut inner = self.inner.borrow_mut();
+            let error = inner.error_at(msg.into(), pos.pos());
+            inner.errors.push(error);
+            Ok(())
+        } else if err {
             let (ln, col) = pos.line_col();
             Err(Error::SyntaxError(
                 msg.into(),
@@ -383,7 +760,7 @@ impl Relax {
     fn handle_comment(&self, pair: Pair<Rule>) -> Result<Document, Error> {
         let comment = pair.as_str();
         if let Some(c) = comment.strip_prefix("/*") {
-            Self::syntax_error(
+            self.syntax_error(
                 !self.comment_block,
                 "block comment",
                 pair.as_span().start_pos(),
@@ -400,7 +777,7 @@ impl Relax {
             let c = lines[start..].join("\n");
             Ok(Document::Comment(c, CommentFormat::Block))
         } else if comment.starts_with("//") {
-            Self::syntax_error(
+            self.syntax_error(
                 !self.comment_slash,
                 "slash comment",
                 pair.as_span().start_pos(),
@@ -417,7 +794,7 @@ impl Relax {
             let c = lines[..end].join("\n");
             Ok(Document::Comment(c, CommentFormat::SlashSlash))
         } else if comment.starts_with("#") {
-            Self::syntax_error(
+            self.syntax_error(
                 !self.comment_hash,
                 "hash comment",
                 pair.as_span().start_pos(),
@@ -441,7 +818,7 @@ impl Relax {
     fn handle_string(&self, pair: Pair<Rule>) -> Result<Document, Error> {
         let s = pair.as_str();
         if s.starts_with("'''") {
-            Self::syntax_error(
+            self.syntax_error(
                 !self.string_hjson_multiline,
                 "unexpected hjson multiline string",
                 pair.as_span().start_pos(),
@@ -462,7 +839,7 @@ impl Relax {
             }
             Ok(Document::String(value.join("\n"), StrFormat::Multiline))
         } else if s.starts_with('\'') || s.starts_with('"') {
-            Self::syntax_error(
+            self.syntax_error(
                 !self.string_single_quote && s.starts_with("'"),
                 "single quote",
                 pair.as_span().start_pos(),
@@ -473,19 +850,33 @@ impl Relax {
                 || s.contains("\\\n")
                 || s.contains("\\\u{2028}")
                 || s.contains("\\\u{2029}");
-            Self::syntax_error(
+            self.syntax_error(
                 !self.string_json5_multiline && json5_line_cont,
                 "unexpected end of line",
                 pair.as_span().start_pos(),
             )?;
             let format = if json5_line_cont {
                 StrFormat::Multiline
+            } else if pair.as_str().starts_with('\'') {
+                StrFormat::SingleQuoted
             } else {
                 StrFormat::Standard
             };
-            Ok(Document::String(Self::unescape(s)?, format))
+            let value = match Self::unescape(s) {
+                Ok(value) => value,
+                Err(e) if self.inner.borrow().tolerant => {
+                    let mut inner = self.inner.borrow_mut();
+                    let start = pair.as_span().start();
+                    let error = inner.error_at(e.to_string(), start);
+                    inner.errors.push(error);
+                    inner.marks.push(start);
+                    s.to_string()
+                }
+                Err(e) => return Err(e),
+            };
+            Ok(Document::String(value, format))
         } else {
-            Self::syntax_error(
+            self.syntax_error(
                 !self.string_unquoted,
                 "missing quotes",
                 pair.as_span().start_pos(),
@@ -494,13 +885,25 @@ impl Relax {
         }
     }
 
+    // Wraps a value supplied by a tolerant parse's repair in the error class.
+    fn mark(&self, pair: &Pair<Rule>, node: Document) -> Document {
+        if self.inner.borrow().marks.contains(&pair.as_span().start()) {
+            Document::ColorClass(ERROR_CLASS.into(), Box::new(node))
+        } else {
+            node
+        }
+    }
+
     fn handle_pair(&self, pair: Pair<Rule>) -> Result<Document, Error> {
         match pair.as_rule() {
-            Rule::null => Ok(Document::Null),
+            Rule::null => Ok(self.mark(&pair, Document::Null)),
             Rule::boolean => Ok(Document::Boolean(pair.as_str().parse().unwrap())),
-            Rule::string => self.handle_string(pair),
+            Rule::string => {
+                let node = self.handle_string(pair.clone())?;
+                Ok(self.mark(&pair, node))
+            }
             Rule::hjson_key => {
-                Self::syntax_error(
+                self.syntax_error(
                     !self.string_ident,
                     "missing quotes",
                     pair.as_span().start_pos(),
@@ -508,7 +911,7 @@ impl Relax {
                 Ok(Document::String(pair.as_str().into(), StrFormat::Unquoted))
             }
             Rule::identifier => {
-                Self::syntax_error(
+                self.syntax_error(
                     !self.string_ident,
                     "missing quotes",
                     pair.as_span().start_pos(),
@@ -525,26 +928,38 @@ impl Relax {
                 let mut need_comma = false;
                 while pairs.peek().is_some() {
                     if !self.comma_optional {
-                        Self::syntax_error(
+                        self.syntax_error(
                             need_comma ^ saw_comma,
                             "expected comma",
                             npair.unwrap().as_span().end_pos(),
                         )?;
                     }
                     npair = pairs.peek();
-                    let (node, comma) = self.handle_kvpair(&mut pairs)?;
-                    kvs.push(node);
+                    let (node, comma, line) = self.handle_kvpair(&mut pairs)?;
+                    kvs.push((node, line));
                     saw_comma = comma;
                     need_comma = true;
                 }
                 if npair.is_some() {
-                    Self::syntax_error(
+                    self.syntax_error(
                         !self.comma_trailing && saw_comma,
                         "no comma expected",
                         npair.unwrap().as_span().end_pos(),
                     )?;
                 }
-                Ok(Document::Mapping(kvs))
+                let kvs = match &self.duplicate_keys {
+                    Some(policy) => self.coalesce(policy, kvs),
+                    None => kvs.into_iter().map(|(kv, _)| kv).collect(),
+                };
+                let mapping = Document::Mapping(kvs);
+                // Non-finite floats may be encoded as `{"$float": "inf"}`
+                // objects in strict JSON.
+                match mapping.as_float_object() {
+                    Some(f) if self.float_objects && !has_comment(&mapping) => {
+                        Ok(Document::Float(f))
+                    }
+                    _ => Ok(mapping),
+                }
             }
             Rule::array => {
                 let mut pairs = pair.into_inner();
@@ -554,7 +969,7 @@ impl Relax {
                 let mut need_comma = false;
                 while pairs.peek().is_some() {
                     if !self.comma_optional {
-                        Self::syntax_error(
+                        self.syntax_error(
                             need_comma ^ saw_comma,
                             "expected comma",
                             npair.unwrap().as_span().end_pos(),
@@ -568,7 +983,7 @@ impl Relax {
                     need_comma = true;
                 }
                 if npair.is_some() {
-                    Self::syntax_error(
+                    self.syntax_error(
                         !self.comma_trailing && saw_comma,
                         "no comma expected",
                         npair.unwrap().as_span().end_pos(),
@@ -600,9 +1015,19 @@ impl Relax {
     }
 }
 
+// Returns whether a mapping or any of its entries carries a comment.
+fn has_comment(doc: &Document) -> bool {
+    match doc {
+        Document::Comment(..) => true,
+        Document::Mapping(m) | Document::Fragment(m) => m.iter().any(has_comment),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::emit::Emit;
     use anyhow::{anyhow, Result};
 
     #[test]
@@ -640,6 +1065,8 @@ mod tests {
         assert_eq!(s, "\"'\\/\u{8}\u{c}\n\r\t\u{2122}\u{00ac}");
         let s = parse_string(&relax, r#" "\e" "#);
         assert_eq!(s.unwrap_err().to_string(), "unhandled escape: `\\e`");
+        let s = parse_string(&relax, r#" "\ud83d\ude00" "#)?;
+        assert_eq!(s, "\u{1f600}");
         let s = parse_string(&relax, r#" "\uD800" "#);
         assert_eq!(
             s.unwrap_err().to_string(),
@@ -692,6 +1119,57 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_number_separator() -> Result<()> {
+        let relax = Relax::default();
+        let i = parse_integer(&relax, "0x1234_5678")?;
+        assert_eq!(i, 0x12345678);
+        let i = parse_integer(&relax, "0b1010_0101")?;
+        assert_eq!(i, 0xA5);
+        let i = parse_integer(&relax, "-0o7_755")?;
+        assert_eq!(i, -0o7755);
+        let relax = Relax::json5();
+        let i = parse_integer(&relax, "0x1234_5678");
+        assert!(i
+            .unwrap_err()
+            .to_string()
+            .starts_with("syntax error: digit separator"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_number_suffix() -> Result<()> {
+        let relax = Relax::default();
+        let i = parse_integer(&relax, "0x10u32")?;
+        assert_eq!(i, 16);
+        let i = parse_integer(&relax, "0b1010_0101U8")?;
+        assert_eq!(i, 0xA5);
+        let i = parse_integer(&relax, "-42L")?;
+        assert_eq!(i, -42);
+        let i = parse_integer(&relax, "0xFFFAi16")?;
+        assert_eq!(i, -6);
+        // Only Rust source accepts the suffix when the value is emitted, and
+        // there only on a literal in the range of its type.
+        let doc = Document::parse("[0xFFFAi16, 0x7FFFi16]")?;
+        let json5 = doc.clone().to_json5().compact(true);
+        assert_eq!(json5.to_text()?, "[0xFFFA, 0x7FFF]");
+        let yaml = doc.clone().to_yaml().compact(true);
+        assert_eq!(yaml.to_text()?, "---\n[0xFFFA, 0x7FFF]");
+        assert_eq!(doc.to_rust().to_text()?, "[\n  -6,\n  0x7FFFi16,\n]\n");
+        let i = parse_integer(&relax, "0x100u8");
+        assert!(i
+            .unwrap_err()
+            .to_string()
+            .starts_with("syntax error: integer out of range for its suffix"));
+        let relax = Relax::json5();
+        let i = parse_integer(&relax, "0x10u32");
+        assert!(i
+            .unwrap_err()
+            .to_string()
+            .starts_with("syntax error: integer suffix"));
+        Ok(())
+    }
+
     #[test]
     fn test_number_dec() -> Result<()> {
         let relax = Relax::default();
@@ -1054,4 +1532,139 @@ mod tests {
         .is_ok());
         Ok(())
     }
+
+    #[test]
+    fn test_tolerant() -> Result<()> {
+        let text = r#"{
+  "a": ,
+  "b" 2,
+  "c": [1, 2,, 3],
+  "d": 0x10,
+  "e": [true, false}
+"#;
+        assert!(Relax::json().from_str(text).is_err());
+        let (doc, errors) = Relax::json().from_str_tolerant(text);
+        let errors = errors
+            .iter()
+            .map(|e| match e {
+                Error::SyntaxError(msg, line, col, _, _) => format!("{}:{}: {}", line, col, msg),
+                e => e.to_string(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "2:8: expected value",
+                "3:7: expected `:`",
+                "4:14: expected value",
+                "5:8: hexadecimal literal",
+                "6:20: expected `]`",
+            ]
+        );
+        assert_eq!(
+            doc.clone().to_json().compact(true).to_text()?,
+            r#"{"a": null, "b": 2, "c": [1, 2, null, 3], "d": 16, "e": [true, false]}"#
+        );
+        // Values supplied by a repair are marked.
+        let marked = doc
+            .iter()
+            .filter(|n| matches!(n, Document::ColorClass(c, _) if c == ERROR_CLASS))
+            .count();
+        assert_eq!(marked, 2);
+
+        // Each error is located in the original text.
+        let (_, errors) = Relax::default().from_str_tolerant("[1 @ 2, {a: }]");
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "syntax error: unexpected `@` at 1:4\n| [1 @ 2, {a: }]\n|    ^",
+                "syntax error: expected value at 1:13\n| [1 @ 2, {a: }]\n|             ^",
+            ]
+        );
+
+        // Well-formed text parses as usual.
+        let (doc, errors) = Relax::default().from_str_tolerant("{a: [1, 2]}");
+        assert!(errors.is_empty());
+        assert_eq!(doc.to_json().compact(true).to_text()?, r#"{"a": [1, 2]}"#);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_keys() -> Result<()> {
+        let text = r#"{
+            // First.
+            a: 1,
+            b: {x: 1, y: 2},
+            // Second.
+            a: 2,
+            b: {y: 3},
+        }"#;
+        let mut relax = Relax::json5();
+        let doc = relax.from_str(text)?;
+        assert!(matches!(doc.as_value()?, Document::Mapping(m) if m.len() == 4));
+
+        let mut parse = |policy| -> Result<String> {
+            relax.duplicate_keys = Some(policy);
+            Ok(relax.from_str(text)?.to_json5().to_text()?)
+        };
+        assert_eq!(
+            parse(CommentMerge::Latest)?,
+            "{\n  // Second.\n  a: 2,\n  b: {\n    x: 1,\n    y: 3\n  }\n}"
+        );
+        assert_eq!(
+            parse(CommentMerge::Earliest)?,
+            "{\n  // First.\n  a: 2,\n  b: {\n    x: 1,\n    y: 3\n  }\n}"
+        );
+        assert!(parse(CommentMerge::Concatenate("--".into()))?
+            .starts_with("{\n  // First.\n  // --\n  // Second.\n  a: 2,"));
+        assert!(parse(CommentMerge::Origin)?
+            .starts_with("{\n  // line 3:\n  // First.\n  // line 6:\n  // Second.\n  a: 2,"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_str_trait() -> Result<()> {
+        let doc: Document = r#"{"a": [1, 2]}"#.parse()?;
+        assert_eq!(doc.to_json().compact(true).to_text()?, r#"{"a": [1, 2]}"#);
+        let doc = Document::from_reader(&b"[true, null]"[..])?;
+        assert_eq!(doc.to_json().compact(true).to_text()?, "[true, null]");
+
+        // Parsing is strict json by default.
+        let err = "{\n  a: 1\n}".parse::<Document>().unwrap_err();
+        assert_eq!(err.line_col(), Some((2, 3)));
+        let err = "[1, 2".parse::<Document>().unwrap_err();
+        assert_eq!(err.line_col(), Some((1, 6)));
+        assert!(err.expected().contains(&"comma".to_string()));
+        let err = "[1 2]".parse::<Document>().unwrap_err();
+        assert_eq!(err.line_col(), Some((1, 3)));
+        assert_eq!(err.expected(), ["comma"]);
+        assert!(matches!(
+            Document::from_reader(&b"\xff"[..]),
+            Err(Error::IoError(_))
+        ));
+        Ok(())
+    }
+    #[test]
+    fn test_float_objects() -> Result<()> {
+        let text = "[{\"$float\": \"inf\"}, {\"$float\": \"nan\" // Unset.\n}]";
+        let doc = Relax::jsonc().from_str(text)?;
+        assert_eq!(
+            doc.clone().to_json5().compact(true).to_text()?,
+            "[Infinity, {$float: \"nan\"}]"
+        );
+        // The comment is kept, and the mapping still reads as a float.
+        assert_eq!(
+            doc.to_json5().to_text()?,
+            "[\n  Infinity,\n  {\n    $float: \"nan\" // Unset.\n  }\n]"
+        );
+        let v: Vec<f64> = crate::from_str(text)?;
+        assert!(v[0].is_infinite() && v[1].is_nan());
+        // Only strict JSON encodes floats as objects.
+        let doc = Relax::json5().from_str(text)?;
+        assert_eq!(
+            doc.to_json5().compact(true).to_text()?,
+            "[{$float: \"inf\"}, {$float: \"nan\"}]"
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,217 @@
+// Rendering of indentation and scalars shared by the emitters.
+use std::fmt;
+
+use ansi_term::Style;
+
+use crate::color::ColorProfile;
+use crate::document::Document;
+use crate::emit::Emit;
+use crate::error::Error;
+use crate::integer::Base;
+use crate::layout::TokenSink;
+use crate::number::{FloatFormat, NumberContext, NumberFormatter};
+use crate::width::str_width;
+
+type Result<T> = std::result::Result<T, Error>;
+
+// The number of bytes written on each line of a multi-line bytes array.
+const BYTES_PER_LINE: usize = 16;
+
+const SPACE: &str = "                                                                                                    ";
+
+// Writes `len` spaces.
+pub(crate) fn write_indent<W: TokenSink + ?Sized>(w: &mut W, mut len: usize) -> Result<()> {
+    while len > 0 {
+        let chunk = std::cmp::min(len, SPACE.len());
+        w.write_str(&SPACE[..chunk])?;
+        len -= chunk;
+    }
+    Ok(())
+}
+
+// A writer which measures a trial rendering.  It fails as soon as the
+// rendering breaks a line or grows wider than its limit, so that measuring a
+// large aggregate stops early.
+pub(crate) struct LineMeasure {
+    width: usize,
+    limit: usize,
+    exceeded: bool,
+}
+
+impl fmt::Write for LineMeasure {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.width += str_width(s);
+        if s.contains('\n') || self.width > self.limit {
+            self.exceeded = true;
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+// Returns the width of the text written by `render`, or `None` if the text
+// does not fit on one line of `limit` columns.
+pub(crate) fn line_width<F>(limit: usize, render: F) -> Result<Option<usize>>
+where
+    F: FnOnce(&mut LineMeasure) -> Result<()>,
+{
+    let mut w = LineMeasure {
+        width: 0,
+        limit,
+        exceeded: false,
+    };
+    match render(&mut w) {
+        _ if w.exceeded => Ok(None),
+        Ok(()) => Ok(Some(w.width)),
+        Err(e) => Err(e),
+    }
+}
+
+// Returns `doc` in compact json5 form for display, or its variant name if
+// it cannot be emitted (e.g. `Document::Absent`).
+pub(crate) fn display_text(doc: &Document) -> String {
+    match doc.clone().to_json5().compact(true).to_text() {
+        Ok(text) => text,
+        Err(_) => doc.variant().to_string(),
+    }
+}
+
+// Returns the literal of the finite float `f`.  A literal without a
+// fraction or an exponent reads as an integer, so it gains a `.0`.  Formats
+// without hexadecimal floats write `FloatFormat::Hex` as the shortest
+// decimal instead.
+pub(crate) fn float_literal(
+    numbers: &dyn NumberFormatter,
+    f: f64,
+    float: FloatFormat,
+    hex: bool,
+) -> String {
+    let float = match float {
+        FloatFormat::Hex if !hex => FloatFormat::Shortest,
+        f => f,
+    };
+    let ctx = NumberContext {
+        base: Base::Dec,
+        width: 0,
+        precision: None,
+        float,
+    };
+    let mut text = numbers.format_float(f, &ctx);
+    if text.bytes().all(|b| b.is_ascii_digit() || b == b'-') {
+        text.push_str(".0");
+    }
+    text
+}
+
+// Writes `value` in `style` between the `open` and `close` quotes.  Each
+// character for which `escape` returns an escape sequence is replaced by
+// that sequence in the escape color.
+pub(crate) fn write_quoted<W, F>(
+    w: &mut W,
+    color: &ColorProfile,
+    value: &str,
+    style: Style,
+    (open, close): (&str, &str),
+    escape: F,
+) -> Result<()>
+where
+    W: fmt::Write,
+    F: Fn(char) -> Option<String>,
+{
+    write!(w, "{}", color.punctuation.paint(open))?;
+    let mut start = 0;
+    for (i, ch) in value.char_indices() {
+        let escaped = match escape(ch) {
+            Some(escaped) => escaped,
+            None => continue,
+        };
+        if start < i {
+            write!(w, "{}", style.paint(&value[start..i]))?;
+        }
+        write!(w, "{}", color.escape.paint(escaped))?;
+        start = i + ch.len_utf8();
+    }
+    if start < value.len() {
+        write!(w, "{}", style.paint(&value[start..]))?;
+    }
+    write!(w, "{}", color.punctuation.paint(close))?;
+    Ok(())
+}
+
+// The punctuation of a bytes array written by `write_bytes`.
+pub(crate) struct ByteArray<'a> {
+    pub open: &'a str,
+    pub close: &'a str,
+    // The punctuation written after each byte but the last on a line, and
+    // after the last byte of each line of a multi-line array.  An empty
+    // separator leaves the bytes separated by spaces only.
+    pub separator: &'a str,
+    // Whether the bytes are written as `0x` hexadecimal or as decimal.
+    pub hex: bool,
+}
+
+// Writes `bytes` as an array of integers.  The array is written on one
+// line when `indent` is `None` or the bytes fit on a line, and otherwise
+// `BYTES_PER_LINE` bytes to a line: `indent` gives the column of the
+// closing bracket and the extra indentation of the lines of bytes.
+pub(crate) fn write_bytes<W: fmt::Write>(
+    w: &mut W,
+    color: &ColorProfile,
+    bytes: &[u8],
+    array: &ByteArray,
+    indent: Option<(usize, usize)>,
+) -> Result<()> {
+    let separator = match array.separator {
+        "" => String::new(),
+        s => color.punctuation.paint(s).to_string(),
+    };
+    let items = |chunk: &[u8]| {
+        chunk
+            .iter()
+            .map(|b| {
+                let text = if array.hex {
+                    format!("0x{:02X}", b)
+                } else {
+                    b.to_string()
+                };
+                color.integer.paint(text).to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(&format!("{} ", separator))
+    };
+    let (column, step) = match indent {
+        Some(indent) if bytes.len() > BYTES_PER_LINE => indent,
+        _ => {
+            write!(
+                w,
+                "{}{}{}",
+                color.aggregate.paint(array.open),
+                items(bytes),
+                color.aggregate.paint(array.close)
+            )?;
+            return Ok(());
+        }
+    };
+    writeln!(w, "{}", color.aggregate.paint(array.open))?;
+    for chunk in bytes.chunks(BYTES_PER_LINE) {
+        write_indent(w, column + step)?;
+        writeln!(w, "{}{}", items(chunk), separator)?;
+    }
+    write_indent(w, column)?;
+    write!(w, "{}", color.aggregate.paint(array.close))?;
+    Ok(())
+}
+
+// Returns the escape sequence of `ch` in a Rust or RON string.
+pub(crate) fn rust_escape(ch: char) -> Option<String> {
+    Some(match ch {
+        '"' => "\\\"".to_string(),
+        '\\' => "\\\\".to_string(),
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\0' => "\\0".to_string(),
+        c if c.is_control() => format!("\\u{{{:x}}}", c as u32),
+        _ => return None,
+    })
+}
@@ -0,0 +1,575 @@
+use crate::color::ColorProfile;
+use crate::document::{BytesFormat, Document, StrFormat};
+use crate::emit::{common_options, guarded, CommonOptions, Emit};
+use crate::entry::{comments_of, MapEntry, SeqItem};
+use crate::error::Error;
+use crate::hexdump;
+use crate::integer::Int;
+use crate::layout::TokenClass;
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use crate::path::segments;
+use crate::render::{
+    float_literal, rust_escape, write_bytes, write_indent, write_quoted, ByteArray,
+};
+use crate::rust_types::{is_identifier, static_name, unit_variant, variant};
+use crate::sort::KeySort;
+use crate::transform::mappings_at;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Error>;
+
+// The extensions needed to read back the serialized forms of options and
+// newtype structs, which `serialize` stores as their bare values.
+const EXTENSIONS: &str = "#![enable(implicit_some, unwrap_newtypes)]";
+
+/// A RON (Rusty Object Notation) document and its formatting properties.
+///
+/// A document does not record the Rust types it was serialized from, so
+/// they are recovered from the names which `serialize` stores as
+/// `Document::StaticStr`:
+///
+/// - A mapping whose keys are all field names is emitted as a struct,
+///   `(x: 1, y: 2)`, and other mappings as maps, `{"x": 1}`.
+/// - A mapping with a single entry whose key is a variant name (a name
+///   starting with an uppercase letter), as serialized for an enum variant,
+///   is emitted as that variant: `Circle(r: 1)`, `Point(1, 2)` or
+///   `Some(1)`.
+/// - A variant name in place of a value is emitted as a unit variant.
+///
+/// Struct names are not serialized; they may be given with
+/// `Ron::struct_name`.  Tuples are serialized as sequences, so they are
+/// emitted as sequences, `[1, 2]`.  Null is emitted as `None`.
+pub struct Ron {
+    document: Document,
+    common: CommonOptions,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    struct_names: Vec<(String, String)>,
+    extensions: bool,
+}
+
+impl Ron {
+    common_options!(
+        /// Set the amount of indentation of the contents of structs, maps and
+        /// sequences.
+        indent,
+        /// Set whether the document is emitted on a single line, without
+        /// comments.
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        max_depth,
+        /// Set the order in which the fields of structs and the entries of maps
+        /// are emitted.
+        sort_keys,
+        newline,
+        ascii_comments,
+    );
+    /// Set the rendering of bytes values: an array of integers
+    /// (`BytesFormat::Standard`) or a string in the given format.  Bytes
+    /// annotated with a format are rendered as strings regardless.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.bytes_format = b;
+        self
+    }
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.  RON has no
+    /// hexadecimal floats, so `FloatFormat::Hex` renders as
+    /// `FloatFormat::Shortest`.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+    /// Emits the mappings at `path` as structs named `name`
+    /// (e.g. `Config(port: 80)`).  The path is a JSON Pointer through
+    /// mapping keys and sequence indices, where `*` matches any single key
+    /// or index (e.g. `/servers/*`).  Paths which do not lead to a mapping
+    /// are ignored.
+    pub fn struct_name(mut self, path: &str, name: &str) -> Self {
+        self.struct_names.push((path.to_string(), name.to_string()));
+        self
+    }
+    /// Set whether the document starts with the attribute enabling the
+    /// `implicit_some` and `unwrap_newtypes` extensions (the default is
+    /// `true`), with which RON reads options and newtype structs as their
+    /// bare values, as serialized.
+    pub fn extensions(mut self, b: bool) -> Self {
+        self.extensions = b;
+        self
+    }
+
+    fn emit<W: fmt::Write>(&self, w: &mut W) -> Result<()> {
+        self.common.check_comments(&self.document)?;
+        let mut names = HashMap::new();
+        for (path, name) in &self.struct_names {
+            let path = segments(path).collect::<Vec<_>>();
+            let mut found = Vec::new();
+            mappings_at(&self.document, &path, &mut found);
+            for mapping in found {
+                names.insert(mapping as *const Document as usize, name.as_str());
+            }
+        }
+        let mut emitter = RonEmitter {
+            level: 0,
+            indent: self.common.indent,
+            color: self.common.color.clone(),
+            compact: self.common.compact,
+            sort_keys: self.common.sort_keys.clone(),
+            bytes_format: self.bytes_format,
+            numbers: self.numbers.clone(),
+            float_format: self.float_format,
+            depth: 0,
+            max_depth: self.common.max_depth,
+            names,
+            pending: Vec::new(),
+        };
+        if self.extensions {
+            writeln!(w, "{}", self.common.color.punctuation.paint(EXTENSIONS))?;
+        }
+        emitter.emit_item(w, &self.document, Item::Root)
+    }
+}
+
+impl Emit for Ron {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.common, w, |mut w| self.emit(&mut w))
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.common
+    }
+}
+
+impl Document {
+    pub fn to_ron(self) -> Ron {
+        Ron {
+            document: self,
+            common: CommonOptions::default(),
+            bytes_format: BytesFormat::Standard,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+            struct_names: Vec::new(),
+            extensions: true,
+        }
+    }
+}
+
+// The position of an item within its aggregate.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Root,
+    Element,
+    Field,
+    Entry,
+}
+
+struct RonEmitter<'a> {
+    level: usize,
+    indent: usize,
+    color: ColorProfile,
+    compact: bool,
+    sort_keys: KeySort,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    depth: usize,
+    max_depth: Option<usize>,
+    // The names of the mappings given struct names, by address.
+    names: HashMap<usize, &'a str>,
+    // The comments of values emitted inline (e.g. of enum variants), which
+    // are emitted at the end of the line of the item holding the value.
+    pending: Vec<String>,
+}
+
+impl RonEmitter<'_> {
+    // Emits a struct field, map entry, sequence element or the document
+    // itself along with its comments.  Comments ahead of the value are
+    // emitted on the lines above it and those after the value at the end
+    // of its line.
+    fn emit_item<W: fmt::Write>(&mut self, w: &mut W, node: &Document, item: Item) -> Result<()> {
+        if !node.has_value() {
+            for (c, _) in comments_of(node) {
+                self.emit_comment(w, c)?;
+            }
+            return Ok(());
+        }
+        let (comments, key, value, trailing) = match item {
+            Item::Field | Item::Entry => {
+                let e = MapEntry::new(node)?;
+                (e.comments, Some(e.key), e.value, e.trailing)
+            }
+            Item::Root | Item::Element => {
+                let i = SeqItem::new(node)?;
+                (i.comments, None, i.value, i.trailing)
+            }
+        };
+        for (c, _) in comments {
+            self.emit_comment(w, c)?;
+        }
+        self.emit_indent(w)?;
+        if let Some(key) = key {
+            if item == Item::Field {
+                // Unwrap is ok: fields are named by strings.
+                let name = key.as_str().unwrap();
+                write!(w, "{}", self.color.style(&TokenClass::BareKey).paint(name))?;
+            } else {
+                self.emit_map_key(w, key)?;
+            }
+            write!(w, "{} ", self.color.punctuation.paint(":"))?;
+        }
+        self.emit_value(w, value)?;
+        if item != Item::Root {
+            write!(w, "{}", self.color.punctuation.paint(","))?;
+        }
+        let mut trailing = trailing
+            .into_iter()
+            .map(|(c, _)| c.to_string())
+            .collect::<Vec<_>>();
+        trailing.append(&mut self.pending);
+        match &trailing[..] {
+            [c] if !c.contains('\n') => {
+                writeln!(w, " {}", self.color.comment.paint(format!("// {}", c)))?;
+            }
+            _ => {
+                writeln!(w)?;
+                for c in &trailing {
+                    self.emit_comment(w, c)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_comment<W: fmt::Write>(&mut self, w: &mut W, comment: &str) -> Result<()> {
+        for line in comment.split('\n') {
+            self.emit_indent(w)?;
+            if line.is_empty() {
+                writeln!(w, "{}", self.color.comment.paint("//"))?;
+            } else {
+                writeln!(w, "{}", self.color.comment.paint(format!("// {}", line)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_value<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        match node {
+            Document::Comment(_, _) => Ok(()),
+            Document::String(v, f) => self.emit_string(w, v, *f),
+            Document::StaticStr(v, f) => match unit_variant(node) {
+                Some(name) => Ok(write!(w, "{}", self.color.string.paint(name))?),
+                None => self.emit_string(w, v, *f),
+            },
+            Document::Boolean(b) => Ok(write!(w, "{}", self.color.boolean.paint(b.to_string()))?),
+            Document::Int(i) => self.emit_int(w, i),
+            Document::Float(f) => self.emit_float(w, *f),
+            Document::Mapping(m) => self.emit_mapping(w, node, m),
+            Document::Sequence(s) => {
+                let (open, close) = ("[", "]");
+                self.emit_aggregate(w, open, close, s, Item::Element)
+            }
+            Document::Bytes(b) => self.emit_bytes(w, b),
+            Document::Null => Ok(write!(w, "{}", self.color.null.paint("None"))?),
+            Document::Absent => Err(Error::AbsentValue),
+            Document::Compact(d) => {
+                let compact = std::mem::replace(&mut self.compact, true);
+                let result = self.emit_value(w, d);
+                self.compact = compact;
+                result
+            }
+            Document::ColorClass(c, d) => {
+                let color = self.color.for_class(c);
+                let saved = std::mem::replace(&mut self.color, color);
+                let result = self.emit_value(w, d);
+                self.color = saved;
+                result
+            }
+            Document::Identified(_, d) | Document::Typed(_, d) => self.emit_value(w, d),
+            Document::Fragment(ds) => {
+                self.emit_value(w, node.as_value()?)?;
+                self.defer_comments(ds);
+                Ok(())
+            }
+        }
+    }
+
+    // Holds the comments among `nodes` until the end of the current line.
+    fn defer_comments(&mut self, nodes: &[Document]) {
+        if !self.compact {
+            let comments = nodes.iter().filter_map(Document::comment);
+            self.pending.extend(comments.map(|(c, _)| c.to_string()));
+        }
+    }
+
+    fn emit_mapping<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        node: &Document,
+        mapping: &[Document],
+    ) -> Result<()> {
+        let name = self.names.get(&(node as *const Document as usize)).copied();
+        if let (None, Some((variant, value, nodes))) = (name, variant(mapping)) {
+            return self.emit_variant(w, variant, value, nodes);
+        }
+        let mut entries = mapping.iter().filter_map(|e| e.as_kv().ok()).peekable();
+        let has_entries = entries.peek().is_some();
+        let fields = entries.all(|(k, _)| {
+            k.as_str().map_or(false, is_identifier) && (name.is_some() || static_name(k).is_some())
+        });
+        if fields && (has_entries || name.is_some()) {
+            if let Some(name) = name {
+                write!(w, "{}", self.color.aggregate.paint(name))?;
+            }
+            self.emit_aggregate(w, "(", ")", mapping, Item::Field)
+        } else {
+            self.emit_aggregate(w, "{", "}", mapping, Item::Entry)
+        }
+    }
+
+    // Emits an enum variant: its name followed by its fields or elements,
+    // or by its value in parentheses.
+    fn emit_variant<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        name: &str,
+        value: &Document,
+        nodes: &[Document],
+    ) -> Result<()> {
+        write!(w, "{}", self.color.aggregate.paint(name))?;
+        match value.as_value()? {
+            Document::Mapping(m) if !m.is_empty() && variant(m).is_none() => {
+                self.emit_aggregate(w, "(", ")", m, Item::Field)?
+            }
+            Document::Sequence(s) => self.emit_aggregate(w, "(", ")", s, Item::Element)?,
+            _ => {
+                write!(w, "{}", self.color.aggregate.paint("("))?;
+                self.emit_value(w, value)?;
+                write!(w, "{}", self.color.aggregate.paint(")"))?;
+            }
+        }
+        self.defer_comments(nodes);
+        Ok(())
+    }
+
+    fn emit_map_key<W: fmt::Write>(&mut self, w: &mut W, key: &Document) -> Result<()> {
+        match key.as_value()? {
+            Document::String(s, _) => self.emit_quoted(w, s, self.color.key),
+            Document::StaticStr(s, _) if unit_variant(key).is_none() => {
+                self.emit_quoted(w, s, self.color.key)
+            }
+            _ => self.emit_value(w, key),
+        }
+    }
+
+    // Emits the items of a struct, map or sequence between `open` and
+    // `close`, each on its own line unless compact.
+    fn emit_aggregate<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        open: &str,
+        close: &str,
+        items: &[Document],
+        item: Item,
+    ) -> Result<()> {
+        match self.max_depth {
+            Some(n) if self.depth >= n => return Err(Error::DepthLimit(n)),
+            _ => self.depth += 1,
+        }
+        let mut items = match item {
+            Item::Field | Item::Entry => self.sort_keys.order(items),
+            _ => items.iter().collect(),
+        };
+        if self.compact {
+            items.retain(|i| i.has_value());
+        }
+        write!(w, "{}", self.color.aggregate.paint(open))?;
+        if self.compact {
+            for (i, node) in items.into_iter().enumerate() {
+                if i > 0 {
+                    write!(w, "{} ", self.color.punctuation.paint(","))?;
+                }
+                match item {
+                    Item::Field => {
+                        let (key, value) = node.as_kv()?;
+                        // Unwrap is ok: fields are named by strings.
+                        let name = key.as_str().unwrap();
+                        write!(w, "{}", self.color.style(&TokenClass::BareKey).paint(name))?;
+                        write!(w, "{} ", self.color.punctuation.paint(":"))?;
+                        self.emit_value(w, value)?;
+                    }
+                    Item::Entry => {
+                        let (key, value) = node.as_kv()?;
+                        self.emit_map_key(w, key)?;
+                        write!(w, "{} ", self.color.punctuation.paint(":"))?;
+                        self.emit_value(w, value)?;
+                    }
+                    _ => self.emit_value(w, node)?,
+                }
+            }
+        } else if !items.is_empty() {
+            // Comments deferred to the end of the line wait until after the
+            // aggregate.
+            let pending = std::mem::take(&mut self.pending);
+            writeln!(w)?;
+            self.level += 1;
+            for node in items {
+                self.emit_item(w, node, item)?;
+            }
+            self.level -= 1;
+            self.emit_indent(w)?;
+            self.pending = pending;
+        }
+        write!(w, "{}", self.color.aggregate.paint(close))?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn emit_bytes<W: fmt::Write>(&mut self, w: &mut W, bytes: &[u8]) -> Result<()> {
+        if let Some(string) = hexdump::to_string(bytes, self.bytes_format) {
+            return self.emit_string(w, &string, self.bytes_format.str_format());
+        }
+        let array = ByteArray {
+            open: "[",
+            close: "]",
+            separator: ",",
+            hex: true,
+        };
+        let indent = (!self.compact).then(|| (self.level * self.indent, self.indent));
+        write_bytes(w, &self.color, bytes, &array, indent)
+    }
+
+    // Emits a string.  Strings in the multiline formats are emitted as raw
+    // strings, in which line breaks are written as they are.
+    fn emit_string<W: fmt::Write>(&mut self, w: &mut W, value: &str, f: StrFormat) -> Result<()> {
+        if self.compact || !f.is_multiline() || !value.contains('\n') {
+            return self.emit_quoted(w, value, self.color.string);
+        }
+        // The raw string is closed by a quote followed by more hashes than
+        // follow any quote in the string.
+        let hashes = value
+            .split('"')
+            .skip(1)
+            .map(|s| s.len() - s.trim_start_matches('#').len() + 1)
+            .max()
+            .unwrap_or(0);
+        let hashes = "#".repeat(hashes);
+        let open = format!("r{}\"", hashes);
+        let close = format!("\"{}", hashes);
+        write!(
+            w,
+            "{}{}{}",
+            self.color.punctuation.paint(open),
+            self.color.string.paint(value),
+            self.color.punctuation.paint(close)
+        )?;
+        Ok(())
+    }
+
+    fn emit_quoted<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        value: &str,
+        color: ansi_term::Style,
+    ) -> Result<()> {
+        write_quoted(w, &self.color, value, color, ("\"", "\""), rust_escape)
+    }
+
+    fn emit_int<W: fmt::Write>(&mut self, w: &mut W, i: &Int) -> Result<()> {
+        let ctx = NumberContext {
+            base: i.base(),
+            width: i.width(),
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        // The dialect has no integer type suffixes.
+        let i = &i.unsuffixed();
+        i.check_format(ctx.base)?;
+        write!(
+            w,
+            "{}",
+            self.color.integer.paint(self.numbers.format_int(i, &ctx))
+        )?;
+        Ok(())
+    }
+
+    fn emit_float<W: fmt::Write>(&mut self, w: &mut W, f: f64) -> Result<()> {
+        let text = if f.is_nan() {
+            "NaN".to_string()
+        } else if f.is_infinite() {
+            if f > 0.0 { "inf" } else { "-inf" }.to_string()
+        } else {
+            float_literal(&*self.numbers, f, self.float_format, false)
+        };
+        write!(w, "{}", self.color.float.paint(text))?;
+        Ok(())
+    }
+
+    fn emit_indent<W: fmt::Write>(&mut self, w: &mut W) -> Result<()> {
+        if self.compact {
+            return Ok(());
+        }
+        write_indent(w, self.level * self.indent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_parsed() -> Result<()> {
+        // Parsed keys are not field names, so mappings are emitted as maps
+        // unless they are given struct names.
+        let doc = Document::parse(
+            r#"{
+                servers: [
+                    {host: "a", port: 0x50},
+                    // The backup.
+                    {host: "b\tc", port: 8080},
+                ],
+                ratio: 2.0,
+                limit: NaN,
+                "odd key": null,
+            }"#,
+        )?;
+        assert_eq!(
+            doc.to_ron()
+                .struct_name("/servers/*", "Server")
+                .extensions(false)
+                .to_text()?,
+            r#"{
+  "servers": [
+    Server(
+      host: "a",
+      port: 0x50,
+    ),
+    // The backup.
+    Server(
+      host: "b\tc",
+      port: 8080,
+    ),
+  ],
+  "ratio": 2.0,
+  "limit": NaN,
+  "odd key": None,
+}
+"#
+        );
+
+        let doc = Document::String("say \"#hi\"\nbye".into(), StrFormat::Multiline);
+        assert_eq!(
+            doc.to_ron().extensions(false).to_text()?,
+            "r##\"say \"#hi\"\nbye\"##\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+// Compression of arithmetic runs in integer sequences.
+use crate::document::{CommentFormat, Document};
+use crate::integer::{Base, Int};
+use crate::path::{children_mut, segments};
+use crate::transform::unwrapped;
+
+// The fewest elements which form a run.
+const MIN_RUN: usize = 4;
+
+// The most elements in a single run.  Longer runs are split, and generator
+// comments describing more elements are not expanded, so that a hostile
+// comment cannot make the parser allocate without bound.
+const MAX_RUN: usize = 1 << 16;
+
+// The marker which begins every generator comment.
+const MARKER: &str = "run ";
+
+/// How `Document::compress_runs` rewrites sequences containing arithmetic
+/// runs (e.g. `0, 16, 32, 48`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunStyle {
+    /// Emit the sequence in compact form, on as few lines as possible.
+    Compact,
+    /// Replace each run with a comment describing it as a generator
+    /// expression (e.g. `// run 0..4096 step 16`).  The end of the range is
+    /// exclusive and a step of 1 is omitted.  Runs of more than 65536
+    /// elements are split.  A run is restored from its comment by
+    /// `Document::expand_runs`; dialects which cannot express comments lose
+    /// the elements of the run.
+    Generator,
+}
+
+impl Document {
+    /// Rewrites the integer sequences at `path` which contain arithmetic
+    /// runs of at least four elements according to `style`.
+    ///
+    /// The path is a JSON Pointer through mapping keys and sequence
+    /// indices, where `*` matches any single key or index (e.g.
+    /// `/tables/*/samples`).  Only bare integer elements, without comments
+    /// of their own, form runs.  Paths which do not exist in the document
+    /// are ignored.
+    pub fn compress_runs(mut self, path: &str, style: RunStyle) -> Document {
+        let path = segments(path).collect::<Vec<_>>();
+        compress_at(&mut self, &path, style);
+        self
+    }
+
+    /// Expands the generator comments written by `RunStyle::Generator`
+    /// back into the elements of their runs.  This is the inverse of
+    /// `compress_runs`.
+    ///
+    /// Only comments beginning with the `run` marker are expanded.  Comments
+    /// describing more than 65536 elements, or whose bounds do not fit in
+    /// an `i128`, are kept as comments.
+    pub fn expand_runs(mut self) -> Document {
+        expand(&mut self);
+        self
+    }
+}
+
+fn compress_at(node: &mut Document, path: &[String], style: RunStyle) {
+    let value = match unwrapped(node) {
+        Some(v) => v,
+        None => return,
+    };
+    let mut wrap = false;
+    match (path, &mut *value) {
+        ([], Document::Sequence(s)) => {
+            let runs = runs(s);
+            if runs.is_empty() {
+                return;
+            }
+            match style {
+                RunStyle::Compact => wrap = true,
+                RunStyle::Generator => {
+                    // Replace the runs from last to first so that the
+                    // indices of earlier runs remain valid.
+                    for (start, end) in runs.into_iter().rev() {
+                        let comment =
+                            Document::Comment(generator(&s[start..end]), CommentFormat::Standard);
+                        s.drain(start..end);
+                        match s.get_mut(start) {
+                            Some(Document::Fragment(nodes)) => nodes.insert(0, comment),
+                            Some(next) => {
+                                let v = std::mem::replace(next, Document::Null);
+                                *next = Document::Fragment(vec![comment, v]);
+                            }
+                            None => s.push(Document::Fragment(vec![comment])),
+                        }
+                    }
+                }
+            }
+        }
+        ([], _) => {}
+        ([seg, rest @ ..], value) => {
+            for n in children_mut(value, seg) {
+                compress_at(n, rest, style);
+            }
+        }
+    }
+    if wrap {
+        let v = std::mem::replace(value, Document::Null);
+        *value = Document::Compact(Box::new(v));
+    }
+}
+
+// Returns the value of a bare integer element.
+fn int_value(node: &Document) -> Option<(i128, Base)> {
+    match node {
+        Document::Int(i) => Some((i.as_i128()?, i.base())),
+        _ => None,
+    }
+}
+
+// Returns the index ranges of the arithmetic runs in `seq`.
+fn runs(seq: &[Document]) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    while start < seq.len() {
+        let mut end = start + 1;
+        if let (Some((a, base)), Some((b, _))) =
+            (int_value(&seq[start]), seq.get(end).and_then(int_value))
+        {
+            let step = b.checked_sub(a).filter(|s| *s != 0);
+            let mut prev = a;
+            while let (Some(step), Some((v, vbase))) = (step, seq.get(end).and_then(int_value)) {
+                if end - start == MAX_RUN
+                    || vbase != base
+                    || prev.checked_add(step) != Some(v)
+                    || v.checked_add(step).is_none()
+                {
+                    break;
+                }
+                prev = v;
+                end += 1;
+            }
+        }
+        if end - start >= MIN_RUN {
+            runs.push((start, end));
+            start = end;
+        } else {
+            start += 1;
+        }
+    }
+    runs
+}
+
+// Returns the generator expression describing a run.
+fn generator(run: &[Document]) -> String {
+    // Unwraps are ok: a run has at least two integer elements.
+    let (first, base) = int_value(&run[0]).unwrap();
+    let (second, _) = int_value(&run[1]).unwrap();
+    let (last, _) = int_value(&run[run.len() - 1]).unwrap();
+    let step = second - first;
+    // Non-decimal bases are only used when every number is non-negative.
+    let base = if first.min(last + step).min(step) < 0 {
+        Base::Dec
+    } else {
+        base
+    };
+    let fmt = |v: i128| Int::new(v, base).to_string();
+    if step == 1 {
+        format!("{}{}..{}", MARKER, fmt(first), fmt(last + step))
+    } else {
+        format!(
+            "{}{}..{} step {}",
+            MARKER,
+            fmt(first),
+            fmt(last + step),
+            fmt(step)
+        )
+    }
+}
+
+// Parses a generator expression into the elements of its run.
+fn parse_generator(text: &str) -> Option<Vec<Document>> {
+    let text = text.trim().strip_prefix(MARKER)?;
+    let (range, step) = match text.split_once(" step ") {
+        Some((range, step)) => (range, Int::from_str_radix(step.trim(), 0).ok()?),
+        None => (text, Int::from(1u8)),
+    };
+    let (start, end) = range.split_once("..")?;
+    let start = Int::from_str_radix(start.trim(), 0).ok()?;
+    let end = Int::from_str_radix(end.trim(), 0).ok()?.as_i128()?;
+    let step = step.as_i128().filter(|s| *s != 0)?;
+    let base = start.base();
+    let start = start.as_i128()?;
+    // The number of elements is the span divided by the step, rounded up.
+    let span = end.checked_sub(start)?;
+    let count = span.checked_div(step)? + i128::from(span.checked_rem(step)? != 0);
+    if count < 0 || count > MAX_RUN as i128 {
+        return None;
+    }
+    let mut elems = Vec::with_capacity(count as usize);
+    let mut v = start;
+    for i in 0..count {
+        if i > 0 {
+            v = v.checked_add(step)?;
+        }
+        elems.push(Document::Int(Int::new(v, base)));
+    }
+    Some(elems)
+}
+
+fn expand(node: &mut Document) {
+    match node {
+        Document::Sequence(s) => {
+            let mut elems = Vec::with_capacity(s.len());
+            for mut elem in std::mem::take(s) {
+                expand(&mut elem);
+                match elem {
+                    Document::Fragment(nodes) => {
+                        let mut rest = Vec::new();
+                        for n in nodes {
+                            let run = match &n {
+                                Document::Comment(c, _) => parse_generator(c),
+                                _ => None,
+                            };
+                            match run {
+                                Some(run) => elems.extend(run),
+                                None => rest.push(n),
+                            }
+                        }
+                        if !rest.is_empty() {
+                            elems.push(if rest.len() == 1 && rest[0].has_value() {
+                                // Unwrap is ok: `rest` holds one node.
+                                rest.pop().unwrap()
+                            } else {
+                                Document::Fragment(rest)
+                            });
+                        }
+                    }
+                    e => elems.push(e),
+                }
+            }
+            *s = elems;
+        }
+        Document::Mapping(nodes) | Document::Fragment(nodes) => nodes.iter_mut().for_each(expand),
+        Document::Compact(n)
+        | Document::ColorClass(_, n)
+        | Document::Identified(_, n)
+        | Document::Typed(_, n) => expand(n),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use crate::relax::Relax;
+    use anyhow::Result;
+
+    #[test]
+    fn test_compress_runs() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                table: [7, 0, 16, 32, 48, 64, 1, 2, 3],
+                hex: [0x10, 0x20, 0x30, 0x40],
+                down: [3, 1, -1, -3, -5],
+                short: [1, 2, 3],
+            }"#,
+        )?;
+        let compressed = doc
+            .clone()
+            .compress_runs("*", RunStyle::Generator)
+            .to_json5()
+            .literals(&[Base::Hex])
+            .to_text()?;
+        assert_eq!(
+            compressed,
+            r#"{
+  table: [
+    7,
+    // run 0..80 step 16
+    1,
+    2,
+    3
+  ],
+  hex: [
+    // run 0x10..0x50 step 0x10
+  ],
+  down: [
+    // run 3..-7 step -2
+  ],
+  short: [
+    1,
+    2,
+    3
+  ]
+}"#
+        );
+        let mut relax = Relax::default();
+        relax.expand_runs = true;
+        let expanded = relax.from_str(&compressed)?;
+        assert_eq!(
+            expanded.to_json().compact(true).to_text()?,
+            doc.clone().to_json().compact(true).to_text()?
+        );
+
+        // Comments without the marker, or describing too many elements,
+        // are kept.
+        for comment in ["0..4", "run 0..0x7fffffffffffffff", "run 0..1 step 0"] {
+            let expanded = relax.from_str(&format!("[// {}\n1]", comment))?;
+            assert_eq!(
+                expanded.to_json5().to_text()?,
+                format!("[\n  // {}\n  1\n]", comment)
+            );
+        }
+        let expanded = relax.from_str("[// run 0..4\n4,\n// run 8..4 step -2\n1]")?;
+        assert_eq!(
+            expanded.to_json().compact(true).to_text()?,
+            "[0, 1, 2, 3, 4, 8, 6, 1]"
+        );
+
+        let compact = doc.compress_runs("/hex", RunStyle::Compact);
+        assert!(compact
+            .to_json5()
+            .to_text()?
+            .contains("hex: [0x10, 0x20, 0x30, 0x40],"));
+        Ok(())
+    }
+}
@@ -0,0 +1,817 @@
+use crate::color::ColorProfile;
+use crate::document::{BytesFormat, CommentFormat, Document, SerdeType};
+use crate::emit::{common_options, guarded, CommonOptions, Emit};
+use crate::entry::{comments_of, MapEntry, SeqItem};
+use crate::error::Error;
+use crate::integer::{Base, Int};
+use crate::layout::TokenClass;
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use crate::path::segments;
+use crate::render::{
+    float_literal, rust_escape, write_bytes, write_indent, write_quoted, ByteArray,
+};
+use crate::rust_types::{is_identifier, static_name, unit_variant, variant};
+use crate::sort::KeySort;
+use crate::transform::mappings_at;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Error>;
+
+// The keywords which may only be used as identifiers in their raw form.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "fn",
+    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "type", "unsafe", "use", "where", "while", "abstract",
+    "become", "box", "do", "final", "gen", "macro", "override", "priv", "try", "typeof", "unsized",
+    "virtual", "yield",
+];
+
+/// The item declared by `RustSource`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RustItem {
+    /// A bare expression, without a declaration.
+    Expression,
+    /// `pub const NAME: Type = ...;`
+    Const,
+    /// `pub static NAME: Type = ...;`
+    Static,
+    /// `pub static ref NAME: Type = ...;` within `lazy_static!`.
+    LazyStatic,
+}
+
+/// A document rendered as Rust source: an expression, or a `const`, `static`
+/// or `lazy_static!` item initialized by it.
+///
+/// A document does not record the Rust types it was serialized from, so
+/// they are recovered as by `Ron`:
+///
+/// - A mapping given a struct name with `RustSource::struct_name` is
+///   emitted as a struct literal, `Config { port: 80 }`.  Other mappings
+///   are emitted as arrays of key/value tuples, `[("port", 80)]`.
+/// - A mapping with a single entry whose key is a variant name, as
+///   serialized for an enum variant, is emitted as that variant:
+///   `Circle { r: 1 }`, `Point(1, 2)` or `Some(1)`.  Variants are written
+///   without their enum's name, so they must be in scope (e.g. with
+///   `use Shape::*;`).
+/// - A variant name in place of a value is emitted as a unit variant.
+///
+/// Sequences and tuples are emitted as arrays, `[1, 2]`, and null as
+/// `None`.  The elements of an array must share a type, so arrays of mixed
+/// types (e.g. `[1, "a"]`) fail with `Error::Unrepresentable`.  The values of options are emitted as `Some(1)` where the
+/// serializer recorded them (see `SerdeType::Some`); parsed documents
+/// record none, so their values are emitted bare.  Integers keep their annotated base
+/// (e.g. `0x50`).  Comments ahead of the item are emitted as `///` doc
+/// comments and the others as `//` comments.
+pub struct RustSource {
+    document: Document,
+    common: CommonOptions,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    struct_names: Vec<(String, String)>,
+    item: RustItem,
+    name: String,
+    type_name: String,
+}
+
+impl RustSource {
+    common_options!(
+        /// Set the amount of indentation of the contents of structs and
+        /// arrays.
+        indent,
+        /// Set whether the value is emitted on a single line, without
+        /// comments.
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        max_depth,
+        /// Set the order in which the fields of structs and the entries of
+        /// mappings are emitted.
+        sort_keys,
+        newline,
+        ascii_comments,
+    );
+    /// Set the rendering of bytes values: an array of integers
+    /// (`BytesFormat::Standard`) or a string in the given format.  Bytes
+    /// annotated with a format are rendered as strings regardless.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.bytes_format = b;
+        self
+    }
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.  Rust has no
+    /// hexadecimal float literals, so `FloatFormat::Hex` renders as
+    /// `FloatFormat::Shortest`.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+    /// Emits the mappings at `path` as struct literals of the struct
+    /// `name`.  The path is a JSON Pointer through mapping keys and
+    /// sequence indices, where `*` matches any single key or index (e.g.
+    /// `/servers/*`).  Paths which do not lead to a mapping are ignored.
+    pub fn struct_name(mut self, path: &str, name: &str) -> Self {
+        self.struct_names.push((path.to_string(), name.to_string()));
+        self
+    }
+    /// Declares the value as `item` named `name` of type `type_name`
+    /// (e.g. `declare(RustItem::Const, "CONFIG", "Config")`).
+    pub fn declare(mut self, item: RustItem, name: &str, type_name: &str) -> Self {
+        self.item = item;
+        self.name = name.to_string();
+        self.type_name = type_name.to_string();
+        self
+    }
+
+    fn emit<W: fmt::Write>(&self, w: &mut W) -> Result<()> {
+        self.common.check_comments(&self.document)?;
+        let mut names = HashMap::new();
+        for (path, name) in &self.struct_names {
+            let path = segments(path).collect::<Vec<_>>();
+            let mut found = Vec::new();
+            mappings_at(&self.document, &path, &mut found);
+            for mapping in found {
+                names.insert(mapping as *const Document as usize, name.as_str());
+            }
+        }
+        let mut emitter = RustEmitter {
+            level: 0,
+            indent: self.common.indent,
+            color: self.common.color.clone(),
+            compact: self.common.compact,
+            sort_keys: self.common.sort_keys.clone(),
+            bytes_format: self.bytes_format,
+            numbers: self.numbers.clone(),
+            float_format: self.float_format,
+            depth: 0,
+            max_depth: self.common.max_depth,
+            names,
+            pending: Vec::new(),
+        };
+        let item = SeqItem::new(&self.document)?;
+        if self.item == RustItem::Expression {
+            for (c, _) in item.comments {
+                emitter.emit_comment(w, c, "//")?;
+            }
+            emitter.emit_value(w, item.value)?;
+            return emitter.end_line(w, &item.trailing);
+        }
+        let keyword = self.common.color.aggregate;
+        if self.item == RustItem::LazyStatic {
+            writeln!(w, "{} {{", keyword.paint("lazy_static!"))?;
+            emitter.level += 1;
+        }
+        for (c, _) in item.comments {
+            emitter.emit_comment(w, c, "///")?;
+        }
+        let declaration = match self.item {
+            RustItem::Const => "pub const",
+            RustItem::Static => "pub static",
+            _ => "pub static ref",
+        };
+        emitter.emit_indent(w)?;
+        write!(
+            w,
+            "{} {}{} {} ",
+            keyword.paint(declaration),
+            self.common.color.key.paint(&self.name),
+            self.common.color.punctuation.paint(":"),
+            self.common.color.aggregate.paint(&self.type_name),
+        )?;
+        write!(w, "{} ", self.common.color.punctuation.paint("="))?;
+        emitter.emit_value(w, item.value)?;
+        write!(w, "{}", self.common.color.punctuation.paint(";"))?;
+        emitter.end_line(w, &item.trailing)?;
+        if self.item == RustItem::LazyStatic {
+            writeln!(w, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Emit for RustSource {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.common, w, |mut w| self.emit(&mut w))
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.common
+    }
+}
+
+impl Document {
+    pub fn to_rust(self) -> RustSource {
+        RustSource {
+            document: self,
+            common: CommonOptions::default(),
+            bytes_format: BytesFormat::Standard,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+            struct_names: Vec::new(),
+            item: RustItem::Expression,
+            name: String::new(),
+            type_name: String::new(),
+        }
+    }
+}
+
+// Returns whether `name` may be written as a field name.  `self`, `Self`,
+// `super` and `crate` have no raw form, so they cannot be.
+fn is_field_name(name: &str) -> bool {
+    is_identifier(name) && !matches!(name, "self" | "Self" | "super" | "crate" | "_")
+}
+
+// Returns `name` as an identifier, in its raw form if it is a keyword.
+fn identifier(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+// The position of an item within its aggregate.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Element,
+    Field,
+    Entry,
+}
+
+// The shape of the Rust type of an emitted value.  The elements of an
+// array must share a shape, except that `Unknown` (e.g. the elements of an
+// empty array) takes the shape of its neighbours.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TypeShape {
+    Unknown,
+    Bool,
+    Int,
+    Float,
+    Str,
+    // A struct literal or enum variant, whose type is not recorded.
+    Named,
+    Option(Box<TypeShape>),
+    Array(Box<TypeShape>),
+    Tuple(Box<TypeShape>, Box<TypeShape>),
+}
+
+impl TypeShape {
+    // Returns the shape of values of both shapes.
+    fn join(self, other: TypeShape) -> Result<TypeShape> {
+        let join = |a: Box<TypeShape>, b: Box<TypeShape>| a.join(*b).map(Box::new);
+        match (self, other) {
+            (TypeShape::Unknown, s) | (s, TypeShape::Unknown) => Ok(s),
+            (TypeShape::Option(a), TypeShape::Option(b)) => Ok(TypeShape::Option(join(a, b)?)),
+            (TypeShape::Array(a), TypeShape::Array(b)) => Ok(TypeShape::Array(join(a, b)?)),
+            (TypeShape::Tuple(a, b), TypeShape::Tuple(c, d)) => {
+                Ok(TypeShape::Tuple(join(a, c)?, join(b, d)?))
+            }
+            (a, b) if a == b => Ok(a),
+            _ => Err(Error::Unrepresentable(
+                "an array of mixed types",
+                "Rust source",
+            )),
+        }
+    }
+}
+
+struct RustEmitter<'a> {
+    level: usize,
+    indent: usize,
+    color: ColorProfile,
+    compact: bool,
+    sort_keys: KeySort,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    depth: usize,
+    max_depth: Option<usize>,
+    // The names of the mappings given struct names, by address.
+    names: HashMap<usize, &'a str>,
+    // The comments of values emitted inline (e.g. of enum variants), which
+    // are emitted at the end of the line of the item holding the value.
+    pending: Vec<String>,
+}
+
+impl RustEmitter<'_> {
+    // Emits a struct field, key/value tuple or array element along with
+    // its comments.  Comments ahead of the value are emitted on the lines
+    // above it and those after the value at the end of its line.
+    fn emit_item<W: fmt::Write>(&mut self, w: &mut W, node: &Document, item: Item) -> Result<()> {
+        if !node.has_value() {
+            for (c, _) in comments_of(node) {
+                self.emit_comment(w, c, "//")?;
+            }
+            return Ok(());
+        }
+        let (comments, key, value, trailing) = match item {
+            Item::Field | Item::Entry => {
+                let e = MapEntry::new(node)?;
+                (e.comments, Some(e.key), e.value, e.trailing)
+            }
+            Item::Element => {
+                let i = SeqItem::new(node)?;
+                (i.comments, None, i.value, i.trailing)
+            }
+        };
+        for (c, _) in comments {
+            self.emit_comment(w, c, "//")?;
+        }
+        self.emit_indent(w)?;
+        self.emit_entry(w, item, key, value)?;
+        write!(w, "{}", self.color.punctuation.paint(","))?;
+        self.end_line(w, &trailing)
+    }
+
+    // Emits a struct field as `name: value`, a key/value tuple as
+    // `(key, value)` or an array element as its value.
+    fn emit_entry<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        item: Item,
+        key: Option<&Document>,
+        value: &Document,
+    ) -> Result<()> {
+        match (item, key) {
+            (Item::Field, Some(key)) => {
+                // Unwrap is ok: fields are named by strings.
+                let name = identifier(key.as_str().unwrap());
+                write!(w, "{}", self.color.style(&TokenClass::BareKey).paint(name))?;
+                write!(w, "{} ", self.color.punctuation.paint(":"))?;
+                self.emit_value(w, value)
+            }
+            (Item::Entry, Some(key)) => {
+                write!(w, "{}", self.color.punctuation.paint("("))?;
+                self.emit_value(w, key)?;
+                write!(w, "{} ", self.color.punctuation.paint(","))?;
+                self.emit_value(w, value)?;
+                write!(w, "{}", self.color.punctuation.paint(")"))?;
+                Ok(())
+            }
+            _ => self.emit_value(w, value),
+        }
+    }
+
+    // Ends the line of a value with the comments after it and those
+    // deferred from values emitted inline.
+    fn end_line<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        trailing: &[(&str, CommentFormat)],
+    ) -> Result<()> {
+        let mut trailing = trailing
+            .iter()
+            .map(|(c, _)| c.to_string())
+            .collect::<Vec<_>>();
+        trailing.append(&mut self.pending);
+        if self.compact {
+            trailing.clear();
+        }
+        match &trailing[..] {
+            [c] if !c.contains('\n') => {
+                writeln!(w, " {}", self.color.comment.paint(format!("// {}", c)))?;
+            }
+            _ => {
+                writeln!(w)?;
+                for c in &trailing {
+                    self.emit_comment(w, c, "//")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_comment<W: fmt::Write>(&mut self, w: &mut W, comment: &str, mark: &str) -> Result<()> {
+        if self.compact {
+            return Ok(());
+        }
+        for line in comment.split('\n') {
+            self.emit_indent(w)?;
+            if line.is_empty() {
+                writeln!(w, "{}", self.color.comment.paint(mark))?;
+            } else {
+                writeln!(
+                    w,
+                    "{}",
+                    self.color.comment.paint(format!("{} {}", mark, line))
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_value<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        match node {
+            Document::Comment(_, _) => Ok(()),
+            Document::String(v, _) => self.emit_string(w, v),
+            Document::StaticStr(v, _) => match unit_variant(node) {
+                Some(name) => Ok(write!(w, "{}", self.color.string.paint(name))?),
+                None => self.emit_string(w, v),
+            },
+            Document::Boolean(b) => Ok(write!(w, "{}", self.color.boolean.paint(b.to_string()))?),
+            Document::Int(i) => self.emit_int(w, i),
+            Document::Float(f) => self.emit_float(w, *f),
+            Document::Mapping(m) => self.emit_mapping(w, node, m),
+            Document::Sequence(s) => self.emit_aggregate(w, "[", "]", s, Item::Element),
+            Document::Bytes(b) => self.emit_bytes(w, b),
+            Document::Null => Ok(write!(w, "{}", self.color.null.paint("None"))?),
+            Document::Absent => Err(Error::AbsentValue),
+            Document::Compact(d) => {
+                let compact = std::mem::replace(&mut self.compact, true);
+                let result = self.emit_value(w, d);
+                self.compact = compact;
+                result
+            }
+            Document::ColorClass(c, d) => {
+                let color = self.color.for_class(c);
+                let saved = std::mem::replace(&mut self.color, color);
+                let result = self.emit_value(w, d);
+                self.color = saved;
+                result
+            }
+            Document::Typed(SerdeType::Some, d) => {
+                write!(w, "{}", self.color.aggregate.paint("Some("))?;
+                self.emit_value(w, d)?;
+                write!(w, "{}", self.color.aggregate.paint(")"))?;
+                Ok(())
+            }
+            Document::Identified(_, d) | Document::Typed(_, d) => self.emit_value(w, d),
+            Document::Fragment(ds) => {
+                self.emit_value(w, node.as_value()?)?;
+                self.defer_comments(ds);
+                Ok(())
+            }
+        }
+    }
+
+    // Returns whether `mapping` is emitted as a struct literal or an enum
+    // variant rather than an array of key/value tuples.
+    fn is_named(&self, node: &Document, mapping: &[Document]) -> bool {
+        match self.names.get(&(node as *const Document as usize)) {
+            Some(_) => Self::is_struct(mapping, true),
+            None => variant(mapping).is_some(),
+        }
+    }
+
+    // Returns the shape of the type of `node`, failing if it holds an array
+    // whose elements differ in shape.
+    fn shape(&self, node: &Document) -> Result<TypeShape> {
+        Ok(match node {
+            Document::Comment(_, _) | Document::Absent => TypeShape::Unknown,
+            Document::StaticStr(_, _) if unit_variant(node).is_some() => TypeShape::Named,
+            Document::String(_, _) | Document::StaticStr(_, _) => TypeShape::Str,
+            Document::Boolean(_) => TypeShape::Bool,
+            Document::Int(_) => TypeShape::Int,
+            Document::Float(_) => TypeShape::Float,
+            Document::Mapping(m) if self.is_named(node, m) => TypeShape::Named,
+            Document::Mapping(m) => TypeShape::Array(Box::new(self.item_shape(m, Item::Entry)?)),
+            Document::Sequence(s) => TypeShape::Array(Box::new(self.item_shape(s, Item::Element)?)),
+            Document::Bytes(b) => match crate::hexdump::to_string(b, self.bytes_format) {
+                Some(_) => TypeShape::Str,
+                None => TypeShape::Array(Box::new(TypeShape::Int)),
+            },
+            Document::Null => TypeShape::Option(Box::new(TypeShape::Unknown)),
+            Document::Typed(SerdeType::Some, d) => TypeShape::Option(Box::new(self.shape(d)?)),
+            Document::Compact(d)
+            | Document::ColorClass(_, d)
+            | Document::Identified(_, d)
+            | Document::Typed(_, d) => self.shape(d)?,
+            Document::Fragment(_) => self.shape(node.as_value()?)?,
+        })
+    }
+
+    // Returns the shape shared by the elements or key/value tuples of an
+    // array.
+    fn item_shape(&self, items: &[Document], item: Item) -> Result<TypeShape> {
+        let mut shape = TypeShape::Unknown;
+        for node in items.iter().filter(|n| n.has_value()) {
+            let s = match item {
+                Item::Element => self.shape(node.as_value()?)?,
+                _ => {
+                    let (key, value) = node.as_kv()?;
+                    TypeShape::Tuple(Box::new(self.shape(key)?), Box::new(self.shape(value)?))
+                }
+            };
+            shape = shape.join(s)?;
+        }
+        Ok(shape)
+    }
+
+    // Holds the comments among `nodes` until the end of the current line.
+    fn defer_comments(&mut self, nodes: &[Document]) {
+        if !self.compact {
+            let comments = nodes.iter().filter_map(Document::comment);
+            self.pending.extend(comments.map(|(c, _)| c.to_string()));
+        }
+    }
+
+    // Returns whether the entries of a mapping may be written as the fields
+    // of a struct literal.
+    fn is_struct(mapping: &[Document], named: bool) -> bool {
+        let mut entries = mapping.iter().filter_map(|e| e.as_kv().ok()).peekable();
+        let has_entries = entries.peek().is_some();
+        let fields = entries.all(|(k, _)| {
+            k.as_str().map_or(false, is_field_name) && (named || static_name(k).is_some())
+        });
+        fields && (has_entries || named)
+    }
+
+    fn emit_mapping<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        node: &Document,
+        mapping: &[Document],
+    ) -> Result<()> {
+        let name = self.names.get(&(node as *const Document as usize)).copied();
+        if let (None, Some((variant, value, nodes))) = (name, variant(mapping)) {
+            return self.emit_variant(w, variant, value, nodes);
+        }
+        match name {
+            Some(name) if Self::is_struct(mapping, true) => {
+                write!(w, "{} ", self.color.aggregate.paint(name))?;
+                self.emit_aggregate(w, "{", "}", mapping, Item::Field)
+            }
+            _ => self.emit_aggregate(w, "[", "]", mapping, Item::Entry),
+        }
+    }
+
+    // Emits an enum variant: its name followed by its fields or elements,
+    // or by its value in parentheses.
+    fn emit_variant<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        name: &str,
+        value: &Document,
+        nodes: &[Document],
+    ) -> Result<()> {
+        write!(w, "{}", self.color.aggregate.paint(name))?;
+        match value.as_value()? {
+            Document::Mapping(m) if Self::is_struct(m, false) && variant(m).is_none() => {
+                write!(w, " ")?;
+                self.emit_aggregate(w, "{", "}", m, Item::Field)?
+            }
+            Document::Sequence(s) => self.emit_aggregate(w, "(", ")", s, Item::Element)?,
+            _ => {
+                write!(w, "{}", self.color.aggregate.paint("("))?;
+                self.emit_value(w, value)?;
+                write!(w, "{}", self.color.aggregate.paint(")"))?;
+            }
+        }
+        self.defer_comments(nodes);
+        Ok(())
+    }
+
+    // Emits the items of a struct literal or array between `open` and
+    // `close`, each on its own line unless compact.
+    fn emit_aggregate<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        open: &str,
+        close: &str,
+        items: &[Document],
+        item: Item,
+    ) -> Result<()> {
+        match self.max_depth {
+            Some(n) if self.depth >= n => return Err(Error::DepthLimit(n)),
+            _ => self.depth += 1,
+        }
+        // The elements of an array must have one type.
+        if open == "[" {
+            self.item_shape(items, item)?;
+        }
+        let mut items = match item {
+            Item::Field | Item::Entry => self.sort_keys.order(items),
+            _ => items.iter().collect(),
+        };
+        if self.compact {
+            items.retain(|i| i.has_value());
+        }
+        write!(w, "{}", self.color.aggregate.paint(open))?;
+        if self.compact {
+            // Struct literals are padded with spaces inside their braces.
+            let pad = if open == "{" && !items.is_empty() {
+                " "
+            } else {
+                ""
+            };
+            write!(w, "{}", pad)?;
+            for (i, node) in items.into_iter().enumerate() {
+                if i > 0 {
+                    write!(w, "{} ", self.color.punctuation.paint(","))?;
+                }
+                match item {
+                    Item::Element => self.emit_value(w, node)?,
+                    _ => {
+                        let (key, value) = node.as_kv()?;
+                        self.emit_entry(w, item, Some(key), value)?;
+                    }
+                }
+            }
+            write!(w, "{}", pad)?;
+        } else if !items.is_empty() {
+            // Comments deferred to the end of the line wait until after the
+            // aggregate.
+            let pending = std::mem::take(&mut self.pending);
+            writeln!(w)?;
+            self.level += 1;
+            for node in items {
+                self.emit_item(w, node, item)?;
+            }
+            self.level -= 1;
+            self.emit_indent(w)?;
+            self.pending = pending;
+        }
+        write!(w, "{}", self.color.aggregate.paint(close))?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn emit_bytes<W: fmt::Write>(&mut self, w: &mut W, bytes: &[u8]) -> Result<()> {
+        if let Some(string) = crate::hexdump::to_string(bytes, self.bytes_format) {
+            return self.emit_string(w, &string);
+        }
+        let array = ByteArray {
+            open: "[",
+            close: "]",
+            separator: ",",
+            hex: true,
+        };
+        let indent = (!self.compact).then(|| (self.level * self.indent, self.indent));
+        write_bytes(w, &self.color, bytes, &array, indent)
+    }
+
+    fn emit_string<W: fmt::Write>(&mut self, w: &mut W, value: &str) -> Result<()> {
+        write_quoted(
+            w,
+            &self.color,
+            value,
+            self.color.string,
+            ("\"", "\""),
+            rust_escape,
+        )
+    }
+
+    // Emits an integer in its annotated base.  Negative integers are
+    // written in decimal, since a negative literal in another base reads as
+    // the negation of its bit pattern.
+    fn emit_int<W: fmt::Write>(&mut self, w: &mut W, i: &Int) -> Result<()> {
+        let negative = i.as_i128().map_or(false, |v| v < 0);
+        let ctx = NumberContext {
+            base: if negative { Base::Dec } else { i.base() },
+            width: i.width(),
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        i.check_format(ctx.base)?;
+        write!(
+            w,
+            "{}",
+            self.color.integer.paint(self.numbers.format_int(i, &ctx))
+        )?;
+        Ok(())
+    }
+
+    fn emit_float<W: fmt::Write>(&mut self, w: &mut W, f: f64) -> Result<()> {
+        let text = if f.is_nan() {
+            "f64::NAN".to_string()
+        } else if f.is_infinite() {
+            if f > 0.0 {
+                "f64::INFINITY"
+            } else {
+                "f64::NEG_INFINITY"
+            }
+            .to_string()
+        } else {
+            float_literal(&*self.numbers, f, self.float_format, false)
+        };
+        write!(w, "{}", self.color.float.paint(text))?;
+        Ok(())
+    }
+
+    fn emit_indent<W: fmt::Write>(&mut self, w: &mut W) -> Result<()> {
+        if self.compact {
+            return Ok(());
+        }
+        write_indent(w, self.level * self.indent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_declarations() -> Result<()> {
+        let doc = Document::parse(
+            r#"
+            // The board configuration.
+            {
+                name: "rev\tb",
+                base: 0x4000_0000,
+                type: 2,
+                pins: [
+                    // The LED.
+                    {pin: 5, level: 1.0},
+                ],
+                extra: {"a b": null},
+            }"#,
+        )?;
+        assert_eq!(
+            doc.to_rust()
+                .indent(4)
+                .struct_name("/", "Board")
+                .struct_name("/pins/*", "Pin")
+                .declare(RustItem::Const, "BOARD", "Board")
+                .to_text()?,
+            r#"/// The board configuration.
+pub const BOARD: Board = Board {
+    name: "rev\tb",
+    base: 0x40000000,
+    r#type: 2,
+    pins: [
+        // The LED.
+        Pin {
+            pin: 5,
+            level: 1.0,
+        },
+    ],
+    extra: [
+        ("a b", None),
+    ],
+};
+"#
+        );
+
+        let doc = Document::parse("[1, -2, 0x10]")?;
+        assert_eq!(
+            doc.to_rust()
+                .compact(true)
+                .declare(RustItem::LazyStatic, "TABLE", "Vec<i64>")
+                .to_text()?,
+            "lazy_static! {\npub static ref TABLE: Vec<i64> = [1, -2, 0x10];\n}\n"
+        );
+        let doc = Document::Float(f64::NEG_INFINITY);
+        assert_eq!(doc.to_rust().to_text()?, "f64::NEG_INFINITY\n");
+
+        // The elements of an array must have one type.
+        let doc = Document::parse("[[], [[1]], [[2, 3]]]")?;
+        let rust = doc.to_rust().compact(true);
+        assert_eq!(rust.to_text()?, "[[], [[1]], [[2, 3]]]\n");
+        for mixed in ["[1, 'a', {k: 1}]", "[[1], ['a']]", "[{a: 1}, {b: 'c'}]"] {
+            let doc = Document::parse(mixed)?;
+            assert!(matches!(
+                doc.to_rust().to_text(),
+                Err(Error::Unrepresentable("an array of mixed types", _))
+            ));
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_serialized_types() -> Result<()> {
+        #[derive(serde_derive::Serialize)]
+        enum Shape {
+            Circle { r: u32 },
+            Empty,
+        }
+        #[derive(serde_derive::Serialize)]
+        struct Config {
+            timeout: Option<u32>,
+            retries: Option<u32>,
+            shape: Option<Shape>,
+            fallback: Shape,
+        }
+        let doc = crate::ser::serialize(&Config {
+            timeout: Some(30),
+            retries: None,
+            shape: Some(Shape::Circle { r: 1 }),
+            fallback: Shape::Empty,
+        })?;
+        assert_eq!(
+            doc.to_rust()
+                .compact(true)
+                .struct_name("/", "Config")
+                .to_text()?,
+            "Config { timeout: Some(30), retries: None, shape: Some(Circle { r: 1 }), \
+             fallback: Empty }\n"
+        );
+
+        // `self` has no raw form, so it cannot name a field.
+        let doc = Document::parse("{self: 1, type: 2}")?;
+        assert_eq!(
+            doc.to_rust()
+                .compact(true)
+                .struct_name("/", "Keys")
+                .to_text()?,
+            "[(\"self\", 1), (\"type\", 2)]\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+// Recovers the Rust types of serialized values from the shape of the
+// document, for the emitters which reproduce them (RON and Rust source).
+use crate::document::{Document, StrFormat};
+
+// Returns the string held by `node` if it is a variant name.
+pub(crate) fn variant_name(node: &Document) -> Option<&str> {
+    let name = node.as_str().ok()?;
+    let mut chars = name.chars();
+    let first = chars.next()?;
+    if first.is_ascii_uppercase() && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+// Returns whether `name` may be written as an identifier in RON or Rust
+// source.  Rust keywords are written in their raw form (e.g. `r#type`).
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !matches!(name, "true" | "false")
+}
+
+// Returns the name held by `node` if it is a name provided by serde.
+pub(crate) fn static_name(node: &Document) -> Option<&'static str> {
+    match node.as_value().ok()? {
+        Document::StaticStr(s, _) => Some(s),
+        _ => None,
+    }
+}
+
+// Returns the name of a serialized enum variant held by `node`.
+pub(crate) fn unit_variant(node: &Document) -> Option<&'static str> {
+    let name = static_name(node)?;
+    variant_name(&Document::StaticStr(name, StrFormat::Standard))?;
+    Some(name)
+}
+
+// Returns the entry of a mapping which represents an enum variant.
+pub(crate) fn variant(mapping: &[Document]) -> Option<(&'static str, &Document, &[Document])> {
+    match mapping {
+        [entry] => {
+            let (key, value) = entry.as_kv().ok()?;
+            Some((unit_variant(key)?, value, entry.fragments().ok()?))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,326 @@
+// Inference of JSON Schema documents from example documents.
+use crate::document::{Document, StrFormat};
+use crate::entry::{comment_lines, key_text, kv};
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// The schema inferred for a node, merged across all of the nodes which share
+// a path (e.g. the elements of a sequence).
+#[derive(Default)]
+struct Schema {
+    // The JSON types of the nodes, in the order they were first seen.
+    types: Vec<&'static str>,
+    description: Option<String>,
+    example: Option<Document>,
+    // The schemas of the keys of mappings and whether every mapping has
+    // the key.
+    properties: Vec<(String, Schema, bool)>,
+    // The schema of the elements of sequences.
+    items: Option<Box<Schema>>,
+}
+
+impl Document {
+    /// Infers a JSON Schema (draft 2020-12) describing this document, as a
+    /// starting point for a validation schema.
+    ///
+    /// Each node is described by its type; mappings list their properties,
+    /// all of which are required, and sequences describe their elements by
+    /// a single schema merged from every element.  Scalars carry their
+    /// value as an example, and the comments on mapping entries become the
+    /// descriptions of their properties.  Keys which are absent from some
+    /// of the mappings in a sequence are not required, and integers merged
+    /// with floats are described as numbers.
+    pub fn infer_schema(&self) -> Document {
+        let mut schema = Schema::default();
+        schema.add(self);
+        let mut root = vec![kv("$schema", Document::from(DRAFT))];
+        root.extend(schema.to_entries());
+        Document::Mapping(root)
+    }
+}
+
+impl Schema {
+    fn add(&mut self, node: &Document) {
+        let value = match node {
+            Document::Fragment(_) => match node.as_value() {
+                Ok(v) => v,
+                Err(_) => return,
+            },
+            Document::Compact(d)
+            | Document::ColorClass(_, d)
+            | Document::Identified(_, d)
+            | Document::Typed(_, d) => return self.add(d),
+            _ => node,
+        };
+        match value {
+            Document::Comment(_, _) | Document::Fragment(_) | Document::Absent => {}
+            Document::String(_, _) | Document::StaticStr(_, _) => self.scalar("string", value),
+            Document::Boolean(_) => self.scalar("boolean", value),
+            Document::Int(_) => self.scalar("integer", value),
+            Document::Float(_) => self.scalar("number", value),
+            Document::Null => self.add_type("null"),
+            Document::Bytes(_) => {
+                self.add_type("array");
+                let mut items = Schema::default();
+                items.add_type("integer");
+                self.merge_items(items);
+            }
+            Document::Sequence(s) => {
+                self.add_type("array");
+                let mut items = Schema::default();
+                for elem in s.iter().filter(|n| n.has_value()) {
+                    items.add(elem);
+                }
+                if !items.types.is_empty() {
+                    self.merge_items(items);
+                }
+            }
+            Document::Mapping(m) => {
+                let first = !self.types.contains(&"object");
+                self.add_type("object");
+                let mut seen = Vec::new();
+                for entry in m.iter().filter(|n| n.has_value()) {
+                    let (key, value) = match entry.as_kv() {
+                        Ok((k, v)) => (key_text(k), v),
+                        Err(_) => continue,
+                    };
+                    let key = match key {
+                        Some(k) => k,
+                        None => continue,
+                    };
+                    let index = match self.properties.iter().position(|(k, _, _)| *k == key) {
+                        Some(i) => i,
+                        None => {
+                            // A key absent from an earlier mapping is optional.
+                            self.properties
+                                .push((key.clone(), Schema::default(), first));
+                            self.properties.len() - 1
+                        }
+                    };
+                    let property = &mut self.properties[index].1;
+                    if property.description.is_none() {
+                        property.description = description(entry);
+                    }
+                    property.add(value);
+                    seen.push(key);
+                }
+                for (key, _, required) in self.properties.iter_mut() {
+                    *required &= seen.contains(key);
+                }
+            }
+            Document::Compact(_)
+            | Document::ColorClass(_, _)
+            | Document::Identified(_, _)
+            | Document::Typed(_, _) => self.add(value),
+        }
+    }
+
+    fn scalar(&mut self, ty: &'static str, value: &Document) {
+        self.add_type(ty);
+        if self.example.is_none() {
+            self.example = Some(value.clone());
+        }
+    }
+
+    fn add_type(&mut self, ty: &'static str) {
+        if !self.types.contains(&ty) {
+            self.types.push(ty);
+        }
+    }
+
+    fn merge_items(&mut self, items: Schema) {
+        match &mut self.items {
+            Some(existing) => existing.merge(items),
+            None => self.items = Some(Box::new(items)),
+        }
+    }
+
+    fn merge(&mut self, other: Schema) {
+        let first = !self.types.contains(&"object");
+        for ty in other.types.iter() {
+            self.add_type(ty);
+        }
+        if self.description.is_none() {
+            self.description = other.description;
+        }
+        if self.example.is_none() {
+            self.example = other.example;
+        }
+        let keys = other
+            .properties
+            .iter()
+            .map(|(k, _, _)| k.clone())
+            .collect::<Vec<_>>();
+        let other_has_object = other.types.contains(&"object");
+        for (key, schema, required) in other.properties {
+            match self.properties.iter_mut().find(|(k, _, _)| *k == key) {
+                Some((_, existing, r)) => {
+                    *r &= required;
+                    existing.merge(schema);
+                }
+                None => self.properties.push((key, schema, first && required)),
+            }
+        }
+        if other_has_object {
+            for (key, _, required) in self.properties.iter_mut() {
+                *required &= keys.contains(key);
+            }
+        }
+        if let Some(items) = other.items {
+            self.merge_items(*items);
+        }
+    }
+
+    fn to_entries(&self) -> Vec<Document> {
+        let mut entries = Vec::new();
+        if let Some(d) = &self.description {
+            entries.push(kv("description", string(d)));
+        }
+        let mut types = self.types.clone();
+        if types.contains(&"number") {
+            types.retain(|t| *t != "integer");
+        }
+        match types.as_slice() {
+            [] => {}
+            [ty] => entries.push(kv("type", Document::from(*ty))),
+            _ => entries.push(kv(
+                "type",
+                Document::Compact(Box::new(Document::Sequence(
+                    types.into_iter().map(Document::from).collect(),
+                ))),
+            )),
+        }
+        if !self.properties.is_empty() {
+            let properties = self
+                .properties
+                .iter()
+                .map(|(k, s, _)| kv(k, Document::Mapping(s.to_entries())))
+                .collect();
+            entries.push(kv("properties", Document::Mapping(properties)));
+            let required = self
+                .properties
+                .iter()
+                .filter(|(_, _, r)| *r)
+                .map(|(k, _, _)| string(k))
+                .collect::<Vec<_>>();
+            if !required.is_empty() {
+                entries.push(kv(
+                    "required",
+                    Document::Compact(Box::new(Document::Sequence(required))),
+                ));
+            }
+        }
+        if let Some(items) = &self.items {
+            entries.push(kv("items", Document::Mapping(items.to_entries())));
+        }
+        if let Some(example) = &self.example {
+            entries.push(kv(
+                "examples",
+                Document::Compact(Box::new(Document::Sequence(vec![example.clone()]))),
+            ));
+        }
+        entries
+    }
+}
+
+// Returns the comments of a mapping entry, one per line.
+fn description(entry: &Document) -> Option<String> {
+    let lines = comment_lines(entry)
+        .map(|l| l.trim_start_matches('*').trim())
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+fn string(s: &str) -> Document {
+    Document::String(s.to_string(), StrFormat::Standard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    #[test]
+    fn test_infer_schema() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // The address to listen on.
+                host: "localhost",
+                ports: [80, 443],
+                // Rate limits
+                // per client.
+                limits: {burst: 10, rate: 0.5},
+                users: [
+                    {name: "ada", admin: true},
+                    {name: "bob", uid: null},
+                    {name: "eve", uid: 1000},
+                ],
+            }"#,
+        )?;
+        assert_eq!(
+            doc.infer_schema().to_json().to_text()?,
+            r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "type": "object",
+  "properties": {
+    "host": {
+      "description": "The address to listen on.",
+      "type": "string",
+      "examples": ["localhost"]
+    },
+    "ports": {
+      "type": "array",
+      "items": {
+        "type": "integer",
+        "examples": [80]
+      }
+    },
+    "limits": {
+      "description": "Rate limits\nper client.",
+      "type": "object",
+      "properties": {
+        "burst": {
+          "type": "integer",
+          "examples": [10]
+        },
+        "rate": {
+          "type": "number",
+          "examples": [0.5]
+        }
+      },
+      "required": ["burst", "rate"]
+    },
+    "users": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "properties": {
+          "name": {
+            "type": "string",
+            "examples": ["ada"]
+          },
+          "admin": {
+            "type": "boolean",
+            "examples": [true]
+          },
+          "uid": {
+            "type": ["null", "integer"],
+            "examples": [1000]
+          }
+        },
+        "required": ["name"]
+      }
+    }
+  },
+  "required": ["host", "ports", "limits", "users"]
+}"#
+        );
+        Ok(())
+    }
+}
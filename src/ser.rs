@@ -1,7 +1,8 @@
 use serde::ser;
+use std::sync::Arc;
 
 use crate::annotate::{Annotate, Format, MemberId};
-use crate::document::{BytesFormat, CommentFormat, Document, StrFormat};
+use crate::document::{BytesFormat, CommentFormat, Document, SerdeType, StrFormat};
 use crate::error::Error;
 use crate::hexdump;
 use crate::integer::{Base, Int};
@@ -22,6 +23,7 @@ pub struct AnnotatedSerializer<'a> {
     strformat: StrFormat,
     bytesformat: BytesFormat,
     compact: bool,
+    static_strings: bool,
 }
 
 impl<'a> AnnotatedSerializer<'a> {
@@ -32,6 +34,25 @@ impl<'a> AnnotatedSerializer<'a> {
             strformat: StrFormat::Standard,
             bytesformat: BytesFormat::Standard,
             compact: false,
+            static_strings: true,
+        }
+    }
+
+    /// Sets whether struct field names and enum variant names, which serde
+    /// provides as `&'static str`, are stored as `Document::StaticStr`
+    /// rather than copied into a `Document::String` (the default is `true`).
+    /// See `Document::static_str_count` to measure the copies avoided.
+    pub fn static_strings(mut self, b: bool) -> Self {
+        self.static_strings = b;
+        self
+    }
+
+    // Returns a string node for a name provided by serde.
+    fn static_str(&self, s: &'static str, f: StrFormat) -> Document {
+        if self.static_strings {
+            Document::StaticStr(s, f)
+        } else {
+            Document::String(s.to_string(), f)
         }
     }
 
@@ -59,21 +80,27 @@ impl<'a> AnnotatedSerializer<'a> {
         x
     }
 
-    fn annotate(&self, variant: Option<&str>, field: &MemberId) -> Option<Self> {
-        match self.annotator.map(|a| a.format(variant, field)).flatten() {
-            Some(Format::Block) => Some(self.with_strformat(StrFormat::Multiline)),
-            Some(Format::Binary) => Some(self.with_base(Base::Bin)),
-            Some(Format::Decimal) => Some(self.with_base(Base::Dec)),
-            Some(Format::Hex) => Some(self.with_base(Base::Hex)),
-            Some(Format::Octal) => Some(self.with_base(Base::Oct)),
-            Some(Format::Compact) => Some(self.with_compact(true)),
-            Some(Format::HexStr) => Some(self.with_bytesformat(BytesFormat::HexStr)),
-            Some(Format::Hexdump) => Some(self.with_bytesformat(BytesFormat::Hexdump)),
-            Some(Format::Xxd) => Some(self.with_bytesformat(BytesFormat::Xxd)),
-            None => None,
+    fn with_format(&self, format: Format) -> Self {
+        match format {
+            Format::Block => self.with_strformat(StrFormat::Multiline),
+            Format::Binary => self.with_base(Base::Bin),
+            Format::Decimal => self.with_base(Base::Dec),
+            Format::Hex => self.with_base(Base::Hex),
+            Format::Octal => self.with_base(Base::Oct),
+            Format::Compact => self.with_compact(true),
+            Format::HexStr => self.with_bytesformat(BytesFormat::HexStr),
+            Format::Base64 => self.with_bytesformat(BytesFormat::Base64),
+            Format::Hexdump => self.with_bytesformat(BytesFormat::Hexdump),
+            Format::Xxd => self.with_bytesformat(BytesFormat::Xxd),
         }
     }
 
+    fn annotate(&self, variant: Option<&str>, field: &MemberId) -> Option<Self> {
+        self.annotator
+            .and_then(|a| a.format(variant, field))
+            .map(|f| self.with_format(f))
+    }
+
     fn comment(&self, variant: Option<&str>, field: &MemberId) -> Option<Document> {
         self.annotator
             .map(|a| a.comment(variant, field))
@@ -81,6 +108,56 @@ impl<'a> AnnotatedSerializer<'a> {
             .map(|c| Document::Comment(c, CommentFormat::Standard))
     }
 
+    fn value_comment(&self, variant: Option<&str>, field: &MemberId) -> Option<Document> {
+        self.annotator
+            .and_then(|a| a.value_comment(variant, field))
+            .map(|c| Document::Comment(c, CommentFormat::Standard))
+    }
+
+    fn color(&self, variant: Option<&str>, field: &MemberId, node: Document) -> Document {
+        match self.annotator.and_then(|a| a.color(variant, field)) {
+            Some(c) => Document::ColorClass(c.to_string(), Box::new(node)),
+            None => node,
+        }
+    }
+
+    // Serializes a bytes object.  The `Document::Bytes` buffer is obtained
+    // from `shared` only when the bytes are not rendered as a string.
+    pub(crate) fn serialize_shared_bytes<F>(&self, v: &[u8], shared: F) -> Result<Document, Error>
+    where
+        F: FnOnce() -> Arc<[u8]>,
+    {
+        if let Some(string) = hexdump::to_string(v, self.bytesformat) {
+            Ok(Document::String(string, self.bytesformat.str_format()))
+        } else {
+            Ok(Document::Bytes(shared()))
+        }
+    }
+
+    // Serializes the value of an `Annotated` wrapper with its annotations.
+    pub(crate) fn serialize_annotated<T>(
+        &self,
+        value: &T,
+        format: Option<Format>,
+        comment: Option<&str>,
+        color: Option<&str>,
+    ) -> Result<Document, Error>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        let mut node = self.serialize(value, format.map(|f| self.with_format(f)))?;
+        if let Some(c) = color {
+            node = Document::ColorClass(c.to_string(), Box::new(node));
+        }
+        match comment {
+            Some(c) => Ok(Document::Fragment(vec![
+                Document::Comment(c.to_string(), CommentFormat::Standard),
+                node,
+            ])),
+            None => Ok(node),
+        }
+    }
+
     fn serialize<T>(&self, value: &T, ser: Option<AnnotatedSerializer>) -> Result<Document, Error>
     where
         T: ?Sized + ser::Serialize,
@@ -91,6 +168,40 @@ impl<'a> AnnotatedSerializer<'a> {
     }
 }
 
+// Separates the comments leading a value (e.g. those of an `Annotated`
+// value) from the value, so that they may be placed ahead of its key.
+fn split_comments(node: Document) -> (Vec<Document>, Document) {
+    match node {
+        Document::Fragment(mut nodes)
+            if nodes.len() > 1
+                && nodes[..nodes.len() - 1]
+                    .iter()
+                    .all(|n| n.comment().is_some()) =>
+        {
+            // Unwrap is ok: the fragment has more than one node.
+            let value = nodes.pop().unwrap();
+            (nodes, value)
+        }
+        node => (Vec::new(), node),
+    }
+}
+
+// Builds a sequence element from a value and the comments describing it,
+// which are emitted above and after the value respectively.
+fn element(comment: Option<Document>, node: Document, value_comment: Option<Document>) -> Document {
+    match (comment, value_comment) {
+        (None, None) => node,
+        (c, vc) => Document::Fragment(c.into_iter().chain([node]).chain(vc).collect()),
+    }
+}
+
+// Builds an enum variant in serde's externally tagged form, a mapping from
+// the variant name to its content, from the nodes of its entry.
+fn variant_node(nodes: Vec<Document>) -> Document {
+    let mapping = Document::Mapping(vec![Document::Fragment(nodes)]);
+    Document::Typed(SerdeType::Variant, Box::new(mapping))
+}
+
 impl<'s, 'a> ser::Serializer for &'s mut AnnotatedSerializer<'a> {
     type Ok = Document;
     type Error = Error;
@@ -164,18 +275,7 @@ impl<'s, 'a> ser::Serializer for &'s mut AnnotatedSerializer<'a> {
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        if let Some(string) = hexdump::to_string(v, self.bytesformat) {
-            Ok(Document::String(
-                string,
-                if self.bytesformat == BytesFormat::HexStr {
-                    StrFormat::Standard
-                } else {
-                    StrFormat::Multiline
-                },
-            ))
-        } else {
-            Ok(Document::Bytes(v.to_vec()))
-        }
+        self.serialize_shared_bytes(v, || v.into())
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
@@ -186,7 +286,16 @@ impl<'s, 'a> ser::Serializer for &'s mut AnnotatedSerializer<'a> {
     where
         T: ?Sized + ser::Serialize,
     {
-        self.serialize(value, None)
+        // The comments of an annotated value stay outside the hint, so that
+        // they are still placed ahead of its key.
+        let (mut nodes, value) = split_comments(self.serialize(value, None)?);
+        let value = Document::Typed(SerdeType::Some, Box::new(value));
+        if nodes.is_empty() {
+            Ok(value)
+        } else {
+            nodes.push(value);
+            Ok(Document::Fragment(nodes))
+        }
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
@@ -203,7 +312,8 @@ impl<'s, 'a> ser::Serializer for &'s mut AnnotatedSerializer<'a> {
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        let node = self.serialize_str(variant)?;
+        let node = self.static_str(variant, self.strformat);
+        let node = self.color(Some(variant), &MemberId::Variant, node);
         if let Some(c) = self.comment(Some(variant), &MemberId::Variant) {
             Ok(Document::Fragment(vec![node, c]))
         } else {
@@ -221,6 +331,7 @@ impl<'s, 'a> ser::Serializer for &'s mut AnnotatedSerializer<'a> {
     {
         let field = MemberId::Index(0);
         let node = self.serialize(value, self.annotate(None, &field))?;
+        let node = self.color(None, &field, node);
         if let Some(c) = self.comment(None, &field) {
             Ok(Document::Fragment(vec![c, node]))
         } else {
@@ -241,19 +352,21 @@ impl<'s, 'a> ser::Serializer for &'s mut AnnotatedSerializer<'a> {
         let a = self.annotate(Some(variant), &MemberId::Variant);
         let compact = a.map(|a| a.compact).unwrap_or(false);
         let v = self.serialize(value, self.annotate(Some(variant), &MemberId::Index(0)))?;
+        let v = self.color(Some(variant), &MemberId::Index(0), v);
         let v = if compact {
             Document::Compact(v.into())
         } else {
             v
         };
+        let v = self.color(Some(variant), &MemberId::Variant, v);
         let mut nodes = vec![];
         if let Some(c) = self.comment(Some(variant), &MemberId::Variant) {
             nodes.push(c);
         }
-        nodes.push(Document::from(variant));
+        nodes.push(self.static_str(variant, StrFormat::Standard));
         nodes.push(v);
 
-        Ok(Document::Mapping(vec![Document::Fragment(nodes)]))
+        Ok(variant_node(nodes))
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
@@ -395,11 +508,12 @@ impl<'s, 'a> ser::SerializeTupleStruct for SerializeTupleStruct<'s, 'a> {
         let node = self
             .serializer
             .serialize(value, self.serializer.annotate(None, &field))?;
-        if let Some(c) = self.serializer.comment(None, &field) {
-            self.sequence.push(Document::Fragment(vec![c, node]));
-        } else {
-            self.sequence.push(node);
-        }
+        let node = self.serializer.color(None, &field, node);
+        self.sequence.push(element(
+            self.serializer.comment(None, &field),
+            node,
+            self.serializer.value_comment(None, &field),
+        ));
         self.index += 1;
         Ok(())
     }
@@ -439,11 +553,12 @@ impl<'s, 'a> ser::SerializeTupleVariant for SerializeTupleVariant<'s, 'a> {
         let node = self
             .serializer
             .serialize(value, self.serializer.annotate(Some(self.variant), &field))?;
-        if let Some(c) = self.serializer.comment(Some(self.variant), &field) {
-            self.sequence.push(Document::Fragment(vec![c, node]));
-        } else {
-            self.sequence.push(node);
-        }
+        let node = self.serializer.color(Some(self.variant), &field, node);
+        self.sequence.push(element(
+            self.serializer.comment(Some(self.variant), &field),
+            node,
+            self.serializer.value_comment(Some(self.variant), &field),
+        ));
 
         self.index += 1;
         Ok(())
@@ -459,6 +574,9 @@ impl<'s, 'a> ser::SerializeTupleVariant for SerializeTupleVariant<'s, 'a> {
         } else {
             Document::Sequence(self.sequence)
         };
+        let sequence = self
+            .serializer
+            .color(Some(self.variant), &MemberId::Variant, sequence);
         let mut nodes = vec![];
         if let Some(c) = self
             .serializer
@@ -466,9 +584,12 @@ impl<'s, 'a> ser::SerializeTupleVariant for SerializeTupleVariant<'s, 'a> {
         {
             nodes.push(c);
         }
-        nodes.push(Document::from(self.variant));
+        nodes.push(
+            self.serializer
+                .static_str(self.variant, StrFormat::Standard),
+        );
         nodes.push(sequence);
-        Ok(Document::Mapping(vec![Document::Fragment(nodes)]))
+        Ok(variant_node(nodes))
     }
 }
 
@@ -510,10 +631,9 @@ impl<'s, 'a> ser::SerializeMap for SerializeMap<'s, 'a> {
     {
         match self.next_key.take() {
             Some(key) => {
-                self.mapping.push(Document::Fragment(vec![
-                    key,
-                    self.serializer.serialize(value, None)?,
-                ]));
+                let (mut nodes, value) = split_comments(self.serializer.serialize(value, None)?);
+                nodes.extend([key, value]);
+                self.mapping.push(Document::Fragment(nodes));
             }
             None => panic!("serialize_value called before serialize_key"),
         };
@@ -525,10 +645,10 @@ impl<'s, 'a> ser::SerializeMap for SerializeMap<'s, 'a> {
         K: ?Sized + ser::Serialize,
         V: ?Sized + ser::Serialize,
     {
-        self.mapping.push(Document::Fragment(vec![
-            key.serialize(&mut *self.serializer)?,
-            self.serializer.serialize(value, None)?,
-        ]));
+        let key = key.serialize(&mut *self.serializer)?;
+        let (mut nodes, value) = split_comments(self.serializer.serialize(value, None)?);
+        nodes.extend([key, value]);
+        self.mapping.push(Document::Fragment(nodes));
         Ok(())
     }
 }
@@ -564,11 +684,14 @@ impl<'s, 'a> ser::SerializeStruct for SerializeStruct<'s, 'a> {
         if let Some(c) = self.serializer.comment(None, &field) {
             nodes.push(c);
         }
-        nodes.push(Document::from(key));
-        nodes.push(
-            self.serializer
-                .serialize(value, self.serializer.annotate(None, &field))?,
-        );
+        let node = self
+            .serializer
+            .serialize(value, self.serializer.annotate(None, &field))?;
+        let (comments, node) = split_comments(node);
+        nodes.extend(comments);
+        nodes.push(self.serializer.static_str(key, StrFormat::Standard));
+        nodes.push(self.serializer.color(None, &field, node));
+        nodes.extend(self.serializer.value_comment(None, &field));
         self.mapping.push(Document::Fragment(nodes));
         Ok(())
     }
@@ -604,6 +727,9 @@ impl<'s, 'a> ser::SerializeStructVariant for SerializeStructVariant<'s, 'a> {
         } else {
             Document::Mapping(self.mapping)
         };
+        let mapping = self
+            .serializer
+            .color(Some(self.variant), &MemberId::Variant, mapping);
         let mut nodes = vec![];
         if let Some(c) = self
             .serializer
@@ -611,9 +737,12 @@ impl<'s, 'a> ser::SerializeStructVariant for SerializeStructVariant<'s, 'a> {
         {
             nodes.push(c);
         }
-        nodes.push(Document::from(self.variant));
+        nodes.push(
+            self.serializer
+                .static_str(self.variant, StrFormat::Standard),
+        );
         nodes.push(mapping);
-        Ok(Document::Mapping(vec![Document::Fragment(nodes)]))
+        Ok(variant_node(nodes))
     }
 
     fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
@@ -621,15 +750,19 @@ impl<'s, 'a> ser::SerializeStructVariant for SerializeStructVariant<'s, 'a> {
         T: ?Sized + ser::Serialize,
     {
         let field = MemberId::Name(key);
+        let variant = Some(self.variant);
         let mut nodes = vec![];
-        if let Some(c) = self.serializer.comment(None, &field) {
+        if let Some(c) = self.serializer.comment(variant, &field) {
             nodes.push(c);
         }
-        nodes.push(Document::from(key));
-        nodes.push(
-            self.serializer
-                .serialize(value, self.serializer.annotate(None, &field))?,
-        );
+        let node = self
+            .serializer
+            .serialize(value, self.serializer.annotate(variant, &field))?;
+        let (comments, node) = split_comments(node);
+        nodes.extend(comments);
+        nodes.push(self.serializer.static_str(key, StrFormat::Standard));
+        nodes.push(self.serializer.color(variant, &field, node));
+        nodes.extend(self.serializer.value_comment(variant, &field));
         self.mapping.push(Document::Fragment(nodes));
         Ok(())
     }
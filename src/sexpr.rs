@@ -0,0 +1,552 @@
+use crate::color::ColorProfile;
+use crate::document::{BytesFormat, Document, StrFormat};
+use crate::emit::{common_options, guarded, CommonOptions, Emit};
+use crate::entry::{comments_of, MapEntry, SeqItem};
+use crate::error::Error;
+use crate::hexdump;
+use crate::integer::{Base, Int};
+use crate::layout::TokenClass;
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use crate::render::{float_literal, write_bytes, write_indent, write_quoted, ByteArray};
+use crate::sort::KeySort;
+use std::fmt;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Error>;
+
+// The number of bytes written on each line of a long bytes array.
+
+/// The flavor of S-expression written by `Sexpr`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SexprDialect {
+    /// Clojure's EDN (extensible data notation): mappings are written as
+    /// maps, `{:port 80}`, booleans as `true` and `false`, and null as `nil`.
+    Edn,
+    /// Generic Lisp S-expressions: mappings are written as property lists,
+    /// `(:port 80)`, booleans as `t` and `nil`, and null as `nil`.  NaN and
+    /// infinite floats cannot be emitted.
+    Lisp,
+}
+
+/// The brackets in which `Sexpr` writes sequences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SexprSequence {
+    /// A vector, `[1 2]`.
+    Vector,
+    /// A list, `(1 2)`.
+    List,
+}
+
+/// An S-expression or EDN document and its formatting properties.
+///
+/// Mapping keys which are strings are written as keywords (`:name`) where
+/// they are legal keyword names, and as strings otherwise.  Comments are
+/// written as `;` comments.  Integers are written in decimal, and strings
+/// in the multiline formats keep their line breaks.
+pub struct Sexpr {
+    document: Document,
+    common: CommonOptions,
+    dialect: SexprDialect,
+    sequences: SexprSequence,
+    keywords: bool,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+}
+
+impl Sexpr {
+    common_options!(
+        /// Set the amount of indentation of the contents of maps and sequences.
+        indent,
+        /// Set whether the document is emitted on a single line, without
+        /// comments.
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        max_depth,
+        /// Set the order in which the entries of mappings are emitted.
+        sort_keys,
+        newline,
+        ascii_comments,
+    );
+    /// Set the flavor of S-expression to emit.
+    pub fn dialect(mut self, d: SexprDialect) -> Self {
+        self.dialect = d;
+        self
+    }
+    /// Set the brackets in which sequences are written.  The default is
+    /// `SexprSequence::Vector` for EDN and `SexprSequence::List` otherwise.
+    pub fn sequences(mut self, s: SexprSequence) -> Self {
+        self.sequences = s;
+        self
+    }
+    /// Set whether string keys are written as keywords where possible (the
+    /// default is `true`).
+    pub fn keywords(mut self, b: bool) -> Self {
+        self.keywords = b;
+        self
+    }
+    /// Set the rendering of bytes values: a sequence of integers
+    /// (`BytesFormat::Standard`) or a string in the given format.  Bytes
+    /// annotated with a format are rendered as strings regardless.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.bytes_format = b;
+        self
+    }
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.  S-expressions have
+    /// no hexadecimal floats, so `FloatFormat::Hex` renders as
+    /// `FloatFormat::Shortest`.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+
+    fn emit<W: fmt::Write>(&self, w: &mut W) -> Result<()> {
+        self.common.check_comments(&self.document)?;
+        let mut emitter = SexprEmitter {
+            level: 0,
+            indent: self.common.indent,
+            color: self.common.color.clone(),
+            compact: self.common.compact,
+            sort_keys: self.common.sort_keys.clone(),
+            dialect: self.dialect,
+            sequences: self.sequences,
+            keywords: self.keywords,
+            bytes_format: self.bytes_format,
+            numbers: self.numbers.clone(),
+            float_format: self.float_format,
+            depth: 0,
+            max_depth: self.common.max_depth,
+            pending: Vec::new(),
+        };
+        emitter.emit_item(w, &self.document, Item::Root)
+    }
+}
+
+impl Emit for Sexpr {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.common, w, |mut w| self.emit(&mut w))
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.common
+    }
+}
+
+impl Document {
+    pub fn to_edn(self) -> Sexpr {
+        self.to_sexpr()
+            .dialect(SexprDialect::Edn)
+            .sequences(SexprSequence::Vector)
+    }
+
+    pub fn to_sexpr(self) -> Sexpr {
+        Sexpr {
+            document: self,
+            common: CommonOptions::default(),
+            dialect: SexprDialect::Lisp,
+            sequences: SexprSequence::List,
+            keywords: true,
+            bytes_format: BytesFormat::Standard,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+        }
+    }
+}
+
+// Returns whether `name` may be written as a keyword, `:name`.  Keywords
+// follow the rules of EDN symbols, which are also legal Lisp symbols.
+fn is_keyword(name: &str) -> bool {
+    let symbolic = |c: char| matches!(c, '*' | '+' | '!' | '-' | '_' | '?' | '<' | '>' | '=');
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || symbolic(c) => {}
+        _ => return false,
+    }
+    // A leading sign followed by a digit reads as a number.
+    let digit = name[1..].starts_with(|c: char| c.is_ascii_digit());
+    !(name.starts_with(['-', '+']) && digit)
+        && chars.all(|c| c.is_ascii_alphanumeric() || symbolic(c) || c == '.')
+}
+
+// The position of an item within its aggregate.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Root,
+    Element,
+    Entry,
+}
+
+struct SexprEmitter {
+    level: usize,
+    indent: usize,
+    color: ColorProfile,
+    compact: bool,
+    sort_keys: KeySort,
+    dialect: SexprDialect,
+    sequences: SexprSequence,
+    keywords: bool,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    depth: usize,
+    max_depth: Option<usize>,
+    // The comments of values emitted inline, which are emitted at the end
+    // of the line of the item holding the value.
+    pending: Vec<String>,
+}
+
+impl SexprEmitter {
+    // Emits a map entry, sequence element or the document itself along
+    // with its comments.  Comments ahead of the value are emitted on the
+    // lines above it and those after the value at the end of its line.
+    fn emit_item<W: fmt::Write>(&mut self, w: &mut W, node: &Document, item: Item) -> Result<()> {
+        if !node.has_value() {
+            for (c, _) in comments_of(node) {
+                self.emit_comment(w, c)?;
+            }
+            return Ok(());
+        }
+        let (comments, key, value, trailing) = match item {
+            Item::Entry => {
+                let e = MapEntry::new(node)?;
+                (e.comments, Some(e.key), e.value, e.trailing)
+            }
+            Item::Root | Item::Element => {
+                let i = SeqItem::new(node)?;
+                (i.comments, None, i.value, i.trailing)
+            }
+        };
+        for (c, _) in comments {
+            self.emit_comment(w, c)?;
+        }
+        self.emit_indent(w)?;
+        if let Some(key) = key {
+            self.emit_key(w, key)?;
+            write!(w, " ")?;
+        }
+        self.emit_value(w, value)?;
+        let mut trailing = trailing
+            .into_iter()
+            .map(|(c, _)| c.to_string())
+            .collect::<Vec<_>>();
+        trailing.append(&mut self.pending);
+        match &trailing[..] {
+            [c] if !c.contains('\n') => {
+                writeln!(w, " {}", self.color.comment.paint(format!("; {}", c)))?;
+            }
+            _ => {
+                writeln!(w)?;
+                for c in &trailing {
+                    self.emit_comment(w, c)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_comment<W: fmt::Write>(&mut self, w: &mut W, comment: &str) -> Result<()> {
+        for line in comment.split('\n') {
+            self.emit_indent(w)?;
+            if line.is_empty() {
+                writeln!(w, "{}", self.color.comment.paint(";"))?;
+            } else {
+                writeln!(w, "{}", self.color.comment.paint(format!("; {}", line)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_value<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        match node {
+            Document::Comment(_, _) => Ok(()),
+            Document::String(v, f) => self.emit_string(w, v, *f, self.color.string),
+            Document::StaticStr(v, f) => self.emit_string(w, v, *f, self.color.string),
+            Document::Boolean(b) => {
+                let text = match (self.dialect, b) {
+                    (SexprDialect::Edn, b) => b.to_string(),
+                    (SexprDialect::Lisp, true) => "t".to_string(),
+                    (SexprDialect::Lisp, false) => "nil".to_string(),
+                };
+                Ok(write!(w, "{}", self.color.boolean.paint(text))?)
+            }
+            Document::Int(i) => self.emit_int(w, i),
+            Document::Float(f) => self.emit_float(w, *f),
+            Document::Mapping(m) => {
+                let (open, close) = match self.dialect {
+                    SexprDialect::Edn => ("{", "}"),
+                    SexprDialect::Lisp => ("(", ")"),
+                };
+                self.emit_aggregate(w, open, close, m, Item::Entry)
+            }
+            Document::Sequence(s) => {
+                let (open, close) = self.brackets();
+                self.emit_aggregate(w, open, close, s, Item::Element)
+            }
+            Document::Bytes(b) => self.emit_bytes(w, b),
+            Document::Null => Ok(write!(w, "{}", self.color.null.paint("nil"))?),
+            Document::Absent => Err(Error::AbsentValue),
+            Document::Compact(d) => {
+                let compact = std::mem::replace(&mut self.compact, true);
+                let result = self.emit_value(w, d);
+                self.compact = compact;
+                result
+            }
+            Document::ColorClass(c, d) => {
+                let color = self.color.for_class(c);
+                let saved = std::mem::replace(&mut self.color, color);
+                let result = self.emit_value(w, d);
+                self.color = saved;
+                result
+            }
+            Document::Identified(_, d) | Document::Typed(_, d) => self.emit_value(w, d),
+            Document::Fragment(ds) => {
+                self.emit_value(w, node.as_value()?)?;
+                if !self.compact {
+                    let comments = ds.iter().filter_map(Document::comment);
+                    self.pending.extend(comments.map(|(c, _)| c.to_string()));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn brackets(&self) -> (&'static str, &'static str) {
+        match self.sequences {
+            SexprSequence::Vector => ("[", "]"),
+            SexprSequence::List => ("(", ")"),
+        }
+    }
+
+    fn emit_key<W: fmt::Write>(&mut self, w: &mut W, key: &Document) -> Result<()> {
+        match key.as_value()? {
+            Document::String(s, _) if self.keywords && is_keyword(s) => {
+                let style = self.color.style(&TokenClass::BareKey);
+                Ok(write!(w, "{}", style.paint(format!(":{}", s)))?)
+            }
+            Document::StaticStr(s, _) if self.keywords && is_keyword(s) => {
+                let style = self.color.style(&TokenClass::BareKey);
+                Ok(write!(w, "{}", style.paint(format!(":{}", s)))?)
+            }
+            Document::String(s, _) => self.emit_string(w, s, StrFormat::Standard, self.color.key),
+            Document::StaticStr(s, _) => {
+                self.emit_string(w, s, StrFormat::Standard, self.color.key)
+            }
+            _ => self.emit_value(w, key),
+        }
+    }
+
+    // Emits the items of a map or sequence between `open` and `close`, each
+    // on its own line unless compact.
+    fn emit_aggregate<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        open: &str,
+        close: &str,
+        items: &[Document],
+        item: Item,
+    ) -> Result<()> {
+        match self.max_depth {
+            Some(n) if self.depth >= n => return Err(Error::DepthLimit(n)),
+            _ => self.depth += 1,
+        }
+        let mut items = match item {
+            Item::Entry => self.sort_keys.order(items),
+            _ => items.iter().collect(),
+        };
+        if self.compact {
+            items.retain(|i| i.has_value());
+        }
+        write!(w, "{}", self.color.aggregate.paint(open))?;
+        if self.compact {
+            for (i, node) in items.into_iter().enumerate() {
+                if i > 0 {
+                    write!(w, " ")?;
+                }
+                if item == Item::Entry {
+                    let (key, value) = node.as_kv()?;
+                    self.emit_key(w, key)?;
+                    write!(w, " ")?;
+                    self.emit_value(w, value)?;
+                } else {
+                    self.emit_value(w, node)?;
+                }
+            }
+        } else if !items.is_empty() {
+            // Comments deferred to the end of the line wait until after the
+            // aggregate.
+            let pending = std::mem::take(&mut self.pending);
+            writeln!(w)?;
+            self.level += 1;
+            for node in items {
+                self.emit_item(w, node, item)?;
+            }
+            self.level -= 1;
+            self.emit_indent(w)?;
+            self.pending = pending;
+        }
+        write!(w, "{}", self.color.aggregate.paint(close))?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn emit_bytes<W: fmt::Write>(&mut self, w: &mut W, bytes: &[u8]) -> Result<()> {
+        if let Some(string) = hexdump::to_string(bytes, self.bytes_format) {
+            let f = self.bytes_format.str_format();
+            return self.emit_string(w, &string, f, self.color.string);
+        }
+        let (open, close) = self.brackets();
+        let array = ByteArray {
+            open,
+            close,
+            separator: "",
+            hex: false,
+        };
+        let indent = (!self.compact).then(|| (self.level * self.indent, self.indent));
+        write_bytes(w, &self.color, bytes, &array, indent)
+    }
+
+    // Emits a string.  Strings in the multiline formats keep their line
+    // breaks, which may appear in S-expression strings as they are.
+    fn emit_string<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        value: &str,
+        f: StrFormat,
+        color: ansi_term::Style,
+    ) -> Result<()> {
+        let multiline = !self.compact && f.is_multiline();
+        write_quoted(w, &self.color, value, color, ("\"", "\""), |ch| {
+            Some(match ch {
+                '"' => "\\\"".to_string(),
+                '\\' => "\\\\".to_string(),
+                '\n' if multiline => return None,
+                '\n' => "\\n".to_string(),
+                '\t' => "\\t".to_string(),
+                '\r' => "\\r".to_string(),
+                c if c.is_control() => format!("\\u{:04x}", c as u32),
+                _ => return None,
+            })
+        })
+    }
+
+    // Emits an integer in decimal.  EDN integers outside the signed 64-bit
+    // range carry the `N` suffix of arbitrary precision integers.
+    fn emit_int<W: fmt::Write>(&mut self, w: &mut W, i: &Int) -> Result<()> {
+        let ctx = NumberContext {
+            base: Base::Dec,
+            width: 0,
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        // The dialect has no integer type suffixes.
+        let i = &i.unsuffixed();
+        i.check_format(ctx.base)?;
+        let mut text = self.numbers.format_int(i, &ctx);
+        let small = i.as_i128().map_or(false, |v| i64::try_from(v).is_ok());
+        if self.dialect == SexprDialect::Edn && !small {
+            text.push('N');
+        }
+        write!(w, "{}", self.color.integer.paint(text))?;
+        Ok(())
+    }
+
+    fn emit_float<W: fmt::Write>(&mut self, w: &mut W, f: f64) -> Result<()> {
+        let text = if f.is_finite() {
+            float_literal(&*self.numbers, f, self.float_format, false)
+        } else if self.dialect == SexprDialect::Lisp {
+            return Err(Error::Unrepresentable(
+                "NaN and infinite floats",
+                "S-expressions",
+            ));
+        } else if f.is_nan() {
+            "##NaN".to_string()
+        } else {
+            if f > 0.0 { "##Inf" } else { "##-Inf" }.to_string()
+        };
+        write!(w, "{}", self.color.float.paint(text))?;
+        Ok(())
+    }
+
+    fn emit_indent<W: fmt::Write>(&mut self, w: &mut W) -> Result<()> {
+        if self.compact {
+            return Ok(());
+        }
+        write_indent(w, self.level * self.indent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    const DOC: &str = r#"{
+        // The service.
+        name: "demo",
+        ports: [80, 443], // Public.
+        "odd key": null,
+        ratio: 2.0,
+        big: 0xFFFFFFFFFFFFFFFF,
+        enabled: false,
+    }"#;
+
+    #[test]
+    fn test_edn() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_edn().to_text()?,
+            r#"{
+  ; The service.
+  :name "demo"
+  :ports [
+    80
+    443
+  ] ; Public.
+  "odd key" nil
+  :ratio 2.0
+  :big 18446744073709551615N
+  :enabled false
+}
+"#
+        );
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_edn()
+                .compact(true)
+                .keywords(false)
+                .sequences(SexprSequence::List)
+                .to_text()?,
+            r#"{"name" "demo" "ports" (80 443) "odd key" nil "ratio" 2.0 "big" 18446744073709551615N "enabled" false}
+"#
+        );
+        let doc = Document::Float(f64::NEG_INFINITY);
+        assert_eq!(doc.to_edn().to_text()?, "##-Inf\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_lisp() -> Result<()> {
+        let doc = Document::parse(DOC)?;
+        assert_eq!(
+            doc.to_sexpr().compact(true).to_text()?,
+            r#"(:name "demo" :ports (80 443) "odd key" nil :ratio 2.0 :big 18446744073709551615 :enabled nil)
+"#
+        );
+        let doc = Document::Float(f64::NAN);
+        assert!(doc.to_sexpr().to_text().is_err());
+
+        let doc = Document::String("a \"b\"\nc\u{1}".into(), StrFormat::Multiline);
+        assert_eq!(doc.to_sexpr().to_text()?, "\"a \\\"b\\\"\nc\\u0001\"\n");
+        assert!(!is_keyword("-1x"));
+        assert!(is_keyword("->x"));
+        assert!(!is_keyword("9a"));
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+// Adapters from the emitters' `fmt::Write` output to `io::Write` sinks.
+use std::fmt;
+use std::io;
+
+// Writes the same output to each of a set of sinks.  The first io error
+// is kept, since `fmt::Error` cannot carry it.
+pub(crate) struct TeeWriter<'a, 'b> {
+    sinks: &'a mut [&'b mut dyn io::Write],
+    pub(crate) error: Option<io::Error>,
+}
+
+impl<'a, 'b> TeeWriter<'a, 'b> {
+    pub(crate) fn new(sinks: &'a mut [&'b mut dyn io::Write]) -> Self {
+        TeeWriter { sinks, error: None }
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        for sink in self.sinks.iter_mut() {
+            sink.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for TeeWriter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.write_all(s.as_bytes()) {
+                self.error = Some(e);
+                return Err(fmt::Error);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Limits the size of the output, and strips color escapes from the output
+// following the first `color_limit` bytes.
+pub(crate) struct GuardWriter<'a, W: fmt::Write> {
+    inner: &'a mut W,
+    written: usize,
+    color_limit: Option<usize>,
+    max_output: Option<usize>,
+    colored: bool,
+    // Whether an escape sequence is in progress, and if so whether it is
+    // being kept.
+    escape: Option<bool>,
+    pub(crate) exceeded: bool,
+}
+
+impl<'a, W: fmt::Write> GuardWriter<'a, W> {
+    pub(crate) fn new(
+        inner: &'a mut W,
+        color_limit: Option<usize>,
+        max_output: Option<usize>,
+    ) -> Self {
+        GuardWriter {
+            inner,
+            written: 0,
+            color_limit,
+            max_output,
+            colored: false,
+            escape: None,
+            exceeded: false,
+        }
+    }
+
+    fn emit(&mut self, s: &str) -> fmt::Result {
+        if self
+            .max_output
+            .map_or(false, |n| self.written + s.len() > n)
+        {
+            self.exceeded = true;
+            return Err(fmt::Error);
+        }
+        self.written += s.len();
+        self.inner.write_str(s)
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for GuardWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.color_limit.is_none() {
+            return self.emit(s);
+        }
+        let mut out = String::with_capacity(s.len());
+        for ch in s.chars() {
+            let sampled = self
+                .color_limit
+                .map_or(false, |n| self.written + out.len() >= n);
+            if let Some(keep) = self.escape {
+                if keep {
+                    out.push(ch);
+                }
+                // ANSI escapes end with a letter (e.g. `\x1b[1;31m`).
+                if ch.is_ascii_alphabetic() {
+                    self.escape = None;
+                }
+            } else if ch == '\x1b' {
+                // Escapes started within the sample are kept whole, even if
+                // they are split across writes.
+                self.escape = Some(!sampled);
+                if !sampled {
+                    self.colored = true;
+                    out.push(ch);
+                }
+            } else {
+                if sampled && self.colored {
+                    // Reset any color in effect where colorization stopped.
+                    self.colored = false;
+                    out.push_str("\x1b[0m");
+                }
+                out.push(ch);
+            }
+        }
+        self.emit(&out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::fmt::Write;
+
+    #[test]
+    fn test_guard_writer() -> Result<()> {
+        let mut out = String::new();
+        let mut w = GuardWriter::new(&mut out, Some(8), None);
+        write!(w, "\x1b[31mred\x1b[0m \x1b[1;32mgreen\x1b[0m")?;
+        write!(w, " \x1b[34mblue\x1b[0m")?;
+        assert_eq!(out, "\x1b[31mred\x1b[0m green blue");
+
+        let mut out = String::new();
+        let mut w = GuardWriter::new(&mut out, None, Some(8));
+        assert!(write!(w, "12345").is_ok());
+        assert!(write!(w, "6789").is_err());
+        assert!(w.exceeded);
+        assert_eq!(out, "12345");
+        Ok(())
+    }
+}
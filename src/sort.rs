@@ -0,0 +1,178 @@
+// Emit-time ordering of mapping entries.
+use crate::document::Document;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// A comparator for mapping keys.
+pub type KeyComparator = Arc<dyn Fn(&str, &str) -> Ordering + Send + Sync>;
+
+/// The order in which emitters write the entries of a mapping.
+#[derive(Clone, Default)]
+pub enum KeySort {
+    /// Entries are written in document order.
+    #[default]
+    None,
+    /// Entries are sorted by key in byte-wise lexical order.
+    Alphabetical,
+    /// Entries are sorted by key, comparing runs of digits numerically
+    /// (e.g. `item2` sorts before `item10`).
+    Natural,
+    /// Entries are sorted by key with a user-supplied comparator.
+    Custom(KeyComparator),
+}
+
+impl KeySort {
+    /// Creates a `KeySort` which orders keys with the comparator `f`.
+    pub fn custom<F: Fn(&str, &str) -> Ordering + Send + Sync + 'static>(f: F) -> Self {
+        KeySort::Custom(Arc::new(f))
+    }
+
+    /// Compares two keys according to this ordering.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            KeySort::None => Ordering::Equal,
+            KeySort::Alphabetical => a.cmp(b),
+            KeySort::Natural => natural_cmp(a, b),
+            KeySort::Custom(f) => f(a, b),
+        }
+    }
+
+    /// Returns the entries of `mapping` in emit order.
+    ///
+    /// Each entry is moved as a unit, so comments attached to an entry stay
+    /// with it.  Entries without a value (e.g. comments trailing the last
+    /// entry) keep their relative order after all of the sorted entries.
+    pub(crate) fn order<'a>(&self, mapping: &'a [Document]) -> Vec<&'a Document> {
+        let mut entries = mapping.iter().collect::<Vec<_>>();
+        if let KeySort::None = self {
+            return entries;
+        }
+        entries.sort_by(|a, b| match (key_of(a), key_of(b)) {
+            (Some(a), Some(b)) => self.compare(&a, &b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+        entries
+    }
+}
+
+// Returns the text of a mapping entry's key.
+fn key_of(entry: &Document) -> Option<String> {
+    let (k, _) = entry.as_kv().ok()?;
+    match k {
+        Document::String(s, _) => Some(s.clone()),
+        Document::StaticStr(s, _) => Some(s.to_string()),
+        Document::Int(v) => Some(v.to_string()),
+        Document::Boolean(v) => Some(v.to_string()),
+        Document::Float(v) => Some(v.to_string()),
+        _ => None,
+    }
+}
+
+// Compares strings treating runs of ASCII digits as numbers.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Less,
+            (false, true) => return Ordering::Greater,
+            _ => {}
+        }
+        let (ca, ra) = chunk(a);
+        let (cb, rb) = chunk(b);
+        let digits = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+        let ord = if digits(ca) && digits(cb) {
+            let (za, zb) = (ca.trim_start_matches('0'), cb.trim_start_matches('0'));
+            za.len().cmp(&zb.len()).then_with(|| za.cmp(zb))
+        } else {
+            ca.cmp(cb)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        a = ra;
+        b = rb;
+    }
+}
+
+// Splits off the leading run of digits or non-digits.
+fn chunk(s: &str) -> (&str, &str) {
+    let digit = s.starts_with(|c: char| c.is_ascii_digit());
+    let end = s
+        .find(|c: char| c.is_ascii_digit() != digit)
+        .unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    const SAMPLE: &str = r#"{
+        item10: 1,
+        // Second item.
+        item2: 2,
+        Item3: 3
+    }"#;
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("item2", "item10"), Ordering::Less);
+        assert_eq!(natural_cmp("item010", "item10"), Ordering::Equal);
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("b", "a1"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_sort_keys_json() -> Result<()> {
+        let doc = Document::parse(SAMPLE)?;
+        assert_eq!(
+            doc.clone()
+                .to_json5()
+                .sort_keys(KeySort::Alphabetical)
+                .to_text()?,
+            r#"{
+  Item3: 3,
+  item10: 1,
+  // Second item.
+  item2: 2
+}"#
+        );
+        assert_eq!(
+            doc.clone()
+                .to_json5()
+                .sort_keys(KeySort::Natural)
+                .to_text()?,
+            r#"{
+  Item3: 3,
+  // Second item.
+  item2: 2,
+  item10: 1
+}"#
+        );
+        let caseless = KeySort::custom(|a, b| natural_cmp(&a.to_lowercase(), &b.to_lowercase()));
+        assert_eq!(
+            doc.to_json5().compact(true).sort_keys(caseless).to_text()?,
+            "{item2: 2, Item3: 3, item10: 1}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_keys_yaml() -> Result<()> {
+        let doc = Document::parse(SAMPLE)?;
+        assert_eq!(
+            doc.to_yaml().sort_keys(KeySort::Natural).to_text()?,
+            r#"---
+Item3: 3
+# Second item.
+item2: 2
+item10: 1"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+// Positions of emitted nodes in the output text.
+use crate::path::{pointer, segments};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+/// A position in emitted text.  All fields are zero-based; the column
+/// counts characters from the start of the line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The range of emitted text occupied by a node, from the position of its
+/// first character to the position just past its last.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// A map from the paths of emitted nodes to their location in the output.
+///
+/// Paths are JSON Pointers through mapping keys and sequence indices
+/// (e.g. `/server/ports/0`), in which `~1` and `~0` stand for a `/` and a
+/// `~` within a key; the root of the document has the empty path.
+/// Byte offsets index the emitted string, including any newline
+/// translation and color escapes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    spans: Vec<(String, Span)>,
+}
+
+impl SourceMap {
+    /// Returns the span of the node at `path`, if it was emitted.
+    pub fn get(&self, path: &str) -> Option<&Span> {
+        let path = pointer(&segments(path).collect::<Vec<_>>());
+        self.spans.iter().find(|(p, _)| *p == path).map(|(_, s)| s)
+    }
+
+    /// Returns the path of the innermost node whose span contains the byte
+    /// `offset`, e.g. to relate an error in a later stage to the document.
+    pub fn path_at(&self, offset: usize) -> Option<&str> {
+        self.spans
+            .iter()
+            .filter(|(_, s)| s.start.offset <= offset && offset < s.end.offset)
+            .max_by_key(|(p, _)| p.len())
+            .map(|(p, _)| p.as_str())
+    }
+
+    /// Returns each path and span in the order the nodes were emitted.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Span)> {
+        self.spans.iter().map(|(p, s)| (p.as_str(), s))
+    }
+
+    /// Returns the number of nodes in the map.
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Returns whether the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+}
+
+// Records the spans of nodes as an emitter writes them.  The current
+// position is advanced by a `TrackingWriter` at the end of the writer chain.
+#[derive(Default)]
+pub(crate) struct Tracker {
+    position: Cell<Position>,
+    spans: RefCell<Vec<(String, Span)>>,
+}
+
+impl Tracker {
+    pub(crate) fn position(&self) -> Position {
+        self.position.get()
+    }
+
+    pub(crate) fn record(&self, path: &[String], start: Position) {
+        let p = pointer(path);
+        let span = Span {
+            start,
+            end: self.position(),
+        };
+        self.spans.borrow_mut().push((p, span));
+    }
+
+    pub(crate) fn into_map(self) -> SourceMap {
+        // Children are recorded before their parents; list nodes in the
+        // order they begin.
+        let mut spans = self.spans.into_inner();
+        spans.sort_by_key(|(_, s)| s.start.offset);
+        SourceMap { spans }
+    }
+}
+
+// A writer which advances the position of a `Tracker`.
+pub(crate) struct TrackingWriter<'a, W: fmt::Write> {
+    inner: &'a mut W,
+    tracker: &'a Tracker,
+}
+
+impl<'a, W: fmt::Write> TrackingWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W, tracker: &'a Tracker) -> Self {
+        TrackingWriter { inner, tracker }
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for TrackingWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_str(s)?;
+        let mut pos = self.tracker.position();
+        pos.offset += s.len();
+        match s.rfind('\n') {
+            Some(i) => {
+                pos.line += s.matches('\n').count();
+                pos.column = s[i + 1..].chars().count();
+            }
+            None => pos.column += s.chars().count(),
+        }
+        self.tracker.position.set(pos);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::newline::Newline;
+    use anyhow::Result;
+
+    #[test]
+    fn test_source_map() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // Where to listen.
+                server: {host: "localhost", ports: [80, 443]},
+                name: "app",
+                "a/b~c": 1,
+            }"#,
+        )?;
+        let (text, map) = doc.clone().to_json5().emit_with_map()?;
+        let at = |path: &str| {
+            let span = map.get(path).unwrap();
+            &text[span.start.offset..span.end.offset]
+        };
+        assert_eq!(at(""), text);
+        assert_eq!(at("/name"), r#""app""#);
+        assert_eq!(at("/server/host"), r#""localhost""#);
+        assert_eq!(at("/server/ports/1"), "443");
+        // `/` and `~` within a key are escaped.
+        assert_eq!(at("/a~1b~0c"), "1");
+        assert!(at("/server").starts_with('{') && at("/server").ends_with('}'));
+        assert_eq!(
+            map.get("/server/ports/1").unwrap().start,
+            Position {
+                offset: text.find("443").unwrap(),
+                line: 6,
+                column: 6,
+            }
+        );
+        assert_eq!(
+            map.path_at(text.find("443").unwrap()),
+            Some("/server/ports/1")
+        );
+        assert_eq!(map.iter().next().map(|(p, _)| p), Some(""));
+        assert_eq!(map.len(), 8);
+
+        // Offsets follow newline translation.
+        let (text, map) = doc.to_json5().newline(Newline::CrLf).emit_with_map()?;
+        let span = map.get("/name").unwrap();
+        assert_eq!(&text[span.start.offset..span.end.offset], r#""app""#);
+        assert_eq!(span.start.line, 9);
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+// Incremental emission of sequences too large to hold in memory.
+use std::io;
+
+use serde::ser::Serialize;
+
+use crate::document::Document;
+use crate::error::Error;
+use crate::json::{Json, JsonOptions};
+use crate::ser::serialize;
+
+/// Emits a JSON sequence to a writer one element at a time, so that a
+/// sequence never needs to be held in memory as a whole.
+///
+/// Each element is serialized into a `Document` and rendered with the
+/// writer's `JsonOptions` as it is added, exactly as it would appear in a
+/// sequence emitted by `Json`.  The `pack_width` and `compact_threshold`
+/// options, which depend on the whole sequence, do not apply to the
+/// streamed sequence itself.
+///
+/// ```
+/// # use serde_annotate::{JsonOptions, JsonStreamWriter};
+/// let mut out = Vec::new();
+/// let mut stream = JsonStreamWriter::new(&mut out, JsonOptions::json5_default());
+/// stream.begin_seq()?;
+/// for i in 0..3 {
+///     stream.element(&i)?;
+/// }
+/// stream.end()?;
+/// assert_eq!(String::from_utf8(out).unwrap(), "[\n  0,\n  1,\n  2\n]");
+/// # Ok::<(), serde_annotate::Error>(())
+/// ```
+pub struct JsonStreamWriter<W: io::Write> {
+    writer: W,
+    json: Json,
+    count: usize,
+    open: bool,
+    quoteless: bool,
+}
+
+impl<W: io::Write> JsonStreamWriter<W> {
+    /// Creates a stream writer which emits to `writer` using `options`.
+    /// Unbuffered writers such as files should be wrapped in a `BufWriter`.
+    pub fn new(writer: W, options: JsonOptions) -> Self {
+        JsonStreamWriter {
+            writer,
+            json: Document::Null.to_json().options(options),
+            count: 0,
+            open: false,
+            quoteless: false,
+        }
+    }
+
+    /// Begins a sequence.  It is an error to begin a sequence before the
+    /// previous one has ended.
+    pub fn begin_seq(&mut self) -> Result<(), Error> {
+        if self.open {
+            return Err(Error::StreamError("sequence already begun"));
+        }
+        self.writer.write_all(self.json.stream_open().as_bytes())?;
+        self.open = true;
+        self.count = 0;
+        self.quoteless = false;
+        Ok(())
+    }
+
+    /// Serializes `value` and emits it as the next element of the sequence.
+    pub fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let doc = serialize(value)?;
+        self.document(&doc)
+    }
+
+    /// Emits an already built `Document` as the next element of the
+    /// sequence.
+    pub fn document(&mut self, doc: &Document) -> Result<(), Error> {
+        if !self.open {
+            return Err(Error::StreamError("sequence not begun"));
+        }
+        let (text, quoteless) = self.json.stream_element(self.count, doc, self.quoteless)?;
+        self.writer.write_all(text.as_bytes())?;
+        self.count += 1;
+        self.quoteless = quoteless;
+        Ok(())
+    }
+
+    /// Ends the sequence and flushes the writer.
+    pub fn end(&mut self) -> Result<(), Error> {
+        if !self.open {
+            return Err(Error::StreamError("sequence not begun"));
+        }
+        let text = self.json.stream_close(self.count, self.quoteless);
+        self.writer.write_all(text.as_bytes())?;
+        self.open = false;
+        Ok(self.writer.flush()?)
+    }
+
+    /// Returns the number of elements emitted in the current (or last)
+    /// sequence.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns whether no elements have been emitted in the current (or
+    /// last) sequence.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+    use serde_derive::Serialize;
+
+    #[derive(Serialize)]
+    struct Sample {
+        t: u32,
+        value: f64,
+    }
+
+    fn stream(options: JsonOptions, n: u32) -> Result<String> {
+        let mut stream = JsonStreamWriter::new(Vec::new(), options);
+        stream.begin_seq()?;
+        for t in 0..n {
+            stream.element(&Sample {
+                t,
+                value: t as f64 / 2.0,
+            })?;
+        }
+        stream.end()?;
+        Ok(String::from_utf8(stream.into_inner())?)
+    }
+
+    #[test]
+    fn test_stream_writer() -> Result<()> {
+        // The streamed output matches the output for the whole sequence.
+        for options in [
+            JsonOptions::strict_default(),
+            JsonOptions::json5_default().trailing_comma(true),
+            JsonOptions::strict_default().compact(true),
+            JsonOptions::hjson_default(),
+        ] {
+            for n in [0, 1, 3] {
+                let samples = (0..n)
+                    .map(|t| Sample {
+                        t,
+                        value: t as f64 / 2.0,
+                    })
+                    .collect::<Vec<_>>();
+                let whole = serialize(&samples)?
+                    .to_json()
+                    .options(options.clone())
+                    .to_text()?;
+                assert_eq!(stream(options.clone(), n)?, whole);
+            }
+        }
+
+        let mut stream = JsonStreamWriter::new(Vec::new(), JsonOptions::strict_default());
+        assert!(matches!(stream.element(&1), Err(Error::StreamError(_))));
+        stream.begin_seq()?;
+        assert!(matches!(stream.begin_seq(), Err(Error::StreamError(_))));
+        stream.element(&1)?;
+        stream.end()?;
+        assert_eq!(stream.len(), 1);
+        assert!(stream.end().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+// Structural identity of documents, for finding repeated nodes.
+use crate::document::Document;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem::discriminant;
+
+// A set of documents compared by their structure: their values along with
+// their formats, comments and annotations.  Floats are compared by their
+// bits.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StructuralSet(HashMap<u64, Vec<Document>>);
+
+impl StructuralSet {
+    pub(crate) fn insert(&mut self, node: &Document) {
+        let nodes = self.0.entry(hash(node)).or_default();
+        if !nodes.iter().any(|n| same(n, node)) {
+            nodes.push(node.clone());
+        }
+    }
+
+    pub(crate) fn contains(&self, node: &Document) -> bool {
+        match self.0.get(&hash(node)) {
+            Some(nodes) => nodes.iter().any(|n| same(n, node)),
+            None => false,
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a Document> for StructuralSet {
+    fn from_iter<I: IntoIterator<Item = &'a Document>>(iter: I) -> Self {
+        let mut set = StructuralSet::default();
+        for node in iter {
+            set.insert(node);
+        }
+        set
+    }
+}
+
+// Returns the structural hash of `node`.
+pub(crate) fn hash(node: &Document) -> u64 {
+    node_hash(node, &mut hash)
+}
+
+// Returns the structural hash of every node in `doc`, by address.  Each
+// node is hashed from the hashes of its children, so the whole document is
+// hashed in linear time.
+pub(crate) fn hashes(doc: &Document) -> HashMap<usize, u64> {
+    fn visit(node: &Document, hashes: &mut HashMap<usize, u64>) -> u64 {
+        let hash = node_hash(node, &mut |child| visit(child, hashes));
+        hashes.insert(node as *const Document as usize, hash);
+        hash
+    }
+    let mut hashes = HashMap::new();
+    visit(doc, &mut hashes);
+    hashes
+}
+
+// Hashes the content of `node`, taking the hash of each child from `child`.
+fn node_hash(node: &Document, child: &mut dyn FnMut(&Document) -> u64) -> u64 {
+    let mut h = DefaultHasher::new();
+    discriminant(node).hash(&mut h);
+    match node {
+        Document::Comment(c, f) => (c, f).hash(&mut h),
+        Document::String(s, f) => (s, f).hash(&mut h),
+        Document::StaticStr(s, f) => (s, f).hash(&mut h),
+        Document::Boolean(b) => b.hash(&mut h),
+        Document::Int(i) => i.hash(&mut h),
+        Document::Float(f) => f.to_bits().hash(&mut h),
+        Document::Bytes(b) => b.hash(&mut h),
+        Document::Null | Document::Absent => {}
+        Document::Mapping(c) | Document::Sequence(c) | Document::Fragment(c) => {
+            c.len().hash(&mut h);
+            for node in c {
+                child(node).hash(&mut h);
+            }
+        }
+        Document::Compact(d) => child(d).hash(&mut h),
+        Document::ColorClass(c, d) => (c, child(d)).hash(&mut h),
+        Document::Identified(id, d) => (id, child(d)).hash(&mut h),
+        Document::Typed(t, d) => (t, child(d)).hash(&mut h),
+    }
+    h.finish()
+}
+
+// Returns whether `a` and `b` have the same structure.
+pub(crate) fn same(a: &Document, b: &Document) -> bool {
+    let all = |a: &[Document], b: &[Document]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+    };
+    match (a, b) {
+        (Document::Comment(a, f), Document::Comment(b, g)) => a == b && f == g,
+        (Document::String(a, f), Document::String(b, g)) => a == b && f == g,
+        (Document::StaticStr(a, f), Document::StaticStr(b, g)) => a == b && f == g,
+        (Document::Boolean(a), Document::Boolean(b)) => a == b,
+        (Document::Int(a), Document::Int(b)) => a == b,
+        (Document::Float(a), Document::Float(b)) => a.to_bits() == b.to_bits(),
+        (Document::Bytes(a), Document::Bytes(b)) => a == b,
+        (Document::Null, Document::Null) | (Document::Absent, Document::Absent) => true,
+        (Document::Mapping(a), Document::Mapping(b))
+        | (Document::Sequence(a), Document::Sequence(b))
+        | (Document::Fragment(a), Document::Fragment(b)) => all(a, b),
+        (Document::Compact(a), Document::Compact(b)) => same(a, b),
+        (Document::ColorClass(c, a), Document::ColorClass(d, b)) => c == d && same(a, b),
+        (Document::Identified(i, a), Document::Identified(j, b)) => i == j && same(a, b),
+        (Document::Typed(s, a), Document::Typed(t, b)) => s == t && same(a, b),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_structural() -> Result<()> {
+        let doc = Document::parse(
+            r#"[
+                {a: 1, b: [0x10, "x"]},
+                {a: 1, b: [0x10, "x"]},
+                {a: 1, b: [16, "x"]},
+                {a: 1.0, b: [0x10, "x"]},
+            ]"#,
+        )?;
+        let items = match &doc {
+            Document::Sequence(items) => items,
+            _ => unreachable!(),
+        };
+        let set = items.iter().collect::<StructuralSet>();
+        assert_eq!(set.0.values().map(Vec::len).sum::<usize>(), 3);
+        assert!(set.contains(&items[1]));
+        assert!(same(&items[0], &items[1]));
+        // A base or a type is part of the structure.
+        assert!(!same(&items[0], &items[2]));
+        assert!(!same(&items[0], &items[3]));
+
+        // Hashing the document at once agrees with hashing each node.
+        let hashes = hashes(&doc);
+        for item in items {
+            assert_eq!(hashes[&(item as *const Document as usize)], hash(item));
+        }
+        assert_eq!(hash(&items[0]), hash(&items[1]));
+        Ok(())
+    }
+}
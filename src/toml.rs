@@ -0,0 +1,746 @@
+use crate::color::ColorProfile;
+use crate::document::{BytesFormat, CommentFormat, Document, StrFormat};
+use crate::emit::{common_options, guarded, CommonOptions, Emit};
+use crate::entry::{comments_of, MapEntry, SeqItem};
+use crate::error::Error;
+use crate::hexdump;
+use crate::integer::{Base, Int};
+use crate::json::DigitGrouping;
+use crate::layout::TokenClass;
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use crate::render::{float_literal, write_bytes, write_indent, write_quoted, ByteArray};
+use crate::sort::KeySort;
+use std::fmt;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A TOML document and its formatting properties.
+///
+/// The document must be a mapping.  Mappings are emitted as `[table]`
+/// sections and sequences of mappings as `[[array-of-tables]]` sections,
+/// while mappings marked with `Document::Compact` are emitted as inline
+/// tables.  TOML has no null: mapping entries whose value is null are
+/// omitted, and null elsewhere fails with `Error::Unrepresentable`.
+pub struct Toml {
+    document: Document,
+    common: CommonOptions,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    digit_grouping: DigitGrouping,
+}
+
+impl Toml {
+    common_options!(
+        /// Set the amount of indentation of the elements of arrays which span
+        /// several lines.
+        indent,
+        /// Set whether all mappings below the document are emitted as inline
+        /// tables and all arrays on a single line.
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        max_depth,
+        /// Set the order in which mapping entries are emitted.  Entries whose
+        /// values are emitted as tables follow the others regardless, as TOML
+        /// requires.
+        sort_keys,
+        newline,
+        ascii_comments,
+    );
+    /// Set the rendering of bytes values: an array of integers
+    /// (`BytesFormat::Standard`) or a string in the given format.  Bytes
+    /// annotated with a format are rendered as strings regardless.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.bytes_format = b;
+        self
+    }
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.  TOML has no
+    /// hexadecimal floats, so `FloatFormat::Hex` renders as
+    /// `FloatFormat::Shortest`.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+    /// Set the insertion of digit group separators in integers.  TOML
+    /// never quotes integers, so only `DigitGrouping::Literals` applies.
+    pub fn digit_grouping(mut self, g: DigitGrouping) -> Self {
+        self.digit_grouping = g;
+        self
+    }
+
+    fn emit<W: fmt::Write>(&self, w: &mut W) -> Result<()> {
+        self.common.check_comments(&self.document)?;
+        let mut emitter = TomlEmitter {
+            level: 0,
+            indent: self.common.indent,
+            color: self.common.color.clone(),
+            compact: self.common.compact,
+            sort_keys: self.common.sort_keys.clone(),
+            bytes_format: self.bytes_format,
+            numbers: self.numbers.clone(),
+            float_format: self.float_format,
+            digit_grouping: self.digit_grouping,
+            depth: 0,
+            max_depth: self.common.max_depth,
+            started: false,
+        };
+        emitter.emit_document(w, &self.document)
+    }
+}
+
+impl Emit for Toml {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.common, w, |mut w| self.emit(&mut w))
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.common
+    }
+}
+
+impl Document {
+    pub fn to_toml(self) -> Toml {
+        Toml {
+            document: self,
+            common: CommonOptions::default(),
+            bytes_format: BytesFormat::Standard,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+            digit_grouping: DigitGrouping::None,
+        }
+    }
+}
+
+// Returns the single value among `nodes`, if there is one.
+fn single_value(nodes: &[Document]) -> Option<&Document> {
+    let mut values = nodes.iter().filter(|n| n.has_value());
+    match (values.next(), values.next()) {
+        (Some(v), None) => Some(v),
+        _ => None,
+    }
+}
+
+// Returns the entries of `node` if it is a mapping to be emitted as a table
+// rather than inline.
+fn table(node: &Document) -> Option<&[Document]> {
+    match node {
+        Document::Mapping(m) => Some(m),
+        Document::ColorClass(_, d) | Document::Identified(_, d) | Document::Typed(_, d) => table(d),
+        Document::Fragment(ds) => table(single_value(ds)?),
+        _ => None,
+    }
+}
+
+// Returns the elements of `node` if it is a non-empty sequence of mappings
+// to be emitted as an array of tables.
+fn array_of_tables(node: &Document) -> Option<&[Document]> {
+    match node {
+        Document::Sequence(s) => {
+            let mut values = s.iter().filter(|e| e.has_value()).peekable();
+            let tables = values.peek().is_some() && values.all(|e| table(e).is_some());
+            tables.then_some(&s[..])
+        }
+        Document::ColorClass(_, d) | Document::Identified(_, d) | Document::Typed(_, d) => {
+            array_of_tables(d)
+        }
+        Document::Fragment(ds) => array_of_tables(single_value(ds)?),
+        _ => None,
+    }
+}
+
+// Returns whether `key` may be written without quotes.
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+struct TomlEmitter {
+    level: usize,
+    indent: usize,
+    color: ColorProfile,
+    compact: bool,
+    sort_keys: KeySort,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    digit_grouping: DigitGrouping,
+    depth: usize,
+    max_depth: Option<usize>,
+    // Whether anything has been written, so that the tables after the
+    // first line are separated by an empty line.
+    started: bool,
+}
+
+impl TomlEmitter {
+    fn emit_document<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        match node {
+            Document::Mapping(m) => self.emit_table(w, &mut Vec::new(), m),
+            Document::Compact(d)
+            | Document::ColorClass(_, d)
+            | Document::Identified(_, d)
+            | Document::Typed(_, d) => self.emit_document(w, d),
+            Document::Fragment(ds) => {
+                for d in ds {
+                    match d.comment() {
+                        Some((c, _)) => self.emit_comment(w, c)?,
+                        None => self.emit_document(w, d)?,
+                    }
+                }
+                Ok(())
+            }
+            Document::Absent => Err(Error::AbsentValue),
+            v => Err(Error::StructureError("Mapping", v.variant())),
+        }
+    }
+
+    // Returns whether a mapping entry is emitted as a table or an array of
+    // tables.
+    fn is_table_entry(&self, entry: &Document) -> bool {
+        !self.compact
+            && entry.as_kv().map_or(false, |(_, v)| {
+                table(v).is_some() || array_of_tables(v).is_some()
+            })
+    }
+
+    // Emits the body of a table: its key/value pairs, followed by its
+    // tables and arrays of tables.
+    fn emit_table<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        path: &mut Vec<String>,
+        mapping: &[Document],
+    ) -> Result<()> {
+        match self.max_depth {
+            Some(n) if self.depth >= n => return Err(Error::DepthLimit(n)),
+            _ => {}
+        }
+        self.depth += 1;
+        let mut entries = self.sort_keys.order(mapping);
+        entries.retain(|e| !e.is_null_entry());
+        let (tables, plain): (Vec<_>, Vec<_>) =
+            entries.into_iter().partition(|e| self.is_table_entry(e));
+        for entry in plain {
+            self.emit_entry(w, entry)?;
+        }
+        for entry in tables {
+            self.emit_table_entry(w, path, entry)?;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    // Emits a `key = value` line along with its comments.  Comments after
+    // the value are emitted at the end of the line if there is only one.
+    fn emit_entry<W: fmt::Write>(&mut self, w: &mut W, entry: &Document) -> Result<()> {
+        let entry = match MapEntry::new(entry) {
+            Ok(entry) => entry,
+            Err(_) => {
+                for (c, _) in comments_of(entry) {
+                    self.emit_comment(w, c)?;
+                }
+                return Ok(());
+            }
+        };
+        for (c, _) in entry.comments {
+            self.emit_comment(w, c)?;
+        }
+        self.emit_key(w, entry.key)?;
+        write!(w, " {} ", self.color.punctuation.paint("="))?;
+        self.emit_value(w, entry.value)?;
+        self.started = true;
+        self.emit_trailing(w, &entry.trailing)
+    }
+
+    // Ends the line of a value, placing the comments after the value at the
+    // end of the line if there is only one.
+    fn emit_trailing<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        trailing: &[(&str, CommentFormat)],
+    ) -> Result<()> {
+        match trailing {
+            [(c, _)] if !c.contains('\n') => {
+                writeln!(w, " {}", self.color.comment.paint(format!("# {}", c)))?;
+            }
+            _ => {
+                writeln!(w)?;
+                for (c, _) in trailing {
+                    self.emit_comment(w, c)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Emits a mapping entry whose value is a table or an array of tables.
+    fn emit_table_entry<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        path: &mut Vec<String>,
+        entry: &Document,
+    ) -> Result<()> {
+        let (key, value) = entry.as_kv()?;
+        let mut comments = entry
+            .fragments()?
+            .iter()
+            .filter_map(Document::comment)
+            .map(|(c, _)| c)
+            .collect::<Vec<_>>();
+        let mut text = String::new();
+        self.emit_key(&mut text, key)?;
+        path.push(text);
+        if let Some(m) = table(value) {
+            // The header of a table holding only tables may be left out.
+            let implicit = comments.is_empty()
+                && m.iter().any(|e| self.is_table_entry(e))
+                && m.iter()
+                    .all(|e| self.is_table_entry(e) || e.is_null_entry());
+            if !implicit {
+                self.emit_header(w, path, false, &comments)?;
+            }
+            self.emit_table(w, path, m)?;
+        } else if let Some(s) = array_of_tables(value) {
+            for element in s.iter().filter(|e| e.has_value()) {
+                if let Document::Fragment(ds) = element {
+                    comments.extend(ds.iter().filter_map(Document::comment).map(|(c, _)| c));
+                }
+                self.emit_header(w, path, true, &comments)?;
+                comments.clear();
+                // Unwrap is ok: each element of an array of tables is a table.
+                self.emit_table(w, path, table(element).unwrap())?;
+            }
+        }
+        path.pop();
+        Ok(())
+    }
+
+    fn emit_header<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        path: &[String],
+        array: bool,
+        comments: &[&str],
+    ) -> Result<()> {
+        if self.started {
+            writeln!(w)?;
+        }
+        for c in comments {
+            self.emit_comment(w, c)?;
+        }
+        let (open, close) = if array { ("[[", "]]") } else { ("[", "]") };
+        write!(w, "{}", self.color.aggregate.paint(open))?;
+        for (i, key) in path.iter().enumerate() {
+            if i > 0 {
+                write!(w, "{}", self.color.punctuation.paint("."))?;
+            }
+            write!(w, "{}", key)?;
+        }
+        writeln!(w, "{}", self.color.aggregate.paint(close))?;
+        self.started = true;
+        Ok(())
+    }
+
+    fn emit_key<W: fmt::Write>(&mut self, w: &mut W, key: &Document) -> Result<()> {
+        let text = match key.as_value()? {
+            Document::String(s, _) => s.clone(),
+            Document::StaticStr(s, _) => s.to_string(),
+            Document::Int(i) => i.format(None),
+            Document::Boolean(b) => b.to_string(),
+            v => return Err(Error::KeyTypeError(v.variant())),
+        };
+        if is_bare_key(&text) {
+            write!(w, "{}", self.color.style(&TokenClass::BareKey).paint(text))?;
+        } else {
+            let color = self.color.key;
+            self.emit_quoted(w, &text, color, false)?;
+        }
+        Ok(())
+    }
+
+    fn emit_comment<W: fmt::Write>(&mut self, w: &mut W, comment: &str) -> Result<()> {
+        if self.compact && self.level > 0 {
+            return Ok(());
+        }
+        for line in comment.split('\n') {
+            self.emit_indent(w)?;
+            if line.is_empty() {
+                writeln!(w, "{}", self.color.comment.paint("#"))?;
+            } else {
+                writeln!(w, "{}", self.color.comment.paint(format!("# {}", line)))?;
+            }
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    fn emit_value<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        match node {
+            Document::Comment(_, _) => Ok(()),
+            Document::String(v, f) => self.emit_string(w, v, *f),
+            Document::StaticStr(v, f) => self.emit_string(w, v, *f),
+            Document::Boolean(b) => Ok(write!(w, "{}", self.color.boolean.paint(b.to_string()))?),
+            Document::Int(i) => self.emit_int(w, i),
+            Document::Float(f) => self.emit_float(w, *f),
+            Document::Mapping(m) => self.emit_inline_table(w, m),
+            Document::Sequence(s) => self.emit_array(w, s),
+            Document::Bytes(b) => self.emit_bytes(w, b),
+            Document::Null => Err(Error::Unrepresentable("null", "TOML")),
+            Document::Absent => Err(Error::AbsentValue),
+            Document::Compact(d) => {
+                let compact = std::mem::replace(&mut self.compact, true);
+                let result = self.emit_value(w, d);
+                self.compact = compact;
+                result
+            }
+            Document::ColorClass(c, d) => {
+                let color = self.color.for_class(c);
+                let saved = std::mem::replace(&mut self.color, color);
+                let result = self.emit_value(w, d);
+                self.color = saved;
+                result
+            }
+            Document::Identified(_, d) | Document::Typed(_, d) => self.emit_value(w, d),
+            // Comments within a value which is emitted inline are dropped.
+            Document::Fragment(_) => self.emit_value(w, node.as_value()?),
+        }
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        match self.max_depth {
+            Some(n) if self.depth >= n => Err(Error::DepthLimit(n)),
+            _ => {
+                self.depth += 1;
+                Ok(())
+            }
+        }
+    }
+
+    // Emits a mapping as an inline table, which must fit on one line.
+    fn emit_inline_table<W: fmt::Write>(&mut self, w: &mut W, mapping: &[Document]) -> Result<()> {
+        self.enter()?;
+        let compact = std::mem::replace(&mut self.compact, true);
+        let mut entries = self.sort_keys.order(mapping);
+        entries.retain(|e| e.as_kv().is_ok() && !e.is_null_entry());
+        write!(w, "{}", self.color.aggregate.paint("{"))?;
+        for (i, entry) in entries.iter().enumerate() {
+            if i > 0 {
+                write!(w, "{}", self.color.punctuation.paint(","))?;
+            }
+            // Unwrap is ok: only kvpairs were retained.
+            let (key, value) = entry.as_kv().unwrap();
+            write!(w, " ")?;
+            self.emit_key(w, key)?;
+            write!(w, " {} ", self.color.punctuation.paint("="))?;
+            self.emit_value(w, value)?;
+        }
+        if !entries.is_empty() {
+            write!(w, " ")?;
+        }
+        write!(w, "{}", self.color.aggregate.paint("}"))?;
+        self.compact = compact;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    // Emits a sequence as an array, with each element on its own line if
+    // any element carries a comment.
+    fn emit_array<W: fmt::Write>(&mut self, w: &mut W, sequence: &[Document]) -> Result<()> {
+        self.enter()?;
+        let commented = sequence.iter().any(|e| match e {
+            Document::Fragment(ds) => ds.iter().any(|d| d.comment().is_some()),
+            e => e.comment().is_some(),
+        });
+        if self.compact || !commented {
+            write!(w, "{}", self.color.aggregate.paint("["))?;
+            for (i, e) in sequence.iter().filter(|e| e.has_value()).enumerate() {
+                if i > 0 {
+                    write!(w, "{} ", self.color.punctuation.paint(","))?;
+                }
+                self.emit_value(w, e)?;
+            }
+            write!(w, "{}", self.color.aggregate.paint("]"))?;
+        } else {
+            writeln!(w, "{}", self.color.aggregate.paint("["))?;
+            self.level += 1;
+            for e in sequence {
+                let item = match SeqItem::new(e) {
+                    Ok(item) => item,
+                    Err(_) => {
+                        for (c, _) in comments_of(e) {
+                            self.emit_comment(w, c)?;
+                        }
+                        continue;
+                    }
+                };
+                for (c, _) in item.comments {
+                    self.emit_comment(w, c)?;
+                }
+                self.emit_indent(w)?;
+                self.emit_value(w, item.value)?;
+                write!(w, "{}", self.color.punctuation.paint(","))?;
+                self.emit_trailing(w, &item.trailing)?;
+            }
+            self.level -= 1;
+            self.emit_indent(w)?;
+            write!(w, "{}", self.color.aggregate.paint("]"))?;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn emit_bytes<W: fmt::Write>(&mut self, w: &mut W, bytes: &[u8]) -> Result<()> {
+        if let Some(string) = hexdump::to_string(bytes, self.bytes_format) {
+            return self.emit_string(w, &string, self.bytes_format.str_format());
+        }
+        let array = ByteArray {
+            open: "[",
+            close: "]",
+            separator: ",",
+            hex: true,
+        };
+        let indent = (!self.compact).then(|| (self.level * self.indent, self.indent));
+        write_bytes(w, &self.color, bytes, &array, indent)
+    }
+
+    fn emit_string<W: fmt::Write>(&mut self, w: &mut W, value: &str, f: StrFormat) -> Result<()> {
+        let color = self.color.string;
+        // A literal string holds no escapes, so it may not hold single
+        // quotes or control characters other than tab.
+        let literal = f == StrFormat::SingleQuoted
+            && !value
+                .chars()
+                .any(|c| c == '\'' || (c.is_control() && c != '\t'));
+        if literal {
+            let quote = self.color.punctuation.paint("'");
+            write!(w, "{}{}{}", quote, color.paint(value), quote)?;
+            Ok(())
+        } else {
+            let multiline = !self.compact && f.is_multiline() && value.contains('\n');
+            self.emit_quoted(w, value, color, multiline)
+        }
+    }
+
+    // Emits a basic string, or a multi-line basic string (`"""`) in which
+    // line breaks are written as they are.
+    fn emit_quoted<W: fmt::Write>(
+        &mut self,
+        w: &mut W,
+        value: &str,
+        color: ansi_term::Style,
+        multiline: bool,
+    ) -> Result<()> {
+        let quotes = if multiline {
+            ("\"\"\"\n", "\"\"\"")
+        } else {
+            ("\"", "\"")
+        };
+        write_quoted(w, &self.color, value, color, quotes, |ch| {
+            Some(match ch {
+                '"' => "\\\"".to_string(),
+                '\\' => "\\\\".to_string(),
+                '\n' if multiline => return None,
+                '\n' => "\\n".to_string(),
+                '\t' => "\\t".to_string(),
+                '\r' => "\\r".to_string(),
+                '\u{8}' => "\\b".to_string(),
+                '\u{c}' => "\\f".to_string(),
+                c if c.is_control() => format!("\\u{:04X}", c as u32),
+                _ => return None,
+            })
+        })
+    }
+
+    // Emits an integer.  TOML writes negative integers only in decimal.
+    fn emit_int<W: fmt::Write>(&mut self, w: &mut W, i: &Int) -> Result<()> {
+        let value =
+            i.as_i128()
+                .filter(|v| i64::try_from(*v).is_ok())
+                .ok_or(Error::Unrepresentable(
+                    "an integer outside the signed 64-bit range",
+                    "TOML",
+                ))?;
+        let ctx = NumberContext {
+            base: if value < 0 { Base::Dec } else { i.base() },
+            width: i.width(),
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        // The dialect has no integer type suffixes.
+        let i = &i.unsuffixed();
+        i.check_format(ctx.base)?;
+        let text = match self.numbers.format_int(i, &ctx) {
+            s if self.digit_grouping == DigitGrouping::Literals && ctx.base != Base::Dec => {
+                Int::group_digits(&s, ctx.base)
+            }
+            s => s,
+        };
+        write!(w, "{}", self.color.integer.paint(text))?;
+        Ok(())
+    }
+
+    fn emit_float<W: fmt::Write>(&mut self, w: &mut W, f: f64) -> Result<()> {
+        let text = if f.is_nan() {
+            "nan".to_string()
+        } else if f.is_infinite() {
+            if f > 0.0 { "inf" } else { "-inf" }.to_string()
+        } else {
+            float_literal(&*self.numbers, f, self.float_format, false)
+        };
+        write!(w, "{}", self.color.float.paint(text))?;
+        Ok(())
+    }
+
+    fn emit_indent<W: fmt::Write>(&mut self, w: &mut W) -> Result<()> {
+        write_indent(w, self.level * self.indent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn cargo_manifest() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // The package.
+                package: {name: "demo", version: "0.1.0", "rust-version": "1.70"},
+                dependencies: {
+                    serde: {version: "1.0", features: ["derive"]},
+                    anyhow: "1.0",
+                },
+                bin: [
+                    // The first binary.
+                    {name: "a", path: "src/a.rs"},
+                    {name: "b", path: "src/b.rs"},
+                ],
+                profile: {release: {lto: true, "opt-level": 3}},
+            }"#,
+        )?;
+        // Mark the `serde` dependency to be emitted as an inline table.
+        let doc = match doc {
+            Document::Mapping(mut m) => {
+                let (_, deps) = m[1].as_kv_mut()?;
+                let serde = match deps.as_value_mut()? {
+                    Document::Mapping(d) => d[0].as_kv_mut()?.1,
+                    _ => unreachable!(),
+                };
+                *serde = Document::Compact(Box::new(serde.clone()));
+                Document::Mapping(m)
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            doc.to_toml().to_text()?,
+            r#"# The package.
+[package]
+name = "demo"
+version = "0.1.0"
+rust-version = "1.70"
+
+[dependencies]
+serde = { version = "1.0", features = ["derive"] }
+anyhow = "1.0"
+
+# The first binary.
+[[bin]]
+name = "a"
+path = "src/a.rs"
+
+[[bin]]
+name = "b"
+path = "src/b.rs"
+
+[profile.release]
+lto = true
+opt-level = 3
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn values() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                hex: 0xFF,
+                octal: 0o17,
+                binary: 0b1010,
+                negative: -0x6,
+                ratio: 1.0,
+                nan: NaN,
+                "key with space": "a \"quoted\"\ttab",
+                literal: 'say "hi"',
+                ports: [
+                    // HTTP.
+                    80,
+                    443, // HTTPS.
+                ],
+                nothing: null,
+                empty: {},
+            }"#,
+        )?;
+        assert_eq!(
+            doc.to_toml().to_text()?,
+            r#"hex = 0xFF
+octal = 0o17
+binary = 0b1010
+negative = -6
+ratio = 1.0
+nan = nan
+"key with space" = "a \"quoted\"\ttab"
+literal = 'say "hi"'
+ports = [
+  # HTTP.
+  80,
+  443, # HTTPS.
+]
+
+[empty]
+"#
+        );
+
+        let doc = Document::parse("{text: \"one\\ntwo\"}")?;
+        let doc = match doc {
+            Document::Mapping(mut m) => {
+                let (_, v) = m[0].as_kv_mut()?;
+                let text = v.as_str()?.to_string();
+                *v = Document::String(text, StrFormat::Multiline);
+                Document::Mapping(m)
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(doc.to_toml().to_text()?, "text = \"\"\"\none\ntwo\"\"\"\n");
+
+        let doc = Document::parse("{addr: 0xFFF01000, size: 65536}")?;
+        assert_eq!(
+            doc.to_toml()
+                .digit_grouping(DigitGrouping::Literals)
+                .to_text()?,
+            "addr = 0xFFF0_1000\nsize = 65536\n"
+        );
+
+        assert!(matches!(
+            Document::parse("{a: [null]}")?
+                .to_toml()
+                .to_writer(&mut Vec::new()),
+            Err(Error::Unrepresentable("null", "TOML"))
+        ));
+        assert!(matches!(
+            Document::parse("[1]")?.to_toml().to_writer(&mut Vec::new()),
+            Err(Error::StructureError("Mapping", "Sequence"))
+        ));
+        Ok(())
+    }
+}
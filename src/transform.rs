@@ -0,0 +1,565 @@
+// Structural transformations of whole documents.
+use crate::document::{CommentFormat, Document};
+use crate::entry::{is_entry, kv};
+use crate::error::Error;
+use crate::path::{children, children_mut, segments, selects_entry, selects_index};
+use std::cmp::Ordering;
+
+// Splits a node into its leading comments, value and trailing comments.
+fn split(node: Document) -> (Vec<Document>, Option<Document>, Vec<Document>) {
+    let mut leading = Vec::new();
+    let mut value = None;
+    let mut trailing = Vec::new();
+    match node {
+        Document::Fragment(nodes) if nodes.iter().filter(|n| n.has_value()).count() <= 1 => {
+            for n in nodes {
+                if n.has_value() {
+                    value = Some(n);
+                } else if value.is_some() {
+                    trailing.push(n);
+                } else {
+                    leading.push(n);
+                }
+            }
+        }
+        n if n.has_value() => value = Some(n),
+        n => leading.push(n),
+    }
+    (leading, value, trailing)
+}
+
+fn join(mut leading: Vec<Document>, value: Document, trailing: Vec<Document>) -> Document {
+    if leading.is_empty() && trailing.is_empty() {
+        value
+    } else {
+        leading.push(value);
+        leading.extend(trailing);
+        Document::Fragment(leading)
+    }
+}
+
+// Collects the mappings at `path` beneath `node`.
+pub(crate) fn mappings_at<'a>(node: &'a Document, path: &[String], found: &mut Vec<&'a Document>) {
+    let node = match node.as_value() {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    match (path, node) {
+        ([], Document::Mapping(_)) => found.push(node),
+        ([], _) => {}
+        ([seg, rest @ ..], _) => {
+            for n in children(node, seg) {
+                mappings_at(n, rest, found);
+            }
+        }
+    }
+}
+
+// Marks the values at `path` beneath `node` with the color class `class`.
+fn color_class_at(node: Document, path: &[String], class: &str) -> Document {
+    if !node.has_value() {
+        return node;
+    }
+    let (leading, value, trailing) = split(node);
+    // Unwrap is ok: the node has a value.
+    let value = value.unwrap();
+    let value = match (path, value) {
+        ([], v) => Document::ColorClass(class.to_string(), Box::new(v)),
+        (p, Document::Compact(v)) => Document::Compact(Box::new(color_class_at(*v, p, class))),
+        (p, Document::ColorClass(c, v)) => {
+            Document::ColorClass(c, Box::new(color_class_at(*v, p, class)))
+        }
+        (p, Document::Identified(id, v)) => {
+            Document::Identified(id, Box::new(color_class_at(*v, p, class)))
+        }
+        (p, Document::Typed(t, v)) => Document::Typed(t, Box::new(color_class_at(*v, p, class))),
+        ([seg, rest @ ..], Document::Mapping(m)) => Document::Mapping(
+            m.into_iter()
+                .map(|entry| {
+                    let matched = selects_entry(seg, &entry);
+                    match entry {
+                        Document::Fragment(mut nodes) if matched => {
+                            // Unwrap is ok: the entry is a kvpair, so it contains a value.
+                            let i = nodes.iter().rposition(Document::has_value).unwrap();
+                            let v = std::mem::replace(&mut nodes[i], Document::Null);
+                            nodes[i] = color_class_at(v, rest, class);
+                            Document::Fragment(nodes)
+                        }
+                        e => e,
+                    }
+                })
+                .collect(),
+        ),
+        ([seg, rest @ ..], Document::Sequence(s)) => {
+            let mut index = 0;
+            Document::Sequence(
+                s.into_iter()
+                    .map(|elem| {
+                        if !elem.has_value() {
+                            return elem;
+                        }
+                        index += 1;
+                        if selects_index(seg, index - 1) {
+                            color_class_at(elem, rest, class)
+                        } else {
+                            elem
+                        }
+                    })
+                    .collect(),
+            )
+        }
+        (_, v) => v,
+    };
+    join(leading, value, trailing)
+}
+
+// Returns the value of `node`, looking through wrapper nodes.
+pub(crate) fn unwrapped(node: &mut Document) -> Option<&mut Document> {
+    let mut value = node.as_value_mut().ok()?;
+    while let Document::Compact(v)
+    | Document::ColorClass(_, v)
+    | Document::Identified(_, v)
+    | Document::Typed(_, v) = value
+    {
+        value = v;
+    }
+    Some(value)
+}
+
+// Inserts `comment` ahead of the value in `node` and, if `class` is given,
+// marks the value with that color class.  `node` is either a bare value or a
+// fragment, such as a kvpair, holding comments and values.
+fn flag(node: &mut Document, comment: Document, class: Option<&str>) {
+    let mark = |v: Document| match class {
+        Some(class) => Document::ColorClass(class.to_string(), Box::new(v)),
+        None => v,
+    };
+    match node {
+        Document::Fragment(nodes) => {
+            let first = nodes.iter().position(Document::has_value);
+            let last = nodes.iter().rposition(Document::has_value);
+            if let (Some(first), Some(last)) = (first, last) {
+                let v = std::mem::replace(&mut nodes[last], Document::Null);
+                nodes[last] = mark(v);
+                nodes.insert(first, comment);
+            } else {
+                nodes.push(comment);
+            }
+        }
+        n => {
+            let v = std::mem::replace(n, Document::Null);
+            *n = Document::Fragment(vec![comment, mark(v)]);
+        }
+    }
+}
+
+// Flags the node at `path` beneath `node` with `comment`.  Returns the
+// comment if the path does not exist.
+fn flag_at(node: &mut Document, path: &[String], comment: Document) -> Option<Document> {
+    if path.is_empty() {
+        flag(node, comment, Some(ERROR_CLASS));
+        return None;
+    }
+    let value = match unwrapped(node) {
+        Some(v) => v,
+        None => return Some(comment),
+    };
+    match (path, value) {
+        ([seg, rest @ ..], Document::Mapping(m)) => {
+            let entry = m.iter_mut().find(|entry| is_entry(entry, seg));
+            match entry {
+                Some(entry) if rest.is_empty() => {
+                    flag(entry, comment, Some(ERROR_CLASS));
+                    None
+                }
+                // Unwrap is ok: the entry was found by its key.
+                Some(entry) => flag_at(entry.as_kv_mut().unwrap().1, rest, comment),
+                None => Some(comment),
+            }
+        }
+        ([seg, rest @ ..], Document::Sequence(s)) => {
+            let elem = seg
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| s.iter_mut().filter(|n| n.has_value()).nth(i));
+            match elem {
+                Some(elem) => flag_at(elem, rest, comment),
+                None => Some(comment),
+            }
+        }
+        _ => Some(comment),
+    }
+}
+
+// Sorts the sequences at `path` beneath `node` by `key_fn`.  Returns the
+// number of sequences sorted.
+fn sort_at<K, F>(node: &mut Document, path: &[String], key_fn: &mut F) -> usize
+where
+    K: Ord,
+    F: FnMut(&Document) -> K,
+{
+    let value = match unwrapped(node) {
+        Some(v) => v,
+        None => return 0,
+    };
+    match (path, value) {
+        ([], Document::Sequence(s)) => {
+            // Each element is moved as a unit, so comments attached to an
+            // element stay with it.  Elements without a value keep their
+            // relative order after all of the sorted elements.
+            let mut elems = std::mem::take(s)
+                .into_iter()
+                .map(|e| (e.as_value().ok().map(&mut *key_fn), e))
+                .collect::<Vec<_>>();
+            elems.sort_by(|(a, _), (b, _)| match (a, b) {
+                (Some(a), Some(b)) => a.cmp(b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            });
+            *s = elems.into_iter().map(|(_, e)| e).collect();
+            1
+        }
+        ([], _) => 0,
+        ([seg, rest @ ..], value) => children_mut(value, seg)
+            .into_iter()
+            .map(|n| sort_at(n, rest, key_fn))
+            .sum(),
+    }
+}
+
+/// The color class of values flagged by `Document::annotate_errors`.
+pub const ERROR_CLASS: &str = "error";
+
+impl Document {
+    /// Inserts each `(path, message)` error as a comment ahead of the node
+    /// at `path` so that errors (e.g. from validating a configuration file)
+    /// can be shown in context when the document is re-emitted.
+    ///
+    /// The path is a JSON Pointer through mapping keys and sequence
+    /// indices; the empty path refers to the root.  The offending values are
+    /// marked with the color class `"error"`, so an emitter whose
+    /// `ColorProfile` defines that class highlights them.  Errors whose path
+    /// does not exist in the document are placed at the root along with
+    /// their path.
+    pub fn annotate_errors<P, M>(mut self, errors: &[(P, M)]) -> Document
+    where
+        P: AsRef<str>,
+        M: AsRef<str>,
+    {
+        for (path, message) in errors {
+            let (path, message) = (path.as_ref(), message.as_ref());
+            let segs = segments(path).collect::<Vec<_>>();
+            let comment = Document::Comment(format!("error: {}", message), CommentFormat::Standard);
+            if flag_at(&mut self, &segs, comment).is_some() {
+                let comment = Document::Comment(
+                    format!("error: {}: {}", path, message),
+                    CommentFormat::Standard,
+                );
+                flag(&mut self, comment, None);
+            }
+        }
+        self
+    }
+
+    /// Sorts the elements of the sequence at `path` by the key which
+    /// `key_fn` extracts from each element's value (e.g. the `name` field of
+    /// an array of objects).
+    ///
+    /// The path is a JSON Pointer through mapping keys and sequence
+    /// indices, where `*` matches any single key or index.  The sort is
+    /// stable and comments attached to an element move with it.  It is an
+    /// error if the path does not lead to a sequence.
+    pub fn sort_sequence_by<K, F>(mut self, path: &str, mut key_fn: F) -> Result<Document, Error>
+    where
+        K: Ord,
+        F: FnMut(&Document) -> K,
+    {
+        let segs = segments(path).collect::<Vec<_>>();
+        if sort_at(&mut self, &segs, &mut key_fn) == 0 {
+            return Err(Error::PathError(format!("{}: no sequence found", path)));
+        }
+        Ok(self)
+    }
+
+    /// Marks the values at `path` with the user-defined color class `class`.
+    ///
+    /// The path is a JSON Pointer through mapping keys and sequence
+    /// indices, where `*` matches any single key or index (e.g.
+    /// `/server/tls/*`).  Emitters render marked values with the class
+    /// style from their `ColorProfile`.  Paths which do not exist in the
+    /// document are ignored.
+    pub fn color_class(self, path: &str, class: &str) -> Document {
+        let path = segments(path).collect::<Vec<_>>();
+        color_class_at(self, &path, class)
+    }
+
+    /// Wraps the document in mappings so that its value is found at `path`.
+    ///
+    /// The path is a JSON Pointer through keys (e.g. `myapp` or
+    /// `/services/myapp`).  Comments attached to the root of the document
+    /// remain at the root, ahead of the new wrapper objects.
+    pub fn nest_under(self, path: &str) -> Document {
+        let (leading, value, trailing) = split(self);
+        let mut value = value.unwrap_or(Document::Null);
+        for key in segments(path).rev() {
+            value = Document::Mapping(vec![kv(&key, value)]);
+        }
+        join(leading, value, trailing)
+    }
+
+    /// Removes the wrapper mappings along `path`, making the value found at
+    /// `path` the root of the document.  This is the inverse of `nest_under`.
+    ///
+    /// Every mapping along the path must contain exactly one entry; lifting
+    /// out of a mapping with other entries would discard them and is an
+    /// error.  Comments attached to the removed entries are kept as comments
+    /// on the new root.
+    pub fn lift(self, path: &str) -> Result<Document, Error> {
+        let (mut leading, value, mut trailing) = split(self);
+        let mut value = value.ok_or_else(|| Error::PathError(path.to_string()))?;
+        for key in segments(path) {
+            let mapping = match value {
+                Document::Compact(c)
+                | Document::ColorClass(_, c)
+                | Document::Identified(_, c)
+                | Document::Typed(_, c) => *c,
+                v => v,
+            };
+            let mapping = match mapping {
+                Document::Mapping(m) => m,
+                v => return Err(Error::StructureError("Mapping", v.variant())),
+            };
+            let mut entries = Vec::new();
+            for node in mapping {
+                if node.has_value() {
+                    entries.push(node);
+                } else {
+                    leading.push(node);
+                }
+            }
+            let found = entries
+                .iter()
+                .any(|e| matches!(e.as_kv().and_then(|(k, _)| k.as_str()), Ok(k) if k == key));
+            if !found {
+                return Err(Error::PathError(format!("{}: `{}` not found", path, key)));
+            }
+            if entries.len() > 1 {
+                return Err(Error::PathError(format!(
+                    "{}: `{}` is not the only entry in its mapping",
+                    path, key
+                )));
+            }
+            // Unwrap is ok: the key was found among the entries.
+            let entry = entries.pop().unwrap();
+            let mut nodes = match entry {
+                Document::Fragment(nodes) => nodes,
+                _ => unreachable!(),
+            };
+            // Unwrap is ok: the entry is a kvpair, so it contains a value after the key.
+            let index = nodes.iter().rposition(Document::has_value).unwrap();
+            trailing.splice(0..0, nodes.drain(index + 1..));
+            value = nodes.pop().unwrap();
+            leading.extend(nodes.into_iter().filter(|n| !n.has_value()));
+        }
+        Ok(join(leading, value, trailing))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    #[test]
+    fn test_nest_under() -> Result<()> {
+        let doc = Document::parse("// Header\n{ a: 1, b: 2 }")?;
+        let doc = doc.nest_under("/services/myapp");
+        assert_eq!(
+            doc.clone().to_json5().to_text()?,
+            r#"// Header
+{
+  services: {
+    myapp: {
+      a: 1,
+      b: 2
+    }
+  }
+}"#
+        );
+        let doc = doc.lift("services/myapp")?;
+        assert_eq!(
+            doc.to_json5().to_text()?,
+            r#"// Header
+{
+  a: 1,
+  b: 2
+}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lift() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                // Settings for myapp.
+                myapp: { a: 1 }
+            }"#,
+        )?;
+        let lifted = doc.clone().lift("myapp")?;
+        assert_eq!(
+            lifted.to_json5().to_text()?,
+            r#"// Settings for myapp.
+{
+  a: 1
+}"#
+        );
+        assert!(matches!(
+            doc.clone().lift("other"),
+            Err(Error::PathError(_))
+        ));
+        let doc = Document::parse("{ myapp: { a: 1 }, other: 2 }")?;
+        assert!(matches!(
+            doc.clone().lift("myapp"),
+            Err(Error::PathError(e)) if e.ends_with("is not the only entry in its mapping")
+        ));
+        // A missing key is reported as such, whatever else the mapping holds.
+        assert!(matches!(
+            doc.lift("/nope"),
+            Err(Error::PathError(e)) if e == "/nope: `nope` not found"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_annotate_errors() -> Result<()> {
+        use crate::color::ColorProfile;
+        use ansi_term::{Color, Style};
+
+        let doc = Document::parse(
+            r#"{
+                // The service name.
+                name: "",
+                ports: [80, 70000],
+            }"#,
+        )?;
+        let doc = doc.annotate_errors(&[
+            ("/name", "must not be empty"),
+            ("/ports/1", "port out of range"),
+            ("/user", "missing required key"),
+        ]);
+        assert_eq!(
+            doc.clone().to_json5().to_text()?,
+            r#"// error: /user: missing required key
+{
+  // The service name.
+  // error: must not be empty
+  name: "",
+  ports: [
+    80,
+    // error: port out of range
+    70000
+  ]
+}"#
+        );
+        let error = Style::new().fg(Color::Red);
+        let profile = ColorProfile::default().class(ERROR_CLASS, error);
+        let text = doc.to_json5().color(profile).to_text()?;
+        assert!(text.contains(&error.paint("70000").to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_sequence_by() -> Result<()> {
+        // Returns the value of the `name` field of a mapping.
+        fn name(doc: &Document) -> Option<String> {
+            match doc {
+                Document::Mapping(m) => m.iter().find_map(|e| match e.as_kv() {
+                    Ok((k, v)) if k.as_str().map_or(false, |k| k == "name") => {
+                        v.as_str().ok().map(str::to_string)
+                    }
+                    _ => None,
+                }),
+                _ => None,
+            }
+        }
+
+        let doc = Document::parse(
+            r#"{
+                users: [
+                    // The operator.
+                    {name: "carol"},
+                    {name: "alice"},
+                    // Added later.
+                    {name: "bob"},
+                ],
+            }"#,
+        )?;
+        let doc = doc.sort_sequence_by("/users", name)?;
+        assert_eq!(
+            doc.clone().to_json5().to_text()?,
+            r#"{
+  users: [
+    {
+      name: "alice"
+    },
+    // Added later.
+    {
+      name: "bob"
+    },
+    // The operator.
+    {
+      name: "carol"
+    }
+  ]
+}"#
+        );
+        assert!(matches!(
+            doc.sort_sequence_by("/groups", name),
+            Err(Error::PathError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_color_class() -> Result<()> {
+        use crate::color::ColorProfile;
+        use ansi_term::{Color, Style};
+
+        let doc = Document::parse(
+            r#"{
+                // TLS settings.
+                tls: { verify: false, ciphers: ["rc4", "aes"] },
+                port: 80
+            }"#,
+        )?;
+        let doc = doc
+            .color_class("/tls/verify", "warning")
+            .color_class("/tls/ciphers/0", "warning")
+            .color_class("/missing/*", "warning");
+        let warning = Style::new().fg(Color::Red);
+        let profile = ColorProfile::default().class("warning", warning);
+        assert_eq!(
+            doc.clone()
+                .to_json5()
+                .compact(true)
+                .color(profile)
+                .to_text()?,
+            format!(
+                "{{tls: {{verify: {}, ciphers: [{}{}{}, \"aes\"]}}, port: 80}}",
+                warning.paint("false"),
+                warning.paint("\""),
+                warning.paint("rc4"),
+                warning.paint("\""),
+            )
+        );
+        // Without a class style, the markers do not affect the output.
+        assert_eq!(
+            doc.to_json5().compact(true).to_text()?,
+            r#"{tls: {verify: false, ciphers: ["rc4", "aes"]}, port: 80}"#
+        );
+        Ok(())
+    }
+}
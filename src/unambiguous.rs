@@ -0,0 +1,419 @@
+// Checks whether strings may be emitted without quotes.
+//
+// The emitters consult these functions before writing a key or string value
+// in a relaxed (unquoted) form, so that the output is never reinterpreted as
+// punctuation, a comment, a number or a keyword when parsed back.
+use once_cell::sync::OnceCell;
+use std::collections::HashSet;
+use unicode_ident::{is_xid_continue, is_xid_start};
+
+/// The text serialization dialects understood by this crate's highlighter
+/// and quoting rules.  The emitters of other formats (e.g.
+/// `Toml` or `Ron`) are created by their own `Document::to_*` methods.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    Json,
+    Json5,
+    Hjson,
+    Yaml,
+}
+
+/// The characters permitted in bare (unquoted) mapping keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Bareword {
+    /// ASCII letters, digits, `_` and `$`.
+    #[default]
+    Ascii,
+    /// Json5 (ECMAScript) identifiers: an `XID_Start` character, `_` or `$`,
+    /// followed by `XID_Continue` characters, `$`, ZWNJ or ZWJ.
+    Unicode,
+}
+
+/// Returns whether `word` is a legal bare key under `policy` in json5 and
+/// hjson.  Reserved words are never bare.
+pub fn is_bareword(word: &str, policy: Bareword) -> bool {
+    match policy {
+        Bareword::Ascii => is_legal_bareword(word),
+        Bareword::Unicode => {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map_or(false, |c| is_xid_start(c) || c == '$' || c == '_')
+                && chars.all(|c| is_xid_continue(c) || matches!(c, '$' | '\u{200C}' | '\u{200D}'))
+                && !is_reserved_word(word)
+        }
+    }
+}
+
+/// Returns whether `key` may be emitted as a bare (unquoted) mapping key in
+/// `dialect`.
+pub fn is_unambiguous_key(key: &str, dialect: Dialect) -> bool {
+    match dialect {
+        Dialect::Json => false,
+        // Hjson permits more punctuation in bare keys than json5, but the
+        // json5 identifier rules are valid in both.
+        Dialect::Json5 | Dialect::Hjson => is_legal_bareword(key),
+        Dialect::Yaml => !yaml_needs_quotes(key),
+    }
+}
+
+/// Returns whether `value` may be emitted as an unquoted string value in
+/// `dialect`.
+///
+/// For Hjson, a quoteless string extends to the end of the line, so the
+/// emitter must additionally ensure that nothing (neither a comma nor a
+/// comment) follows the value on the same line.
+pub fn is_unambiguous_value(value: &str, dialect: Dialect) -> bool {
+    match dialect {
+        Dialect::Json | Dialect::Json5 => false,
+        Dialect::Hjson => is_hjson_quoteless(value),
+        Dialect::Yaml => !yaml_needs_quotes(value),
+    }
+}
+
+fn is_hjson_quoteless(value: &str) -> bool {
+    value.trim() == value
+        && !value.is_empty()
+        // Punctuators, quotes and comments are interpreted by the parser.
+        && !value.starts_with(['{', '}', '[', ']', ',', ':', '"', '\'', '#'])
+        && !value.starts_with("//")
+        && !value.starts_with("/*")
+        // Values which begin like a number are parsed as numbers.
+        && !value.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.')
+        && !["true", "false", "null", "Infinity", "NaN"].contains(&value)
+        // A quoteless string ends at the end of the line.
+        && !value.contains(|c: char| c.is_control() || c == '\u{2028}' || c == '\u{2029}')
+        // Be conservative about text which a reader may mistake for a
+        // trailing comment.
+        && !value.contains('#')
+        && !value.contains("//")
+        && !value.contains("/*")
+}
+
+// More strict than javascript.
+fn bad_identifier_char(ch: char) -> bool {
+    match ch {
+        '0'..='9' => false,
+        'A'..='Z' => false,
+        'a'..='z' => false,
+        '_' => false,
+        '$' => false,
+        _ => true,
+    }
+}
+
+fn is_reserved_word(word: &str) -> bool {
+    static WORDS: OnceCell<HashSet<&str>> = OnceCell::new();
+    let words = WORDS.get_or_init(|| {
+        HashSet::from([
+            "break",
+            "do",
+            "instanceof",
+            "typeof",
+            "case",
+            "else",
+            "new",
+            "var",
+            "catch",
+            "finally",
+            "return",
+            "void",
+            "continue",
+            "for",
+            "switch",
+            "while",
+            "debugger",
+            "function",
+            "this",
+            "with",
+            "default",
+            "if",
+            "throw",
+            "",
+            "delete",
+            "in",
+            "try",
+            "class",
+            "enum",
+            "extends",
+            "super",
+            "const",
+            "export",
+            "import",
+            "implements",
+            "let",
+            "private",
+            "public",
+            "yield",
+            "interface",
+            "package",
+            "protected",
+            "static",
+            "null",
+            "true",
+            "false",
+        ])
+    });
+    words.get(word).is_some()
+}
+
+fn is_legal_bareword(word: &str) -> bool {
+    if word.len() == 0 {
+        return false;
+    }
+    let ch = word.chars().nth(0).unwrap();
+    !((ch >= '0' && ch <= '9') || word.contains(bad_identifier_char) || is_reserved_word(word))
+}
+
+// From yaml-rust:
+// Check if the string requires quoting.
+// Strings starting with any of the following characters must be quoted.
+// :, &, *, ?, |, -, <, >, =, !, %, @
+// Strings containing any of the following characters must be quoted.
+// {, }, [, ], ,, #, `
+//
+// If the string contains any of the following control characters, it must be escaped with double quotes:
+// \0, \x01, \x02, \x03, \x04, \x05, \x06, \a, \b, \t, \n, \v, \f, \r, \x0e, \x0f, \x10, \x11, \x12, \x13, \x14, \x15, \x16, \x17, \x18, \x19, \x1a, \e, \x1c, \x1d, \x1e, \x1f, \N, \_, \L, \P
+//
+// Finally, there are other cases when the strings must be quoted, no matter if you're using single or double quotes:
+// * When the string is true or false (otherwise, it would be treated as a boolean value);
+// * When the string is null or ~ (otherwise, it would be considered as a null value);
+// * When the string looks like a number, such as integers (e.g. 2, 14, etc.), floats (e.g. 2.6, 14.9) and exponential numbers (e.g. 12e7, etc.) (otherwise, it would be treated as a numeric value);
+// * When the string looks like a date (e.g. 2014-12-31) (otherwise it would be automatically converted into a Unix timestamp).
+fn yaml_needs_quotes(string: &str) -> bool {
+    fn need_quotes_spaces(string: &str) -> bool {
+        string.starts_with(' ') || string.ends_with(' ')
+    }
+
+    string == ""
+        || need_quotes_spaces(string)
+        || string.starts_with(|character: char| match character {
+            '&' | '*' | '?' | '|' | '-' | '<' | '>' | '=' | '!' | '%' | '@' => true,
+            _ => false,
+        })
+        || string.contains(|character: char| match character {
+            ':'
+            | '{'
+            | '}'
+            | '['
+            | ']'
+            | ','
+            | '#'
+            | '`'
+            | '\"'
+            | '\''
+            | '\\'
+            | '\0'..='\x06'
+            | '\t'
+            | '\n'
+            | '\r'
+            | '\x0e'..='\x1a'
+            | '\x1c'..='\x1f' => true,
+            _ => false,
+        })
+        || [
+            // http://yaml.org/type/bool.html
+            // Note: libyaml and PyYAML read 'y', 'Y', 'n' and 'N' as strings,
+            // but YAML 1.1 makes them booleans, so they are quoted for other
+            // readers.
+            "y", "Y", "yes", "Yes", "YES", "n", "N", "no", "No", "NO", "True", "TRUE", "true",
+            "False", "FALSE", "false", "on", "On", "ON", "off", "Off", "OFF",
+            // http://yaml.org/type/null.html
+            "null", "Null", "NULL", "~",
+        ]
+        .contains(&string)
+        || string.starts_with('.')
+        || string.starts_with("0x")
+        || string.starts_with("0b")
+        || string.starts_with("0o")
+        || string.parse::<i64>().is_ok()
+        || string.parse::<f64>().is_ok()
+        || is_yaml_number_with_separators(string)
+        || is_yaml_timestamp(string)
+}
+
+// YAML 1.1 permits `_` as a digit separator in numbers (e.g. `1_000`).
+fn is_yaml_number_with_separators(string: &str) -> bool {
+    string.contains('_')
+        && string
+            .trim_start_matches(['-', '+'])
+            .starts_with(|c: char| c.is_ascii_digit())
+        && string.replace('_', "").parse::<f64>().is_ok()
+}
+
+// YAML 1.1 timestamps begin with a date (e.g. `2024-01-01`), optionally
+// followed by a time.
+fn is_yaml_timestamp(string: &str) -> bool {
+    let mut parts = string.splitn(3, '-');
+    let (year, month, rest) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(y), Some(m), Some(r)) => (y, m, r),
+        _ => return false,
+    };
+    let day = rest.split(['T', 't', ' ', '\t']).next().unwrap_or_default();
+    let digits = |s: &str, n: std::ops::RangeInclusive<usize>| {
+        n.contains(&s.len()) && s.chars().all(|c| c.is_ascii_digit())
+    };
+    digits(year, 4..=4) && digits(month, 1..=2) && digits(day, 1..=2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{Document, StrFormat};
+    use anyhow::Result;
+
+    const DIALECTS: [Dialect; 4] = [Dialect::Json, Dialect::Json5, Dialect::Hjson, Dialect::Yaml];
+
+    // Strings which must be quoted in every dialect.
+    const AMBIGUOUS: &[&str] = &[
+        "",
+        " padded",
+        "padded ",
+        "true",
+        "false",
+        "null",
+        "0",
+        "12",
+        "-1",
+        "+1",
+        ".5",
+        "1e3",
+        "0x10",
+        "{",
+        "}",
+        "[",
+        "]",
+        "{brace",
+        "[bracket",
+        ",comma",
+        ":colon",
+        "#hash",
+        "\"dquote",
+        "'squote",
+        "'''",
+        "line\nbreak",
+        "tab\there",
+    ];
+
+    #[test]
+    fn test_ambiguous_everywhere() {
+        for dialect in DIALECTS {
+            for s in AMBIGUOUS {
+                assert!(
+                    !is_unambiguous_value(s, dialect),
+                    "{:?} value {:?}",
+                    dialect,
+                    s
+                );
+                assert!(!is_unambiguous_key(s, dialect), "{:?} key {:?}", dialect, s);
+            }
+        }
+    }
+
+    #[test]
+    fn test_keys() {
+        for s in ["name", "_private", "$ref", "camelCase", "a1"] {
+            assert!(!is_unambiguous_key(s, Dialect::Json));
+            assert!(is_unambiguous_key(s, Dialect::Json5), "{}", s);
+            assert!(is_unambiguous_key(s, Dialect::Hjson), "{}", s);
+            assert!(is_unambiguous_key(s, Dialect::Yaml), "{}", s);
+        }
+        for s in ["with space", "dash-ed", "1abc", "while", "a.b"] {
+            assert!(!is_unambiguous_key(s, Dialect::Json5), "{}", s);
+            assert!(!is_unambiguous_key(s, Dialect::Hjson), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_unicode_barewords() {
+        for s in ["größe", "naïve", "日本語", "ab\u{200C}c", "x\u{0301}"] {
+            assert!(!is_bareword(s, Bareword::Ascii), "{}", s);
+            assert!(is_bareword(s, Bareword::Unicode), "{}", s);
+        }
+        for s in ["1größe", "größe-2", "ä b", "\u{0301}x", "while", "€uro", ""] {
+            assert!(!is_bareword(s, Bareword::Unicode), "{}", s);
+        }
+        for s in ["name", "_private", "$ref"] {
+            assert!(is_bareword(s, Bareword::Unicode), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_yaml_values() {
+        for s in [
+            "no",
+            "On",
+            "OFF",
+            "~",
+            "3.0",
+            "1_000",
+            "-1_0.5",
+            "2024-01-01",
+            "2001-12-14t21",
+            "2001-1-2 10",
+            ".inf",
+            "inf",
+            "NaN",
+            "y",
+            "N",
+        ] {
+            assert!(!is_unambiguous_value(s, Dialect::Yaml), "{}", s);
+        }
+        for s in [
+            "nope",
+            "yy",
+            "_1",
+            "1_a",
+            "2024-01",
+            "2024-001-01",
+            "v1.0",
+            "1.0.0",
+        ] {
+            assert!(is_unambiguous_value(s, Dialect::Yaml), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_hjson_values() {
+        for s in [
+            "hello",
+            "hello world",
+            "a,b",
+            "trailing,",
+            "x: y",
+            "it's",
+            "truthy",
+        ] {
+            assert!(is_unambiguous_value(s, Dialect::Hjson), "{}", s);
+            assert!(!is_unambiguous_value(s, Dialect::Json5), "{}", s);
+        }
+        for s in [
+            "Infinity",
+            "NaN",
+            "// c",
+            "/* c",
+            "a # b",
+            "a // b",
+            "a /* b",
+            "x\u{2028}y",
+        ] {
+            assert!(!is_unambiguous_value(s, Dialect::Hjson), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_hjson_round_trip() -> Result<()> {
+        // Every value considered unambiguous must parse back to itself.
+        for s in ["hello", "hello world", "a,b", "trailing,", "x: y", "it's"] {
+            let text = format!("{{\n  key: {}\n  other: 1\n}}", s);
+            let doc = Document::from_hjson(&text)?;
+            let (_, v) = match &doc {
+                Document::Mapping(m) => m[0].as_kv()?,
+                _ => panic!("Expected a mapping"),
+            };
+            assert_eq!(v.as_str()?, s);
+            assert!(matches!(v, Document::String(_, StrFormat::Unquoted)));
+        }
+        Ok(())
+    }
+}
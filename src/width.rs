@@ -0,0 +1,72 @@
+// Measurement of text in terminal columns.
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Returns the number of columns `text` occupies in a terminal.
+///
+/// Text is measured by grapheme cluster, so that an emoji sequence joined
+/// by zero-width joiners, or given emoji presentation by a variation
+/// selector, is measured as the single character it is drawn as.
+pub(crate) fn str_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Returns the byte offset of the first grapheme cluster of `text` which
+/// does not fit within `width` columns, or the length of `text` if all of
+/// it fits.
+pub(crate) fn fit_width(text: &str, width: usize) -> usize {
+    let mut used = 0;
+    for (i, g) in text.grapheme_indices(true) {
+        used += grapheme_width(g);
+        if used > width {
+            return i;
+        }
+    }
+    text.len()
+}
+
+// Returns the number of columns the grapheme cluster `g` occupies.  Control
+// characters occupy none.
+fn grapheme_width(g: &str) -> usize {
+    match g.chars().next() {
+        Some(ch) if ch.is_control() => 0,
+        _ => g.width(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_str_width() {
+        for (text, width) in [
+            ("", 0),
+            ("ascii", 5),
+            ("naïve", 5),
+            ("x\u{0301}", 1),
+            ("日本語", 6),
+            ("ｆｕｌｌ", 8),
+            ("한국어", 6),
+            ("🎉", 2),
+            ("a🎉b", 4),
+            // A family joined by zero-width joiners is a single emoji.
+            ("👨\u{200d}👩\u{200d}👧", 2),
+            ("👍🏽", 2),
+            // A variation selector gives a text symbol emoji presentation.
+            ("❤\u{fe0f}", 2),
+            ("❤", 1),
+            ("a\u{200b}b", 2),
+            ("tab\t", 3),
+        ] {
+            assert_eq!(str_width(text), width, "{:?}", text);
+        }
+        assert_eq!(fit_width("日本語", 5), "日本".len());
+        assert_eq!(fit_width("日本語", 6), "日本語".len());
+        assert_eq!(fit_width("ab", 0), 0);
+        // A joined emoji is kept whole.
+        let family = "👨\u{200d}👩\u{200d}👧";
+        assert_eq!(fit_width(family, 2), family.len());
+        assert_eq!(fit_width(family, 1), 0);
+    }
+}
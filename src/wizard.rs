@@ -0,0 +1,218 @@
+// Interactive questionnaires driven by annotated templates.
+use std::io::{BufRead, Write};
+
+use crate::document::{Document, StrFormat};
+use crate::entry::{comment_lines, key_text};
+use crate::error::Error;
+use crate::integer::Int;
+use crate::path::pointer;
+use crate::render::display_text;
+
+/// Fills in an annotated template `Document` by asking the user for the
+/// value of each field.
+///
+/// Each scalar in the template becomes a question, named by its JSON
+/// Pointer (e.g. `/server/port`) and preceded by the comments on its
+/// entry as help text.  The template's value is shown as the default and
+/// is kept if the answer is empty.  An answer must have the type of the
+/// default, or the question is asked again; a null default accepts an
+/// answer of any type.  Sequences are answered as a whole in json5 form
+/// (e.g. `[80, 443]`).  Comments and formatting of the template are kept in
+/// the resulting document.
+///
+/// ```
+/// # use serde_annotate::{Document, Emit, Wizard};
+/// let template = Document::parse("{\n  // The TCP port.\n  port: 80\n}")?;
+/// let mut output = Vec::new();
+/// let config = Wizard::new("8080\n".as_bytes(), &mut output).run(&template)?;
+/// assert_eq!(config.to_json().compact(true).to_text()?, r#"{"port": 8080}"#);
+/// assert_eq!(String::from_utf8(output).unwrap(), "The TCP port.\n/port [80]: ");
+/// # Ok::<(), serde_annotate::Error>(())
+/// ```
+pub struct Wizard<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    eof: bool,
+}
+
+impl<R: BufRead, W: Write> Wizard<R, W> {
+    /// Creates a wizard reading answers from `input` and writing questions
+    /// to `output` (e.g. `stdin().lock()` and `stdout()`).  Once `input`
+    /// is exhausted, the remaining questions take their defaults.
+    pub fn new(input: R, output: W) -> Self {
+        Wizard {
+            input,
+            output,
+            eof: false,
+        }
+    }
+
+    /// Asks the questions for `template`, returning the filled-in document.
+    pub fn run(&mut self, template: &Document) -> Result<Document, Error> {
+        let mut doc = template.clone();
+        self.fill(&mut doc, &mut Vec::new(), &[])?;
+        Ok(doc)
+    }
+
+    // Fills in `node`, which is at `path` and described by `help`.
+    fn fill(
+        &mut self,
+        node: &mut Document,
+        path: &mut Vec<String>,
+        help: &[String],
+    ) -> Result<(), Error> {
+        match node {
+            Document::Mapping(m) => {
+                for entry in m.iter_mut().filter(|n| n.has_value()) {
+                    let help = comment_lines(entry).map(String::from).collect::<Vec<_>>();
+                    let (key, value) = entry.as_kv_mut()?;
+                    path.push(key_text(key).unwrap_or_else(|| show(key)));
+                    self.fill(value, path, &help)?;
+                    path.pop();
+                }
+            }
+            Document::Fragment(_) => {
+                let help = comment_lines(node).map(String::from).collect::<Vec<_>>();
+                self.fill(node.as_value_mut()?, path, &help)?;
+            }
+            Document::Compact(d)
+            | Document::ColorClass(_, d)
+            | Document::Identified(_, d)
+            | Document::Typed(_, d) => self.fill(d, path, help)?,
+            Document::Comment(_, _) => {}
+            _ => *node = self.ask(&pointer(path), help, node)?,
+        }
+        Ok(())
+    }
+
+    // Asks for the value at `name`, re-asking until the answer is valid.
+    fn ask(&mut self, name: &str, help: &[String], default: &Document) -> Result<Document, Error> {
+        for line in help {
+            writeln!(self.output, "{}", line)?;
+        }
+        loop {
+            write!(self.output, "{} [{}]: ", name, show(default))?;
+            self.output.flush()?;
+            let mut answer = String::new();
+            if self.eof || self.input.read_line(&mut answer)? == 0 {
+                self.eof = true;
+                writeln!(self.output)?;
+                return Ok(default.clone());
+            }
+            let answer = answer.trim();
+            if answer.is_empty() {
+                return Ok(default.clone());
+            }
+            match parse_answer(answer, default) {
+                Some(value) => return Ok(value),
+                None => writeln!(self.output, "Expected {}.", expected(default))?,
+            }
+        }
+    }
+}
+
+// Renders a default value for display.
+fn show(doc: &Document) -> String {
+    match doc.as_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => display_text(doc),
+    }
+}
+
+fn expected(default: &Document) -> &'static str {
+    match default {
+        Document::Boolean(_) => "true or false",
+        Document::Int(_) => "an integer",
+        Document::Float(_) => "a number",
+        Document::Sequence(_) | Document::Bytes(_) => "a sequence like [1, 2]",
+        _ => "a value",
+    }
+}
+
+// Parses an answer as a value of the same type as `default`.
+fn parse_answer(answer: &str, default: &Document) -> Option<Document> {
+    match default {
+        Document::String(_, f) => Some(Document::String(answer.to_string(), *f)),
+        Document::StaticStr(_, f) => Some(Document::String(answer.to_string(), *f)),
+        Document::Boolean(_) => match answer.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "y" => Some(Document::Boolean(true)),
+            "false" | "no" | "n" => Some(Document::Boolean(false)),
+            _ => None,
+        },
+        Document::Int(_) => Int::from_str_radix(answer, 0).ok().map(Document::Int),
+        Document::Float(_) => answer.parse::<f64>().ok().map(Document::Float),
+        Document::Sequence(_) | Document::Bytes(_) => match Document::parse(answer) {
+            Ok(doc @ Document::Sequence(_)) => Some(doc),
+            _ => None,
+        },
+        _ => match Document::parse(answer) {
+            Ok(doc) => Some(doc),
+            Err(_) => Some(Document::String(answer.to_string(), StrFormat::Standard)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::Emit;
+    use anyhow::Result;
+
+    #[test]
+    fn test_wizard() -> Result<()> {
+        let template = Document::parse(
+            r#"{
+                // The address to listen on.
+                host: "localhost",
+                // Listening ports.
+                ports: [80],
+                server: {
+                    workers: 4,
+                    debug: false,
+                },
+                ratio: 0.5,
+                extra: null,
+            }"#,
+        )?;
+        let answers = "\n[80, 443]\nmany\n0x10\nyes\nhalf\n0.25\n{a: 1}\n";
+        let mut output = Vec::new();
+        let config = Wizard::new(answers.as_bytes(), &mut output).run(&template)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            r#"The address to listen on.
+/host [localhost]: Listening ports.
+/ports [[80]]: /server/workers [4]: Expected an integer.
+/server/workers [4]: /server/debug [false]: /ratio [0.5]: Expected a number.
+/ratio [0.5]: /extra [null]: "#
+        );
+        assert_eq!(
+            config.to_json5().to_text()?,
+            r#"{
+  // The address to listen on.
+  host: "localhost",
+  // Listening ports.
+  ports: [
+    80,
+    443
+  ],
+  server: {
+    workers: 0x10,
+    debug: true
+  },
+  ratio: 0.25,
+  extra: {
+    a: 1
+  }
+}"#
+        );
+
+        // Once the input is exhausted, the defaults are taken.
+        let mut output = Vec::new();
+        let config = Wizard::new("localhost:80\n".as_bytes(), &mut output).run(&template)?;
+        assert_eq!(
+            config.to_json().compact(true).to_text()?,
+            r#"{"host": "localhost:80", "ports": [80], "server": {"workers": 4, "debug": false}, "ratio": 0.5, "extra": null}"#
+        );
+        Ok(())
+    }
+}
@@ -1,51 +1,543 @@
-use crate::color::ColorProfile;
-use crate::document::{CommentFormat, Document, StrFormat};
+use crate::color::{ColorProfile, HtmlProfile};
+use crate::document::{BytesFormat, CommentFormat, Document, SerdeType, StrFormat};
+use crate::emit::{common_options, guarded, write_sinks, CommonOptions, Emit};
+use crate::entry::{comments_of, MapEntry, SeqItem};
 use crate::error::Error;
-use crate::integer::Int;
+use crate::hexdump;
+use crate::integer::{Base, Int};
+use crate::layout::{Layout, TokenClass, TokenSink};
+use crate::newline::Newline;
+use crate::number::{DefaultNumberFormatter, FloatFormat, NumberContext, NumberFormatter};
+use crate::path::{children, segments};
+use crate::render::{line_width, write_indent, LineMeasure};
+use crate::rust_types::variant_name;
+use crate::sort::KeySort;
+use crate::structural::{self, same, StructuralSet};
+use crate::unambiguous::{is_unambiguous_value, Dialect};
+use crate::width::{fit_width, str_width};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::io;
+use std::sync::Arc;
 
 type Result<T> = std::result::Result<T, Error>;
 
+// The width to which the lines of folded block scalars are wrapped.
+const FOLD_WIDTH: usize = 80;
+
+/// The representation of enum variants in YAML documents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VariantTags {
+    /// Variants are emitted as serialized, as mappings which hold the
+    /// variant name.
+    None,
+    /// An enum variant recorded by the serializer in serde's externally
+    /// tagged form (see `SerdeType::Variant`) is emitted as its content
+    /// with a tag: `{Circle: {r: 1}}` becomes `!Circle {r: 1}`.  Parsed
+    /// documents record no variants, so their mappings are unchanged.
+    External,
+    /// A mapping whose first entry is the given field holding a variant
+    /// name, as serialized for an internally tagged enum (e.g.
+    /// `#[serde(tag = "type")]`), is emitted as the remaining entries with
+    /// a tag: `{type: Circle, r: 1}` becomes `!Circle {r: 1}`.
+    Internal(String),
+}
+
+/// Placement of the comments attached to mapping entries and sequence
+/// elements in YAML documents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum YamlCommentPlacement {
+    /// Comments are emitted where they occur in the entry: those ahead of
+    /// the key on the lines above it, and those after the value at the end
+    /// of the value's line.
+    Preserve,
+    /// Comments are emitted on the lines above the key or element.
+    Above,
+    /// A single single-line comment on a value which is emitted on one line
+    /// is emitted at the end of that line.  Other comments are emitted
+    /// above.
+    Trailing,
+    /// The comments of a mapping's entries are gathered into a block ahead
+    /// of the mapping, above the key or sequence dash which precedes it,
+    /// each labelled with its entry's key.  The comments of sequence
+    /// elements are emitted above.
+    Block,
+}
+
 pub struct Yaml {
     document: Document,
-    indent: usize,
-    color: ColorProfile,
-    compact: bool,
+    common: CommonOptions,
     header: bool,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    block_scalar: StrFormat,
+    anchors: bool,
+    variant_tags: VariantTags,
+    compact_threshold: Option<usize>,
+    quote_all: bool,
+    comment_placement: YamlCommentPlacement,
+    extends: Vec<(String, String)>,
 }
 
 impl Yaml {
-    pub fn indent(mut self, i: usize) -> Self {
-        self.indent = i;
+    common_options!(
+        indent,
+        compact,
+        color,
+        color_sampling,
+        max_output,
+        max_depth,
+        /// Set the order in which mapping entries are emitted.
+        sort_keys,
+        /// Set whether mapping entries whose value is null are omitted, along
+        /// with their comments.
+        skip_null,
+        newline,
+        ascii_comments,
+    );
+    /// Emit any mapping or sequence whose flow form, including its
+    /// indentation, fits within `n` columns on a single line.  Aggregates
+    /// containing comments are emitted in block form, so that their
+    /// comments are kept.
+    pub fn compact_threshold(mut self, n: usize) -> Self {
+        self.compact_threshold = Some(n);
         self
     }
-    pub fn compact(mut self, b: bool) -> Self {
-        self.compact = b;
+    /// Set whether all string values are double-quoted.  Otherwise, only
+    /// strings which a reader could take for another type (e.g. `no`,
+    /// `3.0` or `2024-01-01`) or which hold special characters are quoted.
+    /// Mapping keys and block scalars are unaffected.
+    pub fn quote_all(mut self, b: bool) -> Self {
+        self.quote_all = b;
         self
     }
     pub fn header(mut self, b: bool) -> Self {
         self.header = b;
         self
     }
-    pub fn color(mut self, c: ColorProfile) -> Self {
-        self.color = c;
+    /// Set the rendering of bytes values: a list of integers
+    /// (`BytesFormat::Standard`) or a string in the given format.  Bytes
+    /// annotated with a format are rendered as strings regardless.
+    pub fn bytes_format(mut self, b: BytesFormat) -> Self {
+        self.bytes_format = b;
+        self
+    }
+    /// Set the formatter used to render integer and float values.
+    pub fn number_formatter<N: NumberFormatter + 'static>(mut self, n: N) -> Self {
+        self.numbers = Arc::new(n);
+        self
+    }
+    /// Set the style in which float values are rendered.
+    pub fn float_format(mut self, f: FloatFormat) -> Self {
+        self.float_format = f;
+        self
+    }
+    /// Set the style of strings in the standard format which contain line
+    /// breaks: a literal (`StrFormat::Literal`) or folded
+    /// (`StrFormat::Folded`) block, or a double-quoted string with escapes
+    /// (`StrFormat::Standard`, the default).  Strings in the `Multiline`
+    /// format are emitted as folded blocks if this is `Folded` and as
+    /// literal blocks otherwise.
+    ///
+    /// A string which cannot be expressed as a block, such as one holding
+    /// control characters or a mapping key, is double-quoted regardless.
+    pub fn block_scalar(mut self, f: StrFormat) -> Self {
+        self.block_scalar = f;
+        self
+    }
+    /// Set whether mappings and sequences which occur more than once in the
+    /// document are emitted once with an anchor (`&name`) and elsewhere as
+    /// an alias (`*name`).  Anchors are named after the key of the first
+    /// occurrence.  Repeats must be identical, including their comments.
+    pub fn anchors(mut self, b: bool) -> Self {
+        self.anchors = b;
+        self
+    }
+    /// Set whether enum variants are emitted as YAML tags (`!Circle`)
+    /// rather than as the mappings which hold their names.  A variant name
+    /// starts with an ASCII capital letter and otherwise holds only ASCII
+    /// letters, digits and underscores.  Variants whose entries carry
+    /// comments are emitted as mappings, so that the comments are kept.
+    pub fn variant_tags(mut self, v: VariantTags) -> Self {
+        self.variant_tags = v;
+        self
+    }
+    /// Set where the comments attached to mapping entries and sequence
+    /// elements are emitted.
+    pub fn comment_placement(mut self, p: YamlCommentPlacement) -> Self {
+        self.comment_placement = p;
+        self
+    }
+    /// Emit the mapping at `path` as extending the mapping at `base`: the
+    /// base is emitted with an anchor, and the mapping as a merge key
+    /// (`<<: *base`) followed by only the entries which differ from the
+    /// base's.  Paths are JSON Pointers through mapping keys and sequence
+    /// indices (e.g. `/jobs/1`).
+    ///
+    /// The mapping is emitted in full if it lacks some of the keys of the
+    /// base, as the merge would add them, or if it precedes the base in
+    /// the document, as an alias must follow its anchor.
+    pub fn extends(mut self, path: &str, base: &str) -> Self {
+        self.extends.push((path.to_string(), base.to_string()));
         self
     }
 }
 
-impl fmt::Display for Yaml {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Yaml {
+    /// Lays out the document as lines of classified tokens.  The layout
+    /// uses `\n` line endings regardless of the `newline` option, and the
+    /// `color_sampling` and `max_output` options do not apply.
+    pub fn layout(&self) -> Result<Layout> {
+        Layout::capture(|w| self.emit(w, ColorProfile::default()))
+    }
+
+    /// Renders the document as HTML, marking up each token with a `<span>`
+    /// of the CSS class selected by `profile` rather than with color
+    /// escapes.  See `Layout::render_html`.
+    pub fn to_html(&self, profile: &HtmlProfile) -> Result<String> {
+        Ok(self.layout()?.to_html(profile))
+    }
+
+    fn emit<W: TokenSink>(&self, w: &mut W, color: ColorProfile) -> Result<()> {
+        self.common.check_comments(&self.document)?;
         let mut emitter = YamlEmitter {
             level: -1,
-            indent: self.indent,
-            color: self.color,
-            compact: self.compact,
+            indent: self.common.indent,
+            color,
+            compact: self.common.compact,
             is_key: false,
+            sort_keys: self.common.sort_keys.clone(),
+            skip_null: self.common.skip_null,
+            bytes_format: self.bytes_format,
+            numbers: self.numbers.clone(),
+            float_format: self.float_format,
+            depth: 0,
+            max_depth: self.common.max_depth,
+            block_scalar: self.block_scalar,
+            open_block: false,
+            anchors: HashMap::new(),
+            anchor_names: Vec::new(),
+            anchor_written: false,
+            variant_tags: self.variant_tags.clone(),
+            variants: HashSet::new(),
+            compact_threshold: self.compact_threshold,
+            quote_all: self.quote_all,
+            comment_placement: self.comment_placement,
+            merges: HashMap::new(),
+            written: Vec::new(),
+            column: 0,
+            flow: None,
+            flow_widths: HashMap::new(),
+            merged: HashSet::new(),
+            comment_block: None,
+            color_class: None,
         };
+        if self.anchors {
+            (emitter.anchors, emitter.anchor_names) = find_anchors(&self.document);
+        }
+        if self.variant_tags == VariantTags::External {
+            emitter.variants = self
+                .document
+                .iter()
+                .filter_map(|n| match n {
+                    Document::Typed(SerdeType::Variant, v) => Some(v.as_ref() as *const _ as usize),
+                    _ => None,
+                })
+                .collect();
+        }
+        let plain = emitter.clone();
+        for (path, base) in &self.extends {
+            emitter.extend(&self.document, path, base)?;
+        }
+        // A mapping is emitted with a merge key only if its base precedes
+        // it.  Find the merges which a dry run emits, and register the
+        // anchors of only those merges' bases.
+        if !emitter.merges.is_empty() {
+            let mut dry = emitter.clone();
+            dry.emit_node(&mut Discard, &self.document)?;
+            if dry.merged.len() < emitter.merges.len() {
+                emitter = plain;
+                for (path, base) in &self.extends {
+                    let node = mapping_at(&self.document, &segments(path).collect::<Vec<_>>())?;
+                    if dry.merged.contains(&(node as *const Document as usize)) {
+                        emitter.extend(&self.document, path, base)?;
+                    }
+                }
+            }
+        }
         if self.header {
-            writeln!(f, "---")?;
+            writeln!(w, "---")?;
+        }
+        emitter.emit_node(w, &self.document)?;
+        if emitter.open_block {
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
+
+// Finds the mappings and sequences which occur more than once in `doc`.
+// Returns the anchor of each occurrence, by address, and the names of the
+// anchors.  The repeats of a node are not searched, as their contents are
+// emitted only once.
+fn find_anchors(doc: &Document) -> (HashMap<usize, usize>, Vec<(String, bool)>) {
+    let hashes = structural::hashes(doc);
+    // The distinct nodes with each structural hash, along with the
+    // addresses of their occurrences and the key of the first.
+    let mut seen = HashMap::new();
+    let mut order = Vec::new();
+    let mut stack = vec![(doc, String::new())];
+    while let Some((node, key)) = stack.pop() {
+        let node = match node {
+            Document::Compact(d)
+            | Document::ColorClass(_, d)
+            | Document::Identified(_, d)
+            | Document::Typed(_, d) => {
+                stack.push((d, key));
+                continue;
+            }
+            Document::Fragment(_) => match node.as_kv() {
+                Ok((k, v)) => {
+                    let key = k.as_str().map(str::to_string).unwrap_or(key);
+                    stack.push((v, key));
+                    continue;
+                }
+                Err(_) => match node.as_value() {
+                    Ok(v) => v,
+                    Err(_) => continue,
+                },
+            },
+            _ => node,
+        };
+        let children = match node {
+            Document::Mapping(c) | Document::Sequence(c) if !c.is_empty() => c,
+            _ => continue,
+        };
+        let address = node as *const Document as usize;
+        let hash = hashes[&address];
+        let distinct: &mut Vec<(&Document, Vec<usize>, String)> = seen.entry(hash).or_default();
+        if let Some((_, occurrences, _)) = distinct.iter_mut().find(|(n, _, _)| same(n, node)) {
+            occurrences.push(address);
+            continue;
         }
-        emitter.emit_node(f, &self.document).map_err(|_| fmt::Error)
+        order.push((hash, distinct.len()));
+        distinct.push((node, vec![address], key.clone()));
+        // Push children in reverse so that they are searched in order.
+        for child in children.iter().rev() {
+            stack.push((child, key.clone()));
+        }
+    }
+
+    let mut anchors = HashMap::new();
+    let mut names: Vec<(String, bool)> = Vec::new();
+    for (hash, i) in order {
+        let (_, occurrences, key) = &seen[&hash][i];
+        if occurrences.len() < 2 {
+            continue;
+        }
+        for address in occurrences {
+            anchors.insert(*address, names.len());
+        }
+        names.push((anchor_name(key, &names), false));
+    }
+    (anchors, names)
+}
+
+// Returns an anchor name derived from `key` which is distinct from `names`.
+fn anchor_name(key: &str, names: &[(String, bool)]) -> String {
+    let base = key
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .collect::<String>();
+    let base = if base.is_empty() {
+        "anchor".to_string()
+    } else {
+        base
+    };
+    let mut name = base.clone();
+    let mut n = 1;
+    while names.iter().any(|(other, _)| *other == name) {
+        n += 1;
+        name = format!("{}{}", base, n);
+    }
+    name
+}
+
+// The text and format of the comments of a mapping entry or sequence
+// element.
+type Comments<'n> = Vec<(&'n str, CommentFormat)>;
+
+// A writer which discards its output, for dry runs.
+struct Discard;
+
+impl fmt::Write for Discard {
+    fn write_str(&mut self, _: &str) -> fmt::Result {
+        Ok(())
+    }
+}
+
+// Returns the mapping at `path` beneath `node`.
+fn mapping_at<'a>(node: &'a Document, path: &[String]) -> Result<&'a Document> {
+    let node = node.as_value()?;
+    let (seg, rest) = match (path, node) {
+        ([], Document::Mapping(_)) => return Ok(node),
+        ([], v) => return Err(Error::StructureError("Mapping", v.variant())),
+        ([seg, rest @ ..], _) => (seg.as_str(), rest),
+    };
+    let child = match node {
+        Document::Mapping(_) | Document::Sequence(_) => children(node, seg).into_iter().next(),
+        v => return Err(Error::StructureError("Mapping or Sequence", v.variant())),
+    };
+    mapping_at(
+        child.ok_or_else(|| Error::PathError(seg.to_string()))?,
+        rest,
+    )
+}
+
+// Returns the key and value of a mapping entry without comments.
+fn plain_kv(entry: &Document) -> Option<(&Document, &Document)> {
+    match entry {
+        Document::Fragment(nodes) => match &nodes[..] {
+            [key, value] if key.comment().is_none() && value.comment().is_none() => {
+                Some((key, value))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A stream of YAML documents, each introduced by a `---` marker, as used
+/// for Kubernetes manifests and CI pipelines.
+///
+/// ```
+/// # use serde_annotate::{Document, YamlStream};
+/// let mut stream = YamlStream::new();
+/// stream.push(Document::parse("{kind: \"Service\"}")?.to_yaml());
+/// stream.push(Document::parse("{kind: \"Deployment\"}")?.to_yaml());
+/// let text = stream.to_text()?;
+/// assert_eq!(text, "---\nkind: Service\n---\nkind: Deployment\n");
+/// assert_eq!(YamlStream::split(&text), ["kind: Service\n", "kind: Deployment\n"]);
+/// # Ok::<(), serde_annotate::Error>(())
+/// ```
+#[derive(Default)]
+pub struct YamlStream {
+    documents: Vec<Yaml>,
+}
+
+impl YamlStream {
+    /// Creates an empty stream.
+    pub fn new() -> Self {
+        YamlStream::default()
+    }
+
+    /// Appends a document, which is emitted with the options of `yaml`.
+    /// Every document is introduced by a `---` marker regardless of the
+    /// `header` option.
+    pub fn push(&mut self, mut yaml: Yaml) {
+        yaml.header = true;
+        self.documents.push(yaml);
+    }
+
+    /// Returns the number of documents in the stream.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns whether the stream holds no documents.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Emits the stream as a string.  Each document ends with a line break.
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+        self.write_to(&mut text)?;
+        Ok(text)
+    }
+
+    /// Emits the stream to `writer`.  See `Emit::to_writer`.
+    pub fn to_writer(&self, writer: &mut dyn io::Write) -> Result<()> {
+        write_sinks(&mut [writer], |w| self.write_to(w))
+    }
+
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        for yaml in self.documents.iter() {
+            let text = yaml.to_text()?;
+            w.write_str(&text)?;
+            if !text.ends_with('\n') {
+                w.write_str(match yaml.common.newline {
+                    Newline::Lf => "\n",
+                    Newline::CrLf => "\r\n",
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits the text of a YAML stream into the text of its documents, so
+    /// that each may be read by a YAML loader.  Documents begin after a
+    /// `---` marker, which may be followed by content on the same line,
+    /// and end at the next marker or at a `...` end marker.  Directives
+    /// (e.g. `%YAML 1.2`), comments and blank lines outside of any
+    /// document are skipped.
+    pub fn split(text: &str) -> Vec<&str> {
+        let mut documents = Vec::new();
+        // The start of the current document and whether it began without a
+        // marker, in which case it may hold no document at all.
+        let mut current = (0, true);
+        let mut pos = 0;
+        for line in text.split_inclusive('\n') {
+            let body = line.trim_end_matches(['\n', '\r']);
+            if body == "---" {
+                documents.extend(document(text, current, pos));
+                current = (pos + line.len(), false);
+            } else if body.starts_with("--- ") || body.starts_with("---\t") {
+                documents.extend(document(text, current, pos));
+                current = (pos + 4, false);
+            } else if body == "..." {
+                documents.extend(document(text, current, pos));
+                current = (pos + line.len(), true);
+            }
+            pos += line.len();
+        }
+        documents.extend(document(text, current, text.len()));
+        documents
+    }
+}
+
+// Returns the text of the document which starts as given by `current` and
+// ends at `end`, unless it began without a marker and is blank.
+fn document(text: &str, current: (usize, bool), end: usize) -> Option<&str> {
+    let (start, bare) = current;
+    let document = &text[start..end];
+    if bare && is_blank_document(document) {
+        None
+    } else {
+        Some(document)
+    }
+}
+
+// Returns whether the text outside of a document holds only directives,
+// comments and blank lines.
+fn is_blank_document(text: &str) -> bool {
+    text.lines().all(|l| {
+        let l = l.trim();
+        l.is_empty() || l.starts_with('#') || l.starts_with('%')
+    })
+}
+
+impl Emit for Yaml {
+    fn write_to(&self, w: &mut dyn fmt::Write) -> Result<()> {
+        guarded(&self.common, w, |mut w| {
+            self.emit(&mut w, self.common.color.clone())
+        })
+    }
+
+    fn options(&mut self) -> &mut CommonOptions {
+        &mut self.common
     }
 }
 
@@ -53,20 +545,75 @@ impl Document {
     pub fn to_yaml(self) -> Yaml {
         Yaml {
             document: self,
-            indent: 2,
-            color: ColorProfile::default(),
-            compact: false,
+            common: CommonOptions::default(),
             header: true,
+            bytes_format: BytesFormat::Standard,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+            block_scalar: StrFormat::Standard,
+            anchors: false,
+            variant_tags: VariantTags::None,
+            compact_threshold: None,
+            quote_all: false,
+            comment_placement: YamlCommentPlacement::Preserve,
+            extends: Vec::new(),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct YamlEmitter {
     level: isize,
     indent: usize,
     color: ColorProfile,
     compact: bool,
     is_key: bool,
+    sort_keys: KeySort,
+    skip_null: bool,
+    bytes_format: BytesFormat,
+    numbers: Arc<dyn NumberFormatter>,
+    float_format: FloatFormat,
+    depth: usize,
+    max_depth: Option<usize>,
+    block_scalar: StrFormat,
+    // Whether the last node emitted was a block scalar whose final line
+    // break is part of its value.
+    open_block: bool,
+    // The anchor of each repeated node, by address, and the name of each
+    // anchor along with whether it has been emitted.
+    anchors: HashMap<usize, usize>,
+    anchor_names: Vec<(String, bool)>,
+    // Whether the anchor of the next node was written along with its key.
+    anchor_written: bool,
+    variant_tags: VariantTags,
+    // The enum variants recorded by the serializer, by address.
+    variants: HashSet<usize>,
+    compact_threshold: Option<usize>,
+    quote_all: bool,
+    comment_placement: YamlCommentPlacement,
+    // The anchor of the base of each extending mapping, by address, and the
+    // base's entries.
+    merges: HashMap<usize, (usize, Arc<StructuralSet>)>,
+    // The anchors in the order they were emitted, so that a trial rendering
+    // can be undone.
+    written: Vec<usize>,
+    // The width of the text ahead of the value being emitted on its line,
+    // past the indentation.
+    column: usize,
+    // Whether the aggregate at an address is emitted in flow form, as
+    // decided along with the line break ahead of it.
+    flow: Option<(usize, bool)>,
+    // The width of the flow form of each aggregate measured under the
+    // `compact_threshold` option, by address, or `None` if it is too wide.
+    flow_widths: HashMap<usize, Option<usize>>,
+    // The mappings emitted with a merge key, by address.
+    merged: HashSet<usize>,
+    // The mapping whose comment block was emitted ahead of its key or dash
+    // under `YamlCommentPlacement::Block`, by address.
+    comment_block: Option<usize>,
+    // The color class of the node being emitted, which is the class of its
+    // tokens.
+    color_class: Option<String>,
 }
 
 impl Default for YamlEmitter {
@@ -77,13 +624,38 @@ impl Default for YamlEmitter {
             color: ColorProfile::default(),
             compact: false,
             is_key: false,
+            sort_keys: KeySort::None,
+            skip_null: false,
+            bytes_format: BytesFormat::Standard,
+            numbers: Arc::new(DefaultNumberFormatter),
+            float_format: FloatFormat::Display,
+            depth: 0,
+            max_depth: None,
+            block_scalar: StrFormat::Standard,
+            open_block: false,
+            anchors: HashMap::new(),
+            anchor_names: Vec::new(),
+            anchor_written: false,
+            variant_tags: VariantTags::None,
+            variants: HashSet::new(),
+            compact_threshold: None,
+            quote_all: false,
+            comment_placement: YamlCommentPlacement::Preserve,
+            merges: HashMap::new(),
+            written: Vec::new(),
+            column: 0,
+            flow: None,
+            flow_widths: HashMap::new(),
+            merged: HashSet::new(),
+            comment_block: None,
+            color_class: None,
         }
     }
 }
 
 impl YamlEmitter {
-    const SPACE: &'static str = "                                                                                                    ";
-    fn emit_node<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+    fn emit_node<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        self.open_block = false;
         match node {
             Document::Comment(c, f) => self.emit_comment_newline(w, c, f),
             Document::String(v, f) => self.emit_string(w, v.as_str(), *f),
@@ -91,11 +663,35 @@ impl YamlEmitter {
             Document::Boolean(v) => self.emit_boolean(w, *v),
             Document::Int(v) => self.emit_int(w, v),
             Document::Float(v) => self.emit_float(w, *v),
-            Document::Mapping(m) => self.emit_mapping(w, m),
-            Document::Sequence(s) => self.emit_sequence(w, s),
+            Document::Mapping(_) | Document::Sequence(_) => {
+                let anchor_written = std::mem::take(&mut self.anchor_written);
+                match self.anchor(node) {
+                    Some((name, true)) => {
+                        return self.token(w, TokenClass::Punctuation, &format!("*{}", name))
+                    }
+                    Some((name, false)) => {
+                        if !anchor_written {
+                            self.token(w, TokenClass::Punctuation, &format!("&{}", name))?;
+                            write!(w, " ")?;
+                        }
+                        let i = self.anchors[&(node as *const Document as usize)];
+                        self.anchor_names[i].1 = true;
+                        self.written.push(i);
+                    }
+                    None => {}
+                }
+                if let Some((name, content)) = self.variant(node) {
+                    self.emit_helper(w, &format!("!{}", name), &content)?;
+                    return self.emit_node(w, &content);
+                }
+                self.emit_aggregate(w, node)
+            }
             Document::Bytes(v) => self.emit_bytes(w, v),
             Document::Null => self.emit_null(w),
+            Document::Absent => Err(Error::AbsentValue),
             Document::Compact(d) => self.emit_compact(w, d),
+            Document::ColorClass(c, d) => self.emit_color_class(w, c, d),
+            Document::Identified(_, d) | Document::Typed(_, d) => self.emit_node(w, d),
             Document::Fragment(ds) => {
                 match &ds[..] {
                     [n, Document::Comment(c, f)] => {
@@ -122,7 +718,7 @@ impl YamlEmitter {
         }
     }
 
-    fn emit_compact<W: fmt::Write>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+    fn emit_compact<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<()> {
         let compact = self.compact;
         self.compact = true;
         self.emit_node(w, node)?;
@@ -130,66 +726,266 @@ impl YamlEmitter {
         Ok(())
     }
 
-    fn emit_bytes<W: fmt::Write>(&mut self, w: &mut W, bytes: &[u8]) -> Result<()> {
-        self.writeln(w, &self.color.aggregate.paint("[").to_string())?;
+    fn emit_color_class<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        class: &str,
+        node: &Document,
+    ) -> Result<()> {
+        let saved = (
+            self.color.clone(),
+            self.color_class.replace(class.to_string()),
+        );
+        self.color = self.color.for_class(class);
+        let result = self.emit_node(w, node);
+        (self.color, self.color_class) = saved;
+        result
+    }
+
+    fn emit_bytes<W: TokenSink>(&mut self, w: &mut W, bytes: &[u8]) -> Result<()> {
+        if let Some(string) = hexdump::to_string(bytes, self.bytes_format) {
+            return self.emit_string(w, &string, self.bytes_format.str_format());
+        }
+        self.token(w, TokenClass::Aggregate, "[")?;
+        self.writeln(w, "")?;
         self.emit_indent(w)?;
         for (i, chunk) in bytes.chunks(16).enumerate() {
             if i > 0 {
                 self.writeln(w, "")?;
             }
-            let color = if self.is_key {
-                &self.color.key
+            let class = if self.is_key {
+                TokenClass::Key
             } else {
-                &self.color.integer
+                TokenClass::Integer
             };
             for b in chunk {
-                write!(w, "{}", color.paint(format!("0x{:02X},", b)))?;
+                self.token(w, class.clone(), &format!("0x{:02X},", b))?;
             }
         }
         self.writeln(w, "")?;
-        write!(w, "{}", self.color.aggregate.paint("]"))?;
+        self.token(w, TokenClass::Aggregate, "]")?;
         self.emit_indent(w)?;
         Ok(())
     }
 
-    fn emit_helper<W: fmt::Write>(
+    // Writes the `prefix` (a key's colon, a sequence dash or a tag) ahead of
+    // a value, along with the value's anchor, and the line break ahead of an
+    // aggregate which is not emitted in flow form.
+    fn emit_helper<W: TokenSink>(
         &mut self,
         w: &mut W,
         prefix: &str,
         value: &Document,
     ) -> Result<()> {
+        if let Document::ColorClass(_, v) | Document::Identified(_, v) | Document::Typed(_, v) =
+            value
+        {
+            return self.emit_helper(w, prefix, v);
+        }
+        let column = self.column + str_width(prefix) + 1;
+        self.token(w, TokenClass::Punctuation, prefix)?;
         match value {
-            Document::Sequence(v) => {
-                if self.compact || v.is_empty() {
-                    write!(w, "{} ", prefix)?;
-                } else {
-                    writeln!(w, "{}", prefix)?;
-                    self.emit_indent_extra(w, 1)?
+            Document::Sequence(v) | Document::Mapping(v) => match self.anchor(value) {
+                Some((_, true)) => write!(w, " ")?,
+                Some((name, false)) if !self.compact => {
+                    write!(w, " ")?;
+                    self.token(w, TokenClass::Punctuation, &format!("&{}", name))?;
+                    writeln!(w)?;
+                    self.emit_indent_extra(w, 1)?;
+                    self.anchor_written = true;
+                    self.column = 0;
+                    return Ok(());
+                }
+                _ if self.compact || v.is_empty() || self.variant(value).is_some() => {
+                    write!(w, " ")?
+                }
+                _ => {
+                    self.column = column;
+                    let flow = self.fits_compact(value)?;
+                    self.flow = Some((value as *const Document as usize, flow));
+                    if flow {
+                        write!(w, " ")?;
+                    } else {
+                        writeln!(w)?;
+                        self.emit_indent_extra(w, 1)?;
+                        self.column = 0;
+                        return Ok(());
+                    }
                 }
+            },
+            _ => write!(w, " ")?,
+        };
+        self.column = column;
+        Ok(())
+    }
+
+    // Returns the anchor name of a repeated node and whether the node has
+    // already been emitted.
+    fn anchor(&self, node: &Document) -> Option<(String, bool)> {
+        let i = self.anchors.get(&(node as *const Document as usize))?;
+        Some(self.anchor_names[*i].clone())
+    }
+
+    // Returns the variant name and the content of a mapping which
+    // represents an enum variant under the `variant_tags` option.
+    fn variant<'d>(&self, node: &'d Document) -> Option<(&'d str, Cow<'d, Document>)> {
+        let mapping = match node {
+            Document::Mapping(m) => m,
+            _ => return None,
+        };
+        match (&self.variant_tags, &mapping[..]) {
+            (VariantTags::External, [entry])
+                if self.variants.contains(&(node as *const Document as usize)) =>
+            {
+                let (key, value) = plain_kv(entry)?;
+                Some((variant_name(key)?, Cow::Borrowed(value)))
             }
-            Document::Mapping(v) => {
-                if self.compact || v.is_empty() {
-                    write!(w, "{} ", prefix)?;
-                } else {
-                    writeln!(w, "{}", prefix)?;
-                    self.emit_indent_extra(w, 1)?
+            (VariantTags::Internal(field), [entry, rest @ ..]) => {
+                let (key, value) = plain_kv(entry)?;
+                if key.as_str().ok()? != field {
+                    return None;
                 }
+                let content = Document::Mapping(rest.to_vec());
+                Some((variant_name(value)?, Cow::Owned(content)))
             }
-            _ => write!(w, "{} ", prefix)?,
+            _ => None,
+        }
+    }
+
+    // Returns whether an aggregate should be emitted in flow form under
+    // the `compact_threshold` option, following the text ahead of it on its
+    // line.
+    fn fits_compact(&mut self, node: &Document) -> Result<bool> {
+        let threshold = match self.compact_threshold {
+            Some(t) if !self.compact => t,
+            _ => return Ok(false),
         };
-        Ok(())
+        let address = node as *const Document as usize;
+        let width = match self.flow_widths.get(&address) {
+            Some(width) => *width,
+            None => {
+                let width = self.measure(threshold, |e, w| {
+                    e.compact = true;
+                    e.emit_aggregate(w, node)
+                })?;
+                // Comments cannot be written in flow form.
+                let width = width.filter(|_| !node.iter().any(|n| n.comment().is_some()));
+                self.flow_widths.insert(address, width);
+                width
+            }
+        };
+        let indent = self.level.max(0) as usize * self.indent + self.column;
+        Ok(matches!(width, Some(width) if indent + width <= threshold))
+    }
+
+    // Returns the width of the key of a mapping entry, if it is needed to
+    // measure the entry's value under the `compact_threshold` option.
+    fn key_width(&mut self, entry: &Document) -> Result<usize> {
+        let key = match entry.as_kv() {
+            Ok((key, _)) if self.compact_threshold.is_some() && !self.compact => key,
+            _ => return Ok(0),
+        };
+        let width = self.measure(usize::MAX, |e, w| {
+            e.is_key = true;
+            e.emit_node(w, key)
+        })?;
+        Ok(width.unwrap_or(0))
     }
 
-    fn emit_sequence<W: fmt::Write>(&mut self, w: &mut W, sequence: &[Document]) -> Result<()> {
+    // Returns the width of the text written by `render` on this emitter
+    // without color, or `None` if it does not fit on one line of `limit`
+    // columns.  The emitter's state is restored afterwards.
+    fn measure<F>(&mut self, limit: usize, render: F) -> Result<Option<usize>>
+    where
+        F: FnOnce(&mut Self, &mut LineMeasure) -> Result<()>,
+    {
+        line_width(limit, |w| self.trial(w, render))
+    }
+
+    // Writes the text written by `render` on this emitter without color, and
+    // restores the emitter's state afterwards.
+    fn trial<W, F>(&mut self, w: &mut W, render: F) -> Result<()>
+    where
+        W: TokenSink,
+        F: FnOnce(&mut Self, &mut W) -> Result<()>,
+    {
+        let saved = (
+            self.level,
+            self.depth,
+            self.compact,
+            self.color.clone(),
+            self.is_key,
+            self.open_block,
+            self.anchor_written,
+            self.column,
+            self.flow,
+            self.comment_block,
+        );
+        let written = self.written.len();
+        self.color = ColorProfile::default();
+        let result = render(self, w);
+        (
+            self.level,
+            self.depth,
+            self.compact,
+            self.color,
+            self.is_key,
+            self.open_block,
+            self.anchor_written,
+            self.column,
+            self.flow,
+            self.comment_block,
+        ) = saved;
+        for i in self.written.drain(written..) {
+            self.anchor_names[i].1 = false;
+        }
+        result
+    }
+
+    fn emit_aggregate<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        match self.max_depth {
+            Some(n) if self.depth >= n => return Err(Error::DepthLimit(n)),
+            _ => {}
+        }
+        // The node is emitted here rather than by `emit_compact`, as its
+        // anchor has already been written.
+        let compact = self.compact;
+        let address = node as *const Document as usize;
+        let flow = match self.flow.take() {
+            Some((a, flow)) if a == address => flow,
+            _ => self.fits_compact(node)?,
+        };
+        // The node may be a temporary whose address is reused.
+        self.flow_widths.remove(&address);
+        self.compact = compact || flow;
+        self.depth += 1;
+        let result = match node {
+            Document::Mapping(m) => {
+                let merge = self.merge(node);
+                if merge.is_some() {
+                    self.merged.insert(address);
+                }
+                let block = self.comment_block.take() != Some(address);
+                self.emit_mapping(w, m, merge, block)
+            }
+            Document::Sequence(s) => self.emit_sequence(w, s),
+            _ => unreachable!(),
+        };
+        self.depth -= 1;
+        self.compact = compact;
+        result
+    }
+
+    fn emit_sequence<W: TokenSink>(&mut self, w: &mut W, sequence: &[Document]) -> Result<()> {
         if self.compact || sequence.is_empty() {
-            write!(w, "{}", self.color.aggregate.paint("["))?;
+            self.token(w, TokenClass::Aggregate, "[")?;
             for (i, v) in sequence.iter().enumerate() {
                 if i > 0 {
-                    write!(w, "{}", self.color.punctuation.paint(", "))?;
+                    self.token(w, TokenClass::Punctuation, ", ")?;
                 }
                 self.emit_node(w, v)?;
             }
-            write!(w, "{}", self.color.aggregate.paint("]"))?;
+            self.token(w, TokenClass::Aggregate, "]")?;
         } else {
             self.level += 1;
             for (i, value) in sequence.iter().enumerate() {
@@ -197,49 +993,113 @@ impl YamlEmitter {
                     writeln!(w)?;
                     self.emit_indent(w)?
                 }
-                if let Document::Fragment(frags) = value {
-                    let mut val_done = false;
-                    let mut it = frags.iter().peekable();
-                    loop {
-                        let node = if let Some(n) = it.next() {
-                            n
-                        } else {
-                            break;
-                        };
-                        let next = it.peek();
-                        if let Some((c, f)) = node.comment() {
-                            if val_done {
-                                write!(w, " ")?;
-                            }
-                            if self.emit_comment(w, c, f)? && next.is_some() {
-                                self.writeln(w, "")?;
-                                self.emit_indent(w)?;
-                            }
-                            continue;
-                        }
-                        self.emit_helper(w, &self.color.punctuation.paint("-").to_string(), node)?;
-                        self.emit_node(w, node)?;
-                        val_done = true;
+                let item = match SeqItem::new(value) {
+                    Ok(item) => item,
+                    Err(_) => {
+                        self.emit_loose_comments(w, value)?;
+                        continue;
                     }
-                } else {
-                    self.emit_helper(w, &self.color.punctuation.paint("-").to_string(), value)?;
-                    self.emit_node(w, value)?;
+                };
+                self.column = 0;
+                let (above, after) =
+                    self.arrange(item.value, item.comments, item.trailing, false)?;
+                for (c, f) in above {
+                    self.emit_comment_newline(w, c, &f)?;
                 }
+                self.emit_nested_block(w, item.value, 2)?;
+                self.column = 0;
+                self.emit_helper(w, "-", item.value)?;
+                self.emit_node(w, item.value)?;
+                self.emit_after_comments(w, &after)?;
             }
             self.level -= 1;
         }
         Ok(())
     }
 
-    fn emit_mapping<W: fmt::Write>(&mut self, w: &mut W, mapping: &[Document]) -> Result<()> {
-        if self.compact || mapping.is_empty() {
-            write!(w, "{}", self.color.aggregate.paint("{"))?;
+    // Registers the mapping at `path` as extending the mapping at `base`,
+    // unless a merge key would add entries which the mapping lacks.
+    fn extend(&mut self, doc: &Document, path: &str, base: &str) -> Result<()> {
+        let node = mapping_at(doc, &segments(path).collect::<Vec<_>>())?;
+        let base_node = mapping_at(doc, &segments(base).collect::<Vec<_>>())?;
+        let (entries, base_entries) = match (node, base_node) {
+            (Document::Mapping(a), Document::Mapping(b)) => (a, b),
+            _ => unreachable!(),
+        };
+        let keys = entries
+            .iter()
+            .filter_map(|e| Some(e.as_kv().ok()?.0))
+            .collect::<StructuralSet>();
+        let missing = base_entries
+            .iter()
+            .filter_map(|e| Some(e.as_kv().ok()?.0))
+            .any(|k| !keys.contains(k));
+        if base_entries.is_empty() || missing {
+            return Ok(());
+        }
+        let address = base_node as *const Document as usize;
+        let anchor = match self.anchors.get(&address) {
+            Some(i) => *i,
+            None => {
+                // Name the anchor after the nearest key, as sequence indices
+                // are not descriptive.
+                let key = segments(base)
+                    .rfind(|s| s.parse::<usize>().is_err())
+                    .unwrap_or_else(|| "base".to_string());
+                let name = anchor_name(&key, &self.anchor_names);
+                self.anchors.insert(address, self.anchor_names.len());
+                self.anchor_names.push((name, false));
+                self.anchor_names.len() - 1
+            }
+        };
+        let base_entries = base_entries.iter().filter(|e| e.as_kv().is_ok()).collect();
+        let address = node as *const Document as usize;
+        self.merges
+            .insert(address, (anchor, Arc::new(base_entries)));
+        Ok(())
+    }
+
+    // Returns the anchor name of the base of an extending mapping and the
+    // base's entries, if the base has been emitted.
+    fn merge(&self, node: &Document) -> Option<(String, Arc<StructuralSet>)> {
+        let (i, base_entries) = self.merges.get(&(node as *const Document as usize))?;
+        match &self.anchor_names[*i] {
+            (name, true) => Some((name.clone(), base_entries.clone())),
+            _ => None,
+        }
+    }
+
+    fn emit_mapping<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        mapping: &[Document],
+        merge: Option<(String, Arc<StructuralSet>)>,
+        block: bool,
+    ) -> Result<()> {
+        let entries = self.entries(mapping, &merge);
+        let empty = entries.is_empty() && merge.is_none();
+        if self.compact || empty {
+            self.token(w, TokenClass::Aggregate, "{")?;
         } else {
             self.level += 1;
+            if block && self.comment_placement == YamlCommentPlacement::Block {
+                self.emit_comment_block(w, &entries)?;
+            }
         }
-        for (i, frag) in mapping.iter().enumerate() {
-            let nodes = frag.fragments()?;
-            if i > 0 {
+        if let Some((name, _)) = &merge {
+            self.token(w, TokenClass::Key, "<<")?;
+            self.token(w, TokenClass::Punctuation, ":")?;
+            write!(w, " ")?;
+            self.token(w, TokenClass::Punctuation, &format!("*{}", name))?;
+        }
+        let mut first = merge.is_none();
+        for node in entries {
+            let entry = match MapEntry::new(node) {
+                Ok(entry) => Some(entry),
+                Err(_) if self.compact => continue,
+                Err(_) => None,
+            };
+            if !std::mem::take(&mut first) {
                 if self.compact {
                     write!(w, ", ")?;
                 } else {
@@ -247,56 +1107,190 @@ impl YamlEmitter {
                     self.emit_indent(w)?;
                 }
             }
-            let mut key_done = false;
-            let mut val_done = false;
-            let mut it = nodes.iter().peekable();
-            loop {
-                let node = if let Some(n) = it.next() {
-                    n
-                } else {
-                    break;
-                };
-                let next = it.peek();
-                if let Some((c, f)) = node.comment() {
-                    if val_done {
-                        write!(w, " ")?;
-                    }
-                    if self.emit_comment(w, c, f)? && next.is_some() {
-                        self.writeln(w, "")?;
-                        self.emit_indent(w)?;
-                    }
-                    continue;
-                }
-                if !key_done {
-                    if next.is_none() {
-                        return Err(Error::StructureError("a node", "none"));
-                    }
-                    let k = self.is_key;
-                    self.is_key = true;
-                    self.emit_node(w, node)?;
-                    self.is_key = k;
-                    key_done = true;
-                    self.emit_helper(
-                        w,
-                        &self.color.punctuation.paint(":").to_string(),
-                        next.unwrap(),
-                    )?;
-                } else if !val_done {
-                    self.emit_node(w, node)?;
-                    val_done = true;
+            let entry = match entry {
+                Some(entry) => entry,
+                None => {
+                    self.emit_loose_comments(w, node)?;
                     continue;
                 }
+            };
+            let key_width = self.key_width(node)?;
+            self.column = key_width;
+            let (above, after) = self.arrange(entry.value, entry.comments, entry.trailing, true)?;
+            for (c, f) in above {
+                self.emit_comment_newline(w, c, &f)?;
             }
+            self.emit_nested_block(w, entry.value, key_width + 2)?;
+            let k = self.is_key;
+            self.is_key = true;
+            self.emit_node(w, entry.key)?;
+            self.is_key = k;
+            self.column = key_width;
+            self.emit_helper(w, ":", entry.value)?;
+            self.emit_node(w, entry.value)?;
+            self.emit_after_comments(w, &after)?;
         }
-        if self.compact || mapping.is_empty() {
-            write!(w, "{}", self.color.aggregate.paint("}"))?;
+        if self.compact || empty {
+            self.token(w, TokenClass::Aggregate, "}")?;
         } else {
             self.level -= 1;
         }
         Ok(())
     }
 
-    fn emit_comment_newline<W: fmt::Write>(
+    // Returns the entries of a mapping which are emitted, in emit order.
+    fn entries<'m>(
+        &self,
+        mapping: &'m [Document],
+        merge: &Option<(String, Arc<StructuralSet>)>,
+    ) -> Vec<&'m Document> {
+        let mut entries = self.sort_keys.order(mapping);
+        if self.skip_null {
+            entries.retain(|e| !e.is_null_entry());
+        }
+        if let Some((_, base_entries)) = merge {
+            entries.retain(|e| !base_entries.contains(e));
+        }
+        entries
+    }
+
+    // Emits the comment block of `value` ahead of the key or dash which
+    // precedes it, if `value` is a mapping emitted in block form.  `column`
+    // is the column of `value` if it were emitted in flow form.
+    fn emit_nested_block<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        mut value: &Document,
+        column: usize,
+    ) -> Result<()> {
+        if self.comment_placement != YamlCommentPlacement::Block || self.compact {
+            return Ok(());
+        }
+        while let Document::ColorClass(_, v) | Document::Identified(_, v) | Document::Typed(_, v) =
+            value
+        {
+            value = v;
+        }
+        let mapping = match value {
+            Document::Mapping(m) if !m.is_empty() => m,
+            _ => return Ok(()),
+        };
+        if matches!(self.anchor(value), Some((_, true))) || self.variant(value).is_some() {
+            return Ok(());
+        }
+        let saved = std::mem::replace(&mut self.column, column);
+        let flow = self.fits_compact(value);
+        self.column = saved;
+        if flow? {
+            return Ok(());
+        }
+        let merge = self.merge(value);
+        let entries = self.entries(mapping, &merge);
+        self.emit_comment_block(w, &entries)?;
+        self.comment_block = Some(value as *const Document as usize);
+        Ok(())
+    }
+
+    // Places the comments of a mapping entry or sequence element, which
+    // precede and follow its `value`, under the `comment_placement` option.
+    // Returns the comments emitted on the lines above the entry and those
+    // emitted after its value.
+    fn arrange<'n>(
+        &mut self,
+        value: &Document,
+        mut comments: Comments<'n>,
+        trailing: Comments<'n>,
+        mapping: bool,
+    ) -> Result<(Comments<'n>, Comments<'n>)> {
+        match self.comment_placement {
+            _ if self.compact => return Ok((Vec::new(), Vec::new())),
+            YamlCommentPlacement::Preserve => return Ok((comments, trailing)),
+            YamlCommentPlacement::Block if mapping => return Ok((Vec::new(), Vec::new())),
+            _ => {}
+        }
+        comments.extend(trailing);
+        let single = matches!(&comments[..], [(c, _)] if !c.contains('\n'));
+        if self.comment_placement == YamlCommentPlacement::Trailing
+            && single
+            && self.single_line(value)?
+        {
+            return Ok((Vec::new(), comments));
+        }
+        Ok((comments, Vec::new()))
+    }
+
+    // Returns whether `node` is emitted on a single line.
+    fn single_line(&mut self, node: &Document) -> Result<bool> {
+        let value = node.as_value()?;
+        if let Document::Mapping(v) | Document::Sequence(v) = value {
+            return Ok(v.is_empty()
+                || matches!(self.anchor(value), Some((_, true)))
+                || self.fits_compact(value)?);
+        }
+        Ok(self
+            .measure(usize::MAX, |e, w| e.emit_node(w, node))?
+            .is_some())
+    }
+
+    // Emits the comments of a mapping's entries on the lines ahead, each
+    // labelled with the key of its entry.
+    fn emit_comment_block<W: TokenSink>(&mut self, w: &mut W, entries: &[&Document]) -> Result<()> {
+        for entry in entries {
+            let label = match entry.as_kv() {
+                Ok((key, _)) => {
+                    let mut text = String::new();
+                    self.trial(&mut text, |e, w| {
+                        e.compact = true;
+                        e.is_key = true;
+                        e.emit_node(w, key)
+                    })?;
+                    Some(text)
+                }
+                Err(_) => None,
+            };
+            for (c, f) in entry.fragments()?.iter().filter_map(Document::comment) {
+                match &label {
+                    Some(key) => self.emit_comment_newline(w, &format!("{}: {}", key, c), f)?,
+                    None => self.emit_comment_newline(w, c, f)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Emits the comments following a value: the first on the value's line,
+    // and any others on lines of their own.
+    fn emit_after_comments<W: TokenSink>(
+        &mut self,
+        w: &mut W,
+        comments: &[(&str, CommentFormat)],
+    ) -> Result<()> {
+        for (i, (c, f)) in comments.iter().enumerate() {
+            if i == 0 {
+                write!(w, " ")?;
+            } else {
+                self.writeln(w, "")?;
+                self.emit_indent(w)?;
+            }
+            self.emit_comment(w, c, f)?;
+        }
+        Ok(())
+    }
+
+    // Emits the comments of an aggregate's node which holds no entry, such
+    // as those following its last entry, each on a line of its own.
+    fn emit_loose_comments<W: TokenSink>(&mut self, w: &mut W, node: &Document) -> Result<()> {
+        for (i, (c, f)) in comments_of(node).into_iter().enumerate() {
+            if i > 0 {
+                self.writeln(w, "")?;
+                self.emit_indent(w)?;
+            }
+            self.emit_comment(w, c, &f)?;
+        }
+        Ok(())
+    }
+
+    fn emit_comment_newline<W: TokenSink>(
         &mut self,
         w: &mut W,
         comment: &str,
@@ -309,7 +1303,7 @@ impl YamlEmitter {
         Ok(())
     }
 
-    fn emit_comment<W: fmt::Write>(
+    fn emit_comment<W: TokenSink>(
         &mut self,
         w: &mut W,
         comment: &str,
@@ -322,13 +1316,9 @@ impl YamlEmitter {
                     self.emit_indent(w)?;
                 }
                 if line.is_empty() {
-                    write!(w, "{}", &self.color.comment.paint("#").to_string())?;
+                    self.token(w, TokenClass::Comment, "#")?;
                 } else {
-                    write!(
-                        w,
-                        "{}",
-                        &self.color.comment.paint(format!("# {}", line)).to_string()
-                    )?;
+                    self.token(w, TokenClass::Comment, &format!("# {}", line))?;
                 }
             }
             Ok(true)
@@ -337,125 +1327,236 @@ impl YamlEmitter {
         }
     }
 
-    fn emit_string<W: fmt::Write>(&mut self, w: &mut W, value: &str, f: StrFormat) -> Result<()> {
-        match f {
-            StrFormat::Multiline => self.emit_string_multiline(w, value)?,
-            StrFormat::Quoted => self.escape_str(w, value, true)?,
-            StrFormat::Unquoted | StrFormat::Standard => {
-                self.escape_str(w, value, need_quotes(value))?
+    fn emit_string<W: TokenSink>(&mut self, w: &mut W, value: &str, f: StrFormat) -> Result<()> {
+        let block = match (f, self.block_scalar) {
+            (StrFormat::Literal | StrFormat::Folded, _) => Some(f),
+            (StrFormat::Multiline, StrFormat::Folded) => Some(StrFormat::Folded),
+            (StrFormat::Multiline, _) => Some(StrFormat::Literal),
+            (StrFormat::Standard, b @ (StrFormat::Literal | StrFormat::Folded))
+                if value.contains('\n') =>
+            {
+                Some(b)
             }
+            _ => None,
+        };
+        match block {
+            Some(b) if !self.is_key && !self.compact && Self::is_blockable(value) => {
+                self.emit_block(w, value, b == StrFormat::Folded)?
+            }
+            Some(_) => self.escape_str(w, value, true)?,
+            None if f == StrFormat::Quoted || (self.quote_all && !self.is_key) => {
+                self.escape_str(w, value, true)?
+            }
+            None => self.escape_str(w, value, !is_unambiguous_value(value, Dialect::Yaml))?,
         }
         Ok(())
     }
 
-    fn emit_string_multiline<W: fmt::Write>(&mut self, w: &mut W, mut value: &str) -> Result<()> {
-        if value.ends_with('\n') {
-            write!(w, "{}", self.color.punctuation.paint("|+"))?;
-            value = &value[..value.len() - 1];
+    // Returns whether a string can be expressed as a block scalar, which
+    // cannot hold escapes.
+    fn is_blockable(value: &str) -> bool {
+        !value.trim_end_matches('\n').is_empty()
+            && !value
+                .chars()
+                .any(|c| (c.is_control() && c != '\n' && c != '\t') || c == '\u{feff}')
+    }
+
+    // Emits a string as a literal or folded block scalar.  The chomping
+    // indicator preserves the number of trailing line breaks.
+    fn emit_block<W: TokenSink>(&mut self, w: &mut W, value: &str, folded: bool) -> Result<()> {
+        let body = value.trim_end_matches('\n');
+        let chomp = match value.len() - body.len() {
+            0 => "-",
+            1 => "",
+            _ => "+",
+        };
+        // The indentation of a block is detected from its first non-empty
+        // line, so must be given if that line begins with a space.
+        let first = body.split('\n').find(|l| !l.is_empty()).unwrap_or_default();
+        let indicator = if first.starts_with(' ') {
+            self.indent.to_string()
         } else {
-            write!(w, "{}", self.color.punctuation.paint("|-"))?;
+            String::new()
+        };
+        // Lines which begin with whitespace are not folded, so folding is
+        // only used when no line does.
+        let folded = folded && !body.split('\n').any(|l| l.starts_with([' ', '\t']));
+        let style = if folded { ">" } else { "|" };
+        let header = format!("{}{}{}", style, indicator, chomp);
+        self.token(w, TokenClass::Punctuation, &header)?;
+        // The lines are indented below the value's parent, and by at least
+        // one level at the root, where readers reject unindented blocks.
+        let level = self.level;
+        self.level = (level + 1).max(1);
+        let mut lines = Vec::new();
+        let mut prev_empty = true;
+        for line in body.split('\n') {
+            // A single line break between folded lines reads as a space, so
+            // each break is written as an extra empty line.
+            if folded && !prev_empty {
+                lines.push("");
+            }
+            prev_empty = line.is_empty();
+            if folded {
+                lines.extend(self.fold(line));
+            } else {
+                lines.push(line);
+            }
+        }
+        if chomp == "+" {
+            // The trailing line breaks beyond the first are empty lines.
+            lines.extend(std::iter::repeat("").take(value.len() - body.len() - 1));
         }
-        self.level += 1;
-        for line in value.split('\n') {
+        for line in lines {
             writeln!(w)?;
-            self.emit_indent(w)?;
-            self.escape_str(w, line, false)?;
+            if !line.is_empty() {
+                self.emit_indent(w)?;
+                self.token(w, TokenClass::String, &line)?;
+            }
         }
-        self.level -= 1;
+        self.level = level;
+        self.open_block = chomp != "-";
         Ok(())
     }
 
-    fn emit_boolean<W: fmt::Write>(&mut self, w: &mut W, b: bool) -> Result<()> {
-        let color = if self.is_key {
-            &self.color.key
+    // Splits a line of a folded block at single spaces so that it fits
+    // within `FOLD_WIDTH` columns where possible.
+    fn fold<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        let width = FOLD_WIDTH.saturating_sub(self.level.max(0) as usize * self.indent);
+        let mut parts = Vec::new();
+        let mut rest = line;
+        while str_width(rest) > width {
+            // Break at the last single space which fits, or else the first.
+            let bytes = rest.as_bytes();
+            let breakable = |i: &usize| {
+                bytes[*i] == b' '
+                    && *i > 0
+                    && bytes[*i - 1] != b' '
+                    && bytes.get(*i + 1).map_or(false, |b| *b != b' ')
+            };
+            let limit = fit_width(rest, width);
+            let at = (0..limit)
+                .rev()
+                .find(breakable)
+                .or_else(|| (limit..rest.len()).find(breakable));
+            match at {
+                Some(i) => {
+                    parts.push(&rest[..i]);
+                    rest = &rest[i + 1..];
+                }
+                None => break,
+            }
+        }
+        parts.push(rest);
+        parts
+    }
+
+    fn emit_boolean<W: TokenSink>(&mut self, w: &mut W, b: bool) -> Result<()> {
+        let class = if self.is_key {
+            TokenClass::Key
         } else {
-            &self.color.boolean
+            TokenClass::Boolean
         };
-        if b {
-            write!(w, "{}", color.paint("true"))?;
-        } else {
-            write!(w, "{}", color.paint("false"))?;
-        }
-        Ok(())
+        self.token(w, class, if b { "true" } else { "false" })
     }
 
-    fn emit_int<W: fmt::Write>(&mut self, w: &mut W, i: &Int) -> Result<()> {
-        let color = if self.is_key {
-            &self.color.key
+    fn emit_int<W: TokenSink>(&mut self, w: &mut W, i: &Int) -> Result<()> {
+        let class = if self.is_key {
+            TokenClass::Key
         } else {
-            &self.color.integer
+            TokenClass::Integer
         };
-        write!(w, "{}", color.paint(i.to_string()))?;
-        Ok(())
+        let ctx = NumberContext {
+            base: i.base(),
+            width: i.width(),
+            precision: None,
+            float: FloatFormat::Display,
+        };
+        // The dialect has no integer type suffixes.
+        let i = &i.unsuffixed();
+        i.check_format(ctx.base)?;
+        self.token(w, class, &self.numbers.format_int(i, &ctx))
     }
 
-    fn emit_float<W: fmt::Write>(&mut self, w: &mut W, f: f64) -> Result<()> {
-        let color = if self.is_key {
-            &self.color.key
+    fn emit_float<W: TokenSink>(&mut self, w: &mut W, f: f64) -> Result<()> {
+        let class = if self.is_key {
+            TokenClass::Key
         } else {
-            &self.color.float
+            TokenClass::Float
         };
-        write!(w, "{}", color.paint(f.to_string()))?;
-        Ok(())
+        if !f.is_finite() {
+            let s = if f.is_nan() {
+                ".nan"
+            } else if f > 0.0 {
+                ".inf"
+            } else {
+                "-.inf"
+            };
+            return self.token(w, class, s);
+        }
+        let ctx = NumberContext {
+            base: Base::Dec,
+            width: 0,
+            precision: None,
+            float: self.float_format,
+        };
+        self.token(w, class, &self.numbers.format_float(f, &ctx))
     }
 
-    fn emit_null<W: fmt::Write>(&mut self, w: &mut W) -> Result<()> {
-        let color = if self.is_key {
-            &self.color.key
+    fn emit_null<W: TokenSink>(&mut self, w: &mut W) -> Result<()> {
+        let class = if self.is_key {
+            TokenClass::Key
         } else {
-            &self.color.null
+            TokenClass::Null
+        };
+        self.token(w, class, "null")
+    }
+
+    // Writes `text` as a token of `class`.  Within a node of a color class,
+    // every token but a comment is of that class.
+    fn token<W: TokenSink>(&self, w: &mut W, class: TokenClass, text: &str) -> Result<()> {
+        let style = self.color.style(&class);
+        let class = match &self.color_class {
+            Some(name) if class != TokenClass::Comment => TokenClass::Class(name.clone()),
+            _ => class,
         };
-        write!(w, "{}", color.paint("null"))?;
+        w.write_token(&class, style, text)?;
         Ok(())
     }
 
-    fn emit_indent<W: fmt::Write>(&mut self, w: &mut W) -> Result<()> {
+    fn emit_indent<W: TokenSink>(&mut self, w: &mut W) -> Result<()> {
         self.emit_indent_extra(w, 0)
     }
 
-    fn emit_indent_extra<W: fmt::Write>(&mut self, w: &mut W, extra: isize) -> Result<()> {
+    fn emit_indent_extra<W: TokenSink>(&mut self, w: &mut W, extra: isize) -> Result<()> {
         let extra = self.level + extra;
         if self.compact || extra < 0 {
             return Ok(());
         }
-        let mut len = (extra as usize) * self.indent;
-        while len > 0 {
-            let chunk = std::cmp::min(len, Self::SPACE.len());
-            write!(w, "{}", &Self::SPACE[..chunk])?;
-            len -= chunk;
-        }
-        Ok(())
+        write_indent(w, (extra as usize) * self.indent)
     }
 
-    fn writeln<W: fmt::Write>(&mut self, w: &mut W, s: &str) -> Result<()> {
-        if self.compact {
-            match s {
-                "," => write!(w, "{}", self.color.punctuation.paint(", "))?,
-                _ => write!(w, "{}", s)?,
-            };
-        } else {
-            match s {
-                "," => writeln!(w, "{}", self.color.punctuation.paint(","))?,
-                _ => writeln!(w, "{}", s)?,
-            };
+    fn writeln<W: TokenSink>(&mut self, w: &mut W, s: &str) -> Result<()> {
+        match s {
+            "," if self.compact => self.token(w, TokenClass::Punctuation, ", ")?,
+            "," => self.token(w, TokenClass::Punctuation, ",")?,
+            _ => write!(w, "{}", s)?,
+        }
+        if !self.compact {
+            writeln!(w)?;
         }
         Ok(())
     }
 
     // From yaml-rust:
-    fn escape_str<W: fmt::Write>(
-        &self,
-        wr: &mut W,
-        v: &str,
-        quoted: bool,
-    ) -> std::result::Result<(), fmt::Error> {
-        let color = if self.is_key {
-            &self.color.key
-        } else {
-            &self.color.string
+    fn escape_str<W: TokenSink>(&self, wr: &mut W, v: &str, quoted: bool) -> Result<()> {
+        let class = match (self.is_key, quoted) {
+            (true, true) => TokenClass::Key,
+            (true, false) => TokenClass::BareKey,
+            (false, _) => TokenClass::String,
         };
         if quoted {
-            wr.write_str(&self.color.punctuation.paint("\"").to_string())?;
+            self.token(wr, TokenClass::Punctuation, "\"")?;
         }
 
         let mut start = 0;
@@ -499,88 +1600,23 @@ impl YamlEmitter {
                 _ => continue,
             };
             if start < i {
-                wr.write_str(&color.paint(&v[start..i]).to_string())?;
+                self.token(wr, class.clone(), &v[start..i])?;
             }
-            wr.write_str(&self.color.escape.paint(escaped).to_string())?;
+            self.token(wr, TokenClass::Escape, escaped)?;
             start = i + 1;
         }
 
         if start != v.len() {
-            wr.write_str(&color.paint(&v[start..]).to_string())?;
+            self.token(wr, class, &v[start..])?;
         }
 
         if quoted {
-            wr.write_str(&self.color.punctuation.paint("\"").to_string())?;
+            self.token(wr, TokenClass::Punctuation, "\"")?;
         }
         Ok(())
     }
 }
 
-// From yaml-rust:
-// Check if the string requires quoting.
-// Strings starting with any of the following characters must be quoted.
-// :, &, *, ?, |, -, <, >, =, !, %, @
-// Strings containing any of the following characters must be quoted.
-// {, }, [, ], ,, #, `
-//
-// If the string contains any of the following control characters, it must be escaped with double quotes:
-// \0, \x01, \x02, \x03, \x04, \x05, \x06, \a, \b, \t, \n, \v, \f, \r, \x0e, \x0f, \x10, \x11, \x12, \x13, \x14, \x15, \x16, \x17, \x18, \x19, \x1a, \e, \x1c, \x1d, \x1e, \x1f, \N, \_, \L, \P
-//
-// Finally, there are other cases when the strings must be quoted, no matter if you're using single or double quotes:
-// * When the string is true or false (otherwise, it would be treated as a boolean value);
-// * When the string is null or ~ (otherwise, it would be considered as a null value);
-// * When the string looks like a number, such as integers (e.g. 2, 14, etc.), floats (e.g. 2.6, 14.9) and exponential numbers (e.g. 12e7, etc.) (otherwise, it would be treated as a numeric value);
-// * When the string looks like a date (e.g. 2014-12-31) (otherwise it would be automatically converted into a Unix timestamp).
-fn need_quotes(string: &str) -> bool {
-    fn need_quotes_spaces(string: &str) -> bool {
-        string.starts_with(' ') || string.ends_with(' ')
-    }
-
-    string == ""
-        || need_quotes_spaces(string)
-        || string.starts_with(|character: char| match character {
-            '&' | '*' | '?' | '|' | '-' | '<' | '>' | '=' | '!' | '%' | '@' => true,
-            _ => false,
-        })
-        || string.contains(|character: char| match character {
-            ':'
-            | '{'
-            | '}'
-            | '['
-            | ']'
-            | ','
-            | '#'
-            | '`'
-            | '\"'
-            | '\''
-            | '\\'
-            | '\0'..='\x06'
-            | '\t'
-            | '\n'
-            | '\r'
-            | '\x0e'..='\x1a'
-            | '\x1c'..='\x1f' => true,
-            _ => false,
-        })
-        || [
-            // http://yaml.org/type/bool.html
-            // Note: 'y', 'Y', 'n', 'N', is not quoted deliberately, as in libyaml. PyYAML also parse
-            // them as string, not booleans, although it is violating the YAML 1.1 specification.
-            // See https://github.com/dtolnay/serde-yaml/pull/83#discussion_r152628088.
-            "yes", "Yes", "YES", "no", "No", "NO", "True", "TRUE", "true", "False", "FALSE",
-            "false", "on", "On", "ON", "off", "Off", "OFF",
-            // http://yaml.org/type/null.html
-            "null", "Null", "NULL", "~",
-        ]
-        .contains(&string)
-        || string.starts_with('.')
-        || string.starts_with("0x")
-        || string.starts_with("0b")
-        || string.starts_with("0o")
-        || string.parse::<i64>().is_ok()
-        || string.parse::<f64>().is_ok()
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -630,19 +1666,19 @@ mod tests {
     #[test]
     fn basic_document() {
         let c = comment("woohoo!").to_yaml().header(false);
-        assert_eq!(c.to_string(), "# woohoo!\n");
+        assert_eq!(c.to_text().unwrap(), "# woohoo!\n");
         let n = null().to_yaml().header(false);
-        assert_eq!(n.to_string(), "null");
+        assert_eq!(n.to_text().unwrap(), "null");
         let b = boolean(true).to_yaml().header(false);
-        assert_eq!(b.to_string(), "true");
+        assert_eq!(b.to_text().unwrap(), "true");
         let i = int(5).to_yaml().header(false);
-        assert_eq!(i.to_string(), "5");
+        assert_eq!(i.to_text().unwrap(), "5");
         let i = hex(16).to_yaml().header(false);
-        assert_eq!(i.to_string(), "0x10");
+        assert_eq!(i.to_text().unwrap(), "0x10");
         let s = string("hello").to_yaml().header(false);
-        assert_eq!(s.to_string(), "hello");
+        assert_eq!(s.to_text().unwrap(), "hello");
         let f = float(3.14159).to_yaml().header(false);
-        assert_eq!(f.to_string(), "3.14159");
+        assert_eq!(f.to_text().unwrap(), "3.14159");
     }
 
     #[test]
@@ -654,7 +1690,7 @@ mod tests {
 - foo"#;
 
         let list = Document::Sequence(vec![int(5), int(10), int(15), string("foo")]).to_yaml();
-        assert_eq!(list.to_string(), expect);
+        assert_eq!(list.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -671,7 +1707,7 @@ c: 15
             kv("true", string("foo")),
         ])
         .to_yaml();
-        assert_eq!(map.to_string(), expect);
+        assert_eq!(map.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -688,7 +1724,7 @@ c: 0xF
             kv("true", string("foo")),
         ])
         .to_yaml();
-        assert_eq!(map.to_string(), expect);
+        assert_eq!(map.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -703,7 +1739,7 @@ c: 0xF
         ])
         .to_yaml()
         .compact(true);
-        assert_eq!(map.to_string(), expect);
+        assert_eq!(map.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -724,7 +1760,7 @@ reset: {prg: [-1, 0xFFFA]}"#;
             kv("reset", nes_address("prg", -1, 0xFFFA)),
         ])
         .to_yaml();
-        assert_eq!(map.to_string(), expect);
+        assert_eq!(map.to_text().unwrap(), expect);
     }
 
     #[test]
@@ -735,7 +1771,7 @@ unquoted: and you can quote me on that
 singleQuotes: "not really, though"
 lineBreaks: |-
   Look, Mom!
-  No \\n's!
+  No \n's!
 hexadecimal: 0xDECAF
 # more comments
 leadingDecimal(not): 0.8675309
@@ -764,7 +1800,612 @@ backwardsCompatible: with JSON"#;
             kv("backwardsCompatible", string("with JSON")),
         ])
         .to_yaml();
-        println!("{}", map);
-        assert_eq!(map.to_string(), expect);
+        println!("{}", map.to_text().unwrap());
+        assert_eq!(map.to_text().unwrap(), expect);
+    }
+
+    #[test]
+    fn block_scalars() -> Result<()> {
+        let s = |v: &str, f| Document::String(v.to_string(), f);
+        let doc = Document::Mapping(vec![
+            kv("script", string("set -e\nmake \\\n  all\n")),
+            kv("kept", s("a\n\n", StrFormat::Literal)),
+            kv(
+                "prose",
+                s(
+                    "The quick brown fox jumps over the lazy dog, which is a sentence long \
+                     enough to be folded.\nA new line.",
+                    StrFormat::Folded,
+                ),
+            ),
+            kv("indented", s("  x\ny", StrFormat::Folded)),
+            kv("control", s("a\u{7}\nb", StrFormat::Literal)),
+            kv("single", string("one line")),
+            kv("last", s("end\n", StrFormat::Literal)),
+        ]);
+        assert_eq!(
+            doc.clone().to_yaml().to_text()?,
+            r#"---
+script: "set -e\nmake \\\n  all\n"
+kept: |+
+  a
+
+prose: >-
+  The quick brown fox jumps over the lazy dog, which is a sentence long enough
+  to be folded.
+
+  A new line.
+indented: |2-
+    x
+  y
+control: "a\u0007\nb"
+single: one line
+last: |
+  end
+"#
+        );
+        let text = doc.to_yaml().block_scalar(StrFormat::Literal).to_text()?;
+        assert!(text.contains("script: |\n  set -e\n  make \\\n    all\nkept"));
+
+        // The values survive a round trip through a YAML parser.
+        let back: serde_yaml::Value = serde_yaml::from_str(&text).unwrap();
+        assert_eq!(back["script"].as_str(), Some("set -e\nmake \\\n  all\n"));
+        assert_eq!(back["kept"].as_str(), Some("a\n\n"));
+        assert_eq!(
+            back["prose"].as_str(),
+            Some(
+                "The quick brown fox jumps over the lazy dog, which is a sentence long \
+                 enough to be folded.\nA new line."
+            )
+        );
+        assert_eq!(back["indented"].as_str(), Some("  x\ny"));
+        assert_eq!(back["last"].as_str(), Some("end\n"));
+
+        // Blocks at the root are indented, as readers reject them otherwise.
+        let doc = Document::String("  x\ny\n".into(), StrFormat::Multiline);
+        let text = doc.to_yaml().to_text()?;
+        assert_eq!(text, "---\n|2\n    x\n  y\n");
+        let back: serde_yaml::Value = serde_yaml::from_str(&text).unwrap();
+        assert_eq!(back.as_str(), Some("  x\ny\n"));
+        let doc = Document::Bytes(b"abc".to_vec().into());
+        let text = doc.to_yaml().bytes_format(BytesFormat::Hexdump).to_text()?;
+        assert!(text.starts_with("---\n|-\n  00000000  61 62 63"));
+        let back: serde_yaml::Value = serde_yaml::from_str(&text).unwrap();
+        assert!(back.as_str().unwrap().ends_with("|abc|"));
+        Ok(())
+    }
+
+    #[test]
+    fn anchors() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                defaults: {os: "linux", arch: ["x86", "arm"]},
+                jobs: [
+                    {name: "a", env: {os: "linux", arch: ["x86", "arm"]}},
+                    {name: "b", env: {os: "linux", arch: ["x86", "arm"]}},
+                    {name: "c", env: [1, 2], targets: ["x86", "arm"]},
+                ],
+                again: [1, 2],
+            }"#,
+        )?;
+        let yaml = doc.clone().to_yaml().anchors(true);
+        assert_eq!(
+            yaml.to_text()?,
+            r#"---
+defaults: &defaults
+  os: linux
+  arch: &arch
+    - x86
+    - arm
+jobs:
+  -
+    name: a
+    env: *defaults
+  -
+    name: b
+    env: *defaults
+  -
+    name: c
+    env: &env
+      - 1
+      - 2
+    targets: *arch
+again: *env"#
+        );
+        let back: serde_yaml::Value = serde_yaml::from_str(&yaml.to_text()?).unwrap();
+        let plain: serde_yaml::Value =
+            serde_yaml::from_str(&doc.clone().to_yaml().to_text()?).unwrap();
+        assert_eq!(back, plain);
+
+        assert_eq!(
+            doc.to_yaml().anchors(true).compact(true).to_text()?,
+            "---\n{defaults: &defaults {os: linux, arch: &arch [x86, arm]}, \
+             jobs: [{name: a, env: *defaults}, {name: b, env: *defaults}, \
+             {name: c, env: &env [1, 2], targets: *arch}], again: *env}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn variant_tags() -> Result<()> {
+        #[derive(serde_derive::Serialize)]
+        enum Shape {
+            Circle { r: u32 },
+            Square(u32),
+            Empty,
+        }
+        #[derive(serde_derive::Serialize)]
+        #[serde(tag = "type")]
+        enum Event {
+            Start { at: u32 },
+            Stop,
+        }
+        #[derive(serde_derive::Serialize)]
+        struct Config {
+            shapes: Vec<Shape>,
+            main: Shape,
+            events: Vec<Event>,
+        }
+        let config = crate::ser::serialize(&Config {
+            shapes: vec![Shape::Circle { r: 1 }, Shape::Square(2), Shape::Empty],
+            main: Shape::Circle { r: 3 },
+            events: vec![Event::Start { at: 5 }, Event::Stop],
+        })?;
+
+        let yaml = config.clone().to_yaml().variant_tags(VariantTags::External);
+        assert_eq!(
+            yaml.to_text()?,
+            r#"---
+shapes:
+  - !Circle
+    r: 1
+  - !Square 2
+  - Empty
+main: !Circle
+  r: 3
+events:
+  -
+    type: Start
+    at: 5
+  -
+    type: Stop"#
+        );
+        // Loaders without tag support see the variant contents.
+        let back: serde_yaml::Value = serde_yaml::from_str(&yaml.to_text()?).unwrap();
+        assert_eq!(back["main"]["r"].as_u64(), Some(3));
+
+        let yaml = config
+            .clone()
+            .to_yaml()
+            .variant_tags(VariantTags::Internal("type".into()))
+            .compact(true);
+        assert_eq!(
+            yaml.to_text()?,
+            "---\n{shapes: [{Circle: {r: 1}}, {Square: 2}, Empty], main: {Circle: {r: 3}}, \
+             events: [!Start {at: 5}, !Stop {}]}"
+        );
+
+        // Mappings which were not serialized as variants are emitted
+        // unchanged, whatever their shape.
+        #[derive(serde_derive::Serialize)]
+        #[allow(non_snake_case)]
+        struct Wrapper {
+            Circle: u32,
+        }
+        let doc = crate::ser::serialize(&Wrapper { Circle: 1 })?;
+        assert_eq!(
+            doc.to_yaml()
+                .variant_tags(VariantTags::External)
+                .to_text()?,
+            "---\nCircle: 1"
+        );
+        let doc = Document::parse("{ok: {name: 1}, shape: {Circle: {r: 1}}}")?;
+        assert_eq!(
+            doc.to_yaml()
+                .variant_tags(VariantTags::External)
+                .to_text()?,
+            "---\nok:\n  name: 1\nshape:\n  Circle:\n    r: 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compact_threshold() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                point: {x: 1, y: 2},
+                registers: [[0x10, 0xff], [0x11, 0x00]],
+                commented: [
+                    // One.
+                    1,
+                ],
+                long: [1000, 2000, 3000, 4000, 5000, 6000, 7000, 8000],
+                flow: [1, 2],
+            }"#,
+        )?;
+        let doc = match doc {
+            Document::Mapping(mut m) => {
+                let (_, flow) = m[4].as_kv_mut()?;
+                *flow = Document::Compact(Box::new(flow.clone()));
+                Document::Mapping(m)
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            doc.clone().to_yaml().to_text()?,
+            r#"---
+point:
+  x: 1
+  "y": 2
+registers:
+  -
+    - 0x10
+    - 0xFF
+  -
+    - 0x11
+    - 0x00
+commented:
+  # One.
+  - 1
+long:
+  - 1000
+  - 2000
+  - 3000
+  - 4000
+  - 5000
+  - 6000
+  - 7000
+  - 8000
+flow: [1, 2]"#
+        );
+        assert_eq!(
+            doc.clone().to_yaml().compact_threshold(30).to_text()?,
+            r#"---
+point: {x: 1, "y": 2}
+registers:
+  - [0x10, 0xFF]
+  - [0x11, 0x00]
+commented:
+  # One.
+  - 1
+long:
+  - 1000
+  - 2000
+  - 3000
+  - 4000
+  - 5000
+  - 6000
+  - 7000
+  - 8000
+flow: [1, 2]"#
+        );
+        // The key counts towards the width of a flow value.
+        assert_eq!(
+            doc.to_yaml()
+                .compact_threshold(39)
+                .to_text()?
+                .lines()
+                .nth(2),
+            Some("registers: [[0x10, 0xFF], [0x11, 0x00]]")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ambiguous_strings() -> Result<()> {
+        let values = [
+            "no",
+            "On",
+            "~",
+            "3.0",
+            "1_000",
+            "0o17",
+            "2024-01-01",
+            ".inf",
+            "plain",
+            "y",
+        ];
+        let doc = Document::Mapping(
+            values
+                .iter()
+                .map(|v| {
+                    Document::Fragment(vec![
+                        Document::from(*v),
+                        Document::String(v.to_string(), StrFormat::Standard),
+                    ])
+                })
+                .collect(),
+        );
+        let text = doc.clone().to_yaml().compact(true).to_text()?;
+        assert_eq!(
+            text,
+            r#"---
+{"no": "no", "On": "On", "~": "~", "3.0": "3.0", "1_000": "1_000", "0o17": "0o17", "2024-01-01": "2024-01-01", ".inf": ".inf", plain: plain, "y": "y"}"#
+        );
+        // Every value reads back as the same string.
+        let back: serde_yaml::Value = serde_yaml::from_str(&text).unwrap();
+        for v in values {
+            assert_eq!(back[v].as_str(), Some(v));
+        }
+
+        assert_eq!(
+            doc.to_yaml().quote_all(true).compact(true).to_text()?,
+            r#"---
+{"no": "no", "On": "On", "~": "~", "3.0": "3.0", "1_000": "1_000", "0o17": "0o17", "2024-01-01": "2024-01-01", ".inf": ".inf", plain: "plain", "y": "y"}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stream() -> Result<()> {
+        let docs = [
+            "{\n  // The service.\n  kind: \"Service\",\n  ports: [80]\n}",
+            "{kind: \"ConfigMap\", data: {script: \"echo\\n\\n\"}}",
+            "[1, 2]",
+        ];
+        let mut stream = YamlStream::new();
+        assert!(stream.is_empty());
+        for (i, d) in docs.iter().enumerate() {
+            let yaml = Document::parse(d)?.to_yaml().header(false);
+            stream.push(if i == 1 {
+                yaml.block_scalar(StrFormat::Literal)
+            } else {
+                yaml
+            });
+        }
+        assert_eq!(stream.len(), 3);
+        let text = stream.to_text()?;
+        assert_eq!(
+            text,
+            r#"---
+# The service.
+kind: Service
+ports:
+  - 80
+---
+kind: ConfigMap
+data:
+  script: |+
+    echo
+
+---
+- 1
+- 2
+"#
+        );
+
+        // Each document reads back as the original.
+        let parts = YamlStream::split(&text);
+        assert_eq!(parts.len(), 3);
+        for (part, d) in parts.iter().zip(docs) {
+            let expected: serde_yaml::Value =
+                serde_yaml::from_str(&Document::parse(d)?.to_yaml().to_text()?).unwrap();
+            let back: serde_yaml::Value = serde_yaml::from_str(part).unwrap();
+            assert_eq!(back, expected);
+        }
+
+        let text = "%YAML 1.2\n# Leading.\n--- inline\n...\n# Between.\n---\na: 1\n---\n";
+        assert_eq!(YamlStream::split(text), ["inline\n", "a: 1\n", ""]);
+        assert_eq!(YamlStream::split("a: 1\n"), ["a: 1\n"]);
+        assert!(YamlStream::split("").is_empty());
+        assert_eq!(YamlStream::new().to_text()?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_finite() {
+        let doc = Document::Sequence(vec![
+            Document::Float(f64::NAN),
+            Document::Float(f64::INFINITY),
+            Document::Float(f64::NEG_INFINITY),
+        ]);
+        assert_eq!(
+            doc.to_yaml().header(false).compact(true).to_text().unwrap(),
+            "[.nan, .inf, -.inf]"
+        );
+    }
+
+    #[test]
+    fn test_write_to() -> Result<()> {
+        let doc = Document::Mapping(vec![kv("a", int(1)), kv("b", string("text"))]);
+        let yaml = doc.to_yaml().newline(Newline::CrLf);
+        let mut out = Vec::new();
+        yaml.to_writer(&mut out)?;
+        assert_eq!(String::from_utf8(out).unwrap(), yaml.to_text()?);
+
+        let mut full = [0u8; 4];
+        let err = yaml
+            .to_writer(&mut io::Cursor::new(&mut full[..]))
+            .unwrap_err();
+        assert!(matches!(err, Error::IoError(_)));
+        let err = yaml.max_output(4).to_writer(&mut io::sink()).unwrap_err();
+        assert!(matches!(err, Error::OutputLimit(4)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_depth() -> Result<()> {
+        let doc = Document::Mapping(vec![kv(
+            "a",
+            Document::Sequence(vec![Document::Compact(Box::new(Document::Sequence(vec![
+                int(1),
+            ])))]),
+        )]);
+        assert!(doc
+            .clone()
+            .to_yaml()
+            .max_depth(3)
+            .to_writer(&mut io::sink())
+            .is_ok());
+        let err = doc
+            .to_yaml()
+            .max_depth(2)
+            .to_writer(&mut io::sink())
+            .unwrap_err();
+        assert!(matches!(err, Error::DepthLimit(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn comment_placement() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                name: "server", // The name.
+                ports: [
+                    // HTTP.
+                    80,
+                    443,
+                ],
+                // The limits
+                // on clients.
+                limits: {
+                    // Connections.
+                    conns: 10,
+                    // Timeout.
+                    timeout: [1, 2],
+                },
+            }"#,
+        )?;
+        let emit = |p| {
+            doc.clone()
+                .to_yaml()
+                .comment_placement(p)
+                .to_text()
+                .unwrap()
+        };
+        assert_eq!(
+            emit(YamlCommentPlacement::Above),
+            r#"---
+# The name.
+name: server
+ports:
+  # HTTP.
+  - 80
+  - 443
+# The limits
+# on clients.
+limits:
+  # Connections.
+  conns: 10
+  # Timeout.
+  timeout:
+    - 1
+    - 2"#
+        );
+        // Comments on aggregates which span several lines stay above.
+        assert_eq!(
+            emit(YamlCommentPlacement::Trailing),
+            r#"---
+name: server # The name.
+ports:
+  - 80 # HTTP.
+  - 443
+# The limits
+# on clients.
+limits:
+  conns: 10 # Connections.
+  # Timeout.
+  timeout:
+    - 1
+    - 2"#
+        );
+        assert_eq!(
+            emit(YamlCommentPlacement::Block),
+            r#"---
+# name: The name.
+# limits: The limits
+# on clients.
+name: server
+ports:
+  # HTTP.
+  - 80
+  - 443
+# conns: Connections.
+# timeout: Timeout.
+limits:
+  conns: 10
+  timeout:
+    - 1
+    - 2"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn extends() -> Result<()> {
+        let doc = Document::parse(
+            r#"{
+                jobs: [
+                    {image: "rust", script: "make", cache: true},
+                    {image: "rust", script: "make test", cache: true},
+                    {image: "rust", script: "make"},
+                ],
+                early: {image: "rust", script: "make"},
+            }"#,
+        )?;
+        let yaml = doc
+            .clone()
+            .to_yaml()
+            .extends("/jobs/1", "/jobs/0")
+            // Lacks the `cache` key of its base.
+            .extends("/jobs/2", "/jobs/0")
+            .extends("/early", "/jobs/2")
+            // Precedes its base.
+            .extends("/jobs/2", "/early");
+        assert_eq!(
+            yaml.to_text()?,
+            r#"---
+jobs:
+  - &jobs
+    image: rust
+    script: make
+    cache: true
+  -
+    <<: *jobs
+    script: make test
+  - &jobs2
+    image: rust
+    script: make
+early:
+  <<: *jobs2"#
+        );
+        // The merge keys expand back into the original document.
+        fn expand(v: serde_json::Value) -> serde_json::Value {
+            use serde_json::Value;
+            match v {
+                Value::Object(mut m) => {
+                    let mut out = match m.remove("<<") {
+                        Some(Value::Object(base)) => base,
+                        _ => Default::default(),
+                    };
+                    out.extend(m);
+                    Value::Object(out.into_iter().map(|(k, v)| (k, expand(v))).collect())
+                }
+                Value::Array(a) => Value::Array(a.into_iter().map(expand).collect()),
+                v => v,
+            }
+        }
+        let back: serde_json::Value = serde_yaml::from_str(&yaml.to_text()?).unwrap();
+        let plain: serde_json::Value =
+            serde_yaml::from_str(&doc.clone().to_yaml().to_text()?).unwrap();
+        assert_eq!(expand(back), plain);
+
+        assert_eq!(
+            doc.clone()
+                .to_yaml()
+                .extends("/jobs/1", "/jobs/0")
+                .compact(true)
+                .to_text()?,
+            "---\n{jobs: [&jobs {image: rust, script: make, cache: true}, \
+             {<<: *jobs, script: make test}, {image: rust, script: make}], \
+             early: {image: rust, script: make}}"
+        );
+        assert!(matches!(
+            doc.to_yaml()
+                .extends("/jobs/5", "/early")
+                .to_writer(&mut Vec::new()),
+            Err(Error::PathError(_))
+        ));
+        Ok(())
     }
 }
@@ -1,7 +1,7 @@
 #![feature(min_specialization)]
 use anyhow::Result;
 use serde_annotate::serialize;
-use serde_annotate::Annotate;
+use serde_annotate::{Annotate, Emit};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Annotate)]
 struct Hello {
@@ -19,7 +19,7 @@ fn hello() -> Box<dyn Annotate> {
 #[test]
 fn test_erased_serialization_regular() -> Result<()> {
     let greeting = hello();
-    let s = serialize(&*greeting)?.to_json5().to_string();
+    let s = serialize(&*greeting)?.to_json5().to_text()?;
     assert_eq!(s, "{\n  // A greeting\n  message: \"Hello World!\"\n}");
     Ok(())
 }
@@ -41,7 +41,7 @@ fn nested_hello() -> Box<dyn Annotate> {
 #[test]
 fn test_erased_serialization_nested() -> Result<()> {
     let greeting = nested_hello();
-    let s = serialize(&*greeting)?.to_json5().to_string();
+    let s = serialize(&*greeting)?.to_json5().to_text()?;
     assert_eq!(
         s,
         "{\n  greeting: {\n    // A greeting\n    message: \"Hola!\"\n  }\n}"
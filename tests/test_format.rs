@@ -3,6 +3,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_annotate::serialize;
 use serde_annotate::Annotate;
+use serde_annotate::Emit;
 
 fn fixdoc(doc: &str) -> String {
     let mut s = String::new();
@@ -28,21 +29,21 @@ fn fixdoc(doc: &str) -> String {
 macro_rules! tester {
     (json, $t:ty, $value:expr, $expect:expr) => {{
         let doc = serialize($value)?;
-        let string = doc.to_json().to_string();
+        let string = doc.to_json().to_text()?;
         assert_eq!(string, fixdoc($expect));
         let decode: $t = serde_json::from_str(&string)?;
         assert_eq!($value, &decode);
     }};
     (json5, $t:ty, $value:expr, $expect:expr) => {{
         let doc = serialize($value)?;
-        let string = doc.to_json5().to_string();
+        let string = doc.to_json5().to_text()?;
         assert_eq!(string, fixdoc($expect));
         let decode: $t = json5::from_str(&string)?;
         assert_eq!($value, &decode);
     }};
     (hjson, $t:ty, $value:expr, $expect:expr) => {{
         let doc = serialize($value)?;
-        let string = doc.to_hjson().to_string();
+        let string = doc.to_hjson().to_text()?;
         assert_eq!(string, fixdoc($expect));
         let decode: $t = deser_hjson::from_str(&string)?;
         assert_eq!($value, &decode);
@@ -56,28 +57,28 @@ macro_rules! tester {
     }};
     (relax_json5, $t:ty, $value:expr, $expect:expr) => {{
         let doc = serialize($value)?;
-        let string = doc.to_json5().to_string();
+        let string = doc.to_json5().to_text()?;
         assert_eq!(string, fixdoc($expect));
         let decode: $t = serde_annotate::from_str(&string)?;
         assert_eq!($value, &decode);
     }};
     (relax_hjson, $t:ty, $value:expr, $expect:expr) => {{
         let doc = serialize($value)?;
-        let string = doc.to_hjson().to_string();
+        let string = doc.to_hjson().to_text()?;
         assert_eq!(string, fixdoc($expect));
         let decode: $t = serde_annotate::from_str(&string)?;
         assert_eq!($value, &decode);
     }};
     (yaml, $t:ty, $value:expr, $expect:expr) => {{
         let doc = serialize($value)?;
-        let string = doc.to_yaml().to_string();
+        let string = doc.to_yaml().to_text()?;
         assert_eq!(string, fixdoc($expect));
         let decode: $t = serde_yaml::from_str(&string)?;
         assert_eq!($value, &decode);
     }};
     (ser_yaml, $t:ty, $value:expr, $expect:expr) => {{
         let doc = serialize($value)?;
-        let string = doc.to_yaml().to_string();
+        let string = doc.to_yaml().to_text()?;
         assert_eq!(string, fixdoc($expect));
     }};
 }
@@ -146,7 +147,7 @@ fn test_coordinate() -> Result<()> {
         # X-coordinate
         x: 0x10
         # Y-coordinate
-        y: 10
+        "y": 10
         # Z-coordinate
         z: 0o10"#
     );
@@ -501,3 +502,366 @@ fn test_bytes() -> Result<()> {
 
     Ok(())
 }
+
+#[derive(Serialize, Deserialize, Annotate, Debug, PartialEq)]
+struct Server {
+    name: String,
+    #[annotate(color = "warning", comment = "Disables certificate checks")]
+    insecure: bool,
+}
+
+#[test]
+fn test_color_class() -> Result<()> {
+    use ansi_term::{Color, Style};
+    use serde_annotate::ColorProfile;
+
+    let server = Server {
+        name: "prod".into(),
+        insecure: true,
+    };
+    // Without a class style, the value is emitted normally.
+    tester!(
+        relax_json5,
+        Server,
+        &server,
+        r#"
+        {
+          name: "prod",
+          // Disables certificate checks
+          insecure: true
+        }"#
+    );
+
+    let warning = Style::new().fg(Color::Red).bold();
+    let profile = ColorProfile::default().class("warning", warning);
+    let doc = serialize(&server)?;
+    assert_eq!(
+        doc.clone().to_json5().color(profile.clone()).to_text()?,
+        format!(
+            "{{\n  name: \"prod\",\n  // Disables certificate checks\n  insecure: {}\n}}",
+            warning.paint("true")
+        )
+    );
+    assert_eq!(
+        doc.to_yaml().color(profile).to_text()?,
+        format!(
+            "---\nname: prod\n# Disables certificate checks\ninsecure: {}",
+            warning.paint("true")
+        )
+    );
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Annotate, Debug, PartialEq)]
+struct Options {
+    #[annotate(comment = "The log level")]
+    level: Option<u8>,
+    path: Option<String>,
+    retries: Option<u32>,
+}
+
+#[test]
+fn test_skip_null() -> Result<()> {
+    let opts = Options {
+        level: None,
+        path: Some("/tmp".into()),
+        retries: None,
+    };
+    let doc = serialize(&opts)?;
+    assert_eq!(
+        doc.clone().to_json5().to_text()?,
+        "{\n  // The log level\n  level: null,\n  path: \"/tmp\",\n  retries: null\n}"
+    );
+    let text = doc.clone().to_json5().skip_null(true).to_text()?;
+    assert_eq!(text, "{\n  path: \"/tmp\"\n}");
+    // Missing `Option` fields deserialize to `None`.
+    assert_eq!(serde_annotate::from_str::<Options>(&text)?, opts);
+    assert_eq!(
+        doc.clone().to_yaml().skip_null(true).to_text()?,
+        "---\npath: /tmp"
+    );
+
+    // A mapping holding only nulls becomes empty.
+    let opts = Options {
+        level: None,
+        path: None,
+        retries: None,
+    };
+    assert_eq!(
+        serialize(&opts)?.to_yaml().skip_null(true).to_text()?,
+        "---\n{}"
+    );
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Annotate, Debug, PartialEq)]
+struct Firmware {
+    #[serde(with = "serde_bytes")]
+    image: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    #[annotate(format = base64)]
+    signature: Vec<u8>,
+}
+
+#[test]
+fn test_bytes_format() -> Result<()> {
+    use serde_annotate::BytesFormat;
+    let value = Firmware {
+        image: vec![0xca, 0xfe, 0xf0, 0x0d],
+        signature: b"foobar".to_vec(),
+    };
+    let doc = serialize(&value)?;
+    let text = doc.clone().to_json().compact(true).to_text()?;
+    assert_eq!(
+        text,
+        r#"{"image": [202, 254, 240, 13], "signature": "base64:Zm9vYmFy"}"#
+    );
+    assert_eq!(serde_annotate::from_str::<Firmware>(&text)?, value);
+
+    // Unannotated bytes are rendered in the globally selected format.
+    let text = doc
+        .clone()
+        .to_json()
+        .compact(true)
+        .bytes_format(BytesFormat::HexStr)
+        .to_text()?;
+    assert_eq!(
+        text,
+        r#"{"image": "cafef00d", "signature": "base64:Zm9vYmFy"}"#
+    );
+    assert_eq!(serde_annotate::from_str::<Firmware>(&text)?, value);
+    let text = doc.to_yaml().bytes_format(BytesFormat::Base64).to_text()?;
+    assert_eq!(
+        text,
+        "---\nimage: \"base64:yv7wDQ==\"\nsignature: \"base64:Zm9vYmFy\""
+    );
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Annotate, Clone, Debug, PartialEq)]
+enum Level {
+    Debug,
+    Info,
+}
+
+#[derive(Serialize, Deserialize, Annotate, Debug, PartialEq)]
+struct Logger {
+    #[annotate(comment = "The minimum level")]
+    level: Level,
+    targets: Vec<Level>,
+}
+
+#[test]
+fn test_static_strings() -> Result<()> {
+    use serde_annotate::{AnnotatedSerializer, Document};
+    let value = Logger {
+        level: Level::Info,
+        targets: vec![Level::Debug; 100],
+    };
+    // Field names and variant names are not copied.
+    let doc = serialize(&value)?;
+    assert_eq!(doc.static_str_count(), 103);
+    let mut ser = AnnotatedSerializer::new(Some(&value)).static_strings(false);
+    let copied: Document = value.serialize(&mut ser)?;
+    assert_eq!(copied.static_str_count(), 0);
+    assert_eq!(
+        doc.to_json5().to_text()?,
+        copied.clone().to_json5().to_text()?
+    );
+    assert_eq!(
+        serde_annotate::from_str::<Logger>(&copied.to_json().to_text()?)?,
+        value
+    );
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Annotate, Debug, PartialEq)]
+enum Command {
+    Move {
+        #[annotate(comment = "Distance", value_comment_fn = unit)]
+        steps: u32,
+    },
+    Turn(
+        #[annotate(comment = "Angle", value_comment = "Degrees")] u32,
+        #[annotate(value_comment = "Counterclockwise")] bool,
+    ),
+}
+
+impl Command {
+    fn unit(&self) -> Option<String> {
+        Some("Steps".into())
+    }
+}
+
+#[derive(Serialize, Deserialize, Annotate, Debug, PartialEq)]
+struct Motor {
+    #[annotate(comment = "Motor speed", value_comment = units)]
+    speed: u32,
+    #[annotate(value_comment = "Enabled at boot")]
+    enabled: bool,
+    home: Command,
+    turn: Command,
+    #[serde(skip)]
+    units: String,
+}
+
+#[test]
+fn test_value_comments() -> Result<()> {
+    let value = Motor {
+        speed: 1200,
+        enabled: true,
+        home: Command::Move { steps: 4 },
+        turn: Command::Turn(90, false),
+        units: "RPM".into(),
+    };
+    let doc = serialize(&value)?;
+    assert_eq!(
+        doc.clone().to_json5().to_text()?,
+        fixdoc(
+            r#"
+            {
+              // Motor speed
+              speed: 1200, // RPM
+              enabled: true, // Enabled at boot
+              home: {
+                Move: {
+                  // Distance
+                  steps: 4 // Steps
+                }
+              },
+              turn: {
+                Turn: [
+                  // Angle
+                  90, // Degrees
+                  false // Counterclockwise
+                ]
+              }
+            }"#
+        )
+    );
+    assert_eq!(
+        doc.clone().to_yaml().to_text()?,
+        fixdoc(
+            r#"
+            ---
+            # Motor speed
+            speed: 1200 # RPM
+            enabled: true # Enabled at boot
+            home:
+              Move:
+                # Distance
+                steps: 4 # Steps
+            turn:
+              Turn:
+                # Angle
+                - 90 # Degrees
+                - false # Counterclockwise"#
+        )
+    );
+    // Strict JSON drops the comments without leaving stray whitespace.
+    assert_eq!(
+        doc.clone().to_json().to_text()?,
+        fixdoc(
+            r#"
+            {
+              "speed": 1200,
+              "enabled": true,
+              "home": {
+                "Move": {
+                  "steps": 4
+                }
+              },
+              "turn": {
+                "Turn": [
+                  90,
+                  false
+                ]
+              }
+            }"#
+        )
+    );
+    assert_eq!(
+        doc.to_json().compact(true).to_text()?,
+        r#"{"speed": 1200, "enabled": true, "home": {"Move": {"steps": 4}}, "turn": {"Turn": [90, false]}}"#
+    );
+    Ok(())
+}
+
+#[derive(Serialize, Annotate)]
+enum Shape {
+    #[annotate(comment = "A unit square")]
+    Square,
+    Circle {
+        #[annotate(comment = "The radius")]
+        r: f64,
+    },
+    Point(i32, i32),
+    Named(String),
+}
+
+#[derive(Serialize, Annotate)]
+struct Scene {
+    #[annotate(comment = "Scene title")]
+    title: String,
+    #[annotate(format = hex)]
+    flags: u32,
+    origin: Option<(i32, i32)>,
+    parent: Option<u32>,
+    #[annotate(format = block)]
+    script: String,
+    shape: Shape,
+    square: Shape,
+    tags: std::collections::BTreeMap<String, u8>,
+}
+
+#[test]
+fn test_ron() -> Result<()> {
+    let scene = Scene {
+        title: "Intro \"A\"".into(),
+        flags: 0x1f,
+        origin: Some((1, -2)),
+        parent: None,
+        script: "fade(\"in\")\nwait()".into(),
+        shape: Shape::Circle { r: 1.0 },
+        square: Shape::Square,
+        tags: [("level".to_string(), 3)].into_iter().collect(),
+    };
+    let doc = serialize(&scene)?;
+    assert_eq!(
+        doc.clone().to_ron().struct_name("/", "Scene").to_text()?,
+        r##"#![enable(implicit_some, unwrap_newtypes)]
+Scene(
+  // Scene title
+  title: "Intro \"A\"",
+  flags: 0x1F,
+  origin: [
+    1,
+    -2,
+  ],
+  parent: None,
+  script: r#"fade("in")
+wait()"#,
+  shape: Circle(
+    // The radius
+    r: 1.0,
+  ),
+  square: Square, // A unit square
+  tags: {
+    "level": 3,
+  },
+)
+"##
+    );
+    assert_eq!(
+        doc.to_ron().compact(true).extensions(false).to_text()?,
+        r#"(title: "Intro \"A\"", flags: 0x1F, origin: [1, -2], parent: None, script: "fade(\"in\")\nwait()", shape: Circle(r: 1.0), square: Square, tags: {"level": 3})
+"#
+    );
+
+    let doc = serialize(&vec![Shape::Point(1, 2), Shape::Named("x".into())])?;
+    assert_eq!(
+        doc.to_ron().compact(true).extensions(false).to_text()?,
+        "[Point(1, 2), Named(\"x\")]\n"
+    );
+    Ok(())
+}
@@ -1,7 +1,7 @@
 #![feature(min_specialization)]
 use anyhow::Result;
 use serde_annotate::serialize;
-use serde_annotate::{Document, StrFormat};
+use serde_annotate::{Document, Emit, StrFormat};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct Partial {
@@ -26,7 +26,7 @@ fn test_partial_serialize() -> Result<()> {
             Document::String("world".into(), StrFormat::Standard),
         ]),
     };
-    let s = serialize(&p)?.to_json5().to_string();
+    let s = serialize(&p)?.to_json5().to_text()?;
     assert_eq!(s, SERIALIZE_RESULT);
     Ok(())
 }
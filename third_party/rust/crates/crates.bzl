@@ -736,6 +736,26 @@ def raze_fetch_remote_crates():
         build_file = Label("//third_party/rust/crates/remote:BUILD.unicode-ident-1.0.4.bazel"),
     )
 
+    maybe(
+        http_archive,
+        name = "raze__unicode_segmentation__1_13_3",
+        url = "https://crates.io/api/v1/crates/unicode-segmentation/1.13.3/download",
+        type = "tar.gz",
+        sha256 = "c6f5d3c3b1bf09027a88a6bc961fc00497d651009560b5463668dc81b0fa87a8",
+        strip_prefix = "unicode-segmentation-1.13.3",
+        build_file = Label("//third_party/rust/crates/remote:BUILD.unicode-segmentation-1.13.3.bazel"),
+    )
+
+    maybe(
+        http_archive,
+        name = "raze__unicode_width__0_1_14",
+        url = "https://crates.io/api/v1/crates/unicode-width/0.1.14/download",
+        type = "tar.gz",
+        sha256 = "7dd6e30e90baa6f72411720665d41d89b9a3d039dc45b8faea1ddd07f617f6af",
+        strip_prefix = "unicode-width-0.1.14",
+        build_file = Label("//third_party/rust/crates/remote:BUILD.unicode-width-0.1.14.bazel"),
+    )
+
     maybe(
         http_archive,
         name = "raze__version_check__0_9_4",